@@ -0,0 +1,183 @@
+//! Benchmarks of representative programs: long chains of projections,
+//! traits with many impls, auto traits over deeply nested structs, and
+//! programs with many impls to check for coherence. Each goal is solved
+//! with both search strategies of the solver.
+//!
+//! Run with `cargo bench`; criterion keeps the results of the previous
+//! run and reports how much each benchmark changed since.
+
+#[macro_use]
+extern crate criterion;
+
+extern crate chalk;
+extern crate chalk_ir;
+extern crate chalk_parse;
+extern crate chalk_solve;
+
+use std::sync::Arc;
+
+use chalk::db::ChalkDatabase;
+use chalk::query::LoweringDatabase;
+use chalk::rust_ir::lowering::LowerGoal;
+use chalk_ir::{Goal, InEnvironment, ProgramEnvironment, UCanonical};
+use chalk_solve::ext::GoalExt;
+use chalk_solve::solve::{SearchStrategy, SolverChoice};
+use criterion::{black_box, Criterion};
+
+const STRATEGIES: &[(&str, SearchStrategy)] = &[
+    ("depth-first", SearchStrategy::DepthFirst),
+    ("breadth-first", SearchStrategy::BreadthFirst),
+];
+
+/// Lowers `program_text` and `goal_text`, panicking on errors, which
+/// would make the benchmark meaningless.
+fn lower(
+    program_text: &str,
+    goal_text: &str,
+    solver_choice: SolverChoice,
+) -> (Arc<ProgramEnvironment>, UCanonical<InEnvironment<Goal>>) {
+    ChalkDatabase::with_program(Arc::new(program_text.to_string()), solver_choice, |db| {
+        let program = db.checked_program().unwrap();
+        let env = db.environment().unwrap();
+        let goal = chalk_parse::parse_goal(goal_text)
+            .unwrap()
+            .lower(&*program)
+            .unwrap()
+            .into_peeled_goal();
+        (env, goal)
+    })
+}
+
+/// Benchmarks solving `goal_text` with each search strategy, under the
+/// name `name/<strategy>`.
+fn bench_goal(
+    c: &mut Criterion,
+    name: &str,
+    program_text: &str,
+    goal_text: &str,
+    max_size: usize,
+) {
+    for &(strategy_name, strategy) in STRATEGIES {
+        let solver_choice = SolverChoice::slg()
+            .max_size(max_size)
+            .search_strategy(strategy)
+            .build();
+        let (env, goal) = lower(program_text, goal_text, solver_choice);
+        c.bench_function(&format!("{}/{}", name, strategy_name), move |b| {
+            b.iter(|| black_box(solver_choice.solve_root_goal(&env, &goal)))
+        });
+    }
+}
+
+/// Iterator adapters wrapped `depth` times around each other, each of
+/// whose items is the item of the iterator it wraps.
+fn projection_chain(c: &mut Criterion) {
+    let program_text = "
+        trait Iterator { type Item; }
+
+        struct u32 { }
+        struct Once<T> { }
+        struct Wrap<I> { }
+
+        impl<T> Iterator for Once<T> { type Item = T; }
+        impl<I> Iterator for Wrap<I> where I: Iterator { type Item = <I as Iterator>::Item; }
+    ";
+
+    let depth = 16;
+    let mut iterator = "Once<u32>".to_string();
+    for _ in 0..depth {
+        iterator = format!("Wrap<{}>", iterator);
+    }
+    let goal_text = format!(
+        "exists<T> {{ Normalize(<{} as Iterator>::Item -> T) }}",
+        iterator
+    );
+    bench_goal(c, "projection_chain", program_text, &goal_text, depth + 4);
+}
+
+/// A trait with an impl for each of many structs, and a blanket impl
+/// that makes the solver go through all of them.
+fn wide_impls(c: &mut Criterion) {
+    let width = 200;
+    let mut program_text = "
+        trait Trait { }
+        struct Vec<T> { }
+        impl<T> Trait for Vec<T> where T: Trait { }
+    "
+    .to_string();
+    for i in 0..width {
+        program_text.push_str(&format!("struct S{i} {{ }} impl Trait for S{i} {{ }}\n", i = i));
+    }
+
+    let goal_text = format!("Vec<Vec<S{}>>: Trait", width - 1);
+    bench_goal(c, "wide_impls/unique", &program_text, &goal_text, 10);
+    bench_goal(c, "wide_impls/enumerate", &program_text, "exists<T> { T: Trait }", 10);
+}
+
+/// An auto trait, which the solver proves of structs through their
+/// fields, for a struct nested `depth` structs deep.
+fn auto_traits(c: &mut Criterion) {
+    let depth = 12;
+    let mut program_text = "
+        #[auto] trait Send { }
+        struct u32 { }
+        struct Rc<T> { }
+        impl<T> !Send for Rc<T> { }
+        struct L0<T> { value: T }
+    "
+    .to_string();
+    for i in 1..=depth {
+        program_text.push_str(&format!(
+            "struct L{i}<T> {{ left: L{j}<T>, right: L{j}<u32>, value: T }}\n",
+            i = i,
+            j = i - 1
+        ));
+    }
+
+    bench_goal(
+        c,
+        "auto_traits/send",
+        &program_text,
+        &format!("L{}<u32>: Send", depth),
+        10,
+    );
+    bench_goal(
+        c,
+        "auto_traits/not_send",
+        &program_text,
+        &format!("L{}<Rc<u32>>: Send", depth),
+        10,
+    );
+}
+
+/// Many impls of a generic trait, which coherence checks against each
+/// other pairwise. This measures lowering and checking the program, not
+/// solving.
+fn coherence(c: &mut Criterion) {
+    let n = 12;
+    let mut program_text = "trait Convert<T> { }\nstruct Box<T> { }\n".to_string();
+    for i in 0..n {
+        program_text.push_str(&format!("struct S{} {{ }}\n", i));
+    }
+    for i in 0..n {
+        for j in 0..n {
+            program_text.push_str(&format!("impl Convert<S{}> for S{} {{ }}\n", j, i));
+        }
+        program_text.push_str(&format!(
+            "impl<T> Convert<Box<T>> for S{} where S{}: Convert<T> {{ }}\n",
+            i, i
+        ));
+    }
+
+    let program_text = Arc::new(program_text);
+    c.bench_function("coherence/check_program", move |b| {
+        b.iter(|| {
+            ChalkDatabase::with_program(program_text.clone(), SolverChoice::default(), |db| {
+                black_box(db.checked_program().unwrap())
+            })
+        })
+    });
+}
+
+criterion_group!(benches, projection_chain, wide_impls, auto_traits, coherence);
+criterion_main!(benches);
@@ -0,0 +1,223 @@
+//! Derives for the traits of `chalk-ir` that are implemented the same
+//! way for almost every type of the IR:
+//!
+//! - `Fold` folds each field in turn and rebuilds the value;
+//! - `Zip` requires both values to be the same variant and zips their
+//!   fields pairwise;
+//! - `Cast` casts the field of each variant marked `#[cast]` into the
+//!   enum, by wrapping it in that variant.
+//!
+//! Each type parameter of the type must implement the derived trait.
+//! For `Fold`, a type parameter `T` becomes `T::Result` in the type of
+//! the folded value.
+//!
+//! The generated code names the traits through `::chalk_ir` and
+//! `::chalk_engine`, so both crates must be in scope where the derives
+//! are used.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Ident, Path};
+
+#[proc_macro_derive(Fold)]
+pub fn derive_fold(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let params = match type_parameters(&input) {
+        Ok(params) => params,
+        Err(error) => return error.to_compile_error().into(),
+    };
+
+    let mut generics = input.generics.clone();
+    for param in &params {
+        generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(#param: ::chalk_ir::fold::Fold));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let result = if params.is_empty() {
+        quote!(#name)
+    } else {
+        quote!(#name<#(#params::Result),*>)
+    };
+
+    let arms = variants(&input).into_iter().map(|(path, fields)| {
+        let bindings = bindings(fields, "a");
+        let pattern = construct(&path, fields, &bindings);
+        let folded: Vec<_> = bindings
+            .iter()
+            .map(|binding| quote!(::chalk_ir::fold::Fold::fold_with(#binding, folder, binders)?))
+            .collect();
+        let value = construct(&path, fields, &folded);
+        quote!(#pattern => Ok(#value),)
+    });
+
+    TokenStream::from(quote! {
+        impl #impl_generics ::chalk_ir::fold::Fold for #name #ty_generics #where_clause {
+            type Result = #result;
+
+            fn fold_with(
+                &self,
+                folder: &mut dyn ::chalk_ir::fold::Folder,
+                binders: usize,
+            ) -> ::chalk_engine::fallible::Fallible<Self::Result> {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(Zip)]
+pub fn derive_zip(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let params = match type_parameters(&input) {
+        Ok(params) => params,
+        Err(error) => return error.to_compile_error().into(),
+    };
+
+    let mut generics = input.generics.clone();
+    for param in &params {
+        generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(#param: ::chalk_ir::zip::Zip));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let variants = variants(&input);
+    let mismatch = if variants.len() > 1 {
+        quote!(_ => Err(::chalk_engine::fallible::NoSolution),)
+    } else {
+        quote!()
+    };
+    let arms = variants.into_iter().map(|(path, fields)| {
+        let a_bindings = bindings(fields, "a");
+        let b_bindings = bindings(fields, "b");
+        let a_pattern = construct(&path, fields, &a_bindings);
+        let b_pattern = construct(&path, fields, &b_bindings);
+        quote! {
+            (#a_pattern, #b_pattern) => {
+                #(::chalk_ir::zip::Zip::zip_with(zipper, #a_bindings, #b_bindings)?;)*
+                Ok(())
+            }
+        }
+    });
+
+    TokenStream::from(quote! {
+        impl #impl_generics ::chalk_ir::zip::Zip for #name #ty_generics #where_clause {
+            fn zip_with<Z: ::chalk_ir::zip::Zipper>(
+                zipper: &mut Z,
+                a: &Self,
+                b: &Self,
+            ) -> ::chalk_engine::fallible::Fallible<()> {
+                match (a, b) {
+                    #(#arms)*
+                    #mismatch
+                }
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(Cast, attributes(cast))]
+pub fn derive_cast(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let data = match &input.data {
+        Data::Enum(data) if input.generics.params.is_empty() => data,
+        _ => {
+            let message = "`Cast` can only be derived for enums without parameters";
+            return syn::Error::new_spanned(&input, message)
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let mut impls = vec![];
+    for variant in &data.variants {
+        if !variant.attrs.iter().any(|attr| attr.path.is_ident("cast")) {
+            continue;
+        }
+
+        let variant_name = &variant.ident;
+        let field_ty = match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => &fields.unnamed[0].ty,
+            _ => {
+                let message = "`#[cast]` variants must have exactly one field";
+                return syn::Error::new_spanned(variant, message)
+                    .to_compile_error()
+                    .into();
+            }
+        };
+        impls.push(quote! {
+            impl ::chalk_ir::cast::Cast<#name> for #field_ty {
+                fn cast(self) -> #name {
+                    #name::#variant_name(self)
+                }
+            }
+        });
+    }
+
+    TokenStream::from(quote!(#(#impls)*))
+}
+
+/// The type parameters of the type the derive is applied to. Lifetime
+/// and const parameters are not supported: nothing in the IR has them.
+fn type_parameters(input: &DeriveInput) -> syn::Result<Vec<Ident>> {
+    if let Some(param) = input.generics.lifetimes().next() {
+        return Err(syn::Error::new_spanned(param, "lifetime parameters are not supported"));
+    }
+    Ok(input
+        .generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect())
+}
+
+/// The path to each constructor of the type (the struct itself, or each
+/// variant of the enum), along with its fields.
+fn variants(input: &DeriveInput) -> Vec<(Path, &Fields)> {
+    let name = &input.ident;
+    match &input.data {
+        Data::Struct(data) => vec![(parse_quote!(#name), &data.fields)],
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| {
+                let variant_name = &variant.ident;
+                (parse_quote!(#name::#variant_name), &variant.fields)
+            })
+            .collect(),
+        Data::Union(_) => panic!("the IR derives do not support unions"),
+    }
+}
+
+/// One fresh name per field, to bind the fields in patterns.
+fn bindings(fields: &Fields, prefix: &str) -> Vec<TokenStream2> {
+    (0..fields.iter().count())
+        .map(|index| {
+            let binding = Ident::new(&format!("{}_{}", prefix, index), Span::call_site());
+            quote!(#binding)
+        })
+        .collect()
+}
+
+/// Builds the constructor `path` with `values` for `fields`, which is a
+/// pattern or an expression depending on `values`.
+fn construct(path: &Path, fields: &Fields, values: &[TokenStream2]) -> TokenStream2 {
+    match fields {
+        Fields::Named(fields) => {
+            let names = fields.named.iter().map(|field| &field.ident);
+            quote!(#path { #(#names: #values),* })
+        }
+        Fields::Unnamed(_) => quote!(#path(#(#values),*)),
+        Fields::Unit => quote!(#path),
+    }
+}
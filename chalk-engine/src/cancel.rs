@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A handle through which an embedder can cooperatively abort a
+/// long-running query (see `Forest::solve_with_cancellation`). The
+/// solver polls the token at every step of the search, so a
+/// cancellation is noticed soon, even in a long quantum.
+///
+/// A token may also cap the memory the solver's tables may grow by
+/// during the query (see `limit_memory`), so that a pathological
+/// program cannot exhaust the memory of the host.
+///
+/// Tokens are cheap to clone; all clones share the same cancellation
+/// flag, so one clone may be handed to the solver while another is
+/// kept around (e.g. on another thread) to call `cancel`.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    canceled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+    max_memory: Option<usize>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is only canceled by an explicit call to
+    /// `cancel`.
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Creates a token that is canceled once `deadline` has passed
+    /// (or by an explicit call to `cancel`).
+    pub fn with_deadline(deadline: Instant) -> Self {
+        CancellationToken {
+            deadline: Some(deadline),
+            ..CancellationToken::default()
+        }
+    }
+
+    /// Creates a token that is canceled once `timeout` has elapsed,
+    /// starting from now.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        CancellationToken::with_deadline(Instant::now() + timeout)
+    }
+
+    /// Returns a token that, in addition, aborts the query with
+    /// `Canceled::ResourceExhausted` once the solver's tables have
+    /// grown by more than `max_memory` bytes since the query started
+    /// (see `Forest::memory_usage`). Tables left over from earlier
+    /// queries do not count against the limit.
+    pub fn limit_memory(self, max_memory: usize) -> Self {
+        CancellationToken {
+            max_memory: Some(max_memory),
+            ..self
+        }
+    }
+
+    /// Requests that any query using this token (or a clone of it)
+    /// be aborted.
+    pub fn cancel(&self) {
+        self.canceled.store(true, Ordering::SeqCst);
+    }
+
+    /// True if `cancel` was invoked or the deadline has passed.
+    pub fn is_canceled(&self) -> bool {
+        self.check(0).is_err()
+    }
+
+    /// Called by the solver when it polls the token, with the memory
+    /// its tables have grown by since the query started; tells whether
+    /// (and why) the query should be aborted.
+    pub fn check(&self, memory_used: usize) -> Result<(), Canceled> {
+        if self.canceled.load(Ordering::SeqCst) {
+            return Err(Canceled::Requested);
+        }
+
+        if let Some(max_memory) = self.max_memory {
+            if memory_used > max_memory {
+                return Err(Canceled::ResourceExhausted {
+                    memory_used,
+                    max_memory,
+                });
+            }
+        }
+
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(Canceled::DeadlinePassed),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Indicates that a query was aborted through its `CancellationToken`
+/// before a solution could be found, and why.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Canceled {
+    /// `CancellationToken::cancel` was invoked.
+    Requested,
+
+    /// The deadline of the token passed.
+    DeadlinePassed,
+
+    /// The solver's tables grew by `memory_used` bytes during the
+    /// query, more than the `max_memory` the token allows.
+    ResourceExhausted { memory_used: usize, max_memory: usize },
+}
@@ -49,7 +49,7 @@ pub trait Context: Clone + Debug {
     /// binder. See [the rustc-guide] for more information.
     ///
     /// [the rustc-guide]: https://rust-lang-nursery.github.io/rustc-guide/traits-canonicalization.html
-    type CanonicalGoalInEnvironment: Debug;
+    type CanonicalGoalInEnvironment: Debug + Clone + Eq + Hash;
 
     /// A u-canonicalized `GoalInEnvironment` -- this is one where the
     /// free universes are renumbered to consecutive integers starting
@@ -69,7 +69,7 @@ pub trait Context: Clone + Debug {
 
     /// Represents a substitution from the "canonical variables" found
     /// in a canonical goal to specific values.
-    type Substitution: Debug;
+    type Substitution: Clone + Debug;
 
     /// Represents a region constraint that will be propagated back
     /// (but not verified).
@@ -105,7 +105,7 @@ pub trait Context: Clone + Debug {
     ///
     /// `resolvent_clause` combines a program-clause and a concrete
     /// goal we are trying to solve to produce an ex-clause.
-    type ProgramClause: Debug;
+    type ProgramClause: Clone + Debug;
 
     /// A vector of program clauses.
     type ProgramClauses: Debug;
@@ -125,12 +125,61 @@ pub trait Context: Clone + Debug {
         environment: &Self::Environment,
         goal: Self::Goal,
     ) -> Self::GoalInEnvironment;
+
+    /// An estimate of the bytes of heap memory held by `goal`, which
+    /// the tables count against their memory budget (see
+    /// `CancellationToken::limit_memory`).
+    fn goal_heap_size(goal: &Self::UCanonicalGoalInEnvironment) -> usize;
+
+    /// As `goal_heap_size`, for the ex-clause of a strand.
+    fn ex_clause_heap_size(ex_clause: &Self::CanonicalExClause) -> usize;
+
+    /// As `goal_heap_size`, for the substitution of an answer.
+    fn constrained_subst_heap_size(subst: &Self::CanonicalConstrainedSubst) -> usize;
 }
 
 pub trait ContextOps<C: Context>: Sized + Clone + Debug + AggregateOps<C> {
     /// True if this is a coinductive goal -- e.g., proving an auto trait.
     fn is_coinductive(&self, goal: &C::UCanonicalGoalInEnvironment) -> bool;
 
+    /// The maximum depth of the stack of subgoals being solved. A
+    /// subgoal that would go any deeper is treated as an overflow: it
+    /// is neither proven nor disproven. `None` means there is no limit.
+    fn max_stack_depth(&self) -> Option<usize> {
+        None
+    }
+
+    /// If true, the strands of a table are explored breadth-first:
+    /// once a strand has made one step of progress (resolving a
+    /// subgoal with one answer), it goes to the back of the queue and
+    /// the table's other strands get their turn. Otherwise, a strand
+    /// is pursued until it yields an answer or fails, which is usually
+    /// faster but can starve the other strands when one of them keeps
+    /// going around a recursive clause.
+    fn breadth_first(&self) -> bool {
+        false
+    }
+
+    /// If true, each answer records how it was derived (see
+    /// `Forest::derivation`), so that a proof can be given for it. This
+    /// costs a little time and memory for every strand (default: false).
+    fn record_proofs(&self) -> bool {
+        false
+    }
+
+    /// When less than this many bytes of stack are left while pursuing
+    /// a strand, the solver moves on to a fresh stack segment (only
+    /// with the `stack_protection` feature).
+    fn stack_red_zone(&self) -> usize {
+        crate::DEFAULT_STACK_RED_ZONE
+    }
+
+    /// The size in bytes of the stack segments allocated when the
+    /// solver runs into the red zone (see `stack_red_zone`).
+    fn stack_segment_size(&self) -> usize {
+        crate::DEFAULT_STACK_SEGMENT_SIZE
+    }
+
     /// Create an inference table for processing a new goal and instantiate that goal
     /// in that context, returning "all the pieces".
     ///
@@ -260,6 +309,9 @@ pub trait InferenceTable<C: Context, I: Context>:
 
     /// Create a "cannot prove" goal (see `HhGoal::CannotProve`).
     fn cannot_prove(&self) -> I::Goal;
+
+    /// Create the disjunction of `goals` (see `HhGoal::Any`).
+    fn any_goal(&self, goals: Vec<I::Goal>) -> I::Goal;
 }
 
 /// Methods for unifying and manipulating terms and binders.
@@ -307,6 +359,9 @@ pub trait UnificationOps<C: Context, I: Context> {
 
     fn lift_delayed_literal(&self, value: DelayedLiteral<I>) -> DelayedLiteral<C>;
 
+    // Used by: logic (when recording proofs)
+    fn lift_program_clause(&self, clause: &I::ProgramClause) -> C::ProgramClause;
+
     // Used by: logic
     fn invert_goal(&mut self, value: &I::GoalInEnvironment) -> Option<I::GoalInEnvironment>;
 
@@ -379,3 +434,20 @@ pub trait AnswerStream<C: Context> {
     fn any_future_answer(&mut self, test: impl FnMut(&C::InferenceNormalizedSubst) -> bool)
         -> bool;
 }
+
+impl<'a, C: Context, A: AnswerStream<C>> AnswerStream<C> for &'a mut A {
+    fn peek_answer(&mut self) -> Option<SimplifiedAnswer<C>> {
+        (**self).peek_answer()
+    }
+
+    fn next_answer(&mut self) -> Option<SimplifiedAnswer<C>> {
+        (**self).next_answer()
+    }
+
+    fn any_future_answer(
+        &mut self,
+        test: impl FnMut(&C::InferenceNormalizedSubst) -> bool,
+    ) -> bool {
+        (**self).any_future_answer(test)
+    }
+}
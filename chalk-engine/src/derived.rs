@@ -23,6 +23,44 @@ impl<C: Context> Eq for DelayedLiteralSet<C> {}
 
 ///////////////////////////////////////////////////////////////////////////
 
+impl<C: Context> PartialEq for SimplifiedAnswer<C> {
+    fn eq(&self, other: &Self) -> bool {
+        let SimplifiedAnswer {
+            subst: a1,
+            ambiguous: b1,
+            conditional: c1,
+        } = self;
+        let SimplifiedAnswer {
+            subst: a2,
+            ambiguous: b2,
+            conditional: c2,
+        } = other;
+        a1 == a2 && b1 == b2 && c1 == c2
+    }
+}
+
+impl<C: Context> Eq for SimplifiedAnswer<C> {}
+
+///////////////////////////////////////////////////////////////////////////
+
+impl<C: Context> PartialEq for Flounder<C> {
+    fn eq(&self, other: &Self) -> bool {
+        let Flounder {
+            goal_stack: a1,
+            subgoal: b1,
+        } = self;
+        let Flounder {
+            goal_stack: a2,
+            subgoal: b2,
+        } = other;
+        a1 == a2 && b1 == b2
+    }
+}
+
+impl<C: Context> Eq for Flounder<C> {}
+
+///////////////////////////////////////////////////////////////////////////
+
 impl<C: Context> PartialEq for DelayedLiteral<C> {
     fn eq(&self, other: &Self) -> bool {
         if mem::discriminant(self) != mem::discriminant(other) {
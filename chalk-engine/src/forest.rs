@@ -1,17 +1,75 @@
+use crate::cancel::{CancellationToken, Canceled};
 use crate::context::prelude::*;
 use crate::context::AnswerStream;
+use crate::listener::EventListener;
 use crate::logic::RootSearchFail;
+use crate::snapshot::{ForestSnapshot, TableSnapshot};
 use crate::stack::{Stack, StackIndex};
-use crate::table::{Answer, AnswerIndex};
+use crate::table::{Answer, AnswerIndex, Table};
 use crate::tables::Tables;
-use crate::{DepthFirstNumber, SimplifiedAnswer, TableIndex};
+use crate::{
+    DepthFirstNumber, Derivation, Flounder, Overflow, OverflowKind, SimplifiedAnswer, TableIndex,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// The most u-canonical forms `Forest::u_canonicalize_goal` memoizes;
+/// once there are that many, it starts over.
+const MAX_U_CANONICAL_GOALS: usize = 4096;
+
+type UCanonicalGoal<C> = (
+    <C as Context>::UCanonicalGoalInEnvironment,
+    <C as Context>::UniverseMap,
+);
+
+/// How often a memo had the result looked for.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoStats {
+    pub hits: usize,
+    pub misses: usize,
+}
 
 pub struct Forest<C: Context, CO: ContextOps<C>> {
-    #[allow(dead_code)]
     pub(crate) context: CO,
     pub(crate) tables: Tables<C>,
     pub(crate) stack: Stack,
 
+    /// Every time the search overflows, we record the goal stack at
+    /// that point here (see `Overflow`).
+    pub(crate) overflows: Vec<Overflow<C>>,
+
+    /// Likewise, every negative subgoal that floundered (see
+    /// `Flounder`).
+    pub(crate) flounders: Vec<Flounder<C>>,
+
+    /// The tables that were on the stack when the search went deeper
+    /// than `ContextOps::max_stack_depth` allows. Their answers depend
+    /// on how deep in the stack their goals were reached, so they are
+    /// started over before the next root goal (see
+    /// `get_or_create_root_table`), so as not to depend on which goals
+    /// were solved before.
+    depth_overflowed: FxHashSet<TableIndex>,
+
+    /// Told about the progress of the search, if installed (see
+    /// `set_event_listener`).
+    pub(crate) listener: Option<Box<dyn EventListener<C>>>,
+
+    /// The token of the query being solved, if it can be canceled. It
+    /// is polled at every step of the search (see `poll_cancellation`).
+    cancel: Option<CancellationToken>,
+
+    /// `memory_usage` when the query being solved started; only what
+    /// the tables grow by beyond this counts against the token's limit.
+    query_memory: usize,
+
+    /// Why the query being solved was canceled, once it was.
+    canceled: Option<Canceled>,
+
+    /// Memoizes the u-canonical form (and universe map) of the
+    /// subgoals met while solving the current root goal (see
+    /// `u_canonicalize_goal`).
+    u_canonical_goals: FxHashMap<C::CanonicalGoalInEnvironment, UCanonicalGoal<C>>,
+    u_canonical_goal_stats: MemoStats,
+
     dfn: DepthFirstNumber,
 }
 
@@ -21,10 +79,78 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             context,
             tables: Tables::new(),
             stack: Stack::default(),
+            overflows: Vec::new(),
+            flounders: Vec::new(),
+            depth_overflowed: FxHashSet::default(),
+            listener: None,
+            cancel: None,
+            query_memory: 0,
+            canceled: None,
+            u_canonical_goals: FxHashMap::default(),
+            u_canonical_goal_stats: MemoStats::default(),
             dfn: DepthFirstNumber::MIN,
         }
     }
 
+    pub fn context(&self) -> &CO {
+        &self.context
+    }
+
+    /// Installs `listener` to be told about the progress of every
+    /// search from now on, replacing any listener installed before.
+    pub fn set_event_listener(&mut self, listener: Box<dyn EventListener<C>>) {
+        self.listener = Some(listener);
+    }
+
+    /// Removes the installed event listener, if any, and returns it.
+    pub fn take_event_listener(&mut self) -> Option<Box<dyn EventListener<C>>> {
+        self.listener.take()
+    }
+
+    /// Gets or creates the table for `goal`, which the search starts
+    /// from. The tables whose answers depend on overflowing the depth
+    /// of the stack while solving earlier root goals are started over
+    /// first, along with the tables that used their answers.
+    fn get_or_create_root_table(&mut self, goal: C::UCanonicalGoalInEnvironment) -> TableIndex {
+        assert!(self.stack.is_empty());
+        self.u_canonical_goals.clear();
+        if !self.depth_overflowed.is_empty() {
+            let overflowed = self.depth_overflowed.drain().collect();
+            self.restart_tables(overflowed);
+        }
+        self.get_or_create_table_for_ucanonical_goal(goal)
+    }
+
+    /// U-canonicalizes `goal`, a subgoal of the root goal being solved.
+    /// The same subgoals come up again and again during a search, and
+    /// u-canonicalizing one means folding it twice, so the results are
+    /// memoized until the next root goal (or until there are
+    /// `MAX_U_CANONICAL_GOALS` of them).
+    pub(crate) fn u_canonicalize_goal<I: Context>(
+        &mut self,
+        infer: &mut dyn InferenceTable<C, I>,
+        goal: &C::CanonicalGoalInEnvironment,
+    ) -> UCanonicalGoal<C> {
+        if let Some(result) = self.u_canonical_goals.get(goal) {
+            self.u_canonical_goal_stats.hits += 1;
+            return result.clone();
+        }
+
+        self.u_canonical_goal_stats.misses += 1;
+        let result = infer.u_canonicalize_goal(goal);
+        if self.u_canonical_goals.len() >= MAX_U_CANONICAL_GOALS {
+            self.u_canonical_goals.clear();
+        }
+        self.u_canonical_goals.insert(goal.clone(), result.clone());
+        result
+    }
+
+    /// How often a subgoal's u-canonical form was found memoized (see
+    /// `u_canonicalize_goal`), across all goals solved so far.
+    pub fn u_canonical_goal_stats(&self) -> MemoStats {
+        self.u_canonical_goal_stats
+    }
+
     // Gets the next depth-first number. This number never decreases.
     pub(super) fn next_dfn(&mut self) -> DepthFirstNumber {
         self.dfn.next()
@@ -40,7 +166,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         goal: C::UCanonicalGoalInEnvironment,
         num_answers: usize,
     ) -> Vec<Answer<C>> {
-        let table = self.get_or_create_table_for_ucanonical_goal(goal);
+        let table = self.get_or_create_root_table(goal);
         let mut answers = Vec::with_capacity(num_answers);
         for i in 0..num_answers {
             let i = AnswerIndex::from(i);
@@ -65,13 +191,34 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     fn iter_answers<'f>(
         &'f mut self,
         goal: &C::UCanonicalGoalInEnvironment,
-    ) -> impl AnswerStream<C> + 'f {
-        let table = self.get_or_create_table_for_ucanonical_goal(goal.clone());
+    ) -> ForestSolver<'f, C, CO> {
+        let table = self.get_or_create_root_table(goal.clone());
         let answer = AnswerIndex::ZERO;
         ForestSolver {
             forest: self,
             table,
             answer,
+            canceled: false,
+        }
+    }
+
+    /// Returns an iterator over the distinct answers to `goal`. Unlike
+    /// `solve`, which aggregates all answers into a single solution,
+    /// this yields each answer as the SLG engine finds it, doing only
+    /// as much work as is required to produce the next one.
+    ///
+    /// Note that the iterator may never terminate if `goal` has an
+    /// infinite number of answers.
+    pub fn solve_multiple<'f>(
+        &'f mut self,
+        goal: &C::UCanonicalGoalInEnvironment,
+    ) -> impl Iterator<Item = SimplifiedAnswer<C>> + 'f {
+        let table = self.get_or_create_root_table(goal.clone());
+        ForestSolver {
+            forest: self,
+            table,
+            answer: AnswerIndex::ZERO,
+            canceled: false,
         }
     }
 
@@ -84,6 +231,81 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             .make_solution(CO::canonical(&goal), self.iter_answers(goal))
     }
 
+    /// Like `solve`, but polls `cancel` at every step of the search
+    /// and gives up with `Err` once it has been canceled, saying why.
+    /// Work done up to that point remains cached in the forest, so a
+    /// later attempt at the same goal picks up where this one left off.
+    pub fn solve_with_cancellation(
+        &mut self,
+        goal: &C::UCanonicalGoalInEnvironment,
+        cancel: &CancellationToken,
+    ) -> Result<Option<C::Solution>, Canceled> {
+        self.with_cancellation(cancel, |forest| {
+            let context = forest.context.clone();
+            let mut answers = forest.iter_answers(goal);
+            let solution = context.make_solution(CO::canonical(&goal), &mut answers);
+            (solution, answers.canceled)
+        })
+    }
+
+    /// Finds the first answer to `goal`, if there is one, and stops
+    /// there: unlike `solve`, this does not go on to check whether
+    /// the answer is the only one. This is enough for callers that
+    /// only need to know whether `goal` can be proven, and much
+    /// cheaper when there are many ways to prove it.
+    pub fn solve_any(
+        &mut self,
+        goal: &C::UCanonicalGoalInEnvironment,
+    ) -> Option<SimplifiedAnswer<C>> {
+        self.iter_answers(goal).next_answer()
+    }
+
+    /// Like `solve_any`, but polls `cancel` as `solve_with_cancellation`
+    /// does.
+    pub fn solve_any_with_cancellation(
+        &mut self,
+        goal: &C::UCanonicalGoalInEnvironment,
+        cancel: &CancellationToken,
+    ) -> Result<Option<SimplifiedAnswer<C>>, Canceled> {
+        self.with_cancellation(cancel, |forest| {
+            let mut answers = forest.iter_answers(goal);
+            let answer = answers.next_answer();
+            (answer, answers.canceled)
+        })
+    }
+
+    /// Runs `op` with `cancel` as the token of the query being solved;
+    /// `op` tells whether it gave up because the token was canceled.
+    fn with_cancellation<R>(
+        &mut self,
+        cancel: &CancellationToken,
+        op: impl FnOnce(&mut Self) -> (R, bool),
+    ) -> Result<R, Canceled> {
+        cancel.check(0)?;
+
+        self.cancel = Some(cancel.clone());
+        self.query_memory = self.memory_usage();
+        let (result, canceled) = op(self);
+        self.cancel = None;
+
+        match self.canceled.take() {
+            Some(reason) if canceled => Err(reason),
+            _ => Ok(result),
+        }
+    }
+
+    /// Polls the token of the query being solved, if any, and tells
+    /// whether the query was canceled (remembering why, the first time).
+    pub(crate) fn poll_cancellation(&mut self) -> bool {
+        if self.canceled.is_none() {
+            if let Some(cancel) = &self.cancel {
+                let memory_used = self.memory_usage().saturating_sub(self.query_memory);
+                self.canceled = cancel.check(memory_used).err();
+            }
+        }
+        self.canceled.is_some()
+    }
+
     /// True if all the tables on the stack starting from `depth` and
     /// continuing until the top of the stack are coinductive.
     ///
@@ -117,9 +339,234 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         })
     }
 
+    /// Discards all tables, and hence every answer cached so far. The
+    /// forest otherwise retains its tables across calls to `solve` (and
+    /// friends), so that solving a goal -- or any goal that shares
+    /// subgoals with it -- a second time is cheap.
+    pub fn clear_cache(&mut self) {
+        assert!(self.stack.is_empty());
+        self.tables = Tables::new();
+        self.u_canonical_goals.clear();
+        self.overflows.clear();
+        self.flounders.clear();
+        self.depth_overflowed.clear();
+    }
+
+    /// Replaces the forest's context with `context` (typically, one
+    /// for an edited version of the program), discarding the work
+    /// that may no longer be valid under it. That is the work for
+    /// every goal for which `stale` returns true, and for every goal
+    /// that (directly or indirectly) used the answers to one of those.
+    /// The other tables are kept. Returns the number of tables that
+    /// were discarded.
+    ///
+    /// Like `clear_cache`, this must not be called during a search.
+    pub fn invalidate(
+        &mut self,
+        context: CO,
+        stale: impl Fn(&C::UCanonicalGoalInEnvironment) -> bool,
+    ) -> usize {
+        assert!(self.stack.is_empty());
+        self.context = context;
+
+        let stale = self
+            .tables
+            .indices()
+            .filter(|&table| stale(&self.tables[table].table_goal))
+            .collect();
+        let num_invalid = self.restart_tables(stale);
+        if num_invalid > 0 {
+            self.overflows.clear();
+            self.flounders.clear();
+        }
+        num_invalid
+    }
+
+    /// Starts `tables` over, along with every table that (directly or
+    /// indirectly) used their answers, and returns how many tables that
+    /// is. Each table keeps its index: any table that refers to it is
+    /// started over too.
+    fn restart_tables(&mut self, tables: Vec<TableIndex>) -> usize {
+        let mut invalid: FxHashSet<TableIndex> = FxHashSet::default();
+        let mut worklist = tables;
+        while let Some(table) = worklist.pop() {
+            if invalid.insert(table) {
+                worklist.extend(self.tables[table].dependents.iter().cloned());
+            }
+        }
+
+        for &table in &invalid {
+            let goal = self.tables[table].table_goal.clone();
+            let coinductive_goal = self.context.is_coinductive(&goal);
+            self.tables[table] = Table::new(goal, coinductive_goal, self.tables.memory());
+        }
+        for &table in &invalid {
+            self.push_initial_strands(table);
+        }
+        invalid.len()
+    }
+
+    /// Returns the overflows encountered so far by this forest, across
+    /// all goals it has solved. An ambiguous solution can often be
+    /// explained by one of these.
+    pub fn overflows(&self) -> &[Overflow<C>] {
+        &self.overflows
+    }
+
+    /// Returns the negative subgoals that floundered so far, across
+    /// all goals this forest has solved. An ambiguous solution to a
+    /// goal involving `not { .. }` can often be explained by one of
+    /// these.
+    pub fn flounders(&self) -> &[Flounder<C>] {
+        &self.flounders
+    }
+
+    fn goal_stack(&self) -> Vec<C::UCanonicalGoalInEnvironment> {
+        self.stack
+            .iter()
+            .map(|entry| self.tables[entry.table].table_goal.clone())
+            .collect()
+    }
+
+    /// Records an overflow, along with the current goal stack.
+    pub(super) fn record_overflow(&mut self, kind: OverflowKind) {
+        if kind == OverflowKind::Depth {
+            let stack = &self.stack;
+            self.depth_overflowed.extend(stack.iter().map(|entry| entry.table));
+        }
+        let goal_stack = self.goal_stack();
+        if !self
+            .overflows
+            .iter()
+            .any(|overflow| overflow.kind == kind && overflow.goal_stack == goal_stack)
+        {
+            self.overflows.push(Overflow { kind, goal_stack });
+        }
+    }
+
+    /// Records that the negative subgoal `not { subgoal }` floundered,
+    /// along with the current goal stack.
+    pub(super) fn record_flounder(&mut self, subgoal: C::UCanonicalGoalInEnvironment) {
+        let flounder = Flounder {
+            goal_stack: self.goal_stack(),
+            subgoal,
+        };
+        if !self.flounders.contains(&flounder) {
+            self.flounders.push(flounder);
+        }
+    }
+
+    /// Returns a stepper for the answers to `goal`: each call to
+    /// `Stepper::step` pursues a single strand of the search, so that a
+    /// debugger or a visualizer can inspect the tables of the forest
+    /// (see `tables`) in between.
+    pub fn stepper<'f>(
+        &'f mut self,
+        goal: &C::UCanonicalGoalInEnvironment,
+    ) -> Stepper<'f, C, CO> {
+        let table = self.get_or_create_root_table(goal.clone());
+        Stepper {
+            forest: self,
+            table,
+            answer: AnswerIndex::ZERO,
+        }
+    }
+
+    /// The indices of all the tables of the forest, in the order they
+    /// were created.
+    pub fn tables(&self) -> impl Iterator<Item = TableIndex> {
+        self.tables.indices()
+    }
+
+    /// The goal that `table` is trying to solve.
+    pub fn table_goal(&self, table: TableIndex) -> &C::UCanonicalGoalInEnvironment {
+        &self.tables[table].table_goal
+    }
+
+    /// The answers found so far for the goal of `table`.
+    pub fn table_answers(&self, table: TableIndex) -> Vec<SimplifiedAnswer<C>> {
+        self.tables[table]
+            .answers()
+            .iter()
+            .map(|answer| answer.simplified())
+            .collect()
+    }
+
+    /// The table for `goal`, if there is one yet.
+    pub fn table_for_goal(&self, goal: &C::UCanonicalGoalInEnvironment) -> Option<TableIndex> {
+        self.tables.index_of(goal)
+    }
+
+    /// The substitution of the `answer`-th answer of `table`, along with
+    /// how the solver derived it. Only unconditional answers have a
+    /// derivation, and only if the context records them (see
+    /// `ContextOps::record_proofs`).
+    pub fn derivation(
+        &self,
+        table: TableIndex,
+        answer: usize,
+    ) -> Option<(&C::CanonicalConstrainedSubst, &Derivation<C>)> {
+        let answer = self.tables[table].answer(AnswerIndex::from(answer))?;
+        if !answer.is_unconditional() {
+            return None;
+        }
+        let derivation = answer.derivation.as_ref()?;
+        Some((&answer.subst, derivation))
+    }
+
+    /// The number of strands of `table` left to pursue: if there are
+    /// none, the table has all its answers.
+    pub fn table_num_strands(&self, table: TableIndex) -> usize {
+        self.tables[table].num_strands()
+    }
+
+    /// The state of every table of the forest, e.g. to find out why a
+    /// search does not terminate (see the `snapshot` module).
+    pub fn snapshot(&self) -> ForestSnapshot<C> {
+        let mut subgoals: FxHashMap<TableIndex, Vec<TableIndex>> = FxHashMap::default();
+        for table in self.tables() {
+            for &dependent in &self.tables[table].dependents {
+                subgoals.entry(dependent).or_default().push(table);
+            }
+        }
+
+        let tables = self
+            .tables()
+            .map(|table| {
+                let mut subgoals = subgoals.remove(&table).unwrap_or_default();
+                subgoals.sort();
+                let answers = self.tables[table].answers();
+                TableSnapshot {
+                    table,
+                    goal: self.tables[table].table_goal.clone(),
+                    coinductive: self.tables[table].coinductive_goal,
+                    num_answers: answers.len(),
+                    num_ambiguous_answers: answers
+                        .iter()
+                        .filter(|answer| !answer.is_unconditional())
+                        .count(),
+                    num_strands: self.tables[table].num_strands(),
+                    on_stack: self.stack.is_active(table).is_some(),
+                    subgoals,
+                }
+            })
+            .collect();
+        ForestSnapshot { tables }
+    }
+
+    /// An estimate of the memory held by the tables of the forest, in
+    /// bytes: the size of each table, answer and strand, along with the
+    /// heap data of their goals, ex-clauses and substitutions as the
+    /// context estimates it (see `Context::goal_heap_size` and
+    /// `CancellationToken::limit_memory`). It is kept up to date as the
+    /// tables change, so this is cheap.
+    pub fn memory_usage(&self) -> usize {
+        self.tables.memory().get()
+    }
+
     /// Useful for testing.
     pub fn num_cached_answers_for_goal(&mut self, goal: &C::UCanonicalGoalInEnvironment) -> usize {
-        let table = self.get_or_create_table_for_ucanonical_goal(goal.clone());
+        let table = self.get_or_create_root_table(goal.clone());
         self.tables[table].num_cached_answers()
     }
 }
@@ -128,6 +575,10 @@ struct ForestSolver<'forest, C: Context + 'forest, CO: ContextOps<C> + 'forest>
     forest: &'forest mut Forest<C, CO>,
     table: TableIndex,
     answer: AnswerIndex,
+
+    /// Set if the stream ended early because the query was canceled
+    /// (see `Forest::cancel`).
+    canceled: bool,
 }
 
 impl<'forest, C, CO: ContextOps<C>> AnswerStream<C> for ForestSolver<'forest, C, CO>
@@ -135,6 +586,10 @@ where
     C: Context,
 {
     fn peek_answer(&mut self) -> Option<SimplifiedAnswer<C>> {
+        if self.canceled {
+            return None;
+        }
+
         loop {
             match self.forest.ensure_root_answer(self.table, self.answer) {
                 Ok(()) => {
@@ -150,19 +605,19 @@ where
                     // when we could have given a concrete yes/no
                     // answer).
 
-                    let simplified_answer = SimplifiedAnswer {
-                        subst: answer.subst.clone(),
-                        ambiguous: !answer.delayed_literals.is_empty(),
-                    };
-
-                    return Some(simplified_answer);
+                    return Some(answer.simplified());
                 }
 
                 Err(RootSearchFail::NoMoreSolutions) => {
                     return None;
                 }
 
-                Err(RootSearchFail::QuantumExceeded) => {}
+                Err(RootSearchFail::QuantumExceeded) => {
+                    if self.forest.poll_cancellation() {
+                        self.canceled = true;
+                        return None;
+                    }
+                }
             }
         }
     }
@@ -181,3 +636,64 @@ where
         self.forest.any_future_answer(self.table, self.answer, test)
     }
 }
+
+impl<'forest, C, CO: ContextOps<C>> Iterator for ForestSolver<'forest, C, CO>
+where
+    C: Context,
+{
+    type Item = SimplifiedAnswer<C>;
+
+    fn next(&mut self) -> Option<SimplifiedAnswer<C>> {
+        self.next_answer()
+    }
+}
+
+/// The outcome of a step of the search (see `Stepper::step`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step<C: Context> {
+    /// The step found the next answer to the goal.
+    Answer(SimplifiedAnswer<C>),
+
+    /// The step pursued a strand, but did not find an answer yet.
+    Pending,
+
+    /// The goal has no more answers.
+    Done,
+}
+
+/// Searches for the answers to a goal one step at a time (see
+/// `Forest::stepper`).
+pub struct Stepper<'forest, C: Context + 'forest, CO: ContextOps<C> + 'forest> {
+    forest: &'forest mut Forest<C, CO>,
+    table: TableIndex,
+    answer: AnswerIndex,
+}
+
+impl<'forest, C, CO: ContextOps<C>> Stepper<'forest, C, CO>
+where
+    C: Context,
+{
+    /// Pursues a single strand of the search for the next answer. Once
+    /// this returns `Step::Done`, it keeps doing so.
+    pub fn step(&mut self) -> Step<C> {
+        match self.forest.ensure_root_answer(self.table, self.answer) {
+            Ok(()) => {
+                let answer = self.forest.answer(self.table, self.answer).simplified();
+                self.answer.increment();
+                Step::Answer(answer)
+            }
+            Err(RootSearchFail::QuantumExceeded) => Step::Pending,
+            Err(RootSearchFail::NoMoreSolutions) => Step::Done,
+        }
+    }
+
+    /// The table for the goal being solved.
+    pub fn root_table(&self) -> TableIndex {
+        self.table
+    }
+
+    /// The forest, whose tables can be inspected between steps.
+    pub fn forest(&self) -> &Forest<C, CO> {
+        self.forest
+    }
+}
@@ -9,6 +9,15 @@ pub enum HhGoal<C: Context> {
     Exists(C::BindersGoal),
     Implies(C::ProgramClauses, C::Goal),
     And(C::Goal, C::Goal),
+
+    /// The conjunction of any number of goals; like a chain of `And`s,
+    /// but cheaper to fold over when there are many.
+    All(Vec<C::Goal>),
+
+    /// The disjunction of any number of goals: it holds if any of them
+    /// does (and never if there are none).
+    Any(Vec<C::Goal>),
+
     Not(C::Goal),
     Unify(C::Variance, C::Parameter, C::Parameter),
     DomainGoal(C::DomainGoal),
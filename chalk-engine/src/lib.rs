@@ -69,13 +69,16 @@ use rustc_hash::FxHashSet;
 use std::cmp::min;
 use std::usize;
 
+pub mod cancel;
 pub mod context;
 mod derived;
 pub mod fallible;
 pub mod forest;
 pub mod hh;
+pub mod listener;
 mod logic;
 mod simplify;
+pub mod snapshot;
 mod stack;
 mod strand;
 mod table;
@@ -130,7 +133,7 @@ struct SimplifiedAnswers<C: Context> {
     answers: Vec<SimplifiedAnswer<C>>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Debug, PartialOrd, Ord, Hash)]
 pub struct SimplifiedAnswer<C: Context> {
     /// A fully instantiated version of the goal for which the query
     /// is true (including region constraints).
@@ -142,6 +145,109 @@ pub struct SimplifiedAnswer<C: Context> {
     /// either because the size of the answer exceeded `max_size` or
     /// because of a negative loop (e.g., `P :- not { P }`).
     pub ambiguous: bool,
+
+    /// Set (along with `ambiguous`) if the answer is conditional: it
+    /// holds if some negative subgoals it depends on, which could not be
+    /// decided (as in a negative loop), turn out to fail. Not set if the
+    /// answer, or any answer it depends on, was truncated, floundered
+    /// or overflowed.
+    pub conditional: bool,
+}
+
+/// Records that the search overflowed: subgoals nested too deeply, or
+/// some answer or negative subgoal grew larger than the context
+/// permits (see `OverflowKind`), and so the solver had to fall back to
+/// an approximate (ambiguous) result. This usually indicates a
+/// recursive clause that keeps producing bigger and bigger types.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Overflow<C: Context> {
+    pub kind: OverflowKind,
+
+    /// The chain of goals that led to the overflow, starting from
+    /// the root goal. Each goal was being solved as a subgoal of the
+    /// one before it; the last goal is the one whose search overflowed.
+    pub goal_stack: Vec<C::UCanonicalGoalInEnvironment>,
+}
+
+/// Which limit a search ran into (see `Overflow`).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OverflowKind {
+    /// The stack of subgoals grew deeper than
+    /// `Context::max_stack_depth`.
+    Depth,
+
+    /// An answer, or a goal under `not { .. }`, grew larger than the
+    /// context permits and had to be truncated (see `TruncateOps`).
+    /// Positive subgoals are truncated too, but that does not make the
+    /// result any less precise, and so is not an overflow.
+    Size,
+}
+
+/// Records that the search floundered: a negative subgoal `not { G }`
+/// was selected while `G` still contained unresolved inference
+/// variables, so the solver could neither prove nor disprove it and
+/// had to fall back to an ambiguous result.
+#[derive(Clone, Debug)]
+pub struct Flounder<C: Context> {
+    /// The chain of goals whose search selected the negative subgoal,
+    /// starting from the root goal (as in `Overflow::goal_stack`).
+    pub goal_stack: Vec<C::UCanonicalGoalInEnvironment>,
+
+    /// The canonicalized form of `G`, i.e. the negative subgoal with
+    /// the `not` stripped; the unresolved variables show up as its
+    /// canonical binders.
+    pub subgoal: C::UCanonicalGoalInEnvironment,
+}
+
+/// How an answer was derived, when the context records it (see
+/// `ContextOps::record_proofs` and `Forest::derivation`).
+#[derive(Clone, Debug)]
+pub struct Derivation<C: Context> {
+    /// The program clause that the answer's strand started from, or
+    /// `None` if the table's goal is not a domain goal (and so was
+    /// broken down into its subgoals instead).
+    pub clause: Option<C::ProgramClause>,
+
+    /// How each subgoal of the strand was settled, in the order the
+    /// strand settled them.
+    pub subgoals: Vec<DerivedSubgoal>,
+}
+
+impl<C: Context> Derivation<C> {
+    /// The derivation of a strand that starts out by breaking down its
+    /// table's goal, if proofs are being recorded.
+    fn new(record_proofs: bool) -> Option<Self> {
+        if record_proofs {
+            Some(Derivation {
+                clause: None,
+                subgoals: vec![],
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Notes that a strand settled a subgoal, if its derivation is
+    /// being recorded.
+    fn push(derivation: &mut Option<Self>, subgoal: DerivedSubgoal) {
+        if let Some(derivation) = derivation {
+            derivation.subgoals.push(subgoal);
+        }
+    }
+}
+
+/// How a subgoal in a `Derivation` was settled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DerivedSubgoal {
+    /// A positive subgoal, proven by the `answer`-th answer of `table`.
+    Answer { table: TableIndex, answer: usize },
+
+    /// A negative subgoal, which holds because `table` has no answers.
+    Negative { table: TableIndex },
+
+    /// A positive subgoal for a coinductive `table` that was already
+    /// being solved further up the stack, and so holds by the cycle.
+    Cycle { table: TableIndex },
 }
 
 #[derive(Debug)]
@@ -276,11 +382,28 @@ impl<C: Context> DelayedLiteralSet<C> {
         self.delayed_literals.is_empty()
     }
 
+    fn len(&self) -> usize {
+        self.delayed_literals.len()
+    }
+
     fn is_subset(&self, other: &DelayedLiteralSet<C>) -> bool {
         self.delayed_literals
             .iter()
             .all(|elem| other.delayed_literals.contains(elem))
     }
+
+    /// True if the set is not empty and all of its literals might
+    /// still be resolved one way or the other, i.e., none of them is
+    /// `CannotProve`. A literal that depends on an ambiguous answer is
+    /// always delayed along with a `CannotProve`, so this means the
+    /// set only rests on undecided negative literals.
+    fn is_conditional(&self) -> bool {
+        !self.is_empty()
+            && self.delayed_literals.iter().all(|literal| match literal {
+                DelayedLiteral::CannotProve(()) => false,
+                DelayedLiteral::Negative(_) | DelayedLiteral::Positive(..) => true,
+            })
+    }
 }
 
 impl Minimums {
@@ -315,22 +438,29 @@ impl DepthFirstNumber {
     }
 }
 
+// These numbers are somewhat randomly chosen to make tests work
+// well enough on my system. In particular, because we only test
+// for growing the stack in `new_clause`, a red zone of 32K was
+// insufficient to prevent stack overflow. - nikomatsakis
+
+/// The default for `ContextOps::stack_red_zone`.
+pub const DEFAULT_STACK_RED_ZONE: usize = 256 * 1024;
+
+/// The default for `ContextOps::stack_segment_size`.
+pub const DEFAULT_STACK_SEGMENT_SIZE: usize = 2 * 1024 * 1024;
+
 /// Because we recurse so deeply, we rely on stacker to
 /// avoid overflowing the stack.
 #[cfg(feature = "stack_protection")]
-fn maybe_grow_stack<F, R>(op: F) -> R
+fn maybe_grow_stack<F, R>(red_zone: usize, segment_size: usize, op: F) -> R
 where
     F: FnOnce() -> R,
 {
-    // These numbers are somewhat randomly chosen to make tests work
-    // well enough on my system. In particular, because we only test
-    // for growing the stack in `new_clause`, a red zone of 32K was
-    // insufficient to prevent stack overflow. - nikomatsakis
-    stacker::maybe_grow(256 * 1024, 2 * 1024 * 1024, op)
+    stacker::maybe_grow(red_zone, segment_size, op)
 }
 
 #[cfg(not(feature = "stack_protection"))]
-fn maybe_grow_stack<F, R>(op: F) -> R
+fn maybe_grow_stack<F, R>(_red_zone: usize, _segment_size: usize, op: F) -> R
 where
     F: FnOnce() -> R,
 {
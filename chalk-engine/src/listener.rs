@@ -0,0 +1,40 @@
+//! Hooks through which an embedder can watch the solver at work, e.g.
+//! to build a visualizer or a profiler. Install a listener with
+//! `Forest::set_event_listener`.
+
+use crate::context::Context;
+use crate::TableIndex;
+use std::fmt::Debug;
+
+/// Receives an event each time the solver makes some notable step.
+/// Every method does nothing by default, so a listener only needs to
+/// implement the events it cares about.
+///
+/// Tables are identified by their index, which stays the same for as
+/// long as the forest lives; `goal_entered` tells which goal each
+/// table is for.
+pub trait EventListener<C: Context>: Send {
+    /// The solver started solving `goal`, in a new table.
+    fn goal_entered(&mut self, _table: TableIndex, _goal: &C::UCanonicalGoalInEnvironment) {}
+
+    /// The solver tried to prove the goal of `table` with `clause`.
+    /// `applies` tells whether the clause unified with the goal (and
+    /// so whether the solver will go on to prove its conditions).
+    fn clause_tried(&mut self, _table: TableIndex, _clause: &dyn Debug, _applies: bool) {}
+
+    /// The solver found a new answer to the goal of `table`. The
+    /// answer is `ambiguous` if it could not be fully proven (see
+    /// `SimplifiedAnswer`).
+    fn answer_produced(
+        &mut self,
+        _table: TableIndex,
+        _answer: &C::CanonicalConstrainedSubst,
+        _ambiguous: bool,
+    ) {
+    }
+
+    /// The solver found that `table` has no answers beyond those
+    /// already produced. This is reported again each time the solver
+    /// comes back to the table for more answers.
+    fn table_completed(&mut self, _table: TableIndex) {}
+}
@@ -6,7 +6,8 @@ use crate::stack::StackIndex;
 use crate::strand::{CanonicalStrand, SelectedSubgoal, Strand};
 use crate::table::{Answer, AnswerIndex};
 use crate::{
-    DelayedLiteral, DelayedLiteralSet, DepthFirstNumber, ExClause, Literal, Minimums, TableIndex,
+    DelayedLiteral, DelayedLiteralSet, DepthFirstNumber, Derivation, DerivedSubgoal, ExClause,
+    Literal, Minimums, OverflowKind, TableIndex,
 };
 use rustc_hash::FxHashSet;
 use std::marker::PhantomData;
@@ -92,6 +93,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         answer: AnswerIndex,
     ) -> RootSearchResult<()> {
         assert!(self.stack.is_empty());
+        profile_scope!("solve");
 
         match self.ensure_answer_recursively(table, answer) {
             Ok(EnsureSuccess::AnswerAvailable) => Ok(()),
@@ -117,7 +119,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             return test(CO::inference_normalized_subst_from_subst(&answer.subst));
         }
 
-        self.tables[table].strands_mut().any(|strand| {
+        self.tables[table].strands().any(|strand| {
             test(CO::inference_normalized_subst_from_ex_clause(
                 &strand.canonical_ex_clause,
             ))
@@ -184,6 +186,11 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         let result = self.pursue_next_strand(depth);
         self.stack.pop(table, depth);
         info!("ensure_answer: result = {:?}", result);
+        if let Err(RecursiveSearchFail::NoMoreSolutions) = result {
+            if let Some(listener) = &mut self.listener {
+                listener.table_completed(table);
+            }
+        }
         result.map(|()| EnsureSuccess::AnswerAvailable)
     }
 
@@ -276,6 +283,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         let CanonicalStrand {
             canonical_ex_clause,
             selected_subgoal,
+            derivation,
         } = canonical_strand;
         return context.instantiate_ex_clause(
             num_universes,
@@ -283,6 +291,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             With {
                 op,
                 selected_subgoal: selected_subgoal.clone(),
+                derivation: derivation.clone(),
                 ops: PhantomData,
             },
         );
@@ -290,6 +299,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         struct With<C: Context, CO: ContextOps<C>, OP: WithInstantiatedStrand<C, CO>> {
             op: OP,
             selected_subgoal: Option<SelectedSubgoal<C>>,
+            derivation: Option<Derivation<C>>,
             ops: PhantomData<CO>,
         }
 
@@ -306,7 +316,8 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                 self.op.with(Strand {
                     infer,
                     ex_clause,
-                    selected_subgoal: self.selected_subgoal.clone(),
+                    selected_subgoal: self.selected_subgoal,
+                    derivation: self.derivation,
                 })
             }
         }
@@ -317,19 +328,22 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             infer,
             ex_clause,
             selected_subgoal,
+            derivation,
         } = strand;
-        Self::canonicalize_strand_from(&mut *infer, &ex_clause, selected_subgoal)
+        Self::canonicalize_strand_from(&mut *infer, &ex_clause, selected_subgoal, derivation)
     }
 
     fn canonicalize_strand_from<I: Context>(
         infer: &mut dyn InferenceTable<C, I>,
         ex_clause: &ExClause<I>,
         selected_subgoal: Option<SelectedSubgoal<C>>,
+        derivation: Option<Derivation<C>>,
     ) -> CanonicalStrand<C> {
         let canonical_ex_clause = infer.canonicalize_ex_clause(&ex_clause);
         CanonicalStrand {
             canonical_ex_clause,
             selected_subgoal,
+            derivation,
         }
     }
 
@@ -386,6 +400,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             let CanonicalStrand {
                 canonical_ex_clause,
                 selected_subgoal,
+                derivation: _,
             } = strand;
             let selected_subgoal = selected_subgoal.unwrap_or_else(|| {
                 panic!(
@@ -413,7 +428,11 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         let mut tables = vec![];
 
         let num_universes = CO::num_universes(&self.tables[table].table_goal);
-        for canonical_strand in self.tables[table].strands_mut() {
+        // The strands are taken out and put back, so that the table
+        // accounts for the memory of the delayed strands.
+        let strands = self.tables[table].take_strands();
+        let mut delayed_strands = Vec::with_capacity(strands.len());
+        for canonical_strand in &strands {
             // FIXME if CanonicalExClause were not held abstract, we
             // could do this in place like we used to (and
             // `instantiate_strand` could take ownership), since we
@@ -426,12 +445,13 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                 DelayStrandAfterCycle { table },
             );
 
-            *canonical_strand = delayed_strand;
+            delayed_strands.push(delayed_strand);
 
             if visited.insert(subgoal_table) {
                 tables.push(subgoal_table);
             }
         }
+        self.tables[table].extend_strands(delayed_strands);
 
         for table in tables {
             self.delay_strands_after_cycle(table, visited);
@@ -469,6 +489,13 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         (Self::canonicalize_strand(strand), subgoal_table)
     }
 
+    fn stack_is_full(&self) -> bool {
+        match self.context.max_stack_depth() {
+            Some(max) => self.stack.len() >= max,
+            None => false,
+        }
+    }
+
     /// Pursues `strand` to see if it leads us to a new answer, either
     /// by selecting a new subgoal or by checking to see if the
     /// selected subgoal has an answer. `strand` is associated with
@@ -486,6 +513,15 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             strand.selected_subgoal,
         );
 
+        // If the query was canceled, set the strand aside as if it had
+        // used up its quantum, so that the search unwinds and the
+        // caller notices.
+        if self.poll_cancellation() {
+            let table = self.stack[depth].table;
+            self.tables[table].push_strand(Self::canonicalize_strand(strand));
+            return Err(StrandFail::QuantumExceeded);
+        }
+
         // If no subgoal has yet been selected, select one.
         while strand.selected_subgoal.is_none() {
             if strand.ex_clause.subgoals.len() == 0 {
@@ -501,12 +537,22 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             // to pick things like `?T: Sized` if we can help it.
             let subgoal_index = strand.ex_clause.subgoals.len() - 1;
 
-            // Get or create table for this subgoal.
-            match self.get_or_create_table_for_subgoal(
-                &mut *strand.infer,
-                &strand.ex_clause.subgoals[subgoal_index],
-            ) {
+            // Get or create table for this subgoal -- unless the stack
+            // is already as deep as we allow, in which case we treat
+            // the subgoal as an overflow.
+            let subgoal_table = if self.stack_is_full() {
+                self.record_overflow(OverflowKind::Depth);
+                None
+            } else {
+                self.get_or_create_table_for_subgoal(
+                    &mut *strand.infer,
+                    &strand.ex_clause.subgoals[subgoal_index],
+                )
+            };
+            match subgoal_table {
                 Some((subgoal_table, universe_map)) => {
+                    let table = self.stack[depth].table;
+                    self.tables[subgoal_table].dependents.insert(table);
                     strand.selected_subgoal = Some(SelectedSubgoal {
                         subgoal_index,
                         subgoal_table,
@@ -563,6 +609,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                     subgoals,
                 },
             selected_subgoal: _,
+            derivation,
         } = strand;
         assert!(subgoals.is_empty());
 
@@ -581,6 +628,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         let answer = Answer {
             subst: answer_subst,
             delayed_literals,
+            derivation,
         };
 
         // A "trivial" answer is one that is 'just true for all cases'
@@ -647,7 +695,14 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                 && CO::empty_constraints(&answer.subst)
         };
 
+        // Keep a copy of the answer to report, if anyone is listening.
+        let reported_subst = self.listener.as_ref().map(|_| answer.subst.clone());
+        let ambiguous = !answer.delayed_literals.is_empty();
+
         if self.tables[table].push_answer(answer) {
+            if let (Some(listener), Some(subst)) = (&mut self.listener, reported_subst) {
+                listener.answer_produced(table, &subst, ambiguous);
+            }
             if is_trivial_answer {
                 self.tables[table].take_strands();
             }
@@ -687,7 +742,8 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
 
         debug!("canonical_subgoal={:?}", canonical_subgoal);
 
-        let (ucanonical_subgoal, universe_map) = infer.u_canonicalize_goal(&canonical_subgoal);
+        let (ucanonical_subgoal, universe_map) =
+            self.u_canonicalize_goal(infer, &canonical_subgoal);
 
         let table = self.get_or_create_table_for_ucanonical_goal(ucanonical_subgoal);
 
@@ -717,8 +773,12 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             self.tables.next_index(),
             goal
         );
+        profile_scope!("table");
         let coinductive_goal = self.context.is_coinductive(&goal);
         let table = self.tables.insert(goal, coinductive_goal);
+        if let Some(listener) = &mut self.listener {
+            listener.goal_entered(table, &self.tables[table].table_goal);
+        }
         self.push_initial_strands(table);
         table
     }
@@ -734,7 +794,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     /// In terms of the NFTD paper, this corresponds to the *Program
     /// Clause Resolution* step being applied eagerly, as many times
     /// as possible.
-    fn push_initial_strands(&mut self, table: TableIndex) {
+    pub(crate) fn push_initial_strands(&mut self, table: TableIndex) {
         // Instantiate the table goal with fresh inference variables.
         let table_goal = self.tables[table].table_goal.clone();
         self.context.clone().instantiate_ucanonical_goal(
@@ -773,24 +833,66 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         environment: I::Environment,
         goal: I::Goal,
     ) {
+        let record_proofs = self.context.record_proofs();
         let table_ref = &mut self.tables[table];
+        let listener = &mut self.listener;
         match infer.into_hh_goal(goal) {
             HhGoal::DomainGoal(domain_goal) => {
                 let clauses = infer.program_clauses(&environment, &domain_goal);
                 for clause in clauses {
                     debug!("program clause = {:#?}", clause);
-                    if let Ok(resolvent) =
-                        infer.resolvent_clause(&environment, &domain_goal, &subst, &clause)
-                    {
+                    let resolvent =
+                        infer.resolvent_clause(&environment, &domain_goal, &subst, &clause);
+                    if let Some(listener) = listener.as_mut() {
+                        listener.clause_tried(table, &clause, resolvent.is_ok());
+                    }
+                    if let Ok(resolvent) = resolvent {
                         info!("pushing initial strand with ex-clause: {:#?}", &resolvent,);
+                        let derivation = if record_proofs {
+                            Some(Derivation {
+                                clause: Some(infer.lift_program_clause(&clause)),
+                                subgoals: vec![],
+                            })
+                        } else {
+                            None
+                        };
                         table_ref.push_strand(CanonicalStrand {
                             canonical_ex_clause: resolvent,
                             selected_subgoal: None,
+                            derivation,
                         });
                     }
                 }
             }
 
+            HhGoal::Any(subgoals) => {
+                // A disjunction has one strand per alternative, with
+                // that alternative as its only subgoal -- much as if
+                // there were a program clause `G :- A` for each
+                // alternative `A`.
+                for subgoal in subgoals {
+                    let ex_clause = ExClause {
+                        subst: subst.clone(),
+                        delayed_literals: vec![],
+                        constraints: vec![],
+                        subgoals: vec![Literal::Positive(I::goal_in_environment(
+                            &environment,
+                            subgoal,
+                        ))],
+                    };
+                    info!(
+                        "pushing initial strand with ex-clause: {:#?}",
+                        infer.debug_ex_clause(&ex_clause),
+                    );
+                    table_ref.push_strand(Self::canonicalize_strand_from(
+                        &mut *infer,
+                        &ex_clause,
+                        None,
+                        Derivation::new(record_proofs),
+                    ));
+                }
+            }
+
             hh_goal => {
                 // `canonical_goal` is an HH goal. We can simplify it
                 // into a series of *literals*, all of which must be
@@ -811,6 +913,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                         infer,
                         ex_clause,
                         selected_subgoal: None,
+                        derivation: Derivation::new(record_proofs),
                     }));
                 }
             }
@@ -911,7 +1014,15 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         // could instead generate an (imprecise) result). As you can
         // see a bit later, we also diverge in some other aspects that
         // affect completeness when it comes to subgoal abstraction.
-        let inverted_subgoal = infer.invert_goal(subgoal)?;
+        let inverted_subgoal = match infer.invert_goal(subgoal) {
+            Some(inverted_subgoal) => inverted_subgoal,
+            None => {
+                let canonical_subgoal = infer.canonicalize_goal(subgoal);
+                let (ucanonical_subgoal, _) = self.u_canonicalize_goal(infer, &canonical_subgoal);
+                self.record_flounder(ucanonical_subgoal);
+                return None;
+            }
+        };
 
         // DIVERGENCE
         //
@@ -965,7 +1076,10 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         // prior paragraph above.) I just didn't feel like dealing
         // with it yet.
         match infer.truncate_goal(&inverted_subgoal) {
-            Some(_) => None,
+            Some(_) => {
+                self.record_overflow(OverflowKind::Size);
+                None
+            }
             None => Some(infer.canonicalize_goal(&inverted_subgoal)),
         }
     }
@@ -1013,14 +1127,19 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                     infer,
                     mut ex_clause,
                     selected_subgoal: _,
+                    mut derivation,
                 } = strand;
                 ex_clause.subgoals.remove(subgoal_index);
+                Derivation::push(&mut derivation, DerivedSubgoal::Cycle {
+                    table: subgoal_table,
+                });
                 return self.pursue_strand_recursively(
                     depth,
                     Strand {
                         infer,
                         ex_clause,
                         selected_subgoal: None,
+                        derivation,
                     },
                 );
             }
@@ -1053,7 +1172,12 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             infer,
             mut ex_clause,
             selected_subgoal: _,
+            mut derivation,
         } = strand;
+        Derivation::push(&mut derivation, DerivedSubgoal::Answer {
+            table: subgoal_table,
+            answer: answer_index.value,
+        });
         let subgoal = match ex_clause.subgoals.remove(subgoal_index) {
             Literal::Positive(g) => g,
             Literal::Negative(g) => panic!(
@@ -1083,18 +1207,37 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                             subgoal_table,
                             infer.sink_answer_subset(&answer.subst),
                         ));
+
+                        // An answer that rests on an ambiguous one
+                        // (truncated, floundered, ...) is ambiguous too.
+                        if !answer.delayed_literals.is_conditional() {
+                            ex_clause.delayed_literals.push(DelayedLiteral::CannotProve(()));
+                        }
                     }
                 }
 
                 // Apply answer abstraction.
                 let ex_clause = self.truncate_returned(ex_clause, &mut *infer);
 
+                // When searching breadth-first, give the other strands
+                // a turn before we go on with this one.
+                if self.context.breadth_first() {
+                    self.tables[table].push_strand(Self::canonicalize_strand_from(
+                        &mut *infer,
+                        &ex_clause,
+                        None,
+                        derivation,
+                    ));
+                    return Err(StrandFail::QuantumExceeded);
+                }
+
                 self.pursue_strand_recursively(
                     depth,
                     Strand {
                         infer,
                         ex_clause,
                         selected_subgoal: None,
+                        derivation,
                     },
                 )
             }
@@ -1113,7 +1256,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
     /// a positive edge (the SLG POSITIVE RETURN operation). Truncates
     /// the resolvent (or factor) if it has grown too large.
     fn truncate_returned<I: Context>(
-        &self,
+        &mut self,
         ex_clause: ExClause<I>,
         infer: &mut dyn InferenceTable<C, I>,
     ) -> ExClause<I> {
@@ -1143,6 +1286,8 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
 
             // Resolvent got too large. Have to introduce approximation.
             Some(truncated_subst) => {
+                self.record_overflow(OverflowKind::Size);
+
                 // DIVERGENCE
                 //
                 // In RR, `self.delayed_literals` would be
@@ -1181,7 +1326,9 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         depth: StackIndex,
         strand: Strand<'_, C, impl Context>,
     ) -> StrandResult<C, ()> {
-        crate::maybe_grow_stack(|| self.pursue_strand(depth, strand))
+        let red_zone = self.context.stack_red_zone();
+        let segment_size = self.context.stack_segment_size();
+        crate::maybe_grow_stack(red_zone, segment_size, || self.pursue_strand(depth, strand))
     }
 
     /// Invoked when we have found a successful answer to the given
@@ -1200,6 +1347,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             &mut *strand.infer,
             &strand.ex_clause,
             Some(selected_subgoal),
+            strand.derivation.clone(),
         ));
     }
 
@@ -1223,9 +1371,9 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
         // proved that `answer_index` does not exist (in which case
         // the negative literal is true) or if we found a delayed
         // literal (in which case the negative literal *may* be true).
-        // Before exiting the match, then, we set `delayed_literal` to
-        // either `Some` or `None` depending.
-        let delayed_literal: Option<DelayedLiteral<_>>;
+        // Before exiting the match, then, we set `delayed_literals` to
+        // the literals to delay, if any.
+        let delayed_literals: Vec<DelayedLiteral<_>>;
         match self.ensure_answer_recursively(subgoal_table, answer_index) {
             Ok(EnsureSuccess::AnswerAvailable) => {
                 if self.answer(subgoal_table, answer_index).is_unconditional() {
@@ -1251,7 +1399,18 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                 // table. Then later, when all pending work from that
                 // table is completed, all negative links are
                 // converted to delays.
-                delayed_literal = Some(DelayedLiteral::Negative(subgoal_table));
+                //
+                // If the answer is ambiguous, rather than conditional,
+                // there is no hope of deciding the negative literal.
+                let answer = self.answer(subgoal_table, answer_index);
+                delayed_literals = if answer.delayed_literals.is_conditional() {
+                    vec![DelayedLiteral::Negative(subgoal_table)]
+                } else {
+                    vec![
+                        DelayedLiteral::Negative(subgoal_table),
+                        DelayedLiteral::CannotProve(()),
+                    ]
+                };
             }
 
             Ok(EnsureSuccess::Coinductive) => {
@@ -1289,7 +1448,7 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                 // This answer does not exist. Huzzah, happy days are
                 // here again! =) We can just remove this subgoal and continue
                 // with no need for a delayed literal.
-                delayed_literal = None;
+                delayed_literals = vec![];
             }
 
             // Learned nothing yet. Have to try again some other time.
@@ -1309,15 +1468,20 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
             infer,
             mut ex_clause,
             selected_subgoal: _,
+            mut derivation,
         } = strand;
         ex_clause.subgoals.remove(selected_subgoal.subgoal_index); // (i)
-        ex_clause.delayed_literals.extend(delayed_literal); // (ii)
+        ex_clause.delayed_literals.extend(delayed_literals); // (ii)
+        Derivation::push(&mut derivation, DerivedSubgoal::Negative {
+            table: subgoal_table,
+        });
         self.pursue_strand_recursively(
             depth,
             Strand {
                 infer,
                 ex_clause,
                 selected_subgoal: None,
+                derivation,
             },
         )
     }
@@ -1338,7 +1502,7 @@ impl<C: Context, CO: ContextOps<C>> WithInstantiatedStrand<C, CO> for PursueStra
     type Output = StrandResult<C, ()>;
 
     fn with(self, strand: Strand<'_, C, impl Context>) -> Self::Output {
-        self.forest.pursue_strand(self.depth, strand)
+        self.forest.pursue_strand_recursively(self.depth, strand)
     }
 }
 
@@ -1,5 +1,5 @@
 use crate::context::prelude::*;
-use crate::fallible::Fallible;
+use crate::fallible::{Fallible, NoSolution};
 use crate::forest::Forest;
 use crate::hh::HhGoal;
 use crate::{ExClause, Literal};
@@ -42,6 +42,32 @@ impl<C: Context, CO: ContextOps<C>> Forest<C, CO> {
                     pending_goals.push((environment.clone(), infer.into_hh_goal(subgoal1)));
                     pending_goals.push((environment, infer.into_hh_goal(subgoal2)));
                 }
+                HhGoal::All(subgoals) => {
+                    for subgoal in subgoals {
+                        pending_goals.push((environment.clone(), infer.into_hh_goal(subgoal)));
+                    }
+                }
+                HhGoal::Any(mut subgoals) => {
+                    // A disjunction cannot be simplified into literals
+                    // that must all hold, so (unless there is only one
+                    // alternative) it becomes a subgoal of its own; see
+                    // `push_initial_strands_instantiated`.
+                    match subgoals.len() {
+                        0 => return Err(NoSolution),
+                        1 => {
+                            let subgoal = subgoals.pop().unwrap();
+                            pending_goals.push((environment, infer.into_hh_goal(subgoal)));
+                        }
+                        _ => {
+                            ex_clause
+                                .subgoals
+                                .push(Literal::Positive(I::goal_in_environment(
+                                    &environment,
+                                    infer.any_goal(subgoals),
+                                )));
+                        }
+                    }
+                }
                 HhGoal::Not(subgoal) => {
                     ex_clause
                         .subgoals
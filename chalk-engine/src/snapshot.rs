@@ -0,0 +1,288 @@
+//! Snapshots of the tables of a forest (see `Forest::snapshot`), for
+//! debugging. A search that does not terminate usually shows up as
+//! tables that keep multiplying, or as a table that never completes;
+//! a missing answer, as a table that completed without it.
+//!
+//! A snapshot can also be rendered as a graphviz graph (see
+//! `ForestSnapshot::to_dot`), with an edge from each table to the
+//! tables of the subgoals it selected. In the graph:
+//!
+//! - tables that are not complete have a double border;
+//! - coinductive tables are drawn as ellipses, the others as boxes;
+//! - the edges of cycles are red, or green if all the tables of the
+//!   cycle are coinductive (and so the cycle is harmless).
+
+use crate::context::Context;
+use crate::TableIndex;
+use rustc_hash::FxHashMap;
+use std::fmt;
+use std::fmt::Write;
+
+/// The state of each table of a forest at some point.
+#[derive(Clone, Debug)]
+pub struct ForestSnapshot<C: Context> {
+    /// The tables, in the order they were created.
+    pub tables: Vec<TableSnapshot<C>>,
+}
+
+/// The state of a single table.
+#[derive(Clone, Debug)]
+pub struct TableSnapshot<C: Context> {
+    pub table: TableIndex,
+    pub goal: C::UCanonicalGoalInEnvironment,
+
+    /// Whether the goal can assume itself to hold (e.g. for auto
+    /// traits).
+    pub coinductive: bool,
+
+    /// The number of answers found so far.
+    pub num_answers: usize,
+
+    /// How many of those answers are ambiguous.
+    pub num_ambiguous_answers: usize,
+
+    /// The number of strands left to pursue: with none left, the table
+    /// has all its answers.
+    pub num_strands: usize,
+
+    /// Whether the table is on the stack of the search in progress.
+    pub on_stack: bool,
+
+    /// The tables of the subgoals that the strands of this table
+    /// selected.
+    pub subgoals: Vec<TableIndex>,
+}
+
+impl<C: Context> TableSnapshot<C> {
+    pub fn is_complete(&self) -> bool {
+        self.num_strands == 0
+    }
+}
+
+impl<C: Context> ForestSnapshot<C> {
+    /// The tables that do not have all their answers yet.
+    pub fn incomplete_tables(&self) -> impl Iterator<Item = &TableSnapshot<C>> {
+        self.tables.iter().filter(|table| !table.is_complete())
+    }
+
+    /// The position in `tables` of each table.
+    fn positions(&self) -> FxHashMap<TableIndex, usize> {
+        self.tables
+            .iter()
+            .enumerate()
+            .map(|(position, table)| (table.table, position))
+            .collect()
+    }
+
+    /// Groups the tables that depend on each other through their
+    /// subgoals: two tables are in the same group if each can be
+    /// reached from the other. Returns the group of each table, in the
+    /// order of `tables`, and the number of tables in each group.
+    fn cycles(&self) -> (Vec<usize>, Vec<usize>) {
+        let positions = self.positions();
+        let edges: Vec<Vec<usize>> = self
+            .tables
+            .iter()
+            .map(|table| {
+                table
+                    .subgoals
+                    .iter()
+                    .filter_map(|subgoal| positions.get(subgoal).cloned())
+                    .collect()
+            })
+            .collect();
+        StronglyConnected::new(&edges).run()
+    }
+
+    /// Renders the tables and their subgoals as a graphviz graph (see
+    /// the module documentation for what it shows). The goals are
+    /// rendered with `Debug`, so a program should be installed (see
+    /// `chalk_ir::tls`) for them to show names.
+    pub fn to_dot(&self) -> String {
+        let (groups, group_sizes) = self.cycles();
+        let in_cycle = |position: usize| {
+            let table = &self.tables[position];
+            group_sizes[groups[position]] > 1 || table.subgoals.contains(&table.table)
+        };
+        let coinductive_group = |group: usize| {
+            self.tables
+                .iter()
+                .zip(&groups)
+                .all(|(table, &other)| other != group || table.coinductive)
+        };
+
+        let mut dot = String::new();
+        writeln!(dot, "digraph tables {{").unwrap();
+        writeln!(dot, "    node [shape=box];").unwrap();
+        for table in &self.tables {
+            let goal = escape(&format!("{:?}", table.goal));
+            let label = format!("{}\\n{}", goal, table.status().join(", "));
+            let mut attributes = vec![format!("label=\"{}\"", label)];
+            if table.coinductive {
+                attributes.push("shape=ellipse".to_string());
+            }
+            if !table.is_complete() {
+                attributes.push("peripheries=2".to_string());
+            }
+            writeln!(dot, "    t{} [{}];", table.table.value, attributes.join(", ")).unwrap();
+        }
+
+        let positions = self.positions();
+        for (position, table) in self.tables.iter().enumerate() {
+            for subgoal in &table.subgoals {
+                let color = match positions.get(subgoal) {
+                    Some(&other) if groups[other] == groups[position] && in_cycle(position) => {
+                        if coinductive_group(groups[position]) {
+                            " [color=green]"
+                        } else {
+                            " [color=red]"
+                        }
+                    }
+                    _ => "",
+                };
+                writeln!(dot, "    t{} -> t{}{};", table.table.value, subgoal.value, color)
+                    .unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+/// Escapes `text` for a double-quoted graphviz string.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Tarjan's algorithm for the strongly connected components of a graph,
+/// given as the successors of each node.
+struct StronglyConnected<'a> {
+    edges: &'a [Vec<usize>],
+    index: Vec<Option<usize>>,
+    low_link: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    next_index: usize,
+    components: Vec<usize>,
+    component_sizes: Vec<usize>,
+}
+
+impl<'a> StronglyConnected<'a> {
+    fn new(edges: &'a [Vec<usize>]) -> Self {
+        let n = edges.len();
+        StronglyConnected {
+            edges,
+            index: vec![None; n],
+            low_link: vec![0; n],
+            on_stack: vec![false; n],
+            stack: vec![],
+            next_index: 0,
+            components: vec![0; n],
+            component_sizes: vec![],
+        }
+    }
+
+    fn run(mut self) -> (Vec<usize>, Vec<usize>) {
+        for node in 0..self.edges.len() {
+            if self.index[node].is_none() {
+                self.visit(node);
+            }
+        }
+        (self.components, self.component_sizes)
+    }
+
+    fn visit(&mut self, node: usize) {
+        self.index[node] = Some(self.next_index);
+        self.low_link[node] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(node);
+        self.on_stack[node] = true;
+
+        let edges = self.edges;
+        for &successor in &edges[node] {
+            match self.index[successor] {
+                None => {
+                    self.visit(successor);
+                    self.low_link[node] = self.low_link[node].min(self.low_link[successor]);
+                }
+                Some(index) if self.on_stack[successor] => {
+                    self.low_link[node] = self.low_link[node].min(index);
+                }
+                Some(_) => {}
+            }
+        }
+
+        if Some(self.low_link[node]) == self.index[node] {
+            let component = self.component_sizes.len();
+            let mut size = 0;
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack[member] = false;
+                self.components[member] = component;
+                size += 1;
+                if member == node {
+                    break;
+                }
+            }
+            self.component_sizes.push(size);
+        }
+    }
+}
+
+impl<C: Context> TableSnapshot<C> {
+    /// Describes the state of the table, in a few words.
+    fn status(&self) -> Vec<String> {
+        let mut status = vec![];
+        if self.is_complete() {
+            status.push("complete".to_string());
+        } else {
+            status.push(format!("{} strands left", self.num_strands));
+        }
+        match self.num_answers {
+            1 => status.push("1 answer".to_string()),
+            n => status.push(format!("{} answers", n)),
+        }
+        if self.num_ambiguous_answers > 0 {
+            status.push(format!("{} ambiguous", self.num_ambiguous_answers));
+        }
+        if self.coinductive {
+            status.push("coinductive".to_string());
+        }
+        if self.on_stack {
+            status.push("on stack".to_string());
+        }
+        status
+    }
+}
+
+impl<C: Context> fmt::Display for TableSnapshot<C> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "table {}: {:?}", self.table.value, self.goal)?;
+        write!(fmt, "\n    {}", self.status().join(", "))?;
+        if !self.subgoals.is_empty() {
+            let subgoals: Vec<_> = self
+                .subgoals
+                .iter()
+                .map(|subgoal| subgoal.value.to_string())
+                .collect();
+            write!(fmt, "\n    subgoals: tables {}", subgoals.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: Context> fmt::Display for ForestSnapshot<C> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let num_complete = self.tables.iter().filter(|table| table.is_complete()).count();
+        writeln!(
+            fmt,
+            "{} tables, {} complete",
+            self.tables.len(),
+            num_complete
+        )?;
+        for table in &self.tables {
+            writeln!(fmt, "{}", table)?;
+        }
+        Ok(())
+    }
+}
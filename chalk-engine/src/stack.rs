@@ -31,6 +31,10 @@ impl Stack {
         self.stack.is_empty()
     }
 
+    pub(super) fn len(&self) -> usize {
+        self.stack.len()
+    }
+
     /// Searches the stack to see if `table` is active. If so, returns
     /// its stack index.
     pub(super) fn is_active(&self, table: TableIndex) -> Option<StackIndex> {
@@ -47,6 +51,12 @@ impl Stack {
             .next()
     }
 
+    /// Iterates over the stack entries, starting from the bottom
+    /// (i.e., the root goal).
+    pub(super) fn iter(&self) -> impl Iterator<Item = &StackEntry> {
+        self.stack.iter()
+    }
+
     pub(super) fn top_of_stack_from(&self, depth: StackIndex) -> Range<StackIndex> {
         depth..StackIndex::from(self.stack.len())
     }
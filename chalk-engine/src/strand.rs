@@ -1,6 +1,6 @@
 use crate::context::{Context, InferenceTable};
 use crate::table::AnswerIndex;
-use crate::{ExClause, TableIndex};
+use crate::{Derivation, ExClause, TableIndex};
 use std::fmt::{Debug, Error, Formatter};
 
 #[derive(Debug)]
@@ -9,6 +9,10 @@ pub(crate) struct CanonicalStrand<C: Context> {
 
     /// Index into `ex_clause.subgoals`.
     pub(crate) selected_subgoal: Option<SelectedSubgoal<C>>,
+
+    /// How the strand got where it is, if proofs are recorded (see
+    /// `ContextOps::record_proofs`).
+    pub(crate) derivation: Option<Derivation<C>>,
 }
 
 pub(crate) struct Strand<'table, C: Context + 'table, I: Context + 'table> {
@@ -18,6 +22,9 @@ pub(crate) struct Strand<'table, C: Context + 'table, I: Context + 'table> {
 
     /// Index into `ex_clause.subgoals`.
     pub(crate) selected_subgoal: Option<SelectedSubgoal<C>>,
+
+    /// As in `CanonicalStrand`.
+    pub(crate) derivation: Option<Derivation<C>>,
 }
 
 #[derive(Clone, Debug)]
@@ -41,6 +48,7 @@ impl<'table, C: Context, I: Context> Debug for Strand<'table, C, I> {
         fmt.debug_struct("Strand")
             .field("ex_clause", &self.ex_clause)
             .field("selected_subgoal", &self.selected_subgoal)
+            .field("derivation", &self.derivation)
             .finish()
     }
 }
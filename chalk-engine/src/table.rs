@@ -1,9 +1,13 @@
 use crate::context::prelude::*;
 use crate::strand::CanonicalStrand;
-use crate::{DelayedLiteralSet, DelayedLiteralSets};
-use rustc_hash::FxHashMap;
+use crate::tables::TableMemory;
+use crate::{
+    DelayedLiteral, DelayedLiteralSet, DelayedLiteralSets, Derivation, SimplifiedAnswer, TableIndex,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::hash_map::Entry;
 use std::collections::VecDeque;
+use std::fmt::{Debug, Error, Formatter};
 use std::mem;
 
 pub(crate) struct Table<C: Context> {
@@ -29,55 +33,102 @@ pub(crate) struct Table<C: Context> {
     /// Stores the active strands that we can "pull on" to find more
     /// answers.
     strands: VecDeque<CanonicalStrand<C>>,
+
+    /// The tables with a strand that selected this table's goal as a
+    /// subgoal, and so may have used its answers. Used to find the
+    /// tables affected when this one is invalidated.
+    pub(crate) dependents: FxHashSet<TableIndex>,
+
+    /// Kept up to date with the size of this table (see `TableMemory`).
+    memory: TableMemory,
+
+    /// The bytes this table has added to `memory`, which it takes back
+    /// when it is dropped.
+    bytes: usize,
 }
 
 index_struct! {
     pub(crate) struct AnswerIndex {
-        value: usize,
+        pub(crate) value: usize,
     }
 }
 
 /// An "answer" in the on-demand solver corresponds to a fully solved
 /// goal for a particular table (modulo delayed literals). It contains
 /// a substitution
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Answer<C: Context> {
     pub(crate) subst: C::CanonicalConstrainedSubst,
     pub(crate) delayed_literals: DelayedLiteralSet<C>,
+
+    /// How the answer was derived, if proofs are recorded (see
+    /// `ContextOps::record_proofs`).
+    pub(crate) derivation: Option<Derivation<C>>,
 }
 
 impl<C: Context> Table<C> {
     pub(crate) fn new(
         table_goal: C::UCanonicalGoalInEnvironment,
         coinductive_goal: bool,
+        memory: &TableMemory,
     ) -> Table<C> {
-        Table {
+        let mut table = Table {
             table_goal,
             coinductive_goal,
             answers: Vec::new(),
             answers_hash: FxHashMap::default(),
             strands: VecDeque::new(),
-        }
+            dependents: FxHashSet::default(),
+            memory: memory.clone(),
+            bytes: 0,
+        };
+        table.grow(mem::size_of::<Table<C>>() + C::goal_heap_size(&table.table_goal));
+        table
+    }
+
+    fn grow(&mut self, bytes: usize) {
+        self.bytes += bytes;
+        self.memory.add(bytes);
+    }
+
+    fn shrink(&mut self, bytes: usize) {
+        self.bytes -= bytes;
+        self.memory.sub(bytes);
+    }
+
+    /// The memory held by `strand`, as estimated by the context. The
+    /// derivation, which is only recorded on request, is left out.
+    fn strand_size(strand: &CanonicalStrand<C>) -> usize {
+        mem::size_of::<CanonicalStrand<C>>() + C::ex_clause_heap_size(&strand.canonical_ex_clause)
     }
 
     pub(crate) fn push_strand(&mut self, strand: CanonicalStrand<C>) {
+        self.grow(Self::strand_size(&strand));
         self.strands.push_back(strand);
     }
 
     pub(crate) fn extend_strands(&mut self, strands: impl IntoIterator<Item = CanonicalStrand<C>>) {
-        self.strands.extend(strands);
+        for strand in strands {
+            self.push_strand(strand);
+        }
     }
 
-    pub(crate) fn strands_mut(&mut self) -> impl Iterator<Item = &mut CanonicalStrand<C>> {
-        self.strands.iter_mut()
+    pub(crate) fn strands(&self) -> impl Iterator<Item = &CanonicalStrand<C>> {
+        self.strands.iter()
     }
 
     pub(crate) fn take_strands(&mut self) -> VecDeque<CanonicalStrand<C>> {
+        let bytes = self.strands.iter().map(Self::strand_size).sum();
+        self.shrink(bytes);
         mem::replace(&mut self.strands, VecDeque::new())
     }
 
     pub(crate) fn pop_next_strand(&mut self) -> Option<CanonicalStrand<C>> {
-        self.strands.pop_front()
+        let strand = self.strands.pop_front();
+        if let Some(strand) = &strand {
+            self.shrink(Self::strand_size(strand));
+        }
+        strand
     }
 
     /// Adds `answer` to our list of answers, unless it (or some
@@ -93,11 +144,17 @@ impl<C: Context> Table<C> {
             self.answers_hash.get(&answer.subst)
         );
 
+        let subst_size = C::constrained_subst_heap_size(&answer.subst);
         let added = match self.answers_hash.entry(answer.subst.clone()) {
             Entry::Vacant(entry) => {
                 entry.insert(DelayedLiteralSets::singleton(
                     answer.delayed_literals.clone(),
                 ));
+                // The entry holds its own copy of the substitution.
+                self.grow(
+                    mem::size_of::<(C::CanonicalConstrainedSubst, DelayedLiteralSets<C>)>()
+                        + subst_size,
+                );
                 true
             }
 
@@ -111,6 +168,11 @@ impl<C: Context> Table<C> {
             self.table_goal, answer,
         );
         if added {
+            self.grow(
+                mem::size_of::<Answer<C>>()
+                    + subst_size
+                    + answer.delayed_literals.len() * mem::size_of::<DelayedLiteral<C>>(),
+            );
             self.answers.push(answer);
         }
         added
@@ -120,6 +182,14 @@ impl<C: Context> Table<C> {
         self.answers.get(index.value)
     }
 
+    pub(super) fn answers(&self) -> &[Answer<C>] {
+        &self.answers
+    }
+
+    pub(super) fn num_strands(&self) -> usize {
+        self.strands.len()
+    }
+
     /// Useful for testing.
     pub fn num_cached_answers(&self) -> usize {
         self.answers.len()
@@ -130,6 +200,22 @@ impl<C: Context> Table<C> {
     }
 }
 
+/// The derivation is bookkeeping, and is left out.
+impl<C: Context> Debug for Answer<C> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        fmt.debug_struct("Answer")
+            .field("subst", &self.subst)
+            .field("delayed_literals", &self.delayed_literals)
+            .finish()
+    }
+}
+
+impl<C: Context> Drop for Table<C> {
+    fn drop(&mut self) {
+        self.memory.sub(self.bytes);
+    }
+}
+
 impl AnswerIndex {
     pub(crate) const ZERO: AnswerIndex = AnswerIndex { value: 0 };
 }
@@ -140,4 +226,12 @@ impl<C: Context> Answer<C> {
     pub(super) fn is_unconditional(&self) -> bool {
         self.delayed_literals.is_empty()
     }
+
+    pub(super) fn simplified(&self) -> SimplifiedAnswer<C> {
+        SimplifiedAnswer {
+            subst: self.subst.clone(),
+            ambiguous: !self.delayed_literals.is_empty(),
+            conditional: self.delayed_literals.is_conditional(),
+        }
+    }
 }
@@ -3,6 +3,8 @@ use crate::table::Table;
 use crate::TableIndex;
 use rustc_hash::FxHashMap;
 use std::ops::{Index, IndexMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 /// See `Forest`.
 pub(crate) struct Tables<C: Context> {
@@ -12,6 +14,35 @@ pub(crate) struct Tables<C: Context> {
     /// Table: as described above, stores the key information for each
     /// tree in the forest.
     tables: Vec<Table<C>>,
+
+    /// Shared with every table, which updates it as it grows and
+    /// shrinks.
+    memory: TableMemory,
+}
+
+/// A running estimate of the memory held by a set of tables, in
+/// bytes: the size of each table, answer and strand, and the heap data
+/// of their goals, ex-clauses and substitutions (see
+/// `Context::goal_heap_size`). Each `Table` adds to it as it grows and
+/// subtracts from it as it shrinks (or is dropped), so reading it is
+/// cheap.
+#[derive(Clone, Default)]
+pub(crate) struct TableMemory {
+    bytes: Arc<AtomicUsize>,
+}
+
+impl TableMemory {
+    pub(crate) fn get(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn sub(&self, bytes: usize) {
+        self.bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
 }
 
 impl<C: Context> Tables<C> {
@@ -19,6 +50,7 @@ impl<C: Context> Tables<C> {
         Tables {
             table_indices: FxHashMap::default(),
             tables: Vec::default(),
+            memory: TableMemory::default(),
         }
     }
 
@@ -35,11 +67,22 @@ impl<C: Context> Tables<C> {
         coinductive_goal: bool,
     ) -> TableIndex {
         let index = self.next_index();
-        self.tables.push(Table::new(goal.clone(), coinductive_goal));
+        let table = Table::new(goal.clone(), coinductive_goal, &self.memory);
+        self.tables.push(table);
         self.table_indices.insert(goal, index);
         index
     }
 
+    /// The memory held by the tables, as estimated by `TableMemory`.
+    pub(super) fn memory(&self) -> &TableMemory {
+        &self.memory
+    }
+
+    /// The indices of all tables, in the order they were created.
+    pub(super) fn indices(&self) -> impl Iterator<Item = TableIndex> {
+        (0..self.tables.len()).map(|value| TableIndex { value })
+    }
+
     pub(super) fn index_of(&self, literal: &C::UCanonicalGoalInEnvironment) -> Option<TableIndex> {
         self.table_indices.get(literal).cloned()
     }
@@ -19,6 +19,9 @@ use std::marker::PhantomData;
 /// casts each element in the iterator as it is produced (you must
 /// have the `Caster` trait in scope for that).
 ///
+/// The casts that just wrap a value in a variant of an enum are derived,
+/// by marking the variant with `#[cast]`.
+///
 /// # Invariant
 ///
 /// `Cast` imposes a key invariant. You can only implement `T:
@@ -52,18 +55,6 @@ reflexive_impl!(LeafGoal);
 reflexive_impl!(DomainGoal);
 reflexive_impl!(WhereClause);
 
-impl Cast<WhereClause> for TraitRef {
-    fn cast(self) -> WhereClause {
-        WhereClause::Implemented(self)
-    }
-}
-
-impl Cast<WhereClause> for ProjectionEq {
-    fn cast(self) -> WhereClause {
-        WhereClause::ProjectionEq(self)
-    }
-}
-
 impl<T> Cast<DomainGoal> for T
 where
     T: Cast<WhereClause>,
@@ -91,36 +82,6 @@ where
     }
 }
 
-impl Cast<DomainGoal> for Normalize {
-    fn cast(self) -> DomainGoal {
-        DomainGoal::Normalize(self)
-    }
-}
-
-impl Cast<DomainGoal> for UnselectedNormalize {
-    fn cast(self) -> DomainGoal {
-        DomainGoal::UnselectedNormalize(self)
-    }
-}
-
-impl Cast<DomainGoal> for WellFormed {
-    fn cast(self) -> DomainGoal {
-        DomainGoal::WellFormed(self)
-    }
-}
-
-impl Cast<DomainGoal> for FromEnv {
-    fn cast(self) -> DomainGoal {
-        DomainGoal::FromEnv(self)
-    }
-}
-
-impl Cast<LeafGoal> for EqGoal {
-    fn cast(self) -> LeafGoal {
-        LeafGoal::EqGoal(self)
-    }
-}
-
 impl<T: Cast<Goal>> Cast<Goal> for Binders<T> {
     fn cast(self) -> Goal {
         if self.binders.is_empty() {
@@ -134,15 +95,12 @@ impl<T: Cast<Goal>> Cast<Goal> for Binders<T> {
     }
 }
 
-impl Cast<Ty> for ApplicationTy {
-    fn cast(self) -> Ty {
-        Ty::Apply(self)
-    }
-}
-
-impl Cast<Ty> for ProjectionTy {
+impl<T> Cast<Ty> for T
+where
+    T: Cast<TyData>,
+{
     fn cast(self) -> Ty {
-        Ty::Projection(self)
+        self.cast().intern()
     }
 }
 
@@ -165,7 +123,7 @@ where
     fn cast(self) -> ProgramClause {
         ProgramClause::Implies(ProgramClauseImplication {
             consequence: self.cast(),
-            conditions: vec![],
+            conditions: smallvec![],
         })
     }
 }
@@ -177,24 +135,12 @@ impl<T: Cast<DomainGoal>> Cast<ProgramClause> for Binders<T> {
         } else {
             ProgramClause::ForAll(self.map(|bound| ProgramClauseImplication {
                 consequence: bound.cast(),
-                conditions: vec![],
+                conditions: smallvec![],
             }))
         }
     }
 }
 
-impl Cast<ProgramClause> for ProgramClauseImplication {
-    fn cast(self) -> ProgramClause {
-        ProgramClause::Implies(self)
-    }
-}
-
-impl Cast<ProgramClause> for Binders<ProgramClauseImplication> {
-    fn cast(self) -> ProgramClause {
-        ProgramClause::ForAll(self)
-    }
-}
-
 macro_rules! map_impl {
     (impl[$($t:tt)*] Cast<$b:ty> for $a:ty) => {
         impl<$($t)*> Cast<$b> for $a {
@@ -14,8 +14,8 @@ impl<T: Zip> CouldMatch<T> for T {
 
         impl Zipper for MatchZipper {
             fn zip_tys(&mut self, a: &Ty, b: &Ty) -> Fallible<()> {
-                let could_match = match (a, b) {
-                    (&Ty::Apply(ref a), &Ty::Apply(ref b)) => {
+                let could_match = match (a.data(), b.data()) {
+                    (&TyData::Apply(ref a), &TyData::Apply(ref b)) => {
                         let names_could_match = a.name == b.name;
 
                         names_could_match
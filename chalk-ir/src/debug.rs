@@ -32,19 +32,31 @@ impl Debug for TypeName {
             TypeName::ItemId(id) => write!(fmt, "{:?}", id),
             TypeName::Placeholder(index) => write!(fmt, "{:?}", index),
             TypeName::AssociatedType(assoc_ty) => write!(fmt, "{:?}", assoc_ty),
+            TypeName::Tuple(arity) => write!(fmt, "Tuple({})", arity),
+            TypeName::FnPtr(arity) => write!(fmt, "FnPtr({})", arity),
         }
     }
 }
 
 impl Debug for Ty {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        Debug::fmt(self.data(), fmt)
+    }
+}
+
+impl Debug for TyData {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match self {
-            Ty::BoundVar(depth) => write!(fmt, "^{}", depth),
-            Ty::InferenceVar(var) => write!(fmt, "{:?}", var),
-            Ty::Apply(apply) => write!(fmt, "{:?}", apply),
-            Ty::Projection(proj) => write!(fmt, "{:?}", proj),
-            Ty::UnselectedProjection(proj) => write!(fmt, "{:?}", proj),
-            Ty::ForAll(quantified_ty) => write!(fmt, "{:?}", quantified_ty),
+            TyData::BoundVar(depth) => match tls::bound_name(depth.depth()) {
+                Some(name) => write!(fmt, "{}", name),
+                None => write!(fmt, "{:?}", depth),
+            },
+            TyData::InferenceVar(var) => write!(fmt, "{:?}", var),
+            TyData::Apply(apply) => write!(fmt, "{:?}", apply),
+            TyData::Projection(proj) => write!(fmt, "{:?}", proj),
+            TyData::UnselectedProjection(proj) => write!(fmt, "{:?}", proj),
+            TyData::ForAll(quantified_ty) => write!(fmt, "{:?}", quantified_ty),
+            TyData::Dyn(bounds) => write!(fmt, "dyn {:?}", bounds),
         }
     }
 }
@@ -57,32 +69,80 @@ impl Debug for InferenceVar {
 
 impl Debug for QuantifiedTy {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
-        // FIXME -- we should introduce some names or something here
-        let QuantifiedTy { num_binders, ty } = self;
-        write!(fmt, "for<{}> {:?}", num_binders, ty)
+        let QuantifiedTy { binders, ty } = self;
+        let names = vec![None; binders.len()];
+        write!(fmt, "for<{}> ", BinderList(binders, &names))?;
+        tls::with_bound_names(&names, || Debug::fmt(ty, fmt))
     }
 }
 
 impl Debug for Lifetime {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match self {
-            Lifetime::BoundVar(depth) => write!(fmt, "'^{}", depth),
+            // Lifetime names already start with a `'`.
+            Lifetime::BoundVar(depth) => match tls::bound_name(depth.depth()) {
+                Some(name) => write!(fmt, "{}", name),
+                None => write!(fmt, "'{:?}", depth),
+            },
             Lifetime::InferenceVar(var) => write!(fmt, "'{:?}", var),
+            // Named lifetime placeholders already start with a `'`.
+            Lifetime::Placeholder(index) if index.name().is_some() => write!(fmt, "{:?}", index),
             Lifetime::Placeholder(index) => write!(fmt, "'{:?}", index),
+            Lifetime::Erased => write!(fmt, "'<erased>"),
         }
     }
 }
 
+impl Debug for DebruijnIndex {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "^{}", self.depth)
+    }
+}
+
 impl Debug for PlaceholderIndex {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         let PlaceholderIndex { ui, idx } = self;
-        write!(fmt, "!{}_{}", ui.counter, idx)
+        tls::with_forall_name(*ui, |forall| match forall {
+            Some(forall) if *idx < forall.binders.len() => write!(
+                fmt,
+                "!{}(from forall<{}> at depth {})",
+                forall.binders[*idx],
+                forall
+                    .binders
+                    .iter()
+                    .map(|binder| binder.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                forall.depth
+            ),
+            _ => write!(fmt, "!{}_{}", ui.counter, idx),
+        })
+    }
+}
+
+impl PlaceholderIndex {
+    /// The name of the binder this placeholder stands for, if known
+    /// (see `tls::with_forall_names`).
+    fn name(&self) -> Option<Identifier> {
+        tls::with_forall_name(self.ui, |forall| {
+            forall.and_then(|forall| forall.binders.get(self.idx).cloned())
+        })
     }
 }
 
 impl Debug for ApplicationTy {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
-        write!(fmt, "{:?}{:?}", self.name, Angle(&self.parameters))
+        match self.name {
+            TypeName::Tuple(1) => write!(fmt, "({:?},)", self.parameters[0]),
+            TypeName::Tuple(_) => write!(fmt, "({:?})", Comma(&self.parameters)),
+            TypeName::FnPtr(arity) => write!(
+                fmt,
+                "fn({:?}) -> {:?}",
+                Comma(&self.parameters[..arity]),
+                self.parameters[arity]
+            ),
+            _ => write!(fmt, "{:?}{:?}", self.name, Angle(&self.parameters)),
+        }
     }
 }
 
@@ -144,6 +204,37 @@ impl<'a, T: Debug> Debug for Angle<'a, T> {
     }
 }
 
+/// Formats a list as in `A, B`.
+struct Comma<'a, T: 'a>(&'a [T]);
+
+impl<'a, T: Debug> Debug for Comma<'a, T> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        for (index, elem) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(fmt, ", {:?}", elem)?;
+            } else {
+                write!(fmt, "{:?}", elem)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats a list of goals as in `all { A; B }`.
+struct SemiColon<'a, T: 'a>(&'a [T]);
+
+impl<'a, T: Debug> Debug for SemiColon<'a, T> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        for (index, elem) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(fmt, "; ")?;
+            }
+            write!(fmt, "{:?}", elem)?;
+        }
+        Ok(())
+    }
+}
+
 impl Debug for Normalize {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         write!(fmt, "Normalize({:?} -> {:?})", self.projection, self.ty)
@@ -156,6 +247,23 @@ impl Debug for ProjectionEq {
     }
 }
 
+impl Debug for ConstEvaluatable {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(
+            fmt,
+            "ConstEvaluatable({}{:?})",
+            self.const_name,
+            Angle(&self.parameters)
+        )
+    }
+}
+
+impl Debug for CustomGoal {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "Custom({}{:?})", self.name, Angle(&self.parameters))
+    }
+}
+
 impl Debug for UnselectedNormalize {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         write!(
@@ -177,6 +285,7 @@ impl Debug for WhereClause {
                 Angle(&tr.parameters[1..])
             ),
             WhereClause::ProjectionEq(p) => write!(fmt, "{:?}", p),
+            WhereClause::ConstEvaluatable(c) => write!(fmt, "{:?}", c),
         }
     }
 }
@@ -221,6 +330,9 @@ impl Debug for DomainGoal {
             ),
             DomainGoal::Compatible(_) => write!(fmt, "Compatible"),
             DomainGoal::DownstreamType(n) => write!(fmt, "DownstreamType({:?})", n),
+            DomainGoal::IsSized(n) => write!(fmt, "IsSized({:?})", n),
+            DomainGoal::NeedsDrop(n) => write!(fmt, "NeedsDrop({:?})", n),
+            DomainGoal::Custom(n) => write!(fmt, "{:?}", n),
         }
     }
 }
@@ -229,6 +341,7 @@ impl Debug for LeafGoal {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match *self {
             LeafGoal::EqGoal(ref eq) => write!(fmt, "{:?}", eq),
+            LeafGoal::OutlivesGoal(ref outlives) => write!(fmt, "{:?}", outlives),
             LeafGoal::DomainGoal(ref dom) => write!(fmt, "{:?}", dom),
         }
     }
@@ -240,24 +353,24 @@ impl Debug for EqGoal {
     }
 }
 
+impl Debug for OutlivesGoal {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        write!(fmt, "({:?}: {:?})", self.a, self.b)
+    }
+}
+
 impl Debug for Goal {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match *self {
             Goal::Quantified(qkind, ref subgoal) => {
-                write!(fmt, "{:?}<", qkind)?;
-                for (index, binder) in subgoal.binders.iter().enumerate() {
-                    if index > 0 {
-                        write!(fmt, ", ")?;
-                    }
-                    match *binder {
-                        ParameterKind::Ty(()) => write!(fmt, "type")?,
-                        ParameterKind::Lifetime(()) => write!(fmt, "lifetime")?,
-                    }
-                }
-                write!(fmt, "> {{ {:?} }}", subgoal.value)
+                let names = subgoal.names.get(subgoal.binders.len());
+                write!(fmt, "{:?}<{}> ", qkind, BinderList(&subgoal.binders, &names))?;
+                tls::with_bound_names(&names, || write!(fmt, "{{ {:?} }}", subgoal.value))
             }
             Goal::Implies(ref wc, ref g) => write!(fmt, "if ({:?}) {{ {:?} }}", wc, g),
             Goal::And(ref g1, ref g2) => write!(fmt, "({:?}, {:?})", g1, g2),
+            Goal::All(ref goals) => write!(fmt, "all {{ {:?} }}", SemiColon(goals)),
+            Goal::Any(ref goals) => write!(fmt, "any {{ {:?} }}", SemiColon(goals)),
             Goal::Not(ref g) => write!(fmt, "not {{ {:?} }}", g),
             Goal::Leaf(ref wc) => write!(fmt, "{:?}", wc),
             Goal::CannotProve(()) => write!(fmt, r"¯\_(ツ)_/¯"),
@@ -270,21 +383,33 @@ impl<T: Debug> Debug for Binders<T> {
         let Binders {
             ref binders,
             ref value,
+            ref names,
         } = *self;
+        let names = names.get(binders.len());
         if !binders.is_empty() {
-            write!(fmt, "for<")?;
-            for (index, binder) in binders.iter().enumerate() {
-                if index > 0 {
-                    write!(fmt, ", ")?;
-                }
-                match *binder {
-                    ParameterKind::Ty(()) => write!(fmt, "type")?,
-                    ParameterKind::Lifetime(()) => write!(fmt, "lifetime")?,
-                }
+            write!(fmt, "for<{}> ", BinderList(binders, &names))?;
+        }
+        tls::with_bound_names(&names, || Debug::fmt(value, fmt))
+    }
+}
+
+/// The parameters of a binder, by name if known (`T, 'a`) and by kind
+/// otherwise (`type, lifetime`).
+struct BinderList<'a>(&'a [ParameterKind<()>], &'a [Option<Identifier>]);
+
+impl<'a> Display for BinderList<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        for (index, (binder, name)) in self.0.iter().zip(self.1).enumerate() {
+            if index > 0 {
+                write!(fmt, ", ")?;
+            }
+            match (binder, name) {
+                (_, Some(name)) => write!(fmt, "{}", name)?,
+                (ParameterKind::Ty(()), None) => write!(fmt, "type")?,
+                (ParameterKind::Lifetime(()), None) => write!(fmt, "lifetime")?,
             }
-            write!(fmt, "> ")?;
         }
-        Debug::fmt(value, fmt)
+        Ok(())
     }
 }
 
@@ -336,7 +461,8 @@ impl<T: Display> Display for Canonical<T> {
                 write!(f, "?{}", pk.into_inner())?;
             }
 
-            write!(f, "> {{ {} }}", value)?;
+            let names = vec![None; binders.len()];
+            tls::with_bound_names(&names, || write!(f, "> {{ {} }}", value))?;
         }
 
         Ok(())
@@ -356,6 +482,7 @@ impl Debug for Constraint {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         match self {
             Constraint::LifetimeEq(a, b) => write!(fmt, "{:?} == {:?}", a, b),
+            Constraint::Outlives(a, b) => write!(fmt, "{:?}: {:?}", a, b),
         }
     }
 }
@@ -0,0 +1,147 @@
+//! Fingerprints: 128-bit hashes of IR values that are the same in every
+//! build, on every platform and with every interner, so that they can
+//! be stored (as the keys of an on-disk cache, say) or compared between
+//! machines.
+//!
+//! A value is fingerprinted by feeding it to a `StableHasher` through
+//! its `Hash` impl. Identifiers hash as their strings and items as the
+//! ids derived from their paths, so neither the interner nor the order
+//! that the items of a program were declared in shows in fingerprints.
+
+use chalk_macros::intern;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+mod test;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Fingerprint(u128);
+
+impl Fingerprint {
+    pub fn of<T: Hash + ?Sized>(value: &T) -> Fingerprint {
+        intern::hash_by_string(|| {
+            let mut hasher = StableHasher::new();
+            value.hash(&mut hasher);
+            hasher.fingerprint()
+        })
+    }
+
+    /// The fingerprint of `values` regardless of their order, for values
+    /// whose order is not meaningful, such as the clauses of a program.
+    pub fn of_unordered<I>(values: I) -> Fingerprint
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        let mut fingerprints: Vec<_> = values
+            .into_iter()
+            .map(|value| Fingerprint::of(&value))
+            .collect();
+        fingerprints.sort();
+        Fingerprint::of(&fingerprints)
+    }
+
+    pub fn as_u128(self) -> u128 {
+        self.0
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{:032x}", self.0)
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Fingerprint({})", self)
+    }
+}
+
+/// A hasher whose results are the same in every build and on every
+/// platform: 128-bit FNV-1a, with integers written as little-endian
+/// `u64`s or wider, whatever the width of `usize`. (The hashers of the
+/// standard library may change between releases, and are seeded at
+/// random.)
+#[derive(Clone, Debug)]
+pub struct StableHasher {
+    state: u128,
+}
+
+impl StableHasher {
+    const OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    const PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+    pub fn new() -> Self {
+        StableHasher {
+            state: Self::OFFSET_BASIS,
+        }
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint(self.state)
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= u128::from(byte);
+            self.state = self.state.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        (self.state >> 64) as u64 ^ self.state as u64
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64);
+    }
+}
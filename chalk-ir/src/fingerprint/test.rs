@@ -0,0 +1,46 @@
+#![cfg(test)]
+
+use super::*;
+use chalk_macros::intern::{intern, with_interner, Interner};
+use std::sync::Arc;
+
+#[test]
+fn fnv_1a() {
+    assert_eq!(
+        StableHasher::new().fingerprint().as_u128(),
+        0x6c62_272e_07bb_0142_62b8_2175_6295_c58d
+    );
+
+    let mut hasher = StableHasher::new();
+    hasher.write(b"a");
+    assert_eq!(
+        hasher.fingerprint().to_string(),
+        "d228cb696f1a8caf78912b704e4a8964"
+    );
+}
+
+#[test]
+fn usize_hashes_as_u64() {
+    assert_eq!(Fingerprint::of(&7usize), Fingerprint::of(&7u64));
+    assert_eq!(Fingerprint::of(&-7isize), Fingerprint::of(&-7i64));
+}
+
+#[test]
+fn independent_of_interner() {
+    let first = Arc::new(Interner::new());
+    let second = Arc::new(Interner::new());
+    with_interner(&second, || intern("Bar"));
+    assert_eq!(
+        with_interner(&first, || Fingerprint::of(&intern("Foo"))),
+        with_interner(&second, || Fingerprint::of(&intern("Foo")))
+    );
+}
+
+#[test]
+fn unordered() {
+    assert_eq!(
+        Fingerprint::of_unordered(vec!["a", "b"]),
+        Fingerprint::of_unordered(vec!["b", "a"])
+    );
+    assert_ne!(Fingerprint::of(&vec!["a", "b"]), Fingerprint::of(&vec!["b", "a"]));
+}
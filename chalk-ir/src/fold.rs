@@ -1,15 +1,17 @@
 //! Traits for transforming bits of IR.
 
-use crate::cast::Cast;
 use crate::*;
 use chalk_engine::context::Context;
 use chalk_engine::{DelayedLiteral, ExClause, Literal};
 use std::fmt::Debug;
 use std::sync::Arc;
 
+pub mod erase;
 pub mod shift;
 mod subst;
 
+pub use self::erase::EraseLifetimes;
+pub use self::shift::Shift;
 pub use self::subst::Subst;
 
 /// A "folder" is a transformer that can be used to make a copy of
@@ -64,6 +66,13 @@ pub trait Folder: FreeVarFolder + InferenceFolder + PlaceholderFolder + TypeFold
 pub trait TypeFolder {
     fn fold_ty(&mut self, ty: &Ty, binders: usize) -> Fallible<Ty>;
     fn fold_lifetime(&mut self, lifetime: &Lifetime, binders: usize) -> Fallible<Lifetime>;
+
+    /// Invoked for each `ItemId` in the term, including those that name
+    /// types. Almost every folder leaves item ids alone, which is what
+    /// this does by default.
+    fn fold_item_id(&mut self, item_id: ItemId) -> Fallible<ItemId> {
+        Ok(item_id)
+    }
 }
 
 impl<T> Folder for T
@@ -108,10 +117,14 @@ pub trait FreeVarFolder {
     /// - `binders` is the number of binders in scope.
     ///
     /// This should return a type suitable for a context with `binders` in scope.
-    fn fold_free_var_ty(&mut self, depth: usize, binders: usize) -> Fallible<Ty>;
+    fn fold_free_var_ty(&mut self, depth: DebruijnIndex, binders: usize) -> Fallible<Ty>;
 
     /// As `fold_free_var_ty`, but for lifetimes.
-    fn fold_free_var_lifetime(&mut self, depth: usize, binders: usize) -> Fallible<Lifetime>;
+    fn fold_free_var_lifetime(
+        &mut self,
+        depth: DebruijnIndex,
+        binders: usize,
+    ) -> Fallible<Lifetime>;
 }
 
 /// A convenience trait. If you implement this, you get an
@@ -127,19 +140,23 @@ pub trait DefaultFreeVarFolder {
 }
 
 impl<T: DefaultFreeVarFolder> FreeVarFolder for T {
-    fn fold_free_var_ty(&mut self, depth: usize, binders: usize) -> Fallible<Ty> {
+    fn fold_free_var_ty(&mut self, depth: DebruijnIndex, binders: usize) -> Fallible<Ty> {
         if T::forbid() {
             panic!("unexpected free variable with depth `{:?}`", depth)
         } else {
-            Ok(Ty::BoundVar(depth + binders))
+            Ok(depth.shifted_in(binders).to_ty())
         }
     }
 
-    fn fold_free_var_lifetime(&mut self, depth: usize, binders: usize) -> Fallible<Lifetime> {
+    fn fold_free_var_lifetime(
+        &mut self,
+        depth: DebruijnIndex,
+        binders: usize,
+    ) -> Fallible<Lifetime> {
         if T::forbid() {
             panic!("unexpected free variable with depth `{:?}`", depth)
         } else {
-            Ok(Lifetime::BoundVar(depth + binders))
+            Ok(Lifetime::BoundVar(depth.shifted_in(binders)))
         }
     }
 }
@@ -283,6 +300,17 @@ impl<T: Fold> Fold for Vec<T> {
     }
 }
 
+impl<A> Fold for SmallVec<A>
+where
+    A: smallvec::Array,
+    A::Item: Fold<Result = A::Item>,
+{
+    type Result = SmallVec<A>;
+    fn fold_with(&self, folder: &mut dyn Folder, binders: usize) -> Fallible<Self::Result> {
+        self.iter().map(|e| e.fold_with(folder, binders)).collect()
+    }
+}
+
 impl<T: Fold> Fold for Box<T> {
     type Result = Box<T::Result>;
     fn fold_with(&self, folder: &mut dyn Folder, binders: usize) -> Fallible<Self::Result> {
@@ -333,16 +361,13 @@ impl Fold for Ty {
 }
 
 pub fn super_fold_ty(folder: &mut dyn Folder, ty: &Ty, binders: usize) -> Fallible<Ty> {
-    match *ty {
-        Ty::BoundVar(depth) => {
-            if depth >= binders {
-                folder.fold_free_var_ty(depth - binders, binders)
-            } else {
-                Ok(Ty::BoundVar(depth))
-            }
-        }
-        Ty::InferenceVar(var) => folder.fold_inference_ty(var, binders),
-        Ty::Apply(ref apply) => {
+    let data = match *ty.data() {
+        TyData::BoundVar(depth) => match depth.shifted_out(binders) {
+            Some(free_depth) => return folder.fold_free_var_ty(free_depth, binders),
+            None => return Ok(*ty),
+        },
+        TyData::InferenceVar(var) => return folder.fold_inference_ty(var, binders),
+        TyData::Apply(ref apply) => {
             let ApplicationTy {
                 name,
                 ref parameters,
@@ -355,20 +380,41 @@ pub fn super_fold_ty(folder: &mut dyn Folder, ty: &Ty, binders: usize) -> Fallib
                         ty,
                         parameters
                     );
-                    folder.fold_free_placeholder_ty(ui, binders)
+                    return folder.fold_free_placeholder_ty(ui, binders);
                 }
 
-                TypeName::ItemId(_) | TypeName::AssociatedType(_) => {
+                TypeName::ItemId(_)
+                | TypeName::AssociatedType(_)
+                | TypeName::Tuple(_)
+                | TypeName::FnPtr(_) => {
+                    let name = match name {
+                        TypeName::ItemId(id) => TypeName::ItemId(folder.fold_item_id(id)?),
+                        TypeName::AssociatedType(id) => {
+                            TypeName::AssociatedType(folder.fold_item_id(id)?)
+                        }
+                        name => name,
+                    };
                     let parameters = parameters.fold_with(folder, binders)?;
-                    Ok(ApplicationTy { name, parameters }.cast())
+                    TyData::Apply(ApplicationTy { name, parameters })
                 }
             }
         }
-        Ty::Projection(ref proj) => Ok(Ty::Projection(proj.fold_with(folder, binders)?)),
-        Ty::UnselectedProjection(ref proj) => {
-            Ok(Ty::UnselectedProjection(proj.fold_with(folder, binders)?))
+        TyData::Projection(ref proj) => TyData::Projection(proj.fold_with(folder, binders)?),
+        TyData::UnselectedProjection(ref proj) => {
+            TyData::UnselectedProjection(proj.fold_with(folder, binders)?)
         }
-        Ty::ForAll(ref quantified_ty) => Ok(Ty::ForAll(quantified_ty.fold_with(folder, binders)?)),
+        TyData::ForAll(ref quantified_ty) => {
+            TyData::ForAll(quantified_ty.fold_with(folder, binders)?)
+        }
+        TyData::Dyn(ref bounds) => TyData::Dyn(bounds.fold_with(folder, binders)?),
+    };
+
+    // Most folds leave most types alone; comparing the data, whose types
+    // are compared as ids, is cheaper than interning it again.
+    if data == *ty.data() {
+        Ok(*ty)
+    } else {
+        Ok(data.intern())
     }
 }
 
@@ -376,12 +422,12 @@ impl Fold for QuantifiedTy {
     type Result = Self;
     fn fold_with(&self, folder: &mut dyn Folder, binders: usize) -> Fallible<Self::Result> {
         let QuantifiedTy {
-            num_binders,
+            binders: ref self_binders,
             ref ty,
         } = *self;
         Ok(QuantifiedTy {
-            num_binders,
-            ty: ty.fold_with(folder, binders + num_binders)?,
+            binders: self_binders.clone(),
+            ty: ty.fold_with(folder, binders + self_binders.len())?,
         })
     }
 }
@@ -395,11 +441,13 @@ where
         let Binders {
             binders: ref self_binders,
             value: ref self_value,
+            ref names,
         } = *self;
         let value = self_value.fold_with(folder, binders + self_binders.len())?;
         Ok(Binders {
             binders: self_binders.clone(),
             value: value,
+            names: names.clone(),
         })
     }
 }
@@ -435,23 +483,41 @@ pub fn super_fold_lifetime(
     binders: usize,
 ) -> Fallible<Lifetime> {
     match *lifetime {
-        Lifetime::BoundVar(depth) => {
-            if depth >= binders {
-                folder.fold_free_var_lifetime(depth - binders, binders)
-            } else {
-                Ok(Lifetime::BoundVar(depth))
-            }
-        }
+        Lifetime::BoundVar(depth) => match depth.shifted_out(binders) {
+            Some(free_depth) => folder.fold_free_var_lifetime(free_depth, binders),
+            None => Ok(Lifetime::BoundVar(depth)),
+        },
         Lifetime::InferenceVar(var) => folder.fold_inference_lifetime(var, binders),
         Lifetime::Placeholder(universe) => folder.fold_free_placeholder_lifetime(universe, binders),
+        Lifetime::Erased => Ok(Lifetime::Erased),
     }
 }
 
 impl Fold for Substitution {
     type Result = Substitution;
     fn fold_with(&self, folder: &mut dyn Folder, binders: usize) -> Fallible<Self::Result> {
-        let parameters = self.parameters.fold_with(folder, binders)?;
-        Ok(Substitution { parameters })
+        // Collecting into a `Fallible` would go through a `Vec` and then
+        // copy it into the `Arc`. Mapping the slice allocates the `Arc`
+        // once; after an error, the rest of the parameters are cloned
+        // rather than folded, and the result is thrown away.
+        let mut error = None;
+        let parameters = self
+            .parameters
+            .iter()
+            .map(|parameter| {
+                if error.is_none() {
+                    match parameter.fold_with(folder, binders) {
+                        Ok(parameter) => return parameter,
+                        Err(err) => error = Some(err),
+                    }
+                }
+                parameter.clone()
+            })
+            .collect();
+        match error {
+            Some(err) => Err(err),
+            None => Ok(Substitution { parameters }),
+        }
     }
 }
 
@@ -473,214 +539,31 @@ macro_rules! copy_fold {
 
 copy_fold!(Identifier);
 copy_fold!(UniverseIndex);
-copy_fold!(ItemId);
 copy_fold!(usize);
 copy_fold!(QuantifierKind);
 copy_fold!(chalk_engine::TableIndex);
 // copy_fold!(TypeName); -- intentionally omitted! This is folded via `fold_ap`
 copy_fold!(());
 
-#[macro_export]
-macro_rules! enum_fold {
-    ($s:ident [$($n:ident),*] { $($variant:ident($($name:ident),*)),* } $($w:tt)*) => {
-        impl<$($n),*> $crate::fold::Fold for $s<$($n),*> $($w)* {
-            type Result = $s<$($n :: Result),*>;
-            fn fold_with(&self,
-                         folder: &mut dyn ($crate::fold::Folder),
-                         binders: usize)
-                         -> ::chalk_engine::fallible::Fallible<Self::Result> {
-                match self {
-                    $(
-                        $s::$variant( $($name),* ) => {
-                            Ok($s::$variant( $($name.fold_with(folder, binders)?),* ))
-                        }
-                    )*
-                }
-            }
-        }
-    };
-
-    // Hacky variant for use in slg::context::implementation
-    ($s:ty { $p:ident :: { $($variant:ident($($name:ident),*)),* } }) => {
-        impl $crate::fold::Fold for $s {
-            type Result = $s;
-            fn fold_with(&self,
-                         folder: &mut dyn ($crate::fold::Folder),
-                         binders: usize)
-                         -> ::chalk_engine::fallible::Fallible<Self::Result> {
-                match self {
-                    $(
-                        $p::$variant( $($name),* ) => {
-                            Ok($p::$variant( $($name.fold_with(folder, binders)?),* ))
-                        }
-                    )*
-                }
-            }
-        }
+impl Fold for ItemId {
+    type Result = ItemId;
+    fn fold_with(&self, folder: &mut dyn Folder, _binders: usize) -> Fallible<Self::Result> {
+        folder.fold_item_id(*self)
     }
 }
 
-enum_fold!(ParameterKind[T,L] { Ty(a), Lifetime(a) } where T: Fold, L: Fold);
-enum_fold!(WhereClause[] { Implemented(a), ProjectionEq(a) });
-enum_fold!(WellFormed[] { Trait(a), Ty(a) });
-enum_fold!(FromEnv[] { Trait(a), Ty(a) });
-enum_fold!(DomainGoal[] { Holds(a), WellFormed(a), FromEnv(a), Normalize(a), UnselectedNormalize(a),
-                          InScope(a), Derefs(a), IsLocal(a), IsUpstream(a), IsFullyVisible(a),
-                          LocalImplAllowed(a), Compatible(a), DownstreamType(a) });
-enum_fold!(LeafGoal[] { EqGoal(a), DomainGoal(a) });
-enum_fold!(Constraint[] { LifetimeEq(a, b) });
-enum_fold!(Goal[] { Quantified(qkind, subgoal), Implies(wc, subgoal), And(g1, g2), Not(g),
-                    Leaf(wc), CannotProve(a) });
-enum_fold!(ProgramClause[] { Implies(a), ForAll(a) });
-
-#[macro_export]
-macro_rules! struct_fold {
-    ($s:ident $([$($tt_args:tt)*])* { $($name:ident),* $(,)* } $($w:tt)*) => {
-        struct_fold! {
-            @parse_tt_args($($($tt_args)*)*)
-                struct_name($s)
-                parameters()
-                self_args()
-                result_args()
-                field_names($($name),*)
-                where_clauses($($w)*)
-        }
-    };
-
-    (
-        @parse_tt_args()
-            struct_name($s:ident)
-            parameters($($parameters:tt)*)
-            self_args($($self_args:tt)*)
-            result_args($($result_args:tt)*)
-            field_names($($field_names:tt)*)
-        where_clauses($($where_clauses:tt)*)
-    ) => {
-        struct_fold! {
-            @parsed_tt_args
-                struct_name($s)
-                parameters($($parameters)*)
-                self_ty($s < $($self_args)* >)
-                result_ty($s < $($result_args)* >)
-                field_names($($field_names)*)
-                where_clauses($($where_clauses)*)
-        }
-    };
-
-    (
-        @parse_tt_args(, $($input:tt)*)
-            struct_name($s:ident)
-            parameters($($parameters:tt)*)
-            self_args($($self_args:tt)*)
-            result_args($($result_args:tt)*)
-            field_names($($field_names:tt)*)
-        where_clauses($($where_clauses:tt)*)
-    ) => {
-        struct_fold! {
-            @parse_tt_args($($input)*)
-                struct_name($s)
-                parameters($($parameters)*,)
-                self_args($($self_args)*,)
-                result_args($($result_args)*,)
-                field_names($($field_names)*)
-            where_clauses($($where_clauses)*)
-        }
-    };
-
-    (
-        @parse_tt_args(- $n:ident $($input:tt)*)
-            struct_name($s:ident)
-            parameters($($parameters:tt)*)
-            self_args($($self_args:tt)*)
-            result_args($($result_args:tt)*)
-            field_names($($field_names:tt)*)
-        where_clauses($($where_clauses:tt)*)
-    ) => {
-        struct_fold! {
-            @parse_tt_args($($input)*)
-                struct_name($s)
-                parameters($($parameters)* $n)
-                self_args($($self_args)* $n)
-                result_args($($result_args)* $n)
-                field_names($($field_names)*)
-            where_clauses($($where_clauses)*)
-        }
-    };
-
-    (
-        @parse_tt_args($n:ident $($input:tt)*)
-            struct_name($s:ident)
-            parameters($($parameters:tt)*)
-            self_args($($self_args:tt)*)
-            result_args($($result_args:tt)*)
-            field_names($($field_names:tt)*)
-        where_clauses($($where_clauses:tt)*)
-    ) => {
-        struct_fold! {
-            @parse_tt_args($($input)*)
-                struct_name($s)
-                parameters($($parameters)* $n)
-                self_args($($self_args)* $n)
-                result_args($($result_args)* $n :: Result)
-                field_names($($field_names)*)
-            where_clauses($($where_clauses)*)
-        }
-    };
-
-    (
-        @parsed_tt_args
-            struct_name($s:ident)
-            parameters($($parameters:tt)*)
-            self_ty($self_ty:ty)
-            result_ty($result_ty:ty)
-            field_names($($field_name:ident),*)
-        where_clauses($($where_clauses:tt)*)
-    ) => {
-        impl<$($parameters)*> $crate::fold::Fold for $self_ty $($where_clauses)* {
-            type Result = $result_ty;
-            fn fold_with(&self,
-                         folder: &mut dyn ($crate::fold::Folder),
-                         binders: usize)
-                         -> ::chalk_engine::fallible::Fallible<Self::Result> {
-                Ok($s {
-                    $($field_name: self.$field_name.fold_with(folder, binders)?),*
-                })
-            }
-        }
-    };
+impl Fold for Environment {
+    type Result = Environment;
+    fn fold_with(&self, folder: &mut dyn Folder, binders: usize) -> Fallible<Self::Result> {
+        // Folding leaves the keys of the clauses alone, so the index
+        // still applies.
+        Ok(Environment {
+            clauses: self.clauses.fold_with(folder, binders)?,
+            index: self.index.clone(),
+        })
+    }
 }
 
-struct_fold!(ProjectionTy {
-    associated_ty_id,
-    parameters,
-});
-struct_fold!(UnselectedProjectionTy {
-    type_name,
-    parameters,
-});
-struct_fold!(TraitRef {
-    trait_id,
-    parameters,
-});
-struct_fold!(Normalize { projection, ty });
-struct_fold!(ProjectionEq { projection, ty });
-struct_fold!(UnselectedNormalize { projection, ty });
-struct_fold!(Environment { clauses });
-struct_fold!(InEnvironment[F] { environment, goal } where F: Fold<Result = F>);
-struct_fold!(EqGoal { a, b });
-struct_fold!(Derefs { source, target });
-struct_fold!(ProgramClauseImplication {
-    consequence,
-    conditions,
-});
-
-struct_fold!(ConstrainedSubst {
-    subst, /* NB: The `is_trivial` routine relies on the fact that `subst` is folded first. */
-    constraints,
-});
-
-// struct_fold!(ApplicationTy { name, parameters }); -- intentionally omitted, folded through Ty
-
 impl<C: Context> Fold for ExClause<C>
 where
     C: Context,
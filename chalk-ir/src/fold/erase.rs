@@ -0,0 +1,42 @@
+//! Erasing lifetimes, for solving without regard to regions.
+//!
+//! Once the lifetimes of a goal and of the clauses used to prove it are
+//! erased, goals that differ only in their lifetimes are the same goal,
+//! and can share their answers.
+
+use super::{
+    super_fold_ty, DefaultFreeVarFolder, DefaultInferenceFolder, DefaultPlaceholderFolder, Fold,
+    TypeFolder,
+};
+use crate::*;
+
+/// Replaces every lifetime in a value, bound or free, with
+/// `Lifetime::Erased`. This is implemented for everything that can be
+/// folded.
+pub trait EraseLifetimes: Fold {
+    fn erase_lifetimes(&self) -> Self::Result;
+}
+
+impl<T: Fold> EraseLifetimes for T {
+    fn erase_lifetimes(&self) -> T::Result {
+        self.fold_with(&mut LifetimeEraser, 0).unwrap()
+    }
+}
+
+struct LifetimeEraser;
+
+impl TypeFolder for LifetimeEraser {
+    fn fold_ty(&mut self, ty: &Ty, binders: usize) -> Fallible<Ty> {
+        super_fold_ty(self, ty, binders)
+    }
+
+    fn fold_lifetime(&mut self, _lifetime: &Lifetime, _binders: usize) -> Fallible<Lifetime> {
+        Ok(Lifetime::Erased)
+    }
+}
+
+impl DefaultFreeVarFolder for LifetimeEraser {}
+
+impl DefaultPlaceholderFolder for LifetimeEraser {}
+
+impl DefaultInferenceFolder for LifetimeEraser {}
@@ -1,10 +1,18 @@
+//! Shifting debruijn indices, to move values into or out of binders.
+//!
+//! A bound variable refers to its binder by how many binders up it is,
+//! so a value cannot be moved under new binders, or taken out of some,
+//! without adjusting the bound variables that refer to binders outside
+//! of it. These are the *free* variables of the value: those bound
+//! within it are left as they are.
+
 use super::{
     DefaultInferenceFolder, DefaultPlaceholderFolder, DefaultTypeFolder, Fold, FreeVarFolder,
 };
 use crate::*;
 
 /// Methods for converting debruijn indices to move values into or out
-/// of binders.
+/// of binders. This is implemented for everything that can be folded.
 pub trait Shift: Fold {
     /// Shifts debruijn indices in `self` **up**, which is used when a
     /// value is being placed under additional levels of binders.
@@ -59,9 +67,14 @@ pub trait Shift: Fold {
     /// get `Err`, because it refers to the type bound by the
     /// `exists`.
     fn shifted_out(&self, adjustment: usize) -> Fallible<Self::Result>;
+
+    /// Whether `self` has free variables, i.e. refers to binders that it
+    /// is not within. Values given to the solver must have none, as there
+    /// is nothing for them to be bound by.
+    fn has_free_vars(&self) -> bool;
 }
 
-impl<T: Fold + Eq> Shift for T {
+impl<T: Fold> Shift for T {
     fn shifted_in(&self, adjustment: usize) -> T::Result {
         self.fold_with(&mut Shifter { adjustment }, 0).unwrap()
     }
@@ -69,6 +82,26 @@ impl<T: Fold + Eq> Shift for T {
     fn shifted_out(&self, adjustment: usize) -> Fallible<T::Result> {
         self.fold_with(&mut DownShifter { adjustment }, 0)
     }
+
+    fn has_free_vars(&self) -> bool {
+        self.fold_with(&mut FreeVarFinder, 0).is_err()
+    }
+}
+
+impl<T: Fold> Binders<T> {
+    /// Puts `value` under `binders`, none of which it refers to. Its free
+    /// variables are shifted in so that they still refer to the binders
+    /// outside of the new ones.
+    pub fn shifted_under(binders: Vec<ParameterKind<()>>, value: &T) -> Binders<T::Result> {
+        let len = binders.len();
+        Binders::new(binders, value.shifted_in(len))
+    }
+
+    /// The value out of these binders, with its free variables shifted
+    /// out of them, or `None` if it refers to any of them.
+    pub fn shifted_out_of(&self) -> Option<T::Result> {
+        self.value.shifted_out(self.len()).ok()
+    }
 }
 
 /// A folder that adjusts debruijn indices by a certain amount.
@@ -81,19 +114,23 @@ impl Shifter {
     /// Given a free variable at `depth`, shifts that depth to `depth
     /// + self.adjustment`, and then wraps *that* within the internal
     /// set `binders`.
-    fn adjust(&self, depth: usize, binders: usize) -> usize {
-        depth + self.adjustment + binders
+    fn adjust(&self, depth: DebruijnIndex, binders: usize) -> DebruijnIndex {
+        depth.shifted_in(self.adjustment + binders)
     }
 }
 
 impl DefaultTypeFolder for Shifter {}
 
 impl FreeVarFolder for Shifter {
-    fn fold_free_var_ty(&mut self, depth: usize, binders: usize) -> Fallible<Ty> {
-        Ok(Ty::BoundVar(self.adjust(depth, binders)))
+    fn fold_free_var_ty(&mut self, depth: DebruijnIndex, binders: usize) -> Fallible<Ty> {
+        Ok(self.adjust(depth, binders).to_ty())
     }
 
-    fn fold_free_var_lifetime(&mut self, depth: usize, binders: usize) -> Fallible<Lifetime> {
+    fn fold_free_var_lifetime(
+        &mut self,
+        depth: DebruijnIndex,
+        binders: usize,
+    ) -> Fallible<Lifetime> {
         Ok(Lifetime::BoundVar(self.adjust(depth, binders)))
     }
 }
@@ -120,9 +157,9 @@ impl DownShifter {
     /// those internal binders (i.e., `depth < self.adjustment`) the
     /// this will fail with `Err`. Otherwise, returns the variable at
     /// this new depth (but adjusted to appear within `binders`).
-    fn adjust(&self, depth: usize, binders: usize) -> Fallible<usize> {
-        match depth.checked_sub(self.adjustment) {
-            Some(new_depth) => Ok(new_depth + binders),
+    fn adjust(&self, depth: DebruijnIndex, binders: usize) -> Fallible<DebruijnIndex> {
+        match depth.shifted_out(self.adjustment) {
+            Some(new_depth) => Ok(new_depth.shifted_in(binders)),
             None => Err(NoSolution),
         }
     }
@@ -131,11 +168,15 @@ impl DownShifter {
 impl DefaultTypeFolder for DownShifter {}
 
 impl FreeVarFolder for DownShifter {
-    fn fold_free_var_ty(&mut self, depth: usize, binders: usize) -> Fallible<Ty> {
-        Ok(Ty::BoundVar(self.adjust(depth, binders)?))
+    fn fold_free_var_ty(&mut self, depth: DebruijnIndex, binders: usize) -> Fallible<Ty> {
+        Ok(self.adjust(depth, binders)?.to_ty())
     }
 
-    fn fold_free_var_lifetime(&mut self, depth: usize, binders: usize) -> Fallible<Lifetime> {
+    fn fold_free_var_lifetime(
+        &mut self,
+        depth: DebruijnIndex,
+        binders: usize,
+    ) -> Fallible<Lifetime> {
         Ok(Lifetime::BoundVar(self.adjust(depth, binders)?))
     }
 }
@@ -143,3 +184,29 @@ impl FreeVarFolder for DownShifter {
 impl DefaultPlaceholderFolder for DownShifter {}
 
 impl DefaultInferenceFolder for DownShifter {}
+
+//---------------------------------------------------------------------------
+
+/// A folder that fails on the first free variable that it finds, and
+/// otherwise leaves the value as it is.
+struct FreeVarFinder;
+
+impl DefaultTypeFolder for FreeVarFinder {}
+
+impl FreeVarFolder for FreeVarFinder {
+    fn fold_free_var_ty(&mut self, _depth: DebruijnIndex, _binders: usize) -> Fallible<Ty> {
+        Err(NoSolution)
+    }
+
+    fn fold_free_var_lifetime(
+        &mut self,
+        _depth: DebruijnIndex,
+        _binders: usize,
+    ) -> Fallible<Lifetime> {
+        Err(NoSolution)
+    }
+}
+
+impl DefaultPlaceholderFolder for FreeVarFinder {}
+
+impl DefaultInferenceFolder for FreeVarFinder {}
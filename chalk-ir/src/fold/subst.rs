@@ -16,7 +16,7 @@ impl<'s> Subst<'s> {
 
 impl QuantifiedTy {
     pub fn substitute(&self, parameters: &[Parameter]) -> Ty {
-        assert_eq!(self.num_binders, parameters.len());
+        assert_eq!(self.binders.len(), parameters.len());
         Subst::apply(parameters, &self.ty)
     }
 }
@@ -24,25 +24,27 @@ impl QuantifiedTy {
 impl<'b> DefaultTypeFolder for Subst<'b> {}
 
 impl<'b> FreeVarFolder for Subst<'b> {
-    fn fold_free_var_ty(&mut self, depth: usize, binders: usize) -> Fallible<Ty> {
-        if depth >= self.parameters.len() {
-            Ok(Ty::BoundVar(depth - self.parameters.len() + binders))
-        } else {
-            match self.parameters[depth] {
+    fn fold_free_var_ty(&mut self, depth: DebruijnIndex, binders: usize) -> Fallible<Ty> {
+        match depth.shifted_out(self.parameters.len()) {
+            Some(depth) => Ok(depth.shifted_in(binders).to_ty()),
+            None => match self.parameters[depth.depth()] {
                 ParameterKind::Ty(ref t) => Ok(t.shifted_in(binders)),
                 _ => panic!("mismatched kinds in substitution"),
-            }
+            },
         }
     }
 
-    fn fold_free_var_lifetime(&mut self, depth: usize, binders: usize) -> Fallible<Lifetime> {
-        if depth >= self.parameters.len() {
-            Ok(Lifetime::BoundVar(depth - self.parameters.len() + binders))
-        } else {
-            match self.parameters[depth] {
+    fn fold_free_var_lifetime(
+        &mut self,
+        depth: DebruijnIndex,
+        binders: usize,
+    ) -> Fallible<Lifetime> {
+        match depth.shifted_out(self.parameters.len()) {
+            Some(depth) => Ok(Lifetime::BoundVar(depth.shifted_in(binders))),
+            None => match self.parameters[depth.depth()] {
                 ParameterKind::Lifetime(ref l) => Ok(l.shifted_in(binders)),
                 _ => panic!("mismatched kinds in substitution"),
-            }
+            },
         }
     }
 }
@@ -0,0 +1,264 @@
+//! Estimates of the heap memory held by values of the IR, which the
+//! solver counts against the memory budget of its tables (see
+//! `chalk_engine::cancel::CancellationToken::limit_memory`).
+//!
+//! The estimates count the allocations that a value owns: the buffers
+//! of its vectors and boxes, and what the elements of those hold in
+//! turn. Data behind an `Arc` is counted in full, as if it was not
+//! shared, except for the environments of goals: every goal of a query
+//! shares the same few environments, so they are left out. Types are
+//! shared too, in the type arena (see `ty_arena`), but are counted like
+//! the data behind an `Arc`.
+
+use crate::*;
+use chalk_engine::context::Context;
+use chalk_engine::{DelayedLiteral, ExClause, Literal};
+use std::mem;
+use std::sync::Arc;
+
+pub trait HeapSize {
+    /// The number of bytes of heap memory held by `self`, not counting
+    /// the size of `self` itself.
+    fn heap_size(&self) -> usize;
+}
+
+impl<'a, T: HeapSize + ?Sized> HeapSize for &'a T {
+    fn heap_size(&self) -> usize {
+        (**self).heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for [T] {
+    fn heap_size(&self) -> usize {
+        self.iter().map(HeapSize::heap_size).sum()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * mem::size_of::<T>() + self[..].heap_size()
+    }
+}
+
+impl<A: smallvec::Array> HeapSize for SmallVec<A>
+where
+    A::Item: HeapSize,
+{
+    fn heap_size(&self) -> usize {
+        let buffer = if self.spilled() {
+            self.capacity() * mem::size_of::<A::Item>()
+        } else {
+            0
+        };
+        buffer + self[..].heap_size()
+    }
+}
+
+impl<T: HeapSize + ?Sized> HeapSize for Box<T> {
+    fn heap_size(&self) -> usize {
+        mem::size_of_val(&**self) + (**self).heap_size()
+    }
+}
+
+impl<T: HeapSize + ?Sized> HeapSize for Arc<T> {
+    fn heap_size(&self) -> usize {
+        // The two reference counts come before the value.
+        2 * mem::size_of::<usize>() + mem::size_of_val(&**self) + (**self).heap_size()
+    }
+}
+
+impl HeapSize for Ty {
+    fn heap_size(&self) -> usize {
+        // The node keeps the hash after the data.
+        mem::size_of::<u64>() + mem::size_of::<TyData>() + self.data().heap_size()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_size(&self) -> usize {
+        self.as_ref().map_or(0, HeapSize::heap_size)
+    }
+}
+
+impl<A: HeapSize, B: HeapSize> HeapSize for (A, B) {
+    fn heap_size(&self) -> usize {
+        self.0.heap_size() + self.1.heap_size()
+    }
+}
+
+macro_rules! leaf_heap_size {
+    ($($t:ty),* $(,)*) => {
+        $(
+            impl HeapSize for $t {
+                fn heap_size(&self) -> usize {
+                    0
+                }
+            }
+        )*
+    };
+}
+
+leaf_heap_size!(
+    (),
+    usize,
+    Identifier,
+    ItemId,
+    TypeName,
+    UniverseIndex,
+    DebruijnIndex,
+    InferenceVar,
+    PlaceholderIndex,
+    QuantifierKind,
+    Lifetime,
+);
+
+macro_rules! enum_heap_size {
+    ($s:ident [$($n:ident),*] { $($variant:ident($($name:ident),*)),* $(,)* } $($w:tt)*) => {
+        impl<$($n),*> HeapSize for $s<$($n),*> $($w)* {
+            fn heap_size(&self) -> usize {
+                match self {
+                    $(
+                        $s::$variant( $($name),* ) => 0 $(+ $name.heap_size())*,
+                    )*
+                }
+            }
+        }
+    };
+}
+
+enum_heap_size!(ParameterKind[T, L] { Ty(a), Lifetime(a) } where T: HeapSize, L: HeapSize);
+enum_heap_size!(TyData[] {
+    Apply(a),
+    Projection(a),
+    UnselectedProjection(a),
+    ForAll(a),
+    Dyn(a),
+    BoundVar(a),
+    InferenceVar(a),
+});
+enum_heap_size!(WhereClause[] { Implemented(a), ProjectionEq(a), ConstEvaluatable(a) });
+enum_heap_size!(WellFormed[] { Trait(a), Ty(a) });
+enum_heap_size!(FromEnv[] { Trait(a), Ty(a) });
+enum_heap_size!(DomainGoal[] {
+    Holds(a),
+    WellFormed(a),
+    FromEnv(a),
+    Normalize(a),
+    UnselectedNormalize(a),
+    InScope(a),
+    Derefs(a),
+    IsLocal(a),
+    IsUpstream(a),
+    IsFullyVisible(a),
+    LocalImplAllowed(a),
+    Compatible(a),
+    DownstreamType(a),
+    IsSized(a),
+    NeedsDrop(a),
+    Custom(a),
+});
+enum_heap_size!(LeafGoal[] { EqGoal(a), OutlivesGoal(a), DomainGoal(a) });
+enum_heap_size!(Constraint[] { LifetimeEq(a, b), Outlives(a, b) });
+enum_heap_size!(Goal[] {
+    Quantified(qkind, subgoal),
+    Implies(wc, subgoal),
+    And(g1, g2),
+    All(g),
+    Any(g),
+    Not(g),
+    Leaf(wc),
+    CannotProve(a),
+});
+enum_heap_size!(ProgramClause[] { Implies(a), ForAll(a) });
+
+macro_rules! struct_heap_size {
+    ($s:ident $([$($n:ident),*])* { $($name:ident),* $(,)* } $($w:tt)*) => {
+        impl$(<$($n),*>)* HeapSize for $s$(<$($n),*>)* $($w)* {
+            fn heap_size(&self) -> usize {
+                0 $(+ self.$name.heap_size())*
+            }
+        }
+    };
+}
+
+struct_heap_size!(ApplicationTy { name, parameters });
+struct_heap_size!(QuantifiedTy { binders, ty });
+struct_heap_size!(ProjectionTy {
+    associated_ty_id,
+    parameters,
+});
+struct_heap_size!(UnselectedProjectionTy {
+    type_name,
+    parameters,
+});
+struct_heap_size!(TraitRef {
+    trait_id,
+    parameters,
+});
+struct_heap_size!(Normalize { projection, ty });
+struct_heap_size!(ProjectionEq { projection, ty });
+struct_heap_size!(ConstEvaluatable {
+    const_name,
+    parameters,
+});
+struct_heap_size!(UnselectedNormalize { projection, ty });
+struct_heap_size!(CustomGoal { name, parameters });
+struct_heap_size!(EqGoal { a, b });
+struct_heap_size!(OutlivesGoal { a, b });
+struct_heap_size!(Derefs { source, target });
+struct_heap_size!(ProgramClauseImplication {
+    consequence,
+    conditions,
+});
+struct_heap_size!(Binders[T] { binders, value, names } where T: HeapSize);
+struct_heap_size!(BinderNames { names });
+struct_heap_size!(Canonical[T] { value, binders } where T: HeapSize);
+struct_heap_size!(UCanonical[T] { canonical, universes } where T: HeapSize);
+struct_heap_size!(Substitution { parameters });
+struct_heap_size!(ConstrainedSubst { subst, constraints });
+
+/// The environment is shared with the other goals of the query, and so
+/// is left out.
+impl<G: HeapSize> HeapSize for InEnvironment<G> {
+    fn heap_size(&self) -> usize {
+        self.goal.heap_size()
+    }
+}
+
+impl<C: Context> HeapSize for ExClause<C>
+where
+    C::Substitution: HeapSize,
+    C::RegionConstraint: HeapSize,
+    C::CanonicalConstrainedSubst: HeapSize,
+    C::GoalInEnvironment: HeapSize,
+{
+    fn heap_size(&self) -> usize {
+        self.subst.heap_size()
+            + self.delayed_literals.heap_size()
+            + self.constraints.heap_size()
+            + self.subgoals.heap_size()
+    }
+}
+
+impl<C: Context> HeapSize for DelayedLiteral<C>
+where
+    C::CanonicalConstrainedSubst: HeapSize,
+{
+    fn heap_size(&self) -> usize {
+        match self {
+            DelayedLiteral::CannotProve(()) | DelayedLiteral::Negative(_) => 0,
+            DelayedLiteral::Positive(_, subst) => subst.heap_size(),
+        }
+    }
+}
+
+impl<C: Context> HeapSize for Literal<C>
+where
+    C::GoalInEnvironment: HeapSize,
+{
+    fn heap_size(&self) -> usize {
+        match self {
+            Literal::Positive(goal) | Literal::Negative(goal) => goal.heap_size(),
+        }
+    }
+}
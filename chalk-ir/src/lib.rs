@@ -2,18 +2,39 @@
 #![feature(specialization)]
 
 use crate::cast::Cast;
+use crate::could_match::CouldMatch;
+use crate::fingerprint::Fingerprint;
 use crate::fold::shift::Shift;
 use crate::fold::{
-    DefaultInferenceFolder, DefaultPlaceholderFolder, DefaultTypeFolder, Fold, FreeVarFolder,
+    DefaultInferenceFolder, DefaultPlaceholderFolder, DefaultTypeFolder, Fold, FreeVarFolder, Subst,
 };
+use chalk_derive::{Cast, Fold, Zip};
 use chalk_engine::fallible::*;
-use lalrpop_intern::InternedString;
-use std::collections::BTreeSet;
+use chalk_macros::intern::InternedString;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
 use std::iter;
 use std::sync::Arc;
 
+// The derives refer to the traits through `::chalk_ir`.
+extern crate self as chalk_ir;
+
+extern crate chalk_derive;
 extern crate chalk_engine;
-extern crate lalrpop_intern;
+extern crate chalk_macros;
+#[macro_use]
+extern crate lazy_static;
+extern crate rustc_hash;
+#[cfg(feature = "serde")]
+extern crate serde;
+extern crate smallvec;
+
+// Re-exported so that the users of `Parameters` and `Conditions` can
+// build them without depending on `smallvec` themselves.
+pub use smallvec::smallvec;
 
 #[macro_use]
 mod macros;
@@ -28,44 +49,428 @@ pub mod cast;
 
 pub mod could_match;
 pub mod debug;
+pub mod fingerprint;
+pub mod heap_size;
+#[cfg(feature = "serde")]
+pub mod serde_impls;
+mod program_clauses;
 pub mod tls;
+pub mod ty_arena;
+pub mod visit;
+
+pub use crate::program_clauses::ProgramClauses;
+
+mod test;
 
 pub type Identifier = InternedString;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ProgramEnvironment {
     /// Indicates whether a given trait has coinductive semantics --
-    /// at present, this is true only for auto traits.
+    /// this is true for auto traits and for traits declared
+    /// `#[coinductive]`.
     pub coinductive_traits: BTreeSet<ItemId>,
 
+    /// The traits whose impls for tuples and fn pointers are built in (see
+    /// `ProgramEnvironment::builtin_clauses`).
+    pub builtin_traits: BTreeMap<ItemId, BuiltinTrait>,
+
+    /// The `Output` associated type of the `FnOnce` trait, if there is one.
+    pub fn_once_output: Option<ItemId>,
+
     /// Compiled forms of the above:
-    pub program_clauses: Vec<ProgramClause>,
+    pub program_clauses: ProgramClauses,
+}
+
+/// A source of program clauses. The solver asks for the clauses that
+/// could prove each goal as it comes across it, so an implementation
+/// can produce them on demand (as a compiler would, from its own item
+/// tables) rather than materializing every clause up front the way
+/// `ProgramEnvironment` does.
+pub trait ProgramClauseSet: Debug + Send + Sync {
+    /// Returns the program clauses that could be used to prove `goal`.
+    /// Every clause whose consequence could match `goal` must be
+    /// included; extra clauses are harmless, as the solver will fail to
+    /// unify them with the goal.
+    fn program_clauses_for_goal(&self, goal: &DomainGoal) -> Vec<ProgramClause>;
+
+    /// Whether the given trait has coinductive semantics -- that is,
+    /// whether it is an auto trait or declared `#[coinductive]`.
+    fn is_coinductive_trait(&self, trait_id: ItemId) -> bool;
+}
+
+impl ProgramClauseSet for ProgramEnvironment {
+    fn program_clauses_for_goal(&self, goal: &DomainGoal) -> Vec<ProgramClause> {
+        let clauses = self.program_clauses.memoized(goal, |goal| {
+            self.program_clauses
+                .clauses_for_goal(goal)
+                .cloned()
+                .chain(goal.object_clauses())
+                .chain(self.builtin_clauses(goal))
+                .collect()
+        });
+        clauses.to_vec()
+    }
+
+    fn is_coinductive_trait(&self, trait_id: ItemId) -> bool {
+        self.coinductive_traits.contains(&trait_id)
+    }
+}
+
+/// A trait that the language implements for tuples and fn pointers. These
+/// impls exist for every arity, so they are not part of the program
+/// clauses but are produced for each goal that needs them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BuiltinTrait {
+    Sized,
+    Copy,
+    Clone,
+    FnOnce,
+    FnMut,
+    Fn,
+
+    /// An auto trait: a tuple implements it if its elements do, and fn
+    /// pointers always do. Unlike for structs, impls in the program do
+    /// not turn these rules off.
+    Auto,
+}
+
+/// Supplies the program clauses for custom goals with a given name (see
+/// `CustomGoals`).
+pub trait CustomGoalHandler: Debug + Send + Sync {
+    /// Returns the program clauses that could be used to prove `goal`,
+    /// under the same rules as `ProgramClauseSet::program_clauses_for_goal`.
+    /// The parameters of `goal` may contain inference variables, so
+    /// the clauses should be stated in terms of their own (bound)
+    /// variables rather than in terms of the parameters.
+    fn program_clauses(&self, goal: &CustomGoal) -> Vec<ProgramClause>;
+}
+
+/// A `ProgramClauseSet` that extends an underlying program with the
+/// clauses of custom goal handlers: when the solver asks for the
+/// clauses of a custom goal, it calls out to the handler registered for
+/// the goal's name. A custom goal with no handler can only be proven
+/// from the clauses of the program (or of the environment).
+#[derive(Debug)]
+pub struct CustomGoals {
+    program: Arc<dyn ProgramClauseSet>,
+    handlers: BTreeMap<Identifier, Arc<dyn CustomGoalHandler>>,
+}
+
+impl CustomGoals {
+    pub fn new(program: Arc<dyn ProgramClauseSet>) -> Self {
+        CustomGoals {
+            program,
+            handlers: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `handler` for the custom goals named `name`, replacing
+    /// any handler registered for it before.
+    pub fn register(&mut self, name: Identifier, handler: Arc<dyn CustomGoalHandler>) {
+        self.handlers.insert(name, handler);
+    }
+}
+
+impl ProgramClauseSet for CustomGoals {
+    fn program_clauses_for_goal(&self, goal: &DomainGoal) -> Vec<ProgramClause> {
+        let mut clauses = self.program.program_clauses_for_goal(goal);
+        if let DomainGoal::Custom(custom_goal) = goal {
+            if let Some(handler) = self.handlers.get(&custom_goal.name) {
+                clauses.extend(handler.program_clauses(custom_goal));
+            }
+        }
+        clauses
+    }
+
+    fn is_coinductive_trait(&self, trait_id: ItemId) -> bool {
+        self.program.is_coinductive_trait(trait_id)
+    }
+}
+
+impl ProgramEnvironment {
+    /// A fingerprint of the program, for telling whether the solutions
+    /// found for it still hold for another. It does not depend on the
+    /// order that the items of the program were declared in.
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of(&(
+            &self.coinductive_traits,
+            &self.builtin_traits,
+            self.fn_once_output,
+            self.program_clauses.fingerprint(),
+        ))
+    }
+
+    /// Returns the built-in clauses that could be used to prove `goal`,
+    /// instantiated for the arity of its self type. Given
+    /// `Implemented((u32, T): Clone)`, for instance, this is:
+    ///
+    /// ```notrust
+    /// forall<A, B> { Implemented((A, B): Clone) :- Implemented(A: Clone), Implemented(B: Clone) }
+    /// ```
+    ///
+    /// Fn pointers are `Sized`, `Copy` and `Clone`, and implement the `Fn`
+    /// traits for their argument types:
+    ///
+    /// ```notrust
+    /// forall<A, R> { Implemented(fn(A) -> R: Fn<(A,)>) }
+    /// forall<A, R> { Normalize(<fn(A) -> R as FnOnce<(A,)>>::Output -> R) }
+    /// ```
+    ///
+    /// Like a struct without where clauses, tuples and fn pointers are always
+    /// well-formed (the well-formedness of their component types is checked
+    /// separately). For coherence, they are upstream types that are not
+    /// fundamental, so they are never local, and they are fully visible if
+    /// their parameters are:
+    ///
+    /// ```notrust
+    /// forall<A, B> { IsUpstream((A, B)) }
+    /// forall<A, B> { IsFullyVisible((A, B)) :- IsFullyVisible(A), IsFullyVisible(B) }
+    /// ```
+    ///
+    /// A goal whose self type is not known yet gets no built-in clauses.
+    pub fn builtin_clauses(&self, goal: &DomainGoal) -> Vec<ProgramClause> {
+        let self_ty = match goal {
+            DomainGoal::Holds(WhereClause::Implemented(trait_ref)) => {
+                trait_ref.parameters[0].as_ref().ty()
+            }
+            DomainGoal::Normalize(normalize) => normalize.projection.parameters[0].as_ref().ty(),
+            DomainGoal::WellFormed(WellFormed::Ty(ty))
+            | DomainGoal::IsFullyVisible(ty)
+            | DomainGoal::IsUpstream(ty) => Some(ty),
+            _ => None,
+        };
+        let (name, binders) = match self_ty.map(Ty::data) {
+            Some(TyData::Apply(ApplicationTy { name, .. })) => match *name {
+                TypeName::Tuple(arity) => (*name, arity),
+                TypeName::FnPtr(arity) => (*name, arity + 1),
+                _ => return vec![],
+            },
+            _ => return vec![],
+        };
+
+        // The self type, and the tuple of the arguments of a fn pointer, in
+        // terms of the bound variables of the clause.
+        let bound_vars = |range: ::std::ops::Range<usize>| -> Vec<Parameter> {
+            range
+                .map(|index| DebruijnIndex::new(index).to_ty().cast())
+                .collect()
+        };
+        let self_ty = TyData::Apply(ApplicationTy {
+            name,
+            parameters: bound_vars(0..binders),
+        })
+        .intern();
+        let args_ty = || {
+            TyData::Apply(ApplicationTy {
+                name: TypeName::Tuple(binders - 1),
+                parameters: bound_vars(0..binders - 1),
+            })
+            .intern()
+        };
+
+        let implication = match goal {
+            DomainGoal::Holds(WhereClause::Implemented(trait_ref)) => {
+                let trait_id = trait_ref.trait_id;
+                let builtin_trait = match self.builtin_traits.get(&trait_id) {
+                    Some(&builtin_trait) => builtin_trait,
+                    None => return vec![],
+                };
+                let implemented = |parameters: Parameters| TraitRef {
+                    trait_id,
+                    parameters,
+                };
+                match (builtin_trait, name) {
+                    (BuiltinTrait::Sized, TypeName::Tuple(_))
+                    | (BuiltinTrait::Copy, TypeName::Tuple(_))
+                    | (BuiltinTrait::Clone, TypeName::Tuple(_))
+                    | (BuiltinTrait::Auto, TypeName::Tuple(_)) => ProgramClauseImplication {
+                        consequence: implemented(smallvec![self_ty.cast()]).cast(),
+                        conditions: bound_vars(0..binders)
+                            .into_iter()
+                            .map(|bound_var| implemented(smallvec![bound_var]).cast())
+                            .collect(),
+                    },
+                    (BuiltinTrait::Sized, TypeName::FnPtr(_))
+                    | (BuiltinTrait::Copy, TypeName::FnPtr(_))
+                    | (BuiltinTrait::Clone, TypeName::FnPtr(_))
+                    | (BuiltinTrait::Auto, TypeName::FnPtr(_)) => ProgramClauseImplication {
+                        consequence: implemented(smallvec![self_ty.cast()]).cast(),
+                        conditions: smallvec![],
+                    },
+                    (BuiltinTrait::FnOnce, TypeName::FnPtr(_))
+                    | (BuiltinTrait::FnMut, TypeName::FnPtr(_))
+                    | (BuiltinTrait::Fn, TypeName::FnPtr(_)) => ProgramClauseImplication {
+                        consequence: implemented(smallvec![self_ty.cast(), args_ty().cast()])
+                            .cast(),
+                        conditions: smallvec![],
+                    },
+                    _ => return vec![],
+                }
+            }
+            DomainGoal::Normalize(normalize) => match name {
+                TypeName::FnPtr(_)
+                    if Some(normalize.projection.associated_ty_id) == self.fn_once_output =>
+                {
+                    ProgramClauseImplication {
+                        consequence: Normalize {
+                            projection: ProjectionTy {
+                                associated_ty_id: normalize.projection.associated_ty_id,
+                                parameters: vec![self_ty.cast(), args_ty().cast()],
+                            },
+                            ty: DebruijnIndex::new(binders - 1).to_ty(),
+                        }
+                        .cast(),
+                        conditions: smallvec![],
+                    }
+                }
+                _ => return vec![],
+            },
+            DomainGoal::WellFormed(_) => ProgramClauseImplication {
+                consequence: DomainGoal::WellFormed(WellFormed::Ty(self_ty)),
+                conditions: smallvec![],
+            },
+            DomainGoal::IsFullyVisible(_) => ProgramClauseImplication {
+                consequence: DomainGoal::IsFullyVisible(self_ty),
+                conditions: (0..binders)
+                    .map(|index| {
+                        DomainGoal::IsFullyVisible(DebruijnIndex::new(index).to_ty()).cast()
+                    })
+                    .collect(),
+            },
+            DomainGoal::IsUpstream(_) => ProgramClauseImplication {
+                consequence: DomainGoal::IsUpstream(self_ty),
+                conditions: smallvec![],
+            },
+            _ => return vec![],
+        };
+
+        vec![Binders::new(vec![ParameterKind::Ty(()); binders], implication).cast()]
+    }
+
+    /// Returns the clauses that appear in exactly one of `self` and
+    /// `other`. Any goal that none of these clauses could match has
+    /// the same program clauses in both environments.
+    pub fn changed_clauses(&self, other: &ProgramEnvironment) -> Vec<ProgramClause> {
+        let clauses: BTreeSet<_> = self.program_clauses.iter().collect();
+        let other_clauses: BTreeSet<_> = other.program_clauses.iter().collect();
+        clauses
+            .symmetric_difference(&other_clauses)
+            .map(|&clause| clause.clone())
+            .collect()
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        from = "crate::serde_impls::EnvironmentClauses",
+        into = "crate::serde_impls::EnvironmentClauses"
+    )
+)]
 /// The set of assumptions we've made so far, and the current number of
 /// universal (forall) quantifiers we're within.
 pub struct Environment {
     pub clauses: Vec<ProgramClause>,
+
+    /// Where the clauses with each `GoalKey` are in `clauses`.
+    index: ClauseIndex,
 }
 
 impl Environment {
     pub fn new() -> Arc<Self> {
-        Arc::new(Environment { clauses: vec![] })
+        Arc::new(Environment::from_clauses(vec![]))
+    }
+
+    crate fn from_clauses(clauses: Vec<ProgramClause>) -> Self {
+        let index = ClauseIndex::new(&clauses);
+        Environment { clauses, index }
     }
 
     pub fn add_clauses<I>(&self, clauses: I) -> Arc<Self>
     where
         I: IntoIterator<Item = ProgramClause>,
     {
-        let mut env = self.clone();
-        let env_clauses: BTreeSet<_> = env.clauses.into_iter().chain(clauses).collect();
-        env.clauses = env_clauses.into_iter().collect();
-        Arc::new(env)
+        let env_clauses: BTreeSet<_> = self.clauses.iter().cloned().chain(clauses).collect();
+        Arc::new(Environment::from_clauses(env_clauses.into_iter().collect()))
+    }
+
+    /// The clauses that could prove `goal`, in order. When `goal` has a
+    /// `GoalKey`, only the clauses with the same key are looked at, so
+    /// this does not get slower as unrelated hypotheses pile up.
+    pub fn clauses_for_goal<'a>(
+        &'a self,
+        goal: &'a DomainGoal,
+    ) -> impl Iterator<Item = &'a ProgramClause> + 'a {
+        let candidates: Box<dyn Iterator<Item = &'a ProgramClause> + 'a> = match goal.key() {
+            Some(key) => Box::new(
+                self.index
+                    .positions(key)
+                    .iter()
+                    .map(move |&position| &self.clauses[position]),
+            ),
+            None => Box::new(self.clauses.iter()),
+        };
+        candidates.filter(move |&clause| clause.could_match(goal))
+    }
+}
+
+/// Indexes a list of clauses by the `GoalKey`s of their consequences.
+/// It is derived from the clauses, so it is ignored when comparing or
+/// hashing the environment that holds it.
+#[derive(Clone, Default)]
+struct ClauseIndex {
+    positions: BTreeMap<GoalKey, Vec<usize>>,
+}
+
+impl ClauseIndex {
+    fn new(clauses: &[ProgramClause]) -> Self {
+        let mut positions: BTreeMap<GoalKey, Vec<usize>> = BTreeMap::new();
+        for (position, clause) in clauses.iter().enumerate() {
+            if let Some(key) = clause.consequence().key() {
+                positions.entry(key).or_default().push(position);
+            }
+        }
+        ClauseIndex { positions }
+    }
+
+    fn positions(&self, key: GoalKey) -> &[usize] {
+        self.positions
+            .get(&key)
+            .map(|positions| &positions[..])
+            .unwrap_or(&[])
+    }
+}
+
+impl PartialEq for ClauseIndex {
+    fn eq(&self, _other: &ClauseIndex) -> bool {
+        true
+    }
+}
+
+impl Eq for ClauseIndex {}
+
+impl PartialOrd for ClauseIndex {
+    fn partial_cmp(&self, other: &ClauseIndex) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+impl Ord for ClauseIndex {
+    fn cmp(&self, _other: &ClauseIndex) -> std::cmp::Ordering {
+        std::cmp::Ordering::Equal
+    }
+}
+
+impl std::hash::Hash for ClauseIndex {
+    fn hash<H: std::hash::Hasher>(&self, _state: &mut H) {}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InEnvironment<G> {
     pub environment: Arc<Environment>,
     pub goal: G,
@@ -91,6 +496,7 @@ impl<G> InEnvironment<G> {
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum TypeName {
     /// a type like `Vec<T>`
     ItemId(ItemId),
@@ -102,6 +508,15 @@ pub enum TypeName {
 
     /// an associated type like `Iterator::Item`; see `AssociatedType` for details
     AssociatedType(ItemId),
+
+    /// a tuple type of the given arity, like `(u32, T)`; its parameters are
+    /// the types of the elements
+    Tuple(usize),
+
+    /// a fn pointer type taking the given number of arguments, like
+    /// `fn(u32) -> T`; its parameters are the argument types followed by the
+    /// return type
+    FnPtr(usize),
 }
 
 /// An universe index is how a universally quantified parameter is
@@ -112,67 +527,206 @@ pub enum TypeName {
 /// `Goal(!U1)` (the quantifier was moved to the environment and replaced with a universe index)
 /// See https://rust-lang-nursery.github.io/rustc-guide/mir/regionck.html#skol for more.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct UniverseIndex {
-    pub counter: usize,
+    counter: usize,
 }
 
 impl UniverseIndex {
     pub const ROOT: UniverseIndex = UniverseIndex { counter: 0 };
 
+    pub const fn new(counter: usize) -> Self {
+        UniverseIndex { counter }
+    }
+
     pub fn root() -> UniverseIndex {
         Self::ROOT
     }
 
+    pub fn counter(self) -> usize {
+        self.counter
+    }
+
+    /// Whether the names of `ui` are visible from this universe, i.e.
+    /// whether `ui` is this universe or one of the universes it extends.
     pub fn can_see(self, ui: UniverseIndex) -> bool {
         self.counter >= ui.counter
     }
 
+    /// Whether an inference variable of this universe may be equated
+    /// with `placeholder`.
+    pub fn can_name(self, placeholder: PlaceholderIndex) -> bool {
+        self.can_see(placeholder.ui)
+    }
+
     pub fn next(self) -> UniverseIndex {
-        UniverseIndex {
-            counter: self.counter + 1,
-        }
+        self.nth_next(1)
+    }
+
+    /// The universe `n` universes after this one, which can see it and
+    /// all the universes in between.
+    pub fn nth_next(self, n: usize) -> UniverseIndex {
+        UniverseIndex::new(self.counter + n)
+    }
+
+    /// The placeholder for the `idx`th binder of the `forall<>` that this
+    /// universe was created for.
+    pub fn placeholder(self, idx: usize) -> PlaceholderIndex {
+        PlaceholderIndex { ui: self, idx }
     }
 }
 
+/// Identifies an item of the program. Lowering derives ids from the
+/// paths of the items (see `ItemId::from_path`), so they do not change
+/// when items are added, removed or reordered around them.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ItemId {
     pub index: usize,
 }
 
+impl ItemId {
+    /// The number of low bits of an id that hold its disambiguator.
+    const DISAMBIGUATOR_BITS: u32 = 16;
+
+    /// The id of the item at `path`. Items that share a path (such as
+    /// two impls of a trait for the same type) are told apart by their
+    /// `disambiguator`. The hash of the path makes up the high bits of
+    /// the id and the disambiguator its low bits, so ids with the same
+    /// path are ordered by disambiguator.
+    pub fn from_path(path: &str, disambiguator: usize) -> ItemId {
+        assert!(disambiguator < 1 << Self::DISAMBIGUATOR_BITS);
+
+        // FNV-1a, which unlike the hasher of the standard library is
+        // guaranteed to give the same hash in every build.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in path.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+
+        let high_bits = hash >> Self::DISAMBIGUATOR_BITS << Self::DISAMBIGUATOR_BITS;
+        ItemId {
+            index: (high_bits | disambiguator as u64) as usize,
+        }
+    }
+
+    /// The id with the same path as `self` and the next disambiguator,
+    /// or `None` if `self` has the last disambiguator there is.
+    pub fn next_disambiguator(self) -> Option<ItemId> {
+        let mask = (1 << Self::DISAMBIGUATOR_BITS) - 1;
+        if self.index & mask == mask {
+            None
+        } else {
+            Some(ItemId {
+                index: self.index + 1,
+            })
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum TypeSort {
     Struct,
     Trait,
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub enum Ty {
+/// A De Bruijn index: which of the binders in scope a bound variable
+/// refers to, counting outwards from the innermost one, which is 0. A
+/// binder with several variables counts as that many binders.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DebruijnIndex {
+    depth: usize,
+}
+
+impl DebruijnIndex {
+    pub const INNERMOST: DebruijnIndex = DebruijnIndex { depth: 0 };
+
+    pub fn new(depth: usize) -> Self {
+        DebruijnIndex { depth }
+    }
+
+    pub fn depth(self) -> usize {
+        self.depth
+    }
+
+    pub fn to_ty(self) -> Ty {
+        TyData::BoundVar(self).intern()
+    }
+
+    /// The same variable, seen from under `binders` more binders.
+    pub fn shifted_in(self, binders: usize) -> Self {
+        DebruijnIndex::new(self.depth + binders)
+    }
+
+    /// The same variable, seen from outside the innermost `binders` of
+    /// the binders in scope -- or `None` if one of those binds it.
+    pub fn shifted_out(self, binders: usize) -> Option<Self> {
+        self.depth.checked_sub(binders).map(DebruijnIndex::new)
+    }
+}
+
+/// A type, as the id of its `TyData` in the type arena (see `ty_arena`).
+/// Each distinct type is interned there once, so types are copied rather
+/// than cloned, and compared and hashed without walking them: two types
+/// are equal exactly when they are the same node of the arena. They are
+/// still ordered by their data, so that the order does not depend on
+/// when they were interned.
+#[derive(Copy, Clone)]
+pub struct Ty {
+    node: &'static ty_arena::TyNode,
+}
+
+/// The data of a type, which `intern` turns into a `Ty`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Cast)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TyData {
+    #[cast]
     Apply(ApplicationTy),
+    #[cast]
     Projection(ProjectionTy),
     UnselectedProjection(UnselectedProjectionTy),
     ForAll(Box<QuantifiedTy>),
 
+    /// An object type, e.g. `dyn Iterator<Item = u32> + Send`. Its bounds
+    /// refer to the object type itself as the bound variable `^0`, so the
+    /// example is `dyn for<type> [Implemented(^0: Iterator),
+    /// ProjectionEq(<^0 as Iterator>::Item = u32), Implemented(^0: Send)]`.
+    Dyn(Binders<Vec<QuantifiedWhereClause>>),
+
     /// References the binding at the given depth (deBruijn index
     /// style).
-    BoundVar(usize),
+    BoundVar(DebruijnIndex),
 
     /// Inference variable.
     InferenceVar(InferenceVar),
 }
 
+impl TyData {
+    /// The type with this data, interning it if it is new.
+    pub fn intern(self) -> Ty {
+        ty_arena::intern(self)
+    }
+}
+
 impl Ty {
-    /// If this is a `Ty::BoundVar(d)`, returns `Some(d)` else `None`.
-    pub fn bound(&self) -> Option<usize> {
-        if let Ty::BoundVar(depth) = *self {
+    pub fn data(&self) -> &'static TyData {
+        &self.node.data
+    }
+
+    /// If this is a `TyData::BoundVar(d)`, returns `Some(d)` else `None`.
+    pub fn bound(&self) -> Option<DebruijnIndex> {
+        if let TyData::BoundVar(depth) = *self.data() {
             Some(depth)
         } else {
             None
         }
     }
 
-    /// If this is a `Ty::InferenceVar(d)`, returns `Some(d)` else `None`.
+    /// If this is a `TyData::InferenceVar(d)`, returns `Some(d)` else `None`.
     pub fn inference_var(&self) -> Option<InferenceVar> {
-        if let Ty::InferenceVar(depth) = *self {
+        if let TyData::InferenceVar(depth) = *self.data() {
             Some(depth)
         } else {
             None
@@ -180,16 +734,16 @@ impl Ty {
     }
 
     pub fn as_projection_ty_enum(&self) -> ProjectionTyRefEnum {
-        match *self {
-            Ty::Projection(ref proj) => ProjectionTyEnum::Selected(proj),
-            Ty::UnselectedProjection(ref proj) => ProjectionTyEnum::Unselected(proj),
+        match *self.data() {
+            TyData::Projection(ref proj) => ProjectionTyEnum::Selected(proj),
+            TyData::UnselectedProjection(ref proj) => ProjectionTyEnum::Unselected(proj),
             _ => panic!("{:?} is not a projection", self),
         }
     }
 
     pub fn is_projection(&self) -> bool {
-        match *self {
-            Ty::Projection(..) | Ty::UnselectedProjection(..) => true,
+        match *self.data() {
+            TyData::Projection(..) | TyData::UnselectedProjection(..) => true,
             _ => false,
         }
     }
@@ -203,6 +757,7 @@ impl Ty {
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InferenceVar {
     index: u32,
 }
@@ -219,7 +774,7 @@ impl InferenceVar {
     }
 
     pub fn to_ty(self) -> Ty {
-        Ty::InferenceVar(self)
+        TyData::InferenceVar(self).intern()
     }
 
     pub fn to_lifetime(self) -> Lifetime {
@@ -227,20 +782,26 @@ impl InferenceVar {
     }
 }
 
-/// for<'a...'z> X -- all binders are instantiated at once,
-/// and we use deBruijn indices within `self.ty`
+/// for<'a...'z, T...> X -- all binders are instantiated at once,
+/// and we use deBruijn indices within `self.ty`. Binders are usually
+/// lifetimes, as in `for<'a> fn(&'a u32)`, but may be of any kind.
 #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct QuantifiedTy {
-    pub num_binders: usize,
+    pub binders: Vec<ParameterKind<()>>,
     pub ty: Ty,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Lifetime {
     /// See Ty::Var(_).
-    BoundVar(usize),
+    BoundVar(DebruijnIndex),
     InferenceVar(InferenceVar),
     Placeholder(PlaceholderIndex),
+    /// Stands for any lifetime, once lifetimes have been erased (see
+    /// `fold::erase`). Erased lifetimes are all equal to one another.
+    Erased,
 }
 
 impl Lifetime {
@@ -260,6 +821,7 @@ impl Lifetime {
             Lifetime::BoundVar(_) => true,
             Lifetime::InferenceVar(_) => false,
             Lifetime::Placeholder(_) => false,
+            Lifetime::Erased => false,
         }
     }
 }
@@ -268,27 +830,44 @@ impl Lifetime {
 /// Two indexes are required, the one of the universe itself
 /// and the relative index inside the universe.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PlaceholderIndex {
     /// Index *of* the universe.
-    pub ui: UniverseIndex,
+    ui: UniverseIndex,
     /// Index *in* the universe.
-    pub idx: usize,
+    idx: usize,
 }
 
 impl PlaceholderIndex {
+    pub fn universe(self) -> UniverseIndex {
+        self.ui
+    }
+
+    pub fn index(self) -> usize {
+        self.idx
+    }
+
+    /// The placeholder for the same binder, moved to universe `ui`, as
+    /// when mapping the universes of a value to others.
+    pub fn in_universe(self, ui: UniverseIndex) -> PlaceholderIndex {
+        ui.placeholder(self.idx)
+    }
+
     pub fn to_lifetime(self) -> Lifetime {
         Lifetime::Placeholder(self)
     }
 
     pub fn to_ty(self) -> Ty {
-        Ty::Apply(ApplicationTy {
+        TyData::Apply(ApplicationTy {
             name: TypeName::Placeholder(self),
             parameters: vec![],
         })
+        .intern()
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ApplicationTy {
     pub name: TypeName,
     pub parameters: Vec<Parameter>,
@@ -308,7 +887,8 @@ impl ApplicationTy {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ParameterKind<T, L = T> {
     Ty(T),
     Lifetime(L),
@@ -373,14 +953,26 @@ impl<T, L> ParameterKind<T, L> {
 
 pub type Parameter = ParameterKind<Ty, Lifetime>;
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// The parameters of a trait reference. Most trait references only have
+/// the self type, which is kept inline rather than allocating a vector.
+/// Only one is kept inline: trait references are part of every domain
+/// goal, and room for more parameters makes all goals larger, which
+/// costs more than the allocations it saves. Types and projections keep
+/// their parameters in a `Vec`: they are stored inline in `Ty`, which
+/// could not have a size otherwise.
+pub type Parameters = SmallVec<[Parameter; 1]>;
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ProjectionTy {
     pub associated_ty_id: ItemId,
     pub parameters: Vec<Parameter>,
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct UnselectedProjectionTy {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::identifier"))]
     pub type_name: Identifier,
     pub parameters: Vec<Parameter>,
 }
@@ -393,10 +985,12 @@ pub enum ProjectionTyEnum<S = ProjectionTy, U = UnselectedProjectionTy> {
 
 pub type ProjectionTyRefEnum<'a> = ProjectionTyEnum<&'a ProjectionTy, &'a UnselectedProjectionTy>;
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TraitRef {
     pub trait_id: ItemId,
-    pub parameters: Vec<Parameter>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::small_vec"))]
+    pub parameters: Parameters,
 }
 
 impl TraitRef {
@@ -406,19 +1000,50 @@ impl TraitRef {
 }
 
 /// Where clauses that can be written by a Rust programmer.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip, Cast)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum WhereClause {
+    #[cast]
     Implemented(TraitRef),
+    #[cast]
     ProjectionEq(ProjectionEq),
+    #[cast]
+    ConstEvaluatable(ConstEvaluatable),
+}
+
+/// States that a constant expression can be evaluated under the current
+/// environment, as in `where [(); N + 1]: Sized`. Chalk does not evaluate
+/// constants itself: the expression is identified by name and applied to
+/// the parameters it mentions, and it holds only if it can be derived from
+/// the environment or from program clauses.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConstEvaluatable {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::identifier"))]
+    pub const_name: Identifier,
+    pub parameters: Vec<Parameter>,
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+/// A custom predicate, identified by name and applied to some
+/// parameters, as in `Custom(IsPod<T>)`. Its meaning is entirely up to
+/// the `CustomGoalHandler` registered for the name.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CustomGoal {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::identifier"))]
+    pub name: Identifier,
+    pub parameters: Vec<Parameter>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Derefs {
     pub source: Ty,
     pub target: Ty,
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum WellFormed {
     /// A predicate which is true is some trait ref is well-formed.
     /// For example, given the following trait definitions:
@@ -448,7 +1073,8 @@ pub enum WellFormed {
     Ty(Ty),
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum FromEnv {
     /// A predicate which enables deriving everything which should be true if we *know* that
     /// some trait ref is well-formed. For example given the above trait definitions, we can use
@@ -480,13 +1106,18 @@ pub enum FromEnv {
 /// A "domain goal" is a goal that is directly about Rust, rather than a pure
 /// logical statement. As much as possible, the Chalk solver should avoid
 /// decomposing this enum, and instead treat its values opaquely.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip, Cast)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum DomainGoal {
     Holds(WhereClause),
+    #[cast]
     WellFormed(WellFormed),
+    #[cast]
     FromEnv(FromEnv),
 
+    #[cast]
     Normalize(Normalize),
+    #[cast]
     UnselectedNormalize(UnselectedNormalize),
 
     InScope(ItemId),
@@ -549,6 +1180,27 @@ pub enum DomainGoal {
     ///
     /// This makes a new type `T` available and makes `DownstreamType(T)` provable for that type.
     DownstreamType(Ty),
+
+    /// True if a type has a statically known size. This is derived structurally from struct
+    /// definitions: a struct is sized if its last field is sized (or if it has no fields).
+    ///
+    /// forall<T> { IsSized(Foo<T>) :- IsSized(T). } // for `struct Foo<T> { a: u32, b: T }`
+    ///
+    /// Type parameters carry no such rule, so `IsSized(T)` for a generic `T` must come from
+    /// the environment.
+    IsSized(Ty),
+
+    /// True if dropping a value of this type runs some drop glue, i.e. the type implements the
+    /// `#[lang_drop]` trait or one of its fields needs drop:
+    ///
+    /// forall<T> { NeedsDrop(T) :- Implemented(T: Drop). }
+    /// forall<T> { NeedsDrop(Foo<T>) :- NeedsDrop(T). } // for each field of `Foo`
+    NeedsDrop(Ty),
+
+    /// A predicate chalk knows nothing about, proven by the handler registered for its name
+    /// (see `CustomGoals`). This lets an embedder try out new predicates without extending the
+    /// IR.
+    Custom(CustomGoal),
 }
 
 pub type QuantifiedWhereClause = Binders<WhereClause>;
@@ -575,34 +1227,135 @@ impl WhereClause {
 }
 
 impl DomainGoal {
+    /// The facts about the object types of this goal, which the bounds
+    /// of each object type give: `dyn Iterator<Item = u32> + Send`
+    /// implements `Iterator` and `Send`, and `<dyn Iterator<Item = u32> +
+    /// Send as Iterator>::Item` normalizes to `u32`. Only the facts that
+    /// could match the goal are returned.
+    ///
+    /// The facts mention the object types as they appear in the goal,
+    /// inference variables included, so they are only meaningful in the
+    /// inference table of the goal.
+    pub fn object_clauses(&self) -> Vec<ProgramClause> {
+        let parameters = match self {
+            DomainGoal::Holds(WhereClause::Implemented(trait_ref)) => &trait_ref.parameters[..],
+            DomainGoal::Normalize(normalize) => &normalize.projection.parameters[..],
+            _ => return vec![],
+        };
+
+        let mut clauses = vec![];
+        for parameter in parameters {
+            let bounds = match parameter {
+                ParameterKind::Ty(ty) => match ty.data() {
+                    TyData::Dyn(bounds) => bounds,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            for bound in Subst::apply(&[parameter.clone()], &bounds.value) {
+                let clause: ProgramClause = bound
+                    .map(|where_clause| match where_clause {
+                        WhereClause::ProjectionEq(ProjectionEq { projection, ty }) => {
+                            DomainGoal::Normalize(Normalize { projection, ty })
+                        }
+                        where_clause => DomainGoal::Holds(where_clause),
+                    })
+                    .cast();
+                if clause.could_match(self) {
+                    clauses.push(clause);
+                }
+            }
+        }
+        clauses
+    }
+
     pub fn into_from_env_goal(self) -> DomainGoal {
         match self {
             DomainGoal::Holds(wc) => wc.into_from_env_goal(),
             goal => goal,
         }
     }
+
+    /// The key of this goal, if it is about a trait or an associated
+    /// type (see `GoalKey`).
+    pub fn key(&self) -> Option<GoalKey> {
+        match self {
+            DomainGoal::Holds(WhereClause::Implemented(trait_ref)) => {
+                Some(GoalKey::Implemented(trait_ref.trait_id))
+            }
+            DomainGoal::Holds(WhereClause::ProjectionEq(projection_eq)) => Some(
+                GoalKey::ProjectionEq(projection_eq.projection.associated_ty_id),
+            ),
+            DomainGoal::WellFormed(WellFormed::Trait(trait_ref)) => {
+                Some(GoalKey::WellFormedTrait(trait_ref.trait_id))
+            }
+            DomainGoal::FromEnv(FromEnv::Trait(trait_ref)) => {
+                Some(GoalKey::FromEnvTrait(trait_ref.trait_id))
+            }
+            DomainGoal::Normalize(normalize) => {
+                Some(GoalKey::Normalize(normalize.projection.associated_ty_id))
+            }
+            DomainGoal::InScope(trait_id) => Some(GoalKey::InScope(*trait_id)),
+            DomainGoal::LocalImplAllowed(trait_ref) => {
+                Some(GoalKey::LocalImplAllowed(trait_ref.trait_id))
+            }
+            _ => None,
+        }
+    }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// The kind of a domain goal, along with the trait or associated type
+/// it is about. Domain goals with different keys never unify, which
+/// lets an `Environment` or `ProgramClauses` only look at the clauses
+/// with the key of a goal to prove it. Goals of the other kinds have no
+/// key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GoalKey {
+    Implemented(ItemId),
+    ProjectionEq(ItemId),
+    WellFormedTrait(ItemId),
+    FromEnvTrait(ItemId),
+    Normalize(ItemId),
+    InScope(ItemId),
+    LocalImplAllowed(ItemId),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip, Cast)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A goal that does not involve any logical connectives. Equality is treated
 /// specially by the logic (as with most first-order logics), since it interacts
 /// with unification etc.
 pub enum LeafGoal {
+    #[cast]
     EqGoal(EqGoal),
+    #[cast]
+    OutlivesGoal(OutlivesGoal),
     DomainGoal(DomainGoal),
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EqGoal {
     pub a: Parameter,
     pub b: Parameter,
 }
 
+/// Proves that `a` outlives `b`. Whether it does is left to region
+/// checking: the goal always holds, but (unless `a` and `b` are the same
+/// lifetime) its solution carries the constraint `a: b`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OutlivesGoal {
+    pub a: Lifetime,
+    pub b: Lifetime,
+}
+
 /// Proves that the given projection **normalizes** to the given
 /// type. A projection `T::Foo` normalizes to the type `U` if we can
 /// **match it to an impl** and that impl has a `type Foo = V` where
 /// `U = V`.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Normalize {
     pub projection: ProjectionTy,
     pub ty: Ty,
@@ -611,7 +1364,8 @@ pub struct Normalize {
 /// Proves **equality** between a projection `T::Foo` and a type
 /// `U`. Equality can be proven via normalization, but we can also
 /// prove that `T::Foo = V::Foo` if `T = V` without normalizing.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ProjectionEq {
     pub projection: ProjectionTy,
     pub ty: Ty,
@@ -634,7 +1388,8 @@ pub struct ProjectionEq {
 ///     InScope(Iterator),
 ///     <Vec<T> as Iterator>::Item -> T
 /// ```
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct UnselectedNormalize {
     pub projection: UnselectedProjectionTy,
     pub ty: Ty,
@@ -648,12 +1403,97 @@ pub struct UnselectedNormalize {
 /// (IOW, we use deBruijn indices, where binders are introduced in reverse order
 /// of `self.binders`.)
 #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Binders<T> {
     pub binders: Vec<ParameterKind<()>>,
     pub value: T,
+
+    /// The names of the parameters in the source, if known.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub names: BinderNames,
+}
+
+/// The names that the parameters of a `Binders` had in the source. They
+/// are only used to print bound variables as `T` or `'a` rather than
+/// `^0` in debug output: binders that differ only in their names are
+/// equal, and hash and compare the same.
+#[derive(Clone, Default)]
+pub struct BinderNames {
+    names: Vec<Identifier>,
+}
+
+impl BinderNames {
+    pub fn new(names: Vec<Identifier>) -> Self {
+        BinderNames { names }
+    }
+
+    /// The names of the parameters of a binder that puts the parameters
+    /// of `self` before those of `other`, as when the binders of a where
+    /// clause are moved out into those of its item.
+    pub fn chain(&self, other: &BinderNames) -> BinderNames {
+        BinderNames::new(self.names.iter().chain(&other.names).cloned().collect())
+    }
+
+    /// The names of the first `count` of `len` parameters, and those of
+    /// the others.
+    fn split(self, len: usize, count: usize) -> (BinderNames, BinderNames) {
+        if self.names.len() == len {
+            let mut names = self.names;
+            let rest = names.split_off(count);
+            (BinderNames::new(names), BinderNames::new(rest))
+        } else {
+            (BinderNames::default(), BinderNames::default())
+        }
+    }
+
+    /// The names of `len` parameters, or nothing for each parameter if
+    /// the names are unknown.
+    pub fn get(&self, len: usize) -> Vec<Option<Identifier>> {
+        if self.names.len() == len {
+            self.names.iter().cloned().map(Some).collect()
+        } else {
+            vec![None; len]
+        }
+    }
+}
+
+impl PartialEq for BinderNames {
+    fn eq(&self, _other: &BinderNames) -> bool {
+        true
+    }
+}
+
+impl Eq for BinderNames {}
+
+impl PartialOrd for BinderNames {
+    fn partial_cmp(&self, other: &BinderNames) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BinderNames {
+    fn cmp(&self, _other: &BinderNames) -> std::cmp::Ordering {
+        std::cmp::Ordering::Equal
+    }
+}
+
+impl std::hash::Hash for BinderNames {
+    fn hash<H: std::hash::Hasher>(&self, _state: &mut H) {}
 }
 
 impl<T> Binders<T> {
+    /// Binders whose parameters have no known names.
+    pub fn new(binders: Vec<ParameterKind<()>>, value: T) -> Self {
+        Binders {
+            binders,
+            value,
+            names: BinderNames::default(),
+        }
+    }
+
+    /// Maps the value under the binders. `op` sees the bound variables
+    /// as they are, so it must not move the value under more binders
+    /// without shifting it.
     pub fn map<U, OP>(self, op: OP) -> Binders<U>
     where
         OP: FnOnce(T) -> U,
@@ -662,9 +1502,11 @@ impl<T> Binders<T> {
         Binders {
             binders: self.binders,
             value,
+            names: self.names,
         }
     }
 
+    /// As `map`, but borrowing the value.
     pub fn map_ref<U, OP>(&self, op: OP) -> Binders<U>
     where
         OP: FnOnce(&T) -> U,
@@ -673,6 +1515,7 @@ impl<T> Binders<T> {
         Binders {
             binders: self.binders.clone(),
             value,
+            names: self.names.clone(),
         }
     }
 
@@ -686,19 +1529,61 @@ impl<T> Binders<T> {
         T: Shift,
     {
         // The new variable is at the front and everything afterwards is shifted up by 1
-        let new_var = Ty::BoundVar(0);
+        let new_var = DebruijnIndex::INNERMOST.to_ty();
         let value = op(self.value.shifted_in(1), new_var);
-        Binders {
-            binders: iter::once(ParameterKind::Ty(()))
-                .chain(self.binders.iter().cloned())
-                .collect(),
-            value,
-        }
+        let binders = iter::once(ParameterKind::Ty(()))
+            .chain(self.binders.iter().cloned())
+            .collect();
+        Binders::new(binders, value)
     }
 
     pub fn len(&self) -> usize {
         self.binders.len()
     }
+
+    /// Splits off the first `count` binders into a binder of their own,
+    /// inside the remaining ones: `for<A, B, C> X` becomes
+    /// `for<B, C> for<A> X`. The value is unchanged, as the variables
+    /// still refer to the same parameters: the innermost binder binds
+    /// the lowest indices. `fuse` undoes this.
+    pub fn split(self, count: usize) -> Binders<Binders<T>> {
+        let Binders {
+            mut binders,
+            value,
+            names,
+        } = self;
+        let (inner_names, outer_names) = names.split(binders.len(), count);
+        let outer_binders = binders.split_off(count);
+        Binders {
+            binders: outer_binders,
+            value: Binders {
+                binders,
+                value,
+                names: inner_names,
+            },
+            names: outer_names,
+        }
+    }
+}
+
+impl<T> Binders<Binders<T>> {
+    /// Merges two nested binders into one, whose parameters are those of
+    /// the inner binder followed by those of the outer one:
+    /// `for<B, C> for<A> X` becomes `for<A, B, C> X`. This is how the
+    /// binders of e.g. a where clause are moved out into those of the
+    /// item it belongs to, and the value is again unchanged.
+    pub fn fuse(self) -> Binders<T> {
+        let Binders {
+            binders: outer_binders,
+            value: inner,
+            names: outer_names,
+        } = self;
+        Binders {
+            binders: inner.binders.into_iter().chain(outer_binders).collect(),
+            value: inner.value,
+            names: inner.names.chain(&outer_names),
+        }
+    }
 }
 
 /// Allows iterating over a Binders<Vec<T>>, for instance.
@@ -711,6 +1596,7 @@ impl<V: IntoIterator> IntoIterator for Binders<V> {
         BindersIntoIterator {
             iter: self.value.into_iter(),
             binders: self.binders,
+            names: self.names,
         }
     }
 }
@@ -718,6 +1604,7 @@ impl<V: IntoIterator> IntoIterator for Binders<V> {
 pub struct BindersIntoIterator<V: IntoIterator> {
     iter: <V as IntoIterator>::IntoIter,
     binders: Vec<ParameterKind<()>>,
+    names: BinderNames,
 }
 
 impl<V: IntoIterator> Iterator for BindersIntoIterator<V> {
@@ -726,6 +1613,7 @@ impl<V: IntoIterator> Iterator for BindersIntoIterator<V> {
         self.iter.next().map(|v| Binders {
             binders: self.binders.clone(),
             value: v,
+            names: self.names.clone(),
         })
     }
 }
@@ -733,26 +1621,42 @@ impl<V: IntoIterator> Iterator for BindersIntoIterator<V> {
 /// Represents one clause of the form `consequence :- conditions` where
 /// `conditions = cond_1 && cond_2 && ...` is the conjunction of the individual
 /// conditions.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ProgramClauseImplication {
     pub consequence: DomainGoal,
-    pub conditions: Vec<Goal>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_impls::small_vec"))]
+    pub conditions: Conditions,
 }
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// The conditions of a program clause. As for `Parameters`, only one is
+/// kept inline, so that clauses stay small.
+pub type Conditions = SmallVec<[Goal; 1]>;
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Fold, Zip, Cast)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ProgramClause {
+    #[cast]
     Implies(ProgramClauseImplication),
+    #[cast]
     ForAll(Binders<ProgramClauseImplication>),
 }
 
 impl ProgramClause {
+    pub fn consequence(&self) -> &DomainGoal {
+        match self {
+            ProgramClause::Implies(implication) => &implication.consequence,
+            ProgramClause::ForAll(clause) => &clause.value.consequence,
+        }
+    }
+
     pub fn into_from_env_clause(self) -> ProgramClause {
         match self {
             ProgramClause::Implies(implication) => {
                 if implication.conditions.is_empty() {
                     ProgramClause::Implies(ProgramClauseImplication {
                         consequence: implication.consequence.into_from_env_goal(),
-                        conditions: vec![],
+                        conditions: smallvec![],
                     })
                 } else {
                     ProgramClause::Implies(implication)
@@ -769,6 +1673,7 @@ impl ProgramClause {
 /// first appearance; the kind/universe of the variable is recorded in the
 /// `binders` field.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Canonical<T> {
     pub value: T,
     pub binders: Vec<ParameterKind<UniverseIndex>>,
@@ -781,6 +1686,7 @@ pub struct Canonical<T> {
 ///
 /// To produce one of these values, use the `u_canonicalize` method.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct UCanonical<T> {
     pub canonical: Canonical<T>,
     pub universes: usize,
@@ -799,12 +1705,13 @@ impl UCanonical<InEnvironment<Goal>> {
     /// form `WellFormed(T: Trait)` where `Trait` is any trait. The latter is needed for dealing
     /// with WF requirements and cyclic traits, which generates cycles in the proof tree which must
     /// not be rejected but instead must be treated as a success.
-    pub fn is_coinductive(&self, program: &ProgramEnvironment) -> bool {
+    pub fn is_coinductive(&self, program: &dyn ProgramClauseSet) -> bool {
         self.canonical.value.goal.is_coinductive(program)
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold, Zip)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// A general goal; this is the full range of questions you can pose to Chalk.
 pub enum Goal {
     /// Introduces a binding at depth 0, shifting other bindings up
@@ -812,6 +1719,15 @@ pub enum Goal {
     Quantified(QuantifierKind, Binders<Box<Goal>>),
     Implies(Vec<ProgramClause>, Box<Goal>),
     And(Box<Goal>, Box<Goal>),
+
+    /// The conjunction of any number of goals. Large generated goals
+    /// should prefer this to a deep chain of `And`s.
+    All(Vec<Goal>),
+
+    /// The disjunction of any number of goals: true if any of them is
+    /// (so never true if there are none).
+    Any(Vec<Goal>),
+
     Not(Box<Goal>),
     Leaf(LeafGoal),
 
@@ -829,13 +1745,7 @@ pub enum Goal {
 
 impl Goal {
     pub fn quantify(self, kind: QuantifierKind, binders: Vec<ParameterKind<()>>) -> Goal {
-        Goal::Quantified(
-            kind,
-            Binders {
-                value: Box::new(self),
-                binders,
-            },
-        )
+        Goal::Quantified(kind, Binders::new(binders, Box::new(self)))
     }
 
     /// Takes a goal `G` and turns it into `not { G }`
@@ -847,13 +1757,10 @@ impl Goal {
     pub fn compatible(self) -> Self {
         // compatible { G } desugars into: forall<T> { if (Compatible, DownstreamType(T)) { G } }
         // This activates the compatible modality rules and introduces an anonymous downstream type
+        let goal = Binders::new(Vec::new(), Box::new(self));
         Goal::Quantified(
             QuantifierKind::ForAll,
-            Binders {
-                value: Box::new(self),
-                binders: Vec::new(),
-            }
-            .with_fresh_type_var(|goal, ty| {
+            goal.with_fresh_type_var(|goal, ty| {
                 Box::new(Goal::Implies(
                     vec![
                         DomainGoal::Compatible(()).cast(),
@@ -869,20 +1776,29 @@ impl Goal {
         Goal::Implies(predicates, Box::new(self))
     }
 
-    pub fn is_coinductive(&self, program: &ProgramEnvironment) -> bool {
+    pub fn is_coinductive(&self, program: &dyn ProgramClauseSet) -> bool {
+        self.is_coinductive_with(&|trait_id| program.is_coinductive_trait(trait_id))
+    }
+
+    /// Like `is_coinductive`, but `is_coinductive_trait` decides which
+    /// traits have coinductive semantics.
+    pub fn is_coinductive_with(&self, is_coinductive_trait: &dyn Fn(ItemId) -> bool) -> bool {
         match self {
             Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Holds(wca))) => match wca {
-                WhereClause::Implemented(tr) => program.coinductive_traits.contains(&tr.trait_id),
-                WhereClause::ProjectionEq(..) => false,
+                WhereClause::Implemented(tr) => is_coinductive_trait(tr.trait_id),
+                WhereClause::ProjectionEq(..) | WhereClause::ConstEvaluatable(..) => false,
             },
             Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::WellFormed(WellFormed::Trait(..)))) => true,
-            Goal::Quantified(QuantifierKind::ForAll, goal) => goal.value.is_coinductive(program),
+            Goal::Quantified(QuantifierKind::ForAll, goal) => {
+                goal.value.is_coinductive_with(is_coinductive_trait)
+            }
             _ => false,
         }
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum QuantifierKind {
     ForAll,
     Exists,
@@ -894,13 +1810,18 @@ pub enum QuantifierKind {
 /// lifetime constraints, instead gathering them up to return with our solution
 /// for later checking. This allows for decoupling between type and region
 /// checking in the compiler.
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Fold)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Constraint {
     LifetimeEq(Lifetime, Lifetime),
+
+    /// The first lifetime outlives the second.
+    Outlives(Lifetime, Lifetime),
 }
 
 /// A mapping of inference variables to instantiations thereof.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Substitution {
     /// Map free variable with given index to the value with the same
     /// index. Naturally, the kind of the variable must agree with
@@ -909,7 +1830,10 @@ pub struct Substitution {
     /// This is a map because the substitution is not necessarily
     /// complete. We use a btree map to ensure that the result is in a
     /// deterministic order.
-    pub parameters: Vec<Parameter>,
+    ///
+    /// Substitutions are cloned every time an answer is applied, so the
+    /// parameters are shared rather than copied.
+    pub parameters: Arc<[Parameter]>,
 }
 
 impl Substitution {
@@ -934,26 +1858,150 @@ impl Substitution {
             .iter()
             .zip(0..)
             .all(|(parameter, index)| match parameter {
-                ParameterKind::Ty(Ty::BoundVar(depth)) => index == *depth,
-                ParameterKind::Lifetime(Lifetime::BoundVar(depth)) => index == *depth,
+                ParameterKind::Ty(ty) => ty.bound().map_or(false, |depth| index == depth.depth()),
+                ParameterKind::Lifetime(Lifetime::BoundVar(depth)) => index == depth.depth(),
                 _ => false,
             })
     }
 }
 
+/// Builds the parameters for the binders of an item, one after the
+/// other, so that each one is checked against the kind of its binder.
+/// This is how the parameters that refer to an item's own binders (as
+/// in the self type `Foo<^0, ^1>` of `struct Foo<T, U>`), or that
+/// instantiate them with placeholders or inference variables, are made.
+///
+/// ```ignore
+/// // The parameters of `<Self as Trait<..>>::Item<'a>` in an impl, where
+/// // `'a` is bound and the rest comes from the impl's trait reference.
+/// let parameters = SubstBuilder::new(associated_ty_kinds)
+///     .bound_vars(1)
+///     .extend(trait_ref.parameters)
+///     .into_parameters();
+/// ```
+///
+/// Giving a parameter of the wrong kind, or too many or too few
+/// parameters, is a bug and panics.
+#[derive(Clone, Debug)]
+pub struct SubstBuilder {
+    binders: Vec<ParameterKind<()>>,
+    parameters: Vec<Parameter>,
+}
+
+impl SubstBuilder {
+    pub fn new(binders: impl IntoIterator<Item = ParameterKind<()>>) -> Self {
+        SubstBuilder {
+            binders: binders.into_iter().collect(),
+            parameters: vec![],
+        }
+    }
+
+    /// The number of parameters that are still missing.
+    pub fn remaining(&self) -> usize {
+        self.binders.len() - self.parameters.len()
+    }
+
+    /// Adds `parameter` for the next binder.
+    pub fn push(mut self, parameter: Parameter) -> Self {
+        let index = self.parameters.len();
+        let binder = match self.binders.get(index) {
+            Some(binder) => binder,
+            None => panic!("too many parameters for {} binders", self.binders.len()),
+        };
+        let kind = match parameter {
+            ParameterKind::Ty(_) => ParameterKind::Ty(()),
+            ParameterKind::Lifetime(_) => ParameterKind::Lifetime(()),
+        };
+        assert_eq!(*binder, kind, "parameter {} has the wrong kind", index);
+        self.parameters.push(parameter);
+        self
+    }
+
+    pub fn extend(self, parameters: impl IntoIterator<Item = Parameter>) -> Self {
+        parameters
+            .into_iter()
+            .fold(self, |builder, parameter| builder.push(parameter))
+    }
+
+    /// Adds a parameter for each of the remaining binders: `op` is given
+    /// the kind of the binder and its index.
+    pub fn fill_with(mut self, mut op: impl FnMut(ParameterKind<()>, usize) -> Parameter) -> Self {
+        while self.remaining() > 0 {
+            let index = self.parameters.len();
+            let parameter = op(self.binders[index], index);
+            self = self.push(parameter);
+        }
+        self
+    }
+
+    /// Adds, for each of the next `count` binders, the bound variable
+    /// with the index of that binder.
+    pub fn bound_vars(self, count: usize) -> Self {
+        let start = self.parameters.len();
+        let binders = self.binders[start..start + count].to_vec();
+        binders
+            .into_iter()
+            .zip(start..)
+            .fold(self, |builder, (binder, index)| builder.push(binder.to_bound_var(index)))
+    }
+
+    /// As `bound_vars`, for all the remaining binders.
+    pub fn fill_bound_vars(self) -> Self {
+        self.fill_with(|binder, index| binder.to_bound_var(index))
+    }
+
+    /// Adds, for each of the remaining binders, the placeholder of
+    /// universe `ui` with the index of that binder.
+    pub fn fill_placeholders(self, ui: UniverseIndex) -> Self {
+        self.fill_with(|binder, idx| {
+            let placeholder = ui.placeholder(idx);
+            match binder {
+                ParameterKind::Ty(()) => ParameterKind::Ty(placeholder.to_ty()),
+                ParameterKind::Lifetime(()) => ParameterKind::Lifetime(placeholder.to_lifetime()),
+            }
+        })
+    }
+
+    pub fn into_parameters(self) -> Vec<Parameter> {
+        assert_eq!(self.remaining(), 0, "missing parameters");
+        self.parameters
+    }
+
+    pub fn build(self) -> Substitution {
+        Substitution {
+            parameters: self.into_parameters().into(),
+        }
+    }
+}
+
+impl ParameterKind<()> {
+    /// The bound variable of this kind at `index`.
+    pub fn to_bound_var(self, index: usize) -> Parameter {
+        let depth = DebruijnIndex::new(index);
+        match self {
+            ParameterKind::Ty(()) => ParameterKind::Ty(depth.to_ty()),
+            ParameterKind::Lifetime(()) => ParameterKind::Lifetime(Lifetime::BoundVar(depth)),
+        }
+    }
+}
+
 impl<'a> DefaultTypeFolder for &'a Substitution {}
 
 impl<'a> DefaultInferenceFolder for &'a Substitution {}
 
 impl<'a> FreeVarFolder for &'a Substitution {
-    fn fold_free_var_ty(&mut self, depth: usize, binders: usize) -> Fallible<Ty> {
-        let ty = &self.parameters[depth];
+    fn fold_free_var_ty(&mut self, depth: DebruijnIndex, binders: usize) -> Fallible<Ty> {
+        let ty = &self.parameters[depth.depth()];
         let ty = ty.assert_ty_ref();
         Ok(ty.shifted_in(binders))
     }
 
-    fn fold_free_var_lifetime(&mut self, depth: usize, binders: usize) -> Fallible<Lifetime> {
-        let l = &self.parameters[depth];
+    fn fold_free_var_lifetime(
+        &mut self,
+        depth: DebruijnIndex,
+        binders: usize,
+    ) -> Fallible<Lifetime> {
+        let l = &self.parameters[depth.depth()];
         let l = l.assert_lifetime_ref();
         Ok(l.shifted_in(binders))
     }
@@ -961,8 +2009,11 @@ impl<'a> FreeVarFolder for &'a Substitution {
 
 impl<'a> DefaultPlaceholderFolder for &'a Substitution {}
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Fold)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ConstrainedSubst {
+    /// NB: The `is_trivial` routine relies on the fact that `subst` is
+    /// folded first.
     pub subst: Substitution,
     pub constraints: Vec<InEnvironment<Constraint>>,
 }
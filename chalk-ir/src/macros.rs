@@ -3,32 +3,35 @@
 #[macro_export]
 macro_rules! ty {
     (apply $n:tt $($arg:tt)*) => {
-        $crate::Ty::Apply(ApplicationTy {
+        $crate::TyData::Apply(ApplicationTy {
             name: ty_name!($n),
             parameters: vec![$(arg!($arg)),*],
         })
+        .intern()
     };
 
     (for_all $n:tt $t:tt) => {
-        $crate::Ty::ForAll(Box::new(QuantifiedTy {
-            num_binders: $n,
+        $crate::TyData::ForAll(Box::new(QuantifiedTy {
+            binders: vec![$crate::ParameterKind::Lifetime(()); $n],
             ty: ty!($t),
         }))
+        .intern()
     };
 
     (projection (item $n:tt) $($arg:tt)*) => {
-        $crate::Ty::Projection(ProjectionTy {
+        $crate::TyData::Projection(ProjectionTy {
             associated_ty_id: ItemId { index: $n },
             parameters: vec![$(arg!($arg)),*],
         })
+        .intern()
     };
 
     (infer $b:expr) => {
-        $crate::Ty::InferenceVar($crate::InferenceVar::from($b))
+        $crate::TyData::InferenceVar($crate::InferenceVar::from($b)).intern()
     };
 
     (bound $b:expr) => {
-        $crate::Ty::BoundVar($b)
+        $crate::DebruijnIndex::new($b).to_ty()
     };
 
     (expr $b:expr) => {
@@ -58,11 +61,11 @@ macro_rules! lifetime {
     };
 
     (bound $b:expr) => {
-        $crate::Lifetime::BoundVar($b)
+        $crate::Lifetime::BoundVar($crate::DebruijnIndex::new($b))
     };
 
     (placeholder $b:expr) => {
-        $crate::Lifetime::Placeholder(PlaceholderIndex { ui: UniverseIndex { counter: $b }, idx: 0})
+        $crate::Lifetime::Placeholder($crate::UniverseIndex::new($b).placeholder(0))
     };
 
     (expr $b:expr) => {
@@ -80,9 +83,6 @@ macro_rules! ty_name {
         $crate::TypeName::ItemId(ItemId { index: $n })
     };
     ((placeholder $n:expr)) => {
-        $crate::TypeName::Placeholder(PlaceholderIndex {
-            ui: UniverseIndex { counter: $n },
-            idx: 0,
-        })
+        $crate::TypeName::Placeholder($crate::UniverseIndex::new($n).placeholder(0))
     };
 }
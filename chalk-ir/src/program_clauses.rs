@@ -0,0 +1,172 @@
+use crate::could_match::CouldMatch;
+use crate::fingerprint::Fingerprint;
+use crate::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::FromIterator;
+use std::sync::RwLock;
+
+mod test;
+
+/// The program clauses of a `ProgramEnvironment`, stored by the
+/// `GoalKey`s of their consequences. Finding the clauses that could prove
+/// a goal only looks at the clauses with the same key, rather than at
+/// every clause of the program.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct ProgramClauses {
+    keyed: BTreeMap<GoalKey, Vec<ProgramClause>>,
+
+    /// The clauses whose consequences have no key. Only goals without a
+    /// key could be proven by them.
+    unkeyed: Vec<ProgramClause>,
+
+    memo: ClauseMemo,
+}
+
+impl ProgramClauses {
+    pub fn new() -> Self {
+        ProgramClauses::default()
+    }
+
+    pub fn push(&mut self, clause: ProgramClause) {
+        self.memo.clear();
+        match clause.consequence().key() {
+            Some(key) => self.keyed.entry(key).or_default().push(clause),
+            None => self.unkeyed.push(clause),
+        }
+    }
+
+    /// The number of clauses.
+    pub fn len(&self) -> usize {
+        self.keyed.values().map(|clauses| clauses.len()).sum::<usize>() + self.unkeyed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// All the clauses, grouped by key. The clauses with the same key are
+    /// in the order they were added in.
+    pub fn iter(&self) -> impl Iterator<Item = &ProgramClause> + '_ {
+        self.keyed.values().flatten().chain(&self.unkeyed)
+    }
+
+    /// The clauses whose consequence could match `goal`, in the order
+    /// they were added in.
+    pub fn clauses_for_goal<'a>(
+        &'a self,
+        goal: &'a DomainGoal,
+    ) -> impl Iterator<Item = &'a ProgramClause> + 'a {
+        let candidates = match goal.key() {
+            Some(key) => self.keyed.get(&key).map(|clauses| &clauses[..]).unwrap_or(&[]),
+            None => &self.unkeyed[..],
+        };
+        candidates
+            .iter()
+            .filter(move |&clause| clause.could_match(goal))
+    }
+
+    /// A fingerprint of the clauses, whatever order they were added in.
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint::of_unordered(self.iter())
+    }
+
+    /// The clauses that `generate` gives for `goal`, which are only
+    /// generated the first time they are asked for: the solver asks
+    /// for the clauses of the same goals over and over, within a query
+    /// and across the queries against the same program. `generate` must
+    /// only depend on `goal` and on the clauses of `self`. The memo is
+    /// emptied when clauses are added.
+    pub fn memoized(
+        &self,
+        goal: &DomainGoal,
+        generate: impl FnOnce(&DomainGoal) -> Vec<ProgramClause>,
+    ) -> Arc<[ProgramClause]> {
+        if let Some(clauses) = self.memo.clauses.read().unwrap().get(goal) {
+            return clauses.clone();
+        }
+
+        let clauses: Arc<[ProgramClause]> = generate(goal).into();
+        let mut memo = self.memo.clauses.write().unwrap();
+        if memo.len() >= ClauseMemo::MAX_GOALS {
+            memo.clear();
+        }
+        memo.insert(goal.clone(), clauses.clone());
+        clauses
+    }
+}
+
+impl fmt::Debug for ProgramClauses {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ProgramClauses")
+            .field("keyed", &self.keyed)
+            .field("unkeyed", &self.unkeyed)
+            .finish()
+    }
+}
+
+/// The clauses generated for each goal so far (see
+/// `ProgramClauses::memoized`). Two stores with the same clauses are
+/// equal whatever their memos hold.
+#[derive(Default)]
+struct ClauseMemo {
+    clauses: RwLock<HashMap<DomainGoal, Arc<[ProgramClause]>>>,
+}
+
+impl ClauseMemo {
+    /// The number of goals to remember the clauses of. Beyond that, the
+    /// memo starts over, so that a long-lived environment does not keep
+    /// the clauses of every goal it was ever asked about.
+    const MAX_GOALS: usize = 1 << 12;
+
+    fn clear(&mut self) {
+        self.clauses.get_mut().unwrap().clear();
+    }
+}
+
+impl Clone for ClauseMemo {
+    fn clone(&self) -> Self {
+        ClauseMemo {
+            clauses: RwLock::new(self.clauses.read().unwrap().clone()),
+        }
+    }
+}
+
+impl PartialEq for ClauseMemo {
+    fn eq(&self, _: &ClauseMemo) -> bool {
+        true
+    }
+}
+
+impl Eq for ClauseMemo {}
+
+impl FromIterator<ProgramClause> for ProgramClauses {
+    fn from_iter<I>(clauses: I) -> Self
+    where
+        I: IntoIterator<Item = ProgramClause>,
+    {
+        let mut program_clauses = ProgramClauses::new();
+        program_clauses.extend(clauses);
+        program_clauses
+    }
+}
+
+impl Extend<ProgramClause> for ProgramClauses {
+    fn extend<I>(&mut self, clauses: I)
+    where
+        I: IntoIterator<Item = ProgramClause>,
+    {
+        for clause in clauses {
+            self.push(clause);
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a ProgramClauses {
+    type Item = &'a ProgramClause;
+    type IntoIter = Box<dyn Iterator<Item = &'a ProgramClause> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
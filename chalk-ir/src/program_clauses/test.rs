@@ -0,0 +1,96 @@
+#![cfg(test)]
+
+use crate::cast::Cast;
+use crate::*;
+
+fn implemented(trait_id: usize, ty: Ty) -> DomainGoal {
+    TraitRef {
+        trait_id: ItemId { index: trait_id },
+        parameters: smallvec![ty.cast()],
+    }
+    .cast()
+}
+
+fn fact(goal: DomainGoal) -> ProgramClause {
+    ProgramClause::Implies(ProgramClauseImplication {
+        consequence: goal,
+        conditions: smallvec![],
+    })
+}
+
+fn clauses() -> ProgramClauses {
+    vec![
+        fact(implemented(10, ty!(apply (item 0)))),
+        fact(DomainGoal::WellFormed(WellFormed::Ty(ty!(apply (item 0))))),
+        fact(implemented(11, ty!(apply (item 0)))),
+        fact(implemented(10, ty!(apply (item 1)))),
+    ]
+    .into_iter()
+    .collect()
+}
+
+#[test]
+fn clauses_for_goal() {
+    let clauses = clauses();
+    assert_eq!(clauses.len(), 4);
+
+    let goal = implemented(10, ty!(infer 0));
+    assert_eq!(
+        clauses.clauses_for_goal(&goal).cloned().collect::<Vec<_>>(),
+        vec![
+            fact(implemented(10, ty!(apply (item 0)))),
+            fact(implemented(10, ty!(apply (item 1)))),
+        ]
+    );
+
+    let goal = implemented(11, ty!(apply (item 1)));
+    assert_eq!(clauses.clauses_for_goal(&goal).count(), 0);
+    let goal = implemented(12, ty!(apply (item 0)));
+    assert_eq!(clauses.clauses_for_goal(&goal).count(), 0);
+
+    let goal = DomainGoal::WellFormed(WellFormed::Ty(ty!(infer 0)));
+    assert_eq!(clauses.clauses_for_goal(&goal).count(), 1);
+}
+
+#[test]
+fn iter_keeps_every_clause() {
+    let clauses = clauses();
+    let all: BTreeSet<_> = clauses.iter().cloned().collect();
+    assert_eq!(all.len(), 4);
+    assert!(all.contains(&fact(implemented(11, ty!(apply (item 0))))));
+}
+
+#[test]
+fn fingerprint_ignores_order() {
+    let mut reversed: Vec<_> = clauses().iter().cloned().collect();
+    reversed.reverse();
+    let reversed: ProgramClauses = reversed.into_iter().collect();
+    assert_ne!(reversed, clauses());
+    assert_eq!(reversed.fingerprint(), clauses().fingerprint());
+
+    let fewer: ProgramClauses = clauses().iter().skip(1).cloned().collect();
+    assert_ne!(fewer.fingerprint(), clauses().fingerprint());
+}
+
+#[test]
+fn memoized_until_clauses_are_added() {
+    let mut clauses = clauses();
+    let goal = implemented(10, ty!(infer 0));
+    let generate = |clauses: &ProgramClauses, generated: &mut usize| {
+        clauses
+            .memoized(&goal, |goal| {
+                *generated += 1;
+                clauses.clauses_for_goal(goal).cloned().collect()
+            })
+            .len()
+    };
+
+    let mut generated = 0;
+    assert_eq!(generate(&clauses, &mut generated), 2);
+    assert_eq!(generate(&clauses, &mut generated), 2);
+    assert_eq!(generated, 1);
+
+    clauses.push(fact(implemented(10, ty!(apply (item 2)))));
+    assert_eq!(generate(&clauses, &mut generated), 3);
+    assert_eq!(generated, 2);
+}
@@ -0,0 +1,131 @@
+//! Helpers for types that do not implement the serde traits
+//! themselves, or not as derived; used through `#[serde(with = "...")]`
+//! and `#[serde(from = "...", into = "...")]`. The helpers for
+//! identifiers are public so that IRs built on top of this one (like
+//! the Rust IR) can serialize their own identifiers the same way.
+
+use crate::{Environment, ProgramClause};
+use serde::{Deserialize, Serialize};
+
+/// Identifiers are interned, so the index they carry is meaningless
+/// in another process; we write out the string instead and intern it
+/// again on the way back in.
+pub mod identifier {
+    use crate::Identifier;
+    use chalk_macros::intern::intern;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(id: &Identifier, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&id.to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Identifier, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        Ok(intern(&name))
+    }
+}
+
+/// Named parameter kinds, as in `identifier`.
+pub mod parameter_kinds {
+    use crate::{Identifier, ParameterKind};
+    use chalk_macros::intern::intern;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        kinds: &[ParameterKind<Identifier>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(kinds.iter().map(|kind| kind.as_ref().map(|id| id.to_string())))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<ParameterKind<Identifier>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let kinds = Vec::<ParameterKind<String>>::deserialize(deserializer)?;
+        Ok(kinds
+            .into_iter()
+            .map(|kind| kind.map(|name| intern(&name)))
+            .collect())
+    }
+}
+
+/// Maps keyed by identifiers, as in `identifier`. Any map type that can
+/// be iterated over and collected into works.
+pub mod identifier_keys {
+    use crate::Identifier;
+    use chalk_macros::intern::intern;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+    use std::iter::FromIterator;
+
+    pub fn serialize<'a, M, V, S>(map: &'a M, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        &'a M: IntoIterator<Item = (&'a Identifier, &'a V)>,
+        V: Serialize + 'a,
+        S: Serializer,
+    {
+        serializer.collect_map(map.into_iter().map(|(id, value)| (id.to_string(), value)))
+    }
+
+    pub fn deserialize<'de, M, V, D>(deserializer: D) -> Result<M, D::Error>
+    where
+        M: FromIterator<(Identifier, V)>,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let map = BTreeMap::<String, V>::deserialize(deserializer)?;
+        Ok(map
+            .into_iter()
+            .map(|(name, value)| (intern(&name), value))
+            .collect())
+    }
+}
+
+/// Small vectors (see `Parameters`), which are written out like vectors.
+pub mod small_vec {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use smallvec::{Array, SmallVec};
+
+    pub fn serialize<A, S>(vec: &SmallVec<A>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        A: Array,
+        A::Item: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(vec.iter())
+    }
+
+    pub fn deserialize<'de, A, D>(deserializer: D) -> Result<SmallVec<A>, D::Error>
+    where
+        A: Array,
+        A::Item: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Vec::deserialize(deserializer).map(SmallVec::from_vec)
+    }
+}
+
+/// `Environment`s are written out without their clause index, which is
+/// rebuilt when they are read back in.
+#[derive(Serialize, Deserialize)]
+crate struct EnvironmentClauses {
+    clauses: Vec<ProgramClause>,
+}
+
+impl From<EnvironmentClauses> for Environment {
+    fn from(environment: EnvironmentClauses) -> Self {
+        Environment::from_clauses(environment.clauses)
+    }
+}
+
+impl From<Environment> for EnvironmentClauses {
+    fn from(environment: Environment) -> Self {
+        EnvironmentClauses {
+            clauses: environment.clauses,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+#![cfg(test)]
+
+use crate::fold::{Shift, Subst};
+use crate::*;
+use chalk_macros::intern::intern;
+
+fn kinds(count: usize) -> Vec<ParameterKind<()>> {
+    (0..count).map(|_| ParameterKind::Ty(())).collect()
+}
+
+/// `for<T0, T1> Foo<^0, ^1, ^2>`, where `^2` is free.
+fn foo() -> Binders<Ty> {
+    Binders::new(kinds(2), ty!(apply (item 0) (bound 0) (bound 1) (bound 2)))
+}
+
+#[test]
+fn fuse_instantiates_inner_binders_first() {
+    let a = arg!((apply (item 1)));
+    let b = arg!((apply (item 2)));
+    let nested = Binders::new(
+        kinds(1),
+        Binders::new(kinds(1), ty!(apply (item 0) (bound 0) (bound 1) (bound 2))),
+    );
+
+    let inner = Subst::apply(&[b.clone()], &nested.value);
+    let stepwise = Subst::apply(&[a.clone()], &inner.value);
+    let fused = nested.fuse();
+    assert_eq!(fused.binders.len(), 2);
+    assert_eq!(Subst::apply(&[a, b], &fused.value), stepwise);
+    assert_eq!(
+        stepwise,
+        ty!(apply (item 0) (apply (item 1)) (apply (item 2)) (bound 0))
+    );
+}
+
+#[test]
+fn split_then_fuse() {
+    let split = foo().split(1);
+    assert_eq!(split.binders.len(), 1);
+    assert_eq!(split.value.binders.len(), 1);
+    assert_eq!(split.fuse(), foo());
+}
+
+#[test]
+fn split_keeps_names() {
+    let (t, u) = (intern("T"), intern("U"));
+    let foo = Binders {
+        names: BinderNames::new(vec![t, u]),
+        ..foo()
+    };
+    let split = foo.split(1);
+    assert_eq!(split.value.names.get(1), vec![Some(t)]);
+    assert_eq!(split.names.get(1), vec![Some(u)]);
+    assert_eq!(split.fuse().names.get(2), vec![Some(t), Some(u)]);
+}
+
+#[test]
+fn shift_in_and_out() {
+    let ty = ty!(apply (item 0) (bound 0) (bound 1));
+    let shifted = ty.shifted_in(2);
+    assert_eq!(shifted, ty!(apply (item 0) (bound 2) (bound 3)));
+    assert_eq!(shifted.shifted_out(2), Ok(ty.clone()));
+    assert_eq!(ty.shifted_out(1), Err(NoSolution));
+}
+
+#[test]
+fn shift_skips_vars_bound_within() {
+    // `^0` and `^1` are bound by the binders of `foo`, `^2` is free.
+    assert_eq!(
+        foo().shifted_in(1).value,
+        ty!(apply (item 0) (bound 0) (bound 1) (bound 3))
+    );
+    assert_eq!(foo().shifted_in(1).shifted_out(1), Ok(foo()));
+}
+
+#[test]
+fn free_vars() {
+    assert!(foo().has_free_vars());
+    assert!(!Binders::new(kinds(3), foo().value).has_free_vars());
+    assert!(!ty!(apply (item 0) (apply (item 1))).has_free_vars());
+}
+
+#[test]
+fn shifted_under_binders() {
+    let ty = ty!(apply (item 0) (bound 0));
+    let binders = Binders::shifted_under(kinds(2), &ty);
+    assert_eq!(binders.value, ty!(apply (item 0) (bound 2)));
+    assert_eq!(binders.shifted_out_of(), Some(ty));
+    assert_eq!(foo().shifted_out_of(), None);
+}
+
+#[test]
+fn universes_name_their_ancestors_placeholders() {
+    let u1 = UniverseIndex::ROOT.next();
+    let u3 = u1.nth_next(2);
+    assert_eq!(u3.counter(), 3);
+    assert!(u3.can_see(u1) && !u1.can_see(u3));
+
+    let placeholder = u1.placeholder(1);
+    assert_eq!((placeholder.universe(), placeholder.index()), (u1, 1));
+    assert!(u1.can_name(placeholder) && u3.can_name(placeholder));
+    assert!(!UniverseIndex::ROOT.can_name(placeholder));
+
+    let moved = placeholder.in_universe(u3);
+    assert_eq!((moved.universe(), moved.index()), (u3, 1));
+    assert!(!u1.can_name(moved));
+}
+
+#[test]
+fn spawned_threads_share_the_interner() {
+    use chalk_macros::intern::{with_interner, Interner};
+    use std::sync::Arc;
+
+    let interner = Arc::new(Interner::new());
+    with_interner(&interner, || {
+        intern("Bar");
+        let foo = intern("Foo");
+        let worker = tls::spawn(move || (foo.to_string(), intern("Foo") == foo));
+        assert_eq!(worker.join().unwrap(), ("Foo".to_string(), true));
+    });
+}
+
+#[test]
+fn next_disambiguator_stays_at_the_same_path() {
+    let id = ItemId::from_path("impl Foo for Bar", 0);
+    assert_eq!(id.next_disambiguator(), Some(ItemId::from_path("impl Foo for Bar", 1)));
+
+    let last = ItemId::from_path("impl Foo for Bar", (1 << 16) - 1);
+    assert_eq!(last.next_disambiguator(), None);
+}
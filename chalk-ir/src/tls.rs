@@ -1,13 +1,25 @@
-use crate::{ItemId, ProjectionTy};
+use crate::{Identifier, ItemId, ProjectionTy, UniverseIndex};
+use chalk_macros::intern;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread;
 
 thread_local! {
-    static PROGRAM: RefCell<Option<Arc<dyn DebugContext>>> = RefCell::new(None)
+    static PROGRAM: RefCell<Option<Arc<dyn DebugContext>>> = RefCell::new(None);
+
+    static FORALL_NAMES: RefCell<BTreeMap<UniverseIndex, ForallName>> =
+        RefCell::new(BTreeMap::new());
+
+    /// The names of the parameters of the binders being printed, the
+    /// innermost parameter (depth 0) last.
+    static BOUND_NAMES: RefCell<Vec<Option<Identifier>>> = RefCell::new(vec![])
 }
 
-pub trait DebugContext {
+pub trait DebugContext: Send + Sync {
     fn debug_item_id(&self, item_id: ItemId, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error>;
 
     fn debug_projection(
@@ -36,3 +48,144 @@ where
         r
     })
 }
+
+/// Spawns a thread that runs `op` with the current program and the
+/// current interner of this thread installed, so that identifiers and
+/// items can be displayed (and identifiers compared) on it as usual.
+pub fn spawn<OP, R>(op: OP) -> thread::JoinHandle<R>
+where
+    OP: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let program = PROGRAM.with(|prog_cell| prog_cell.borrow().clone());
+    let interner = intern::current_interner();
+    thread::spawn(move || {
+        intern::with_interner(&interner, || {
+            PROGRAM.with(|prog_cell| *prog_cell.borrow_mut() = program);
+            op()
+        })
+    })
+}
+
+/// Applies `op` to every item, on at most `num_threads` threads started
+/// with `spawn`, and returns the results in the order of `items`. A panic
+/// in `op` is resumed on this thread.
+///
+/// To keep the debug output in order, everything runs on this thread
+/// when `CHALK_DEBUG` is set.
+pub fn map_in_parallel<T, R, OP>(items: Vec<T>, num_threads: usize, op: OP) -> Vec<R>
+where
+    T: Send + Sync + 'static,
+    R: Send + 'static,
+    OP: Fn(&T) -> R + Send + Sync + 'static,
+{
+    let num_threads = num_threads.min(items.len());
+    if num_threads <= 1 || *chalk_macros::INFO_ENABLED {
+        return items.iter().map(op).collect();
+    }
+
+    // Each thread takes the next item that no thread has taken yet, so
+    // that a thread that gets the quick items does not sit idle.
+    let items = Arc::new(items);
+    let op = Arc::new(op);
+    let next = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..num_threads)
+        .map(|_| {
+            let items = items.clone();
+            let op = op.clone();
+            let next = next.clone();
+            spawn(move || {
+                let mut results = vec![];
+                loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    if index >= items.len() {
+                        break;
+                    }
+                    results.push((index, op(&items[index])));
+                }
+                results
+            })
+        })
+        .collect();
+
+    let mut results: Vec<(usize, R)> = workers
+        .into_iter()
+        .flat_map(|worker| {
+            worker
+                .join()
+                .unwrap_or_else(|payload| panic::resume_unwind(payload))
+        })
+        .collect();
+    results.sort_by_key(|&(index, _)| index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// The `forall<>` quantifier that a universe was created for. While
+/// one is installed with `with_forall_names`, the placeholders of that
+/// universe are printed as e.g. `!T(from forall<T, U> at depth 1)`
+/// rather than `!1_0`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForallName {
+    /// The names of the binders, in order.
+    pub binders: Vec<Identifier>,
+
+    /// Which quantifier this is, counting the enclosing `forall<>`
+    /// quantifiers from 1 (outermost).
+    pub depth: usize,
+}
+
+pub fn with_forall_name<R>(
+    universe: UniverseIndex,
+    op: impl FnOnce(Option<&ForallName>) -> R,
+) -> R {
+    FORALL_NAMES.with(|names_cell| {
+        let names = names_cell.borrow();
+        op(names.get(&universe))
+    })
+}
+
+pub fn with_forall_names<OP, R>(names: BTreeMap<UniverseIndex, ForallName>, op: OP) -> R
+where
+    OP: FnOnce() -> R,
+{
+    FORALL_NAMES.with(|names_cell| {
+        let old_names = names_cell.replace(names);
+        let r = op();
+        *names_cell.borrow_mut() = old_names;
+        r
+    })
+}
+
+/// Runs `op` with the parameters of a binder in scope for debug output:
+/// while it runs, a bound variable that refers to the `i`th parameter is
+/// printed as `names[i]`, if it is `Some`, rather than as an index.
+pub fn with_bound_names<OP, R>(names: &[Option<Identifier>], op: OP) -> R
+where
+    OP: FnOnce() -> R,
+{
+    let len = BOUND_NAMES.with(|names_cell| {
+        let mut bound_names = names_cell.borrow_mut();
+        let len = bound_names.len();
+        bound_names.extend(names.iter().rev().cloned());
+        len
+    });
+    let r = op();
+    BOUND_NAMES.with(|names_cell| names_cell.borrow_mut().truncate(len));
+    r
+}
+
+/// The name of the bound variable at `depth` (see `with_bound_names`),
+/// unless it is shadowed by a parameter of the same name in an inner
+/// binder.
+pub fn bound_name(depth: usize) -> Option<Identifier> {
+    BOUND_NAMES.with(|names_cell| {
+        let names = names_cell.borrow();
+        let index = names.len().checked_sub(depth + 1)?;
+        let name = names[index]?;
+        if names[index + 1..].contains(&Some(name)) {
+            None
+        } else {
+            Some(name)
+        }
+    })
+}
@@ -0,0 +1,132 @@
+//! The arena that types are interned in.
+//!
+//! Every distinct `TyData` is stored once, in a node that lives for the
+//! rest of the process, and a `Ty` is a pointer to its node. Types are
+//! built far more often than new types are seen -- folding and
+//! unification rebuild the same types over and over -- so interning
+//! mostly finds the node that is already there, and copying, comparing
+//! and hashing types never walks them. Like the process-wide identifier
+//! interner, the arena is never emptied.
+//!
+//! The nodes are kept by hash, in shards that each have their own lock,
+//! so that threads interning types at the same time seldom wait on each
+//! other. A node also keeps its hash, which is what hashing its `Ty`
+//! feeds to the hasher.
+
+use crate::{Ty, TyData};
+use chalk_macros::intern;
+use rustc_hash::{FxHashMap, FxHasher};
+use smallvec::SmallVec;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::ptr;
+use std::sync::RwLock;
+
+mod test;
+
+crate struct TyNode {
+    crate data: TyData,
+    hash: u64,
+}
+
+/// The nodes of a shard, by hash. Types seldom share a hash, so one
+/// node per hash is kept inline.
+type Shard = FxHashMap<u64, SmallVec<[&'static TyNode; 1]>>;
+
+const SHARDS: usize = 16;
+
+lazy_static! {
+    static ref ARENA: Vec<RwLock<Shard>> = (0..SHARDS).map(|_| RwLock::default()).collect();
+}
+
+crate fn intern(data: TyData) -> Ty {
+    // The hash is kept, so it must not depend on whether the type is
+    // interned while fingerprinting.
+    let hash = intern::hash_by_index(|| {
+        let mut hasher = FxHasher::default();
+        data.hash(&mut hasher);
+        hasher.finish()
+    });
+    let shard = &ARENA[(hash >> 32) as usize % SHARDS];
+    if let Some(node) = find(&shard.read().unwrap(), hash, &data) {
+        return Ty { node };
+    }
+
+    // Another thread may have interned the type since we looked.
+    let mut shard = shard.write().unwrap();
+    if let Some(node) = find(&shard, hash, &data) {
+        return Ty { node };
+    }
+    let node: &'static TyNode = Box::leak(Box::new(TyNode { data, hash }));
+    shard.entry(hash).or_default().push(node);
+    Ty { node }
+}
+
+fn find(shard: &Shard, hash: u64, data: &TyData) -> Option<&'static TyNode> {
+    let nodes = shard.get(&hash)?;
+    nodes.iter().cloned().find(|node| node.data == *data)
+}
+
+/// The number of types interned so far.
+pub fn len() -> usize {
+    ARENA
+        .iter()
+        .map(|shard| shard.read().unwrap().values().map(SmallVec::len).sum::<usize>())
+        .sum()
+}
+
+impl PartialEq for Ty {
+    fn eq(&self, other: &Ty) -> bool {
+        ptr::eq(self.node, other.node)
+    }
+}
+
+impl Eq for Ty {}
+
+impl Hash for Ty {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Fingerprints must not depend on the interner, which the kept
+        // hash does.
+        if intern::hashing_by_string() {
+            self.data().hash(state)
+        } else {
+            state.write_u64(self.node.hash)
+        }
+    }
+}
+
+impl PartialOrd for Ty {
+    fn partial_cmp(&self, other: &Ty) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ty {
+    fn cmp(&self, other: &Ty) -> Ordering {
+        if self == other {
+            Ordering::Equal
+        } else {
+            self.data().cmp(other.data())
+        }
+    }
+}
+
+/// Types are written out as their data and interned again when they are
+/// read back in.
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use crate::{Ty, TyData};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    impl Serialize for Ty {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            self.data().serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Ty {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Ty, D::Error> {
+            TyData::deserialize(deserializer).map(TyData::intern)
+        }
+    }
+}
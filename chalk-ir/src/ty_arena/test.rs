@@ -0,0 +1,44 @@
+#![cfg(test)]
+
+use super::*;
+use crate::fingerprint::Fingerprint;
+use crate::*;
+use std::thread;
+
+#[test]
+fn intern_same_type() {
+    let foo = ty!(apply (item 0) (apply (item 1)) (bound 0));
+    assert!(ptr::eq(foo.node, ty!(apply (item 0) (apply (item 1)) (bound 0)).node));
+    assert_ne!(foo, ty!(apply (item 0) (apply (item 1)) (bound 1)));
+    assert_eq!(foo.data().clone().intern(), foo);
+}
+
+#[test]
+fn intern_on_many_threads() {
+    let interned: Vec<_> = (0..4)
+        .map(|_| thread::spawn(|| ty!(apply (item 2) (infer 7) (apply (item 3)))))
+        .collect();
+    for ty in interned {
+        assert_eq!(ty.join().unwrap(), ty!(apply (item 2) (infer 7) (apply (item 3))));
+    }
+}
+
+#[test]
+fn intern_while_fingerprinting() {
+    // The kept hash does not depend on whether strings are hashed by
+    // string, so the type is found again.
+    let foo = ty!(apply (item 4) (apply (item 5)));
+    let interned = intern::hash_by_string(|| ty!(apply (item 4) (apply (item 5))));
+    assert_eq!(interned, foo);
+    assert_eq!(Fingerprint::of(&foo), Fingerprint::of(foo.data()));
+}
+
+#[test]
+fn ordered_by_data() {
+    // Interned the other way around from how they are ordered.
+    let later = ty!(apply (item 7) (bound 1));
+    let earlier = ty!(apply (item 6) (bound 1));
+    assert!(earlier < later);
+    assert_eq!(earlier.cmp(&later), earlier.data().cmp(later.data()));
+    assert!(ty!(apply (item 7) (bound 0)) < later);
+}
@@ -0,0 +1,279 @@
+//! Traits for walking over the IR without rebuilding it. This is the
+//! read-only counterpart to `fold`: analyses that only need to look at
+//! a value (which universes or items it mentions, whether it contains
+//! inference variables, ...) visit it instead of folding it into a copy
+//! that is then thrown away.
+
+use crate::*;
+use std::sync::Arc;
+
+/// Invoked on the leaves of the values being visited. Every method does
+/// nothing by default, so a visitor only implements those it cares
+/// about. As with folding, `binders` is the number of binders in scope
+/// at the leaf.
+pub trait Visitor {
+    /// Invoked for `TyData::BoundVar` instances that are not bound within
+    /// the value being visited; `depth` has been adjusted to account for
+    /// the binders in scope (see `FreeVarFolder::fold_free_var_ty`).
+    fn visit_free_var_ty(&mut self, _depth: DebruijnIndex, _binders: usize) {}
+
+    /// As `visit_free_var_ty`, but for lifetimes.
+    fn visit_free_var_lifetime(&mut self, _depth: DebruijnIndex, _binders: usize) {}
+
+    fn visit_placeholder_ty(&mut self, _universe: PlaceholderIndex, _binders: usize) {}
+
+    fn visit_placeholder_lifetime(&mut self, _universe: PlaceholderIndex, _binders: usize) {}
+
+    fn visit_inference_ty(&mut self, _var: InferenceVar, _binders: usize) {}
+
+    fn visit_inference_lifetime(&mut self, _var: InferenceVar, _binders: usize) {}
+
+    /// Invoked for each item mentioned: the structs and associated
+    /// types in types, the traits in trait references, and so on.
+    fn visit_item_id(&mut self, _item_id: ItemId) {}
+}
+
+/// Applies a `Visitor` to all the leaves of a value, in the order in
+/// which `Fold` would fold them.
+pub trait Visit {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize);
+}
+
+impl<'a, T: Visit + ?Sized> Visit for &'a T {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        (**self).visit_with(visitor, binders)
+    }
+}
+
+impl<T: Visit> Visit for [T] {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        for element in self {
+            element.visit_with(visitor, binders);
+        }
+    }
+}
+
+impl<T: Visit> Visit for Vec<T> {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        self[..].visit_with(visitor, binders)
+    }
+}
+
+impl<A: smallvec::Array> Visit for SmallVec<A>
+where
+    A::Item: Visit,
+{
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        self[..].visit_with(visitor, binders)
+    }
+}
+
+impl<T: Visit + ?Sized> Visit for Box<T> {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        (**self).visit_with(visitor, binders)
+    }
+}
+
+impl<T: Visit + ?Sized> Visit for Arc<T> {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        (**self).visit_with(visitor, binders)
+    }
+}
+
+impl<T: Visit> Visit for Option<T> {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        if let Some(value) = self {
+            value.visit_with(visitor, binders);
+        }
+    }
+}
+
+macro_rules! tuple_visit {
+    ($($n:ident),*) => {
+        impl<$($n: Visit,)*> Visit for ($($n,)*) {
+            fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+                #[allow(non_snake_case)]
+                let &($(ref $n),*) = self;
+                $($n.visit_with(visitor, binders);)*
+            }
+        }
+    }
+}
+
+tuple_visit!(A, B);
+tuple_visit!(A, B, C);
+tuple_visit!(A, B, C, D);
+
+impl Visit for Ty {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        match self.data() {
+            TyData::BoundVar(depth) => {
+                if let Some(free_depth) = depth.shifted_out(binders) {
+                    visitor.visit_free_var_ty(free_depth, binders);
+                }
+            }
+            TyData::InferenceVar(var) => visitor.visit_inference_ty(*var, binders),
+            TyData::Apply(apply) => {
+                match apply.name {
+                    TypeName::Placeholder(universe) => {
+                        visitor.visit_placeholder_ty(universe, binders)
+                    }
+                    TypeName::ItemId(item_id) | TypeName::AssociatedType(item_id) => {
+                        visitor.visit_item_id(item_id)
+                    }
+                    TypeName::Tuple(_) | TypeName::FnPtr(_) => {}
+                }
+                apply.parameters.visit_with(visitor, binders);
+            }
+            TyData::Projection(proj) => proj.visit_with(visitor, binders),
+            TyData::UnselectedProjection(proj) => proj.visit_with(visitor, binders),
+            TyData::ForAll(quantified_ty) => quantified_ty
+                .ty
+                .visit_with(visitor, binders + quantified_ty.binders.len()),
+            TyData::Dyn(bounds) => bounds.visit_with(visitor, binders),
+        }
+    }
+}
+
+impl Visit for Lifetime {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        match self {
+            Lifetime::BoundVar(depth) => {
+                if let Some(free_depth) = depth.shifted_out(binders) {
+                    visitor.visit_free_var_lifetime(free_depth, binders);
+                }
+            }
+            Lifetime::InferenceVar(var) => visitor.visit_inference_lifetime(*var, binders),
+            Lifetime::Placeholder(universe) => {
+                visitor.visit_placeholder_lifetime(*universe, binders)
+            }
+            Lifetime::Erased => {}
+        }
+    }
+}
+
+impl<T: Visit> Visit for Binders<T> {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        self.value.visit_with(visitor, binders + self.binders.len())
+    }
+}
+
+impl<T: Visit> Visit for Canonical<T> {
+    fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+        self.value.visit_with(visitor, binders + self.binders.len())
+    }
+}
+
+impl Visit for ItemId {
+    fn visit_with(&self, visitor: &mut dyn Visitor, _binders: usize) {
+        visitor.visit_item_id(*self)
+    }
+}
+
+macro_rules! leaf_visit {
+    ($t:ty) => {
+        impl Visit for $t {
+            fn visit_with(&self, _visitor: &mut dyn Visitor, _binders: usize) {}
+        }
+    };
+}
+
+leaf_visit!(Identifier);
+leaf_visit!(UniverseIndex);
+leaf_visit!(usize);
+leaf_visit!(QuantifierKind);
+leaf_visit!(());
+
+macro_rules! enum_visit {
+    ($s:ident [$($n:ident),*] { $($variant:ident($($name:ident),*)),* $(,)* } $($w:tt)*) => {
+        impl<$($n),*> Visit for $s<$($n),*> $($w)* {
+            fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+                match self {
+                    $(
+                        $s::$variant( $($name),* ) => {
+                            $($name.visit_with(visitor, binders);)*
+                        }
+                    )*
+                }
+            }
+        }
+    };
+}
+
+enum_visit!(ParameterKind[T, L] { Ty(a), Lifetime(a) } where T: Visit, L: Visit);
+enum_visit!(WhereClause[] { Implemented(a), ProjectionEq(a), ConstEvaluatable(a) });
+enum_visit!(WellFormed[] { Trait(a), Ty(a) });
+enum_visit!(FromEnv[] { Trait(a), Ty(a) });
+enum_visit!(DomainGoal[] {
+    Holds(a),
+    WellFormed(a),
+    FromEnv(a),
+    Normalize(a),
+    UnselectedNormalize(a),
+    InScope(a),
+    Derefs(a),
+    IsLocal(a),
+    IsUpstream(a),
+    IsFullyVisible(a),
+    LocalImplAllowed(a),
+    Compatible(a),
+    DownstreamType(a),
+    IsSized(a),
+    NeedsDrop(a),
+    Custom(a),
+});
+enum_visit!(LeafGoal[] { EqGoal(a), OutlivesGoal(a), DomainGoal(a) });
+enum_visit!(Constraint[] { LifetimeEq(a, b), Outlives(a, b) });
+enum_visit!(Goal[] {
+    Quantified(qkind, subgoal),
+    Implies(wc, subgoal),
+    And(g1, g2),
+    All(g),
+    Any(g),
+    Not(g),
+    Leaf(wc),
+    CannotProve(a),
+});
+enum_visit!(ProgramClause[] { Implies(a), ForAll(a) });
+
+macro_rules! struct_visit {
+    ($s:ident $([$($n:ident),*])* { $($name:ident),* $(,)* } $($w:tt)*) => {
+        impl$(<$($n),*>)* Visit for $s$(<$($n),*>)* $($w)* {
+            fn visit_with(&self, visitor: &mut dyn Visitor, binders: usize) {
+                $(self.$name.visit_with(visitor, binders);)*
+            }
+        }
+    };
+}
+
+struct_visit!(ProjectionTy {
+    associated_ty_id,
+    parameters,
+});
+struct_visit!(UnselectedProjectionTy {
+    type_name,
+    parameters,
+});
+struct_visit!(TraitRef {
+    trait_id,
+    parameters,
+});
+struct_visit!(Normalize { projection, ty });
+struct_visit!(ProjectionEq { projection, ty });
+struct_visit!(ConstEvaluatable {
+    const_name,
+    parameters,
+});
+struct_visit!(UnselectedNormalize { projection, ty });
+struct_visit!(CustomGoal { name, parameters });
+struct_visit!(Environment { clauses });
+struct_visit!(InEnvironment[G] { environment, goal } where G: Visit);
+struct_visit!(EqGoal { a, b });
+struct_visit!(OutlivesGoal { a, b });
+struct_visit!(Derefs { source, target });
+struct_visit!(ProgramClauseImplication {
+    consequence,
+    conditions,
+});
+struct_visit!(Substitution { parameters });
+struct_visit!(ConstrainedSubst { subst, constraints });
@@ -56,8 +56,8 @@ impl<'f, Z: Zipper> Zipper for &'f mut Z {
 ///
 /// See `Zipper` trait for more details.
 ///
-/// To implement the trait, typically you would use one of the macros
-/// like `eq_zip!`, `struct_zip!`, or `enum_zip!`.
+/// To implement the trait, typically you would derive it, or use
+/// `eq_zip!` for atomic values.
 pub trait Zip: Debug {
     fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()>;
 }
@@ -80,6 +80,15 @@ impl<T: Zip> Zip for Vec<T> {
     }
 }
 
+impl<A: smallvec::Array> Zip for SmallVec<A>
+where
+    A::Item: Zip,
+{
+    fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
+        <[A::Item] as Zip>::zip_with(zipper, a, b)
+    }
+}
+
 impl<T: Zip> Zip for [T] {
     fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
         if a.len() != b.len() {
@@ -152,46 +161,6 @@ eq_zip!(TypeName);
 eq_zip!(Identifier);
 eq_zip!(QuantifierKind);
 
-macro_rules! struct_zip {
-    ($t:ident$([$($param:tt)*])* { $($field:ident),* $(,)* } $($w:tt)*) => {
-        impl$(<$($param)*>)* Zip for $t $(<$($param)*>)* $($w)* {
-            fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
-                // Validate that we have indeed listed all fields
-                let $t { $($field: _),* } = *a;
-                $(
-                    Zip::zip_with(zipper, &a.$field, &b.$field)?;
-                )*
-                Ok(())
-            }
-        }
-    }
-}
-
-/// Generates a Zip impl that zips each field of the struct in turn.
-struct_zip!(TraitRef {
-    trait_id,
-    parameters,
-});
-struct_zip!(InEnvironment[T] { environment, goal } where T: Zip);
-struct_zip!(ApplicationTy { name, parameters });
-struct_zip!(ProjectionTy {
-    associated_ty_id,
-    parameters,
-});
-struct_zip!(UnselectedProjectionTy {
-    type_name,
-    parameters,
-});
-struct_zip!(Normalize { projection, ty });
-struct_zip!(ProjectionEq { projection, ty });
-struct_zip!(UnselectedNormalize { projection, ty });
-struct_zip!(EqGoal { a, b });
-struct_zip!(ProgramClauseImplication {
-    consequence,
-    conditions
-});
-struct_zip!(Derefs { source, target });
-
 impl Zip for Environment {
     fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
         assert_eq!(a.clauses.len(), b.clauses.len()); // or different numbers of clauses
@@ -200,87 +169,8 @@ impl Zip for Environment {
     }
 }
 
-macro_rules! enum_zip {
-    ($t:ident$([$($param:tt)*])* { $( $variant:ident ),* $(,)* } $($w:tt)*) => {
-        impl$(<$($param)*>)* Zip for $t $(<$($param)*>)* $($w)* {
-            fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
-                match (a, b) {
-                    $(
-                        (&$t :: $variant (ref f_a), &$t :: $variant (ref f_b)) => {
-                            Zip::zip_with(zipper, f_a, f_b)
-                        }
-                    )*
-
-                    $((&$t :: $variant ( .. ), _))|* => {
-                        return Err(NoSolution);
-                    }
-                }
-            }
-        }
-    }
-}
-
-/// Generates a Zip impl that requires the two enums be the same
-/// variant, then zips each field of the variant in turn. Only works
-/// if all variants have a single parenthesized value right now.
-enum_zip!(WhereClause {
-    Implemented,
-    ProjectionEq
-});
-enum_zip!(WellFormed { Trait, Ty });
-enum_zip!(FromEnv { Trait, Ty });
-enum_zip!(DomainGoal {
-    Holds,
-    WellFormed,
-    FromEnv,
-    Normalize,
-    UnselectedNormalize,
-    InScope,
-    Derefs,
-    IsLocal,
-    IsUpstream,
-    IsFullyVisible,
-    LocalImplAllowed,
-    Compatible,
-    DownstreamType
-});
-enum_zip!(LeafGoal { DomainGoal, EqGoal });
-enum_zip!(ProgramClause { Implies, ForAll });
-
-// Annoyingly, Goal cannot use `enum_zip` because some variants have
-// two parameters, and I'm too lazy to make the macro account for the
-// relevant name mangling.
-impl Zip for Goal {
-    fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
-        match (a, b) {
-            (&Goal::Quantified(ref f_a, ref g_a), &Goal::Quantified(ref f_b, ref g_b)) => {
-                Zip::zip_with(zipper, f_a, f_b)?;
-                Zip::zip_with(zipper, g_a, g_b)
-            }
-            (&Goal::Implies(ref f_a, ref g_a), &Goal::Implies(ref f_b, ref g_b)) => {
-                Zip::zip_with(zipper, f_a, f_b)?;
-                Zip::zip_with(zipper, g_a, g_b)
-            }
-            (&Goal::And(ref f_a, ref g_a), &Goal::And(ref f_b, ref g_b)) => {
-                Zip::zip_with(zipper, f_a, f_b)?;
-                Zip::zip_with(zipper, g_a, g_b)
-            }
-            (&Goal::Not(ref f_a), &Goal::Not(ref f_b)) => Zip::zip_with(zipper, f_a, f_b),
-            (&Goal::Leaf(ref f_a), &Goal::Leaf(ref f_b)) => Zip::zip_with(zipper, f_a, f_b),
-            (&Goal::CannotProve(()), &Goal::CannotProve(())) => Ok(()),
-            (&Goal::Quantified(..), _)
-            | (&Goal::Implies(..), _)
-            | (&Goal::And(..), _)
-            | (&Goal::Not(..), _)
-            | (&Goal::Leaf(..), _)
-            | (&Goal::CannotProve(..), _) => {
-                return Err(NoSolution);
-            }
-        }
-    }
-}
-
-// I'm too lazy to make `enum_zip` support type parameters.
+// Zipped by hand: parameters of different kinds in matching spots are
+// a bug, not a failure to unify.
 impl<T: Zip, L: Zip> Zip for ParameterKind<T, L> {
     fn zip_with<Z: Zipper>(zipper: &mut Z, a: &Self, b: &Self) -> Fallible<()> {
         match (a, b) {
@@ -0,0 +1,132 @@
+//! Filtering of the debug output. `CHALK_DEBUG` holds the level of
+//! output (1 for `info!`, 2 for `debug!` as well), optionally followed
+//! by comma-separated directives that narrow it down:
+//!
+//! - `module=NAME` only keeps the output of the modules named `NAME`
+//!   (e.g. `engine`, `coherence`, `wf` or `lowering`): any module whose
+//!   path has a component `NAME` or `chalk_NAME`.
+//! - `item=NAME` only keeps the output that mentions the identifier
+//!   `NAME` (e.g. the name of a trait or struct), along with everything
+//!   nested under a heading that mentions it.
+//! - `goal=TEXT` is like `item`, but matches any text (without commas),
+//!   so that it can pick out a particular goal, e.g.
+//!   `goal=Implemented(Foo: Clone)`.
+//!
+//! Each directive can be given more than once, in which case output
+//! matching any of them is kept. For example,
+//! `CHALK_DEBUG=2,module=engine,item=Foo` shows what the engine does
+//! for the goals that involve `Foo`. The level may be left out when
+//! there are directives, and defaults to 2.
+
+use std::cell::Cell;
+use std::env;
+
+mod test;
+
+thread_local! {
+    /// The number of headings being printed that matched an `item` or
+    /// `goal` pattern: everything under them is shown.
+    static MATCHED_HEADINGS: Cell<usize> = Cell::new(0);
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DebugFilter {
+    pub level: u32,
+    modules: Vec<String>,
+    patterns: Vec<Pattern>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Pattern {
+    Item(String),
+    Text(String),
+}
+
+impl Pattern {
+    fn is_match(&self, message: &str) -> bool {
+        match self {
+            Pattern::Item(name) => mentions_identifier(message, name),
+            Pattern::Text(text) => message.contains(&text[..]),
+        }
+    }
+}
+
+impl DebugFilter {
+    pub fn from_env() -> DebugFilter {
+        env::var("CHALK_DEBUG")
+            .map(|value| DebugFilter::parse(&value))
+            .unwrap_or_default()
+    }
+
+    /// Parses the value of `CHALK_DEBUG`; directives that make no sense
+    /// are reported and ignored.
+    pub fn parse(value: &str) -> DebugFilter {
+        let mut filter = DebugFilter::default();
+        let mut level = None;
+        let directives = value.split(',').map(|directive| directive.trim());
+        for directive in directives.filter(|directive| !directive.is_empty()) {
+            if let Ok(value) = directive.parse::<u32>() {
+                level = Some(value);
+                continue;
+            }
+            let mut parts = directive.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some("module"), Some(name)) => filter.modules.push(name.to_string()),
+                (Some("item"), Some(name)) => filter.patterns.push(Pattern::Item(name.to_string())),
+                (Some("goal"), Some(text)) => filter.patterns.push(Pattern::Text(text.to_string())),
+                _ => eprintln!("CHALK_DEBUG: ignoring unknown directive `{}`", directive),
+            }
+        }
+
+        let has_directives = !filter.modules.is_empty() || !filter.patterns.is_empty();
+        filter.level = level.unwrap_or(if has_directives { 2 } else { 0 });
+        filter
+    }
+
+    /// Whether output from the module at `module_path` is kept.
+    pub fn allows_module(&self, module_path: &str) -> bool {
+        self.modules.is_empty()
+            || module_path.split("::").any(|component| {
+                let component = component.trim_start_matches("chalk_");
+                self.modules.iter().any(|module| module == component)
+            })
+    }
+
+    /// Whether `message` matches one of the `item` or `goal` patterns.
+    pub fn matches(&self, message: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.is_match(message))
+    }
+
+    /// Whether `message`, from the module at `module_path`, is kept:
+    /// it must be from one of the modules given, and either match one
+    /// of the patterns itself or be nested under a heading that does.
+    pub fn allows(&self, module_path: &str, message: &str) -> bool {
+        self.allows_module(module_path)
+            && (self.patterns.is_empty()
+                || MATCHED_HEADINGS.with(|count| count.get() > 0)
+                || self.matches(message))
+    }
+}
+
+/// Records that a heading which matched a pattern was opened (`true`)
+/// or closed (`false`).
+crate fn enter_matched_heading(entered: bool) {
+    MATCHED_HEADINGS.with(|count| {
+        if entered {
+            count.set(count.get() + 1)
+        } else {
+            count.set(count.get() - 1)
+        }
+    });
+}
+
+/// Whether `message` contains `name` as a whole identifier (and not as
+/// part of a longer one).
+fn mentions_identifier(message: &str, name: &str) -> bool {
+    let is_identifier_char = |c: char| c.is_alphanumeric() || c == '_';
+    message.match_indices(name).any(|(start, _)| {
+        let before = message[..start].chars().next_back();
+        let after = message[start + name.len()..].chars().next();
+        !before.map_or(false, is_identifier_char) && !after.map_or(false, is_identifier_char)
+    })
+}
@@ -0,0 +1,58 @@
+#![cfg(test)]
+
+use super::*;
+
+#[test]
+fn level_only() {
+    assert_eq!(DebugFilter::parse("").level, 0);
+    assert_eq!(DebugFilter::parse("1").level, 1);
+
+    let filter = DebugFilter::parse("2");
+    assert_eq!(filter.level, 2);
+    assert!(filter.allows("chalk_engine::logic", "anything"));
+}
+
+#[test]
+fn modules() {
+    let filter = DebugFilter::parse("module=engine,module=wf");
+    assert_eq!(filter.level, 2);
+    assert!(filter.allows_module("chalk_engine::logic"));
+    assert!(filter.allows_module("chalk::rules::wf"));
+    assert!(!filter.allows_module("chalk::coherence::solve"));
+    assert!(!filter.allows_module("chalk::rules::wfx"));
+}
+
+#[test]
+fn items() {
+    let filter = DebugFilter::parse("1,item=Foo");
+    assert_eq!(filter.level, 1);
+    assert!(filter.allows("chalk_solve", "Implemented(Foo: Clone)"));
+    assert!(filter.allows("chalk_solve", "Vec<Foo>"));
+    assert!(!filter.allows("chalk_solve", "Implemented(Foobar: Clone)"));
+    assert!(!filter.allows("chalk_solve", "Implemented(MyFoo: Clone)"));
+}
+
+#[test]
+fn goals() {
+    let filter = DebugFilter::parse("goal=Foo: Clone");
+    assert!(filter.matches("Implemented(Foo: Clone)"));
+    assert!(!filter.matches("Implemented(Foo: Copy)"));
+}
+
+#[test]
+fn nested_under_matched_heading() {
+    let filter = DebugFilter::parse("item=Foo");
+    assert!(!filter.allows("chalk_engine", "solve subgoal"));
+
+    enter_matched_heading(true);
+    assert!(filter.allows("chalk_engine", "solve subgoal"));
+    enter_matched_heading(false);
+
+    assert!(!filter.allows("chalk_engine", "solve subgoal"));
+}
+
+#[test]
+fn unknown_directives_are_ignored() {
+    let filter = DebugFilter::parse("2,color=always");
+    assert_eq!(filter, DebugFilter::parse("2"));
+}
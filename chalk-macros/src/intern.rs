@@ -0,0 +1,206 @@
+//! Interning of the identifiers of programs.
+//!
+//! Identifiers are interned in an `Interner`, which a host can create
+//! for each program it works on (or share between them). The interner
+//! that identifiers are interned in and looked up in is the one
+//! installed on the current thread with `with_interner`, or else the
+//! process-wide interner, so that identifiers interned without
+//! installing an interner can be used on every thread. An installed
+//! interner can be shared between threads too, by installing it on all
+//! the threads that work on its program.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, RwLock};
+
+mod test;
+
+lazy_static! {
+    static ref GLOBAL_INTERNER: Arc<Interner> = Arc::new(Interner::new());
+}
+
+thread_local! {
+    /// The interner installed by `with_interner`, if any.
+    static INTERNER: RefCell<Option<Arc<Interner>>> = RefCell::new(None);
+    static HASH_BY_STRING: Cell<bool> = Cell::new(false);
+}
+
+/// A table of strings, each of which is given an `InternedString`.
+#[derive(Default)]
+pub struct Interner {
+    strings: RwLock<Strings>,
+}
+
+#[derive(Default)]
+struct Strings {
+    indices: HashMap<Arc<str>, InternedString>,
+    strings: Vec<Arc<str>>,
+}
+
+/// A string interned in an `Interner`. Interned strings are compared,
+/// ordered and hashed by their index, so two of them are only meaningful
+/// together if they come from the same interner, and they are ordered by
+/// when they were interned rather than alphabetically. They are displayed
+/// and debugged by looking them up in the current interner.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InternedString {
+    index: u32,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    pub fn intern(&self, s: &str) -> InternedString {
+        if let Some(&interned) = self.strings.read().unwrap().indices.get(s) {
+            return interned;
+        }
+
+        // Another thread may have interned `s` since we looked.
+        let mut strings = self.strings.write().unwrap();
+        if let Some(&interned) = strings.indices.get(s) {
+            return interned;
+        }
+        let interned = InternedString {
+            index: strings.strings.len() as u32,
+        };
+        let s: Arc<str> = Arc::from(s);
+        strings.indices.insert(s.clone(), interned);
+        strings.strings.push(s);
+        interned
+    }
+
+    /// The string that was interned as `interned`. Panics if `interned`
+    /// does not come from this interner.
+    pub fn data(&self, interned: InternedString) -> Arc<str> {
+        match self.strings.read().unwrap().strings.get(interned.index()) {
+            Some(s) => s.clone(),
+            None => panic!(
+                "string {} was not interned in this interner \
+                 (is the interner of its program installed on this thread?)",
+                interned.index
+            ),
+        }
+    }
+
+    /// The number of strings interned so far.
+    pub fn len(&self) -> usize {
+        self.strings.read().unwrap().strings.len()
+    }
+}
+
+/// The interner installed on the current thread, or the process-wide
+/// interner if there is none.
+pub fn current_interner() -> Arc<Interner> {
+    INTERNER.with(|interner| match *interner.borrow() {
+        Some(ref interner) => interner.clone(),
+        None => GLOBAL_INTERNER.clone(),
+    })
+}
+
+/// Runs `op` with `interner` installed as the current interner. The
+/// interner that was installed before is installed again afterwards,
+/// even if `op` panics.
+pub fn with_interner<OP, R>(interner: &Arc<Interner>, op: OP) -> R
+where
+    OP: FnOnce() -> R,
+{
+    struct Restore(Option<Arc<Interner>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            INTERNER.with(|cell| *cell.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _restore = Restore(INTERNER.with(|cell| cell.replace(Some(interner.clone()))));
+    op()
+}
+
+/// Runs `op` with interned strings hashed by their string instead of
+/// their index, for hashes that must not depend on the interner (or on
+/// the order that strings were interned in), like fingerprints. Hashing
+/// goes back to what it was afterwards, even if `op` panics.
+pub fn hash_by_string<OP, R>(op: OP) -> R
+where
+    OP: FnOnce() -> R,
+{
+    set_hash_by_string(true, op)
+}
+
+/// Runs `op` with interned strings hashed by their index, as they are
+/// outside `hash_by_string`, for hashes that are kept and must not depend
+/// on where they were computed, like those of interned values.
+pub fn hash_by_index<OP, R>(op: OP) -> R
+where
+    OP: FnOnce() -> R,
+{
+    set_hash_by_string(false, op)
+}
+
+/// Whether interned strings are being hashed by their string, inside
+/// `hash_by_string`.
+pub fn hashing_by_string() -> bool {
+    HASH_BY_STRING.with(Cell::get)
+}
+
+fn set_hash_by_string<OP, R>(by_string: bool, op: OP) -> R
+where
+    OP: FnOnce() -> R,
+{
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            HASH_BY_STRING.with(|cell| cell.set(self.0));
+        }
+    }
+
+    let _restore = Restore(HASH_BY_STRING.with(|cell| cell.replace(by_string)));
+    op()
+}
+
+/// Interns `s` in the current interner.
+pub fn intern(s: &str) -> InternedString {
+    current_interner().intern(s)
+}
+
+impl InternedString {
+    fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    /// The string, as found in the current interner.
+    pub fn data(&self) -> Arc<str> {
+        current_interner().data(*self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data().len()
+    }
+}
+
+impl fmt::Debug for InternedString {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.data(), fmt)
+    }
+}
+
+impl fmt::Display for InternedString {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&*self.data(), fmt)
+    }
+}
+
+impl Hash for InternedString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        if HASH_BY_STRING.with(Cell::get) {
+            self.data().hash(state)
+        } else {
+            self.index.hash(state)
+        }
+    }
+}
@@ -0,0 +1,109 @@
+#![cfg(test)]
+
+use super::*;
+use std::cmp::Ordering;
+use std::thread;
+
+#[test]
+fn intern_same_string() {
+    let interner = Interner::new();
+    let foo = interner.intern("Foo");
+    assert_eq!(interner.intern("Foo"), foo);
+    assert_ne!(interner.intern("Bar"), foo);
+    assert_eq!(&*interner.data(foo), "Foo");
+    assert_eq!(interner.len(), 2);
+}
+
+#[test]
+fn scoped_interners() {
+    let first = Arc::new(Interner::new());
+    let second = Arc::new(Interner::new());
+    let foo = with_interner(&first, || intern("Foo"));
+    with_interner(&second, || {
+        intern("Bar");
+        assert_eq!(intern("Foo").to_string(), "Foo");
+    });
+    assert_eq!(first.len(), 1);
+    assert_eq!(second.len(), 2);
+    assert_eq!(with_interner(&first, || foo.to_string()), "Foo");
+}
+
+#[test]
+fn order_by_index() {
+    let interner = Arc::new(Interner::new());
+    with_interner(&interner, || {
+        let (b, a) = (intern("b"), intern("a"));
+        assert!(b < a);
+        assert_eq!(a.cmp(&a), Ordering::Equal);
+    });
+}
+
+#[test]
+fn share_between_threads() {
+    let interner = Arc::new(Interner::new());
+    let foo = with_interner(&interner, || intern("Foo"));
+    let workers: Vec<_> = (0..4)
+        .map(|_| {
+            let interner = interner.clone();
+            thread::spawn(move || {
+                with_interner(&interner, || (intern("Foo"), foo.to_string()))
+            })
+        })
+        .collect();
+    for worker in workers {
+        assert_eq!(worker.join().unwrap(), (foo, "Foo".to_string()));
+    }
+}
+
+#[test]
+fn hash_by_index() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn hash(interner: &Arc<Interner>, s: &str) -> u64 {
+        with_interner(interner, || {
+            let mut hasher = DefaultHasher::new();
+            intern(s).hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+
+    let first = Arc::new(Interner::new());
+    let second = Arc::new(Interner::new());
+    with_interner(&second, || intern("Bar"));
+    assert_ne!(hash(&first, "Foo"), hash(&second, "Foo"));
+    assert_eq!(
+        hash_by_string(|| hash(&first, "Foo")),
+        hash_by_string(|| hash(&second, "Foo"))
+    );
+    assert_eq!(
+        hash_by_string(|| super::hash_by_index(|| hash(&first, "Foo"))),
+        hash(&first, "Foo")
+    );
+    assert!(hash_by_string(|| {
+        super::hash_by_index(|| !hashing_by_string()) && hashing_by_string()
+    }));
+}
+
+#[test]
+fn global_interner_on_spawned_threads() {
+    // Without an installed interner, every thread uses the process-wide
+    // one.
+    let foo = intern("Foo");
+    let formatted = thread::spawn(move || format!("{} {:?}", foo, foo));
+    assert_eq!(formatted.join().unwrap(), "Foo \"Foo\"");
+}
+
+#[test]
+fn restore_after_panic() {
+    use std::panic;
+
+    let interner = Arc::new(Interner::new());
+    let result = panic::catch_unwind(|| with_interner(&interner, || panic!("op failed")));
+    assert!(result.is_err());
+    assert!(!Arc::ptr_eq(&current_interner(), &interner));
+
+    let result = panic::catch_unwind(|| hash_by_string(|| panic!("op failed")));
+    assert!(result.is_err());
+    assert!(!HASH_BY_STRING.with(Cell::get));
+}
@@ -8,23 +8,16 @@ extern crate lazy_static;
 #[macro_use]
 mod index;
 
+mod filter;
+pub mod intern;
+pub mod profile;
+
+pub use filter::DebugFilter;
+
 lazy_static! {
-    pub static ref DEBUG_ENABLED: bool = {
-        use std::env;
-        env::var("CHALK_DEBUG")
-            .ok()
-            .and_then(|s| s.parse::<u32>().ok())
-            .map(|x| x >= 2)
-            .unwrap_or(false)
-    };
-    pub static ref INFO_ENABLED: bool = {
-        use std::env;
-        env::var("CHALK_DEBUG")
-            .ok()
-            .and_then(|s| s.parse::<u32>().ok())
-            .map(|x| x >= 1)
-            .unwrap_or(false)
-    };
+    pub static ref DEBUG_FILTER: DebugFilter = DebugFilter::from_env();
+    pub static ref DEBUG_ENABLED: bool = DEBUG_FILTER.level >= 2;
+    pub static ref INFO_ENABLED: bool = DEBUG_FILTER.level >= 1;
 }
 
 thread_local! {
@@ -40,7 +33,7 @@ const OVERFLOW_DEPTH: usize = 100;
 macro_rules! debug {
     ($($t:tt)*) => {
         if *$crate::DEBUG_ENABLED {
-            $crate::dump(&format!($($t)*), "");
+            $crate::dump_filtered(module_path!(), &format!($($t)*));
         }
     }
 }
@@ -49,9 +42,7 @@ macro_rules! debug {
 macro_rules! debug_heading {
     ($($t:tt)*) => {
         let _ = &if *$crate::DEBUG_ENABLED {
-            let string = format!($($t)*);
-            $crate::dump(&string, " {");
-            $crate::Indent::new(true, string)
+            $crate::Indent::heading(module_path!(), format!($($t)*))
         } else {
             $crate::Indent::new(false, String::new())
         };
@@ -62,7 +53,7 @@ macro_rules! debug_heading {
 macro_rules! info {
     ($($t:tt)*) => {
         if *$crate::INFO_ENABLED {
-            $crate::dump(&format!($($t)*), "");
+            $crate::dump_filtered(module_path!(), &format!($($t)*));
         }
     }
 }
@@ -71,15 +62,31 @@ macro_rules! info {
 macro_rules! info_heading {
     ($($t:tt)*) => {
         let _ = &if *$crate::INFO_ENABLED {
-            let string = format!($($t)*);
-            $crate::dump(&string, " {");
-            $crate::Indent::new(true, string)
+            $crate::Indent::heading(module_path!(), format!($($t)*))
         } else {
             $crate::Indent::new(false, String::new())
         };
     }
 }
 
+/// Dumps `string`, from the module at `module_path`, unless the
+/// `CHALK_DEBUG` directives filter it out (see `DebugFilter`).
+pub fn dump_filtered(module_path: &str, string: &str) {
+    if DEBUG_FILTER.allows(module_path, string) {
+        dump(string, "");
+    }
+}
+
+/// Profiles the rest of the enclosing block as the scope `$name`,
+/// nested in the scopes of the blocks around it (see the `profile`
+/// module).
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_guard = $crate::profile::enter($name);
+    };
+}
+
 pub fn dump(string: &str, suffix: &str) {
     let indent = INDENT.with(|i| i.borrow().len());
     let mut first = true;
@@ -105,9 +112,29 @@ pub fn dump(string: &str, suffix: &str) {
 
 pub struct Indent {
     enabled: bool,
+    matched: bool,
 }
 
 impl Indent {
+    /// Dumps the heading `value`, from the module at `module_path`, and
+    /// indents what follows until the `Indent` is dropped. If the
+    /// `CHALK_DEBUG` directives filter the heading out, nothing is
+    /// dumped or indented.
+    pub fn heading(module_path: &str, value: String) -> Self {
+        let filter = &*DEBUG_FILTER;
+        let enabled = filter.allows(module_path, &value);
+        let matched = enabled && filter.matches(&value);
+        if matched {
+            filter::enter_matched_heading(true);
+        }
+        if enabled {
+            dump(&value, " {");
+        }
+        let mut indent = Indent::new(enabled, value);
+        indent.matched = matched;
+        indent
+    }
+
     pub fn new(enabled: bool, value: String) -> Self {
         if enabled {
             INDENT.with(|i| {
@@ -121,7 +148,10 @@ impl Indent {
                 }
             });
         }
-        Indent { enabled }
+        Indent {
+            enabled,
+            matched: false,
+        }
     }
 }
 
@@ -131,5 +161,8 @@ impl Drop for Indent {
             INDENT.with(|i| i.borrow_mut().pop().unwrap());
             dump("}", "");
         }
+        if self.matched {
+            filter::enter_matched_heading(false);
+        }
     }
 }
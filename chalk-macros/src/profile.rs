@@ -0,0 +1,132 @@
+//! A lightweight profiler for the phases of the solver (canonicalizing,
+//! selecting clauses, unifying, creating tables, ...), each of which is
+//! marked with `profile_scope!`.
+//!
+//! The time is only measured when the `profile` feature is enabled;
+//! otherwise a scope costs next to nothing, and `folded_stacks` is
+//! empty. The results are kept as "folded stacks": one line per stack
+//! of nested scopes, with the microseconds spent in the innermost one,
+//! e.g. `solve;table;clauses 1234`. Tools like `inferno-flamegraph` or
+//! `flamegraph.pl` turn these into a flamegraph.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::mem;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+mod test;
+
+lazy_static! {
+    /// The time spent in each stack, in nanoseconds, across all
+    /// threads.
+    static ref STACKS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+}
+
+thread_local! {
+    static FRAMES: RefCell<Vec<Frame>> = RefCell::new(vec![]);
+
+    /// The time spent in each stack on this thread, which is added to
+    /// `STACKS` once the outermost scope exits.
+    static THREAD_STACKS: RefCell<BTreeMap<String, u64>> = RefCell::new(BTreeMap::new());
+}
+
+struct Frame {
+    name: &'static str,
+    start: Instant,
+
+    /// The time spent in the scopes nested in this one so far.
+    nested: Duration,
+}
+
+/// Whether the time is measured, i.e. whether the `profile` feature is
+/// enabled.
+pub fn enabled() -> bool {
+    cfg!(feature = "profile")
+}
+
+/// Exits the scope that `enter` entered when dropped.
+#[must_use]
+pub struct ProfileGuard {
+    entered: bool,
+}
+
+/// Enters the scope `name`, nested in the scopes entered before on this
+/// thread, until the returned guard is dropped. Use `profile_scope!`
+/// rather than calling this directly.
+#[inline]
+pub fn enter(name: &'static str) -> ProfileGuard {
+    if !enabled() {
+        return ProfileGuard { entered: false };
+    }
+
+    FRAMES.with(|frames| {
+        frames.borrow_mut().push(Frame {
+            name,
+            start: Instant::now(),
+            nested: Duration::default(),
+        })
+    });
+    ProfileGuard { entered: true }
+}
+
+impl Drop for ProfileGuard {
+    fn drop(&mut self) {
+        if self.entered {
+            exit();
+        }
+    }
+}
+
+fn exit() {
+    let now = Instant::now();
+    FRAMES.with(|frames| {
+        let mut frames = frames.borrow_mut();
+        let frame = frames.pop().unwrap();
+        let elapsed = now - frame.start;
+
+        let mut stack: Vec<_> = frames.iter().map(|frame| frame.name).collect();
+        stack.push(frame.name);
+        let own_time = elapsed.checked_sub(frame.nested).unwrap_or_default();
+        THREAD_STACKS.with(|stacks| {
+            *stacks.borrow_mut().entry(stack.join(";")).or_insert(0) += nanos(own_time);
+        });
+
+        match frames.last_mut() {
+            Some(parent) => parent.nested += elapsed,
+            None => flush(),
+        }
+    });
+}
+
+/// Adds the stacks of this thread to those of all threads.
+fn flush() {
+    let thread_stacks =
+        THREAD_STACKS.with(|stacks| mem::replace(&mut *stacks.borrow_mut(), BTreeMap::new()));
+    let mut stacks = STACKS.lock().unwrap();
+    for (stack, time) in thread_stacks {
+        *stacks.entry(stack).or_insert(0) += time;
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos())
+}
+
+/// The stacks of all the scopes that exited since the last
+/// `reset`, in the folded format (see the module documentation). Stacks
+/// that took less than a microsecond in all are left out.
+pub fn folded_stacks() -> String {
+    let mut folded = String::new();
+    for (stack, time) in STACKS.lock().unwrap().iter() {
+        let micros = time / 1000;
+        if micros > 0 {
+            folded.push_str(&format!("{} {}\n", stack, micros));
+        }
+    }
+    folded
+}
+
+pub fn reset() {
+    STACKS.lock().unwrap().clear();
+}
@@ -0,0 +1,33 @@
+#![cfg(test)]
+
+use super::*;
+
+// A single test, as the stacks are shared by all threads.
+#[test]
+fn stacks() {
+    reset();
+    {
+        let _outer = enter("outer");
+        {
+            let _inner = enter("inner");
+        }
+        let _sibling = enter("sibling");
+    }
+
+    let stacks: Vec<_> = STACKS.lock().unwrap().keys().cloned().collect();
+    if enabled() {
+        assert_eq!(stacks, vec!["outer", "outer;inner", "outer;sibling"]);
+    } else {
+        assert!(stacks.is_empty());
+    }
+
+    let mut stacks = BTreeMap::new();
+    stacks.insert("a;b".to_string(), 2_500_000);
+    stacks.insert("a".to_string(), 500);
+    *STACKS.lock().unwrap() = stacks;
+
+    // The stack that took less than a microsecond is left out.
+    assert_eq!(folded_stacks(), "a;b 2500\n");
+    reset();
+    assert_eq!(folded_stacks(), "");
+}
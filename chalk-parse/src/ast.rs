@@ -1,7 +1,11 @@
-use lalrpop_intern::InternedString;
+use chalk_macros::intern::InternedString;
 use std::fmt;
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// Where something is in the source text. Spans are ignored when
+/// comparing ASTs, so that an item is equal to itself after text was
+/// added or removed before it; compare `lo` and `hi` to compare the
+/// positions themselves.
+#[derive(Copy, Clone, Debug)]
 pub struct Span {
     pub lo: usize,
     pub hi: usize,
@@ -13,6 +17,14 @@ impl Span {
     }
 }
 
+impl PartialEq for Span {
+    fn eq(&self, _other: &Span) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Program {
     pub items: Vec<Item>,
@@ -53,10 +65,24 @@ pub struct TraitDefn {
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct TraitFlags {
     pub auto: bool,
+    pub coinductive: bool,
     pub marker: bool,
     pub upstream: bool,
     pub fundamental: bool,
     pub deref: bool,
+    pub drop: bool,
+    pub builtin: Option<BuiltinLangItem>,
+}
+
+/// A lang item trait whose impls for tuples and fn pointers are built in.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BuiltinLangItem {
+    Sized,
+    Copy,
+    Clone,
+    FnOnce,
+    FnMut,
+    Fn,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -163,9 +189,19 @@ pub enum Ty {
         proj: UnselectedProjectionTy,
     },
     ForAll {
-        lifetime_names: Vec<Identifier>,
+        parameter_kinds: Vec<ParameterKind>,
         ty: Box<Ty>,
     },
+    Dyn {
+        bounds: Vec<QuantifiedInlineBound>,
+    },
+    Tuple {
+        types: Vec<Ty>,
+    },
+    FnPtr {
+        args: Vec<Ty>,
+        ret: Box<Ty>,
+    },
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -206,6 +242,14 @@ impl PolarizedTraitRef {
             PolarizedTraitRef::Negative(trait_ref)
         }
     }
+
+    pub fn trait_ref(&self) -> &TraitRef {
+        match self {
+            PolarizedTraitRef::Positive(trait_ref) | PolarizedTraitRef::Negative(trait_ref) => {
+                trait_ref
+            }
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -224,6 +268,7 @@ impl fmt::Display for Identifier {
 pub enum WhereClause {
     Implemented { trait_ref: TraitRef },
     ProjectionEq { projection: ProjectionTy, ty: Ty },
+    ConstEvaluatable { const_name: Identifier, args: Vec<Parameter> },
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -242,6 +287,9 @@ pub enum DomainGoal {
     LocalImplAllowed { trait_ref: TraitRef },
     Compatible,
     DownstreamType { ty: Ty },
+    IsSized { ty: Ty },
+    NeedsDrop { ty: Ty },
+    Custom { name: Identifier, args: Vec<Parameter> },
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -249,6 +297,7 @@ pub enum LeafGoal {
     DomainGoal { goal: DomainGoal },
     UnifyTys { a: Ty, b: Ty },
     UnifyLifetimes { a: Lifetime, b: Lifetime },
+    Outlives { a: Lifetime, b: Lifetime },
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -278,6 +327,13 @@ pub enum Goal {
     Exists(Vec<ParameterKind>, Box<Goal>),
     Implies(Vec<Clause>, Box<Goal>),
     And(Box<Goal>, Box<Goal>),
+
+    /// The `all { G1; ...; Gn }` syntax
+    All(Vec<Box<Goal>>),
+
+    /// The `any { G1; ...; Gn }` syntax
+    Any(Vec<Box<Goal>>),
+
     Not(Box<Goal>),
 
     /// The `compatible { G }` syntax
@@ -5,7 +5,7 @@ extern crate failure;
 
 #[macro_use]
 extern crate lalrpop_util;
-extern crate lalrpop_intern;
+extern crate chalk_macros;
 
 pub mod ast;
 #[rustfmt::skip]
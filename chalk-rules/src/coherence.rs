@@ -0,0 +1,198 @@
+use petgraph::prelude::*;
+
+use crate::rust_ir::Program;
+use chalk_ir::ProgramEnvironment;
+use chalk_ir::{self, Identifier, ItemId};
+use chalk_solve::solve::SolverChoice;
+use failure::Fallible;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+mod graph;
+pub mod orphan;
+mod solve;
+mod test;
+
+pub use self::graph::SpecializationGraph;
+
+#[derive(Fail, Debug)]
+pub enum CoherenceError {
+    #[fail(display = "overlapping impls of trait {:?}", trait_name)]
+    OverlappingImpls {
+        trait_name: Identifier,
+        impls: (ItemId, ItemId),
+    },
+    #[fail(
+        display = "overlapping impls of trait {:?}: both apply to `{}`",
+        trait_name, intersection
+    )]
+    OverlappingImplsFor {
+        trait_name: Identifier,
+        impls: (ItemId, ItemId),
+        intersection: String,
+    },
+    #[fail(display = "impl for trait {:?} violates the orphan rules", trait_name)]
+    FailedOrphanCheck {
+        trait_name: Identifier,
+        impl_id: ItemId,
+    },
+}
+
+impl CoherenceError {
+    /// A code identifying the kind of error (see `LoweringError::code`).
+    pub fn code(&self) -> &'static str {
+        match self {
+            CoherenceError::OverlappingImpls { .. } => "C0001",
+            CoherenceError::OverlappingImplsFor { .. } => "C0002",
+            CoherenceError::FailedOrphanCheck { .. } => "C0003",
+        }
+    }
+
+    /// The impls the error is about.
+    pub fn items(&self) -> Vec<ItemId> {
+        match *self {
+            CoherenceError::OverlappingImpls { impls, .. }
+            | CoherenceError::OverlappingImplsFor { impls, .. } => vec![impls.0, impls.1],
+            CoherenceError::FailedOrphanCheck { impl_id, .. } => vec![impl_id],
+        }
+    }
+
+    /// The message prefixed with the error code.
+    pub fn render(&self) -> String {
+        format!("error[{}]: {}", self.code(), self)
+    }
+}
+
+impl Program {
+    pub fn record_specialization_priorities(
+        &mut self,
+        env: Arc<ProgramEnvironment>,
+        solver_choice: SolverChoice,
+    ) -> Fallible<()> {
+        self.update_specialization_priorities(env, solver_choice, None)
+    }
+
+    /// As `record_specialization_priorities`, but only checks the pairs of
+    /// impls that involve one of `impls`. The specializations found before
+    /// between two other impls are kept.
+    crate fn recheck_specialization_priorities(
+        &mut self,
+        env: Arc<ProgramEnvironment>,
+        solver_choice: SolverChoice,
+        impls: &BTreeSet<ItemId>,
+    ) -> Fallible<()> {
+        self.update_specialization_priorities(env, solver_choice, Some(impls))
+    }
+
+    fn update_specialization_priorities(
+        &mut self,
+        env: Arc<ProgramEnvironment>,
+        solver_choice: SolverChoice,
+        impls: Option<&BTreeSet<ItemId>>,
+    ) -> Fallible<()> {
+        let program = Arc::new(self.clone());
+        chalk_ir::tls::set_current_program(&program, || {
+            let forest = build_specialization_forest(&program, env, solver_choice, impls)?;
+            self.specializations = forest
+                .raw_edges()
+                .iter()
+                .map(|edge| (forest[edge.source()], forest[edge.target()]))
+                .collect();
+
+            // Visit every root in the forest & set specialization
+            // priority for the tree that is the root of.
+            for impl_datum in self.impl_data.values_mut() {
+                impl_datum.binders.value.specialization_priority = 0;
+            }
+            for root_idx in forest.externals(Direction::Incoming) {
+                self.set_priorities(root_idx, &forest, 0);
+            }
+
+            Ok(())
+        })
+    }
+
+    /// True if `impl_a` specializes `impl_b`: the two impls overlap, and
+    /// `impl_a` applies to fewer types. The relation is computed by the
+    /// coherence check, so it is empty until then (see
+    /// `LoweringDatabase::lowered_program`).
+    pub fn specializes(&self, impl_a: ItemId, impl_b: ItemId) -> bool {
+        self.specializations.contains(&(impl_b, impl_a))
+    }
+
+    /// Every pair `(less_special, more_special)` of impls such that
+    /// `more_special` specializes `less_special`.
+    pub fn specializations(&self) -> &BTreeSet<(ItemId, ItemId)> {
+        &self.specializations
+    }
+
+    /// The specialization relation as a graph, with queries for the
+    /// parent and children of each impl.
+    pub fn specialization_graph(&self) -> SpecializationGraph {
+        SpecializationGraph::new(self)
+    }
+
+    /// The specialization priority of each impl, that is its depth in the
+    /// specialization forest: impls that specialize no other impl have
+    /// priority 0.
+    pub fn specialization_priorities(&self) -> BTreeMap<ItemId, usize> {
+        self.impl_data
+            .iter()
+            .map(|(&impl_id, impl_datum)| {
+                (impl_id, impl_datum.binders.value.specialization_priority)
+            })
+            .collect()
+    }
+
+    // Recursively set priorities for those node and all of its children.
+    fn set_priorities(&mut self, idx: NodeIndex, forest: &Graph<ItemId, ()>, p: usize) {
+        // Get the impl datum recorded at this node and reset its priority
+        {
+            let impl_id = forest
+                .node_weight(idx)
+                .expect("index should be a valid index into graph");
+            let impl_datum = self
+                .impl_data
+                .get_mut(impl_id)
+                .expect("node should be valid impl id");
+            impl_datum.binders.value.specialization_priority = p;
+        }
+
+        // Visit all children of this node, setting their priority to this + 1
+        for child_idx in forest.neighbors(idx) {
+            self.set_priorities(child_idx, forest, p + 1)
+        }
+    }
+}
+
+// Build the forest of specialization relationships. If only some `impls`
+// are to be checked, the specializations between the others are taken
+// from `program`.
+fn build_specialization_forest(
+    program: &Arc<Program>,
+    env: Arc<ProgramEnvironment>,
+    solver_choice: SolverChoice,
+    impls: Option<&BTreeSet<ItemId>>,
+) -> Fallible<Graph<ItemId, ()>> {
+    // The forest is returned as a graph but built as a GraphMap; this is
+    // so that we never add multiple nodes with the same ItemId.
+    let mut forest = DiGraphMap::new();
+
+    if let Some(impls) = impls {
+        let kept = |id: ItemId| !impls.contains(&id) && program.impl_data.contains_key(&id);
+        for &(less_special, more_special) in &program.specializations {
+            if kept(less_special) && kept(more_special) {
+                forest.add_edge(less_special, more_special, ());
+            }
+        }
+    }
+
+    // Find all specializations (implemented in coherence/solve)
+    // Record them in the forest by adding an edge from the less special
+    // to the more special.
+    solve::visit_specializations(program, env, solver_choice, impls, |less, more| {
+        forest.add_edge(less, more, ());
+    })?;
+
+    Ok(forest.into_graph())
+}
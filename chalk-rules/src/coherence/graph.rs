@@ -0,0 +1,77 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::rust_ir::Program;
+use chalk_ir::ItemId;
+
+/// The impls of a program along with the specialization relation between
+/// them. An impl's parent is the most special of the impls it specializes,
+/// so following parents from any impl walks up a single chain to an impl
+/// that specializes nothing (a root).
+///
+/// Like `Program::specializes`, this is only meaningful after the
+/// coherence check has run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SpecializationGraph {
+    impls: BTreeSet<ItemId>,
+    parents: BTreeMap<ItemId, ItemId>,
+    children: BTreeMap<ItemId, Vec<ItemId>>,
+}
+
+impl SpecializationGraph {
+    crate fn new(program: &Program) -> Self {
+        let mut less_special: BTreeMap<ItemId, Vec<ItemId>> = BTreeMap::new();
+        for &(less, more) in program.specializations() {
+            less_special.entry(more).or_default().push(less);
+        }
+
+        // The relation is transitive, so the most special of the impls that
+        // an impl specializes is the one that specializes all the others.
+        let parents: BTreeMap<ItemId, ItemId> = less_special
+            .iter()
+            .filter_map(|(&impl_id, candidates)| {
+                let parent = candidates.iter().cloned().max_by_key(|&candidate| {
+                    candidates
+                        .iter()
+                        .filter(|&&other| program.specializes(candidate, other))
+                        .count()
+                })?;
+                Some((impl_id, parent))
+            })
+            .collect();
+
+        let mut children: BTreeMap<ItemId, Vec<ItemId>> = BTreeMap::new();
+        for (&child, &parent) in &parents {
+            children.entry(parent).or_default().push(child);
+        }
+
+        SpecializationGraph {
+            impls: program.impl_data.keys().cloned().collect(),
+            parents,
+            children,
+        }
+    }
+
+    /// Every impl of the program.
+    pub fn impls(&self) -> impl Iterator<Item = ItemId> + '_ {
+        self.impls.iter().cloned()
+    }
+
+    /// The impls that do not specialize any other impl.
+    pub fn roots(&self) -> impl Iterator<Item = ItemId> + '_ {
+        self.impls()
+            .filter(move |impl_id| !self.parents.contains_key(impl_id))
+    }
+
+    /// The most special of the impls that `impl_id` specializes, if any.
+    pub fn parent(&self, impl_id: ItemId) -> Option<ItemId> {
+        self.parents.get(&impl_id).cloned()
+    }
+
+    /// The impls whose parent is `impl_id`.
+    pub fn children(&self, impl_id: ItemId) -> &[ItemId] {
+        self.children
+            .get(&impl_id)
+            .map(|children| &children[..])
+            .unwrap_or(&[])
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::CoherenceError;
+use crate::rust_ir::*;
+use chalk_ir::cast::*;
+use chalk_ir::*;
+use chalk_solve::ext::*;
+use chalk_solve::solve::SolverChoice;
+use failure::Fallible;
+
+struct OrphanSolver {
+    env: Arc<ProgramEnvironment>,
+    solver_choice: SolverChoice,
+}
+
+pub fn perform_orphan_check(
+    program: Arc<Program>,
+    env: Arc<ProgramEnvironment>,
+    solver_choice: SolverChoice,
+) -> Fallible<()> {
+    check_impls(program, env, solver_choice, None)
+}
+
+/// As `perform_orphan_check`, but only checks the impls among `items`.
+crate fn perform_orphan_check_of(
+    program: Arc<Program>,
+    env: Arc<ProgramEnvironment>,
+    solver_choice: SolverChoice,
+    items: &BTreeSet<ItemId>,
+) -> Fallible<()> {
+    check_impls(program, env, solver_choice, Some(items))
+}
+
+fn check_impls(
+    program: Arc<Program>,
+    env: Arc<ProgramEnvironment>,
+    solver_choice: SolverChoice,
+    items: Option<&BTreeSet<ItemId>>,
+) -> Fallible<()> {
+    let solver = OrphanSolver { env, solver_choice };
+
+    let mut local_impls: Vec<ItemId> = program
+        .impl_data
+        .iter()
+        .filter(|(impl_id, _)| items.map_or(true, |items| items.contains(*impl_id)))
+        // Only keep local impls (i.e. impls in the current crate)
+        .filter(|(_, impl_datum)| impl_datum.binders.value.impl_type == ImplType::Local)
+        .map(|(&impl_id, _)| impl_id)
+        .collect();
+    solver_choice.clause_order().shuffle(&mut local_impls, &"orphan check");
+
+    // Each impl is checked on its own, so we check them in parallel.
+    let num_threads = solver_choice.num_threads();
+    let shared_program = program.clone();
+    let impls_allowed = tls::map_in_parallel(local_impls.clone(), num_threads, move |impl_id| {
+        solver.orphan_check(&shared_program.impl_data[impl_id])
+    });
+
+    for (impl_id, allowed) in local_impls.iter().zip(impls_allowed) {
+        if !allowed {
+            let impl_datum = &program.impl_data[impl_id];
+            let trait_id = impl_datum.binders.value.trait_ref.trait_ref().trait_id;
+            Err(CoherenceError::FailedOrphanCheck {
+                trait_name: program.type_kinds.get(&trait_id).unwrap().name,
+                impl_id: *impl_id,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+impl OrphanSolver {
+    // Test if a local impl violates the orphan rules.
+    //
+    // For `impl<T> Trait for MyType<T>` we generate:
+    //
+    //     forall<T> { LocalImplAllowed(MyType<T>: Trait) }
+    //
+    // This must be provable in order to pass the orphan check.
+    fn orphan_check(&self, impl_datum: &ImplDatum) -> bool {
+        debug_heading!("orphan_check(impl={:#?})", impl_datum);
+
+        let impl_allowed: Goal = impl_datum
+            .binders
+            .map_ref(|bound_impl| {
+                // Ignoring the polarization of the impl's polarized trait ref
+                DomainGoal::LocalImplAllowed(bound_impl.trait_ref.trait_ref().clone())
+            })
+            .cast();
+
+        let canonical_goal = &impl_allowed.into_closed_goal();
+        let result = self
+            .solver_choice
+            .solve_any_root_goal(&self.env, canonical_goal)
+            .unwrap()
+            .is_some();
+        debug!("overlaps: result = {:?}", result);
+        result
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::CoherenceError;
+use crate::rust_ir::*;
+use chalk_ir::cast::*;
+use chalk_ir::fold::shift::Shift;
+use chalk_ir::*;
+use chalk_solve::ext::*;
+use chalk_solve::solve::{Solution, SolverChoice};
+use failure::Fallible;
+use itertools::Itertools;
+
+struct DisjointSolver {
+    env: Arc<ProgramEnvironment>,
+    solver_choice: SolverChoice,
+}
+
+/// What the coherence check found out about a pair of impls of the same trait.
+enum Overlap {
+    Disjoint,
+    Specializes { less_special: ItemId, more_special: ItemId },
+    Conflict { intersection: Option<TraitRef> },
+}
+
+/// Checks every pair of impls of the same trait, or only those that
+/// involve one of `checked_impls` if given, and reports the specializations.
+pub(super) fn visit_specializations<F>(
+    program: &Arc<Program>,
+    env: Arc<ProgramEnvironment>,
+    solver_choice: SolverChoice,
+    checked_impls: Option<&BTreeSet<ItemId>>,
+    mut record_specialization: F,
+) -> Fallible<()>
+where
+    F: FnMut(ItemId, ItemId),
+{
+    let solver = DisjointSolver { env, solver_choice };
+
+    // Create a vector of references to impl datums, sorted by trait ref.
+    let mut impl_data: Vec<_> = program
+        .impl_data
+        .iter()
+        .filter(|&(_, impl_datum)| {
+            // Ignore impls for marker traits as they are allowed to overlap.
+            let trait_id = impl_datum.binders.value.trait_ref.trait_ref().trait_id;
+            let trait_datum = &program.trait_data[&trait_id];
+            !trait_datum.binders.value.flags.marker
+        })
+        .collect();
+    // The sort is stable, so the impls of a trait stay shuffled (if the
+    // solver shuffles clauses).
+    solver_choice.clause_order().shuffle(&mut impl_data, &"overlap check");
+    impl_data.sort_by_key(|&(_, impl_datum)| {
+        impl_datum.binders.value.trait_ref.trait_ref().trait_id
+    });
+
+    // Group impls by trait.
+    let impl_groupings = impl_data
+        .into_iter()
+        .group_by(|&(_, impl_datum)| impl_datum.binders.value.trait_ref.trait_ref().trait_id);
+
+    // Collect every pair of impls for the same trait.
+    let mut pairs = vec![];
+    for (trait_id, impls) in &impl_groupings {
+        let impls: Vec<(&ItemId, &ImplDatum)> = impls.collect();
+
+        for ((&l_id, lhs), (&r_id, rhs)) in impls.into_iter().tuple_combinations() {
+            // Two negative impls never overlap.
+            if !lhs.binders.value.trait_ref.is_positive()
+                && !rhs.binders.value.trait_ref.is_positive()
+            {
+                continue;
+            }
+
+            if let Some(checked_impls) = checked_impls {
+                if !checked_impls.contains(&l_id) && !checked_impls.contains(&r_id) {
+                    continue;
+                }
+            }
+
+            pairs.push((trait_id, l_id, r_id));
+        }
+    }
+
+    // Every pair is checked with its own solver queries, so we check them all in parallel.
+    let num_threads = solver_choice.num_threads();
+    let shared_program = program.clone();
+    let overlaps = tls::map_in_parallel(pairs.clone(), num_threads, move |&(_, l_id, r_id)| {
+        let program = &shared_program;
+        let lhs = &program.impl_data[&l_id];
+        let rhs = &program.impl_data[&r_id];
+
+        // Check if the impls overlap, then if they do, check if one specializes
+        // the other. Note that specialization can only run one way - if both
+        // specialization checks return *either* true or false, that's an error.
+        if solver.disjoint(lhs, rhs) {
+            return Overlap::Disjoint;
+        }
+        match (solver.specializes(lhs, rhs), solver.specializes(rhs, lhs)) {
+            (true, false) => Overlap::Specializes {
+                less_special: l_id,
+                more_special: r_id,
+            },
+            (false, true) => Overlap::Specializes {
+                less_special: r_id,
+                more_special: l_id,
+            },
+            (_, _) => Overlap::Conflict {
+                intersection: solver.intersection(lhs, rhs),
+            },
+        }
+    });
+
+    for (&(trait_id, l_id, r_id), overlap) in pairs.iter().zip(overlaps) {
+        match overlap {
+            Overlap::Disjoint => {}
+            Overlap::Specializes {
+                less_special,
+                more_special,
+            } => record_specialization(less_special, more_special),
+            Overlap::Conflict { intersection } => {
+                let trait_name = program.type_kinds.get(&trait_id).unwrap().name;
+                let impls = (l_id, r_id);
+                let error = match intersection {
+                    Some(trait_ref) => CoherenceError::OverlappingImplsFor {
+                        trait_name,
+                        impls,
+                        intersection: describe_intersection(&trait_ref),
+                    },
+                    None => CoherenceError::OverlappingImpls { trait_name, impls },
+                };
+                Err(error)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl DisjointSolver {
+    // Test if the set of types that these two impls apply to overlap. If the test succeeds, these
+    // two impls are disjoint.
+    //
+    // We combine the binders of the two impls & treat them as existential quantifiers. Then we
+    // attempt to unify the input types to the trait provided by each impl, as well as prove that
+    // the where clauses from both impls all hold. At the end, we apply the `compatible` modality
+    // and negate the query. Negating the query means that we are asking chalk to prove that no
+    // such overlapping impl exists. By applying `compatible { G }`, chalk attempts to prove that
+    // "there exists a compatible world where G is provable." When we negate compatible, it turns
+    // into the statement "for all compatible worlds, G is not provable." This is exactly what we
+    // want since we want to ensure that there is no overlap in *all* compatible worlds, not just
+    // that there is no overlap in *some* compatible world.
+    //
+    // Examples:
+    //
+    //  Impls:
+    //      impl<T> Foo for T { }
+    //      impl Foo for i32 { }
+    //  Generates:
+    //      not { compatible { exists<T> { T = i32 } } }
+    //
+    //  Impls:
+    //      impl<T1, U> Foo<T1> for Vec<U> { }
+    //      impl<T2> Foo<T2> for Vec<i32> { }
+    //  Generates:
+    //      not { compatible { exists<T1, U, T2> { Vec<U> = Vec<i32>, T1 = T2 } } }
+    //
+    //  Impls:
+    //      impl<T> Foo for Vec<T> where T: Bar { }
+    //      impl<U> Foo for Vec<U> where U: Baz { }
+    //  Generates:
+    //      not { compatible { exists<T, U> { Vec<T> = Vec<U>, T: Bar, U: Baz } } }
+    //
+    fn disjoint(&self, lhs: &ImplDatum, rhs: &ImplDatum) -> bool {
+        debug_heading!("overlaps(lhs={:#?}, rhs={:#?})", lhs, rhs);
+
+        let (binders, goal) = overlap_goal(lhs, rhs);
+        let goal = goal
+            .quantify(QuantifierKind::Exists, binders)
+            .compatible()
+            .negate();
+
+        let canonical_goal = &goal.into_closed_goal();
+        let solution = self
+            .solver_choice
+            .solve_root_goal(&self.env, canonical_goal)
+            .unwrap(); // internal errors in the solver are fatal
+        let result = match solution {
+            // Goal was proven with a unique solution, so no impl was found that causes these two
+            // to overlap
+            Some(Solution::Unique(_)) => true,
+            // Goal was ambiguous, so there *may* be overlap
+            Some(Solution::Ambig(_)) |
+            Some(Solution::MoreAnswers(_)) |
+            Some(Solution::Conditional(_)) |
+            // Goal cannot be proven, so there is some impl that causes overlap
+            None => false,
+        };
+        debug!("overlaps: result = {:?}", result);
+        result
+    }
+
+    // Find a trait reference that both impls apply to in this crate, to report along with an
+    // overlap. We solve the goal of `disjoint` without the `compatible` modality or the
+    // negation, with the binders of both impls as the variables of the query. We also add a
+    // variable for each input type of the trait, equal to the input types of both impls, so that
+    // the answer gives their normalized values.
+    //
+    // Example:
+    //
+    //  Impls:
+    //      impl<T> Foo for T { }
+    //      impl Foo for i32 { }
+    //  Solves:
+    //      exists<T, X> { T = i32, X = T, X = i32 }
+    //  Returns:
+    //      i32 as Foo
+    //
+    // Returns `None` if the impls only overlap in some other compatible world, or if the first
+    // answer found is ambiguous or not fully known.
+    fn intersection(&self, lhs: &ImplDatum, rhs: &ImplDatum) -> Option<TraitRef> {
+        debug_heading!("intersection(lhs={:#?}, rhs={:#?})", lhs, rhs);
+
+        let lhs_len = lhs.binders.len();
+        let (mut binders, goal) = overlap_goal(lhs, rhs);
+
+        let first_input = binders.len();
+        let inputs: Vec<Parameter> = params(lhs)
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let depth = DebruijnIndex::new(first_input + i);
+                match param {
+                    ParameterKind::Ty(_) => ParameterKind::Ty(depth.to_ty()),
+                    ParameterKind::Lifetime(_) => {
+                        ParameterKind::Lifetime(Lifetime::BoundVar(depth))
+                    }
+                }
+            })
+            .collect();
+        binders.extend(inputs.iter().map(|input| match input {
+            ParameterKind::Ty(_) => ParameterKind::Ty(()),
+            ParameterKind::Lifetime(_) => ParameterKind::Lifetime(()),
+        }));
+
+        let lhs_params = params(lhs).iter().cloned();
+        let rhs_params = params(rhs).iter().map(|param| param.shifted_in(lhs_len));
+        let goal = inputs
+            .iter()
+            .zip(lhs_params)
+            .chain(inputs.iter().zip(rhs_params))
+            .map(|(input, b)| Goal::Leaf(LeafGoal::EqGoal(EqGoal { a: input.clone(), b })))
+            .fold(goal, |goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)));
+
+        let canonical_goal = UCanonical {
+            canonical: Canonical {
+                value: InEnvironment::new(&Environment::new(), goal),
+                binders: binders
+                    .into_iter()
+                    .map(|kind| kind.map(|()| UniverseIndex::root()))
+                    .collect(),
+            },
+            universes: 1,
+        };
+        let solution = self
+            .solver_choice
+            .solve_any_root_goal(&self.env, &canonical_goal)
+            .unwrap()?;
+        debug!("intersection: solution = {:?}", solution.subst);
+        if solution.ambiguous || !solution.subst.binders.is_empty() {
+            return None;
+        }
+
+        let parameters = &solution.subst.value.subst.parameters;
+        Some(TraitRef {
+            trait_id: lhs.binders.value.trait_ref.trait_ref().trait_id,
+            parameters: parameters[first_input..].iter().cloned().collect(),
+        })
+    }
+
+    // Test for specialization.
+    //
+    // If this test suceeds, the second impl specializes the first.
+    //
+    // Example lowering:
+    //
+    // more: impl<T: Clone> Foo for Vec<T>
+    // less: impl<U: Clone> Foo for U
+    //
+    // forall<T> {
+    //  if (T: Clone) {
+    //    exists<U> {
+    //      Vec<T> = U, U: Clone
+    //    }
+    //  }
+    // }
+    fn specializes(&self, less_special: &ImplDatum, more_special: &ImplDatum) -> bool {
+        debug_heading!(
+            "specializes(less_special={:#?}, more_special={:#?})",
+            less_special,
+            more_special
+        );
+
+        // Negative impls cannot specialize.
+        if !less_special.binders.value.trait_ref.is_positive()
+            || !more_special.binders.value.trait_ref.is_positive()
+        {
+            return false;
+        }
+
+        let less_len = less_special.binders.len();
+
+        // Create parameter equality goals. The parameters of the less
+        // special impl are bound by the innermost binder.
+        let more_special_params = params(more_special).iter().map(|p| p.shifted_in(less_len));
+        let less_special_params = params(less_special).iter().cloned();
+        let params_goals = more_special_params
+            .zip(less_special_params)
+            .map(|(a, b)| Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })));
+
+        // Create the where clause goals.
+        let more_special_wc = more_special
+            .binders
+            .value
+            .where_clauses
+            .iter()
+            .cloned()
+            .casted()
+            .collect();
+        let less_special_wc = less_special
+            .binders
+            .value
+            .where_clauses
+            .iter()
+            .cloned()
+            .casted();
+
+        // Join all of the goals together.
+        let goal = params_goals
+            .chain(less_special_wc)
+            .fold1(|goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)))
+            .expect("Every trait takes at least one input type")
+            .quantify(QuantifierKind::Exists, less_special.binders.binders.clone())
+            .implied_by(more_special_wc)
+            .quantify(QuantifierKind::ForAll, more_special.binders.binders.clone());
+
+        let canonical_goal = &goal.into_closed_goal();
+        let result = match self
+            .solver_choice
+            .solve_root_goal(&self.env, canonical_goal)
+            .unwrap()
+        {
+            Some(sol) => sol.is_unique(),
+            None => false,
+        };
+
+        debug!("specializes: result = {:?}", result);
+
+        result
+    }
+}
+
+// The conjunction of the goals that hold when both impls apply: the input types of the trait
+// unify and the where clauses of both impls hold. The variables of the goal are the binders of
+// the two impls joined together, which are returned along with it.
+fn overlap_goal(lhs: &ImplDatum, rhs: &ImplDatum) -> (Vec<ParameterKind<()>>, Goal) {
+    let lhs_len = lhs.binders.len();
+
+    // Join the two impls' binders together
+    let mut binders = lhs.binders.binders.clone();
+    binders.extend(rhs.binders.binders.clone());
+
+    // Upshift the rhs variables in params to account for the joined binders
+    let lhs_params = params(lhs).iter().cloned();
+    let rhs_params = params(rhs).iter().map(|param| param.shifted_in(lhs_len));
+
+    // Create an equality goal for every input type the trait, attempting
+    // to unify the inputs to both impls with one another
+    let params_goals = lhs_params
+        .zip(rhs_params)
+        .map(|(a, b)| Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })));
+
+    // Upshift the rhs variables in where clauses
+    let lhs_where_clauses = lhs.binders.value.where_clauses.iter().cloned();
+    let rhs_where_clauses = rhs
+        .binders
+        .value
+        .where_clauses
+        .iter()
+        .map(|wc| wc.shifted_in(lhs_len));
+
+    // Create a goal for each clause in both where clauses
+    let wc_goals = lhs_where_clauses
+        .chain(rhs_where_clauses)
+        .map(|wc| wc.cast());
+
+    // Join all the goals we've created together with And.
+    let goal = params_goals
+        .chain(wc_goals)
+        .fold1(|goal, leaf| Goal::And(Box::new(goal), Box::new(leaf)))
+        .expect("Every trait takes at least one input type");
+
+    (binders, goal)
+}
+
+// Traits without parameters other than `Self` apply to types, which we name on their own.
+fn describe_intersection(trait_ref: &TraitRef) -> String {
+    if trait_ref.parameters.len() == 1 {
+        format!("{:?}", trait_ref.parameters[0])
+    } else {
+        format!("{:?}", trait_ref)
+    }
+}
+
+fn params(impl_datum: &ImplDatum) -> &[Parameter] {
+    &impl_datum.binders.value.trait_ref.trait_ref().parameters
+}
@@ -1,6 +1,8 @@
 #![cfg(test)]
 
 use crate::test_util::*;
+use chalk_ir::ItemId;
+use chalk_solve::solve::SolverChoice;
 
 #[test]
 fn two_impls_for_same_type() {
@@ -12,7 +14,7 @@ fn two_impls_for_same_type() {
             impl Foo for Bar { }
         }
         error_msg {
-            "overlapping impls of trait \"Foo\""
+            "overlapping impls of trait \"Foo\": both apply to `Bar`"
         }
     }
 }
@@ -42,6 +44,52 @@ fn concrete_impl_and_blanket_impl() {
     }
 }
 
+#[test]
+fn specialization_relation() {
+    let program = parse_and_lower_program(
+        "
+            trait Foo { }
+            struct Vec<T> { }
+            struct Bar { }
+            impl<T> Foo for T { }
+            impl<T> Foo for Vec<T> { }
+            impl Foo for Vec<Bar> { }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+
+    // Impls are identified by their trait reference, in which their
+    // parameters are numbered.
+    let blanket = ItemId::from_path("impl Foo for ^0", 0);
+    let vec = ItemId::from_path("impl Foo for Vec<^0>", 0);
+    let vec_bar = ItemId::from_path("impl Foo for Vec<Bar>", 0);
+    let impls: Vec<_> = program.impl_data.keys().cloned().collect();
+    assert_eq!(impls.len(), 3);
+    assert!(impls.contains(&blanket) && impls.contains(&vec) && impls.contains(&vec_bar));
+
+    assert!(program.specializes(vec, blanket));
+    assert!(program.specializes(vec_bar, blanket));
+    assert!(program.specializes(vec_bar, vec));
+    assert!(!program.specializes(blanket, vec));
+    assert!(!program.specializes(vec, vec));
+    assert_eq!(program.specializations().len(), 3);
+
+    let priorities = program.specialization_priorities();
+    assert_eq!(priorities[&blanket], 0);
+    assert_eq!(priorities[&vec], 1);
+    assert!(priorities[&vec_bar] > 0);
+
+    let graph = program.specialization_graph();
+    assert_eq!(graph.roots().collect::<Vec<_>>(), vec![blanket]);
+    assert_eq!(graph.parent(blanket), None);
+    assert_eq!(graph.parent(vec), Some(blanket));
+    assert_eq!(graph.parent(vec_bar), Some(vec));
+    assert_eq!(graph.children(blanket), &[vec]);
+    assert_eq!(graph.children(vec), &[vec_bar]);
+    assert!(graph.children(vec_bar).is_empty());
+}
+
 #[test]
 fn two_blanket_impls() {
     lowering_error! {
@@ -56,7 +104,7 @@ fn two_blanket_impls() {
             impl Baz for Quux { }
         }
         error_msg {
-            "overlapping impls of trait \"Foo\""
+            "overlapping impls of trait \"Foo\": both apply to `Quux`"
         }
     }
 }
@@ -113,7 +161,7 @@ fn multiple_parameters() {
             impl<T> Foo<Baz> for T { }
             impl<T> Foo<T> for Baz { }
         } error_msg {
-            "overlapping impls of trait \"Foo\""
+            "overlapping impls of trait \"Foo\": both apply to `Baz as Foo<Baz>`"
         }
     }
 }
@@ -184,7 +232,7 @@ fn overlapping_assoc_types_error() {
 
             impl<A, B> Foo<A> for B where A: Bar { }
         } error_msg {
-            "overlapping impls of trait \"Foo\""
+            "overlapping impls of trait \"Foo\": both apply to `Vec<Other> as Foo<Other>`"
         }
     }
 }
@@ -199,7 +247,37 @@ fn overlapping_negative_positive_impls() {
             impl Send for i32 { }
             impl !Send for i32 { }
         } error_msg {
-            "overlapping impls of trait \"Send\""
+            "overlapping impls of trait \"Send\": both apply to `i32`"
+        }
+    }
+}
+
+#[test]
+fn upstream_negative_impls() {
+    lowering_error! {
+        program {
+            #[upstream] trait Bar { }
+            #[upstream] struct Baz { }
+            trait Foo { }
+
+            impl<T> Foo for T where T: Bar { }
+            impl Foo for Baz { }
+        } error_msg {
+            "overlapping impls of trait \"Foo\""
+        }
+    }
+
+    // The upstream crate promises that `Baz` will never implement `Bar`, so the impls cannot
+    // overlap in any compatible world.
+    lowering_success! {
+        program {
+            #[upstream] trait Bar { }
+            #[upstream] struct Baz { }
+            #[upstream] impl !Bar for Baz { }
+            trait Foo { }
+
+            impl<T> Foo for T where T: Bar { }
+            impl Foo for Baz { }
         }
     }
 }
@@ -289,6 +367,55 @@ fn fundamental_traits() {
     }
 }
 
+#[test]
+fn orphan_check_tuples_and_fn_ptrs() {
+    // Tuples and fn pointers are upstream, and not fundamental, so they do not
+    // make an impl local even if they contain local types.
+    lowering_error! {
+        program {
+            #[upstream] trait Remote { }
+            struct Local { }
+
+            impl Remote for (Local, Local) { }
+        } error_msg {
+            "impl for trait \"Remote\" violates the orphan rules"
+        }
+    }
+
+    lowering_error! {
+        program {
+            #[upstream] trait Remote { }
+            struct Local { }
+
+            impl Remote for fn(Local) -> Local { }
+        } error_msg {
+            "impl for trait \"Remote\" violates the orphan rules"
+        }
+    }
+
+    lowering_error! {
+        program {
+            #[upstream] trait Remote<T> { }
+            struct Local { }
+
+            impl<T> Remote<Local> for (T,) { }
+        } error_msg {
+            "impl for trait \"Remote\" violates the orphan rules"
+        }
+    }
+
+    lowering_success! {
+        program {
+            #[upstream] trait Remote<T> { }
+            struct Local { }
+
+            impl Remote<Local> for (Local,) { }
+            impl Remote<(Local,)> for Local { }
+            impl<T> Remote<fn(T)> for Local { }
+        }
+    }
+}
+
 #[test]
 fn orphan_check() {
     // These tests are largely adapted from the compile-fail coherence-*.rs tests from rustc
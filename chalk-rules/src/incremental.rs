@@ -0,0 +1,211 @@
+//! Lowering and checking a program again after some of its items were
+//! edited. Only the items that changed, and those that (transitively)
+//! mention them, are lowered and checked again; everything else is taken
+//! from the program as it was lowered before the edit.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use chalk_ir::visit::{Visit, Visitor};
+use chalk_ir::ItemId;
+use chalk_parse::ast::{self, Item};
+use chalk_solve::solve::SolverChoice;
+use failure::Fallible;
+
+use crate::coherence::orphan;
+use crate::rules::wf;
+use crate::rules::ClauseFamilies;
+use crate::rust_ir::lowering::{LoweringErrors, ProgramScope};
+use crate::rust_ir::Program;
+
+mod test;
+
+/// A program lowered and checked again by `relower`.
+pub struct Relowered {
+    /// The new program, checked the way `chalk`'s
+    /// `LoweringDatabase::checked_program` checks it.
+    pub program: Arc<Program>,
+
+    /// The items that were lowered and checked again: those that were
+    /// added, removed or changed, and those that mention them.
+    pub affected: BTreeSet<ItemId>,
+}
+
+/// Lowers and checks `new`, an edited version of the program `old`.
+/// `program` must be `old` as lowered and checked by `chalk`'s
+/// `LoweringDatabase`, with the same `solver_choice` and `families`.
+///
+/// Items are compared without their spans, so moving an item around in
+/// the text does not make it change. An edited custom clause may apply to
+/// any item, so it makes the whole program be lowered and checked again.
+pub fn relower(
+    program: &Program,
+    old: &ast::Program,
+    new: &ast::Program,
+    solver_choice: SolverChoice,
+    families: ClauseFamilies,
+) -> Fallible<Relowered> {
+    let old_scope = ProgramScope::new(old)?;
+    let new_scope = ProgramScope::new(new)?;
+    let old_items: BTreeMap<_, _> = old_scope.item_ids.iter().cloned().zip(&old.items).collect();
+    let new_items: BTreeMap<_, _> = new_scope.item_ids.iter().cloned().zip(&new.items).collect();
+
+    let mut changed: BTreeSet<ItemId> = old_items
+        .keys()
+        .chain(new_items.keys())
+        .filter(|&id| old_items.get(id) != new_items.get(id))
+        .cloned()
+        .collect();
+
+    // A trait whose associated types were given other ids has to be
+    // lowered again, even if it did not change itself.
+    changed.extend(
+        new_items
+            .keys()
+            .filter(|&&id| old_scope.associated_ty_ids(id) != new_scope.associated_ty_ids(id))
+            .cloned(),
+    );
+
+    let is_clause = |item: Option<&&Item>| match item {
+        Some(Item::Clause(_)) => true,
+        _ => false,
+    };
+    let clauses_changed = changed
+        .iter()
+        .any(|id| is_clause(old_items.get(id)) || is_clause(new_items.get(id)));
+
+    let mut affected = changed.clone();
+    if clauses_changed {
+        affected.extend(old_items.keys().chain(new_items.keys()));
+    }
+
+    // Adding or removing an impl changes what holds of its trait, so the
+    // items that mention the trait must be checked again.
+    for id in &changed {
+        if let Some(datum) = program.impl_data.get(id) {
+            affected.insert(datum.binders.value.trait_ref.trait_ref().trait_id);
+        }
+        if let Some(Item::Impl(impl_)) = new_items.get(id) {
+            affected.extend(new_scope.type_id(&impl_.trait_ref.trait_ref().trait_name));
+        }
+    }
+
+    let mentions: BTreeMap<ItemId, BTreeSet<ItemId>> = old_items
+        .keys()
+        .filter(|&id| new_items.contains_key(id))
+        .map(|&id| (id, mentioned_items(program, id)))
+        .collect();
+    loop {
+        let ids: Vec<ItemId> = affected.iter().cloned().collect();
+        for id in ids {
+            affected.extend(new_scope.associated_ty_ids(id));
+            affected.extend(
+                program
+                    .associated_ty_data
+                    .values()
+                    .filter(|datum| datum.trait_id == id)
+                    .map(|datum| datum.id),
+            );
+
+            // Like an impl that was edited, an impl that mentions an
+            // affected item may now hold for other types than before.
+            if let Some(datum) = program.impl_data.get(&id) {
+                affected.insert(datum.binders.value.trait_ref.trait_ref().trait_id);
+            }
+        }
+
+        let dependents: Vec<ItemId> = mentions
+            .iter()
+            .filter(|(id, mentioned)| !affected.contains(*id) && !mentioned.is_disjoint(&affected))
+            .map(|(&id, _)| id)
+            .collect();
+        if dependents.is_empty() {
+            break;
+        }
+        affected.extend(dependents);
+    }
+
+    let mut program = program.clone();
+    new_scope.set_types(&mut program);
+    let kept = |id: &ItemId| new_items.contains_key(id) && !affected.contains(id);
+    program.struct_data.retain(|id, _| kept(id));
+    program.trait_data.retain(|id, _| kept(id));
+    program.impl_data.retain(|id, _| kept(id));
+    program
+        .associated_ty_data
+        .retain(|_, datum| kept(&datum.trait_id));
+    program.lang_items.retain(|_, id| kept(id));
+    if clauses_changed {
+        program.custom_clauses.clear();
+    }
+
+    let mut errors = vec![];
+    for (&id, item) in &new_items {
+        let relower = match item {
+            Item::Clause(_) => clauses_changed,
+            _ => affected.contains(&id),
+        };
+        if relower {
+            if let Err(error) = new_scope.lower_item(item, id, &mut program) {
+                errors.push(error);
+            }
+        }
+    }
+    LoweringErrors::check(errors)?;
+
+    let mut env = program.environment(families);
+    solver_choice.clause_order().shuffle_program_clauses(&mut env);
+    let env = Arc::new(env);
+    program.recheck_specialization_priorities(env.clone(), solver_choice, &affected)?;
+    let program = Arc::new(program);
+    orphan::perform_orphan_check_of(program.clone(), env.clone(), solver_choice, &affected)?;
+    wf::verify_well_formedness_of(program.clone(), env, solver_choice, &affected)?;
+
+    Ok(Relowered { program, affected })
+}
+
+/// The items that the lowered item `id` mentions, besides itself. The
+/// associated types of a trait count as part of the trait.
+fn mentioned_items(program: &Program, id: ItemId) -> BTreeSet<ItemId> {
+    let mut collector = ItemCollector::default();
+    {
+        let visitor: &mut dyn Visitor = &mut collector;
+        if let Some(datum) = program.struct_data.get(&id) {
+            datum.binders.value.fields.visit_with(visitor, 0);
+            datum.binders.value.where_clauses.visit_with(visitor, 0);
+        }
+        if let Some(datum) = program.trait_data.get(&id) {
+            datum.binders.value.where_clauses.visit_with(visitor, 0);
+            for datum in program.associated_ty_data.values() {
+                if datum.trait_id == id {
+                    datum.bounds_on_self().visit_with(visitor, 0);
+                    datum.where_clauses.visit_with(visitor, 0);
+                }
+            }
+        }
+        if let Some(datum) = program.impl_data.get(&id) {
+            let bound = &datum.binders.value;
+            bound.trait_ref.trait_ref().visit_with(visitor, 0);
+            bound.where_clauses.visit_with(visitor, 0);
+            for value in &bound.associated_ty_values {
+                visitor.visit_item_id(value.associated_ty_id);
+                value.value.value.ty.visit_with(visitor, 0);
+            }
+        }
+    }
+
+    let mut mentioned = collector.items;
+    mentioned.remove(&id);
+    mentioned
+}
+
+#[derive(Default)]
+struct ItemCollector {
+    items: BTreeSet<ItemId>,
+}
+
+impl Visitor for ItemCollector {
+    fn visit_item_id(&mut self, item_id: ItemId) {
+        self.items.insert(item_id);
+    }
+}
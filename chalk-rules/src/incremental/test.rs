@@ -0,0 +1,134 @@
+#![cfg(test)]
+
+use super::relower;
+use crate::rules::ClauseFamilies;
+use crate::test_util::*;
+use chalk_ir::ItemId;
+use chalk_solve::solve::SolverChoice;
+use std::collections::BTreeSet;
+
+const OLD: &str = "
+    trait Clone { }
+    struct Foo { }
+    struct Bar { }
+    struct Unrelated { }
+    impl Clone for Foo { }
+    struct Wrapper<T> where T: Clone { }
+    struct Uses { field: Wrapper<Foo> }
+";
+
+fn relower_text(new: &str) -> Result<super::Relowered, String> {
+    relower_edit(OLD, new)
+}
+
+fn relower_edit(old: &str, new: &str) -> Result<super::Relowered, String> {
+    let solver_choice = SolverChoice::default();
+    let program = parse_and_lower_program(old, solver_choice).unwrap();
+    let old = chalk_parse::parse_program(old).unwrap();
+    let new = chalk_parse::parse_program(new).unwrap();
+    relower(&program, &old, &new, solver_choice, ClauseFamilies::default())
+        .map_err(|err| err.to_string())
+}
+
+#[test]
+fn relower_affected_items() {
+    let new = "
+        struct Unrelated { }
+        trait Clone { }
+        struct Foo { }
+        struct Bar { }
+        struct Baz { }
+        impl Clone for Foo { }
+        impl Clone for Bar { }
+        struct Wrapper<T> where T: Clone { }
+        struct Uses { field: Wrapper<Foo> }
+    ";
+    let relowered = relower_text(new).unwrap();
+    let expected = parse_and_lower_program(new, SolverChoice::default()).unwrap();
+    assert_eq!(relowered.program, expected);
+
+    // `Foo`, `Bar` and `Unrelated` do not mention anything that changed,
+    // and moving `Unrelated` around does not change it.
+    let affected: BTreeSet<_> = [
+        "Clone",
+        "Baz",
+        "Wrapper",
+        "Uses",
+        "impl Clone for Foo",
+        "impl Clone for Bar",
+    ]
+    .iter()
+    .map(|path| ItemId::from_path(path, 0))
+    .collect();
+    assert_eq!(relowered.affected, affected);
+}
+
+#[test]
+fn relower_unchanged() {
+    let relowered = relower_text(OLD).unwrap();
+    assert!(relowered.affected.is_empty());
+    assert_eq!(
+        relowered.program,
+        parse_and_lower_program(OLD, SolverChoice::default()).unwrap()
+    );
+}
+
+#[test]
+fn relower_checks_dependents() {
+    // Removing the impl breaks `Uses`, which only mentions `Wrapper`.
+    let new = "
+        trait Clone { }
+        struct Foo { }
+        struct Bar { }
+        struct Unrelated { }
+        struct Wrapper<T> where T: Clone { }
+        struct Uses { field: Wrapper<Foo> }
+    ";
+    let expected = parse_and_lower_program(new, SolverChoice::default()).unwrap_err();
+    assert_eq!(relower_text(new).err(), Some(expected));
+}
+
+#[test]
+fn relower_checks_dependents_of_dependent_impls() {
+    // Removing the impl of `Copy` makes the impl of `Clone` (which only
+    // mentions `Copy`) no longer hold, which breaks `Uses`.
+    let old = "
+        trait Copy { }
+        trait Clone { }
+        struct Foo { }
+        impl Copy for Foo { }
+        impl Clone for Foo where Foo: Copy { }
+        struct Wrapper<T> where T: Clone { }
+        struct Uses { field: Wrapper<Foo> }
+    ";
+    let new = "
+        trait Copy { }
+        trait Clone { }
+        struct Foo { }
+        impl Clone for Foo where Foo: Copy { }
+        struct Wrapper<T> where T: Clone { }
+        struct Uses { field: Wrapper<Foo> }
+    ";
+    let expected = parse_and_lower_program(new, SolverChoice::default()).unwrap_err();
+    assert_eq!(relower_edit(old, new).err(), Some(expected));
+}
+
+#[test]
+fn relower_custom_clauses() {
+    let new = "
+        trait Clone { }
+        struct Foo { }
+        struct Bar { }
+        struct Unrelated { }
+        impl Clone for Foo { }
+        struct Wrapper<T> where T: Clone { }
+        struct Uses { field: Wrapper<Foo> }
+        forall<T> { Bar: Clone }
+    ";
+    let relowered = relower_text(new).unwrap();
+    assert_eq!(
+        relowered.program,
+        parse_and_lower_program(new, SolverChoice::default()).unwrap()
+    );
+    assert_eq!(relowered.affected.len(), 8);
+}
@@ -0,0 +1,30 @@
+#![feature(crate_visibility_modifier)]
+#![feature(in_band_lifetimes)]
+#![feature(try_blocks)]
+
+extern crate chalk_parse;
+#[macro_use]
+extern crate chalk_macros;
+extern crate chalk_derive;
+extern crate chalk_engine;
+extern crate chalk_ir;
+extern crate chalk_solve;
+#[macro_use]
+extern crate failure;
+extern crate itertools;
+extern crate petgraph;
+#[cfg(test)]
+extern crate regex;
+extern crate rustc_hash;
+
+#[macro_use]
+mod test_util;
+
+pub mod coherence;
+pub mod incremental;
+pub mod rules;
+pub mod rust_ir;
+
+pub use crate::coherence::{CoherenceError, SpecializationGraph};
+pub use crate::rules::supertraits::SupertraitCycle;
+pub use crate::rules::wf::{WfError, WfErrors};
@@ -6,10 +6,39 @@ use chalk_ir::*;
 use std::iter;
 
 mod default;
-crate mod wf;
+pub mod supertraits;
+pub mod wf;
+
+/// Which families of derived clauses `Program::environment` generates.
+/// Every family is needed for the solver to be complete, but turning
+/// one off shows how much it costs and which goals depend on it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClauseFamilies {
+    /// The reverse rules giving the implied bounds of types, traits and
+    /// associated types, e.g. `FromEnv(T: Eq) :- FromEnv(T: Ord)`.
+    pub implied_bounds: bool,
+
+    /// The rules decomposing `WellFormed` goals on types, traits and
+    /// associated types into the well-formedness of their where clauses.
+    pub well_formed: bool,
+
+    /// The "fallback" rule equating each projection with its
+    /// placeholder type, e.g. `ProjectionEq(<T as Foo>::Assoc = (Foo::Assoc)<T>)`.
+    pub projection_fallback: bool,
+}
+
+impl Default for ClauseFamilies {
+    fn default() -> Self {
+        ClauseFamilies {
+            implied_bounds: true,
+            well_formed: true,
+            projection_fallback: true,
+        }
+    }
+}
 
 impl Program {
-    pub fn environment(&self) -> ProgramEnvironment {
+    pub fn environment(&self, families: ClauseFamilies) -> ProgramEnvironment {
         // Construct the set of *clauses*; these are sort of a compiled form
         // of the data above that always has the form:
         //
@@ -19,22 +48,19 @@ impl Program {
             .iter()
             .cloned()
             .chain(
-                self.struct_data
-                    .values()
-                    .flat_map(|d| d.to_program_clauses()),
+                self.in_declaration_order(&self.struct_data)
+                    .flat_map(|d| d.to_program_clauses(families)),
             )
             .chain(
-                self.trait_data
-                    .values()
-                    .flat_map(|d| d.to_program_clauses()),
+                self.in_declaration_order(&self.trait_data)
+                    .flat_map(|d| d.to_program_clauses(self, families)),
             )
             .chain(
-                self.associated_ty_data
-                    .values()
-                    .flat_map(|d| d.to_program_clauses(self)),
+                self.in_declaration_order(&self.associated_ty_data)
+                    .flat_map(|d| d.to_program_clauses(self, families)),
             )
-            .chain(self.default_impl_data.iter().map(|d| d.to_program_clause()))
-            .collect::<Vec<_>>();
+            .chain(self.auto_trait_clauses())
+            .collect::<ProgramClauses>();
 
         // Adds clause that defines the Derefs domain goal:
         // forall<T, U> { Derefs(T, U) :- ProjectionEq(<T as Deref>::Target = U>) }
@@ -46,8 +72,8 @@ impl Program {
                 .find(|d| d.trait_id == *trait_id)
                 .expect("Deref has no assoc item")
                 .id;
-            let t = || Ty::BoundVar(0);
-            let u = || Ty::BoundVar(1);
+            let t = || DebruijnIndex::new(0).to_ty();
+            let u = || DebruijnIndex::new(1).to_ty();
             program_clauses.push(
                 Binders {
                     binders: vec![ParameterKind::Ty(()), ParameterKind::Ty(())],
@@ -56,7 +82,7 @@ impl Program {
                             source: t(),
                             target: u(),
                         }),
-                        conditions: vec![ProjectionEq {
+                        conditions: smallvec![ProjectionEq {
                             projection: ProjectionTy {
                                 associated_ty_id,
                                 parameters: vec![t().cast()],
@@ -65,12 +91,33 @@ impl Program {
                         }
                         .cast()],
                     },
+                    names: BinderNames::default(),
+                }
+                .cast(),
+            );
+        }
+
+        // Adds clause that connects the NeedsDrop domain goal to the drop trait:
+        // forall<T> { NeedsDrop(T) :- Implemented(T: Drop) }
+        if let Some(&trait_id) = self.lang_items.get(&LangItem::DropTrait) {
+            program_clauses.push(
+                Binders {
+                    binders: vec![ParameterKind::Ty(())],
+                    value: ProgramClauseImplication {
+                        consequence: DomainGoal::NeedsDrop(DebruijnIndex::INNERMOST.to_ty()),
+                        conditions: smallvec![TraitRef {
+                            trait_id,
+                            parameters: smallvec![DebruijnIndex::INNERMOST.to_ty().cast()],
+                        }
+                        .cast()],
+                    },
+                    names: BinderNames::default(),
                 }
                 .cast(),
             );
         }
 
-        for datum in self.impl_data.values() {
+        for datum in self.in_declaration_order(&self.impl_data) {
             // If we encounter a negative impl, do not generate any rule. Negative impls
             // are currently just there to deactivate default impls for auto traits.
             if datum.binders.value.trait_ref.is_positive() {
@@ -90,7 +137,8 @@ impl Program {
             .trait_data
             .iter()
             .filter_map(|(&trait_id, trait_datum)| {
-                if trait_datum.binders.value.flags.auto {
+                let flags = &trait_datum.binders.value.flags;
+                if flags.auto || flags.coinductive {
                     Some(trait_id)
                 } else {
                     None
@@ -98,8 +146,46 @@ impl Program {
             })
             .collect();
 
+        // The impls of these traits (and of the auto traits) for tuples and
+        // fn pointers are produced for each goal, see
+        // `ProgramEnvironment::builtin_clauses`.
+        let auto_traits = self
+            .trait_data
+            .iter()
+            .filter(|(_, trait_datum)| trait_datum.binders.value.flags.auto)
+            .map(|(&trait_id, _)| (trait_id, BuiltinTrait::Auto));
+        let builtin_traits = [
+            (LangItem::SizedTrait, BuiltinTrait::Sized),
+            (LangItem::CopyTrait, BuiltinTrait::Copy),
+            (LangItem::CloneTrait, BuiltinTrait::Clone),
+            (LangItem::FnOnceTrait, BuiltinTrait::FnOnce),
+            (LangItem::FnMutTrait, BuiltinTrait::FnMut),
+            (LangItem::FnTrait, BuiltinTrait::Fn),
+        ]
+        .iter()
+        .filter_map(|(lang_item, builtin_trait)| {
+            self.lang_items
+                .get(lang_item)
+                .map(|&trait_id| (trait_id, *builtin_trait))
+        })
+        .chain(auto_traits)
+        .collect();
+
+        // Find `FnOnce::Output`.
+        let fn_once_output = self
+            .lang_items
+            .get(&LangItem::FnOnceTrait)
+            .and_then(|trait_id| {
+                self.associated_ty_data
+                    .values()
+                    .find(|d| d.trait_id == *trait_id)
+            })
+            .map(|d| d.id);
+
         ProgramEnvironment {
             coinductive_traits,
+            builtin_traits,
+            fn_once_output,
             program_clauses,
         }
     }
@@ -114,7 +200,7 @@ impl ImplDatum {
     ///     Implemented(Vec<T>: Clone) :- Implemented(T: Clone).
     /// }
     /// ```
-    fn to_program_clause(&self) -> ProgramClause {
+    crate fn to_program_clause(&self) -> ProgramClause {
         self.binders
             .map_ref(|bound| ProgramClauseImplication {
                 consequence: bound.trait_ref.trait_ref().clone().cast(),
@@ -122,46 +208,31 @@ impl ImplDatum {
             })
             .cast()
     }
-}
 
-impl DefaultImplDatum {
-    /// For each accessible type `T` in a struct which needs a default implementation for the auto
-    /// trait `Foo` (accessible types are the struct fields types), we add a bound `T: Foo` (which
-    /// is then expanded with `WF(T: Foo)`). For example, given:
+    /// Given `impl<T> !Send for Vec<T> { }` and the trait reference `U: Send`, generate the
+    /// goal that holds if the impl applies to it:
     ///
     /// ```notrust
-    /// #[auto] trait Send { }
-    ///
-    /// struct MyList<T> {
-    ///     data: T,
-    ///     next: Box<Option<MyList<T>>>,
-    /// }
-    ///
+    /// exists<T> { U = Vec<T> }
     /// ```
     ///
-    /// generate:
-    ///
-    /// ```notrust
-    /// forall<T> {
-    ///     Implemented(MyList<T>: Send) :-
-    ///         Implemented(T: Send),
-    ///         Implemented(Box<Option<MyList<T>>>: Send).
-    /// }
-    /// ```
-    fn to_program_clause(&self) -> ProgramClause {
-        self.binders
-            .map_ref(|bound| ProgramClauseImplication {
-                consequence: bound.trait_ref.clone().cast(),
-                conditions: {
-                    let wc = bound.accessible_tys.iter().cloned().map(|ty| TraitRef {
-                        trait_id: bound.trait_ref.trait_id,
-                        parameters: vec![ParameterKind::Ty(ty)],
-                    });
-
-                    wc.casted().collect()
-                },
+    /// The where clauses of the impl are not part of the goal.
+    fn rules_out(&self, trait_ref: &TraitRef) -> Goal {
+        let num_binders = self.binders.len();
+        let impl_parameters = &self.binders.value.trait_ref.trait_ref().parameters;
+        let goals = trait_ref
+            .parameters
+            .iter()
+            .zip(impl_parameters)
+            .map(|(a, b)| {
+                EqGoal {
+                    a: a.shifted_in(num_binders),
+                    b: b.clone(),
+                }
+                .cast()
             })
-            .cast()
+            .collect();
+        Goal::All(goals).quantify(QuantifierKind::Exists, self.binders.binders.clone())
     }
 }
 
@@ -201,7 +272,11 @@ impl AssociatedTyValue {
     ///         Normalize(<Vec<T> as Iterable>::IntoIter<'a> -> Iter<'a, T>).
     /// }
     /// ```
-    fn to_program_clauses(&self, program: &Program, impl_datum: &ImplDatum) -> Vec<ProgramClause> {
+    crate fn to_program_clauses(
+        &self,
+        program: &Program,
+        impl_datum: &ImplDatum,
+    ) -> Vec<ProgramClause> {
         let associated_ty = &program.associated_ty_data[&self.associated_ty_id];
 
         // Begin with the innermost parameters (`'a`) and then add those from impl (`T`).
@@ -212,6 +287,7 @@ impl AssociatedTyValue {
             .chain(impl_datum.binders.binders.iter())
             .cloned()
             .collect();
+        let all_names = self.value.names.chain(&impl_datum.binders.names);
 
         let impl_trait_ref = impl_datum
             .binders
@@ -220,14 +296,10 @@ impl AssociatedTyValue {
             .trait_ref()
             .shifted_in(self.value.len());
 
-        let all_parameters: Vec<_> = self
-            .value
-            .binders
-            .iter()
-            .zip(0..)
-            .map(|p| p.to_parameter())
-            .chain(impl_trait_ref.parameters.iter().cloned())
-            .collect();
+        let all_parameters = SubstBuilder::new(associated_ty.parameter_kinds.anonymize())
+            .bound_vars(self.value.len())
+            .extend(impl_trait_ref.parameters.iter().cloned())
+            .into_parameters();
 
         // Assemble the full list of conditions for projection to be valid.
         // This comes in two parts, marked as (1) and (2) in doc above:
@@ -241,19 +313,19 @@ impl AssociatedTyValue {
             .map(|wc| Subst::apply(&all_parameters, wc))
             .casted();
 
-        let conditions: Vec<Goal> = where_clauses
+        let conditions: Conditions = where_clauses
             .chain(Some(impl_trait_ref.clone().cast()))
             .collect();
 
         // Bound parameters + `Self` type of the trait-ref
-        let parameters: Vec<_> = {
-            // First add refs to the bound parameters (`'a`, in above example)
-            let parameters = self.value.binders.iter().zip(0..).map(|p| p.to_parameter());
-
-            // Then add the `Self` type (`Vec<T>`, in above example)
-            parameters
-                .chain(Some(impl_trait_ref.parameters[0].clone()))
-                .collect()
+        let parameters = {
+            let binders = self.value.binders.iter().cloned();
+            SubstBuilder::new(binders.chain(Some(ParameterKind::Ty(()))))
+                // First add refs to the bound parameters (`'a`, in above example)
+                .bound_vars(self.value.len())
+                // Then add the `Self` type (`Vec<T>`, in above example)
+                .push(impl_trait_ref.parameters[0].clone())
+                .into_parameters()
         };
 
         let projection = ProjectionTy {
@@ -279,6 +351,7 @@ impl AssociatedTyValue {
                 consequence: normalize_goal.clone(),
                 conditions: conditions,
             },
+            names: all_names.clone(),
         }
         .cast();
 
@@ -294,11 +367,12 @@ impl AssociatedTyValue {
                     projection: unselected_projection,
                     ty: self.value.value.ty.clone(),
                 }),
-                conditions: vec![
+                conditions: smallvec![
                     normalize_goal.cast(),
                     DomainGoal::InScope(impl_trait_ref.trait_id).cast(),
                 ],
             },
+            names: all_names,
         }
         .cast();
 
@@ -307,6 +381,54 @@ impl AssociatedTyValue {
 }
 
 impl StructDatum {
+    /// The types that make up a value of this struct, on which its auto
+    /// traits depend: the types of its fields.
+    fn constituent_types(&self) -> &[Ty] {
+        &self.binders.value.fields
+    }
+
+    /// Given an auto trait `Send`, and the following type definition:
+    ///
+    /// ```notrust
+    /// struct MyList<T> {
+    ///     data: T,
+    ///     next: Box<Option<MyList<T>>>,
+    /// }
+    /// ```
+    ///
+    /// generate the rule implementing `Send` for `MyList<T>` whenever all of its
+    /// constituent types are `Send`:
+    ///
+    /// ```notrust
+    /// forall<T> {
+    ///     Implemented(MyList<T>: Send) :-
+    ///         Implemented(T: Send),
+    ///         Implemented(Box<Option<MyList<T>>>: Send).
+    /// }
+    /// ```
+    ///
+    /// A type like `MyList` contains itself, so proving `MyList<T>: Send` runs
+    /// into a cycle; auto traits are coinductive, so the cycle is a proof.
+    fn to_auto_trait_clause(&self, auto_trait_id: ItemId) -> ProgramClause {
+        let auto_trait_ref = |ty: Ty| TraitRef {
+            trait_id: auto_trait_id,
+            parameters: smallvec![ParameterKind::Ty(ty)],
+        };
+
+        self.binders
+            .map_ref(|bound_datum| ProgramClauseImplication {
+                consequence: auto_trait_ref(bound_datum.self_ty.clone().cast()).cast(),
+                conditions: self
+                    .constituent_types()
+                    .iter()
+                    .cloned()
+                    .map(auto_trait_ref)
+                    .casted()
+                    .collect(),
+            })
+            .cast()
+    }
+
     /// Given the following type definition: `struct Foo<T: Eq> { }`, generate:
     ///
     /// ```notrust
@@ -325,6 +447,18 @@ impl StructDatum {
     /// }
     /// ```
     ///
+    /// For the layout-flavored built-in goals, given `struct Foo<T> { a: u32, b: T }`,
+    /// we generate:
+    ///
+    /// ```notrust
+    /// // The last field determines sizedness.
+    /// forall<T> { IsSized(Foo<T>) :- IsSized(T). }
+    ///
+    /// // One rule per field.
+    /// forall<T> { NeedsDrop(Foo<T>) :- NeedsDrop(u32). }
+    /// forall<T> { NeedsDrop(Foo<T>) :- NeedsDrop(T). }
+    /// ```
+    ///
     /// If the type `Foo` is marked `#[upstream]`, we also generate:
     ///
     /// ```notrust
@@ -355,7 +489,9 @@ impl StructDatum {
     /// forall<T> { DownstreamType(Box<T>) :- DownstreamType(T). }
     /// ```
     ///
-    fn to_program_clauses(&self) -> Vec<ProgramClause> {
+    /// The first two rules are left out if `families` turns off the
+    /// well-formedness and implied bounds rules respectively.
+    crate fn to_program_clauses(&self, families: ClauseFamilies) -> Vec<ProgramClause> {
         let wf = self
             .binders
             .map_ref(|bound_datum| ProgramClauseImplication {
@@ -383,7 +519,32 @@ impl StructDatum {
             })
             .cast();
 
-        let mut clauses = vec![wf, is_fully_visible];
+        let is_sized = self
+            .binders
+            .map_ref(|bound_datum| ProgramClauseImplication {
+                consequence: DomainGoal::IsSized(bound_datum.self_ty.clone().cast()),
+                conditions: bound_datum
+                    .fields
+                    .last()
+                    .map(|ty| DomainGoal::IsSized(ty.clone()).cast())
+                    .into_iter()
+                    .collect(),
+            })
+            .cast();
+
+        let mut clauses = vec![is_fully_visible, is_sized];
+        if families.well_formed {
+            clauses.push(wf);
+        }
+
+        clauses.extend(self.binders.value.fields.iter().map(|field_ty| {
+            self.binders
+                .map_ref(|bound_datum| ProgramClauseImplication {
+                    consequence: DomainGoal::NeedsDrop(bound_datum.self_ty.clone().cast()),
+                    conditions: smallvec![DomainGoal::NeedsDrop(field_ty.clone()).cast()],
+                })
+                .cast()
+        }));
 
         // Fundamental types often have rules in the form of:
         //     Goal(FundamentalType<T>) :- Goal(T)
@@ -400,7 +561,7 @@ impl StructDatum {
 
                 clauses.push(self.binders.map_ref(|bound_datum| ProgramClauseImplication {
                     consequence: DomainGoal::$goal(bound_datum.self_ty.clone().cast()),
-                    conditions: vec![
+                    conditions: smallvec![
                     DomainGoal::$goal(
                         // This unwrap is safe because we asserted above for the presence of a type
                         // parameter
@@ -418,7 +579,7 @@ impl StructDatum {
                 .binders
                 .map_ref(|bound_datum| ProgramClauseImplication {
                     consequence: DomainGoal::IsLocal(bound_datum.self_ty.clone().cast()),
-                    conditions: Vec::new(),
+                    conditions: smallvec![],
                 })
                 .cast();
 
@@ -435,7 +596,7 @@ impl StructDatum {
                 .binders
                 .map_ref(|bound_datum| ProgramClauseImplication {
                     consequence: DomainGoal::IsUpstream(bound_datum.self_ty.clone().cast()),
-                    conditions: Vec::new(),
+                    conditions: smallvec![],
                 })
                 .cast();
 
@@ -446,6 +607,10 @@ impl StructDatum {
             fundamental_rule!(DownstreamType);
         }
 
+        if !families.implied_bounds {
+            return clauses;
+        }
+
         let condition = DomainGoal::FromEnv(FromEnv::Ty(self.binders.value.self_ty.clone().cast()));
 
         for wc in self
@@ -465,20 +630,13 @@ impl StructDatum {
             // `forall<'a, T> { FromEnv(T: Fn(&'a i32)) :- FromEnv(Foo<T>) }`
             //
             let shift = wc.binders.len();
-            clauses.push(
-                Binders {
-                    binders: wc
-                        .binders
-                        .into_iter()
-                        .chain(self.binders.binders.clone())
-                        .collect(),
-                    value: ProgramClauseImplication {
-                        consequence: wc.value,
-                        conditions: vec![condition.clone().shifted_in(shift).cast()],
-                    },
-                }
-                .cast(),
-            );
+            let clause = self.binders.map_ref(|_| {
+                wc.map(|consequence| ProgramClauseImplication {
+                    consequence,
+                    conditions: smallvec![condition.clone().shifted_in(shift).cast()],
+                })
+            });
+            clauses.push(clause.fuse().cast());
         }
 
         clauses
@@ -599,9 +757,46 @@ impl TraitDatum {
     /// To implement fundamental traits, we simply just do not add the rule above that allows
     /// upstream types to implement upstream traits. Fundamental traits are not allowed to
     /// compatibly do that.
-    fn to_program_clauses(&self) -> Vec<ProgramClause> {
+    ///
+    /// Negative impls are a similar promise: no crate may ever add the impls they rule out. So
+    /// for each negative impl of the trait, the rules for impls that may exist in a compatible
+    /// world get a condition that the trait reference is not one of those. For
+    /// `impl<X> !Foo<X, X, X> for Vec<X> { }`, the conditions above would include:
+    ///
+    /// ```notrust
+    /// not { exists<X> { Self = Vec<X>, T = X, U = X, V = X } }
+    /// ```
+    ///
+    /// Negative impls with where clauses are left out of this: the condition would have to
+    /// negate the where clauses, so the impl is conservatively assumed not to rule out anything.
+    ///
+    /// If `families` turns off the well-formedness or implied bounds rules,
+    /// the `WellFormed-TraitRef` or `Implied-Bound-From-Trait` rules are left
+    /// out. `Implemented-From-Env` is always generated: without it, the
+    /// hypotheses of `if` goals could not be used at all.
+    crate fn to_program_clauses(
+        &self,
+        program: &Program,
+        families: ClauseFamilies,
+    ) -> Vec<ProgramClause> {
         let trait_ref = self.binders.value.trait_ref.clone();
 
+        let negative_impls: Vec<_> = program
+            .in_declaration_order(&program.impl_data)
+            .filter(|impl_datum| {
+                let bound = &impl_datum.binders.value;
+                !bound.trait_ref.is_positive()
+                    && bound.trait_ref.trait_ref().trait_id == trait_ref.trait_id
+                    && bound.where_clauses.is_empty()
+            })
+            .collect();
+        let not_ruled_out = |trait_ref: &TraitRef| {
+            negative_impls
+                .iter()
+                .map(|impl_datum| impl_datum.rules_out(trait_ref).negate())
+                .collect::<Vec<_>>()
+        };
+
         let trait_ref_impl = WhereClause::Implemented(self.binders.value.trait_ref.clone());
 
         let wf = self
@@ -622,7 +817,10 @@ impl TraitDatum {
             })
             .cast();
 
-        let mut clauses = vec![wf];
+        let mut clauses = vec![];
+        if families.well_formed {
+            clauses.push(wf);
+        }
 
         // The number of parameters will always be at least 1 because of the Self parameter
         // that is automatically added to every trait. This is important because otherwise
@@ -649,6 +847,7 @@ impl TraitDatum {
                             .chain(iter::once(
                                 DomainGoal::DownstreamType(type_parameters[i].clone()).cast(),
                             ))
+                            .chain(not_ruled_out(&bound_datum.trait_ref))
                             .chain(iter::once(Goal::CannotProve(())))
                             .collect(),
                     })
@@ -662,7 +861,7 @@ impl TraitDatum {
                 .binders
                 .map_ref(|bound_datum| ProgramClauseImplication {
                     consequence: DomainGoal::LocalImplAllowed(bound_datum.trait_ref.clone()),
-                    conditions: Vec::new(),
+                    conditions: smallvec![],
                 })
                 .cast();
 
@@ -706,6 +905,7 @@ impl TraitDatum {
                                     .type_parameters()
                                     .map(|ty| DomainGoal::IsUpstream(ty).cast()),
                             )
+                            .chain(not_ruled_out(&bound_datum.trait_ref))
                             .chain(iter::once(Goal::CannotProve(())))
                             .collect(),
                     })
@@ -717,10 +917,14 @@ impl TraitDatum {
 
         let condition = DomainGoal::FromEnv(FromEnv::Trait(trait_ref.clone()));
 
+        let implied_bounds = if families.implied_bounds {
+            &self.binders.value.where_clauses[..]
+        } else {
+            &[]
+        };
+
         clauses.extend(
-            self.binders
-                .value
-                .where_clauses
+            implied_bounds
                 .iter()
                 .cloned()
                 .map(|wc| wc.map(|bound| bound.into_from_env_goal()))
@@ -728,19 +932,13 @@ impl TraitDatum {
                     // We move the binders of the where-clause to the left for the reverse rules,
                     // cf `StructDatum::to_program_clauses`.
                     let shift = wc.binders.len();
-
-                    Binders {
-                        binders: wc
-                            .binders
-                            .into_iter()
-                            .chain(self.binders.binders.clone())
-                            .collect(),
-                        value: ProgramClauseImplication {
-                            consequence: wc.value,
-                            conditions: vec![condition.clone().shifted_in(shift).cast()],
-                        },
-                    }
-                    .cast()
+                    let clause = self.binders.map_ref(|_| {
+                        wc.map(|consequence| ProgramClauseImplication {
+                            consequence,
+                            conditions: smallvec![condition.clone().shifted_in(shift).cast()],
+                        })
+                    });
+                    clause.fuse().cast()
                 }),
         );
 
@@ -748,7 +946,7 @@ impl TraitDatum {
             self.binders
                 .map_ref(|_| ProgramClauseImplication {
                     consequence: DomainGoal::Holds(trait_ref_impl),
-                    conditions: vec![condition.cast()],
+                    conditions: smallvec![condition.cast()],
                 })
                 .cast(),
         );
@@ -826,13 +1024,23 @@ impl AssociatedTyDatum {
     ///     FromEnv(Self: Foo) :- FromEnv((Foo::Assoc)<Self, 'a,T>).
     /// }
     /// ```
-    fn to_program_clauses(&self, program: &Program) -> Vec<ProgramClause> {
+    ///
+    /// Only `ProjectionEq-Normalize` is always generated: the other rules
+    /// each belong to one of the families that `families` can turn off.
+    crate fn to_program_clauses(
+        &self,
+        program: &Program,
+        families: ClauseFamilies,
+    ) -> Vec<ProgramClause> {
         let binders: Vec<_> = self
             .parameter_kinds
             .iter()
             .map(|pk| pk.map(|_| ()))
             .collect();
-        let parameters: Vec<_> = binders.iter().zip(0..).map(|p| p.to_parameter()).collect();
+        let names = self.parameter_kinds.names();
+        let parameters = SubstBuilder::new(binders.clone())
+            .fill_bound_vars()
+            .into_parameters();
         let projection = ProjectionTy {
             associated_ty_id: self.id,
             parameters: parameters.clone(),
@@ -843,7 +1051,7 @@ impl AssociatedTyDatum {
             let (associated_ty_data, trait_params, _) = program.split_projection(&projection);
             TraitRef {
                 trait_id: associated_ty_data.trait_id,
-                parameters: trait_params.to_owned(),
+                parameters: trait_params.into(),
             }
         };
 
@@ -853,7 +1061,7 @@ impl AssociatedTyDatum {
             name: TypeName::AssociatedType(self.id),
             parameters,
         };
-        let app_ty = Ty::Apply(app);
+        let app_ty = TyData::Apply(app).intern();
 
         let projection_eq = ProjectionEq {
             projection: projection.clone(),
@@ -862,116 +1070,128 @@ impl AssociatedTyDatum {
 
         let mut clauses = vec![];
 
-        // Fallback rule. The solver uses this to move between the projection
-        // and placeholder type.
-        //
-        //    forall<Self> {
-        //        ProjectionEq(<Self as Foo>::Assoc = (Foo::Assoc)<Self>).
-        //    }
-        clauses.push(
-            Binders {
-                binders: binders.clone(),
-                value: ProgramClauseImplication {
-                    consequence: projection_eq.clone().cast(),
-                    conditions: vec![],
-                },
-            }
-            .cast(),
-        );
+        if families.projection_fallback {
+            // Fallback rule. The solver uses this to move between the projection
+            // and placeholder type.
+            //
+            //    forall<Self> {
+            //        ProjectionEq(<Self as Foo>::Assoc = (Foo::Assoc)<Self>).
+            //    }
+            clauses.push(
+                Binders {
+                    binders: binders.clone(),
+                    value: ProgramClauseImplication {
+                        consequence: projection_eq.clone().cast(),
+                        conditions: smallvec![],
+                    },
+                    names: names.clone(),
+                }
+                .cast(),
+            );
+        }
 
-        // Well-formedness of projection type.
-        //
-        //    forall<Self> {
-        //        WellFormed((Foo::Assoc)<Self>) :- WellFormed(Self: Foo), WellFormed(WC).
-        //    }
-        clauses.push(
-            Binders {
-                binders: binders.clone(),
-                value: ProgramClauseImplication {
-                    consequence: WellFormed::Ty(app_ty.clone()).cast(),
-                    conditions: iter::once(WellFormed::Trait(trait_ref.clone()).cast())
-                        .chain(
-                            self.where_clauses
-                                .iter()
-                                .cloned()
-                                .map(|wc| wc.map(|bound| bound.into_well_formed_goal()))
-                                .casted(),
-                        )
-                        .collect(),
-                },
-            }
-            .cast(),
-        );
+        if families.well_formed {
+            // Well-formedness of projection type.
+            //
+            //    forall<Self> {
+            //        WellFormed((Foo::Assoc)<Self>) :- WellFormed(Self: Foo), WellFormed(WC).
+            //    }
+            clauses.push(
+                Binders {
+                    binders: binders.clone(),
+                    value: ProgramClauseImplication {
+                        consequence: WellFormed::Ty(app_ty.clone()).cast(),
+                        conditions: iter::once(WellFormed::Trait(trait_ref.clone()).cast())
+                            .chain(
+                                self.where_clauses
+                                    .iter()
+                                    .cloned()
+                                    .map(|wc| wc.map(|bound| bound.into_well_formed_goal()))
+                                    .casted(),
+                            )
+                            .collect(),
+                    },
+                    names: names.clone(),
+                }
+                .cast(),
+            );
+        }
 
-        // Assuming well-formedness of projection type means we can assume
-        // the trait ref as well. Mostly used in function bodies.
-        //
-        //    forall<Self> {
-        //        FromEnv(Self: Foo) :- FromEnv((Foo::Assoc)<Self>).
-        //    }
-        clauses.push(
-            Binders {
-                binders: binders.clone(),
-                value: ProgramClauseImplication {
-                    consequence: FromEnv::Trait(trait_ref.clone()).cast(),
-                    conditions: vec![FromEnv::Ty(app_ty.clone()).cast()],
-                },
-            }
-            .cast(),
-        );
+        if families.implied_bounds {
+            // Assuming well-formedness of projection type means we can assume
+            // the trait ref as well. Mostly used in function bodies.
+            //
+            //    forall<Self> {
+            //        FromEnv(Self: Foo) :- FromEnv((Foo::Assoc)<Self>).
+            //    }
+            clauses.push(
+                Binders {
+                    binders: binders.clone(),
+                    value: ProgramClauseImplication {
+                        consequence: FromEnv::Trait(trait_ref.clone()).cast(),
+                        conditions: smallvec![FromEnv::Ty(app_ty.clone()).cast()],
+                    },
+                    names: names.clone(),
+                }
+                .cast(),
+            );
 
-        // Reverse rule for where clauses.
-        //
-        //    forall<Self> {
-        //        FromEnv(WC) :- FromEnv((Foo::Assoc)<Self>).
-        //    }
-        //
-        // This is really a family of clauses, one for each where clause.
-        clauses.extend(self.where_clauses.iter().map(|wc| {
-            // Don't forget to move the binders to the left in case of higher-ranked where clauses.
-            let shift = wc.binders.len();
-            Binders {
-                binders: wc.binders.iter().chain(binders.iter()).cloned().collect(),
-                value: ProgramClauseImplication {
-                    consequence: wc.value.clone().into_from_env_goal(),
-                    conditions: vec![FromEnv::Ty(app_ty.clone()).shifted_in(shift).cast()],
-                },
-            }
-            .cast()
-        }));
+            // Reverse rule for where clauses.
+            //
+            //    forall<Self> {
+            //        FromEnv(WC) :- FromEnv((Foo::Assoc)<Self>).
+            //    }
+            //
+            // This is really a family of clauses, one for each where clause.
+            clauses.extend(self.where_clauses.iter().map(|wc| {
+                // Don't forget to move the binders to the left in case of higher-ranked
+                // where clauses.
+                let shift = wc.binders.len();
+                Binders {
+                    binders: wc.binders.iter().chain(binders.iter()).cloned().collect(),
+                    value: ProgramClauseImplication {
+                        consequence: wc.value.clone().into_from_env_goal(),
+                        conditions: smallvec![FromEnv::Ty(app_ty.clone()).shifted_in(shift).cast()],
+                    },
+                    names: wc.names.chain(&names),
+                }
+                .cast()
+            }));
 
-        // Reverse rule for implied bounds.
-        //
-        //    forall<Self> {
-        //        FromEnv(<Self as Foo>::Assoc: Bounds) :- FromEnv(Self: Foo), WC
-        //    }
-        clauses.extend(self.bounds_on_self().into_iter().map(|bound| {
-            // Same as above in case of higher-ranked inline bounds.
-            let shift = bound.binders.len();
-            let from_env_trait =
-                iter::once(FromEnv::Trait(trait_ref.clone()).shifted_in(shift).cast());
+            // Reverse rule for implied bounds.
+            //
+            //    forall<Self> {
+            //        FromEnv(<Self as Foo>::Assoc: Bounds) :- FromEnv(Self: Foo), WC
+            //    }
+            clauses.extend(self.bounds_on_self().into_iter().map(|bound| {
+                // Same as above in case of higher-ranked inline bounds.
+                let shift = bound.binders.len();
+                let from_env_trait =
+                    iter::once(FromEnv::Trait(trait_ref.clone()).shifted_in(shift).cast());
 
-            let where_clauses = self.where_clauses.iter().cloned().casted();
+                let where_clauses = self.where_clauses.iter().cloned().casted();
 
-            Binders {
-                binders: bound
-                    .binders
-                    .iter()
-                    .chain(binders.iter())
-                    .cloned()
-                    .collect(),
-                value: ProgramClauseImplication {
-                    consequence: bound.value.clone().into_from_env_goal(),
-                    conditions: from_env_trait.chain(where_clauses).collect(),
-                },
-            }
-            .cast()
-        }));
+                Binders {
+                    binders: bound
+                        .binders
+                        .iter()
+                        .chain(binders.iter())
+                        .cloned()
+                        .collect(),
+                    value: ProgramClauseImplication {
+                        consequence: bound.value.clone().into_from_env_goal(),
+                        conditions: from_env_trait.chain(where_clauses).collect(),
+                    },
+                    names: bound.names.chain(&names),
+                }
+                .cast()
+            }));
+        }
 
         // add new type parameter U
         let mut binders = binders;
         binders.push(ParameterKind::Ty(()));
-        let ty = Ty::BoundVar(binders.len() - 1);
+        let ty = DebruijnIndex::new(binders.len() - 1).to_ty();
 
         // `Normalize(<T as Foo>::Assoc -> U)`
         let normalize = Normalize {
@@ -996,8 +1216,9 @@ impl AssociatedTyDatum {
                 binders: binders.clone(),
                 value: ProgramClauseImplication {
                     consequence: projection_eq.clone().cast(),
-                    conditions: vec![normalize.clone().cast()],
+                    conditions: smallvec![normalize.clone().cast()],
                 },
+                names: BinderNames::default(),
             }
             .cast(),
         );
@@ -4,39 +4,37 @@ use chalk_ir::*;
 use chalk_solve::infer::InferenceTable;
 
 impl Program {
-    pub fn add_default_impls(&mut self) {
-        // For each auto trait `MyAutoTrait` and for each struct/type `MyStruct`
+    /// The rules implementing the auto traits structurally: for each auto trait `MyAutoTrait`
+    /// and each struct `MyStruct`, `MyStruct` implements `MyAutoTrait` if its constituent types
+    /// do (see `StructDatum::to_auto_trait_clause`). The rules for tuples, whose constituent
+    /// types are their elements, and for fn pointers exist for every arity, so they are built
+    /// in instead (see `BuiltinTrait::Auto`).
+    crate fn auto_trait_clauses(&self) -> Vec<ProgramClause> {
+        let mut clauses = vec![];
         for auto_trait in self
-            .trait_data
-            .values()
+            .in_declaration_order(&self.trait_data)
             .filter(|t| t.binders.value.flags.auto)
         {
-            for struct_datum in self.struct_data.values() {
+            let auto_trait_id = auto_trait.binders.value.trait_ref.trait_id;
+            for struct_datum in self.in_declaration_order(&self.struct_data) {
                 // `MyStruct: MyAutoTrait`
                 let trait_ref = TraitRef {
-                    trait_id: auto_trait.binders.value.trait_ref.trait_id,
-                    parameters: vec![ParameterKind::Ty(Ty::Apply(
-                        struct_datum.binders.value.self_ty.clone(),
-                    ))],
+                    trait_id: auto_trait_id,
+                    parameters: smallvec![ParameterKind::Ty(
+                        struct_datum.binders.value.self_ty.clone().cast()
+                    )],
                 };
 
                 // If a positive or negative impl is already provided for a type family
-                // which includes `MyStruct`, we do not generate a default impl.
-                if self.impl_provided_for(trait_ref.clone(), struct_datum) {
+                // which includes `MyStruct`, we do not generate the rule.
+                if self.impl_provided_for(trait_ref, struct_datum) {
                     continue;
                 }
 
-                self.default_impl_data.push(DefaultImplDatum {
-                    binders: Binders {
-                        binders: struct_datum.binders.binders.clone(),
-                        value: DefaultImplDatumBound {
-                            trait_ref,
-                            accessible_tys: struct_datum.binders.value.fields.clone(),
-                        },
-                    },
-                });
+                clauses.push(struct_datum.to_auto_trait_clause(auto_trait_id));
             }
         }
+        clauses
     }
 
     fn impl_provided_for(&self, trait_ref: TraitRef, struct_datum: &StructDatum) -> bool {
@@ -0,0 +1,133 @@
+//! Cycles among supertraits, e.g. `trait A where Self: B { }` and
+//! `trait B where Self: A { }`. Elaborating the implied bounds of such
+//! traits goes around the cycle forever (`FromEnv(Self: B) :-
+//! FromEnv(Self: A)`, and back), so goals that need it can only end in
+//! overflow, or in an answer that holds by assuming itself.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+use crate::rust_ir::*;
+use chalk_ir::fold::shift::Shift;
+use chalk_ir::*;
+
+mod test;
+
+/// Traits each of which is a supertrait of the one before it, the first
+/// being a supertrait of the last.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SupertraitCycle {
+    pub traits: Vec<(ItemId, Identifier)>,
+}
+
+/// Prints the cycle as a chain of traits, e.g. `A` -> `B` -> `A`.
+impl fmt::Display for SupertraitCycle {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "the supertraits of `{}` form a cycle: ", self.traits[0].1)?;
+        for (_, name) in &self.traits {
+            write!(fmt, "`{}` -> ", name)?;
+        }
+        write!(fmt, "`{}`", self.traits[0].1)
+    }
+}
+
+impl Program {
+    /// The cycles among the supertraits of the program, reporting at most
+    /// one cycle for each group of traits that are supertraits of each
+    /// other. Such cycles are not errors, but hosts may want to report
+    /// them as warnings.
+    pub fn supertrait_cycles(&self) -> Vec<SupertraitCycle> {
+        let supertraits: BTreeMap<ItemId, BTreeSet<ItemId>> = self
+            .trait_data
+            .iter()
+            .map(|(&trait_id, datum)| (trait_id, datum.supertraits().collect()))
+            .collect();
+
+        let mut cycles: Vec<Vec<ItemId>> = vec![];
+        for &trait_id in supertraits.keys() {
+            let reported = cycles.iter().any(|cycle| {
+                reachable(&supertraits, trait_id).contains(&cycle[0])
+                    && reachable(&supertraits, cycle[0]).contains(&trait_id)
+            });
+            if reported {
+                continue;
+            }
+            cycles.extend(cycle_through(&supertraits, trait_id));
+        }
+
+        cycles
+            .into_iter()
+            .map(|cycle| SupertraitCycle {
+                traits: cycle
+                    .into_iter()
+                    .map(|trait_id| (trait_id, self.type_kinds[&trait_id].name))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+impl TraitDatum {
+    /// The traits that the where clauses of this trait require `Self` to
+    /// implement.
+    fn supertraits<'a>(&'a self) -> impl Iterator<Item = ItemId> + 'a {
+        let self_parameter = &self.binders.value.trait_ref.parameters[0];
+        self.binders
+            .value
+            .where_clauses
+            .iter()
+            .filter_map(move |where_clause| match &where_clause.value {
+                WhereClause::Implemented(trait_ref)
+                    if trait_ref.parameters.first()
+                        == Some(&self_parameter.shifted_in(where_clause.len())) =>
+                {
+                    Some(trait_ref.trait_id)
+                }
+                _ => None,
+            })
+    }
+}
+
+/// The traits that `trait_id` has as supertraits, directly or not.
+fn reachable(
+    supertraits: &BTreeMap<ItemId, BTreeSet<ItemId>>,
+    trait_id: ItemId,
+) -> BTreeSet<ItemId> {
+    let mut reached = BTreeSet::new();
+    let mut queue: VecDeque<_> = supertraits[&trait_id].iter().cloned().collect();
+    while let Some(trait_id) = queue.pop_front() {
+        if reached.insert(trait_id) {
+            queue.extend(supertraits.get(&trait_id).into_iter().flatten());
+        }
+    }
+    reached
+}
+
+/// A shortest cycle of supertraits that starts with `start`, if any.
+fn cycle_through(
+    supertraits: &BTreeMap<ItemId, BTreeSet<ItemId>>,
+    start: ItemId,
+) -> Option<Vec<ItemId>> {
+    // The trait that each trait was reached from.
+    let mut subtraits: BTreeMap<ItemId, ItemId> = BTreeMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(start);
+    while let Some(trait_id) = queue.pop_front() {
+        for &supertrait in supertraits.get(&trait_id).into_iter().flatten() {
+            if supertrait == start {
+                let mut cycle = vec![trait_id];
+                while *cycle.last().unwrap() != start {
+                    let subtrait = subtraits[cycle.last().unwrap()];
+                    cycle.push(subtrait);
+                }
+                cycle.reverse();
+                return Some(cycle);
+            }
+            if !subtraits.contains_key(&supertrait) {
+                subtraits.insert(supertrait, trait_id);
+                queue.push_back(supertrait);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,80 @@
+#![cfg(test)]
+
+use crate::test_util::*;
+use chalk_solve::solve::SolverChoice;
+
+/// The names of the traits of each supertrait cycle of `program_text`,
+/// sorted, as the cycles may start with any of their traits.
+fn cycles(program_text: &str) -> Vec<Vec<String>> {
+    let program = parse_and_lower_program(program_text, SolverChoice::default()).unwrap();
+    program
+        .supertrait_cycles()
+        .iter()
+        .map(|cycle| {
+            let mut names: Vec<_> = cycle
+                .traits
+                .iter()
+                .map(|(_, name)| name.to_string())
+                .collect();
+            names.sort();
+            names
+        })
+        .collect()
+}
+
+#[test]
+fn no_cycles() {
+    assert!(cycles(
+        "
+        trait Clone { }
+        trait Copy where Self: Clone { }
+        trait Foo<T> where T: Foo<Self> { }
+        "
+    )
+    .is_empty());
+}
+
+#[test]
+fn cycles_are_reported_once() {
+    assert_eq!(
+        cycles(
+            "
+            trait A where Self: B { }
+            trait B where Self: C { }
+            trait C where Self: A { }
+            trait D where Self: A { }
+            "
+        ),
+        vec![vec!["A", "B", "C"]]
+    );
+
+    assert_eq!(
+        cycles(
+            "
+            trait A where Self: B { }
+            trait B where Self: A { }
+            trait C where Self: D { }
+            trait D where Self: C { }
+            "
+        )
+        .len(),
+        2
+    );
+}
+
+#[test]
+fn cycle_display() {
+    let program = parse_and_lower_program(
+        "
+        trait A where Self: A { }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+    let cycles: Vec<String> = program
+        .supertrait_cycles()
+        .iter()
+        .map(|cycle| cycle.to_string())
+        .collect();
+    assert_eq!(cycles, vec!["the supertraits of `A` form a cycle: `A` -> `A`"]);
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+use std::fmt;
 use std::sync::Arc;
 
 use crate::rust_ir::*;
@@ -16,29 +18,104 @@ mod test;
 pub enum WfError {
     #[fail(
         display = "type declaration {:?} does not meet well-formedness requirements",
-        _0
+        name
     )]
-    IllFormedTypeDecl(chalk_ir::Identifier),
+    IllFormedTypeDecl {
+        name: chalk_ir::Identifier,
+        struct_id: ItemId,
+    },
     #[fail(
         display = "trait impl for {:?} does not meet well-formedness requirements",
-        _0
+        trait_name
     )]
-    IllFormedTraitImpl(chalk_ir::Identifier),
+    IllFormedTraitImpl {
+        trait_name: chalk_ir::Identifier,
+        impl_id: ItemId,
+    },
+}
+
+impl WfError {
+    /// A code identifying the kind of error (see `LoweringError::code`).
+    pub fn code(&self) -> &'static str {
+        match self {
+            WfError::IllFormedTypeDecl { .. } => "W0001",
+            WfError::IllFormedTraitImpl { .. } => "W0002",
+        }
+    }
+
+    /// The ill-formed item.
+    pub fn item(&self) -> ItemId {
+        match *self {
+            WfError::IllFormedTypeDecl { struct_id, .. } => struct_id,
+            WfError::IllFormedTraitImpl { impl_id, .. } => impl_id,
+        }
+    }
+
+    /// The message prefixed with the error code.
+    pub fn render(&self) -> String {
+        format!("error[{}]: {}", self.code(), self)
+    }
+}
+
+/// All the ill-formed items of a program: its structs first, then its
+/// impls, each in the order of their ids. Displays as its error if
+/// there is only one, and as a list of them otherwise (as
+/// `LoweringErrors` does).
+#[derive(Debug)]
+pub struct WfErrors {
+    pub errors: Vec<WfError>,
+}
+
+impl WfErrors {
+    /// Each error rendered by `WfError::render`, one per line.
+    pub fn render(&self) -> String {
+        self.errors.iter().map(|error| error.render()).join("\n")
+    }
+}
+
+impl Fail for WfErrors {}
+
+impl fmt::Display for WfErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.errors[..] {
+            [error] => fmt::Display::fmt(error, f),
+            errors => {
+                write!(f, "{} errors:", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 struct WfSolver<'me> {
     program: &'me Program,
+}
+
+/// Checks that the structs and impls of `program` are well-formed,
+/// failing with the `WfErrors` of all those that are not.
+pub fn verify_well_formedness(
+    program: Arc<Program>,
     env: Arc<ProgramEnvironment>,
     solver_choice: SolverChoice,
+) -> Fallible<()> {
+    tls::set_current_program(&program, || {
+        solve_wf_requirements(program.clone(), env, solver_choice, None)
+    })
 }
 
-pub fn verify_well_formedness(
+/// As `verify_well_formedness`, but only checks the structs and impls
+/// among `items`.
+crate fn verify_well_formedness_of(
     program: Arc<Program>,
     env: Arc<ProgramEnvironment>,
     solver_choice: SolverChoice,
+    items: &BTreeSet<ItemId>,
 ) -> Fallible<()> {
     tls::set_current_program(&program, || {
-        solve_wf_requirements(program.clone(), env, solver_choice)
+        solve_wf_requirements(program.clone(), env, solver_choice, Some(items))
     })
 }
 
@@ -46,29 +123,82 @@ fn solve_wf_requirements(
     program: Arc<Program>,
     env: Arc<ProgramEnvironment>,
     solver_choice: SolverChoice,
+    items: Option<&BTreeSet<ItemId>>,
 ) -> Fallible<()> {
-    let solver = WfSolver {
-        program: &program,
-        env,
-        solver_choice,
-    };
-
-    for (id, struct_datum) in &program.struct_data {
-        if !solver.verify_struct_decl(struct_datum) {
-            let name = program.type_kinds.get(id).unwrap().name;
-            Err(WfError::IllFormedTypeDecl(name))?;
+    let checked = |id: &&ItemId| items.map_or(true, |items| items.contains(*id));
+    let solver = WfSolver { program: &program };
+
+    let mut struct_ids: Vec<ItemId> = program.struct_data.keys().filter(checked).cloned().collect();
+    let mut impl_ids: Vec<ItemId> = program.impl_data.keys().filter(checked).cloned().collect();
+    let clause_order = solver_choice.clause_order();
+    clause_order.shuffle(&mut struct_ids, &"well-formed structs");
+    clause_order.shuffle(&mut impl_ids, &"well-formed impls");
+    let goals: Vec<Option<Goal>> = struct_ids
+        .iter()
+        .map(|id| solver.struct_decl_goal(&program.struct_data[id]))
+        .chain(
+            impl_ids
+                .iter()
+                .map(|id| solver.trait_impl_goal(&program.impl_data[id])),
+        )
+        .collect();
+    let mut well_formed = solve_wf_goals(env, solver_choice, &goals).into_iter();
+
+    let mut errors = vec![];
+    for id in &struct_ids {
+        if !well_formed.next().unwrap() {
+            errors.push(WfError::IllFormedTypeDecl {
+                name: program.type_kinds.get(id).unwrap().name,
+                struct_id: *id,
+            });
         }
     }
-
-    for impl_datum in program.impl_data.values() {
-        if !solver.verify_trait_impl(impl_datum) {
+    for id in &impl_ids {
+        if !well_formed.next().unwrap() {
+            let impl_datum = &program.impl_data[id];
             let trait_ref = impl_datum.binders.value.trait_ref.trait_ref();
-            let name = program.type_kinds.get(&trait_ref.trait_id).unwrap().name;
-            Err(WfError::IllFormedTraitImpl(name))?;
+            errors.push(WfError::IllFormedTraitImpl {
+                trait_name: program.type_kinds.get(&trait_ref.trait_id).unwrap().name,
+                impl_id: *id,
+            });
         }
     }
 
-    Ok(())
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(WfErrors { errors }.into())
+    }
+}
+
+/// Solves the WF goals of some items (`None` being the goal of an item that is trivially
+/// well-formed), and tells which of them are well-formed. The goals are spread over several
+/// threads with `solve_all`, so that the goals of each thread share the work for their common
+/// subgoals (e.g. the well-formedness of the types they mention).
+fn solve_wf_goals(
+    env: Arc<ProgramEnvironment>,
+    solver_choice: SolverChoice,
+    goals: &[Option<Goal>],
+) -> Vec<bool> {
+    let closed_goals: Vec<_> = goals
+        .iter()
+        .filter_map(|goal| goal.clone())
+        .map(|goal| goal.into_closed_goal())
+        .collect();
+    let solutions = solver_choice.solve_all(&env, &closed_goals);
+
+    // The goals are closed, so they hold if they have a unique solution.
+    let mut solutions = solutions.into_iter();
+    goals
+        .iter()
+        .map(|goal| match goal {
+            Some(_) => match solutions.next().unwrap() {
+                Some(solution) => solution.is_unique(),
+                None => false,
+            },
+            None => true,
+        })
+        .collect()
 }
 
 /// A trait for retrieving all types appearing in some Chalk construction.
@@ -84,6 +214,14 @@ impl<T: FoldInputTypes> FoldInputTypes for Vec<T> {
     }
 }
 
+impl FoldInputTypes for Parameters {
+    fn fold(&self, accumulator: &mut Vec<Ty>) {
+        for f in self {
+            f.fold(accumulator);
+        }
+    }
+}
+
 impl FoldInputTypes for Parameter {
     fn fold(&self, accumulator: &mut Vec<Ty>) {
         if let ParameterKind::Ty(ty) = self {
@@ -94,31 +232,34 @@ impl FoldInputTypes for Parameter {
 
 impl FoldInputTypes for Ty {
     fn fold(&self, accumulator: &mut Vec<Ty>) {
-        match self {
-            Ty::Apply(app) => {
+        match self.data() {
+            TyData::Apply(app) => {
                 accumulator.push(self.clone());
                 app.parameters.fold(accumulator);
             }
-            Ty::Projection(proj) => {
+            TyData::Projection(proj) => {
                 accumulator.push(self.clone());
                 proj.parameters.fold(accumulator);
             }
-            Ty::UnselectedProjection(proj) => {
+            TyData::UnselectedProjection(proj) => {
                 accumulator.push(self.clone());
                 proj.parameters.fold(accumulator);
             }
 
             // Type parameters do not carry any input types (so we can sort of assume they are
             // always WF).
-            Ty::BoundVar(..) => (),
+            TyData::BoundVar(..) => (),
 
             // Higher-kinded types such as `for<'a> fn(&'a u32)` introduce their own implied
             // bounds, and these bounds will be enforced upon calling such a function. In some
             // sense, well-formedness requirements for the input types of an HKT will be enforced
             // lazily, so no need to include them here.
-            Ty::ForAll(..) => (),
+            TyData::ForAll(..) => (),
+
+            // Likewise, the bounds of object types are enforced when the object is created.
+            TyData::Dyn(..) => (),
 
-            Ty::InferenceVar(..) => {
+            TyData::InferenceVar(..) => {
                 panic!("unexpected inference variable in wf rules: {:?}", self,)
             }
         }
@@ -133,16 +274,23 @@ impl FoldInputTypes for TraitRef {
 
 impl FoldInputTypes for ProjectionEq {
     fn fold(&self, accumulator: &mut Vec<Ty>) {
-        Ty::Projection(self.projection.clone()).fold(accumulator);
+        TyData::Projection(self.projection.clone()).intern().fold(accumulator);
         self.ty.fold(accumulator);
     }
 }
 
+impl FoldInputTypes for ConstEvaluatable {
+    fn fold(&self, accumulator: &mut Vec<Ty>) {
+        self.parameters.fold(accumulator);
+    }
+}
+
 impl FoldInputTypes for WhereClause {
     fn fold(&self, accumulator: &mut Vec<Ty>) {
         match self {
             WhereClause::Implemented(tr) => tr.fold(accumulator),
             WhereClause::ProjectionEq(p) => p.fold(accumulator),
+            WhereClause::ConstEvaluatable(c) => c.fold(accumulator),
         }
     }
 }
@@ -154,7 +302,8 @@ impl<T: FoldInputTypes> FoldInputTypes for Binders<T> {
 }
 
 impl<'me> WfSolver<'me> {
-    fn verify_struct_decl(&self, struct_datum: &StructDatum) -> bool {
+    /// The goal that holds if the struct is well-formed, or `None` if it trivially is.
+    fn struct_decl_goal(&self, struct_datum: &StructDatum) -> Option<Goal> {
         // We retrieve all the input types of the struct fields.
         let mut input_types = Vec::new();
         struct_datum.binders.value.fields.fold(&mut input_types);
@@ -165,7 +314,7 @@ impl<'me> WfSolver<'me> {
             .fold(&mut input_types);
 
         if input_types.is_empty() {
-            return true;
+            return None;
         }
 
         let goals = input_types
@@ -190,21 +339,14 @@ impl<'me> WfSolver<'me> {
         // on the struct definition hold.
         let goal = Goal::Implies(hypotheses, Box::new(goal))
             .quantify(QuantifierKind::ForAll, struct_datum.binders.binders.clone());
-
-        match self
-            .solver_choice
-            .solve_root_goal(&self.env, &goal.into_closed_goal())
-            .unwrap()
-        {
-            Some(sol) => sol.is_unique(),
-            None => false,
-        }
+        Some(goal)
     }
 
-    fn verify_trait_impl(&self, impl_datum: &ImplDatum) -> bool {
+    /// The goal that holds if the impl is well-formed, or `None` if it trivially is.
+    fn trait_impl_goal(&self, impl_datum: &ImplDatum) -> Option<Goal> {
         let trait_ref = match impl_datum.binders.value.trait_ref {
             PolarizedTraitRef::Positive(ref trait_ref) => trait_ref,
-            _ => return true,
+            _ => return None,
         };
 
         // We retrieve all the input types of the where clauses appearing on the trait impl,
@@ -264,14 +406,10 @@ impl<'me> WfSolver<'me> {
 
             let trait_ref = trait_ref.shifted_in(assoc_ty.value.binders.len());
 
-            let all_parameters: Vec<_> = assoc_ty
-                .value
-                .binders
-                .iter()
-                .zip(0..)
-                .map(|p| p.to_parameter())
-                .chain(trait_ref.parameters.iter().cloned())
-                .collect();
+            let all_parameters = SubstBuilder::new(assoc_ty_datum.parameter_kinds.anonymize())
+                .bound_vars(assoc_ty.value.binders.len())
+                .extend(trait_ref.parameters.iter().cloned())
+                .into_parameters();
 
             // Add bounds from the trait. Because they are defined on the trait,
             // their parameters must be substituted with those of the impl.
@@ -346,14 +484,6 @@ impl<'me> WfSolver<'me> {
             .quantify(QuantifierKind::ForAll, impl_datum.binders.binders.clone());
 
         debug!("WF trait goal: {:?}", goal);
-
-        match self
-            .solver_choice
-            .solve_root_goal(&self.env, &goal.into_closed_goal())
-            .unwrap()
-        {
-            Some(sol) => sol.is_unique(),
-            None => false,
-        }
+        Some(goal)
     }
 }
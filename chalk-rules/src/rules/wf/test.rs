@@ -151,6 +151,32 @@ fn ill_formed_ty_decl() {
     }
 }
 
+#[test]
+fn all_ill_formed_items() {
+    lowering_error! {
+        program {
+            trait Hash { }
+            struct Set<K> where K: Hash { }
+
+            struct MyType<K> {
+                value: Set<K>
+            }
+
+            trait Foo {
+                type Value;
+            }
+
+            impl<K> Foo for MyType<K> {
+                type Value = Set<K>;
+            }
+        } error_msg {
+            "2 errors:
+  - type declaration \"MyType\" does not meet well-formedness requirements
+  - trait impl for \"Foo\" does not meet well-formedness requirements"
+        }
+    }
+}
+
 #[test]
 fn implied_bounds_on_ty_decl() {
     lowering_success! {
@@ -528,16 +554,16 @@ fn higher_ranked_inline_bound_on_gat() {
             struct Ref<'a, T> { }
             struct i32 {}
 
-            struct fn<T> { }
+            struct Fun<T> { }
 
-            impl<'a, T> Fn<Ref<'a, T>> for for<'b> fn<Ref<'b, T>> { }
+            impl<'a, T> Fn<Ref<'a, T>> for for<'b> Fun<Ref<'b, T>> { }
 
             trait Bar {
                 type Item<T>: forall<'a> Fn<Ref<'a, T>>;
             }
 
             impl Bar for i32 {
-                type Item<T> = for<'a> fn<Ref<'a, T>>;
+                type Item<T> = for<'a> Fun<Ref<'a, T>>;
             }
         }
     }
@@ -547,16 +573,16 @@ fn higher_ranked_inline_bound_on_gat() {
             trait Fn<T, U> { }
             struct i32 {}
 
-            struct fn<T, U> { }
+            struct Fun<T, U> { }
 
-            impl<T, U> Fn<T, U> for fn<T, U> { }
+            impl<T, U> Fn<T, U> for Fun<T, U> { }
 
             trait Bar {
                 type Item<T>: forall<U> Fn<T, U>;
             }
 
             impl Bar for i32 {
-                type Item<T> = fn<T, i32>;
+                type Item<T> = Fun<T, i32>;
             }
         } error_msg {
             "trait impl for \"Bar\" does not meet well-formedness requirements"
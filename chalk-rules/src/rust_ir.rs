@@ -2,23 +2,37 @@
 //! version of the AST, roughly corresponding to [the HIR] in the Rust
 //! compiler.
 
+use chalk_derive::Fold;
 use chalk_ir::debug::Angle;
 use chalk_ir::fold::shift::Shift;
 use chalk_ir::tls;
 use chalk_ir::{
-    ApplicationTy, Binders, Identifier, ItemId, Lifetime, Parameter, ParameterKind, ProgramClause,
-    ProjectionEq, ProjectionTy, QuantifiedWhereClause, TraitRef, Ty, WhereClause,
+    ApplicationTy, BinderNames, Binders, Identifier, ItemId, Parameter, ParameterKind,
+    ProgramClause, ProjectionEq, ProjectionTy, QuantifiedWhereClause, SubstBuilder, TraitRef, Ty,
+    TyData, WhereClause,
 };
-use std::collections::BTreeMap;
+use rustc_hash::FxHashMap;
+use self::diff::ItemDiff;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::iter;
 
+pub mod builder;
+pub mod diff;
 pub mod lowering;
-
-#[derive(Clone, Debug, PartialEq, Eq)]
+mod merge;
+mod printer;
+pub mod stats;
+
+/// A lowered program. It can be written out with any serde format, to
+/// cache the result of lowering for instance; identifiers are written
+/// out as strings, so it can be read back in by another process.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Program {
     /// From type-name to item-id. Used during lowering only.
-    crate type_ids: BTreeMap<Identifier, ItemId>,
+    #[serde(with = "chalk_ir::serde_impls::identifier_keys")]
+    crate type_ids: FxHashMap<Identifier, ItemId>,
 
     /// For each struct/trait:
     crate type_kinds: BTreeMap<ItemId, TypeKind>,
@@ -35,14 +49,21 @@ pub struct Program {
     /// For each associated ty:
     crate associated_ty_data: BTreeMap<ItemId, AssociatedTyDatum>,
 
-    /// For each default impl (automatically generated for auto traits):
-    crate default_impl_data: Vec<DefaultImplDatum>,
+    /// The ids of the structs, traits, associated types and impls, in the
+    /// order the program declares them (the associated types of a trait
+    /// come right after it). Ids are derived from paths, so the maps above
+    /// are in no particular order; clauses are generated in this one.
+    crate item_order: Vec<ItemId>,
 
     /// For each user-specified clause
     crate custom_clauses: Vec<ProgramClause>,
 
     /// Special types and traits.
     crate lang_items: BTreeMap<LangItem, ItemId>,
+
+    /// The pairs `(less_special, more_special)` of overlapping impls,
+    /// filled in by the coherence check.
+    crate specializations: BTreeSet<(ItemId, ItemId)>,
 }
 
 impl Program {
@@ -68,6 +89,31 @@ impl Program {
         let (other_params, trait_params) = parameters.split_at(split_point);
         (associated_ty_data, trait_params, other_params)
     }
+
+    /// The values of `data`, one of the maps of items of `self`, in the
+    /// order the program declares their items.
+    crate fn in_declaration_order<'a, T>(
+        &'a self,
+        data: &'a BTreeMap<ItemId, T>,
+    ) -> impl Iterator<Item = &'a T> + 'a {
+        self.item_order.iter().filter_map(move |id| data.get(id))
+    }
+
+    /// Returns the items (structs, traits, impls and associated types)
+    /// that differ between `self` and `other`, including those that
+    /// appear in only one of them. Items are matched up by `ItemId`.
+    /// Custom clauses and lang items are not items and so are never
+    /// reported; `ProgramEnvironment::changed_clauses` covers them. See
+    /// `diff::diff` for a breakdown by sort of item.
+    pub fn changed_items(&self, other: &Program) -> BTreeSet<ItemId> {
+        let diff = diff::diff(self, other);
+        let mut changed = ItemDiff::new(&self.type_kinds, &other.type_kinds).items();
+        changed.extend(diff.structs.items());
+        changed.extend(diff.impls.items());
+        changed.extend(diff.traits.items());
+        changed.extend(diff.associated_tys.items());
+        changed
+    }
 }
 
 impl tls::DebugContext for Program {
@@ -101,17 +147,24 @@ impl tls::DebugContext for Program {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum LangItem {
     DerefTrait,
+    DropTrait,
+    SizedTrait,
+    CopyTrait,
+    CloneTrait,
+    FnOnceTrait,
+    FnMutTrait,
+    FnTrait,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ImplDatum {
     crate binders: Binders<ImplDatumBound>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ImplDatumBound {
     crate trait_ref: PolarizedTraitRef,
     crate where_clauses: Vec<QuantifiedWhereClause>,
@@ -120,29 +173,18 @@ pub struct ImplDatumBound {
     crate impl_type: ImplType,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ImplType {
     Local,
     External,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct DefaultImplDatum {
-    crate binders: Binders<DefaultImplDatumBound>,
-}
-
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct DefaultImplDatumBound {
-    crate trait_ref: TraitRef,
-    crate accessible_tys: Vec<Ty>,
-}
-
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct StructDatum {
     crate binders: Binders<StructDatumBound>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct StructDatumBound {
     crate self_ty: ApplicationTy,
     crate fields: Vec<Ty>,
@@ -150,42 +192,42 @@ pub struct StructDatumBound {
     crate flags: StructFlags,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct StructFlags {
     crate upstream: bool,
     crate fundamental: bool,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TraitDatum {
     crate binders: Binders<TraitDatumBound>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TraitDatumBound {
     crate trait_ref: TraitRef,
     crate where_clauses: Vec<QuantifiedWhereClause>,
     crate flags: TraitFlags,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TraitFlags {
     crate auto: bool,
+    crate coinductive: bool,
     crate marker: bool,
     crate upstream: bool,
     crate fundamental: bool,
     pub deref: bool,
+    crate drop: bool,
 }
 
 /// An inline bound, e.g. `: Foo<K>` in `impl<K, T: Foo<K>> SomeType<T>`.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Fold, Serialize, Deserialize)]
 pub enum InlineBound {
     TraitBound(TraitBound),
     ProjectionEqBound(ProjectionEqBound),
 }
 
-enum_fold!(InlineBound[] { TraitBound(a), ProjectionEqBound(a) });
-
 pub type QuantifiedInlineBound = Binders<InlineBound>;
 
 crate trait IntoWhereClauses {
@@ -221,6 +263,7 @@ impl IntoWhereClauses for QuantifiedInlineBound {
             .map(|wc| Binders {
                 binders: self.binders.clone(),
                 value: wc,
+                names: self.names.clone(),
             })
             .collect()
     }
@@ -228,17 +271,12 @@ impl IntoWhereClauses for QuantifiedInlineBound {
 
 /// Represents a trait bound on e.g. a type or type parameter.
 /// Does not know anything about what it's binding.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Fold, Serialize, Deserialize)]
 pub struct TraitBound {
     crate trait_id: ItemId,
     crate args_no_self: Vec<Parameter>,
 }
 
-struct_fold!(TraitBound {
-    trait_id,
-    args_no_self,
-});
-
 impl TraitBound {
     fn into_where_clauses(&self, self_ty: Ty) -> Vec<WhereClause> {
         let trait_ref = self.as_trait_ref(self_ty);
@@ -257,7 +295,7 @@ impl TraitBound {
 }
 /// Represents a projection equality bound on e.g. a type or type parameter.
 /// Does not know anything about what it's binding.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Fold, Serialize, Deserialize)]
 pub struct ProjectionEqBound {
     crate trait_bound: TraitBound,
     crate associated_ty_id: ItemId,
@@ -266,13 +304,6 @@ pub struct ProjectionEqBound {
     crate value: Ty,
 }
 
-struct_fold!(ProjectionEqBound {
-    trait_bound,
-    associated_ty_id,
-    parameters,
-    value,
-});
-
 impl ProjectionEqBound {
     fn into_where_clauses(&self, self_ty: Ty) -> Vec<WhereClause> {
         let trait_ref = self.trait_bound.as_trait_ref(self_ty);
@@ -299,34 +330,23 @@ pub trait Anonymize {
     /// "anonymous" generic parameters that just preserves their
     /// kinds (`ParameterKind<()>`). Often convenient in lowering.
     fn anonymize(&self) -> Vec<ParameterKind<()>>;
+
+    /// The names that `anonymize` drops, to keep alongside the anonymous
+    /// parameters for debug output.
+    fn names(&self) -> BinderNames;
 }
 
 impl Anonymize for [ParameterKind<Identifier>] {
     fn anonymize(&self) -> Vec<ParameterKind<()>> {
         self.iter().map(|pk| pk.map(|_| ())).collect()
     }
-}
-
-pub trait ToParameter {
-    /// Utility for converting a list of all the binders into scope
-    /// into references to those binders. Simply pair the binders with
-    /// the indices, and invoke `to_parameter()` on the `(binder,
-    /// index)` pair. The result will be a reference to a bound
-    /// variable of appropriate kind at the corresponding index.
-    fn to_parameter(&self) -> Parameter;
-}
 
-impl<'a> ToParameter for (&'a ParameterKind<()>, usize) {
-    fn to_parameter(&self) -> Parameter {
-        let &(binder, index) = self;
-        match *binder {
-            ParameterKind::Lifetime(_) => ParameterKind::Lifetime(Lifetime::BoundVar(index)),
-            ParameterKind::Ty(_) => ParameterKind::Ty(Ty::BoundVar(index)),
-        }
+    fn names(&self) -> BinderNames {
+        BinderNames::new(self.iter().map(|pk| *pk.as_ref().into_inner()).collect())
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct AssociatedTyDatum {
     /// The trait this associated type is defined in.
     crate trait_id: ItemId,
@@ -335,10 +355,12 @@ pub struct AssociatedTyDatum {
     crate id: ItemId,
 
     /// Name of this associated type.
+    #[serde(with = "chalk_ir::serde_impls::identifier")]
     crate name: Identifier,
 
     /// Parameters on this associated type, beginning with those from the trait,
     /// but possibly including more.
+    #[serde(with = "chalk_ir::serde_impls::parameter_kinds")]
     crate parameter_kinds: Vec<ParameterKind<Identifier>>,
 
     /// Bounds on the associated type itself.
@@ -358,17 +380,14 @@ impl AssociatedTyDatum {
     /// Implemented(<?0 as Foo>::Item<?1>: Sized)
     /// ```
     crate fn bounds_on_self(&self) -> Vec<QuantifiedWhereClause> {
-        let parameters = self
-            .parameter_kinds
-            .anonymize()
-            .iter()
-            .zip(0..)
-            .map(|p| p.to_parameter())
-            .collect();
-        let self_ty = Ty::Projection(ProjectionTy {
+        let parameters = SubstBuilder::new(self.parameter_kinds.anonymize())
+            .fill_bound_vars()
+            .into_parameters();
+        let self_ty = TyData::Projection(ProjectionTy {
             associated_ty_id: self.id,
             parameters,
-        });
+        })
+        .intern();
         self.bounds
             .iter()
             .flat_map(|b| b.into_where_clauses(self_ty.clone()))
@@ -376,7 +395,7 @@ impl AssociatedTyDatum {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Fold, Serialize, Deserialize)]
 pub struct AssociatedTyValue {
     crate associated_ty_id: ItemId,
 
@@ -384,40 +403,32 @@ pub struct AssociatedTyValue {
     crate value: Binders<AssociatedTyValueBound>,
 }
 
-struct_fold!(AssociatedTyValue {
-    associated_ty_id,
-    value,
-});
-
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Fold, Serialize, Deserialize)]
 pub struct AssociatedTyValueBound {
     /// Type that we normalize to. The X in `type Foo<'a> = X`.
     crate ty: Ty,
 }
 
-struct_fold!(AssociatedTyValueBound { ty });
-
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TypeKind {
     crate sort: TypeSort,
+    #[serde(with = "chalk_ir::serde_impls::identifier")]
     crate name: Identifier,
     crate binders: Binders<()>,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum TypeSort {
     Struct,
     Trait,
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Fold, Serialize, Deserialize)]
 pub enum PolarizedTraitRef {
     Positive(TraitRef),
     Negative(TraitRef),
 }
 
-enum_fold!(PolarizedTraitRef[] { Positive(a), Negative(a) });
-
 impl PolarizedTraitRef {
     crate fn is_positive(&self) -> bool {
         match *self {
@@ -0,0 +1,290 @@
+//! Building programs in memory, without writing them out as text for the
+//! parser, for embedders and for tests that generate programs.
+//!
+//! ```ignore
+//! let program = ProgramBuilder::new()
+//!     .trait_("Clone", 0)
+//!     .struct_("Vec", 1)
+//!     .field("data", Ty::param(0))
+//!     .impl_(1, "Clone", Ty::apply("Vec", vec![Ty::param(0)]), vec![])
+//!     .where_(Ty::param(0), "Clone", vec![])
+//!     .build(SolverChoice::default())?;
+//! ```
+//!
+//! is the program
+//!
+//! ```notrust
+//! trait Clone { }
+//! struct Vec<T0> { data: T0 }
+//! impl<T0> Clone for Vec<T0> where T0: Clone { }
+//! ```
+//!
+//! The type parameters of an item are named `T0`, `T1`, ..., so they
+//! hide any struct with such a name. Only type parameters are supported.
+
+use std::sync::Arc;
+
+use chalk_macros::intern::intern;
+use chalk_parse::ast;
+use chalk_solve::solve::SolverChoice;
+use failure::Fallible;
+
+use super::lowering::LowerProgram;
+use super::Program;
+use crate::coherence::orphan;
+use crate::rules::wf;
+use crate::rules::ClauseFamilies;
+
+mod test;
+
+/// A type, as given to a `ProgramBuilder`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ty {
+    /// The `n`th type parameter of the item being built.
+    Param(usize),
+
+    /// `Self`, in a trait.
+    SelfTy,
+
+    /// A struct applied to some types, e.g. `Vec<T0>`.
+    Apply(String, Vec<Ty>),
+}
+
+impl Ty {
+    pub fn param(index: usize) -> Ty {
+        Ty::Param(index)
+    }
+
+    pub fn apply(name: &str, args: Vec<Ty>) -> Ty {
+        Ty::Apply(name.to_string(), args)
+    }
+
+    fn to_ast(&self) -> ast::Ty {
+        match self {
+            Ty::Param(index) => ast::Ty::Id {
+                name: param_name(*index),
+            },
+            Ty::SelfTy => ast::Ty::Id {
+                name: identifier("Self"),
+            },
+            Ty::Apply(name, args) if args.is_empty() => ast::Ty::Id {
+                name: identifier(name),
+            },
+            Ty::Apply(name, args) => ast::Ty::Apply {
+                name: identifier(name),
+                args: args.iter().map(|arg| ast::Parameter::Ty(arg.to_ast())).collect(),
+            },
+        }
+    }
+}
+
+/// Builds a program item by item. Methods like `field` or `where_`
+/// modify the item added last, and panic if it is not of the right
+/// sort; mistakes in the program itself, such as an unknown trait, are
+/// reported by `build`, as they would be for a program that was parsed.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramBuilder {
+    items: Vec<ast::Item>,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `struct name<T0, ..., Tn> { }`.
+    pub fn struct_(mut self, name: &str, num_params: usize) -> Self {
+        self.items.push(ast::Item::StructDefn(ast::StructDefn {
+            name: identifier(name),
+            parameter_kinds: param_kinds(num_params),
+            where_clauses: vec![],
+            fields: vec![],
+            flags: ast::StructFlags {
+                upstream: false,
+                fundamental: false,
+            },
+        }));
+        self
+    }
+
+    /// Adds `trait name<T0, ..., Tn> { }`; `num_params` does not count
+    /// `Self`.
+    pub fn trait_(mut self, name: &str, num_params: usize) -> Self {
+        self.items.push(ast::Item::TraitDefn(ast::TraitDefn {
+            name: identifier(name),
+            parameter_kinds: param_kinds(num_params),
+            where_clauses: vec![],
+            assoc_ty_defns: vec![],
+            flags: ast::TraitFlags {
+                auto: false,
+                coinductive: false,
+                marker: false,
+                upstream: false,
+                fundamental: false,
+                deref: false,
+                drop: false,
+                builtin: None,
+            },
+        }));
+        self
+    }
+
+    /// Adds `impl<T0, ..., Tn> trait_name<args> for self_ty { }`.
+    pub fn impl_(
+        mut self,
+        num_params: usize,
+        trait_name: &str,
+        self_ty: Ty,
+        args: Vec<Ty>,
+    ) -> Self {
+        self.items.push(ast::Item::Impl(ast::Impl {
+            parameter_kinds: param_kinds(num_params),
+            trait_ref: ast::PolarizedTraitRef::Positive(trait_ref(trait_name, &self_ty, &args)),
+            where_clauses: vec![],
+            assoc_ty_values: vec![],
+            impl_type: ast::ImplType::Local,
+        }));
+        self
+    }
+
+    /// Adds the field `name: ty` to the last struct.
+    pub fn field(mut self, name: &str, ty: Ty) -> Self {
+        match self.items.last_mut() {
+            Some(ast::Item::StructDefn(defn)) => defn.fields.push(ast::Field {
+                name: identifier(name),
+                ty: ty.to_ast(),
+            }),
+            _ => panic!("`field` must follow a struct"),
+        }
+        self
+    }
+
+    /// Adds the where clause `ty: trait_name<args>` to the last struct,
+    /// trait or impl.
+    pub fn where_(mut self, ty: Ty, trait_name: &str, args: Vec<Ty>) -> Self {
+        let where_clause = ast::QuantifiedWhereClause {
+            parameter_kinds: vec![],
+            where_clause: ast::WhereClause::Implemented {
+                trait_ref: trait_ref(trait_name, &ty, &args),
+            },
+        };
+        match self.items.last_mut() {
+            Some(ast::Item::StructDefn(defn)) => defn.where_clauses.push(where_clause),
+            Some(ast::Item::TraitDefn(defn)) => defn.where_clauses.push(where_clause),
+            Some(ast::Item::Impl(impl_)) => impl_.where_clauses.push(where_clause),
+            _ => panic!("`where_` must follow a struct, trait or impl"),
+        }
+        self
+    }
+
+    /// Adds the associated type `type name;` to the last trait.
+    pub fn assoc_ty(mut self, name: &str) -> Self {
+        match self.items.last_mut() {
+            Some(ast::Item::TraitDefn(defn)) => defn.assoc_ty_defns.push(ast::AssocTyDefn {
+                name: identifier(name),
+                parameter_kinds: vec![],
+                bounds: vec![],
+                where_clauses: vec![],
+            }),
+            _ => panic!("`assoc_ty` must follow a trait"),
+        }
+        self
+    }
+
+    /// Adds the associated type value `type name = ty;` to the last impl.
+    pub fn assoc_ty_value(mut self, name: &str, ty: Ty) -> Self {
+        match self.items.last_mut() {
+            Some(ast::Item::Impl(impl_)) => impl_.assoc_ty_values.push(ast::AssocTyValue {
+                name: identifier(name),
+                parameter_kinds: vec![],
+                value: ty.to_ast(),
+            }),
+            _ => panic!("`assoc_ty_value` must follow an impl"),
+        }
+        self
+    }
+
+    /// Marks the last struct or trait as `#[upstream]`, or the last impl
+    /// as an upstream impl.
+    pub fn upstream(mut self) -> Self {
+        match self.items.last_mut() {
+            Some(ast::Item::StructDefn(defn)) => defn.flags.upstream = true,
+            Some(ast::Item::TraitDefn(defn)) => defn.flags.upstream = true,
+            Some(ast::Item::Impl(impl_)) => impl_.impl_type = ast::ImplType::External,
+            _ => panic!("`upstream` must follow a struct, trait or impl"),
+        }
+        self
+    }
+
+    /// Marks the last trait as `#[auto]`.
+    pub fn auto(mut self) -> Self {
+        match self.items.last_mut() {
+            Some(ast::Item::TraitDefn(defn)) => defn.flags.auto = true,
+            _ => panic!("`auto` must follow a trait"),
+        }
+        self
+    }
+
+    /// Marks the last trait as `#[coinductive]`.
+    pub fn coinductive(mut self) -> Self {
+        match self.items.last_mut() {
+            Some(ast::Item::TraitDefn(defn)) => defn.flags.coinductive = true,
+            _ => panic!("`coinductive` must follow a trait"),
+        }
+        self
+    }
+
+    /// Makes the last impl a negative impl, `impl !Trait for Type`.
+    pub fn negative(mut self) -> Self {
+        match self.items.last_mut() {
+            Some(ast::Item::Impl(impl_)) => {
+                let trait_ref = impl_.trait_ref.trait_ref().clone();
+                impl_.trait_ref = ast::PolarizedTraitRef::Negative(trait_ref);
+            }
+            _ => panic!("`negative` must follow an impl"),
+        }
+        self
+    }
+
+    /// Lowers and checks the program, exactly as for a program that was
+    /// parsed: the lowering errors, coherence errors and well-formedness
+    /// errors are the same.
+    pub fn build(self, solver_choice: SolverChoice) -> Fallible<Program> {
+        let mut program = ast::Program { items: self.items }.lower()?;
+        let env = Arc::new(program.environment(ClauseFamilies::default()));
+        program.record_specialization_priorities(env.clone(), solver_choice)?;
+
+        let program = Arc::new(program);
+        orphan::perform_orphan_check(program.clone(), env.clone(), solver_choice)?;
+        wf::verify_well_formedness(program.clone(), env, solver_choice)?;
+        Ok(Arc::try_unwrap(program).unwrap_or_else(|program| (*program).clone()))
+    }
+}
+
+fn identifier(name: &str) -> ast::Identifier {
+    ast::Identifier {
+        str: intern(name),
+        span: ast::Span::new(0, 0),
+    }
+}
+
+fn param_name(index: usize) -> ast::Identifier {
+    identifier(&format!("T{}", index))
+}
+
+fn param_kinds(num_params: usize) -> Vec<ast::ParameterKind> {
+    (0..num_params)
+        .map(|index| ast::ParameterKind::Ty(param_name(index)))
+        .collect()
+}
+
+fn trait_ref(trait_name: &str, self_ty: &Ty, args: &[Ty]) -> ast::TraitRef {
+    ast::TraitRef {
+        trait_name: identifier(trait_name),
+        args: Some(self_ty)
+            .into_iter()
+            .chain(args)
+            .map(|arg| ast::Parameter::Ty(arg.to_ast()))
+            .collect(),
+    }
+}
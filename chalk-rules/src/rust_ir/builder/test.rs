@@ -0,0 +1,71 @@
+#![cfg(test)]
+
+use super::{ProgramBuilder, Ty};
+use crate::test_util::*;
+use chalk_solve::solve::SolverChoice;
+
+#[test]
+fn build_same_as_parsed() {
+    let built = ProgramBuilder::new()
+        .trait_("Clone", 0)
+        .trait_("Iterator", 0)
+        .assoc_ty("Item")
+        .struct_("Foo", 0)
+        .struct_("Vec", 1)
+        .field("data", Ty::param(0))
+        .where_(Ty::param(0), "Clone", vec![])
+        .impl_(0, "Clone", Ty::apply("Foo", vec![]), vec![])
+        .impl_(1, "Clone", Ty::apply("Vec", vec![Ty::param(0)]), vec![])
+        .where_(Ty::param(0), "Clone", vec![])
+        .impl_(1, "Iterator", Ty::apply("Vec", vec![Ty::param(0)]), vec![])
+        .where_(Ty::param(0), "Clone", vec![])
+        .assoc_ty_value("Item", Ty::param(0))
+        .build(SolverChoice::default())
+        .unwrap();
+
+    let parsed = parse_and_lower_program(
+        "
+        trait Clone { }
+        trait Iterator { type Item; }
+        struct Foo { }
+        struct Vec<T0> where T0: Clone { data: T0 }
+        impl Clone for Foo { }
+        impl<T0> Clone for Vec<T0> where T0: Clone { }
+        impl<T0> Iterator for Vec<T0> where T0: Clone { type Item = T0; }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+
+    assert_eq!(built, *parsed);
+}
+
+#[test]
+fn build_errors() {
+    let error = ProgramBuilder::new()
+        .struct_("Foo", 0)
+        .impl_(0, "Clone", Ty::apply("Foo", vec![]), vec![])
+        .build(SolverChoice::default())
+        .unwrap_err();
+    assert!(error.to_string().contains("invalid type name `Clone`"));
+
+    // The impl is not well-formed, as `Foo` does not implement `Clone`.
+    let error = ProgramBuilder::new()
+        .trait_("Clone", 0)
+        .trait_("Copy", 0)
+        .where_(Ty::SelfTy, "Clone", vec![])
+        .struct_("Foo", 0)
+        .impl_(0, "Copy", Ty::apply("Foo", vec![]), vec![])
+        .build(SolverChoice::default())
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "trait impl for \"Copy\" does not meet well-formedness requirements"
+    );
+}
+
+#[test]
+#[should_panic(expected = "`field` must follow a struct")]
+fn field_without_struct() {
+    ProgramBuilder::new().trait_("Clone", 0).field("data", Ty::param(0));
+}
@@ -0,0 +1,126 @@
+//! Comparing two lowered programs item by item, e.g. to find what an edit
+//! invalidates, or to compare the output of lowering across versions.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chalk_ir::{ItemId, ProgramClause};
+
+use super::Program;
+
+mod test;
+
+/// How two programs differ, as computed by `diff`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramDiff {
+    pub structs: ItemDiff,
+    pub traits: ItemDiff,
+    pub impls: ItemDiff,
+
+    /// Associated types are reported apart from the traits that define
+    /// them, so that a trait whose only change is in one of its
+    /// associated types does not show up in `traits`.
+    pub associated_tys: ItemDiff,
+
+    pub clauses: ClauseDiff,
+}
+
+/// The items of one sort that differ between two programs. Items are
+/// matched up by `ItemId`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ItemDiff {
+    /// Items that are only in the new program.
+    pub added: BTreeSet<ItemId>,
+
+    /// Items that are only in the old program.
+    pub removed: BTreeSet<ItemId>,
+
+    /// Items that are in both programs, but lowered differently.
+    pub changed: BTreeSet<ItemId>,
+}
+
+/// The custom clauses that differ between two programs. Clauses have no
+/// ids to match them up by, so a changed clause shows up as one removed
+/// clause and one added clause.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClauseDiff {
+    /// Clauses of the new program that the old one does not have, in the
+    /// order of the new program.
+    pub added: Vec<ProgramClause>,
+
+    /// Clauses of the old program that the new one does not have, in the
+    /// order of the old program.
+    pub removed: Vec<ProgramClause>,
+}
+
+/// Compares the program `old` with the program `new`.
+pub fn diff(old: &Program, new: &Program) -> ProgramDiff {
+    ProgramDiff {
+        structs: ItemDiff::new(&old.struct_data, &new.struct_data),
+        traits: ItemDiff::new(&old.trait_data, &new.trait_data),
+        impls: ItemDiff::new(&old.impl_data, &new.impl_data),
+        associated_tys: ItemDiff::new(&old.associated_ty_data, &new.associated_ty_data),
+        clauses: ClauseDiff::new(&old.custom_clauses, &new.custom_clauses),
+    }
+}
+
+impl ProgramDiff {
+    pub fn is_empty(&self) -> bool {
+        self.structs.is_empty()
+            && self.traits.is_empty()
+            && self.impls.is_empty()
+            && self.associated_tys.is_empty()
+            && self.clauses.is_empty()
+    }
+}
+
+impl ItemDiff {
+    crate fn new<V: PartialEq>(old: &BTreeMap<ItemId, V>, new: &BTreeMap<ItemId, V>) -> Self {
+        let mut diff = ItemDiff::default();
+        for (&id, value) in old {
+            match new.get(&id) {
+                None => diff.removed.insert(id),
+                Some(new_value) if new_value != value => diff.changed.insert(id),
+                Some(_) => false,
+            };
+        }
+        diff.added
+            .extend(new.keys().filter(|id| !old.contains_key(id)));
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// All the items that were added, removed or changed.
+    pub fn items(&self) -> BTreeSet<ItemId> {
+        self.added
+            .iter()
+            .chain(&self.removed)
+            .chain(&self.changed)
+            .cloned()
+            .collect()
+    }
+}
+
+impl ClauseDiff {
+    fn new(old: &[ProgramClause], new: &[ProgramClause]) -> Self {
+        // A clause may appear several times, so each clause of `old`
+        // accounts for at most one equal clause of `new`.
+        let mut added = new.to_vec();
+        let mut removed = vec![];
+        for clause in old {
+            match added.iter().position(|added_clause| added_clause == clause) {
+                Some(index) => {
+                    added.remove(index);
+                }
+                None => removed.push(clause.clone()),
+            }
+        }
+        ClauseDiff { added, removed }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
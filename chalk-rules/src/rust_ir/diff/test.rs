@@ -0,0 +1,70 @@
+#![cfg(test)]
+
+use super::diff;
+use crate::test_util::*;
+use chalk_ir::ItemId;
+use chalk_solve::solve::SolverChoice;
+
+#[test]
+fn diff_items() {
+    let old = parse_and_lower_program(
+        "
+        trait Clone { }
+        trait Iterator { type Item; }
+        struct Foo { }
+        struct Bar { }
+        impl Clone for Foo { }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+    let new = parse_and_lower_program(
+        "
+        trait Clone { }
+        trait Iterator { type Item: Clone; }
+        struct Foo { field: Bar }
+        struct Bar { }
+        impl Clone for Bar { }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+
+    let changes = diff(&old, &new);
+    let ids = |paths: &[&str]| paths.iter().map(|path| ItemId::from_path(path, 0)).collect();
+    assert_eq!(changes.structs.changed, ids(&["Foo"]));
+    assert!(changes.structs.added.is_empty() && changes.structs.removed.is_empty());
+    assert!(changes.traits.is_empty());
+    assert_eq!(changes.associated_tys.changed, ids(&["Iterator::Item"]));
+    assert_eq!(changes.impls.added, ids(&["impl Clone for Bar"]));
+    assert_eq!(changes.impls.removed, ids(&["impl Clone for Foo"]));
+    assert!(changes.clauses.is_empty());
+    assert!(diff(&new, &new).is_empty());
+}
+
+#[test]
+fn diff_clauses() {
+    let old = parse_and_lower_program(
+        "
+        struct Foo { }
+        forall<T> { WellFormed(Foo) }
+        forall<T> { FromEnv(Foo) }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+    let new = parse_and_lower_program(
+        "
+        struct Foo { }
+        forall<T> { FromEnv(Foo) }
+        forall<T> { FromEnv(Foo) }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+
+    let changes = diff(&old, &new);
+    assert_eq!(changes.clauses.added, new.custom_clauses[1..].to_vec());
+    assert_eq!(changes.clauses.removed, old.custom_clauses[..1].to_vec());
+    assert!(changes.structs.is_empty());
+}
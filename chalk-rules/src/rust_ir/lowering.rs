@@ -0,0 +1,2025 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chalk_parse::ast::*;
+
+use crate::rust_ir::{self, Anonymize, IntoWhereClauses};
+use chalk_ir;
+use chalk_ir::cast::{Cast, Caster};
+use chalk_ir::fold::shift::Shift;
+use chalk_macros::intern::{intern, InternedString};
+use failure::{Fail, Fallible};
+use itertools::Itertools;
+use rustc_hash::FxHashMap;
+use std::fmt;
+
+mod test;
+
+type TypeKinds = BTreeMap<chalk_ir::ItemId, rust_ir::TypeKind>;
+
+// Lowering only ever looks names up in these maps (and never iterates
+// over them in an order that matters), so they are hash maps.
+type TypeIds = FxHashMap<chalk_ir::Identifier, chalk_ir::ItemId>;
+type AssociatedTyInfos = FxHashMap<(chalk_ir::ItemId, chalk_ir::Identifier), AssociatedTyInfo>;
+type ParameterMap = FxHashMap<chalk_ir::ParameterKind<chalk_ir::Identifier>, usize>;
+
+#[derive(Fail, Debug)]
+pub enum LoweringError {
+    #[fail(display = "invalid type name `{}`", _0)]
+    InvalidTypeName(Identifier),
+    #[fail(display = "duplicate lang item `{:?}`", _0)]
+    DuplicateLangItem(rust_ir::LangItem),
+    #[fail(display = "expected a trait, found `{}`, which is not a trait", _0)]
+    NotTrait(Identifier),
+    #[fail(
+        display = "`{}` takes {} type parameters, not {}",
+        identifier, expected, actual
+    )]
+    IncorrectNumberOfTypeParameters {
+        identifier: Identifier,
+        expected: usize,
+        actual: usize,
+    },
+    #[fail(display = "cannot apply type parameter `{}`", _0)]
+    CannotApplyTypeParameter(Identifier),
+    #[fail(display = "invalid lifetime name: {:?}", _0)]
+    InvalidLifetimeName(chalk_ir::Identifier),
+    #[fail(display = "duplicate or shadowed parameters")]
+    DuplicateParameters,
+    #[fail(display = "auto trait cannot define associated types")]
+    AutoTraitAssociatedTypes(Identifier),
+    #[fail(display = "auto trait cannot have parameters")]
+    AutoTraitParameters(Identifier),
+    #[fail(display = "auto trait cannot have where clauses")]
+    AutoTraitWhereClauses(Identifier),
+    #[fail(display = "Only fundamental types with a single parameter are supported")]
+    FundamentalTypeParameters(Identifier),
+    #[fail(
+        display = "{}: expected {}, found {} (parameter {} of `{}`)",
+        context, expected, actual, index, identifier
+    )]
+    IncorrectParameterKind {
+        context: &'static str,
+        /// The item whose parameter it is.
+        identifier: Identifier,
+        /// The position of the parameter among the arguments written
+        /// for it, counting from 0.
+        index: usize,
+        expected: Kind,
+        actual: Kind,
+    },
+    #[fail(
+        display = "wrong number of parameters, expected `{:?}`, got `{:?}`",
+        expected, actual
+    )]
+    IncorrectNumberOfTraitParameters {
+        identifier: Identifier,
+        expected: usize,
+        actual: usize,
+    },
+    #[fail(display = "no associated type `{}` defined in trait", _0)]
+    MissingAssociatedType(Identifier),
+    #[fail(
+        display = "wrong number of parameters for associated type (expected {}, got {})",
+        expected, actual
+    )]
+    IncorrectNumberOfAssociatedTypeParameters {
+        identifier: Identifier,
+        expected: usize,
+        actual: usize,
+    },
+    #[fail(display = "negative impls cannot define associated values")]
+    NegativeImplAssociatedValues(Identifier),
+    #[fail(display = "duplicate type name `{}`", _0)]
+    DuplicateTypeName(chalk_ir::Identifier),
+    #[fail(display = "no disambiguator left to renumber item {:?}", _0)]
+    NoFreeItemId(chalk_ir::ItemId),
+}
+
+impl LoweringError {
+    /// A code identifying the kind of error, which stays the same when
+    /// the wording of the message changes.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LoweringError::InvalidTypeName(_) => "L0001",
+            LoweringError::DuplicateLangItem(_) => "L0002",
+            LoweringError::NotTrait(_) => "L0003",
+            LoweringError::IncorrectNumberOfTypeParameters { .. } => "L0004",
+            LoweringError::CannotApplyTypeParameter(_) => "L0005",
+            LoweringError::InvalidLifetimeName(_) => "L0006",
+            LoweringError::DuplicateParameters => "L0007",
+            LoweringError::AutoTraitAssociatedTypes(_) => "L0008",
+            LoweringError::AutoTraitParameters(_) => "L0009",
+            LoweringError::AutoTraitWhereClauses(_) => "L0010",
+            LoweringError::FundamentalTypeParameters(_) => "L0011",
+            LoweringError::IncorrectParameterKind { .. } => "L0012",
+            LoweringError::IncorrectNumberOfTraitParameters { .. } => "L0013",
+            LoweringError::MissingAssociatedType(_) => "L0014",
+            LoweringError::IncorrectNumberOfAssociatedTypeParameters { .. } => "L0015",
+            LoweringError::NegativeImplAssociatedValues(_) => "L0016",
+            LoweringError::DuplicateTypeName(_) => "L0017",
+            LoweringError::NoFreeItemId(_) => "L0018",
+        }
+    }
+
+    /// The message prefixed with the error code, e.g.
+    /// `error[L0003]: expected a trait, found `Foo`, which is not a trait`.
+    pub fn render(&self) -> String {
+        format!("error[{}]: {}", self.code(), self)
+    }
+}
+
+/// A lowering error, along with the span of the source text it is
+/// about: the name of the offending item, where clause or type. The
+/// error displays as `error` does.
+#[derive(Debug)]
+pub struct SpannedError {
+    pub span: Span,
+    pub error: LoweringError,
+}
+
+impl SpannedError {
+    /// As `LoweringError::render`, followed by the span.
+    pub fn render(&self) -> String {
+        format!("{} (at {}..{})", self.error.render(), self.span.lo, self.span.hi)
+    }
+}
+
+impl Fail for SpannedError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        Some(&self.error)
+    }
+}
+
+impl fmt::Display for SpannedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
+
+/// All the errors found while lowering a program, in the order of the
+/// items they are about. Displays as its error if there is only one, and
+/// as a list of them otherwise.
+#[derive(Debug)]
+pub struct LoweringErrors {
+    pub errors: Vec<failure::Error>,
+}
+
+impl LoweringErrors {
+    /// Succeeds if `errors` is empty, and fails with all of them
+    /// otherwise.
+    crate fn check(errors: Vec<failure::Error>) -> Fallible<()> {
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(LoweringErrors { errors }.into())
+        }
+    }
+}
+
+impl Fail for LoweringErrors {}
+
+impl fmt::Display for LoweringErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.errors[..] {
+            [error] => fmt::Display::fmt(error, f),
+            errors => {
+                write!(f, "{} errors:", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Attributes `error` to the source text at `span`.
+fn spanned(span: Span, error: LoweringError) -> failure::Error {
+    SpannedError { span, error }.into()
+}
+
+trait AtSpan {
+    /// Attributes the error, if any, to `span`, unless it is already
+    /// attributed to something more precise (or is not a lowering
+    /// error).
+    fn at_span(self, span: Span) -> Self;
+}
+
+impl<T> AtSpan for Fallible<T> {
+    fn at_span(self, span: Span) -> Self {
+        self.map_err(|error| match error.downcast::<LoweringError>() {
+            Ok(error) => spanned(span, error),
+            Err(error) => error,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Env<'k> {
+    type_ids: &'k TypeIds,
+    type_kinds: &'k TypeKinds,
+    associated_ty_infos: &'k AssociatedTyInfos,
+    /// Parameter identifiers are used as keys, therefore
+    /// all indentifiers in an environment must be unique (no shadowing).
+    parameter_map: ParameterMap,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct AssociatedTyInfo {
+    id: chalk_ir::ItemId,
+    addl_parameter_kinds: Vec<chalk_ir::ParameterKind<chalk_ir::Identifier>>,
+}
+
+enum NameLookup {
+    Type(chalk_ir::ItemId),
+    Parameter(usize),
+}
+
+enum LifetimeLookup {
+    Parameter(usize),
+}
+
+const SELF: &str = "Self";
+
+impl<'k> Env<'k> {
+    /// An environment without parameters, for the items of `program`.
+    fn empty(program: &'k rust_ir::Program, associated_ty_infos: &'k AssociatedTyInfos) -> Self {
+        Env {
+            type_ids: &program.type_ids,
+            type_kinds: &program.type_kinds,
+            associated_ty_infos,
+            parameter_map: ParameterMap::default(),
+        }
+    }
+
+    fn lookup(&self, name: Identifier) -> Fallible<NameLookup> {
+        if let Some(k) = self
+            .parameter_map
+            .get(&chalk_ir::ParameterKind::Ty(name.str))
+        {
+            return Ok(NameLookup::Parameter(*k));
+        }
+
+        if let Some(id) = self.type_ids.get(&name.str) {
+            return Ok(NameLookup::Type(*id));
+        }
+
+        Err(spanned(name.span, LoweringError::InvalidTypeName(name)))
+    }
+
+    fn lookup_lifetime(&self, name: Identifier) -> Fallible<LifetimeLookup> {
+        if let Some(k) = self
+            .parameter_map
+            .get(&chalk_ir::ParameterKind::Lifetime(name.str))
+        {
+            return Ok(LifetimeLookup::Parameter(*k));
+        }
+
+        Err(spanned(
+            name.span,
+            LoweringError::InvalidLifetimeName(name.str),
+        ))
+    }
+
+    fn type_kind(&self, id: chalk_ir::ItemId) -> &rust_ir::TypeKind {
+        &self.type_kinds[&id]
+    }
+
+    /// Introduces new parameters, shifting the indices of existing
+    /// parameters to accommodate them. The indices of the new binders
+    /// will be assigned in order as they are iterated.
+    fn introduce<I>(&self, binders: I) -> Fallible<Self>
+    where
+        I: IntoIterator<Item = chalk_ir::ParameterKind<chalk_ir::Identifier>>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let binders = binders.into_iter().enumerate().map(|(i, k)| (k, i));
+        let len = binders.len();
+        let parameter_map: ParameterMap = self
+            .parameter_map
+            .iter()
+            .map(|(&k, &v)| (k, v + len))
+            .chain(binders)
+            .collect();
+        if parameter_map.len() != self.parameter_map.len() + len {
+            Err(LoweringError::DuplicateParameters)?;
+        }
+        Ok(Env {
+            parameter_map,
+            ..*self
+        })
+    }
+
+    fn in_binders<I, T, OP>(&self, binders: I, op: OP) -> Fallible<chalk_ir::Binders<T>>
+    where
+        I: IntoIterator<Item = chalk_ir::ParameterKind<chalk_ir::Identifier>>,
+        I::IntoIter: ExactSizeIterator,
+        OP: FnOnce(&Self) -> Fallible<T>,
+    {
+        let binders: Vec<_> = binders.into_iter().collect();
+        let env = self.introduce(binders.iter().cloned())?;
+        Ok(chalk_ir::Binders {
+            binders: binders.anonymize(),
+            value: op(&env)?,
+            names: binders.names(),
+        })
+    }
+}
+
+pub trait LowerProgram {
+    /// Lowers from a Program AST to the internal IR for a program. An
+    /// error in one item does not keep the others from being lowered: the
+    /// errors of all of them are reported together, as `LoweringErrors`.
+    fn lower(&self) -> Fallible<rust_ir::Program>;
+}
+
+impl LowerProgram for Program {
+    fn lower(&self) -> Fallible<rust_ir::Program> {
+        let scope = ProgramScope::new(self)?;
+        let mut program = rust_ir::Program {
+            type_ids: scope.type_ids.clone(),
+            type_kinds: scope.type_kinds.clone(),
+            struct_data: BTreeMap::new(),
+            trait_data: BTreeMap::new(),
+            impl_data: BTreeMap::new(),
+            associated_ty_data: BTreeMap::new(),
+            item_order: scope.item_order.clone(),
+            custom_clauses: Vec::new(),
+            lang_items: BTreeMap::new(),
+            specializations: BTreeSet::new(),
+        };
+        let errors = self
+            .items
+            .iter()
+            .zip(&scope.item_ids)
+            .filter_map(|(item, &item_id)| scope.lower_item(item, item_id, &mut program).err())
+            .collect();
+        LoweringErrors::check(errors)?;
+
+        Ok(program)
+    }
+}
+
+/// What lowering an item needs to know about the other items of its
+/// program: their ids, and the kinds of the types and associated types
+/// they define.
+crate struct ProgramScope {
+    /// The id of each item of the program, in order.
+    crate item_ids: Vec<chalk_ir::ItemId>,
+    /// The ids of the structs, traits, associated types and impls, in the
+    /// order of `rust_ir::Program::item_order`.
+    item_order: Vec<chalk_ir::ItemId>,
+    type_ids: TypeIds,
+    type_kinds: TypeKinds,
+    associated_ty_infos: AssociatedTyInfos,
+}
+
+impl ProgramScope {
+    /// Fails with the `LoweringErrors` of all the items whose names or
+    /// kinds cannot be determined; the items themselves are not lowered
+    /// then, as the scope they would be lowered in is incomplete.
+    crate fn new(program: &Program) -> Fallible<Self> {
+        // Make a vector mapping each thing in `items` to an id:
+        let mut used_ids = BTreeSet::new();
+        let item_ids: Vec<_> = program
+            .items
+            .iter()
+            .map(|item| fresh_item_id(&mut used_ids, item.path()))
+            .collect();
+
+        let mut errors = vec![];
+
+        // Create ids for associated types, and record the order in which
+        // the items are declared
+        let mut associated_ty_infos = AssociatedTyInfos::default();
+        let mut item_order = vec![];
+        for (item, &item_id) in program.items.iter().zip(&item_ids) {
+            match *item {
+                Item::StructDefn(_) | Item::Impl(_) => item_order.push(item_id),
+                Item::TraitDefn(ref d) => {
+                    item_order.push(item_id);
+                    match new_associated_ty_infos(d, item_id, &mut used_ids) {
+                        Ok(infos) => {
+                            item_order.extend(infos.iter().map(|(_, info)| info.id));
+                            associated_ty_infos.extend(infos);
+                        }
+                        Err(error) => errors.push(error),
+                    }
+                }
+                Item::Clause(_) => {}
+            }
+        }
+
+        let mut type_ids = TypeIds::default();
+        let mut type_kinds = BTreeMap::new();
+        for (item, &item_id) in program.items.iter().zip(&item_ids) {
+            let k = match *item {
+                Item::StructDefn(ref d) => d.lower_type_kind(),
+                Item::TraitDefn(ref d) => d.lower_type_kind(),
+                Item::Impl(_) => continue,
+                Item::Clause(_) => continue,
+            };
+            match k {
+                Ok(k) => {
+                    type_ids.insert(k.name, item_id);
+                    type_kinds.insert(item_id, k);
+                }
+                Err(error) => errors.push(error),
+            }
+        }
+        LoweringErrors::check(errors)?;
+
+        Ok(ProgramScope {
+            item_ids,
+            item_order,
+            type_ids,
+            type_kinds,
+            associated_ty_infos,
+        })
+    }
+
+    /// The ids of the associated types of the trait `trait_id`.
+    crate fn associated_ty_ids(&self, trait_id: chalk_ir::ItemId) -> BTreeSet<chalk_ir::ItemId> {
+        self.associated_ty_infos
+            .iter()
+            .filter(|((id, _), _)| *id == trait_id)
+            .map(|(_, info)| info.id)
+            .collect()
+    }
+
+    /// The id of the struct or trait named `name`, if there is one.
+    crate fn type_id(&self, name: &Identifier) -> Option<chalk_ir::ItemId> {
+        self.type_ids.get(&name.str).cloned()
+    }
+
+    /// Sets the types of `program`, and the order of its items, to those
+    /// of this scope.
+    crate fn set_types(&self, program: &mut rust_ir::Program) {
+        program.type_ids = self.type_ids.clone();
+        program.type_kinds = self.type_kinds.clone();
+        program.item_order = self.item_order.clone();
+    }
+
+    /// Lowers `item`, whose id is `item_id`, and adds it to `program`.
+    crate fn lower_item(
+        &self,
+        item: &Item,
+        item_id: chalk_ir::ItemId,
+        program: &mut rust_ir::Program,
+    ) -> Fallible<()> {
+        let empty_env = Env {
+            type_ids: &self.type_ids,
+            type_kinds: &self.type_kinds,
+            associated_ty_infos: &self.associated_ty_infos,
+            parameter_map: ParameterMap::default(),
+        };
+
+        match *item {
+            Item::StructDefn(ref d) => {
+                let datum = d.lower_struct(item_id, &empty_env).at_span(d.name.span)?;
+                program.struct_data.insert(item_id, datum);
+            }
+            Item::TraitDefn(ref d) => {
+                let datum = d.lower_trait(item_id, &empty_env).at_span(d.name.span)?;
+                program.trait_data.insert(item_id, datum);
+                lower_associated_tys(d, item_id, &empty_env, &mut program.associated_ty_data)?;
+                add_lang_items(d, item_id, &mut program.lang_items)?;
+            }
+            Item::Impl(ref d) => {
+                let span = d.trait_ref.trait_ref().trait_name.span;
+                program
+                    .impl_data
+                    .insert(item_id, d.lower_impl(&empty_env).at_span(span)?);
+            }
+            Item::Clause(ref clause) => {
+                program
+                    .custom_clauses
+                    .extend(clause.lower_clause(&empty_env)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Gives `path` the first id that is not in `used_ids` yet, and adds it
+/// there. Ids are based on the paths of items, and disambiguated by the
+/// order in which the items that share a path are declared (a hash
+/// collision between two paths is handled the same way).
+fn fresh_item_id(used_ids: &mut BTreeSet<chalk_ir::ItemId>, path: String) -> chalk_ir::ItemId {
+    (0..)
+        .map(|disambiguator| chalk_ir::ItemId::from_path(&path, disambiguator))
+        .find(|&id| used_ids.insert(id))
+        .unwrap()
+}
+
+/// Creates ids for the associated types of the trait `d`.
+fn new_associated_ty_infos(
+    d: &TraitDefn,
+    trait_id: chalk_ir::ItemId,
+    used_ids: &mut BTreeSet<chalk_ir::ItemId>,
+) -> Fallible<Vec<((chalk_ir::ItemId, chalk_ir::Identifier), AssociatedTyInfo)>> {
+    if d.flags.auto && !d.assoc_ty_defns.is_empty() {
+        return Err(spanned(
+            d.name.span,
+            LoweringError::AutoTraitAssociatedTypes(d.name),
+        ));
+    }
+    let infos = d
+        .assoc_ty_defns
+        .iter()
+        .map(|defn| {
+            let info = AssociatedTyInfo {
+                id: fresh_item_id(used_ids, format!("{}::{}", d.name, defn.name)),
+                addl_parameter_kinds: defn.all_parameters(),
+            };
+            ((trait_id, defn.name.str), info)
+        })
+        .collect();
+    Ok(infos)
+}
+
+/// Lowers the associated types of the trait `d`, whose ids must be in
+/// `env` already, into `associated_ty_data`.
+fn lower_associated_tys(
+    d: &TraitDefn,
+    trait_id: chalk_ir::ItemId,
+    empty_env: &Env,
+    associated_ty_data: &mut BTreeMap<chalk_ir::ItemId, rust_ir::AssociatedTyDatum>,
+) -> Fallible<()> {
+    for defn in &d.assoc_ty_defns {
+        let info = &empty_env.associated_ty_infos[&(trait_id, defn.name.str)];
+
+        let mut parameter_kinds = defn.all_parameters();
+        parameter_kinds.extend(d.all_parameters());
+        let span = defn.name.span;
+        let env = empty_env.introduce(parameter_kinds.clone()).at_span(span)?;
+
+        associated_ty_data.insert(
+            info.id,
+            rust_ir::AssociatedTyDatum {
+                trait_id,
+                id: info.id,
+                name: defn.name.str,
+                parameter_kinds: parameter_kinds,
+                bounds: defn.bounds.lower(&env).at_span(span)?,
+                where_clauses: defn.where_clauses.lower(&env).at_span(span)?,
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Records the lang items that the trait `d` is declared to be.
+fn add_lang_items(
+    d: &TraitDefn,
+    trait_id: chalk_ir::ItemId,
+    lang_items: &mut BTreeMap<rust_ir::LangItem, chalk_ir::ItemId>,
+) -> Fallible<()> {
+    let builtin = d.flags.builtin;
+    let trait_lang_items = [
+        (d.flags.deref, rust_ir::LangItem::DerefTrait),
+        (d.flags.drop, rust_ir::LangItem::DropTrait),
+        (builtin == Some(BuiltinLangItem::Sized), rust_ir::LangItem::SizedTrait),
+        (builtin == Some(BuiltinLangItem::Copy), rust_ir::LangItem::CopyTrait),
+        (builtin == Some(BuiltinLangItem::Clone), rust_ir::LangItem::CloneTrait),
+        (builtin == Some(BuiltinLangItem::FnOnce), rust_ir::LangItem::FnOnceTrait),
+        (builtin == Some(BuiltinLangItem::FnMut), rust_ir::LangItem::FnMutTrait),
+        (builtin == Some(BuiltinLangItem::Fn), rust_ir::LangItem::FnTrait),
+    ];
+    for (flag, lang_item) in trait_lang_items.iter().cloned() {
+        if !flag {
+            continue;
+        }
+
+        use std::collections::btree_map::Entry::*;
+        match lang_items.entry(lang_item.clone()) {
+            Vacant(entry) => {
+                entry.insert(trait_id);
+            }
+            Occupied(_) => Err(spanned(
+                d.name.span,
+                LoweringError::DuplicateLangItem(lang_item),
+            ))?,
+        }
+    }
+    Ok(())
+}
+
+trait ItemPath {
+    /// The path from which the id of the item is derived.
+    fn path(&self) -> String;
+}
+
+impl ItemPath for Item {
+    fn path(&self) -> String {
+        match *self {
+            Item::StructDefn(ref d) => d.name.to_string(),
+            Item::TraitDefn(ref d) => d.name.to_string(),
+            Item::Impl(ref d) => d.path(),
+            Item::Clause(_) => "{clause}".to_string(),
+        }
+    }
+}
+
+impl ItemPath for Impl {
+    fn path(&self) -> String {
+        // Impls have no name, so they are identified by their trait
+        // reference, written out in full. Like lowering, the path refers
+        // to the parameters of the impl by position (`^0`, `^1`...), so
+        // that renaming them does not change the id.
+        let (polarity, trait_ref) = match self.trait_ref {
+            PolarizedTraitRef::Positive(ref trait_ref) => ("", trait_ref),
+            PolarizedTraitRef::Negative(ref trait_ref) => ("!", trait_ref),
+        };
+        let mut writer = PathWriter::default();
+        writer.introduce(&self.parameter_kinds);
+        writer.push(&format!("impl {}{}", polarity, trait_ref.trait_name));
+        writer.args(&trait_ref.args[1..]);
+        writer.push(" for ");
+        writer.parameter(&trait_ref.args[0]);
+        writer.path
+    }
+}
+
+/// Writes out types for the paths of impls (see `ItemPath for Impl`).
+#[derive(Default)]
+struct PathWriter {
+    path: String,
+    /// The names of the parameters in scope, innermost binder first, as
+    /// `Env::introduce` numbers them.
+    parameters: Vec<InternedString>,
+}
+
+impl PathWriter {
+    fn introduce(&mut self, parameter_kinds: &[ParameterKind]) {
+        let names = parameter_kinds.iter().map(|pk| match *pk {
+            ParameterKind::Ty(name) | ParameterKind::Lifetime(name) => name.str,
+        });
+        self.parameters.splice(0..0, names);
+    }
+
+    fn leave(&mut self, parameter_kinds: &[ParameterKind]) {
+        self.parameters.drain(0..parameter_kinds.len());
+    }
+
+    fn push(&mut self, text: &str) {
+        self.path.push_str(text);
+    }
+
+    fn name(&mut self, name: Identifier) {
+        match self.parameters.iter().position(|&p| p == name.str) {
+            Some(index) => self.push(&format!("^{}", index)),
+            None => self.push(&name.to_string()),
+        }
+    }
+
+    fn list<T>(&mut self, values: &[T], separator: &str, mut write: impl FnMut(&mut Self, &T)) {
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                self.push(separator);
+            }
+            write(self, value);
+        }
+    }
+
+    /// Writes `<args>`, or nothing if there are no `args`.
+    fn args(&mut self, args: &[Parameter]) {
+        if !args.is_empty() {
+            self.push("<");
+            self.list(args, ", ", Self::parameter);
+            self.push(">");
+        }
+    }
+
+    fn parameter(&mut self, parameter: &Parameter) {
+        match *parameter {
+            Parameter::Ty(ref ty) => self.ty(ty),
+            Parameter::Lifetime(Lifetime::Id { name }) => self.name(name),
+        }
+    }
+
+    fn ty(&mut self, ty: &Ty) {
+        match *ty {
+            Ty::Id { name } => self.name(name),
+            Ty::Apply { name, ref args } => {
+                self.name(name);
+                self.args(args);
+            }
+            Ty::Projection { ref proj } => {
+                self.push("<");
+                self.parameter(&proj.trait_ref.args[0]);
+                self.push(&format!(" as {}", proj.trait_ref.trait_name));
+                self.args(&proj.trait_ref.args[1..]);
+                self.push(&format!(">::{}", proj.name));
+                self.args(&proj.args);
+            }
+            Ty::UnselectedProjection { ref proj } => {
+                self.parameter(&proj.args[0]);
+                self.push(&format!("::{}", proj.name));
+                self.args(&proj.args[1..]);
+            }
+            Ty::ForAll {
+                ref parameter_kinds,
+                ref ty,
+            } => {
+                self.push(&format!("for<{}> ", parameter_kinds.len()));
+                self.introduce(parameter_kinds);
+                self.ty(ty);
+                self.leave(parameter_kinds);
+            }
+            Ty::Dyn { ref bounds } => {
+                self.push("dyn ");
+                self.list(bounds, " + ", Self::quantified_inline_bound);
+            }
+            Ty::Tuple { ref types } => {
+                self.push("(");
+                self.list(types, ", ", Self::ty);
+                self.push(")");
+            }
+            Ty::FnPtr { ref args, ref ret } => {
+                self.push("fn(");
+                self.list(args, ", ", Self::ty);
+                self.push(") -> ");
+                self.ty(ret);
+            }
+        }
+    }
+
+    fn quantified_inline_bound(&mut self, bound: &QuantifiedInlineBound) {
+        let parameter_kinds = &bound.parameter_kinds;
+        if !parameter_kinds.is_empty() {
+            self.push(&format!("for<{}> ", parameter_kinds.len()));
+        }
+        self.introduce(parameter_kinds);
+        let trait_bound = match bound.bound {
+            InlineBound::TraitBound(ref trait_bound) => trait_bound,
+            InlineBound::ProjectionEqBound(ref bound) => &bound.trait_bound,
+        };
+        self.push(&trait_bound.trait_name.to_string());
+        self.push("<");
+        self.list(&trait_bound.args_no_self, ", ", Self::parameter);
+        if let InlineBound::ProjectionEqBound(ref bound) = bound.bound {
+            if !trait_bound.args_no_self.is_empty() {
+                self.push(", ");
+            }
+            self.push(&bound.name.to_string());
+            self.args(&bound.args);
+            self.push(" = ");
+            self.ty(&bound.value);
+        }
+        self.push(">");
+        self.leave(parameter_kinds);
+    }
+}
+
+trait LowerTypeKind {
+    fn lower_type_kind(&self) -> Fallible<rust_ir::TypeKind>;
+}
+
+trait LowerParameterMap {
+    fn synthetic_parameters(&self) -> Option<chalk_ir::ParameterKind<chalk_ir::Identifier>>;
+    fn declared_parameters(&self) -> &[ParameterKind];
+    fn all_parameters(&self) -> Vec<chalk_ir::ParameterKind<chalk_ir::Identifier>> {
+        self.synthetic_parameters()
+            .into_iter()
+            .chain(self.declared_parameters().iter().map(|id| id.lower()))
+            .collect()
+
+        /* TODO: switch to this ordering, but adjust *all* the code to match
+
+        self.declared_parameters()
+            .iter()
+            .map(|id| id.lower())
+            .chain(self.synthetic_parameters()) // (*) see below
+            .collect()
+         */
+    }
+
+    fn parameter_refs(&self) -> Vec<chalk_ir::Parameter> {
+        chalk_ir::SubstBuilder::new(self.all_parameters().anonymize())
+            .fill_bound_vars()
+            .into_parameters()
+    }
+
+    fn parameter_map(&self) -> ParameterMap {
+        // (*) It is important that the declared parameters come
+        // before the subtle parameters in the ordering. This is
+        // because of traits, when used as types, only have the first
+        // N parameters in their kind (that is, they do not have Self).
+        //
+        // Note that if `Self` appears in the where-clauses etc, the
+        // trait is not object-safe, and hence not supposed to be used
+        // as an object. Actually the handling of object types is
+        // probably just kind of messed up right now. That's ok.
+        self.all_parameters().into_iter().zip(0..).collect()
+    }
+}
+
+impl LowerParameterMap for StructDefn {
+    fn synthetic_parameters(&self) -> Option<chalk_ir::ParameterKind<chalk_ir::Identifier>> {
+        None
+    }
+
+    fn declared_parameters(&self) -> &[ParameterKind] {
+        &self.parameter_kinds
+    }
+}
+
+impl LowerParameterMap for Impl {
+    fn synthetic_parameters(&self) -> Option<chalk_ir::ParameterKind<chalk_ir::Identifier>> {
+        None
+    }
+
+    fn declared_parameters(&self) -> &[ParameterKind] {
+        &self.parameter_kinds
+    }
+}
+
+impl LowerParameterMap for AssocTyDefn {
+    fn synthetic_parameters(&self) -> Option<chalk_ir::ParameterKind<chalk_ir::Identifier>> {
+        None
+    }
+
+    fn declared_parameters(&self) -> &[ParameterKind] {
+        &self.parameter_kinds
+    }
+}
+
+impl LowerParameterMap for AssocTyValue {
+    fn synthetic_parameters(&self) -> Option<chalk_ir::ParameterKind<chalk_ir::Identifier>> {
+        None
+    }
+
+    fn declared_parameters(&self) -> &[ParameterKind] {
+        &self.parameter_kinds
+    }
+}
+
+impl LowerParameterMap for TraitDefn {
+    fn synthetic_parameters(&self) -> Option<chalk_ir::ParameterKind<chalk_ir::Identifier>> {
+        Some(chalk_ir::ParameterKind::Ty(intern(SELF)))
+    }
+
+    fn declared_parameters(&self) -> &[ParameterKind] {
+        &self.parameter_kinds
+    }
+}
+
+impl LowerParameterMap for Clause {
+    fn synthetic_parameters(&self) -> Option<chalk_ir::ParameterKind<chalk_ir::Identifier>> {
+        None
+    }
+
+    fn declared_parameters(&self) -> &[ParameterKind] {
+        &self.parameter_kinds
+    }
+}
+
+trait LowerParameterKind {
+    fn lower(&self) -> chalk_ir::ParameterKind<chalk_ir::Identifier>;
+}
+
+impl LowerParameterKind for ParameterKind {
+    fn lower(&self) -> chalk_ir::ParameterKind<chalk_ir::Identifier> {
+        match *self {
+            ParameterKind::Ty(ref n) => chalk_ir::ParameterKind::Ty(n.str),
+            ParameterKind::Lifetime(ref n) => chalk_ir::ParameterKind::Lifetime(n.str),
+        }
+    }
+}
+
+trait LowerWhereClauses {
+    fn where_clauses(&self) -> &[QuantifiedWhereClause];
+
+    fn lower_where_clauses(&self, env: &Env) -> Fallible<Vec<chalk_ir::QuantifiedWhereClause>> {
+        self.where_clauses().lower(env)
+    }
+}
+
+impl LowerTypeKind for StructDefn {
+    fn lower_type_kind(&self) -> Fallible<rust_ir::TypeKind> {
+        Ok(rust_ir::TypeKind {
+            sort: rust_ir::TypeSort::Struct,
+            name: self.name.str,
+            binders: chalk_ir::Binders {
+                binders: self.all_parameters().anonymize(),
+                value: (),
+                names: self.all_parameters().names(),
+            },
+        })
+    }
+}
+
+impl LowerWhereClauses for StructDefn {
+    fn where_clauses(&self) -> &[QuantifiedWhereClause] {
+        &self.where_clauses
+    }
+}
+
+impl LowerTypeKind for TraitDefn {
+    fn lower_type_kind(&self) -> Fallible<rust_ir::TypeKind> {
+        let binders: Vec<_> = self.parameter_kinds.iter().map(|p| p.lower()).collect();
+        Ok(rust_ir::TypeKind {
+            sort: rust_ir::TypeSort::Trait,
+            name: self.name.str,
+            binders: chalk_ir::Binders {
+                // for the purposes of the *type*, ignore `Self`:
+                binders: binders.anonymize(),
+                value: (),
+                names: binders.names(),
+            },
+        })
+    }
+}
+
+impl LowerWhereClauses for TraitDefn {
+    fn where_clauses(&self) -> &[QuantifiedWhereClause] {
+        &self.where_clauses
+    }
+}
+
+impl LowerWhereClauses for Impl {
+    fn where_clauses(&self) -> &[QuantifiedWhereClause] {
+        &self.where_clauses
+    }
+}
+
+trait LowerWhereClauseVec {
+    fn lower(&self, env: &Env) -> Fallible<Vec<chalk_ir::QuantifiedWhereClause>>;
+}
+
+impl LowerWhereClauseVec for [QuantifiedWhereClause] {
+    fn lower(&self, env: &Env) -> Fallible<Vec<chalk_ir::QuantifiedWhereClause>> {
+        self.iter()
+            .flat_map(|wc| match wc.lower(env) {
+                Ok(v) => v.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            })
+            .collect()
+    }
+}
+
+trait LowerWhereClause<T> {
+    /// Lower from an AST `where` clause to an internal IR.
+    /// Some AST `where` clauses can lower to multiple ones, this is why we return a `Vec`.
+    /// As for now, this is the only the case for `where T: Foo<Item = U>` which lowers to
+    /// `Implemented(T: Foo)` and `ProjectionEq(<T as Foo>::Item = U)`.
+    fn lower(&self, env: &Env) -> Fallible<Vec<T>>;
+}
+
+impl LowerWhereClause<chalk_ir::WhereClause> for WhereClause {
+    fn lower(&self, env: &Env) -> Fallible<Vec<chalk_ir::WhereClause>> {
+        let where_clauses = match self {
+            WhereClause::Implemented { trait_ref } => {
+                vec![chalk_ir::WhereClause::Implemented(trait_ref.lower(env)?)]
+            }
+            WhereClause::ProjectionEq { projection, ty } => vec![
+                chalk_ir::WhereClause::ProjectionEq(chalk_ir::ProjectionEq {
+                    projection: projection.lower(env)?,
+                    ty: ty.lower(env)?,
+                }),
+                chalk_ir::WhereClause::Implemented(projection.trait_ref.lower(env)?),
+            ],
+            WhereClause::ConstEvaluatable { const_name, args } => {
+                let parameters = args
+                    .iter()
+                    .map(|a| a.lower(env))
+                    .collect::<Fallible<_>>()?;
+                vec![chalk_ir::WhereClause::ConstEvaluatable(
+                    chalk_ir::ConstEvaluatable {
+                        const_name: const_name.str,
+                        parameters,
+                    },
+                )]
+            }
+        };
+        Ok(where_clauses)
+    }
+}
+impl LowerWhereClause<chalk_ir::QuantifiedWhereClause> for QuantifiedWhereClause {
+    fn lower(&self, env: &Env) -> Fallible<Vec<chalk_ir::QuantifiedWhereClause>> {
+        let parameter_kinds = self.parameter_kinds.iter().map(|pk| pk.lower());
+        let binders = env.in_binders(parameter_kinds, |env| Ok(self.where_clause.lower(env)?))?;
+        Ok(binders.into_iter().collect())
+    }
+}
+
+trait LowerDomainGoal {
+    fn lower(&self, env: &Env) -> Fallible<Vec<chalk_ir::DomainGoal>>;
+}
+
+impl LowerDomainGoal for DomainGoal {
+    fn lower(&self, env: &Env) -> Fallible<Vec<chalk_ir::DomainGoal>> {
+        let goals = match self {
+            DomainGoal::Holds { where_clause } => {
+                where_clause.lower(env)?.into_iter().casted().collect()
+            }
+            DomainGoal::Normalize { projection, ty } => {
+                vec![chalk_ir::DomainGoal::Normalize(chalk_ir::Normalize {
+                    projection: projection.lower(env)?,
+                    ty: ty.lower(env)?,
+                })]
+            }
+            DomainGoal::TyWellFormed { ty } => vec![chalk_ir::DomainGoal::WellFormed(
+                chalk_ir::WellFormed::Ty(ty.lower(env)?),
+            )],
+            DomainGoal::TraitRefWellFormed { trait_ref } => vec![chalk_ir::DomainGoal::WellFormed(
+                chalk_ir::WellFormed::Trait(trait_ref.lower(env)?),
+            )],
+            DomainGoal::TyFromEnv { ty } => vec![chalk_ir::DomainGoal::FromEnv(
+                chalk_ir::FromEnv::Ty(ty.lower(env)?),
+            )],
+            DomainGoal::TraitRefFromEnv { trait_ref } => vec![chalk_ir::DomainGoal::FromEnv(
+                chalk_ir::FromEnv::Trait(trait_ref.lower(env)?),
+            )],
+            DomainGoal::TraitInScope { trait_name } => {
+                let id = match env.lookup(*trait_name)? {
+                    NameLookup::Type(id) => id,
+                    NameLookup::Parameter(_) => {
+                        Err(spanned(trait_name.span, LoweringError::NotTrait(*trait_name)))?
+                    }
+                };
+
+                if env.type_kind(id).sort != rust_ir::TypeSort::Trait {
+                    Err(spanned(trait_name.span, LoweringError::NotTrait(*trait_name)))?;
+                }
+
+                vec![chalk_ir::DomainGoal::InScope(id)]
+            }
+            DomainGoal::Derefs { source, target } => {
+                vec![chalk_ir::DomainGoal::Derefs(chalk_ir::Derefs {
+                    source: source.lower(env)?,
+                    target: target.lower(env)?,
+                })]
+            }
+            DomainGoal::IsLocal { ty } => vec![chalk_ir::DomainGoal::IsLocal(ty.lower(env)?)],
+            DomainGoal::IsUpstream { ty } => vec![chalk_ir::DomainGoal::IsUpstream(ty.lower(env)?)],
+            DomainGoal::IsFullyVisible { ty } => {
+                vec![chalk_ir::DomainGoal::IsFullyVisible(ty.lower(env)?)]
+            }
+            DomainGoal::LocalImplAllowed { trait_ref } => {
+                vec![chalk_ir::DomainGoal::LocalImplAllowed(
+                    trait_ref.lower(env)?,
+                )]
+            }
+            DomainGoal::Compatible => vec![chalk_ir::DomainGoal::Compatible(())],
+            DomainGoal::DownstreamType { ty } => {
+                vec![chalk_ir::DomainGoal::DownstreamType(ty.lower(env)?)]
+            }
+            DomainGoal::IsSized { ty } => vec![chalk_ir::DomainGoal::IsSized(ty.lower(env)?)],
+            DomainGoal::NeedsDrop { ty } => vec![chalk_ir::DomainGoal::NeedsDrop(ty.lower(env)?)],
+            DomainGoal::Custom { name, args } => {
+                let parameters = args
+                    .iter()
+                    .map(|a| a.lower(env))
+                    .collect::<Fallible<_>>()?;
+                vec![chalk_ir::DomainGoal::Custom(chalk_ir::CustomGoal {
+                    name: name.str,
+                    parameters,
+                })]
+            }
+        };
+        Ok(goals)
+    }
+}
+
+trait LowerLeafGoal {
+    fn lower(&self, env: &Env) -> Fallible<Vec<chalk_ir::LeafGoal>>;
+}
+
+impl LowerLeafGoal for LeafGoal {
+    fn lower(&self, env: &Env) -> Fallible<Vec<chalk_ir::LeafGoal>> {
+        let goals = match self {
+            LeafGoal::DomainGoal { goal } => goal
+                .lower(env)?
+                .into_iter()
+                .map(|goal| chalk_ir::LeafGoal::DomainGoal(goal))
+                .collect(),
+            LeafGoal::UnifyTys { a, b } => vec![chalk_ir::EqGoal {
+                a: chalk_ir::ParameterKind::Ty(a.lower(env)?),
+                b: chalk_ir::ParameterKind::Ty(b.lower(env)?),
+            }
+            .cast()],
+            LeafGoal::UnifyLifetimes { ref a, ref b } => vec![chalk_ir::EqGoal {
+                a: chalk_ir::ParameterKind::Lifetime(a.lower(env)?),
+                b: chalk_ir::ParameterKind::Lifetime(b.lower(env)?),
+            }
+            .cast()],
+            LeafGoal::Outlives { ref a, ref b } => vec![chalk_ir::OutlivesGoal {
+                a: a.lower(env)?,
+                b: b.lower(env)?,
+            }
+            .cast()],
+        };
+        Ok(goals)
+    }
+}
+
+trait LowerStructDefn {
+    fn lower_struct(&self, item_id: chalk_ir::ItemId, env: &Env) -> Fallible<rust_ir::StructDatum>;
+}
+
+impl LowerStructDefn for StructDefn {
+    fn lower_struct(&self, item_id: chalk_ir::ItemId, env: &Env) -> Fallible<rust_ir::StructDatum> {
+        let binders = env.in_binders(self.all_parameters(), |env| {
+            let self_ty = chalk_ir::ApplicationTy {
+                name: chalk_ir::TypeName::ItemId(item_id),
+                parameters: self.parameter_refs(),
+            };
+
+            if self.flags.fundamental && self_ty.len_type_parameters() != 1 {
+                return Err(spanned(
+                    self.name.span,
+                    LoweringError::FundamentalTypeParameters(self.name),
+                ));
+            }
+
+            let fields: Fallible<_> = self.fields.iter().map(|f| f.ty.lower(env)).collect();
+            let where_clauses = self.lower_where_clauses(env)?;
+
+            Ok(rust_ir::StructDatumBound {
+                self_ty,
+                fields: fields?,
+                where_clauses,
+                flags: rust_ir::StructFlags {
+                    upstream: self.flags.upstream,
+                    fundamental: self.flags.fundamental,
+                },
+            })
+        })?;
+
+        Ok(rust_ir::StructDatum { binders })
+    }
+}
+
+/// Checks that the argument `actual` given for the parameter `expected`
+/// of `identifier`, at position `index`, is of the right kind.
+fn check_type_kinds<A: Kinded, B: Kinded>(
+    msg: &'static str,
+    identifier: Identifier,
+    index: usize,
+    expected: &A,
+    actual: &B,
+) -> Fallible<()> {
+    let expected_kind = expected.kind();
+    let actual_kind = actual.kind();
+    if expected_kind != actual_kind {
+        Err(spanned(
+            identifier.span,
+            LoweringError::IncorrectParameterKind {
+                context: msg,
+                identifier,
+                index,
+                expected: expected_kind,
+                actual: actual_kind,
+            },
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+trait LowerTraitRef {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::TraitRef>;
+}
+
+impl LowerTraitRef for TraitRef {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::TraitRef> {
+        let without_self = TraitBound {
+            trait_name: self.trait_name,
+            args_no_self: self.args.iter().cloned().skip(1).collect(),
+        }
+        .lower(env)?;
+
+        let self_parameter = self.args[0].lower(env)?;
+        Ok(without_self.as_trait_ref(self_parameter.ty().unwrap()))
+    }
+}
+
+trait LowerTraitBound {
+    fn lower(&self, env: &Env) -> Fallible<rust_ir::TraitBound>;
+}
+
+impl LowerTraitBound for TraitBound {
+    fn lower(&self, env: &Env) -> Fallible<rust_ir::TraitBound> {
+        let span = self.trait_name.span;
+        let not_trait = || spanned(span, LoweringError::NotTrait(self.trait_name));
+        let id = match env.lookup(self.trait_name)? {
+            NameLookup::Type(id) => id,
+            NameLookup::Parameter(_) => Err(not_trait())?,
+        };
+
+        let k = env.type_kind(id);
+        if k.sort != rust_ir::TypeSort::Trait {
+            Err(not_trait())?;
+        }
+
+        let parameters = self
+            .args_no_self
+            .iter()
+            .map(|a| Ok(a.lower(env)?))
+            .collect::<Fallible<Vec<_>>>()?;
+
+        if parameters.len() != k.binders.len() {
+            return Err(spanned(
+                span,
+                LoweringError::IncorrectNumberOfTraitParameters {
+                    identifier: self.trait_name,
+                    expected: k.binders.len(),
+                    actual: parameters.len(),
+                },
+            ));
+        }
+
+        for (index, (binder, param)) in k.binders.binders.iter().zip(&parameters).enumerate() {
+            let msg = "incorrect kind for trait parameter";
+            check_type_kinds(msg, self.trait_name, index, binder, param)?;
+        }
+
+        Ok(rust_ir::TraitBound {
+            trait_id: id,
+            args_no_self: parameters,
+        })
+    }
+}
+
+trait LowerProjectionEqBound {
+    fn lower(&self, env: &Env) -> Fallible<rust_ir::ProjectionEqBound>;
+}
+
+impl LowerProjectionEqBound for ProjectionEqBound {
+    fn lower(&self, env: &Env) -> Fallible<rust_ir::ProjectionEqBound> {
+        let trait_bound = self.trait_bound.lower(env)?;
+        let info = match env
+            .associated_ty_infos
+            .get(&(trait_bound.trait_id, self.name.str))
+        {
+            Some(info) => info,
+            None => {
+                return Err(spanned(
+                    self.name.span,
+                    LoweringError::MissingAssociatedType(self.name),
+                ));
+            }
+        };
+        let args: Vec<_> = self
+            .args
+            .iter()
+            .map(|a| a.lower(env))
+            .collect::<Fallible<_>>()?;
+
+        if args.len() != info.addl_parameter_kinds.len() {
+            return Err(spanned(
+                self.name.span,
+                LoweringError::IncorrectNumberOfAssociatedTypeParameters {
+                    identifier: self.name,
+                    expected: info.addl_parameter_kinds.len(),
+                    actual: args.len(),
+                },
+            ));
+        }
+
+        for (index, (param, arg)) in info.addl_parameter_kinds.iter().zip(&args).enumerate() {
+            let msg = "incorrect kind for associated type parameter";
+            check_type_kinds(msg, self.name, index, param, arg)?;
+        }
+
+        Ok(rust_ir::ProjectionEqBound {
+            trait_bound,
+            associated_ty_id: info.id,
+            parameters: args,
+            value: self.value.lower(env)?,
+        })
+    }
+}
+
+trait LowerInlineBound {
+    fn lower(&self, env: &Env) -> Fallible<rust_ir::InlineBound>;
+}
+
+impl LowerInlineBound for InlineBound {
+    fn lower(&self, env: &Env) -> Fallible<rust_ir::InlineBound> {
+        let bound = match self {
+            InlineBound::TraitBound(b) => rust_ir::InlineBound::TraitBound(b.lower(&env)?),
+            InlineBound::ProjectionEqBound(b) => {
+                rust_ir::InlineBound::ProjectionEqBound(b.lower(&env)?)
+            }
+        };
+        Ok(bound)
+    }
+}
+
+trait LowerQuantifiedInlineBound {
+    fn lower(&self, env: &Env) -> Fallible<rust_ir::QuantifiedInlineBound>;
+}
+
+impl LowerQuantifiedInlineBound for QuantifiedInlineBound {
+    fn lower(&self, env: &Env) -> Fallible<rust_ir::QuantifiedInlineBound> {
+        let parameter_kinds = self.parameter_kinds.iter().map(|pk| pk.lower());
+        let binders = env.in_binders(parameter_kinds, |env| Ok(self.bound.lower(env)?))?;
+        Ok(binders)
+    }
+}
+
+trait LowerQuantifiedInlineBoundVec {
+    fn lower(&self, env: &Env) -> Fallible<Vec<rust_ir::QuantifiedInlineBound>>;
+}
+
+impl LowerQuantifiedInlineBoundVec for [QuantifiedInlineBound] {
+    fn lower(&self, env: &Env) -> Fallible<Vec<rust_ir::QuantifiedInlineBound>> {
+        self.iter().map(|b| b.lower(env)).collect()
+    }
+}
+
+trait LowerPolarizedTraitRef {
+    fn lower(&self, env: &Env) -> Fallible<rust_ir::PolarizedTraitRef>;
+}
+
+impl LowerPolarizedTraitRef for PolarizedTraitRef {
+    fn lower(&self, env: &Env) -> Fallible<rust_ir::PolarizedTraitRef> {
+        Ok(match *self {
+            PolarizedTraitRef::Positive(ref tr) => {
+                rust_ir::PolarizedTraitRef::Positive(tr.lower(env)?)
+            }
+            PolarizedTraitRef::Negative(ref tr) => {
+                rust_ir::PolarizedTraitRef::Negative(tr.lower(env)?)
+            }
+        })
+    }
+}
+
+trait LowerProjectionTy {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::ProjectionTy>;
+}
+
+impl LowerProjectionTy for ProjectionTy {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::ProjectionTy> {
+        let ProjectionTy {
+            ref trait_ref,
+            ref name,
+            ref args,
+        } = *self;
+        let chalk_ir::TraitRef {
+            trait_id,
+            parameters: trait_parameters,
+        } = trait_ref.lower(env)?;
+        let info = match env.associated_ty_infos.get(&(trait_id, name.str)) {
+            Some(info) => info,
+            None => {
+                return Err(spanned(
+                    name.span,
+                    LoweringError::MissingAssociatedType(*name),
+                ));
+            }
+        };
+        let mut args: Vec<_> = args.iter().map(|a| a.lower(env)).collect::<Fallible<_>>()?;
+
+        if args.len() != info.addl_parameter_kinds.len() {
+            return Err(spanned(
+                name.span,
+                LoweringError::IncorrectNumberOfAssociatedTypeParameters {
+                    identifier: *name,
+                    expected: info.addl_parameter_kinds.len(),
+                    actual: args.len(),
+                },
+            ));
+        }
+
+        for (index, (param, arg)) in info.addl_parameter_kinds.iter().zip(&args).enumerate() {
+            let msg = "incorrect kind for associated type parameter";
+            check_type_kinds(msg, *name, index, param, arg)?;
+        }
+
+        args.extend(trait_parameters);
+
+        Ok(chalk_ir::ProjectionTy {
+            associated_ty_id: info.id,
+            parameters: args,
+        })
+    }
+}
+
+trait LowerUnselectedProjectionTy {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::UnselectedProjectionTy>;
+}
+
+impl LowerUnselectedProjectionTy for UnselectedProjectionTy {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::UnselectedProjectionTy> {
+        let parameters: Vec<_> = self
+            .args
+            .iter()
+            .map(|a| a.lower(env))
+            .collect::<Fallible<_>>()?;
+        let ret = chalk_ir::UnselectedProjectionTy {
+            type_name: self.name.str,
+            parameters: parameters,
+        };
+        Ok(ret)
+    }
+}
+
+trait LowerTy {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::Ty>;
+}
+
+impl LowerTy for Ty {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::Ty> {
+        match *self {
+            Ty::Id { name } => match env.lookup(name)? {
+                NameLookup::Type(id) => {
+                    let k = env.type_kind(id);
+                    if k.binders.len() > 0 {
+                        let error = LoweringError::IncorrectNumberOfTypeParameters {
+                            identifier: name,
+                            expected: k.binders.len(),
+                            actual: 0,
+                        };
+                        Err(spanned(name.span, error))
+                    } else {
+                        Ok(chalk_ir::TyData::Apply(chalk_ir::ApplicationTy {
+                            name: chalk_ir::TypeName::ItemId(id),
+                            parameters: vec![],
+                        })
+                        .intern())
+                    }
+                }
+                NameLookup::Parameter(d) => {
+                    Ok(chalk_ir::DebruijnIndex::new(d).to_ty())
+                }
+            },
+
+            Ty::Apply { name, ref args } => {
+                let id = match env.lookup(name)? {
+                    NameLookup::Type(id) => id,
+                    NameLookup::Parameter(_) => {
+                        Err(spanned(name.span, LoweringError::CannotApplyTypeParameter(name)))?
+                    }
+                };
+
+                let k = env.type_kind(id);
+                if k.binders.len() != args.len() {
+                    let error = LoweringError::IncorrectNumberOfTypeParameters {
+                        identifier: name,
+                        expected: k.binders.len(),
+                        actual: args.len(),
+                    };
+                    Err(spanned(name.span, error))?;
+                }
+
+                let parameters = args
+                    .iter()
+                    .map(|t| Ok(t.lower(env)?))
+                    .collect::<Fallible<Vec<_>>>()?;
+
+                for (index, (param, arg)) in k.binders.binders.iter().zip(args).enumerate() {
+                    check_type_kinds("incorrect parameter kind", name, index, param, arg)?;
+                }
+
+                Ok(chalk_ir::TyData::Apply(chalk_ir::ApplicationTy {
+                    name: chalk_ir::TypeName::ItemId(id),
+                    parameters: parameters,
+                })
+                .intern())
+            }
+
+            Ty::Projection { ref proj } => {
+                Ok(chalk_ir::TyData::Projection(proj.lower(env)?).intern())
+            }
+
+            Ty::UnselectedProjection { ref proj } => {
+                Ok(chalk_ir::TyData::UnselectedProjection(proj.lower(env)?).intern())
+            }
+
+            Ty::ForAll {
+                ref parameter_kinds,
+                ref ty,
+            } => {
+                let binders: Vec<_> = parameter_kinds.iter().map(|pk| pk.lower()).collect();
+                let quantified_env = env.introduce(binders.iter().cloned())?;
+
+                let ty = ty.lower(&quantified_env)?;
+                let quantified_ty = chalk_ir::QuantifiedTy {
+                    binders: binders.anonymize(),
+                    ty,
+                };
+                Ok(chalk_ir::TyData::ForAll(Box::new(quantified_ty)).intern())
+            }
+
+            Ty::Dyn { ref bounds } => {
+                // The bounds are lowered outside of the binder of the object type, which
+                // they refer to as `^0` once shifted in.
+                let bounds = bounds
+                    .lower(env)?
+                    .into_iter()
+                    .flat_map(|bound| {
+                        bound
+                            .shifted_in(1)
+                            .into_where_clauses(chalk_ir::DebruijnIndex::INNERMOST.to_ty())
+                    })
+                    .collect();
+                Ok(chalk_ir::TyData::Dyn(chalk_ir::Binders::new(
+                    vec![chalk_ir::ParameterKind::Ty(())],
+                    bounds,
+                ))
+                .intern())
+            }
+
+            Ty::Tuple { ref types } => Ok(chalk_ir::TyData::Apply(chalk_ir::ApplicationTy {
+                name: chalk_ir::TypeName::Tuple(types.len()),
+                parameters: types
+                    .iter()
+                    .map(|t| Ok(t.lower(env)?.cast()))
+                    .collect::<Fallible<Vec<_>>>()?,
+            })
+            .intern()),
+
+            Ty::FnPtr { ref args, ref ret } => Ok(chalk_ir::TyData::Apply(chalk_ir::ApplicationTy {
+                name: chalk_ir::TypeName::FnPtr(args.len()),
+                parameters: args
+                    .iter()
+                    .chain(std::iter::once(&**ret))
+                    .map(|t| Ok(t.lower(env)?.cast()))
+                    .collect::<Fallible<Vec<_>>>()?,
+            })
+            .intern()),
+        }
+    }
+}
+
+trait LowerParameter {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::Parameter>;
+}
+
+impl LowerParameter for Parameter {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::Parameter> {
+        match *self {
+            Parameter::Ty(ref t) => Ok(chalk_ir::ParameterKind::Ty(t.lower(env)?)),
+            Parameter::Lifetime(ref l) => Ok(chalk_ir::ParameterKind::Lifetime(l.lower(env)?)),
+        }
+    }
+}
+
+trait LowerLifetime {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::Lifetime>;
+}
+
+impl LowerLifetime for Lifetime {
+    fn lower(&self, env: &Env) -> Fallible<chalk_ir::Lifetime> {
+        match *self {
+            Lifetime::Id { name } => match env.lookup_lifetime(name)? {
+                LifetimeLookup::Parameter(d) => {
+                    Ok(chalk_ir::Lifetime::BoundVar(chalk_ir::DebruijnIndex::new(d)))
+                }
+            },
+        }
+    }
+}
+
+trait LowerImpl {
+    fn lower_impl(&self, empty_env: &Env) -> Fallible<rust_ir::ImplDatum>;
+}
+
+impl LowerImpl for Impl {
+    fn lower_impl(&self, empty_env: &Env) -> Fallible<rust_ir::ImplDatum> {
+        let binders = empty_env.in_binders(self.all_parameters(), |env| {
+            let trait_ref = self.trait_ref.lower(env)?;
+
+            if !trait_ref.is_positive() && !self.assoc_ty_values.is_empty() {
+                let trait_name = self.trait_ref.trait_ref().trait_name;
+                return Err(spanned(
+                    trait_name.span,
+                    LoweringError::NegativeImplAssociatedValues(trait_name),
+                ));
+            }
+
+            let trait_id = trait_ref.trait_ref().trait_id;
+            let where_clauses = self.lower_where_clauses(&env)?;
+            let associated_ty_values = self
+                .assoc_ty_values
+                .iter()
+                .map(|v| v.lower(trait_id, env))
+                .collect::<Fallible<_>>()?;
+            Ok(rust_ir::ImplDatumBound {
+                trait_ref,
+                where_clauses,
+                associated_ty_values,
+                specialization_priority: 0,
+                impl_type: match self.impl_type {
+                    ImplType::Local => rust_ir::ImplType::Local,
+                    ImplType::External => rust_ir::ImplType::External,
+                },
+            })
+        })?;
+
+        Ok(rust_ir::ImplDatum { binders: binders })
+    }
+}
+
+trait LowerClause {
+    fn lower_clause(&self, env: &Env) -> Fallible<Vec<chalk_ir::ProgramClause>>;
+}
+
+impl LowerClause for Clause {
+    fn lower_clause(&self, env: &Env) -> Fallible<Vec<chalk_ir::ProgramClause>> {
+        let implications = env.in_binders(self.all_parameters(), |env| {
+            let consequences: Vec<chalk_ir::DomainGoal> = self.consequence.lower(env)?;
+
+            let conditions: chalk_ir::Conditions = self
+                .conditions
+                .iter()
+                .map(|g| g.lower(env).map(|g| *g))
+                .rev() // (*)
+                .collect::<Fallible<_>>()?;
+
+            // (*) Subtle: in the SLG solver, we pop conditions from R to
+            // L. To preserve the expected order (L to R), we must
+            // therefore reverse.
+
+            let implications = consequences
+                .into_iter()
+                .map(|consequence| chalk_ir::ProgramClauseImplication {
+                    consequence,
+                    conditions: conditions.clone(),
+                })
+                .collect::<Vec<_>>();
+            Ok(implications)
+        })?;
+
+        let clauses = implications
+            .into_iter()
+            .map(
+                |implication: chalk_ir::Binders<chalk_ir::ProgramClauseImplication>| {
+                    if implication.binders.is_empty() {
+                        chalk_ir::ProgramClause::Implies(implication.value)
+                    } else {
+                        chalk_ir::ProgramClause::ForAll(implication)
+                    }
+                },
+            )
+            .collect();
+        Ok(clauses)
+    }
+}
+
+trait LowerAssocTyValue {
+    fn lower(&self, trait_id: chalk_ir::ItemId, env: &Env) -> Fallible<rust_ir::AssociatedTyValue>;
+}
+
+impl LowerAssocTyValue for AssocTyValue {
+    fn lower(&self, trait_id: chalk_ir::ItemId, env: &Env) -> Fallible<rust_ir::AssociatedTyValue> {
+        let info = &env.associated_ty_infos[&(trait_id, self.name.str)];
+        let value = env.in_binders(self.all_parameters(), |env| {
+            Ok(rust_ir::AssociatedTyValueBound {
+                ty: self.value.lower(env)?,
+            })
+        })?;
+        Ok(rust_ir::AssociatedTyValue {
+            associated_ty_id: info.id,
+            value: value,
+        })
+    }
+}
+
+trait LowerTrait {
+    fn lower_trait(&self, trait_id: chalk_ir::ItemId, env: &Env) -> Fallible<rust_ir::TraitDatum>;
+}
+
+impl LowerTrait for TraitDefn {
+    fn lower_trait(&self, trait_id: chalk_ir::ItemId, env: &Env) -> Fallible<rust_ir::TraitDatum> {
+        let binders = env.in_binders(self.all_parameters(), |env| {
+            let trait_ref = chalk_ir::TraitRef {
+                trait_id: trait_id,
+                parameters: self.parameter_refs().into(),
+            };
+
+            if self.flags.auto {
+                if trait_ref.parameters.len() > 1 {
+                    return Err(spanned(
+                        self.name.span,
+                        LoweringError::AutoTraitParameters(self.name),
+                    ));
+                }
+                if !self.where_clauses.is_empty() {
+                    return Err(spanned(
+                        self.name.span,
+                        LoweringError::AutoTraitWhereClauses(self.name),
+                    ));
+                }
+            }
+
+            Ok(rust_ir::TraitDatumBound {
+                trait_ref: trait_ref,
+                where_clauses: self.lower_where_clauses(env)?,
+                flags: rust_ir::TraitFlags {
+                    auto: self.flags.auto,
+                    coinductive: self.flags.coinductive,
+                    marker: self.flags.marker,
+                    upstream: self.flags.upstream,
+                    fundamental: self.flags.fundamental,
+                    deref: self.flags.deref,
+                    drop: self.flags.drop,
+                },
+            })
+        })?;
+
+        Ok(rust_ir::TraitDatum { binders: binders })
+    }
+}
+
+pub trait LowerGoal<A> {
+    fn lower(&self, arg: &A) -> Fallible<Box<chalk_ir::Goal>>;
+}
+
+/// The names of the binders of each `forall<>` quantifier that
+/// `GoalExt::into_peeled_goal_with_universes` peels off the lowered
+/// `goal`, outermost first (see `PeeledGoal::forall_names`).
+pub fn peeled_forall_binders(mut goal: &Goal) -> Vec<Vec<chalk_ir::Identifier>> {
+    let mut foralls = vec![];
+    loop {
+        match goal {
+            Goal::ForAll(ids, g) => {
+                // Empty quantifiers are dropped by lowering.
+                if !ids.is_empty() {
+                    foralls.push(ids.iter().map(|id| id.lower().into_inner()).collect());
+                }
+                goal = g;
+            }
+            Goal::Exists(_, g) | Goal::Implies(_, g) => goal = g,
+            _ => return foralls,
+        }
+    }
+}
+
+impl LowerGoal<rust_ir::Program> for Goal {
+    fn lower(&self, program: &rust_ir::Program) -> Fallible<Box<chalk_ir::Goal>> {
+        let associated_ty_infos = associated_ty_infos(program);
+        self.lower(&Env::empty(program, &associated_ty_infos))
+    }
+}
+
+/// The ids and parameters of the associated types of `program`.
+fn associated_ty_infos(program: &rust_ir::Program) -> AssociatedTyInfos {
+    program
+        .associated_ty_data
+        .iter()
+        .map(|(&associated_ty_id, datum)| {
+            let trait_datum = &program.trait_data[&datum.trait_id];
+            let num_trait_params = trait_datum.binders.len();
+            let num_addl_params = datum.parameter_kinds.len() - num_trait_params;
+            let addl_parameter_kinds = datum.parameter_kinds[..num_addl_params].to_owned();
+            let info = AssociatedTyInfo {
+                id: associated_ty_id,
+                addl_parameter_kinds,
+            };
+            ((datum.trait_id, datum.name), info)
+        })
+        .collect()
+}
+
+/// The ids of the items of `program`.
+fn used_item_ids(program: &rust_ir::Program) -> BTreeSet<chalk_ir::ItemId> {
+    program
+        .type_kinds
+        .keys()
+        .chain(program.impl_data.keys())
+        .chain(program.associated_ty_data.keys())
+        .cloned()
+        .collect()
+}
+
+/// Adds the type `kind` to `program` with the id `id`, unless a type of
+/// the same name is there already.
+fn add_type_kind(
+    program: &mut rust_ir::Program,
+    name: Identifier,
+    id: chalk_ir::ItemId,
+    kind: rust_ir::TypeKind,
+) -> Fallible<()> {
+    if program.type_ids.contains_key(&name.str) {
+        return Err(spanned(name.span, LoweringError::DuplicateTypeName(name.str)));
+    }
+    program.type_ids.insert(name.str, id);
+    program.type_kinds.insert(id, kind);
+    Ok(())
+}
+
+fn remove_type_kind(program: &mut rust_ir::Program, id: chalk_ir::ItemId) {
+    if let Some(kind) = program.type_kinds.remove(&id) {
+        program.type_ids.remove(&kind.name);
+    }
+}
+
+/// Lowers the struct `defn` and adds it to `program`, returning its id.
+/// The types and traits that `defn` refers to must be in `program`
+/// already, apart from the struct itself. Nothing is added if lowering
+/// fails.
+///
+/// As with whole programs, nothing is checked beyond lowering itself:
+/// specialization priorities, orphan rules and well-formedness are left
+/// to the caller (see `query::lowered_program` and
+/// `query::checked_program`).
+pub fn lower_struct_defn(
+    program: &mut rust_ir::Program,
+    defn: &StructDefn,
+) -> Fallible<chalk_ir::ItemId> {
+    let id = fresh_item_id(&mut used_item_ids(program), defn.name.to_string());
+    add_type_kind(program, defn.name, id, defn.lower_type_kind()?)?;
+
+    let associated_ty_infos = associated_ty_infos(program);
+    let datum = defn
+        .lower_struct(id, &Env::empty(program, &associated_ty_infos))
+        .at_span(defn.name.span);
+    match datum {
+        Ok(datum) => {
+            program.struct_data.insert(id, datum);
+            program.item_order.push(id);
+            Ok(id)
+        }
+        Err(error) => {
+            remove_type_kind(program, id);
+            Err(error)
+        }
+    }
+}
+
+/// Lowers the trait `defn` and its associated types and adds them to
+/// `program`, as `lower_struct_defn` does for structs. Returns the id of
+/// the trait.
+pub fn lower_trait_defn(
+    program: &mut rust_ir::Program,
+    defn: &TraitDefn,
+) -> Fallible<chalk_ir::ItemId> {
+    let mut used_ids = used_item_ids(program);
+    let id = fresh_item_id(&mut used_ids, defn.name.to_string());
+    let new_associated_ty_infos = new_associated_ty_infos(defn, id, &mut used_ids)?;
+    add_type_kind(program, defn.name, id, defn.lower_type_kind()?)?;
+
+    let associated_ty_ids: Vec<_> = new_associated_ty_infos
+        .iter()
+        .map(|(_, info)| info.id)
+        .collect();
+    let mut associated_ty_infos = associated_ty_infos(program);
+    associated_ty_infos.extend(new_associated_ty_infos);
+    let mut associated_ty_data = BTreeMap::new();
+    let mut lang_items = program.lang_items.clone();
+    let datum: Fallible<_> = try {
+        let env = Env::empty(program, &associated_ty_infos);
+        let datum = defn.lower_trait(id, &env).at_span(defn.name.span)?;
+        lower_associated_tys(defn, id, &env, &mut associated_ty_data)?;
+        add_lang_items(defn, id, &mut lang_items)?;
+        datum
+    };
+    match datum {
+        Ok(datum) => {
+            program.trait_data.insert(id, datum);
+            program.item_order.push(id);
+            program.item_order.extend(associated_ty_ids);
+            program.associated_ty_data.extend(associated_ty_data);
+            program.lang_items = lang_items;
+            Ok(id)
+        }
+        Err(error) => {
+            remove_type_kind(program, id);
+            Err(error)
+        }
+    }
+}
+
+/// Lowers `impl_` and adds it to `program`, returning its id. The trait
+/// and types that `impl_` refers to must be in `program` already.
+pub fn lower_impl(program: &mut rust_ir::Program, impl_: &Impl) -> Fallible<chalk_ir::ItemId> {
+    let associated_ty_infos = associated_ty_infos(program);
+    let span = impl_.trait_ref.trait_ref().trait_name.span;
+    let datum = impl_
+        .lower_impl(&Env::empty(program, &associated_ty_infos))
+        .at_span(span)?;
+    let id = fresh_item_id(&mut used_item_ids(program), impl_.path());
+    program.impl_data.insert(id, datum);
+    program.item_order.push(id);
+    Ok(id)
+}
+
+/// Lowers `goal`, which refers to the items of `program`.
+pub fn lower_goal_in_program(
+    program: &rust_ir::Program,
+    goal: &Goal,
+) -> Fallible<Box<chalk_ir::Goal>> {
+    goal.lower(program)
+}
+
+impl<'k> LowerGoal<Env<'k>> for Goal {
+    fn lower(&self, env: &Env<'k>) -> Fallible<Box<chalk_ir::Goal>> {
+        match self {
+            Goal::ForAll(ids, g) => g.lower_quantified(env, chalk_ir::QuantifierKind::ForAll, ids),
+            Goal::Exists(ids, g) => g.lower_quantified(env, chalk_ir::QuantifierKind::Exists, ids),
+            Goal::Implies(hyp, g) => {
+                // The hypotheses are lowered as written: the solver elaborates
+                // their implied bounds (see `chalk_solve::solve::elaborate`).
+                let where_clauses: Fallible<Vec<_>> = hyp
+                    .into_iter()
+                    .flat_map(|h| h.lower_clause(env).apply_result())
+                    .collect();
+                Ok(Box::new(chalk_ir::Goal::Implies(
+                    where_clauses?,
+                    g.lower(env)?,
+                )))
+            }
+            Goal::And(g1, g2) => Ok(Box::new(chalk_ir::Goal::And(
+                g1.lower(env)?,
+                g2.lower(env)?,
+            ))),
+            Goal::All(goals) => Ok(Box::new(chalk_ir::Goal::All(
+                goals.iter().map(|g| Ok(*g.lower(env)?)).collect::<Fallible<_>>()?,
+            ))),
+            Goal::Any(goals) => Ok(Box::new(chalk_ir::Goal::Any(
+                goals.iter().map(|g| Ok(*g.lower(env)?)).collect::<Fallible<_>>()?,
+            ))),
+            Goal::Not(g) => Ok(Box::new(chalk_ir::Goal::Not(g.lower(env)?))),
+            Goal::Compatible(g) => Ok(Box::new(g.lower(env)?.compatible())),
+            Goal::Leaf(leaf) => {
+                // A where clause can lower to multiple leaf goals; wrap these in Goal::And.
+                let leaves = leaf.lower(env)?.into_iter().map(chalk_ir::Goal::Leaf);
+                let goal = leaves
+                    .fold1(|goal, leaf| chalk_ir::Goal::And(Box::new(goal), Box::new(leaf)))
+                    .expect("at least one goal");
+                Ok(Box::new(goal))
+            }
+        }
+    }
+}
+
+trait LowerQuantifiedGoal {
+    fn lower_quantified(
+        &self,
+        env: &Env,
+        quantifier_kind: chalk_ir::QuantifierKind,
+        parameter_kinds: &[ParameterKind],
+    ) -> Fallible<Box<chalk_ir::Goal>>;
+}
+
+impl LowerQuantifiedGoal for Goal {
+    fn lower_quantified(
+        &self,
+        env: &Env,
+        quantifier_kind: chalk_ir::QuantifierKind,
+        parameter_kinds: &[ParameterKind],
+    ) -> Fallible<Box<chalk_ir::Goal>> {
+        if parameter_kinds.is_empty() {
+            return self.lower(env);
+        }
+
+        let parameter_kinds = parameter_kinds.iter().map(|pk| pk.lower());
+        let subgoal = env.in_binders(parameter_kinds, |env| self.lower(env))?;
+        Ok(Box::new(chalk_ir::Goal::Quantified(
+            quantifier_kind,
+            subgoal,
+        )))
+    }
+}
+
+/// Lowers Fallible<Vec<T>> -> Vec<Fallible<T>>.
+trait ApplyResult {
+    type Output;
+    fn apply_result(self) -> Self::Output;
+}
+
+impl<T> ApplyResult for Fallible<Vec<T>> {
+    type Output = Vec<Fallible<T>>;
+    fn apply_result(self) -> Self::Output {
+        match self {
+            Ok(v) => v.into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e)],
+        }
+    }
+}
+
+trait Kinded {
+    fn kind(&self) -> Kind;
+}
+
+impl Kinded for ParameterKind {
+    fn kind(&self) -> Kind {
+        match *self {
+            ParameterKind::Ty(_) => Kind::Ty,
+            ParameterKind::Lifetime(_) => Kind::Lifetime,
+        }
+    }
+}
+
+impl Kinded for Parameter {
+    fn kind(&self) -> Kind {
+        match *self {
+            Parameter::Ty(_) => Kind::Ty,
+            Parameter::Lifetime(_) => Kind::Lifetime,
+        }
+    }
+}
+
+impl<T, L> Kinded for chalk_ir::ParameterKind<T, L> {
+    fn kind(&self) -> Kind {
+        match *self {
+            chalk_ir::ParameterKind::Ty(_) => Kind::Ty,
+            chalk_ir::ParameterKind::Lifetime(_) => Kind::Lifetime,
+        }
+    }
+}
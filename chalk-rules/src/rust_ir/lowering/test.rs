@@ -2,7 +2,9 @@
 
 use crate::test_util::*;
 use chalk_ir::tls;
+use chalk_macros::intern::intern;
 use chalk_solve::solve::SolverChoice;
+use std::collections::BTreeSet;
 
 #[test]
 fn lower_success() {
@@ -15,20 +17,6 @@ fn lower_success() {
     }
 }
 
-#[test]
-fn not_trait() {
-    lowering_error! {
-        program {
-            struct Foo { }
-            trait Bar { }
-            impl Foo for Bar { }
-        }
-        error_msg {
-            "expected a trait, found `Foo`, which is not a trait"
-        }
-    }
-}
-
 #[test]
 fn auto_trait() {
     lowering_error! {
@@ -68,6 +56,29 @@ fn auto_trait() {
     }
 }
 
+#[test]
+fn error_patterns() {
+    lowering_error! {
+        program {
+            #[auto] trait Foo<T> { }
+        }
+        error_msg_contains {
+            "cannot have parameters"
+        }
+    }
+
+    lowering_error! {
+        program {
+            struct Foo { }
+            trait Bar { }
+            impl Foo for Bar { }
+        }
+        error_msg_matches {
+            r"^expected a trait, found `\w+`"
+        }
+    }
+}
+
 #[test]
 fn negative_impl() {
     lowering_error! {
@@ -163,7 +174,7 @@ fn goal_quantifiers() {
     tls::set_current_program(&program, || {
         assert_eq!(
             format!("{:?}", goal),
-            "ForAll<type> { Exists<type> { ForAll<type> { Implemented(^0: Foo<^1, ^2>) } } }"
+            "ForAll<X> { Exists<Y> { ForAll<Z> { Implemented(Z: Foo<Y, X>) } } }"
         );
     });
 }
@@ -193,16 +204,16 @@ fn atc_accounting() {
         assert_eq!(
             &impl_text[..],
             r#"ImplDatum {
-    binders: for<type> ImplDatumBound {
+    binders: for<T> ImplDatumBound {
         trait_ref: Positive(
-            Vec<^0> as Iterable
+            Vec<T> as Iterable
         ),
         where_clauses: [],
         associated_ty_values: [
             AssociatedTyValue {
                 associated_ty_id: (Iterable::Iter),
-                value: for<lifetime> AssociatedTyValueBound {
-                    ty: Iter<'^0, ^1>
+                value: for<'a> AssociatedTyValueBound {
+                    ty: Iter<'a, T>
                 }
             }
         ],
@@ -221,11 +232,11 @@ fn atc_accounting() {
         println!("{}", goal_text);
         assert_eq!(
             goal_text,
-            "ForAll<type> { \
-             ForAll<lifetime> { \
-             ForAll<type> { \
-             (ProjectionEq(<^2 as Iterable>::Iter<'^1> = ^0), \
-             Implemented(^2: Iterable)) \
+            "ForAll<X> { \
+             ForAll<'a> { \
+             ForAll<Y> { \
+             (ProjectionEq(<X as Iterable>::Iter<'a> = Y), \
+             Implemented(X: Iterable)) \
              } \
              } \
              }"
@@ -233,6 +244,32 @@ fn atc_accounting() {
     });
 }
 
+#[test]
+fn binder_names() {
+    use crate::rules::ClauseFamilies;
+
+    let program = parse_and_lower_program(
+        "trait Bar<U> { } struct Foo<T> where forall<U> T: Bar<U> { }",
+        SolverChoice::default(),
+    )
+    .unwrap();
+    tls::set_current_program(&program, || {
+        // The binder of the where clause is moved in front of that of
+        // `Foo`, and both keep their names.
+        let clauses: Vec<_> = program
+            .environment(ClauseFamilies::default())
+            .program_clauses
+            .iter()
+            .map(|clause| format!("{:?}", clause))
+            .collect();
+        assert!(
+            clauses.contains(&"for<U, T> FromEnv(T as Bar<U>) :- FromEnv(Foo<T>)".to_string()),
+            "{:#?}",
+            clauses
+        );
+    });
+}
+
 #[test]
 fn check_parameter_kinds() {
     lowering_error! {
@@ -243,7 +280,7 @@ fn check_parameter_kinds() {
             impl Bar for Foo<i32> { }
         }
         error_msg {
-            "incorrect parameter kind: expected lifetime, found type"
+            "incorrect parameter kind: expected lifetime, found type (parameter 0 of `Foo`)"
         }
     };
 
@@ -254,7 +291,7 @@ fn check_parameter_kinds() {
             impl<'a> Bar for Foo<'a> { }
         }
         error_msg {
-            "incorrect parameter kind: expected type, found lifetime"
+            "incorrect parameter kind: expected type, found lifetime (parameter 0 of `Foo`)"
         }
     };
 
@@ -265,7 +302,8 @@ fn check_parameter_kinds() {
             impl<X, T> Foo for <X as Iterator>::Item<T> where X: Iterator { }
         }
         error_msg {
-            "incorrect kind for associated type parameter: expected lifetime, found type"
+            "incorrect kind for associated type parameter: expected lifetime, found type \
+             (parameter 0 of `Item`)"
         }
     };
 
@@ -276,7 +314,8 @@ fn check_parameter_kinds() {
             impl<X, 'a> Foo for <X as Iterator>::Item<'a> where X: Iterator { }
         }
         error_msg {
-            "incorrect kind for associated type parameter: expected type, found lifetime"
+            "incorrect kind for associated type parameter: expected type, found lifetime \
+             (parameter 0 of `Item`)"
         }
     };
 
@@ -287,7 +326,8 @@ fn check_parameter_kinds() {
             impl<'a> Into<'a> for Foo {}
         }
         error_msg {
-            "incorrect kind for trait parameter: expected type, found lifetime"
+            "incorrect kind for trait parameter: expected type, found lifetime \
+             (parameter 0 of `Into`)"
         }
     }
 
@@ -298,9 +338,22 @@ fn check_parameter_kinds() {
             impl<T> IntoTime<T> for Foo {}
         }
         error_msg {
-            "incorrect kind for trait parameter: expected lifetime, found type"
+            "incorrect kind for trait parameter: expected lifetime, found type \
+             (parameter 0 of `IntoTime`)"
         }
     }
+
+    lowering_error! {
+        program {
+            struct Foo<T, 'a> { }
+            struct i32 { }
+            trait Bar { }
+            impl Bar for Foo<i32, i32> { }
+        }
+        error_msg {
+            "incorrect parameter kind: expected lifetime, found type (parameter 1 of `Foo`)"
+        }
+    };
 }
 
 #[test]
@@ -388,9 +441,9 @@ fn duplicate_parameters() {
 
     lowering_error! {
         program {
-            struct fn<'a> { }
+            struct Fun<'a> { }
             struct Foo<'a> {
-                a: for<'a> fn<'a>
+                a: for<'a> Fun<'a>
             }
         } error_msg {
             "duplicate or shadowed parameters"
@@ -439,6 +492,27 @@ fn deref_trait() {
     }
 }
 
+#[test]
+fn builtin_lang_items() {
+    lowering_success! {
+        program {
+            #[lang_copy] trait Copy { }
+            #[lang_fn_once] trait FnOnce<Args> { type Output; }
+            struct Foo<T> { a: (T, fn(T) -> (u32,)) }
+            struct u32 { }
+        }
+    }
+
+    lowering_error! {
+        program {
+            #[lang_copy] trait Copy { }
+            #[lang_copy] trait CopyDupe { }
+        } error_msg {
+            "duplicate lang item `CopyTrait`"
+        }
+    }
+}
+
 #[test]
 fn fundamental_multiple_type_parameters() {
     lowering_error! {
@@ -452,3 +526,186 @@ fn fundamental_multiple_type_parameters() {
         }
     }
 }
+
+#[test]
+fn all_errors() {
+    lowering_error! {
+        program {
+            struct Foo { }
+            trait Bar { }
+            impl Foo for Bar { }
+            impl Bar for Baz { }
+        }
+        error_msg {
+            "2 errors:
+  - expected a trait, found `Foo`, which is not a trait
+  - invalid type name `Baz`"
+        }
+    }
+
+    lowering_error! {
+        program {
+            #[auto] trait Foo {
+                type Item;
+            }
+            #[auto] trait Bar {
+                type Item;
+            }
+        }
+        error_msg {
+            "2 errors:
+  - auto trait cannot define associated types
+  - auto trait cannot define associated types"
+        }
+    }
+}
+
+#[test]
+fn error_span() {
+    use super::{LowerProgram, LoweringError, LoweringErrors, SpannedError};
+
+    let text = "struct Foo { } trait Bar { } impl Foo for Bar { }";
+    let errors = chalk_parse::parse_program(text)
+        .unwrap()
+        .lower()
+        .unwrap_err();
+    let errors = &errors.downcast_ref::<LoweringErrors>().unwrap().errors;
+    assert_eq!(errors.len(), 1);
+    let error = errors[0].downcast_ref::<SpannedError>().unwrap();
+    match error.error {
+        LoweringError::NotTrait(name) => assert_eq!(name.str, intern("Foo")),
+        ref error => panic!("unexpected error: {}", error),
+    }
+    assert_eq!(
+        error.error.render(),
+        "error[L0003]: expected a trait, found `Foo`, which is not a trait"
+    );
+
+    let lo = text.rfind("Foo").unwrap();
+    assert_eq!((error.span.lo, error.span.hi), (lo, lo + "Foo".len()));
+}
+
+#[test]
+fn stable_item_ids() {
+    let program = parse_and_lower_program(
+        "
+        struct Foo { }
+        struct Bar { }
+        trait Baz { type Item; }
+        impl Baz for Foo { type Item = Foo; }
+        impl Baz for Bar { type Item = Foo; }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+    let reordered = parse_and_lower_program(
+        "
+        impl Baz for Bar { type Item = Foo; }
+        trait Baz { type Item; }
+        struct Unrelated { }
+        struct Bar { }
+        impl Baz for Foo { type Item = Foo; }
+        struct Foo { }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+
+    let foo = chalk_ir::ItemId::from_path("Foo", 0);
+    assert_eq!(program.type_ids[&intern("Foo")], foo);
+    assert_eq!(reordered.type_ids[&intern("Foo")], foo);
+
+    // Only `Unrelated` tells the programs apart.
+    let unrelated = reordered.type_ids[&intern("Unrelated")];
+    let changed: Vec<_> = program.changed_items(&reordered).into_iter().collect();
+    assert_eq!(changed, vec![unrelated]);
+}
+
+#[test]
+fn impl_ids_from_trait_refs() {
+    let program = parse_and_lower_program(
+        "
+        struct Vec<T> { }
+        struct Ref<'a, T> { }
+        struct u32 { }
+        trait Foo<U> { }
+        impl<T> Foo<T> for Vec<T> { }
+        impl Foo<u32> for Vec<u32> { }
+        impl<'a, T> Foo<for<'b> Ref<'b, T>> for Vec<dyn Foo<T>> { }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+    let reordered = parse_and_lower_program(
+        "
+        struct u32 { }
+        struct Ref<'a, T> { }
+        struct Vec<T> { }
+        trait Foo<U> { }
+        impl<'a, S> Foo<for<'c> Ref<'c, S>> for Vec<dyn Foo<S>> { }
+        impl Foo<u32> for Vec<u32> { }
+        impl<S> Foo<S> for Vec<S> { }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+
+    // The impls for `Vec` are told apart by their types rather than by
+    // the order of their declarations, and the names of their
+    // parameters do not matter.
+    let ids: BTreeSet<_> = program.impl_data.keys().cloned().collect();
+    let expected: BTreeSet<_> = [
+        "impl Foo<^0> for Vec<^0>",
+        "impl Foo<u32> for Vec<u32>",
+        "impl Foo<for<1> Ref<^0, ^2>> for Vec<dyn Foo<^1>>",
+    ]
+    .iter()
+    .map(|path| chalk_ir::ItemId::from_path(path, 0))
+    .collect();
+    assert_eq!(ids, expected);
+    assert!(program.changed_items(&reordered).is_empty());
+}
+
+#[test]
+fn lower_items_one_by_one() {
+    use super::{lower_goal_in_program, lower_impl, lower_struct_defn, lower_trait_defn};
+    use super::{LowerGoal, LowerProgram};
+    use chalk_parse::ast::Item;
+
+    let text = "
+        struct Foo { field: Foo }
+        trait Bar { type Item; }
+        impl Bar for Foo { type Item = Foo; }
+    ";
+    let whole_program = chalk_parse::parse_program(text).unwrap().lower().unwrap();
+
+    let mut program = chalk_parse::parse_program("").unwrap().lower().unwrap();
+    for item in chalk_parse::parse_program(text).unwrap().items {
+        let id = match item {
+            Item::StructDefn(defn) => lower_struct_defn(&mut program, &defn),
+            Item::TraitDefn(defn) => lower_trait_defn(&mut program, &defn),
+            Item::Impl(impl_) => lower_impl(&mut program, &impl_),
+            Item::Clause(_) => unreachable!(),
+        };
+        id.unwrap();
+    }
+    assert!(program.changed_items(&whole_program).is_empty());
+
+    let goal = chalk_parse::parse_goal("Foo: Bar").unwrap();
+    assert_eq!(
+        lower_goal_in_program(&program, &goal).unwrap(),
+        goal.lower(&whole_program).unwrap()
+    );
+
+    // Items that fail to lower are not added.
+    let items = chalk_parse::parse_program("struct Baz { field: Missing } struct Foo { }")
+        .unwrap()
+        .items;
+    for item in &items {
+        match item {
+            Item::StructDefn(defn) => assert!(lower_struct_defn(&mut program, defn).is_err()),
+            _ => unreachable!(),
+        }
+    }
+    assert!(program.changed_items(&whole_program).is_empty());
+}
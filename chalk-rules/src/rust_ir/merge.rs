@@ -0,0 +1,271 @@
+//! Merging programs that were lowered separately, such as a prelude and
+//! each of the programs that use it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use chalk_ir::fold::{
+    self, DefaultFreeVarFolder, DefaultInferenceFolder, DefaultPlaceholderFolder, Fold,
+    TypeFolder,
+};
+use chalk_ir::{ApplicationTy, ItemId, Lifetime, Ty, TypeName};
+use chalk_engine::fallible::Fallible as FoldFallible;
+use chalk_solve::solve::SolverChoice;
+use failure::Fallible;
+
+use super::lowering::LoweringError;
+use super::*;
+use crate::coherence::orphan;
+use crate::rules::ClauseFamilies;
+
+mod test;
+
+impl Program {
+    /// Combines `self` and `other` into one program, as if their items
+    /// had been declared together.
+    ///
+    /// A struct or trait may be defined in both programs if the two
+    /// definitions are the same; this is how a prelude gets combined with
+    /// programs that declare the prelude items they use. Otherwise, two
+    /// types with the same name are an error. The items of `other` whose
+    /// ids `self` already uses for something else are given new ids.
+    /// Coherence is checked again on the merged program, since impls of
+    /// the shared traits may now overlap.
+    pub fn merge(mut self, other: Program, solver_choice: SolverChoice) -> Fallible<Program> {
+        // Look at the names in order, so that which of several conflicting
+        // names gets reported does not depend on the order of the map.
+        let mut names: Vec<_> = other.type_ids.iter().collect();
+        names.sort_by_key(|&(name, _)| name.data());
+
+        let mut shared = BTreeSet::new();
+        for (name, &id) in names {
+            if let Some(&self_id) = self.type_ids.get(name) {
+                if self_id != id || !self.defines_same_type(&other, id) {
+                    Err(LoweringError::DuplicateTypeName(*name))?;
+                }
+                shared.insert(id);
+                shared.extend(other.associated_ty_ids(id));
+            }
+        }
+
+        // Like lowering does for items that share a path, give an item the
+        // next disambiguator that is free.
+        let self_ids = self.item_ids();
+        let other_ids = other.item_ids();
+        let mut used: BTreeSet<ItemId> = self_ids.union(&other_ids).cloned().collect();
+        let mut ids = BTreeMap::new();
+        for &id in other_ids.difference(&shared) {
+            if self_ids.contains(&id) {
+                let mut fresh = id;
+                while used.contains(&fresh) {
+                    fresh = fresh
+                        .next_disambiguator()
+                        .ok_or(LoweringError::NoFreeItemId(id))?;
+                }
+                used.insert(fresh);
+                ids.insert(id, fresh);
+            }
+        }
+        let other = Renumberer { ids: &ids }.renumber(&other);
+
+        for (lang_item, id) in other.lang_items {
+            if self.lang_items.get(&lang_item).map_or(false, |&self_id| self_id != id) {
+                return Err(LoweringError::DuplicateLangItem(lang_item).into());
+            }
+            self.lang_items.insert(lang_item, id);
+        }
+        self.type_ids.extend(other.type_ids);
+        self.type_kinds.extend(other.type_kinds);
+        self.struct_data.extend(other.struct_data);
+        self.trait_data.extend(other.trait_data);
+        self.impl_data.extend(other.impl_data);
+        self.associated_ty_data.extend(other.associated_ty_data);
+        self.item_order.extend(
+            other
+                .item_order
+                .into_iter()
+                .filter(|id| !shared.contains(id)),
+        );
+        self.custom_clauses.extend(other.custom_clauses);
+
+        let env = Arc::new(self.environment(ClauseFamilies::default()));
+        self.record_specialization_priorities(env.clone(), solver_choice)?;
+        orphan::perform_orphan_check(Arc::new(self.clone()), env, solver_choice)?;
+
+        Ok(self)
+    }
+
+    /// Whether the struct or trait `id` is defined the same way in `self`
+    /// and `other`, associated types included.
+    fn defines_same_type(&self, other: &Program, id: ItemId) -> bool {
+        let associated_ty_data = |program: &Program| -> Vec<AssociatedTyDatum> {
+            program
+                .associated_ty_data
+                .values()
+                .filter(|datum| datum.trait_id == id)
+                .cloned()
+                .collect()
+        };
+
+        self.type_kinds.get(&id) == other.type_kinds.get(&id)
+            && self.struct_data.get(&id) == other.struct_data.get(&id)
+            && self.trait_data.get(&id) == other.trait_data.get(&id)
+            && associated_ty_data(self) == associated_ty_data(other)
+    }
+
+    fn associated_ty_ids(&self, trait_id: ItemId) -> Vec<ItemId> {
+        self.associated_ty_data
+            .values()
+            .filter(|datum| datum.trait_id == trait_id)
+            .map(|datum| datum.id)
+            .collect()
+    }
+
+    /// The ids of the structs, traits, impls and associated types.
+    fn item_ids(&self) -> BTreeSet<ItemId> {
+        self.type_kinds
+            .keys()
+            .chain(self.impl_data.keys())
+            .chain(self.associated_ty_data.keys())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Replaces the item ids that are keys of `ids` by their values.
+struct Renumberer<'i> {
+    ids: &'i BTreeMap<ItemId, ItemId>,
+}
+
+impl Renumberer<'_> {
+    fn id(&self, id: ItemId) -> ItemId {
+        self.ids.get(&id).cloned().unwrap_or(id)
+    }
+
+    fn fold<T: Fold>(&mut self, value: &T) -> T::Result {
+        // Renumbering cannot fail.
+        value.fold_with(self, 0).unwrap()
+    }
+
+    fn renumber(&mut self, program: &Program) -> Program {
+        Program {
+            type_ids: program
+                .type_ids
+                .iter()
+                .map(|(&name, &id)| (name, self.id(id)))
+                .collect(),
+            type_kinds: program
+                .type_kinds
+                .iter()
+                .map(|(&id, kind)| (self.id(id), kind.clone()))
+                .collect(),
+            struct_data: program
+                .struct_data
+                .iter()
+                .map(|(&id, datum)| (self.id(id), self.struct_datum(datum)))
+                .collect(),
+            trait_data: program
+                .trait_data
+                .iter()
+                .map(|(&id, datum)| (self.id(id), self.trait_datum(datum)))
+                .collect(),
+            impl_data: program
+                .impl_data
+                .iter()
+                .map(|(&id, datum)| (self.id(id), self.impl_datum(datum)))
+                .collect(),
+            associated_ty_data: program
+                .associated_ty_data
+                .iter()
+                .map(|(&id, datum)| (self.id(id), self.associated_ty_datum(datum)))
+                .collect(),
+            item_order: program.item_order.iter().map(|&id| self.id(id)).collect(),
+            custom_clauses: self.fold(&program.custom_clauses),
+            lang_items: program
+                .lang_items
+                .iter()
+                .map(|(lang_item, &id)| (lang_item.clone(), self.id(id)))
+                .collect(),
+            specializations: program
+                .specializations
+                .iter()
+                .map(|&(less_special, more_special)| (self.id(less_special), self.id(more_special)))
+                .collect(),
+        }
+    }
+
+    fn struct_datum(&mut self, datum: &StructDatum) -> StructDatum {
+        StructDatum {
+            binders: datum.binders.map_ref(|bound| StructDatumBound {
+                self_ty: self.application_ty(&bound.self_ty),
+                fields: self.fold(&bound.fields),
+                where_clauses: self.fold(&bound.where_clauses),
+                flags: bound.flags.clone(),
+            }),
+        }
+    }
+
+    fn trait_datum(&mut self, datum: &TraitDatum) -> TraitDatum {
+        TraitDatum {
+            binders: datum.binders.map_ref(|bound| TraitDatumBound {
+                trait_ref: self.fold(&bound.trait_ref),
+                where_clauses: self.fold(&bound.where_clauses),
+                flags: bound.flags.clone(),
+            }),
+        }
+    }
+
+    fn impl_datum(&mut self, datum: &ImplDatum) -> ImplDatum {
+        ImplDatum {
+            binders: datum.binders.map_ref(|bound| ImplDatumBound {
+                trait_ref: self.fold(&bound.trait_ref),
+                where_clauses: self.fold(&bound.where_clauses),
+                associated_ty_values: self.fold(&bound.associated_ty_values),
+                specialization_priority: bound.specialization_priority,
+                impl_type: bound.impl_type.clone(),
+            }),
+        }
+    }
+
+    fn associated_ty_datum(&mut self, datum: &AssociatedTyDatum) -> AssociatedTyDatum {
+        AssociatedTyDatum {
+            trait_id: self.id(datum.trait_id),
+            id: self.id(datum.id),
+            name: datum.name,
+            parameter_kinds: datum.parameter_kinds.clone(),
+            bounds: self.fold(&datum.bounds),
+            where_clauses: self.fold(&datum.where_clauses),
+        }
+    }
+
+    fn application_ty(&mut self, ty: &ApplicationTy) -> ApplicationTy {
+        let name = match ty.name {
+            TypeName::ItemId(id) => TypeName::ItemId(self.id(id)),
+            name => name,
+        };
+        ApplicationTy {
+            name,
+            parameters: self.fold(&ty.parameters),
+        }
+    }
+}
+
+impl TypeFolder for Renumberer<'_> {
+    fn fold_ty(&mut self, ty: &Ty, binders: usize) -> FoldFallible<Ty> {
+        fold::super_fold_ty(self, ty, binders)
+    }
+
+    fn fold_lifetime(&mut self, lifetime: &Lifetime, binders: usize) -> FoldFallible<Lifetime> {
+        fold::super_fold_lifetime(self, lifetime, binders)
+    }
+
+    fn fold_item_id(&mut self, item_id: ItemId) -> FoldFallible<ItemId> {
+        Ok(self.id(item_id))
+    }
+}
+
+impl DefaultFreeVarFolder for Renumberer<'_> {}
+
+impl DefaultPlaceholderFolder for Renumberer<'_> {}
+
+impl DefaultInferenceFolder for Renumberer<'_> {}
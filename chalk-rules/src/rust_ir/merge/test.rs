@@ -0,0 +1,74 @@
+#![cfg(test)]
+
+use crate::rust_ir::Program;
+use crate::test_util::*;
+use chalk_solve::solve::SolverChoice;
+
+fn lower(text: &str) -> Program {
+    (*parse_and_lower_program(text, SolverChoice::default()).unwrap()).clone()
+}
+
+fn merge(prelude: &str, program: &str) -> Result<Program, String> {
+    lower(prelude)
+        .merge(lower(program), SolverChoice::default())
+        .map_err(|err| err.to_string())
+}
+
+#[test]
+fn merge_with_prelude() {
+    let prelude = "
+        trait Clone { }
+        struct Foo { }
+        impl Clone for Foo { }
+    ";
+    let program = "
+        trait Clone { }
+        struct Bar { }
+        impl Clone for Bar { }
+    ";
+    let expected = lower(
+        "
+        trait Clone { }
+        struct Foo { }
+        impl Clone for Foo { }
+        struct Bar { }
+        impl Clone for Bar { }
+    ",
+    );
+    assert_eq!(merge(prelude, program), Ok(expected));
+}
+
+#[test]
+fn merge_conflicting_types() {
+    assert_eq!(
+        merge("struct Foo { }", "struct Foo<T> { }").unwrap_err(),
+        "duplicate type name `Foo`"
+    );
+}
+
+#[test]
+fn merge_overlapping_impls() {
+    // Both impls have the same id before merging, so the second one has
+    // to be renumbered before coherence can find the overlap.
+    let program = "
+        trait Clone { }
+        struct Foo { }
+        impl Clone for Foo { }
+    ";
+    assert_eq!(
+        merge(program, program).unwrap_err(),
+        "overlapping impls of trait \"Clone\": both apply to `Foo`"
+    );
+}
+
+#[test]
+fn merge_reports_conflicts_in_name_order() {
+    assert_eq!(
+        merge(
+            "struct Foo { } struct Bar { } struct Baz { }",
+            "struct Foo<T> { } struct Bar<T> { } struct Baz<T> { }"
+        )
+        .unwrap_err(),
+        "duplicate type name `Bar`"
+    );
+}
@@ -0,0 +1,752 @@
+//! Renders lowered programs, clauses and goals back into chalk's surface
+//! syntax, so that they can be parsed again (unlike their `Debug` output).
+//!
+//! Lowering forgets the names of parameters and fields, so the printer
+//! makes up new ones (`T0`, `'a1`, `field0`, ...). Printing a program,
+//! then parsing, lowering and printing it again gives the same text.
+
+use crate::rust_ir::{
+    Anonymize, AssociatedTyDatum, AssociatedTyValue, ImplDatum, ImplType, InlineBound, LangItem,
+    Program, StructDatum, TraitDatum,
+};
+use chalk_ir::{
+    ApplicationTy, Binders, DebruijnIndex, DomainGoal, FromEnv, Goal, ItemId, LeafGoal, Lifetime,
+    Parameter, ParameterKind, ProgramClause, ProgramClauseImplication, ProjectionEq, ProjectionTy,
+    QuantifiedWhereClause, QuantifierKind, TraitRef, Ty, TyData, TypeName,
+    UnselectedProjectionTy, WellFormed, WhereClause,
+};
+use chalk_macros::intern::intern;
+use failure::Fallible;
+
+mod test;
+
+impl Program {
+    /// The program in chalk's surface syntax, one item per line (or per
+    /// block for traits and impls). Structs come first, then traits and
+    /// impls, each in the order of their ids, followed by the custom
+    /// clauses. Lowering the result again gives the items the same ids.
+    pub fn to_source(&self) -> Fallible<String> {
+        let mut printer = Printer::new(self);
+        let mut items = vec![];
+        for (&id, datum) in &self.struct_data {
+            items.push(printer.struct_datum(id, datum)?);
+        }
+        for (&id, datum) in &self.trait_data {
+            items.push(printer.trait_datum(id, datum)?);
+        }
+        for datum in self.impl_data.values() {
+            items.push(printer.impl_datum(datum)?);
+        }
+        for (index, clause) in self.custom_clauses.iter().enumerate() {
+            if index > 0 && implied_clause(&self.custom_clauses[index - 1], clause, self) {
+                continue;
+            }
+            items.push(printer.program_clause(clause)?);
+        }
+
+        Ok(items.iter().map(|item| format!("{}\n", item)).collect())
+    }
+
+    /// `goal` in chalk's surface syntax, with the names of this program.
+    pub fn goal_to_source(&self, goal: &Goal) -> Fallible<String> {
+        Printer::new(self).goal(goal)
+    }
+
+    /// `clause` in chalk's surface syntax, as it would be written as an
+    /// item of this program.
+    pub fn clause_to_source(&self, clause: &ProgramClause) -> Fallible<String> {
+        Printer::new(self).program_clause(clause)
+    }
+}
+
+struct Printer<'p> {
+    program: &'p Program,
+
+    /// The names of the parameters of the binders in scope, innermost
+    /// binder last. `None` stands for a parameter that surface syntax
+    /// cannot name, like the self type of a `dyn` type.
+    scopes: Vec<Vec<Option<String>>>,
+}
+
+impl<'p> Printer<'p> {
+    fn new(program: &'p Program) -> Self {
+        Printer {
+            program,
+            scopes: vec![],
+        }
+    }
+
+    /// A name for a new parameter of the given kind that is neither in
+    /// scope nor the name of a type.
+    fn fresh_name(&self, kind: &ParameterKind<()>) -> String {
+        let in_scope: Vec<&String> = self.scopes.iter().flatten().flatten().collect();
+        (in_scope.len()..)
+            .map(|index| match kind {
+                ParameterKind::Ty(()) => format!("T{}", index),
+                ParameterKind::Lifetime(()) => format!("'a{}", index),
+            })
+            .find(|name| {
+                !in_scope.contains(&name) && !self.program.type_ids.contains_key(&intern(name))
+            })
+            .unwrap()
+    }
+
+    /// Runs `op` under a binder whose first parameters are named `names`,
+    /// and whose other parameters, of the kinds `binders`, get fresh
+    /// names. `op` is given the fresh names.
+    fn in_named_binders<T>(
+        &mut self,
+        names: Vec<Option<String>>,
+        binders: &[ParameterKind<()>],
+        op: impl FnOnce(&mut Self, &[String]) -> Fallible<T>,
+    ) -> Fallible<T> {
+        self.scopes.push(names);
+        let mut fresh_names = vec![];
+        for kind in binders {
+            let name = self.fresh_name(kind);
+            self.scopes.last_mut().unwrap().push(Some(name.clone()));
+            fresh_names.push(name);
+        }
+        let result = op(self, &fresh_names);
+        self.scopes.pop();
+        result
+    }
+
+    fn in_binders<T>(
+        &mut self,
+        binders: &[ParameterKind<()>],
+        op: impl FnOnce(&mut Self, &[String]) -> Fallible<T>,
+    ) -> Fallible<T> {
+        self.in_named_binders(vec![], binders, op)
+    }
+
+    fn bound_var(&self, depth: DebruijnIndex) -> Fallible<String> {
+        let mut depth = depth.depth();
+        for scope in self.scopes.iter().rev() {
+            if depth < scope.len() {
+                return match &scope[depth] {
+                    Some(name) => Ok(name.clone()),
+                    None => Err(format_err!("cannot name a bound variable in surface syntax")),
+                };
+            }
+            depth -= scope.len();
+        }
+        Err(format_err!("unbound variable ^{}", depth))
+    }
+
+    fn item_name(&self, id: ItemId) -> Fallible<String> {
+        match self.program.type_kinds.get(&id) {
+            Some(k) => Ok(k.name.to_string()),
+            None => Err(format_err!("no struct or trait with id {:?}", id)),
+        }
+    }
+
+    fn ty(&mut self, ty: &Ty) -> Fallible<String> {
+        match ty.data() {
+            TyData::Apply(apply) => match apply.name {
+                TypeName::ItemId(id) => {
+                    let parameters = self.parameters(&apply.parameters)?;
+                    Ok(format!("{}{}", self.item_name(id)?, angle(&parameters)))
+                }
+                TypeName::Tuple(_) => {
+                    let types = self.parameters(&apply.parameters)?;
+                    match types.len() {
+                        1 => Ok(format!("({},)", types[0])),
+                        _ => Ok(format!("({})", types.join(", "))),
+                    }
+                }
+                TypeName::FnPtr(_) => {
+                    let mut types = self.parameters(&apply.parameters)?;
+                    let ret = types.pop().unwrap();
+                    Ok(format!("fn({}) -> {}", types.join(", "), ret))
+                }
+                TypeName::Placeholder(_) | TypeName::AssociatedType(_) => {
+                    Err(format_err!("cannot print {:?} in surface syntax", apply.name))
+                }
+            },
+            TyData::Projection(projection) => self.projection_ty(projection),
+            TyData::UnselectedProjection(projection) => self.unselected_projection_ty(projection),
+            TyData::ForAll(quantified_ty) => {
+                self.in_binders(&quantified_ty.binders, |printer, names| {
+                    let ty = printer.ty(&quantified_ty.ty)?;
+                    Ok(format!("for<{}> {}", names.join(", "), ty))
+                })
+            }
+            TyData::Dyn(bounds) => {
+                // The bounds refer to the object type as `^0`, which has no
+                // name: it is the implicit self type of each bound.
+                let bounds = self.in_named_binders(vec![None], &[], |printer, _| {
+                    printer.inline_where_clauses(&bounds.value)
+                })?;
+                Ok(format!("dyn {}", bounds.join(" + ")).trim_end().to_string())
+            }
+            TyData::BoundVar(depth) => self.bound_var(*depth),
+            TyData::InferenceVar(_) => Err(format_err!("cannot print an inference variable")),
+        }
+    }
+
+    /// `ty`, in parentheses if it could not otherwise be the self type of
+    /// an unselected projection.
+    fn ty_without_for(&mut self, ty: &Ty) -> Fallible<String> {
+        let printed = self.ty(ty)?;
+        match ty.data() {
+            TyData::ForAll(_) | TyData::Dyn(_) => Ok(format!("({})", printed)),
+            TyData::Apply(ApplicationTy {
+                name: TypeName::FnPtr(_),
+                ..
+            }) => Ok(format!("({})", printed)),
+            _ => Ok(printed),
+        }
+    }
+
+    fn lifetime(&self, lifetime: &Lifetime) -> Fallible<String> {
+        match lifetime {
+            Lifetime::BoundVar(depth) => self.bound_var(*depth),
+            Lifetime::InferenceVar(_) | Lifetime::Placeholder(_) | Lifetime::Erased => {
+                Err(format_err!("cannot print {:?} in surface syntax", lifetime))
+            }
+        }
+    }
+
+    fn parameter(&mut self, parameter: &Parameter) -> Fallible<String> {
+        match parameter {
+            ParameterKind::Ty(ty) => self.ty(ty),
+            ParameterKind::Lifetime(lifetime) => self.lifetime(lifetime),
+        }
+    }
+
+    fn parameters(&mut self, parameters: &[Parameter]) -> Fallible<Vec<String>> {
+        parameters.iter().map(|p| self.parameter(p)).collect()
+    }
+
+    /// `Trait<P1..Pn>` for the trait reference `P0: Trait<P1..Pn>`.
+    fn trait_bound(&mut self, trait_ref: &TraitRef) -> Fallible<String> {
+        let parameters = self.parameters(&trait_ref.parameters[1..])?;
+        Ok(format!("{}{}", self.item_name(trait_ref.trait_id)?, angle(&parameters)))
+    }
+
+    /// `P0 <separator> Trait<P1..Pn>`.
+    fn trait_ref(&mut self, trait_ref: &TraitRef, separator: &str) -> Fallible<String> {
+        let self_ty = self.parameter(&trait_ref.parameters[0])?;
+        Ok(format!("{}{} {}", self_ty, separator, self.trait_bound(trait_ref)?))
+    }
+
+    fn projection_ty(&mut self, projection: &ProjectionTy) -> Fallible<String> {
+        let (trait_ref, name, parameters) = self.split_projection(projection);
+        let trait_ref = self.trait_ref(&trait_ref, " as")?;
+        let parameters = self.parameters(&parameters)?;
+        Ok(format!("<{}>::{}{}", trait_ref, name, angle(&parameters)))
+    }
+
+    fn unselected_projection_ty(
+        &mut self,
+        projection: &UnselectedProjectionTy,
+    ) -> Fallible<String> {
+        let (self_ty, parameters) = projection.parameters.split_last().unwrap();
+        let self_ty = match self_ty {
+            ParameterKind::Ty(ty) => self.ty_without_for(ty)?,
+            ParameterKind::Lifetime(_) => Err(format_err!("projection from a lifetime"))?,
+        };
+        let parameters = self.parameters(parameters)?;
+        Ok(format!("{}::{}{}", self_ty, projection.type_name, angle(&parameters)))
+    }
+
+    /// The trait reference of `projection`, the name of the associated
+    /// type, and the parameters of the associated type itself.
+    fn split_projection(&self, projection: &ProjectionTy) -> (TraitRef, String, Vec<Parameter>) {
+        let (datum, trait_parameters, other_parameters) =
+            self.program.split_projection(projection);
+        let trait_ref = TraitRef {
+            trait_id: datum.trait_id,
+            parameters: trait_parameters.into(),
+        };
+        (trait_ref, datum.name.to_string(), other_parameters.to_vec())
+    }
+
+    /// `P0: Trait<P1..Pn, Name<Q1..Qm> = ty>` for the projection equality
+    /// `<P0 as Trait<P1..Pn>>::Name<Q1..Qm> = ty`, without the `P0: `
+    /// part if `with_self` is false.
+    fn projection_eq(&mut self, projection_eq: &ProjectionEq, with_self: bool) -> Fallible<String> {
+        let (trait_ref, name, parameters) = self.split_projection(&projection_eq.projection);
+        let mut args = self.parameters(&trait_ref.parameters[1..])?;
+        let parameters = self.parameters(&parameters)?;
+        let ty = self.ty(&projection_eq.ty)?;
+        args.push(format!("{}{} = {}", name, angle(&parameters), ty));
+        let bound = format!("{}<{}>", self.item_name(trait_ref.trait_id)?, args.join(", "));
+        if with_self {
+            let self_ty = self.parameter(&trait_ref.parameters[0])?;
+            Ok(format!("{}: {}", self_ty, bound))
+        } else {
+            Ok(bound)
+        }
+    }
+
+    fn where_clause(&mut self, where_clause: &WhereClause) -> Fallible<String> {
+        match where_clause {
+            WhereClause::Implemented(trait_ref) => self.trait_ref(trait_ref, ":"),
+            WhereClause::ProjectionEq(projection_eq) => self.projection_eq(projection_eq, true),
+            WhereClause::ConstEvaluatable(const_evaluatable) => {
+                let parameters = self.parameters(&const_evaluatable.parameters)?;
+                Ok(format!(
+                    "ConstEvaluatable({}{})",
+                    const_evaluatable.const_name,
+                    angle(&parameters)
+                ))
+            }
+        }
+    }
+
+    /// `forall<..> <printed>`, or just `<printed>` without binders.
+    fn quantified<T>(
+        &mut self,
+        binders: &Binders<T>,
+        op: impl FnOnce(&mut Self, &T) -> Fallible<String>,
+    ) -> Fallible<String> {
+        self.in_binders(&binders.binders, |printer, names| {
+            let printed = op(printer, &binders.value)?;
+            if names.is_empty() {
+                Ok(printed)
+            } else {
+                Ok(format!("forall<{}> {}", names.join(", "), printed))
+            }
+        })
+    }
+
+    /// The where clauses of an item. Lowering turns `T: Foo<Item = U>`
+    /// into a projection equality followed by `T: Foo`, which is left
+    /// out again.
+    fn where_clauses(&mut self, where_clauses: &[QuantifiedWhereClause]) -> Fallible<String> {
+        let mut printed = vec![];
+        for (index, where_clause) in where_clauses.iter().enumerate() {
+            let previous = index.checked_sub(1).map(|previous| &where_clauses[previous]);
+            if previous.map_or(false, |p| implied_where_clause(p, where_clause, self.program)) {
+                continue;
+            }
+            printed.push(self.quantified(where_clause, |printer, wc| printer.where_clause(wc))?);
+        }
+        if printed.is_empty() {
+            Ok(String::new())
+        } else {
+            Ok(format!(" where {}", printed.join(", ")))
+        }
+    }
+
+    /// The bounds of a `dyn` type, whose self type is `^0` outside of
+    /// each bound. There, `dyn Foo<Item = U>` gives `Foo` first, then the
+    /// projection equality.
+    fn inline_where_clauses(
+        &mut self,
+        where_clauses: &[QuantifiedWhereClause],
+    ) -> Fallible<Vec<String>> {
+        let mut printed = vec![];
+        for (index, where_clause) in where_clauses.iter().enumerate() {
+            let next = where_clauses.get(index + 1);
+            if next.map_or(false, |next| implied_where_clause(next, where_clause, self.program)) {
+                continue;
+            }
+            printed.push(self.quantified(where_clause, |printer, wc| match wc {
+                WhereClause::Implemented(trait_ref) => printer.trait_bound(trait_ref),
+                WhereClause::ProjectionEq(projection_eq) => {
+                    printer.projection_eq(projection_eq, false)
+                }
+                WhereClause::ConstEvaluatable(_) => {
+                    Err(format_err!("cannot print a const bound of a `dyn` type"))
+                }
+            })?);
+        }
+        Ok(printed)
+    }
+
+    fn inline_bound(&mut self, bound: &InlineBound) -> Fallible<String> {
+        let trait_bound = match bound {
+            InlineBound::TraitBound(trait_bound) => trait_bound,
+            InlineBound::ProjectionEqBound(bound) => &bound.trait_bound,
+        };
+        let mut args = self.parameters(&trait_bound.args_no_self)?;
+        if let InlineBound::ProjectionEqBound(bound) = bound {
+            let name = self.program.associated_ty_data[&bound.associated_ty_id].name;
+            let parameters = self.parameters(&bound.parameters)?;
+            let value = self.ty(&bound.value)?;
+            args.push(format!("{}{} = {}", name, angle(&parameters), value));
+        }
+        Ok(format!("{}{}", self.item_name(trait_bound.trait_id)?, angle(&args)))
+    }
+
+    fn domain_goal(&mut self, domain_goal: &DomainGoal) -> Fallible<String> {
+        match domain_goal {
+            DomainGoal::Holds(where_clause) => self.where_clause(where_clause),
+            DomainGoal::WellFormed(WellFormed::Trait(trait_ref)) => {
+                Ok(format!("WellFormed({})", self.trait_ref(trait_ref, ":")?))
+            }
+            DomainGoal::WellFormed(WellFormed::Ty(ty)) => {
+                Ok(format!("WellFormed({})", self.ty(ty)?))
+            }
+            DomainGoal::FromEnv(FromEnv::Trait(trait_ref)) => {
+                Ok(format!("FromEnv({})", self.trait_ref(trait_ref, ":")?))
+            }
+            DomainGoal::FromEnv(FromEnv::Ty(ty)) => Ok(format!("FromEnv({})", self.ty(ty)?)),
+            DomainGoal::Normalize(normalize) => {
+                let projection = self.projection_ty(&normalize.projection)?;
+                Ok(format!("Normalize({} -> {})", projection, self.ty(&normalize.ty)?))
+            }
+            DomainGoal::UnselectedNormalize(_) => {
+                Err(format_err!("cannot print an unselected normalization"))
+            }
+            DomainGoal::InScope(trait_id) => Ok(format!("InScope({})", self.item_name(*trait_id)?)),
+            DomainGoal::Derefs(derefs) => {
+                let source = self.ty(&derefs.source)?;
+                Ok(format!("Derefs({}, {})", source, self.ty(&derefs.target)?))
+            }
+            DomainGoal::IsLocal(ty) => Ok(format!("IsLocal({})", self.ty(ty)?)),
+            DomainGoal::IsUpstream(ty) => Ok(format!("IsUpstream({})", self.ty(ty)?)),
+            DomainGoal::IsFullyVisible(ty) => Ok(format!("IsFullyVisible({})", self.ty(ty)?)),
+            DomainGoal::LocalImplAllowed(trait_ref) => {
+                Ok(format!("LocalImplAllowed({})", self.trait_ref(trait_ref, ":")?))
+            }
+            DomainGoal::Compatible(()) => Ok("Compatible".to_string()),
+            DomainGoal::DownstreamType(ty) => Ok(format!("DownstreamType({})", self.ty(ty)?)),
+            DomainGoal::IsSized(ty) => Ok(format!("IsSized({})", self.ty(ty)?)),
+            DomainGoal::NeedsDrop(ty) => Ok(format!("NeedsDrop({})", self.ty(ty)?)),
+            DomainGoal::Custom(custom) => {
+                let parameters = self.parameters(&custom.parameters)?;
+                Ok(format!("Custom({}{})", custom.name, angle(&parameters)))
+            }
+        }
+    }
+
+    fn leaf_goal(&mut self, leaf: &LeafGoal) -> Fallible<String> {
+        match leaf {
+            LeafGoal::EqGoal(eq) => {
+                let a = self.parameter(&eq.a)?;
+                Ok(format!("{} = {}", a, self.parameter(&eq.b)?))
+            }
+            LeafGoal::OutlivesGoal(outlives) => {
+                let a = self.lifetime(&outlives.a)?;
+                Ok(format!("{}: {}", a, self.lifetime(&outlives.b)?))
+            }
+            LeafGoal::DomainGoal(domain_goal) => self.domain_goal(domain_goal),
+        }
+    }
+
+    /// `goal` where a list of goals separated by commas may appear.
+    fn goal(&mut self, goal: &Goal) -> Fallible<String> {
+        match goal {
+            Goal::And(g1, g2) => {
+                if implied_goal(g1, g2, self.program) {
+                    return self.goal1(g1);
+                }
+                let g1 = self.goal1(g1)?;
+                Ok(format!("{}, {}", g1, self.goal(g2)?))
+            }
+            _ => self.goal1(goal),
+        }
+    }
+
+    /// `goal` where a single goal must appear, e.g. in the conditions of
+    /// a clause.
+    fn goal1(&mut self, goal: &Goal) -> Fallible<String> {
+        match goal {
+            Goal::Quantified(kind, subgoal) => {
+                let keyword = match kind {
+                    QuantifierKind::ForAll => "forall",
+                    QuantifierKind::Exists => "exists",
+                };
+                self.in_binders(&subgoal.binders, |printer, names| {
+                    let subgoal = printer.goal(&subgoal.value)?;
+                    Ok(format!("{}<{}> {{ {} }}", keyword, names.join(", "), subgoal))
+                })
+            }
+            Goal::Implies(clauses, subgoal) => {
+                let mut hypotheses = vec![];
+                for (index, clause) in clauses.iter().enumerate() {
+                    if index > 0 && implied_clause(&clauses[index - 1], clause, self.program) {
+                        continue;
+                    }
+                    hypotheses.push(self.inline_clause(clause)?);
+                }
+                let subgoal = self.goal(subgoal)?;
+                Ok(format!("if ({}) {{ {} }}", hypotheses.join("; "), subgoal))
+            }
+            Goal::And(..) => Ok(format!("({})", self.goal(goal)?)),
+            Goal::All(goals) => {
+                let goals: Fallible<Vec<_>> = goals.iter().map(|g| self.goal(g)).collect();
+                Ok(format!("all {{ {} }}", goals?.join("; ")))
+            }
+            Goal::Any(goals) => {
+                let goals: Fallible<Vec<_>> = goals.iter().map(|g| self.goal(g)).collect();
+                Ok(format!("any {{ {} }}", goals?.join("; ")))
+            }
+            Goal::Not(subgoal) => Ok(format!("not {{ {} }}", self.goal(subgoal)?)),
+            Goal::Leaf(leaf) => self.leaf_goal(leaf),
+            Goal::CannotProve(()) => Err(format_err!("cannot print `CannotProve`")),
+        }
+    }
+
+    /// The consequence and conditions of `implication`. Lowering reverses
+    /// the conditions, so we reverse them back.
+    fn implication(
+        &mut self,
+        implication: &ProgramClauseImplication,
+    ) -> Fallible<(String, Vec<String>)> {
+        let consequence = self.domain_goal(&implication.consequence)?;
+        let conditions: Fallible<Vec<_>> = implication
+            .conditions
+            .iter()
+            .rev()
+            .map(|g| self.goal1(g))
+            .collect();
+        Ok((consequence, conditions?))
+    }
+
+    /// `clause` as an item: `forall<..> { consequence if conditions }`.
+    fn program_clause(&mut self, clause: &ProgramClause) -> Fallible<String> {
+        let (binders, implication) = match clause {
+            ProgramClause::Implies(implication) => (&[][..], implication),
+            ProgramClause::ForAll(binders) => (&binders.binders[..], &binders.value),
+        };
+        self.in_binders(binders, |printer, names| {
+            let (consequence, conditions) = printer.implication(implication)?;
+            let mut clause = format!("forall{} {{ {}", angle(names), consequence);
+            if !conditions.is_empty() {
+                clause.push_str(&format!(" if {}", conditions.join(", ")));
+            }
+            clause.push_str(" }");
+            Ok(clause)
+        })
+    }
+
+    /// `clause` as the hypothesis of an `if` goal:
+    /// `forall<..> { consequence :- conditions }`.
+    fn inline_clause(&mut self, clause: &ProgramClause) -> Fallible<String> {
+        let print = |printer: &mut Self, implication| {
+            let (consequence, conditions) = printer.implication(implication)?;
+            if conditions.is_empty() {
+                Ok(consequence)
+            } else {
+                Ok(format!("{} :- {}", consequence, conditions.join(", ")))
+            }
+        };
+        match clause {
+            ProgramClause::Implies(implication) => print(self, implication),
+            ProgramClause::ForAll(binders) => self.in_binders(&binders.binders, |printer, names| {
+                let clause = print(printer, &binders.value)?;
+                Ok(format!("forall<{}> {{ {} }}", names.join(", "), clause))
+            }),
+        }
+    }
+
+    fn struct_datum(&mut self, id: ItemId, datum: &StructDatum) -> Fallible<String> {
+        let name = self.item_name(id)?;
+        self.in_binders(&datum.binders.binders, |printer, names| {
+            let bound = &datum.binders.value;
+            let mut attributes = String::new();
+            if bound.flags.upstream {
+                attributes.push_str("#[upstream] ");
+            }
+            if bound.flags.fundamental {
+                attributes.push_str("#[fundamental] ");
+            }
+            let where_clauses = printer.where_clauses(&bound.where_clauses)?;
+            let fields = bound
+                .fields
+                .iter()
+                .enumerate()
+                .map(|(index, ty)| Ok(format!("field{}: {}", index, printer.ty(ty)?)))
+                .collect::<Fallible<Vec<_>>>()?;
+            Ok(format!(
+                "{}struct {}{}{} {{ {} }}",
+                attributes,
+                name,
+                angle(names),
+                where_clauses,
+                fields.join(", ")
+            ))
+        })
+    }
+
+    fn trait_datum(&mut self, id: ItemId, datum: &TraitDatum) -> Fallible<String> {
+        let name = self.item_name(id)?;
+        let bound = &datum.binders.value;
+        let flags = &bound.flags;
+        let lang_item = |lang_item| self.program.lang_items.get(&lang_item) == Some(&id);
+        let mut attributes = String::new();
+        for &(flag, attribute) in &[
+            (flags.auto, "auto"),
+            (flags.coinductive, "coinductive"),
+            (flags.marker, "marker"),
+            (flags.upstream, "upstream"),
+            (flags.fundamental, "fundamental"),
+            (flags.deref, "lang_deref"),
+            (flags.drop, "lang_drop"),
+            (lang_item(LangItem::SizedTrait), "lang_sized"),
+            (lang_item(LangItem::CopyTrait), "lang_copy"),
+            (lang_item(LangItem::CloneTrait), "lang_clone"),
+            (lang_item(LangItem::FnOnceTrait), "lang_fn_once"),
+            (lang_item(LangItem::FnMutTrait), "lang_fn_mut"),
+            (lang_item(LangItem::FnTrait), "lang_fn"),
+        ] {
+            if flag {
+                attributes.push_str(&format!("#[{}] ", attribute));
+            }
+        }
+
+        // The first parameter of a trait is its implicit `Self`.
+        let self_name = vec![Some("Self".to_string())];
+        let binders = &datum.binders.binders[1..];
+        self.in_named_binders(self_name, binders, |printer, names| {
+            let where_clauses = printer.where_clauses(&bound.where_clauses)?;
+            let mut associated_tys = vec![];
+            for associated_ty in printer.program.associated_ty_data.values() {
+                if associated_ty.trait_id == id {
+                    associated_tys.push(printer.associated_ty_datum(associated_ty)?);
+                }
+            }
+            Ok(format!(
+                "{}trait {}{}{} {{{}}}",
+                attributes,
+                name,
+                angle(names),
+                where_clauses,
+                block(&associated_tys)
+            ))
+        })
+    }
+
+    /// `type Name<..>: bounds where ..;`, printed under the binder of its
+    /// trait. Its own parameters come before those of the trait.
+    fn associated_ty_datum(&mut self, datum: &AssociatedTyDatum) -> Fallible<String> {
+        let num_trait_parameters = self.scopes.last().unwrap().len();
+        let num_own = datum.parameter_kinds.len() - num_trait_parameters;
+        let binders = datum.parameter_kinds[..num_own].anonymize();
+        self.in_binders(&binders, |printer, names| {
+            let bounds = datum
+                .bounds
+                .iter()
+                .map(|bound| printer.quantified(bound, |printer, b| printer.inline_bound(b)))
+                .collect::<Fallible<Vec<_>>>()?;
+            let bounds = if bounds.is_empty() {
+                String::new()
+            } else {
+                format!(": {}", bounds.join(" + "))
+            };
+            let where_clauses = printer.where_clauses(&datum.where_clauses)?;
+            Ok(format!(
+                "type {}{}{}{};",
+                datum.name,
+                angle(names),
+                bounds,
+                where_clauses
+            ))
+        })
+    }
+
+    fn impl_datum(&mut self, datum: &ImplDatum) -> Fallible<String> {
+        self.in_binders(&datum.binders.binders, |printer, names| {
+            let bound = &datum.binders.value;
+            let attributes = match bound.impl_type {
+                ImplType::Local => "",
+                ImplType::External => "#[upstream] ",
+            };
+            let polarity = if bound.trait_ref.is_positive() { "" } else { "!" };
+            let trait_ref = bound.trait_ref.trait_ref();
+            let self_ty = printer.parameter(&trait_ref.parameters[0])?;
+            let trait_bound = printer.trait_bound(trait_ref)?;
+            let where_clauses = printer.where_clauses(&bound.where_clauses)?;
+            let values = bound
+                .associated_ty_values
+                .iter()
+                .map(|value| printer.associated_ty_value(value))
+                .collect::<Fallible<Vec<_>>>()?;
+            Ok(format!(
+                "{}impl{} {}{} for {}{} {{{}}}",
+                attributes,
+                angle(names),
+                polarity,
+                trait_bound,
+                self_ty,
+                where_clauses,
+                block(&values)
+            ))
+        })
+    }
+
+    fn associated_ty_value(&mut self, value: &AssociatedTyValue) -> Fallible<String> {
+        let name = self.program.associated_ty_data[&value.associated_ty_id].name;
+        self.in_binders(&value.value.binders, |printer, names| {
+            let ty = printer.ty(&value.value.value.ty)?;
+            Ok(format!("type {}{} = {};", name, angle(names), ty))
+        })
+    }
+}
+
+/// `<a, b>`, or nothing if there is nothing in the list.
+fn angle(list: &[String]) -> String {
+    if list.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", list.join(", "))
+    }
+}
+
+/// The body of a trait or impl: each line indented, or ` ` if there is
+/// nothing in it.
+fn block(lines: &[String]) -> String {
+    if lines.is_empty() {
+        " ".to_string()
+    } else {
+        lines.iter().map(|line| format!("\n    {}", line)).collect::<String>() + "\n"
+    }
+}
+
+/// True if `where_clause` is `T: Foo` and `previous` is a projection
+/// equality `<T as Foo>::Item = U` under the same binders, i.e. if both
+/// come from lowering `T: Foo<Item = U>`.
+fn implied_where_clause(
+    previous: &QuantifiedWhereClause,
+    where_clause: &QuantifiedWhereClause,
+    program: &Program,
+) -> bool {
+    previous.binders == where_clause.binders
+        && implied(&previous.value, &where_clause.value, program)
+}
+
+fn implied(previous: &WhereClause, where_clause: &WhereClause, program: &Program) -> bool {
+    match (previous, where_clause) {
+        (WhereClause::ProjectionEq(projection_eq), WhereClause::Implemented(trait_ref)) => {
+            let (datum, trait_parameters, _) = program.split_projection(&projection_eq.projection);
+            datum.trait_id == trait_ref.trait_id && trait_parameters == &trait_ref.parameters[..]
+        }
+        _ => false,
+    }
+}
+
+/// As `implied_where_clause`, for the goals `g1, g2`.
+fn implied_goal(g1: &Goal, g2: &Goal, program: &Program) -> bool {
+    match (g1, g2) {
+        (
+            Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Holds(previous))),
+            Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Holds(where_clause))),
+        ) => implied(previous, where_clause, program),
+        _ => false,
+    }
+}
+
+/// As `implied_where_clause`, for clauses with the same conditions.
+fn implied_clause(previous: &ProgramClause, clause: &ProgramClause, program: &Program) -> bool {
+    let implied_implication = |a: &ProgramClauseImplication, b: &ProgramClauseImplication| {
+        a.conditions == b.conditions
+            && match (&a.consequence, &b.consequence) {
+                (DomainGoal::Holds(previous), DomainGoal::Holds(where_clause)) => {
+                    implied(previous, where_clause, program)
+                }
+                _ => false,
+            }
+    };
+    match (previous, clause) {
+        (ProgramClause::Implies(a), ProgramClause::Implies(b)) => implied_implication(a, b),
+        (ProgramClause::ForAll(a), ProgramClause::ForAll(b)) => {
+            a.binders == b.binders && implied_implication(&a.value, &b.value)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,70 @@
+#![cfg(test)]
+
+use crate::rust_ir::lowering::{LowerGoal, LowerProgram};
+use crate::rust_ir::Program;
+
+fn lower(text: &str) -> Program {
+    chalk_parse::parse_program(text).unwrap().lower().unwrap()
+}
+
+/// Printing `text` once it is lowered must give source that prints the
+/// same way once lowered again.
+fn round_trip(text: &str) -> String {
+    let source = lower(text).to_source().unwrap();
+    let reprinted = lower(&source).to_source().unwrap();
+    assert_eq!(source, reprinted, "source was:\n{}", text);
+    source
+}
+
+#[test]
+fn print_items() {
+    let source = round_trip(
+        "
+        struct Foo<T> { field: T }
+        trait Bar<U> { type Item; }
+        impl<T> Bar<T> for Foo<T> { type Item = T; }
+        ",
+    );
+    assert_eq!(
+        source,
+        "struct Foo<T0> { field0: T0 }\n\
+         trait Bar<T1> {\n    type Item;\n}\n\
+         impl<T0> Bar<T0> for Foo<T0> {\n    type Item = T0;\n}\n"
+    );
+}
+
+#[test]
+fn print_round_trip() {
+    round_trip(
+        "
+        #[upstream] #[fundamental] struct Box<T> { }
+        struct Ref<'a, T> { }
+        struct T0 { }
+        #[auto] trait Send { }
+        #[marker] trait Marker { }
+        #[lang_sized] trait Sized { }
+        trait Iterator where Self: Sized {
+            type Item<'a>: Clone where Self::Item<'a>: Sized;
+        }
+        trait Clone { }
+        trait Foo<T> where forall<'x> T: Iterator<Item<'x> = T0> { }
+        impl<T> Foo<T> for Box<T> where forall<'x> T: Iterator<Item<'x> = T0> { }
+        impl<T> !Send for Box<T> { }
+        impl<'a, T> Iterator for Ref<'a, T> where T: Clone {
+            type Item<'b> = for<'c> fn(Ref<'c, T>) -> (T, dyn Foo<T> + Send);
+        }
+        forall<T> { T: Clone if T: Marker, forall<'x> { <T as Iterator>::Item<'x>: Clone } }
+        forall<T> { WellFormed(Box<T>) if not { IsLocal(T) }, exists<U> { T = Box<U> } }
+        ",
+    );
+}
+
+#[test]
+fn print_goal() {
+    let program = lower("trait Foo { type Item; } struct S { }");
+    let text = "forall<T> { if (T: Foo; FromEnv(T)) { exists<U> { T: Foo<Item = U>, U = S } } }";
+    let goal = chalk_parse::parse_goal(text).unwrap().lower(&program).unwrap();
+    let source = program.goal_to_source(&goal).unwrap();
+    let reparsed = chalk_parse::parse_goal(&source).unwrap().lower(&program).unwrap();
+    assert_eq!(goal, reparsed);
+}
@@ -0,0 +1,180 @@
+//! Counting what a lowered program is made of, to characterize the
+//! programs used in benchmarks, or to warn about pathological inputs
+//! before handing them to the solver.
+
+use std::fmt;
+
+use chalk_ir::{Goal, ProgramClause, ProgramClauseImplication};
+
+use super::Program;
+use crate::rules::ClauseFamilies;
+
+mod test;
+
+/// The sizes of a program, as computed by `Program::stats`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramStats {
+    pub structs: usize,
+    pub traits: usize,
+    pub impls: usize,
+    pub associated_tys: usize,
+
+    /// The clauses of `Program::environment`, with every family of
+    /// clauses turned on.
+    pub clauses: ClauseStats,
+
+    /// The deepest nesting of binders (`forall` and `exists`) in any of
+    /// the clauses, counting those of the goals in their conditions.
+    pub max_binder_depth: usize,
+}
+
+/// The number of clauses generated for each sort of item.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClauseStats {
+    /// The clauses written out in the program itself.
+    pub custom: usize,
+
+    pub structs: usize,
+    pub traits: usize,
+
+    /// The clauses of the impls, including those of the values they give
+    /// to associated types. Negative impls have none.
+    pub impls: usize,
+
+    pub associated_tys: usize,
+
+    /// The clauses implementing the auto traits for each struct.
+    pub auto_traits: usize,
+
+    /// The clauses defining `Derefs` and `NeedsDrop` in terms of the
+    /// `Deref` and `Drop` lang items.
+    pub lang_items: usize,
+}
+
+impl ClauseStats {
+    pub fn total(&self) -> usize {
+        self.custom
+            + self.structs
+            + self.traits
+            + self.impls
+            + self.associated_tys
+            + self.auto_traits
+            + self.lang_items
+    }
+}
+
+impl Program {
+    /// Counts the items of the program and the clauses generated for
+    /// them. This lowers the whole program to clauses, so it costs as
+    /// much as `Program::environment`.
+    pub fn stats(&self) -> ProgramStats {
+        let families = ClauseFamilies::default();
+        let environment = self.environment(families);
+
+        let mut clauses = ClauseStats {
+            custom: self.custom_clauses.len(),
+            structs: self
+                .struct_data
+                .values()
+                .map(|d| d.to_program_clauses(families).len())
+                .sum(),
+            traits: self
+                .trait_data
+                .values()
+                .map(|d| d.to_program_clauses(self, families).len())
+                .sum(),
+            impls: self
+                .impl_data
+                .values()
+                .filter(|d| d.binders.value.trait_ref.is_positive())
+                .map(|d| {
+                    let values = &d.binders.value.associated_ty_values;
+                    1 + values
+                        .iter()
+                        .map(|atv| atv.to_program_clauses(self, d).len())
+                        .sum::<usize>()
+                })
+                .sum(),
+            associated_tys: self
+                .associated_ty_data
+                .values()
+                .map(|d| d.to_program_clauses(self, families).len())
+                .sum(),
+            auto_traits: self.auto_trait_clauses().len(),
+            lang_items: 0,
+        };
+        // The lang item clauses are not generated by any item, so we count
+        // them as whatever is left of the environment.
+        clauses.lang_items = environment.program_clauses.len() - clauses.total();
+
+        ProgramStats {
+            structs: self.struct_data.len(),
+            traits: self.trait_data.len(),
+            impls: self.impl_data.len(),
+            associated_tys: self.associated_ty_data.len(),
+            clauses,
+            max_binder_depth: environment
+                .program_clauses
+                .iter()
+                .map(clause_depth)
+                .max()
+                .unwrap_or(0),
+        }
+    }
+}
+
+impl fmt::Display for ProgramStats {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(fmt, "structs:          {}", self.structs)?;
+        writeln!(fmt, "traits:           {}", self.traits)?;
+        writeln!(fmt, "impls:            {}", self.impls)?;
+        writeln!(fmt, "associated types: {}", self.associated_tys)?;
+        writeln!(fmt, "clauses:          {}", self.clauses)?;
+        writeln!(fmt, "max binder depth: {}", self.max_binder_depth)
+    }
+}
+
+impl fmt::Display for ClauseStats {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{} ({} custom, {} from structs, {} from traits, {} from impls, \
+             {} from associated types, {} from auto traits, {} from lang items)",
+            self.total(),
+            self.custom,
+            self.structs,
+            self.traits,
+            self.impls,
+            self.associated_tys,
+            self.auto_traits,
+            self.lang_items
+        )
+    }
+}
+
+fn clause_depth(clause: &ProgramClause) -> usize {
+    match clause {
+        ProgramClause::Implies(implication) => implication_depth(implication),
+        ProgramClause::ForAll(binders) => 1 + implication_depth(&binders.value),
+    }
+}
+
+fn implication_depth(implication: &ProgramClauseImplication) -> usize {
+    implication.conditions.iter().map(goal_depth).max().unwrap_or(0)
+}
+
+fn goal_depth(goal: &Goal) -> usize {
+    match goal {
+        Goal::Quantified(_, binders) => 1 + goal_depth(&binders.value),
+        Goal::Implies(clauses, goal) => clauses
+            .iter()
+            .map(clause_depth)
+            .chain(Some(goal_depth(goal)))
+            .max()
+            .unwrap_or(0),
+        Goal::And(a, b) => goal_depth(a).max(goal_depth(b)),
+        Goal::All(goals) | Goal::Any(goals) => goals.iter().map(goal_depth).max().unwrap_or(0),
+        Goal::Not(goal) => goal_depth(goal),
+        Goal::Leaf(_) | Goal::CannotProve(()) => 0,
+    }
+}
@@ -0,0 +1,38 @@
+#![cfg(test)]
+
+use crate::rules::ClauseFamilies;
+use crate::test_util::*;
+use chalk_solve::solve::SolverChoice;
+
+#[test]
+fn program_stats() {
+    let program = parse_and_lower_program(
+        "
+        trait Clone { }
+        #[lang_deref]
+        trait Deref { type Target; }
+        struct Foo { }
+        struct Bar<T> { }
+        impl Clone for Foo { }
+        impl<T> Clone for Bar<T> where T: Clone { }
+        forall<T> { WellFormed(Foo) if forall<U> { exists<V> { FromEnv(Foo) } } }
+        ",
+        SolverChoice::default(),
+    )
+    .unwrap();
+
+    let stats = program.stats();
+    assert_eq!(stats.structs, 2);
+    assert_eq!(stats.traits, 2);
+    assert_eq!(stats.impls, 2);
+    assert_eq!(stats.associated_tys, 1);
+    assert_eq!(stats.clauses.custom, 1);
+    assert_eq!(stats.clauses.impls, 2);
+    assert_eq!(stats.clauses.auto_traits, 0);
+    assert_eq!(stats.clauses.lang_items, 1);
+    assert_eq!(
+        stats.clauses.total(),
+        program.environment(ClauseFamilies::default()).program_clauses.len()
+    );
+    assert_eq!(stats.max_binder_depth, 3);
+}
@@ -0,0 +1,104 @@
+#![cfg(test)]
+
+use crate::coherence::orphan;
+use crate::rules::{wf, ClauseFamilies};
+use crate::rust_ir::lowering::{LowerGoal, LowerProgram};
+use crate::rust_ir::Program;
+use chalk_ir::Goal;
+use chalk_parse;
+use chalk_solve::solve::SolverChoice;
+use failure::{Error, Fallible};
+use regex::Regex;
+use std::sync::Arc;
+
+/// Parses, lowers and checks `text` the way the queries of the `chalk`
+/// crate do, without a database.
+pub fn parse_and_lower_program(
+    text: &str,
+    solver_choice: SolverChoice,
+) -> Result<Arc<Program>, String> {
+    let program: Fallible<_> = try {
+        let mut program = chalk_parse::parse_program(text)?.lower()?;
+        let mut env = program.environment(ClauseFamilies::default());
+        solver_choice.clause_order().shuffle_program_clauses(&mut env);
+        let env = Arc::new(env);
+
+        program.record_specialization_priorities(env.clone(), solver_choice)?;
+        let program = Arc::new(program);
+        orphan::perform_orphan_check(program.clone(), env.clone(), solver_choice)?;
+        wf::verify_well_formedness(program.clone(), env, solver_choice)?;
+        program
+    };
+    program.map_err(|err| err.to_string())
+}
+
+pub fn parse_and_lower_goal(program: &Program, text: &str) -> Result<Box<Goal>, Error> {
+    chalk_parse::parse_goal(text)?.lower(program)
+}
+
+macro_rules! lowering_success {
+    (program $program:tt) => {
+        let program_text = stringify!($program);
+        assert!(program_text.starts_with("{"));
+        assert!(program_text.ends_with("}"));
+        let result = parse_and_lower_program(
+            &program_text[1..program_text.len() - 1],
+            chalk_solve::solve::SolverChoice::default(),
+        );
+        if let Err(ref e) = result {
+            println!("lowering error: {}", e);
+        }
+        assert!(result.is_ok());
+    };
+}
+
+macro_rules! lowering_error {
+    (program $program:tt error_msg { $expected:expr }) => {
+        let error = lowering_error!(@lower $program);
+        let expected = $expected;
+        assert_eq!(error.to_string(), expected.to_string());
+    };
+
+    // error_msg_contains { "S" } -- test that the error message contains S
+    (program $program:tt error_msg_contains { $pattern:expr }) => {
+        let error = lowering_error!(@lower $program);
+        assert_error_contains(&error, $pattern);
+    };
+
+    // error_msg_matches { "R" } -- test that the regex R matches (part of) the
+    // error message
+    (program $program:tt error_msg_matches { $regex:expr }) => {
+        let error = lowering_error!(@lower $program);
+        assert_error_matches(&error, $regex);
+    };
+
+    (@lower $program:tt) => {{
+        let program_text = stringify!($program);
+        assert!(program_text.starts_with("{"));
+        assert!(program_text.ends_with("}"));
+        parse_and_lower_program(
+            &program_text[1..program_text.len() - 1],
+            chalk_solve::solve::SolverChoice::default(),
+        )
+        .unwrap_err()
+    }};
+}
+
+crate fn assert_error_contains(error: &str, pattern: &str) {
+    assert!(
+        error.contains(pattern),
+        "error `{}` does not contain `{}`",
+        error,
+        pattern
+    );
+}
+
+crate fn assert_error_matches(error: &str, regex: &str) {
+    let regex = Regex::new(regex).unwrap();
+    assert!(
+        regex.is_match(error),
+        "error `{}` does not match `{}`",
+        error,
+        regex
+    );
+}
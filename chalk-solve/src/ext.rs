@@ -1,6 +1,10 @@
+use crate::infer::ucanonicalize::{UCanonicalized, UniverseMap};
 use crate::infer::InferenceTable;
-use chalk_ir::fold::Fold;
+use crate::solve::elaborate;
+use chalk_ir::fold::{Fold, Shift};
+use chalk_ir::tls::ForallName;
 use chalk_ir::*;
+use std::collections::BTreeMap;
 
 pub trait CanonicalExt<T> {
     fn map<OP, U>(self, op: OP) -> Canonical<U::Result>
@@ -45,9 +49,64 @@ impl<T> CanonicalExt<T> for Canonical<T> {
 
 pub trait GoalExt {
     fn into_peeled_goal(self) -> UCanonical<InEnvironment<Goal>>;
+    fn into_peeled_goal_with_universes(self) -> PeeledGoal;
     fn into_closed_goal(self) -> UCanonical<InEnvironment<Goal>>;
 }
 
+/// A goal peeled by `into_peeled_goal_with_universes`, along with which
+/// universes its `forall<>` binders were turned into. Placeholders in
+/// the solutions to `goal` are in the universes of `goal`, so this is
+/// what tells which binder each of them stands for.
+#[derive(Clone, Debug)]
+pub struct PeeledGoal {
+    pub goal: UCanonical<InEnvironment<Goal>>,
+
+    /// For each peeled `forall<>`, outermost first, the universe of
+    /// its placeholders in `goal` -- or `None` if none of them appear
+    /// in `goal`, in which case the universe was dropped.
+    pub forall_universes: Vec<Option<UniverseIndex>>,
+
+    /// Maps the universes of `goal` to those of the peeling, where the
+    /// `n`th `forall<>` (counting from 1) got universe `n`.
+    pub universes: UniverseMap,
+}
+
+impl PeeledGoal {
+    /// For a placeholder in a solution to `goal`, returns which
+    /// `forall<>` it comes from (counting from 0, outermost first) and
+    /// which of that quantifier's binders it is.
+    pub fn placeholder_binder(&self, placeholder: PlaceholderIndex) -> Option<(usize, usize)> {
+        let forall = self
+            .forall_universes
+            .iter()
+            .position(|&universe| universe == Some(placeholder.universe()))?;
+        Some((forall, placeholder.index()))
+    }
+
+    /// Names the universes of `goal` after the `forall<>` binders they
+    /// come from, given the names of each peeled `forall<>`'s binders
+    /// (outermost first). Installing the result with
+    /// `tls::with_forall_names` makes the placeholders in the solutions
+    /// to `goal` print with those names.
+    pub fn forall_names(
+        &self,
+        binders: Vec<Vec<Identifier>>,
+    ) -> BTreeMap<UniverseIndex, ForallName> {
+        self.forall_universes
+            .iter()
+            .zip(binders)
+            .enumerate()
+            .filter_map(|(index, (&universe, binders))| {
+                let forall = ForallName {
+                    binders,
+                    depth: index + 1,
+                };
+                Some((universe?, forall))
+            })
+            .collect()
+    }
+}
+
 impl GoalExt for Goal {
     /// Returns a canonical goal in which the outermost `exists<>` and
     /// `forall<>` quantifiers (as well as implications) have been
@@ -56,7 +115,15 @@ impl GoalExt for Goal {
     /// does not -- at present -- contain any variables. Useful for
     /// REPLs and tests but not much else.
     fn into_peeled_goal(self) -> UCanonical<InEnvironment<Goal>> {
+        self.into_peeled_goal_with_universes().goal
+    }
+
+    /// Like `into_peeled_goal`, but also tells which universe each of
+    /// the peeled `forall<>` binders ended up in.
+    fn into_peeled_goal_with_universes(self) -> PeeledGoal {
+        debug_assert!(!self.has_free_vars(), "goal has escaping bound variables: {:?}", self);
         let mut infer = InferenceTable::new();
+        let mut foralls = vec![];
         let peeled_goal = {
             let mut env_goal = InEnvironment::new(&Environment::new(), self);
             loop {
@@ -64,6 +131,7 @@ impl GoalExt for Goal {
                 match goal {
                     Goal::Quantified(QuantifierKind::ForAll, subgoal) => {
                         let subgoal = infer.instantiate_binders_universally(&subgoal);
+                        foralls.push(infer.max_universe());
                         env_goal = InEnvironment::new(&environment, *subgoal);
                     }
 
@@ -73,7 +141,7 @@ impl GoalExt for Goal {
                     }
 
                     Goal::Implies(wc, subgoal) => {
-                        let new_environment = &environment.add_clauses(wc);
+                        let new_environment = &elaborate::add_hypotheses(&environment, wc);
                         env_goal = InEnvironment::new(&new_environment, *subgoal);
                     }
 
@@ -82,7 +150,18 @@ impl GoalExt for Goal {
             }
         };
         let canonical = infer.canonicalize(&peeled_goal).quantified;
-        infer.u_canonicalize(&canonical).quantified
+        let UCanonicalized {
+            quantified,
+            universes,
+        } = infer.u_canonicalize(&canonical);
+        PeeledGoal {
+            goal: quantified,
+            forall_universes: foralls
+                .into_iter()
+                .map(|universe| universes.canonical_universe(universe))
+                .collect(),
+            universes,
+        }
     }
 
     /// Given a goal with no free variables (a "closed" goal), creates
@@ -95,6 +174,7 @@ impl GoalExt for Goal {
     ///
     /// Will panic if this goal does in fact contain free variables.
     fn into_closed_goal(self) -> UCanonical<InEnvironment<Goal>> {
+        debug_assert!(!self.has_free_vars(), "goal has escaping bound variables: {:?}", self);
         let mut infer = InferenceTable::new();
         let env_goal = InEnvironment::new(&Environment::new(), self);
         let canonical_goal = infer.canonicalize(&env_goal).quantified;
@@ -6,18 +6,28 @@ pub mod canonicalize;
 pub mod instantiate;
 mod invert;
 mod normalize_deep;
+pub mod round_trip;
 mod test;
 pub mod ucanonicalize;
 pub mod unify;
 pub mod var;
 
+use self::unify::UnificationHook;
 use self::var::*;
+use std::sync::Arc;
 
-#[derive(Clone)]
 pub struct InferenceTable {
     unify: ena::InPlaceUnificationTable<EnaVariable>,
     vars: Vec<EnaVariable>,
     max_universe: UniverseIndex,
+
+    /// Consulted when unifying two applications of the same type
+    /// constructor (see `set_unification_hook`).
+    unification_hook: Option<Arc<dyn UnificationHook>>,
+
+    /// For a table that can be `reset`, a snapshot of it while it was
+    /// still empty.
+    empty: Option<ena::Snapshot<ena::InPlace<EnaVariable>>>,
 }
 
 pub struct InferenceSnapshot {
@@ -35,9 +45,37 @@ impl InferenceTable {
             unify: ena::UnificationTable::new(),
             vars: vec![],
             max_universe: UniverseIndex::root(),
+            unification_hook: None,
+            empty: None,
         }
     }
 
+    /// Creates an empty inference table that can be `reset` to empty,
+    /// keeping the memory it has grown. Until then, the changes made to
+    /// it are logged, so that they can be undone.
+    pub fn resettable() -> Self {
+        let mut table = InferenceTable::new();
+        table.empty = Some(table.unify.snapshot());
+        table
+    }
+
+    /// Removes all the variables and universes of a table made by
+    /// `resettable`.
+    pub fn reset(&mut self) {
+        let empty = self.empty.take().expect("only resettable tables can be reset");
+        self.unify.rollback_to(empty);
+        self.empty = Some(self.unify.snapshot());
+        self.vars.clear();
+        self.max_universe = UniverseIndex::root();
+        self.unification_hook = None;
+    }
+
+    /// Makes all later unifications in this table consult `hook` (or
+    /// stop consulting a hook, if `None`).
+    pub fn set_unification_hook(&mut self, hook: Option<Arc<dyn UnificationHook>>) {
+        self.unification_hook = hook;
+    }
+
     /// Creates a new inference table, pre-populated with
     /// `num_universes` fresh universes. Instantiates the canonical
     /// value `canonical` within those universes (which must not
@@ -52,17 +90,29 @@ impl InferenceTable {
     where
         T: Fold<Result = T> + Clone,
     {
-        let mut table = InferenceTable::new();
+        InferenceTable::new().instantiate_empty(num_universes, canonical)
+    }
+
+    /// As `from_canonical`, but in `self`, which must be empty.
+    pub fn instantiate_empty<T>(
+        mut self,
+        num_universes: usize,
+        canonical: &Canonical<T>,
+    ) -> (Self, Substitution, T)
+    where
+        T: Fold<Result = T> + Clone,
+    {
+        assert!(self.vars.is_empty() && self.max_universe == UniverseIndex::root());
 
         assert!(num_universes >= 1); // always have U0
         for _ in 1..num_universes {
-            table.new_universe();
+            self.new_universe();
         }
 
-        let subst = table.fresh_subst(&canonical.binders);
+        let subst = self.fresh_subst(&canonical.binders);
         let value = canonical.value.fold_with(&mut &subst, 0).unwrap();
 
-        (table, subst, value)
+        (self, subst, value)
     }
 
     /// Creates and returns a fresh universe that is distinct from all
@@ -6,7 +6,7 @@ use chalk_ir::fold::{
 use chalk_ir::*;
 use std::cmp::max;
 
-use super::{EnaVariable, InferenceTable, ParameterEnaVariable};
+use super::{EnaVariable, InferenceTable, ParameterEnaVariable, ParameterEnaVariableExt};
 
 impl InferenceTable {
     /// Given a value `value` with variables in it, replaces those variables
@@ -62,6 +62,14 @@ pub struct Canonicalized<T> {
     max_universe: UniverseIndex,
 }
 
+impl<T> Canonicalized<T> {
+    /// The inference variables that the binders of `quantified` stand
+    /// for, in order.
+    pub fn free_vars(&self) -> Vec<Parameter> {
+        self.free_vars.iter().cloned().map(|v| v.to_parameter()).collect()
+    }
+}
+
 struct Canonicalizer<'q> {
     table: &'q mut InferenceTable,
     free_vars: Vec<ParameterEnaVariable>,
@@ -101,7 +109,7 @@ impl<'q> PlaceholderFolder for Canonicalizer<'q> {
         universe: PlaceholderIndex,
         _binders: usize,
     ) -> Fallible<Ty> {
-        self.max_universe = max(self.max_universe, universe.ui);
+        self.max_universe = max(self.max_universe, universe.universe());
         Ok(universe.to_ty())
     }
 
@@ -110,7 +118,7 @@ impl<'q> PlaceholderFolder for Canonicalizer<'q> {
         universe: PlaceholderIndex,
         _binders: usize,
     ) -> Fallible<Lifetime> {
-        self.max_universe = max(self.max_universe, universe.ui);
+        self.max_universe = max(self.max_universe, universe.universe());
         Ok(universe.to_lifetime())
     }
 }
@@ -138,7 +146,7 @@ impl<'q> InferenceFolder for Canonicalizer<'q> {
                 let free_var = ParameterKind::Ty(self.table.unify.find(var));
                 let position = self.add(free_var);
                 debug!("not yet unified: position={:?}", position);
-                Ok(Ty::BoundVar(position + binders))
+                Ok(DebruijnIndex::new(position).shifted_in(binders).to_ty())
             }
         }
     }
@@ -159,7 +167,7 @@ impl<'q> InferenceFolder for Canonicalizer<'q> {
                 let free_var = ParameterKind::Lifetime(self.table.unify.find(var));
                 let position = self.add(free_var);
                 debug!("not yet unified: position={:?}", position);
-                Ok(Lifetime::BoundVar(position + binders))
+                Ok(Lifetime::BoundVar(DebruijnIndex::new(position).shifted_in(binders)))
             }
         }
     }
@@ -10,6 +10,9 @@ impl InferenceTable {
     /// C, which would be equivalent to
     /// `self.instantiate_canonical(v)`.
     pub fn fresh_subst(&mut self, binders: &[ParameterKind<UniverseIndex>]) -> Substitution {
+        // Each parameter is made from its own binder, so there is nothing
+        // for a `SubstBuilder` to check; collecting straight into the
+        // substitution saves its vectors on this hot path.
         Substitution {
             parameters: binders
                 .iter()
@@ -77,20 +80,9 @@ impl InferenceTable {
     {
         let (binders, value) = arg.split();
         let ui = self.new_universe();
-        let parameters: Vec<_> = binders
-            .iter()
-            .enumerate()
-            .map(|(idx, pk)| {
-                let placeholder_idx = PlaceholderIndex { ui, idx };
-                match *pk {
-                    ParameterKind::Lifetime(()) => {
-                        let lt = placeholder_idx.to_lifetime();
-                        ParameterKind::Lifetime(lt)
-                    }
-                    ParameterKind::Ty(()) => ParameterKind::Ty(placeholder_idx.to_ty()),
-                }
-            })
-            .collect();
+        let parameters = SubstBuilder::new(binders.iter().cloned())
+            .fill_placeholders(ui)
+            .into_parameters();
         Subst::apply(&parameters, value)
     }
 }
@@ -109,6 +101,14 @@ impl<T> BindersAndValue for Binders<T> {
     }
 }
 
+impl BindersAndValue for QuantifiedTy {
+    type Output = Ty;
+
+    fn split(&self) -> (&[ParameterKind<()>], &Self::Output) {
+        (&self.binders, &self.ty)
+    }
+}
+
 impl<'a, T> BindersAndValue for (&'a Vec<ParameterKind<()>>, &'a T) {
     type Output = T;
 
@@ -125,7 +125,7 @@ impl<'q> PlaceholderFolder for Inverter<'q> {
         Ok(self
             .inverted_ty
             .entry(universe)
-            .or_insert_with(|| table.new_variable(universe.ui))
+            .or_insert_with(|| table.new_variable(universe.universe()))
             .to_ty()
             .shifted_in(binders))
     }
@@ -139,7 +139,7 @@ impl<'q> PlaceholderFolder for Inverter<'q> {
         Ok(self
             .inverted_lifetime
             .entry(universe)
-            .or_insert_with(|| table.new_variable(universe.ui))
+            .or_insert_with(|| table.new_variable(universe.universe()))
             .to_lifetime()
             .shifted_in(binders))
     }
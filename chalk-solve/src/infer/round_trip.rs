@@ -0,0 +1,89 @@
+//! The round trip a goal makes through the solver: it is
+//! canonicalized (so that it no longer refers to the inference
+//! variables of the table it came from), solved, and then the answer is
+//! instantiated back into the original table. The solver does this for
+//! each of its own subgoals; these methods let external tools do the
+//! same.
+//!
+//! ```ignore
+//! let query = infer.canonicalize_query(&goal);
+//! if let Some(Solution::Unique(answer)) = solver.solve(&query.goal) {
+//!     let result = infer.instantiate_answer(&query, &goal.environment, &answer)?;
+//!     // `goal` now refers to the values of its variables in `answer`,
+//!     // provided `result.goals` hold.
+//! }
+//! ```
+
+use chalk_engine::fallible::*;
+use chalk_ir::*;
+use std::sync::Arc;
+
+use super::canonicalize::Canonicalized;
+use super::ucanonicalize::{UCanonicalized, UniverseMap};
+use super::unify::UnificationResult;
+use super::InferenceTable;
+
+/// A goal canonicalized with `InferenceTable::canonicalize_query`.
+#[derive(Clone, Debug)]
+pub struct CanonicalQuery {
+    /// The goal to give to the solver.
+    pub goal: UCanonical<InEnvironment<Goal>>,
+
+    /// The inference variables that the canonical binders of `goal`
+    /// stand for.
+    pub free_vars: Vec<Parameter>,
+
+    /// Maps the universes of `goal` to those of the table.
+    pub universes: UniverseMap,
+}
+
+impl InferenceTable {
+    /// Canonicalizes `goal`, which refers to the inference variables of
+    /// this table, into the form the solver expects.
+    pub fn canonicalize_query(&mut self, goal: &InEnvironment<Goal>) -> CanonicalQuery {
+        let canonicalized = self.canonicalize(goal);
+        let free_vars = canonicalized.free_vars();
+        let Canonicalized { quantified, .. } = canonicalized;
+        let UCanonicalized {
+            quantified,
+            universes,
+        } = self.u_canonicalize(&quantified);
+        CanonicalQuery {
+            goal: quantified,
+            free_vars,
+            universes,
+        }
+    }
+
+    /// Instantiates `answer`, an answer to `query.goal` (such as the
+    /// substitution of a `Solution::Unique`), in this table: each
+    /// variable of the goal is unified with its value in the answer.
+    ///
+    /// The result holds the lifetime constraints of the answer, plus
+    /// whatever goals and constraints the unification produced.
+    pub fn instantiate_answer(
+        &mut self,
+        query: &CanonicalQuery,
+        environment: &Arc<Environment>,
+        answer: &Canonical<ConstrainedSubst>,
+    ) -> Fallible<UnificationResult> {
+        let answer = query.universes.map_from_canonical(answer);
+        let ConstrainedSubst { subst, constraints } = self.instantiate_canonical(&answer);
+        let mut result = self.unify(environment, &query.free_vars[..], &subst.parameters[..])?;
+        result.constraints.extend(constraints);
+        Ok(result)
+    }
+
+    /// Like `instantiate_answer`, but for the substitution of a
+    /// `Guidance`, which has no lifetime constraints.
+    pub fn instantiate_guidance(
+        &mut self,
+        query: &CanonicalQuery,
+        environment: &Arc<Environment>,
+        guidance: &Canonical<Substitution>,
+    ) -> Fallible<UnificationResult> {
+        let guidance = query.universes.map_from_canonical(guidance);
+        let subst = self.instantiate_canonical(&guidance);
+        self.unify(environment, &query.free_vars[..], &subst.parameters[..])
+    }
+}
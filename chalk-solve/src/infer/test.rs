@@ -137,9 +137,84 @@ fn projection_eq() {
         .unwrap_err();
 }
 
-const U0: UniverseIndex = UniverseIndex { counter: 0 };
-const U1: UniverseIndex = UniverseIndex { counter: 1 };
-const U2: UniverseIndex = UniverseIndex { counter: 2 };
+#[test]
+fn unification_hook() {
+    use super::unify::{ApplicationUnification, UnificationHook};
+
+    /// Treats `item 0` as non-injective and `item 1` as distinct from
+    /// itself, and relates the parameters of `item 2` with a goal.
+    #[derive(Debug)]
+    struct Hook;
+
+    impl UnificationHook for Hook {
+        fn unify_application_tys(
+            &self,
+            a: &ApplicationTy,
+            b: &ApplicationTy,
+        ) -> ApplicationUnification {
+            match a.name {
+                TypeName::ItemId(ItemId { index: 0 }) => ApplicationUnification::Equal,
+                TypeName::ItemId(ItemId { index: 1 }) => ApplicationUnification::Distinct,
+                TypeName::ItemId(ItemId { index: 2 }) => {
+                    ApplicationUnification::Goals(vec![DomainGoal::Derefs(Derefs {
+                        source: a.parameters[0].assert_ty_ref().clone(),
+                        target: b.parameters[0].assert_ty_ref().clone(),
+                    })])
+                }
+                _ => ApplicationUnification::Structural,
+            }
+        }
+    }
+
+    let mut table = InferenceTable::new();
+    table.set_unification_hook(Some(Arc::new(Hook)));
+    let environment0 = Environment::new();
+    let a = table.new_variable(U0).to_ty();
+
+    table
+        .unify(
+            &environment0,
+            &ty!(apply (item 0) (apply (item 3))),
+            &ty!(apply (item 0) (apply (item 4))),
+        )
+        .unwrap();
+    table
+        .unify(&environment0, &ty!(apply (item 1)), &ty!(apply (item 1)))
+        .unwrap_err();
+
+    let UnificationResult { goals, constraints } = table
+        .unify(
+            &environment0,
+            &ty!(apply (item 2) (expr a)),
+            &ty!(apply (item 2) (apply (item 3))),
+        )
+        .unwrap();
+    assert_eq!(
+        goals,
+        vec![InEnvironment::new(
+            &environment0,
+            DomainGoal::Derefs(Derefs {
+                source: a.clone(),
+                target: ty!(apply (item 3)),
+            })
+        )]
+    );
+    assert!(constraints.is_empty());
+
+    // The parameters of other types are unified as usual.
+    table
+        .unify(
+            &environment0,
+            &ty!(apply (item 3) (expr a)),
+            &ty!(apply (item 3) (apply (item 4))),
+        )
+        .unwrap();
+    assert_eq!(table.normalize_deep(&a), ty!(apply (item 4)));
+}
+
+const U0: UniverseIndex = UniverseIndex::new(0);
+const U1: UniverseIndex = UniverseIndex::new(1);
+const U2: UniverseIndex = UniverseIndex::new(2);
 
 fn make_table() -> InferenceTable {
     let mut table = InferenceTable::new();
@@ -265,3 +340,67 @@ fn lifetime_constraint_indirect() {
         "InEnvironment { environment: Env([]), goal: \'?2 == \'!1_0 }",
     );
 }
+
+#[test]
+fn round_trip() {
+    // exists(A -> A = foo B), answered with `B = bar`.
+    let mut table = InferenceTable::new();
+    let environment0 = Environment::new();
+    let a = table.new_variable(U0).to_ty();
+    let b = table.new_variable(U0).to_ty();
+    let goal = InEnvironment::new(
+        &environment0,
+        Goal::Leaf(LeafGoal::EqGoal(EqGoal {
+            a: ParameterKind::Ty(a.clone()),
+            b: ParameterKind::Ty(ty!(apply (item 0) (expr b))),
+        })),
+    );
+
+    let query = table.canonicalize_query(&goal);
+    assert_eq!(query.goal.canonical.binders.len(), 2);
+    assert_eq!(
+        query.free_vars,
+        vec![ParameterKind::Ty(a.clone()), ParameterKind::Ty(b.clone())]
+    );
+
+    let answer = Canonical {
+        value: ConstrainedSubst {
+            subst: Substitution {
+                parameters: vec![
+                    ParameterKind::Ty(ty!(apply (item 0) (apply (item 1)))),
+                    ParameterKind::Ty(ty!(apply (item 1))),
+                ]
+                .into(),
+            },
+            constraints: vec![],
+        },
+        binders: vec![],
+    };
+    let result = table
+        .instantiate_answer(&query, &environment0, &answer)
+        .unwrap();
+    assert!(result.goals.is_empty());
+    assert!(result.constraints.is_empty());
+    assert_eq!(
+        table.normalize_deep(&a),
+        ty!(apply (item 0) (apply (item 1)))
+    );
+}
+
+#[test]
+fn reset() {
+    let mut table = InferenceTable::resettable();
+    let environment0 = Environment::new();
+    let u1 = table.new_universe();
+    let a = table.new_variable(u1).to_ty();
+    table
+        .unify(&environment0, &a, &ty!(apply (item 0)))
+        .unwrap();
+
+    // The variable is gone, and the next one takes its place, unbound.
+    table.reset();
+    assert_eq!(table.max_universe(), U0);
+    let b = table.new_variable(U0).to_ty();
+    assert_eq!(b, a);
+    assert_eq!(table.normalize_deep(&b), b);
+}
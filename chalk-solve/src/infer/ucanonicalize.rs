@@ -2,25 +2,26 @@ use chalk_engine::fallible::*;
 use chalk_ir::fold::{
     DefaultFreeVarFolder, DefaultInferenceFolder, DefaultTypeFolder, Fold, PlaceholderFolder,
 };
+use chalk_ir::visit::{Visit, Visitor};
 use chalk_ir::*;
 
 use super::InferenceTable;
 
 impl InferenceTable {
-    pub fn u_canonicalize<T: Fold>(&mut self, value0: &Canonical<T>) -> UCanonicalized<T::Result> {
+    pub fn u_canonicalize<T: Fold + Visit>(
+        &mut self,
+        value0: &Canonical<T>,
+    ) -> UCanonicalized<T::Result> {
         debug!("u_canonicalize({:#?})", value0);
 
         // First, find all the universes that appear in `value`.
         let mut universes = UniverseMap::new();
-        value0
-            .value
-            .fold_with(
-                &mut UCollector {
-                    universes: &mut universes,
-                },
-                0,
-            )
-            .unwrap();
+        value0.value.visit_with(
+            &mut UCollector {
+                universes: &mut universes,
+            },
+            0,
+        );
 
         // Now re-map the universes found in value. We have to do this
         // in a second pass because it is only then that we know the
@@ -59,7 +60,7 @@ pub struct UCanonicalized<T> {
     pub quantified: UCanonical<T>,
 
     /// A map between the universes in `quantified` and the original universes
-    crate universes: UniverseMap,
+    pub universes: UniverseMap,
 }
 
 /// Maps the universes found in the `u_canonicalize` result (the
@@ -83,10 +84,20 @@ impl UniverseMap {
     }
 
     /// Number of canonical universes.
-    fn num_canonical_universes(&self) -> usize {
+    pub fn num_canonical_universes(&self) -> usize {
         self.universes.len()
     }
 
+    /// The canonical universe for `universe`, if it appeared in the
+    /// original value; unlike `map_universe_to_canonical`, this does not
+    /// fall back to a lower universe.
+    pub fn canonical_universe(&self, universe: UniverseIndex) -> Option<UniverseIndex> {
+        self.universes
+            .binary_search(&universe)
+            .ok()
+            .map(UniverseIndex::new)
+    }
+
     fn add(&mut self, universe: UniverseIndex) {
         if let Err(i) = self.universes.binary_search(&universe) {
             self.universes.insert(i, universe);
@@ -139,9 +150,9 @@ impl UniverseMap {
     /// versa). Moreover, since there are no placeholders from U2 in
     /// the original query, there is no way we would have equated `?0`
     /// with such a name.
-    fn map_universe_to_canonical(&self, universe: UniverseIndex) -> UniverseIndex {
+    pub fn map_universe_to_canonical(&self, universe: UniverseIndex) -> UniverseIndex {
         match self.universes.binary_search(&universe) {
-            Ok(index) => UniverseIndex { counter: index },
+            Ok(index) => UniverseIndex::new(index),
 
             // `index` is the location in the vector where universe
             // *would have* gone.  So, in our example from the comment
@@ -155,7 +166,7 @@ impl UniverseMap {
             // root universe, we always add that to the vector.
             Err(index) => {
                 assert!(index > 0);
-                UniverseIndex { counter: index - 1 }
+                UniverseIndex::new(index - 1)
             }
         }
     }
@@ -163,21 +174,17 @@ impl UniverseMap {
     /// Given a "canonical universe" -- one found in the
     /// `u_canonicalize` result -- returns the original universe that
     /// it corresponded to.
-    fn map_universe_from_canonical(&self, universe: UniverseIndex) -> UniverseIndex {
-        if universe.counter < self.universes.len() {
-            self.universes[universe.counter]
+    pub fn map_universe_from_canonical(&self, universe: UniverseIndex) -> UniverseIndex {
+        if universe.counter() < self.universes.len() {
+            self.universes[universe.counter()]
         } else {
             // If this universe is out of bounds, we assume an
             // implicit `forall` binder, effectively, and map to a
             // "big enough" universe in the original space. See
             // comments on `map_from_canonical` for a detailed
             // explanation.
-            let difference = universe.counter - self.universes.len();
-            let max_counter = self.universes.last().unwrap().counter;
-            let new_counter = max_counter + difference + 1;
-            UniverseIndex {
-                counter: new_counter,
-            }
+            let difference = universe.counter() - self.universes.len();
+            self.universes.last().unwrap().nth_next(difference + 1)
         }
     }
 
@@ -217,39 +224,27 @@ impl UniverseMap {
     }
 }
 
-/// The `UCollector` is a "no-op" in terms of the value, but along the
-/// way it collects all universes that were found into a vector.
+/// The `UCollector` collects all universes that are found in a value
+/// into a vector.
 struct UCollector<'q> {
     universes: &'q mut UniverseMap,
 }
 
-impl<'q> DefaultTypeFolder for UCollector<'q> {}
-
-impl<'q> PlaceholderFolder for UCollector<'q> {
-    fn fold_free_placeholder_ty(
-        &mut self,
-        universe: PlaceholderIndex,
-        _binders: usize,
-    ) -> Fallible<Ty> {
-        self.universes.add(universe.ui);
-        Ok(universe.to_ty())
+impl<'q> Visitor for UCollector<'q> {
+    fn visit_placeholder_ty(&mut self, universe: PlaceholderIndex, _binders: usize) {
+        self.universes.add(universe.universe());
     }
 
-    fn fold_free_placeholder_lifetime(
-        &mut self,
-        universe: PlaceholderIndex,
-        _binders: usize,
-    ) -> Fallible<Lifetime> {
-        self.universes.add(universe.ui);
-        Ok(universe.to_lifetime())
+    fn visit_placeholder_lifetime(&mut self, universe: PlaceholderIndex, _binders: usize) {
+        self.universes.add(universe.universe());
     }
-}
 
-impl<'q> DefaultFreeVarFolder for UCollector<'q> {}
+    fn visit_inference_ty(&mut self, var: InferenceVar, _binders: usize) {
+        panic!("unexpected inference type `{:?}`", var)
+    }
 
-impl<'q> DefaultInferenceFolder for UCollector<'q> {
-    fn forbid() -> bool {
-        true
+    fn visit_inference_lifetime(&mut self, var: InferenceVar, _binders: usize) {
+        panic!("unexpected inference lifetime `'{:?}`", var)
     }
 }
 
@@ -271,12 +266,8 @@ impl<'q> PlaceholderFolder for UMapToCanonical<'q> {
         universe0: PlaceholderIndex,
         _binders: usize,
     ) -> Fallible<Ty> {
-        let ui = self.universes.map_universe_to_canonical(universe0.ui);
-        Ok(PlaceholderIndex {
-            ui,
-            idx: universe0.idx,
-        }
-        .to_ty())
+        let ui = self.universes.map_universe_to_canonical(universe0.universe());
+        Ok(universe0.in_universe(ui).to_ty())
     }
 
     fn fold_free_placeholder_lifetime(
@@ -284,12 +275,8 @@ impl<'q> PlaceholderFolder for UMapToCanonical<'q> {
         universe0: PlaceholderIndex,
         _binders: usize,
     ) -> Fallible<Lifetime> {
-        let universe = self.universes.map_universe_to_canonical(universe0.ui);
-        Ok(PlaceholderIndex {
-            ui: universe,
-            idx: universe0.idx,
-        }
-        .to_lifetime())
+        let ui = self.universes.map_universe_to_canonical(universe0.universe());
+        Ok(universe0.in_universe(ui).to_lifetime())
     }
 }
 
@@ -307,12 +294,8 @@ impl<'q> PlaceholderFolder for UMapFromCanonical<'q> {
         universe0: PlaceholderIndex,
         _binders: usize,
     ) -> Fallible<Ty> {
-        let ui = self.universes.map_universe_from_canonical(universe0.ui);
-        Ok(PlaceholderIndex {
-            ui,
-            idx: universe0.idx,
-        }
-        .to_ty())
+        let ui = self.universes.map_universe_from_canonical(universe0.universe());
+        Ok(universe0.in_universe(ui).to_ty())
     }
 
     fn fold_free_placeholder_lifetime(
@@ -320,12 +303,8 @@ impl<'q> PlaceholderFolder for UMapFromCanonical<'q> {
         universe0: PlaceholderIndex,
         _binders: usize,
     ) -> Fallible<Lifetime> {
-        let universe = self.universes.map_universe_from_canonical(universe0.ui);
-        Ok(PlaceholderIndex {
-            ui: universe,
-            idx: universe0.idx,
-        }
-        .to_lifetime())
+        let ui = self.universes.map_universe_from_canonical(universe0.universe());
+        Ok(universe0.in_universe(ui).to_lifetime())
     }
 }
 
@@ -4,6 +4,7 @@ use chalk_ir::fold::{
     DefaultFreeVarFolder, DefaultTypeFolder, Fold, InferenceFolder, PlaceholderFolder,
 };
 use chalk_ir::zip::{Zip, Zipper};
+use std::fmt::Debug;
 use std::sync::Arc;
 
 use super::var::*;
@@ -37,6 +38,104 @@ impl InferenceTable {
             }
         }
     }
+
+    /// Relates `a` and `b` according to `variance`. Types have no
+    /// subtyping, so they are always unified; lifetimes related
+    /// covariantly produce an outlives constraint instead (see
+    /// `outlives`).
+    pub fn relate(
+        &mut self,
+        environment: &Arc<Environment>,
+        variance: Variance,
+        a: &Parameter,
+        b: &Parameter,
+    ) -> Fallible<UnificationResult> {
+        match (variance, a, b) {
+            (Variance::Covariant, ParameterKind::Lifetime(a), ParameterKind::Lifetime(b)) => {
+                Ok(self.outlives(environment, a, b))
+            }
+            _ => self.unify(environment, a, b),
+        }
+    }
+
+    /// Requires `a` to outlive `b`. This never fails: unless `a` and
+    /// `b` are the same lifetime, it produces the region constraint
+    /// `a: b`, to be checked with the rest of the solution. Unlike
+    /// equality, this works whatever universes `a` and `b` are in, so
+    /// that a `forall<'a>` goal can relate `'a` to other lifetimes.
+    pub fn outlives(
+        &mut self,
+        environment: &Arc<Environment>,
+        a: &Lifetime,
+        b: &Lifetime,
+    ) -> UnificationResult {
+        let a = self.normalize_lifetime(a).unwrap_or(*a);
+        let b = self.normalize_lifetime(b).unwrap_or(*b);
+        debug!("outlives(a={:?}, b={:?})", a, b);
+
+        let same = match (a, b) {
+            (Lifetime::InferenceVar(var_a), Lifetime::InferenceVar(var_b)) => self
+                .unify
+                .unioned(EnaVariable::from(var_a), EnaVariable::from(var_b)),
+            _ => a == b,
+        };
+
+        let mut constraints = vec![];
+        if !same {
+            constraints.push(InEnvironment::new(environment, Constraint::Outlives(a, b)));
+        }
+        UnificationResult {
+            goals: vec![],
+            constraints,
+        }
+    }
+}
+
+/// How `InferenceTable::relate` relates two parameters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Variance {
+    /// The parameters must be equal.
+    Invariant,
+
+    /// The first parameter must be a subtype of the second: for
+    /// lifetimes, it must outlive the second.
+    Covariant,
+}
+
+/// Lets an embedder decide how two applications of the same type
+/// constructor are unified, e.g. to relate their parameters according
+/// to some variance, or to treat the constructor as non-injective. The
+/// hook is installed with `InferenceTable::set_unification_hook`.
+///
+/// Note that program clauses are still pre-filtered by `could_match`,
+/// which assumes that types whose parameters cannot match are distinct;
+/// so a hook that equates such types only affects goals that reach
+/// unification, such as equality goals.
+pub trait UnificationHook: Debug + Send + Sync {
+    /// Decides how to unify `a` and `b`, which have the same name. The
+    /// parameters of either may contain inference variables.
+    fn unify_application_tys(
+        &self,
+        a: &ApplicationTy,
+        b: &ApplicationTy,
+    ) -> ApplicationUnification;
+}
+
+/// How a `UnificationHook` wants two applications unified.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ApplicationUnification {
+    /// Unify the parameters pairwise, as if there were no hook.
+    Structural,
+
+    /// The types are equal, whatever their parameters.
+    Equal,
+
+    /// The types are never equal.
+    Distinct,
+
+    /// The types are equal if the given goals hold (e.g. custom goals
+    /// relating the parameters), which the solver will then prove.
+    Goals(Vec<DomainGoal>),
 }
 
 struct Unifier<'t> {
@@ -46,10 +145,15 @@ struct Unifier<'t> {
     constraints: Vec<InEnvironment<Constraint>>,
 }
 
+/// What it takes for a unification to hold, beyond the variables it
+/// bound.
 #[derive(Debug)]
 pub struct UnificationResult {
-    crate goals: Vec<InEnvironment<DomainGoal>>,
-    crate constraints: Vec<InEnvironment<Constraint>>,
+    /// Goals that must be proven, e.g. normalizing projections.
+    pub goals: Vec<InEnvironment<DomainGoal>>,
+
+    /// Lifetime constraints that must hold.
+    pub constraints: Vec<InEnvironment<Constraint>>,
 }
 
 impl<'t> Unifier<'t> {
@@ -91,6 +195,13 @@ impl<'t> Unifier<'t> {
 
     fn unify_ty_ty<'a>(&mut self, a: &'a Ty, b: &'a Ty) -> Fallible<()> {
         //         ^^                 ^^         ^^ FIXME rustc bug
+        // Types are interned, so the same type is told without walking it;
+        // it unifies with itself without binding anything -- unless a hook
+        // decides how applications unify, which may not be structurally.
+        if a == b && self.table.unification_hook.is_none() {
+            return Ok(());
+        }
+
         if let Some(n_a) = self.table.normalize_shallow(a) {
             return self.unify_ty_ty(&n_a, b);
         } else if let Some(n_b) = self.table.normalize_shallow(b) {
@@ -104,8 +215,8 @@ impl<'t> Unifier<'t> {
             b
         );
 
-        match (a, b) {
-            (&Ty::InferenceVar(var1), &Ty::InferenceVar(var2)) => {
+        match (a, a.data(), b, b.data()) {
+            (_, &TyData::InferenceVar(var1), _, &TyData::InferenceVar(var2)) => {
                 debug!("unify_ty_ty: unify_var_var({:?}, {:?})", var1, var2);
                 let var1 = EnaVariable::from(var1);
                 let var2 = EnaVariable::from(var2);
@@ -116,59 +227,113 @@ impl<'t> Unifier<'t> {
                     .expect("unification of two unbound variables cannot fail"))
             }
 
-            (&Ty::InferenceVar(var), ty @ &Ty::Apply(_))
-            | (ty @ &Ty::Apply(_), &Ty::InferenceVar(var))
-            | (&Ty::InferenceVar(var), ty @ &Ty::ForAll(_))
-            | (ty @ &Ty::ForAll(_), &Ty::InferenceVar(var)) => self.unify_var_ty(var, ty),
+            (_, &TyData::InferenceVar(var), ty, &TyData::Apply(_))
+            | (ty, &TyData::Apply(_), _, &TyData::InferenceVar(var))
+            | (_, &TyData::InferenceVar(var), ty, &TyData::ForAll(_))
+            | (ty, &TyData::ForAll(_), _, &TyData::InferenceVar(var))
+            | (_, &TyData::InferenceVar(var), ty, &TyData::Dyn(_))
+            | (ty, &TyData::Dyn(_), _, &TyData::InferenceVar(var)) => self.unify_var_ty(var, ty),
 
-            (&Ty::ForAll(ref quantified_ty1), &Ty::ForAll(ref quantified_ty2)) => {
+            (_, &TyData::ForAll(ref quantified_ty1), _, &TyData::ForAll(ref quantified_ty2)) => {
                 self.unify_forall_tys(quantified_ty1, quantified_ty2)
             }
 
-            (&Ty::ForAll(ref quantified_ty), apply_ty @ &Ty::Apply(_))
-            | (apply_ty @ &Ty::Apply(_), &Ty::ForAll(ref quantified_ty)) => {
+            (_, &TyData::ForAll(ref quantified_ty), apply_ty, &TyData::Apply(_))
+            | (apply_ty, &TyData::Apply(_), _, &TyData::ForAll(ref quantified_ty))
+            | (_, &TyData::ForAll(ref quantified_ty), apply_ty, &TyData::Dyn(_))
+            | (apply_ty, &TyData::Dyn(_), _, &TyData::ForAll(ref quantified_ty)) => {
                 self.unify_forall_apply(quantified_ty, apply_ty)
             }
 
-            (&Ty::Apply(ref apply1), &Ty::Apply(ref apply2)) => {
+            // Object types are equal if their bounds are, in the same order.
+            (_, &TyData::Dyn(ref bounds1), _, &TyData::Dyn(ref bounds2)) => {
+                Zip::zip_with(self, bounds1, bounds2)
+            }
+
+            (_, &TyData::Dyn(_), _, &TyData::Apply(_))
+            | (_, &TyData::Apply(_), _, &TyData::Dyn(_)) => Err(NoSolution),
+
+            (_, &TyData::Apply(ref apply1), _, &TyData::Apply(ref apply2)) => {
                 if apply1.name != apply2.name {
                     return Err(NoSolution);
                 }
 
-                Zip::zip_with(self, &apply1.parameters, &apply2.parameters)
+                self.unify_apply_tys(apply1, apply2)
+            }
+
+            (proj1, &TyData::Projection(_), proj2, &TyData::UnselectedProjection(_))
+            | (proj1, &TyData::UnselectedProjection(_), proj2, &TyData::Projection(_))
+            | (
+                proj1,
+                &TyData::UnselectedProjection(_),
+                proj2,
+                &TyData::UnselectedProjection(_),
+            ) => {
+                self.unify_projection_tys(
+                    proj1.as_projection_ty_enum(),
+                    proj2.as_projection_ty_enum(),
+                )
             }
 
-            (proj1 @ &Ty::Projection(_), proj2 @ &Ty::UnselectedProjection(_))
-            | (proj1 @ &Ty::UnselectedProjection(_), proj2 @ &Ty::Projection(_))
-            | (proj1 @ &Ty::UnselectedProjection(_), proj2 @ &Ty::UnselectedProjection(_)) => self
-                .unify_projection_tys(proj1.as_projection_ty_enum(), proj2.as_projection_ty_enum()),
-
-            (ty @ &Ty::Apply(_), &Ty::Projection(ref proj))
-            | (ty @ &Ty::ForAll(_), &Ty::Projection(ref proj))
-            | (ty @ &Ty::InferenceVar(_), &Ty::Projection(ref proj))
-            | (&Ty::Projection(ref proj), ty @ &Ty::Projection(_))
-            | (&Ty::Projection(ref proj), ty @ &Ty::Apply(_))
-            | (&Ty::Projection(ref proj), ty @ &Ty::ForAll(_))
-            | (&Ty::Projection(ref proj), ty @ &Ty::InferenceVar(_)) => {
+            (ty, &TyData::Apply(_), _, &TyData::Projection(ref proj))
+            | (ty, &TyData::ForAll(_), _, &TyData::Projection(ref proj))
+            | (ty, &TyData::Dyn(_), _, &TyData::Projection(ref proj))
+            | (ty, &TyData::InferenceVar(_), _, &TyData::Projection(ref proj))
+            | (_, &TyData::Projection(ref proj), ty, &TyData::Projection(_))
+            | (_, &TyData::Projection(ref proj), ty, &TyData::Apply(_))
+            | (_, &TyData::Projection(ref proj), ty, &TyData::ForAll(_))
+            | (_, &TyData::Projection(ref proj), ty, &TyData::Dyn(_))
+            | (_, &TyData::Projection(ref proj), ty, &TyData::InferenceVar(_)) => {
                 self.unify_projection_ty(proj, ty)
             }
 
-            (ty @ &Ty::Apply(_), &Ty::UnselectedProjection(ref proj))
-            | (ty @ &Ty::ForAll(_), &Ty::UnselectedProjection(ref proj))
-            | (ty @ &Ty::InferenceVar(_), &Ty::UnselectedProjection(ref proj))
-            | (&Ty::UnselectedProjection(ref proj), ty @ &Ty::Apply(_))
-            | (&Ty::UnselectedProjection(ref proj), ty @ &Ty::ForAll(_))
-            | (&Ty::UnselectedProjection(ref proj), ty @ &Ty::InferenceVar(_)) => {
+            (ty, &TyData::Apply(_), _, &TyData::UnselectedProjection(ref proj))
+            | (ty, &TyData::ForAll(_), _, &TyData::UnselectedProjection(ref proj))
+            | (ty, &TyData::Dyn(_), _, &TyData::UnselectedProjection(ref proj))
+            | (ty, &TyData::InferenceVar(_), _, &TyData::UnselectedProjection(ref proj))
+            | (_, &TyData::UnselectedProjection(ref proj), ty, &TyData::Apply(_))
+            | (_, &TyData::UnselectedProjection(ref proj), ty, &TyData::ForAll(_))
+            | (_, &TyData::UnselectedProjection(ref proj), ty, &TyData::Dyn(_))
+            | (_, &TyData::UnselectedProjection(ref proj), ty, &TyData::InferenceVar(_)) => {
                 self.unify_unselected_projection_ty(proj, ty)
             }
 
-            (Ty::BoundVar(_), _) | (_, Ty::BoundVar(_)) => panic!(
+            (_, &TyData::BoundVar(_), _, _) | (_, _, _, &TyData::BoundVar(_)) => panic!(
                 "unification encountered bound variable: a={:?} b={:?}",
                 a, b
             ),
         }
     }
 
+    fn unify_apply_tys(
+        &mut self,
+        apply1: &ApplicationTy,
+        apply2: &ApplicationTy,
+    ) -> Fallible<()> {
+        let unification = match &self.table.unification_hook {
+            Some(hook) => hook.unify_application_tys(apply1, apply2),
+            None => ApplicationUnification::Structural,
+        };
+        debug!("unify_apply_tys: unification={:?}", unification);
+
+        match unification {
+            ApplicationUnification::Structural => {
+                Zip::zip_with(self, &apply1.parameters, &apply2.parameters)
+            }
+            ApplicationUnification::Equal => Ok(()),
+            ApplicationUnification::Distinct => Err(NoSolution),
+            ApplicationUnification::Goals(goals) => {
+                let environment = self.environment;
+                self.goals.extend(
+                    goals
+                        .into_iter()
+                        .map(|goal| InEnvironment::new(environment, goal)),
+                );
+                Ok(())
+            }
+        }
+    }
+
     fn unify_forall_tys(&mut self, ty1: &QuantifiedTy, ty2: &QuantifiedTy) -> Fallible<()> {
         // for<'a...> T == for<'b...> U
         //
@@ -179,21 +344,13 @@ impl<'t> Unifier<'t> {
         //
         // Here we only check for<'a...> exists<'b...> T == U,
         // can someone smart comment why this is sufficient?
+        //
+        // The same goes for binders of other kinds than lifetimes.
 
         debug!("unify_forall_tys({:?}, {:?})", ty1, ty2);
 
-        let ui = self.table.new_universe();
-        let lifetimes1: Vec<_> = (0..ty1.num_binders)
-            .map(|idx| Lifetime::Placeholder(PlaceholderIndex { ui, idx }).cast())
-            .collect();
-
-        let max_universe = self.table.max_universe;
-        let lifetimes2: Vec<_> = (0..ty2.num_binders)
-            .map(|_| self.table.new_variable(max_universe).to_lifetime().cast())
-            .collect();
-
-        let ty1 = ty1.substitute(&lifetimes1);
-        let ty2 = ty2.substitute(&lifetimes2);
+        let ty1 = self.table.instantiate_binders_universally(ty1);
+        let ty2 = self.table.instantiate_binders_existentially(ty2);
         debug!("unify_forall_tys: ty1 = {:?}", ty1);
         debug!("unify_forall_tys: ty2 = {:?}", ty2);
 
@@ -246,12 +403,7 @@ impl<'t> Unifier<'t> {
     }
 
     fn unify_forall_apply(&mut self, ty1: &QuantifiedTy, ty2: &Ty) -> Fallible<()> {
-        let ui = self.table.new_universe();
-        let lifetimes1: Vec<_> = (0..ty1.num_binders)
-            .map(|idx| Lifetime::Placeholder(PlaceholderIndex { ui, idx }).cast())
-            .collect();
-
-        let ty1 = ty1.substitute(&lifetimes1);
+        let ty1 = self.table.instantiate_binders_universally(ty1);
         let ty2 = ty2.clone();
 
         self.sub_unify(ty1, ty2)
@@ -305,10 +457,10 @@ impl<'t> Unifier<'t> {
             | (&Lifetime::Placeholder(idx), &Lifetime::InferenceVar(var)) => {
                 let var = EnaVariable::from(var);
                 let var_ui = self.table.universe_of_unbound_var(var);
-                if var_ui.can_see(idx.ui) {
+                if var_ui.can_name(idx) {
                     debug!(
                         "unify_lifetime_lifetime: {:?} in {:?} can see {:?}; unifying",
-                        var, var_ui, idx.ui
+                        var, var_ui, idx.universe()
                     );
                     let v = Lifetime::Placeholder(idx);
                     self.table
@@ -319,7 +471,7 @@ impl<'t> Unifier<'t> {
                 } else {
                     debug!(
                         "unify_lifetime_lifetime: {:?} in {:?} cannot see {:?}; pushing constraint",
-                        var, var_ui, idx.ui
+                        var, var_ui, idx.universe()
                     );
                     Ok(self.push_lifetime_eq_constraint(*a, *b))
                 }
@@ -333,6 +485,26 @@ impl<'t> Unifier<'t> {
                 }
             }
 
+            // Erased lifetimes are in no universe, so every variable
+            // can name them.
+            (&Lifetime::InferenceVar(var), &Lifetime::Erased)
+            | (&Lifetime::Erased, &Lifetime::InferenceVar(var)) => {
+                let var = EnaVariable::from(var);
+                debug!("unify_lifetime_lifetime: {:?} set to erased", var);
+                self.table
+                    .unify
+                    .unify_var_value(var, InferenceValue::from(Lifetime::Erased))
+                    .unwrap();
+                Ok(())
+            }
+
+            (&Lifetime::Erased, &Lifetime::Erased) => Ok(()),
+
+            (&Lifetime::Placeholder(_), &Lifetime::Erased)
+            | (&Lifetime::Erased, &Lifetime::Placeholder(_)) => {
+                Ok(self.push_lifetime_eq_constraint(*a, *b))
+            }
+
             (Lifetime::BoundVar(_), _) | (_, Lifetime::BoundVar(_)) => panic!(
                 "unification encountered bound variable: a={:?} b={:?}",
                 a, b
@@ -357,11 +529,21 @@ impl<'t> Zipper for Unifier<'t> {
         self.unify_lifetime_lifetime(a, b)
     }
 
-    fn zip_binders<T>(&mut self, _: &Binders<T>, _: &Binders<T>) -> Fallible<()>
+    /// Values under binders (such as the bounds of object types) are
+    /// equal if they are equal with the same placeholders in place of
+    /// their bound variables.
+    fn zip_binders<T>(&mut self, a: &Binders<T>, b: &Binders<T>) -> Fallible<()>
     where
         T: Zip + Fold<Result = T>,
     {
-        panic!("cannot unify things with binders (other than types)")
+        if a.binders != b.binders {
+            return Err(NoSolution);
+        }
+
+        let (a, b) = self
+            .table
+            .instantiate_binders_universally(&(&a.binders, &(&a.value, &b.value)));
+        Zip::zip_with(self, &a, &b)
     }
 }
 
@@ -389,7 +571,7 @@ impl<'u, 't> PlaceholderFolder for OccursCheck<'u, 't> {
         universe: PlaceholderIndex,
         _binders: usize,
     ) -> Fallible<Ty> {
-        if self.universe_index < universe.ui {
+        if !self.universe_index.can_name(universe) {
             Err(NoSolution)
         } else {
             Ok(universe.to_ty()) // no need to shift, not relative to depth
@@ -401,7 +583,7 @@ impl<'u, 't> PlaceholderFolder for OccursCheck<'u, 't> {
         ui: PlaceholderIndex,
         _binders: usize,
     ) -> Fallible<Lifetime> {
-        if self.universe_index < ui.ui {
+        if !self.universe_index.can_name(ui) {
             // Scenario is like:
             //
             // exists<T> forall<'b> ?T = Foo<'b>
@@ -447,7 +629,7 @@ impl<'u, 't> InferenceFolder for OccursCheck<'u, 't> {
                     return Err(NoSolution);
                 }
 
-                if self.universe_index < ui {
+                if !self.universe_index.can_see(ui) {
                     // Scenario is like:
                     //
                     // ?A = foo(?B)
@@ -472,7 +654,7 @@ impl<'u, 't> InferenceFolder for OccursCheck<'u, 't> {
         let var = EnaVariable::from(var);
         match self.unifier.table.unify.probe_value(var) {
             InferenceValue::Unbound(ui) => {
-                if self.universe_index < ui {
+                if !self.universe_index.can_see(ui) {
                     // Scenario is like:
                     //
                     // exists<T> forall<'b> exists<'a> ?T = Foo<'a>
@@ -5,7 +5,10 @@ extern crate chalk_macros;
 extern crate chalk_engine;
 extern crate chalk_ir;
 extern crate ena;
+extern crate num_cpus;
+extern crate serde;
 
 pub mod ext;
 pub mod infer;
 pub mod solve;
+pub mod stratify;
@@ -1,16 +1,36 @@
+use crate::infer::unify::UnificationHook;
+use crate::solve::cache::{CachedSolver, SolutionCache};
+use crate::solve::differential::DifferentialSolver;
+use crate::solve::explain::{AmbiguityExplanation, FailureExplanation};
+use crate::solve::proof::ProofTree;
 use crate::solve::slg::implementation::SlgContext;
-use chalk_engine::context::Context;
+use chalk_engine::cancel::{CancellationToken, Canceled};
 use chalk_engine::context::ContextOps;
 use chalk_engine::fallible::*;
-use chalk_engine::forest::Forest;
+use chalk_engine::forest::{Forest, Step};
+use chalk_engine::listener::EventListener;
+use chalk_engine::snapshot::ForestSnapshot;
+pub use chalk_engine::OverflowKind;
+use chalk_ir::fingerprint::Fingerprint;
 use chalk_ir::*;
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::BTreeSet;
 use std::fmt;
-use std::sync::Arc;
+use std::hash::Hash;
+use std::sync::{mpsc, Arc};
 
+pub mod cache;
+pub mod differential;
+pub mod elaborate;
+pub mod explain;
+pub mod proof;
+pub mod simplify;
 pub mod slg;
 mod truncate;
+pub mod verify;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 /// A (possible) solution for a proposed goal. Usually packaged in a `Result`,
 /// where `Err` represents definite *failure* to prove a goal.
 pub enum Solution {
@@ -24,9 +44,23 @@ pub enum Solution {
     /// constraints, since we have not "committed" to any particular solution
     /// yet.
     Ambig(Guidance),
+
+    /// There is a unique answer, but it only holds if some negative
+    /// subgoals fail, and the solver could not decide whether they do
+    /// (typically because of a negative cycle such as `P :- not { Q }`
+    /// and `Q :- not { P }`; see `stratify`). Like `Unique`, this
+    /// records lifetime constraints; treating it as `Ambig` is always
+    /// sound.
+    Conditional(Canonical<ConstrainedSubst>),
+
+    /// The solver gave up after examining `max_answers` answers (see
+    /// `SolverChoiceBuilder::max_answers`) while more answers remained. The guidance
+    /// only reflects the answers examined so far, so it is never
+    /// `Definite`.
+    MoreAnswers(Guidance),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 /// When a goal holds ambiguously (e.g., because there are multiple possible
 /// solutions), we issue a set of *guidance* back to type inference.
 pub enum Guidance {
@@ -42,6 +76,30 @@ pub enum Guidance {
 
     /// There's no useful information to feed back to type inference
     Unknown,
+
+    /// The goal has only a few answers (at most `max_candidates`, see
+    /// `SolverChoiceBuilder::max_candidates`), and if it holds at all,
+    /// it holds for one of the `candidates`. As with `Definite`, the
+    /// existential variables *must* have the values given by
+    /// `generalization`, the best common generalization of the
+    /// candidates (which may be trivial).
+    Candidates {
+        generalization: Canonical<Substitution>,
+        candidates: Vec<Canonical<Substitution>>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+/// One of possibly many answers to a goal, as produced by
+/// `Solver::solve_multiple` or `Solver::solve_any`. Each answer gives values
+/// for the existential variables of the goal, along with any lifetime
+/// constraints that must hold.
+pub struct CanonicalSolution {
+    pub subst: Canonical<ConstrainedSubst>,
+
+    /// If true, the answer could neither be proven nor disproven (e.g.
+    /// because it depends on a negative cycle or exceeded `max_size`).
+    pub ambiguous: bool,
 }
 
 impl Solution {
@@ -51,12 +109,72 @@ impl Solution {
             _ => false,
         }
     }
+
+    /// True for `Ambig` and `MoreAnswers`: the goal may hold, but the
+    /// solver could not find a unique answer.
+    pub fn is_ambiguous(&self) -> bool {
+        match *self {
+            Solution::Ambig(..) | Solution::MoreAnswers(..) => true,
+            Solution::Unique(..) | Solution::Conditional(..) => false,
+        }
+    }
+
+    /// The answer of a `Unique` or `Conditional` solution, with its
+    /// lifetime constraints.
+    pub fn constrained_subst(&self) -> Option<&Canonical<ConstrainedSubst>> {
+        match self {
+            Solution::Unique(constrained) | Solution::Conditional(constrained) => {
+                Some(constrained)
+            }
+            Solution::Ambig(..) | Solution::MoreAnswers(..) => None,
+        }
+    }
+
+    /// The lifetime constraints that must hold for the goal to hold.
+    /// Ambiguous solutions never have any.
+    pub fn constraints(&self) -> &[InEnvironment<Constraint>] {
+        match self.constrained_subst() {
+            Some(constrained) => &constrained.value.constraints,
+            None => &[],
+        }
+    }
+
+    /// The values that the existential variables *must* have if the
+    /// goal is ever to hold: the answer of a `Unique` or `Conditional`
+    /// solution, or the definite guidance of an `Ambig` one. Variables
+    /// that may have any value are mapped to themselves.
+    pub fn definite_subst(&self) -> Option<Canonical<Substitution>> {
+        match self {
+            Solution::Unique(constrained) | Solution::Conditional(constrained) => Some(Canonical {
+                value: constrained.value.subst.clone(),
+                binders: constrained.binders.clone(),
+            }),
+            Solution::Ambig(Guidance::Definite(subst))
+            | Solution::Ambig(Guidance::Candidates {
+                generalization: subst,
+                ..
+            }) => Some(subst.clone()),
+            Solution::Ambig(..) | Solution::MoreAnswers(..) => None,
+        }
+    }
+
+    /// The guidance for type inference that this solution gives: a
+    /// `Unique` or `Conditional` answer is definite guidance.
+    pub fn guidance(&self) -> Guidance {
+        match self {
+            Solution::Unique(..) | Solution::Conditional(..) => {
+                Guidance::Definite(self.definite_subst().unwrap())
+            }
+            Solution::Ambig(guidance) | Solution::MoreAnswers(guidance) => guidance.clone(),
+        }
+    }
 }
 
 impl fmt::Display for Solution {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
             Solution::Unique(constrained) => write!(f, "Unique; {}", constrained,),
+            Solution::Conditional(constrained) => write!(f, "Conditional; {}", constrained),
             Solution::Ambig(Guidance::Definite(subst)) => {
                 write!(f, "Ambiguous; definite substitution {}", subst)
             }
@@ -64,14 +182,384 @@ impl fmt::Display for Solution {
                 write!(f, "Ambiguous; suggested substitution {}", subst)
             }
             Solution::Ambig(Guidance::Unknown) => write!(f, "Ambiguous; no inference guidance"),
+            Solution::Ambig(Guidance::Candidates {
+                generalization,
+                candidates,
+            }) => {
+                write!(f, "Ambiguous; definite substitution {}; ", generalization)?;
+                write!(f, "candidate substitutions ")?;
+                for (i, candidate) in candidates.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", candidate)?;
+                }
+                Ok(())
+            }
+            Solution::MoreAnswers(Guidance::Definite(subst))
+            | Solution::MoreAnswers(Guidance::Suggested(subst))
+            | Solution::MoreAnswers(Guidance::Candidates {
+                generalization: subst,
+                ..
+            }) => write!(
+                f,
+                "Ambiguous; too many answers; suggested substitution {}",
+                subst
+            ),
+            Solution::MoreAnswers(Guidance::Unknown) => {
+                write!(f, "Ambiguous; too many answers; no inference guidance")
+            }
         }
     }
 }
 
+/// The solution to a goal, along with the overflows the solver ran
+/// into while solving it (see `Solver::solve_partial`). If there were
+/// any, the solution is the best the solver could do within its limits
+/// (typically an ambiguous solution with some guidance), rather than
+/// the actual solution.
+///
+/// The negative subgoals that floundered are listed as well: they too
+/// make a solution ambiguous, and tell which `not { .. }` is at fault.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialSolution {
+    pub solution: Option<Solution>,
+
+    /// The overflows, each seen from the goal being solved (see
+    /// `Overflow::starting_from`).
+    pub overflows: Vec<Overflow>,
+
+    /// The flounders, each seen from the goal being solved (see
+    /// `Flounder::starting_from`).
+    pub flounders: Vec<Flounder>,
+}
+
+impl PartialSolution {
+    /// True if the solver did not run into any limit, so `solution`
+    /// is the actual solution.
+    pub fn is_complete(&self) -> bool {
+        self.overflows.is_empty()
+    }
+
+    /// True if some term grew larger than `max_size` and had to be
+    /// truncated: an ambiguous solution may then be an artifact of the
+    /// truncation rather than a genuine ambiguity.
+    pub fn truncated(&self) -> bool {
+        self.overflows
+            .iter()
+            .any(|overflow| overflow.kind == OverflowKind::Size)
+    }
+
+    /// The depth of the shallowest overflow, if any.
+    pub fn overflow_depth(&self) -> Option<usize> {
+        self.overflows.iter().map(|overflow| overflow.depth()).min()
+    }
+}
+
+impl fmt::Display for PartialSolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match &self.solution {
+            Some(solution) => write!(f, "{}", solution)?,
+            None => write!(f, "No possible solution")?,
+        }
+        // The shallowest overflow of each kind.
+        for &(kind, what) in &[
+            (OverflowKind::Depth, "overflowed"),
+            (OverflowKind::Size, "terms truncated"),
+        ] {
+            let depth = self
+                .overflows
+                .iter()
+                .filter(|overflow| overflow.kind == kind)
+                .map(|overflow| overflow.depth())
+                .min();
+            if let Some(depth) = depth {
+                write!(f, "; {} at depth {}", what, depth)?;
+            }
+        }
+        for flounder in &self.flounders {
+            write!(f, "; floundered on `not {{ {:?} }}`", flounder.subgoal.canonical)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CanonicalSolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        if self.ambiguous {
+            write!(f, "Ambiguous; {}", self.subst)
+        } else {
+            write!(f, "{}", self.subst)
+        }
+    }
+}
+
+/// Configures the solver. Start from `SolverChoice::slg()`, adjust
+/// the options you care about, and call `build`:
+///
+/// ```
+/// # use chalk_solve::solve::SolverChoice;
+/// let solver_choice = SolverChoice::slg().max_size(30).overflow_depth(100).build();
+/// ```
+///
+/// `SolverChoice::default()` gives the default for every option.
 #[derive(Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
-pub enum SolverChoice {
-    /// Run the SLG solver, producing a Solution.
-    SLG { max_size: usize },
+pub struct SolverChoice {
+    max_size: usize,
+    overflow_depth: Option<usize>,
+    erase_lifetimes: bool,
+    max_answers: Option<usize>,
+    max_candidates: Option<usize>,
+    coinduction: Coinduction,
+    deterministic: bool,
+    search_strategy: SearchStrategy,
+    simplify_goals: bool,
+    prefer_where_clauses: bool,
+    clause_order: ClauseOrder,
+    record_proofs: bool,
+    stack_red_zone: usize,
+    stack_segment_size: usize,
+    num_threads: Option<usize>,
+}
+
+/// The order in which the solver explores the ways to prove a goal.
+#[derive(Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum SearchStrategy {
+    /// Follow each way of proving the goal as far as it goes before
+    /// trying the next one. This is usually fastest.
+    DepthFirst,
+
+    /// Take turns between the ways of proving the goal, one step at a
+    /// time, so that a recursive clause that keeps producing subgoals
+    /// cannot starve the others. Answers may be found in a different
+    /// order than with `DepthFirst`.
+    BreadthFirst,
+}
+
+/// The order in which the solver tries the clauses that could prove a
+/// goal.
+#[derive(Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum ClauseOrder {
+    /// Where clauses in scope first, then program clauses in the order
+    /// the program declares them (or in a fixed order, if the solver
+    /// is deterministic).
+    AsWritten,
+
+    /// Like `AsWritten`, but clauses with fewer conditions come first,
+    /// and among those, clauses with more specific heads (that is,
+    /// fewer bound variables). Facts are then tried before recursive
+    /// impls, so a program that happens to declare its most expensive
+    /// impls first does not pay for exploring them before the cheap
+    /// answers are found.
+    FewestConditions,
+
+    /// In a pseudo-random order, which only depends on `seed` and on
+    /// the goal. Solving goals with a few different seeds catches
+    /// results that depend on the order in which a program happens to
+    /// declare its impls (or on the iteration order of some map); the
+    /// tests do so when `CHALK_SHUFFLE_SEED` is set.
+    ///
+    /// The checks that go through the impls of a program (coherence
+    /// and well-formedness), and the lowering of a program to clauses,
+    /// shuffle the impls and clauses they iterate over as well (see
+    /// `ClauseOrder::shuffle`).
+    Shuffled { seed: u64 },
+}
+
+impl ClauseOrder {
+    /// Shuffles `values` if the order is `Shuffled`, and leaves them
+    /// alone otherwise. The order only depends on the seed and on
+    /// `salt`, which tells apart the lists being shuffled (e.g. the
+    /// goal whose clauses they are).
+    pub fn shuffle<T>(self, values: &mut [T], salt: &impl Hash) {
+        let seed = match self {
+            ClauseOrder::Shuffled { seed } => seed,
+            ClauseOrder::AsWritten | ClauseOrder::FewestConditions => return,
+        };
+
+        // Fisher-Yates, drawing pseudo-random numbers from splitmix64.
+        let mut state = Fingerprint::of(&(seed, salt)).as_u128() as u64;
+        for i in (1..values.len()).rev() {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^= z >> 31;
+            values.swap(i, (z % (i as u64 + 1)) as usize);
+        }
+    }
+
+    /// Shuffles the clauses of `env` if the order is `Shuffled`, so
+    /// that nothing depends on the order in which lowering generated
+    /// them either.
+    pub fn shuffle_program_clauses(self, env: &mut ProgramEnvironment) {
+        if let ClauseOrder::Shuffled { .. } = self {
+            let mut clauses: Vec<_> = env.program_clauses.iter().cloned().collect();
+            self.shuffle(&mut clauses, &"program clauses");
+            env.program_clauses = clauses.into_iter().collect();
+        }
+    }
+}
+
+/// Which traits have coinductive semantics, so that a cycle in the
+/// proof of `T: Trait` counts as a success rather than a failure.
+#[derive(Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum Coinduction {
+    /// No trait is coinductive.
+    None,
+
+    /// The traits the program declares coinductive: auto traits, and
+    /// traits marked `#[coinductive]`.
+    Declared,
+
+    /// Every trait is coinductive. This is meant for experiments with
+    /// coinductive semantics for traits like `Sized`, without having
+    /// to edit the program.
+    AllTraits,
+}
+
+/// Builds a `SolverChoice`; see `SolverChoice::slg`.
+#[derive(Copy, Clone, Debug)]
+pub struct SolverChoiceBuilder {
+    choice: SolverChoice,
+}
+
+impl SolverChoiceBuilder {
+    /// Goals and answers whose terms grow larger than this are
+    /// truncated, which guarantees termination at the cost of
+    /// ambiguity (default: 10).
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.choice.max_size = max_size;
+        self
+    }
+
+    /// The deepest the solver may nest subgoals; a subgoal any deeper
+    /// is treated as an overflow, and cannot be proven or disproven
+    /// (default: unlimited).
+    pub fn overflow_depth(mut self, overflow_depth: usize) -> Self {
+        self.choice.overflow_depth = Some(overflow_depth);
+        self
+    }
+
+    /// If true, all lifetimes are treated as equal: goals and clauses
+    /// have their lifetimes erased before they are solved, so no
+    /// lifetime constraints are produced, lifetime variables come out
+    /// as `'<erased>`, and goals that differ only in their lifetimes
+    /// share their tables. Useful for consumers that do not care about
+    /// regions, as it yields fewer ambiguous answers (default: false).
+    pub fn erase_lifetimes(mut self, erase_lifetimes: bool) -> Self {
+        self.choice.erase_lifetimes = erase_lifetimes;
+        self
+    }
+
+    /// The solver stops after examining this many answers to the goal
+    /// and reports `Solution::MoreAnswers` if there are others left.
+    /// This bounds the work done for goals with huge or infinite
+    /// answer sets (default: unlimited).
+    pub fn max_answers(mut self, max_answers: usize) -> Self {
+        self.choice.max_answers = Some(max_answers);
+        self
+    }
+
+    /// When a goal has at most this many answers, an ambiguous
+    /// solution lists them all (see `Guidance::Candidates`), so that
+    /// callers such as method resolution can try each in turn
+    /// (default: none).
+    pub fn max_candidates(mut self, max_candidates: usize) -> Self {
+        self.choice.max_candidates = Some(max_candidates);
+        self
+    }
+
+    /// Which traits have coinductive semantics (default:
+    /// `Coinduction::Declared`).
+    pub fn coinduction(mut self, coinduction: Coinduction) -> Self {
+        self.choice.coinduction = coinduction;
+        self
+    }
+
+    /// If true, program clauses are tried (and so answers are found)
+    /// in an order that depends only on what the clauses say, and not
+    /// on the order in which items were declared or clauses generated.
+    /// Clauses are ordered by their fingerprints (see
+    /// `chalk_ir::fingerprint`), as are the candidates of an ambiguous
+    /// solution. This is slower, and meant for tests whose expectations
+    /// should survive refactors (default: false).
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.choice.deterministic = deterministic;
+        self
+    }
+
+    /// The order in which ways of proving a goal are explored
+    /// (default: `SearchStrategy::DepthFirst`).
+    pub fn search_strategy(mut self, search_strategy: SearchStrategy) -> Self {
+        self.choice.search_strategy = search_strategy;
+        self
+    }
+
+    /// If true, root goals are simplified before they are solved (see
+    /// `simplify::simplify_goal`). This pays off for large, generated
+    /// goals, but changes the subgoals the solver reports, e.g. in
+    /// overflows (default: false).
+    pub fn simplify_goals(mut self, simplify_goals: bool) -> Self {
+        self.choice.simplify_goals = simplify_goals;
+        self
+    }
+
+    /// If true, a trait goal that one of the where clauses in scope
+    /// could prove is only proven from the where clauses, and never
+    /// from impls (or other program clauses). This mirrors how rustc
+    /// winnows its candidates, and so e.g. infers the type parameters
+    /// of a trait from a where clause even when impls would apply too
+    /// (default: false).
+    pub fn prefer_where_clauses(mut self, prefer_where_clauses: bool) -> Self {
+        self.choice.prefer_where_clauses = prefer_where_clauses;
+        self
+    }
+
+    /// If true, the solver records how it derives each answer, so that
+    /// `Solver::proof_tree` can tell how a goal was proven. This costs
+    /// some time and memory for every step of the search (default:
+    /// false).
+    pub fn record_proofs(mut self, record_proofs: bool) -> Self {
+        self.choice.record_proofs = record_proofs;
+        self
+    }
+
+    /// The order in which the clauses that could prove a goal are tried
+    /// (default: `ClauseOrder::AsWritten`).
+    pub fn clause_order(mut self, clause_order: ClauseOrder) -> Self {
+        self.choice.clause_order = clause_order;
+        self
+    }
+
+    /// When less than this many bytes of stack are left, the solver
+    /// continues on a fresh stack segment rather than risk a stack
+    /// overflow on deeply nested goals. Raise it if the solver still
+    /// overflows the stack, e.g. in a debug build (default: 256K).
+    pub fn stack_red_zone(mut self, stack_red_zone: usize) -> Self {
+        self.choice.stack_red_zone = stack_red_zone;
+        self
+    }
+
+    /// The size in bytes of each fresh stack segment (see
+    /// `stack_red_zone`; default: 2M).
+    pub fn stack_segment_size(mut self, stack_segment_size: usize) -> Self {
+        self.choice.stack_segment_size = stack_segment_size;
+        self
+    }
+
+    /// The most threads that `solve_all`, and the checks of programs
+    /// that solve many independent goals (such as WF and coherence
+    /// checking), spread their goals over (default: the number of CPUs).
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        assert!(num_threads > 0, "num_threads must be at least 1");
+        self.choice.num_threads = Some(num_threads);
+        self
+    }
+
+    pub fn build(self) -> SolverChoice {
+        self.choice
+    }
 }
 
 impl SolverChoice {
@@ -94,37 +582,553 @@ impl SolverChoice {
         Ok(self.create_solver(env).solve(canonical_goal))
     }
 
-    /// Returns the default SLG parameters.
-    fn slg() -> Self {
-        SolverChoice::SLG { max_size: 10 }
+    /// Like `solve_root_goal`, but stops at the first answer (see
+    /// `Solver::solve_any`).
+    pub fn solve_any_root_goal(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Fallible<Option<CanonicalSolution>> {
+        Ok(self.create_solver(env).solve_any(canonical_goal))
     }
 
-    pub fn create_solver(self, env: &Arc<ProgramEnvironment>) -> Box<Solver> {
-        match self {
-            SolverChoice::SLG { max_size } => Box::new(Forest::new(SlgContext::new(env, max_size))),
+    /// Solves each of `goals`, as `solve_root_goal` would, with a
+    /// single solver: the goals share its tables, so the work for the
+    /// subgoals they have in common (e.g. the well-formedness of the
+    /// types of a program, when checking its impls) is done only once.
+    /// The results are in the same order as `goals`, and do not depend
+    /// on it, except with an `overflow_depth`: a goal may then find
+    /// answers below the overflow depth in tables that an earlier goal
+    /// completed.
+    pub fn solve_batch(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        goals: &[UCanonical<InEnvironment<Goal>>],
+    ) -> Vec<Option<Solution>> {
+        let mut solver = self.create_solver(env);
+        goals.iter().map(|goal| solver.solve(goal)).collect()
+    }
+
+    /// Solves each of `goals`, as `solve_root_goal` would, spreading
+    /// the work over `num_threads` threads (see
+    /// `SolverChoiceBuilder::num_threads`). The goals are split into a
+    /// batch for each thread, which solves its batch as `solve_batch`
+    /// would, with a solver (and so tables) of its own: goals whose
+    /// work overlaps may be solved more than once if they end up in
+    /// different batches. This pays off for large batches of mostly
+    /// independent goals, such as WF checks or test suites.
+    /// The results are in the same order as `goals`.
+    pub fn solve_all(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        goals: &[UCanonical<InEnvironment<Goal>>],
+    ) -> Vec<Option<Solution>> {
+        let num_threads = self.num_threads();
+        let batch_size = cmp::max(1, (goals.len() + num_threads - 1) / num_threads);
+        let batches: Vec<Vec<_>> = goals.chunks(batch_size).map(|batch| batch.to_vec()).collect();
+        let env = env.clone();
+        tls::map_in_parallel(batches, num_threads, move |batch| self.solve_batch(&env, batch))
+            .into_iter()
+            .flat_map(|solutions| solutions)
+            .collect()
+    }
+
+    /// Solves `goal` on a thread of its own, sending each answer over
+    /// the returned channel as soon as it is found (as
+    /// `Solver::solve_multiple` would produce them). This lets hosts
+    /// with an event loop of their own consume the answers as they
+    /// come, e.g. with `Receiver::try_recv`.
+    ///
+    /// The solver works at most one answer ahead of the receiver. It
+    /// stops, closing the channel, once there are no more answers,
+    /// `cancel` is canceled, or the receiver is dropped.
+    pub fn stream_answers(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        goal: &UCanonical<InEnvironment<Goal>>,
+        cancel: &CancellationToken,
+    ) -> mpsc::Receiver<CanonicalSolution> {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let env = env.clone();
+        let goal = goal.clone();
+        let cancel = cancel.clone();
+        tls::spawn(move || {
+            let context = SlgContext::new(&env, self);
+            let goal = context.prepare_root_goal(&goal).into_owned();
+            let mut forest = Forest::new(context);
+            let mut stepper = forest.stepper(&goal);
+            loop {
+                match stepper.step() {
+                    Step::Answer(answer) => {
+                        let answer = CanonicalSolution {
+                            subst: answer.subst,
+                            ambiguous: answer.ambiguous,
+                        };
+                        if sender.send(answer).is_err() {
+                            break;
+                        }
+                    }
+                    Step::Pending => {
+                        if cancel.check(stepper.forest().memory_usage()).is_err() {
+                            break;
+                        }
+                    }
+                    Step::Done => break,
+                }
+            }
+        });
+        receiver
+    }
+
+    /// Like `solve_root_goal`, but if the goal has a unique solution,
+    /// also returns a proof tree showing how it holds (see the `proof`
+    /// module), whether or not this solver choice records proofs. The
+    /// proof tree may be missing if the proof requires some step that
+    /// is not itself unique.
+    pub fn solve_root_goal_with_proof(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Fallible<Option<(Solution, Option<ProofTree>)>> {
+        let solver_choice = SolverChoice {
+            record_proofs: true,
+            ..self
+        };
+        let mut solver = solver_choice.create_solver(env);
+        let solution = match solver.solve(canonical_goal) {
+            Some(solution) => solution,
+            None => return Ok(None),
+        };
+
+        let proof_tree = solver.proof_tree(canonical_goal);
+        Ok(Some((solution, proof_tree)))
+    }
+
+    /// If the given goal has no solution, explains why: which part of
+    /// the goal fails, which clauses could have proven it, and what
+    /// went wrong with each (see the `explain` module). Returns `None`
+    /// if the goal does have a solution.
+    pub fn explain_failure(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Option<FailureExplanation> {
+        explain::explain_failure(env, &mut *self.create_solver(env), canonical_goal)
+    }
+
+    /// If the given goal has an ambiguous solution, explains why: e.g.
+    /// which clauses could prove it, or which overflow or negative
+    /// subgoal is to blame (see `explain::AmbiguityReason`). Returns
+    /// `None` if the goal has a unique solution or none at all.
+    pub fn explain_ambiguity(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        canonical_goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Option<AmbiguityExplanation> {
+        explain::explain_ambiguity(env, &mut *self.create_solver(env), canonical_goal)
+    }
+
+    /// Starts configuring an SLG solver, with every option at its
+    /// default.
+    pub fn slg() -> SolverChoiceBuilder {
+        SolverChoiceBuilder {
+            choice: SolverChoice {
+                max_size: 10,
+                overflow_depth: None,
+                erase_lifetimes: false,
+                max_answers: None,
+                max_candidates: None,
+                coinduction: Coinduction::Declared,
+                deterministic: false,
+                search_strategy: SearchStrategy::DepthFirst,
+                simplify_goals: false,
+                prefer_where_clauses: false,
+                clause_order: ClauseOrder::AsWritten,
+                record_proofs: false,
+                stack_red_zone: chalk_engine::DEFAULT_STACK_RED_ZONE,
+                stack_segment_size: chalk_engine::DEFAULT_STACK_SEGMENT_SIZE,
+                num_threads: None,
+            },
         }
     }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn overflow_depth(&self) -> Option<usize> {
+        self.overflow_depth
+    }
+
+    pub fn erase_lifetimes(&self) -> bool {
+        self.erase_lifetimes
+    }
+
+    pub fn max_answers(&self) -> Option<usize> {
+        self.max_answers
+    }
+
+    pub fn max_candidates(&self) -> Option<usize> {
+        self.max_candidates
+    }
+
+    pub fn coinduction(&self) -> Coinduction {
+        self.coinduction
+    }
+
+    pub fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    pub fn search_strategy(&self) -> SearchStrategy {
+        self.search_strategy
+    }
+
+    pub fn simplify_goals(&self) -> bool {
+        self.simplify_goals
+    }
+
+    pub fn prefer_where_clauses(&self) -> bool {
+        self.prefer_where_clauses
+    }
+
+    pub fn clause_order(&self) -> ClauseOrder {
+        self.clause_order
+    }
+
+    pub fn record_proofs(&self) -> bool {
+        self.record_proofs
+    }
+
+    pub fn stack_red_zone(&self) -> usize {
+        self.stack_red_zone
+    }
+
+    pub fn stack_segment_size(&self) -> usize {
+        self.stack_segment_size
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.num_threads.unwrap_or_else(num_cpus::get)
+    }
+
+    pub fn create_solver(self, env: &Arc<ProgramEnvironment>) -> Box<Solver> {
+        self.create_solver_for_clause_set(env.clone())
+    }
+
+    /// Like `create_solver`, but the solver takes its program clauses
+    /// from `program`, which may produce them lazily (see
+    /// `ProgramClauseSet`).
+    pub fn create_solver_for_clause_set(self, program: Arc<dyn ProgramClauseSet>) -> Box<Solver> {
+        Box::new(Forest::new(SlgContext::from_clause_set(program, self)))
+    }
+
+    /// Like `create_solver_for_clause_set`, but the solver unifies
+    /// types as `hook` decides (see `UnificationHook`).
+    pub fn create_solver_with_unification_hook(
+        self,
+        program: Arc<dyn ProgramClauseSet>,
+        hook: Arc<dyn UnificationHook>,
+    ) -> Box<Solver> {
+        let context = SlgContext::from_clause_set(program, self).with_unification_hook(hook);
+        Box::new(Forest::new(context))
+    }
+
+    /// Creates a solver that first consults `cache`, which must have
+    /// been built for this same program and solver choice (see the
+    /// `cache` module). Pass `SolutionCache::new()` to start cold.
+    pub fn create_cached_solver(
+        self,
+        env: &Arc<ProgramEnvironment>,
+        cache: SolutionCache,
+    ) -> CachedSolver {
+        CachedSolver::new(cache, self.create_solver(env))
+    }
+
+    /// Creates a solver that solves each goal with both this solver
+    /// choice and `other`, recording the goals on which they disagree
+    /// (see the `differential` module).
+    pub fn create_differential_solver(
+        self,
+        other: SolverChoice,
+        env: &Arc<ProgramEnvironment>,
+    ) -> DifferentialSolver {
+        DifferentialSolver::new((self, other), env.clone())
+    }
 }
 
 impl Default for SolverChoice {
     fn default() -> Self {
-        SolverChoice::slg()
+        SolverChoice::slg().build()
     }
 }
 
+/// A solver retains the work it has done across calls, so reusing the
+/// same solver for many goals against one program (as a REPL or a
+/// batch run would) avoids redoing identical work.
 pub trait Solver {
     /// Solves a given goal, producing the solution. This will do only
     /// as much work towards `goal` as it has to (and that works is
     /// cached for future attempts).
-    fn solve(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<Solution>;
+    fn solve(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<Solution> {
+        self.solve_with_cancellation(goal, &CancellationToken::new())
+            .expect("a new token is never canceled")
+    }
+
+    /// Like `solve`, but the solver polls `cancel` at every step, and
+    /// returns `Err` once it has been canceled (or its deadline has
+    /// passed, or the solver used more memory than it allows), saying
+    /// why. This lets embedders abort long-running queries.
+    fn solve_with_cancellation(
+        &mut self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Solution>, Canceled>;
+
+    /// Streams the distinct answers to `goal`, rather than collapsing
+    /// them into a single `Solution`. This is mostly useful to find out
+    /// *why* a goal is ambiguous. Answers are produced lazily, so callers
+    /// should take only as many as they need: the stream may be infinite.
+    fn solve_multiple<'a>(
+        &'a mut self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Box<Iterator<Item = CanonicalSolution> + 'a>;
+
+    /// Finds the first answer to `goal`, without checking whether it
+    /// is the only one (see `Forest::solve_any`). If all you need to
+    /// know is whether `goal` holds, this is much faster than `solve`
+    /// when there are many candidate impls.
+    fn solve_any(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<CanonicalSolution>;
+
+    /// Like `solve`, but also reports the overflows that occurred
+    /// beneath `goal`, so that callers can tell a solution that is
+    /// only the best the solver could do within its limits (e.g. an
+    /// ambiguous one, truncated at some depth) from the real thing,
+    /// and the negative subgoals that floundered beneath it.
+    ///
+    /// A cached solution (see `CachedSolver`) reports neither.
+    fn solve_partial(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> PartialSolution;
+
+    /// Solves `goal` and, if it has a unique solution, returns the proof
+    /// of it that the solver recorded while solving (see the `proof`
+    /// module). There is no proof unless the solver was created with
+    /// `SolverChoiceBuilder::record_proofs`, or if some step of the
+    /// proof is not itself unique.
+    fn proof_tree(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<ProofTree>;
+
+    /// Returns the chain of goals leading to each overflow the solver
+    /// has run into so far. An overflow causes the solver to give an
+    /// ambiguous answer, so this is useful to find out which
+    /// recursive clause is at fault.
+    fn overflows(&self) -> Vec<Overflow>;
+
+    /// The state of the tables of the solver (see
+    /// `chalk_engine::snapshot`): which goals it has tables for, how
+    /// many answers each has, and which are complete.
+    fn snapshot(&self) -> ForestSnapshot<SlgContext>;
+
+    /// Discards everything the solver has cached so far.
+    fn clear_cache(&mut self);
+
+    /// Switches the solver over to `program`, an edited version of the
+    /// program it has been solving goals for. Only the cached work
+    /// that could depend on the edited parts of the program is
+    /// discarded, which is much cheaper than starting over.
+    fn update_program(&mut self, program: Arc<dyn ProgramClauseSet>);
+
+    /// Installs `listener` to be told about the progress of the
+    /// solver from now on (see `chalk_engine::listener`).
+    fn set_event_listener(&mut self, listener: Box<dyn EventListener<SlgContext>>);
+}
+
+/// Describes an overflow encountered while solving: each goal in
+/// `goal_stack` was being solved as a subgoal of the one before it,
+/// and the search for the last one either needed a subgoal nested
+/// deeper than `overflow_depth`, or produced a result larger than
+/// `max_size` (see `OverflowKind`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Overflow {
+    pub kind: OverflowKind,
+    pub goal_stack: Vec<UCanonical<InEnvironment<Goal>>>,
+}
+
+impl Overflow {
+    /// The part of the goal stack starting at `goal`, if `goal` is on
+    /// it: the overflow as seen from solving `goal`.
+    pub fn starting_from(&self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<Overflow> {
+        let position = self.goal_stack.iter().position(|g| g == goal)?;
+        Some(Overflow {
+            kind: self.kind,
+            goal_stack: self.goal_stack[position..].to_vec(),
+        })
+    }
+
+    /// How deeply the goal that overflowed is nested beneath the
+    /// first goal of the stack (which is at depth 0).
+    pub fn depth(&self) -> usize {
+        self.goal_stack.len() - 1
+    }
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self.kind {
+            OverflowKind::Depth => write!(f, "overflow evaluating:")?,
+            OverflowKind::Size => write!(f, "term too large evaluating:")?,
+        }
+        for goal in self.goal_stack.iter().rev() {
+            write!(f, "\n    {:?}", goal.canonical)?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes a negative subgoal `not { subgoal }` that floundered
+/// while solving: `subgoal` (in canonical form) still had unresolved
+/// inference variables when it was selected. `goal_stack` is the chain
+/// of goals that led to it, as in `Overflow`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Flounder {
+    pub goal_stack: Vec<UCanonical<InEnvironment<Goal>>>,
+    pub subgoal: UCanonical<InEnvironment<Goal>>,
 }
 
-impl<C, CO> Solver for Forest<C, CO>
-where
-    C: Context<UCanonicalGoalInEnvironment = UCanonical<InEnvironment<Goal>>, Solution = Solution>,
-    CO: ContextOps<C>,
-{
+impl Flounder {
+    /// The flounder as seen from solving `goal`, if `goal` is on its
+    /// goal stack (see `Overflow::starting_from`).
+    pub fn starting_from(&self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<Flounder> {
+        let position = self.goal_stack.iter().position(|g| g == goal)?;
+        Some(Flounder {
+            goal_stack: self.goal_stack[position..].to_vec(),
+            subgoal: self.subgoal.clone(),
+        })
+    }
+}
+
+impl fmt::Display for Flounder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "floundered on `not {{ {:?} }}`", self.subgoal.canonical)?;
+        for goal in self.goal_stack.iter().rev() {
+            write!(f, "\n    while evaluating {:?}", goal.canonical)?;
+        }
+        Ok(())
+    }
+}
+
+impl Solver for Forest<SlgContext, SlgContext> {
     fn solve(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<Solution> {
-        self.solve(goal)
+        let goal = self.context().prepare_root_goal(goal);
+        self.solve(&goal)
+    }
+
+    fn solve_with_cancellation(
+        &mut self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Solution>, Canceled> {
+        let goal = self.context().prepare_root_goal(goal);
+        self.solve_with_cancellation(&goal, cancel)
+    }
+
+    fn solve_multiple<'a>(
+        &'a mut self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Box<Iterator<Item = CanonicalSolution> + 'a> {
+        let goal = self.context().prepare_root_goal(goal);
+        Box::new(self.solve_multiple(&goal).map(|answer| CanonicalSolution {
+            subst: answer.subst,
+            ambiguous: answer.ambiguous,
+        }))
+    }
+
+    fn solve_any(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<CanonicalSolution> {
+        let goal = self.context().prepare_root_goal(goal);
+        self.solve_any(&goal).map(|answer| CanonicalSolution {
+            subst: answer.subst,
+            ambiguous: answer.ambiguous,
+        })
+    }
+
+    /// The goal may be simplified before it is solved, so the
+    /// overflows and flounders are looked up using the simplified goal.
+    fn solve_partial(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> PartialSolution {
+        let goal = self.context().prepare_root_goal(goal);
+        let solution = self.solve(&goal);
+        let overflows = Solver::overflows(self)
+            .iter()
+            .filter_map(|overflow| overflow.starting_from(&goal))
+            .collect();
+        let flounders = self
+            .flounders()
+            .iter()
+            .map(|flounder| Flounder {
+                goal_stack: flounder.goal_stack.clone(),
+                subgoal: flounder.subgoal.clone(),
+            })
+            .filter_map(|flounder| flounder.starting_from(&goal))
+            .collect();
+        PartialSolution {
+            solution,
+            overflows,
+            flounders,
+        }
+    }
+
+    /// The goal may be simplified before it is solved, so the proof
+    /// is that of the simplified goal.
+    fn proof_tree(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<ProofTree> {
+        let goal = self.context().prepare_root_goal(goal);
+        proof::proof_tree(self, &goal)
+    }
+
+    fn overflows(&self) -> Vec<Overflow> {
+        self.overflows()
+            .iter()
+            .map(|overflow| Overflow {
+                kind: overflow.kind,
+                goal_stack: overflow.goal_stack.clone(),
+            })
+            .collect()
+    }
+
+    fn snapshot(&self) -> ForestSnapshot<SlgContext> {
+        self.snapshot()
+    }
+
+    fn clear_cache(&mut self) {
+        self.clear_cache()
+    }
+
+    fn update_program(&mut self, program: Arc<dyn ProgramClauseSet>) {
+        let old_context = self.context().clone();
+        let old_program = old_context.program();
+        let context = old_context.with_program(program.clone());
+        self.invalidate(context.clone(), |goal| {
+            if old_context.is_coinductive(goal) != context.is_coinductive(goal) {
+                return true;
+            }
+
+            // The strands of a table for a domain goal come from the
+            // program clauses for that goal; other goals are
+            // simplified without looking at the program.
+            match &goal.canonical.value.goal {
+                Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) => {
+                    let old_clauses: BTreeSet<_> = old_program
+                        .program_clauses_for_goal(domain_goal)
+                        .into_iter()
+                        .collect();
+                    let clauses: BTreeSet<_> = program
+                        .program_clauses_for_goal(domain_goal)
+                        .into_iter()
+                        .collect();
+                    old_clauses != clauses
+                }
+                _ => false,
+            }
+        });
+    }
+
+    fn set_event_listener(&mut self, listener: Box<dyn EventListener<SlgContext>>) {
+        self.set_event_listener(listener)
     }
 }
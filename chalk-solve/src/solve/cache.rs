@@ -0,0 +1,270 @@
+//! A cache of root goals and their solutions that can be saved and
+//! reloaded, so that a host solving the same goals over and over
+//! (a CI run, or an IDE restarting) can warm-start from disk.
+//!
+//! The cache is only meaningful for the program (and `SolverChoice`)
+//! it was built with. It is up to the host to discard the cache
+//! whenever the program changes, e.g. by saving it along with the
+//! `ProgramEnvironment::fingerprint` of the program. Goals can be
+//! fingerprinted too (see `chalk_ir::fingerprint`), to compare them
+//! between runs without keeping them around.
+
+use crate::solve::slg::implementation::SlgContext;
+use crate::solve::proof::ProofTree;
+use crate::solve::{CanonicalSolution, Overflow, PartialSolution, Solution, Solver};
+use chalk_engine::cancel::{CancellationToken, Canceled};
+use chalk_engine::listener::EventListener;
+use chalk_engine::snapshot::ForestSnapshot;
+use chalk_ir::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Maps canonical goals to their solution (`None` if the goal has no
+/// solution). The cache implements `Serialize` and `Deserialize`, so
+/// it can be written out with any serde format.
+///
+/// By default the cache grows without bound. A cache created with
+/// `with_capacity` instead holds at most that many goals, evicting
+/// the least recently used one to make room for a new one.
+#[derive(Clone, Debug, Default)]
+pub struct SolutionCache {
+    /// Each goal's solution, along with the time it was last used.
+    solutions: HashMap<UCanonical<InEnvironment<Goal>>, (Option<Solution>, u64)>,
+
+    /// The goals in `solutions`, keyed by the time they were last
+    /// used, so that the first one is the least recently used.
+    recency: BTreeMap<u64, UCanonical<InEnvironment<Goal>>>,
+
+    /// Ticks every time a goal is used.
+    clock: u64,
+
+    capacity: Option<usize>,
+
+    /// The number of goals evicted so far.
+    evictions: usize,
+}
+
+impl SolutionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a cache that holds at most `capacity` goals.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut cache = Self::default();
+        cache.set_capacity(capacity);
+        cache
+    }
+
+    /// Limits the cache to `capacity` goals, evicting the least
+    /// recently used ones if it holds more than that already (as a
+    /// freshly deserialized cache may).
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = Some(capacity);
+        self.evict();
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Returns the cached solution for `goal`, if any. The outer
+    /// `Option` tells whether `goal` is in the cache at all. This
+    /// counts as a use of `goal`, making it the last to be evicted.
+    pub fn get(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<&Option<Solution>> {
+        let time = self.tick();
+        let (solution, last_used) = self.solutions.get_mut(goal)?;
+        let goal = self.recency.remove(last_used).unwrap();
+        self.recency.insert(time, goal);
+        *last_used = time;
+        Some(&*solution)
+    }
+
+    pub fn insert(&mut self, goal: UCanonical<InEnvironment<Goal>>, solution: Option<Solution>) {
+        let time = self.tick();
+        if let Some((_, last_used)) = self.solutions.insert(goal.clone(), (solution, time)) {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(time, goal);
+        self.evict();
+    }
+
+    pub fn len(&self) -> usize {
+        self.solutions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.solutions.is_empty()
+    }
+
+    /// The number of goals evicted to respect the capacity, since the
+    /// cache was created.
+    pub fn evictions(&self) -> usize {
+        self.evictions
+    }
+
+    pub fn clear(&mut self) {
+        self.solutions.clear();
+        self.recency.clear();
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn evict(&mut self) {
+        let capacity = match self.capacity {
+            Some(capacity) => capacity,
+            None => return,
+        };
+        while self.solutions.len() > capacity {
+            let time = *self.recency.keys().next().unwrap();
+            let goal = self.recency.remove(&time).unwrap();
+            self.solutions.remove(&goal);
+            self.evictions += 1;
+        }
+    }
+}
+
+/// Two caches are equal if they hold the same solutions, however they
+/// were used.
+impl PartialEq for SolutionCache {
+    fn eq(&self, other: &SolutionCache) -> bool {
+        self.len() == other.len()
+            && self
+                .solutions
+                .iter()
+                .all(|(goal, (solution, _))| match other.solutions.get(goal) {
+                    Some((other_solution, _)) => solution == other_solution,
+                    None => false,
+                })
+    }
+}
+
+impl Eq for SolutionCache {}
+
+// Goals are not strings, so many formats (e.g. JSON) cannot use them
+// as map keys; we write the cache out as a list of pairs instead,
+// from the least to the most recently used, so that a reloaded cache
+// evicts goals in the same order.
+impl Serialize for SolutionCache {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            self.recency
+                .values()
+                .map(|goal| (goal, &self.solutions[goal].0)),
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for SolutionCache {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries: Vec<(UCanonical<InEnvironment<Goal>>, Option<Solution>)> =
+            Deserialize::deserialize(deserializer)?;
+        let mut cache = SolutionCache::new();
+        for (goal, solution) in entries {
+            cache.insert(goal, solution);
+        }
+        Ok(cache)
+    }
+}
+
+/// A solver that answers goals from a `SolutionCache` when it can,
+/// falling back to another solver (and recording the result) when it
+/// cannot. Create one with `SolverChoice::create_cached_solver`.
+pub struct CachedSolver {
+    cache: SolutionCache,
+    solver: Box<Solver>,
+}
+
+impl CachedSolver {
+    pub fn new(cache: SolutionCache, solver: Box<Solver>) -> Self {
+        CachedSolver { cache, solver }
+    }
+
+    /// The solutions found so far, including those loaded initially.
+    pub fn cache(&self) -> &SolutionCache {
+        &self.cache
+    }
+
+    pub fn into_cache(self) -> SolutionCache {
+        self.cache
+    }
+}
+
+impl Solver for CachedSolver {
+    fn solve_with_cancellation(
+        &mut self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Solution>, Canceled> {
+        if let Some(solution) = self.cache.get(goal) {
+            return Ok(solution.clone());
+        }
+
+        let solution = self.solver.solve_with_cancellation(goal, cancel)?;
+        self.cache.insert(goal.clone(), solution.clone());
+        Ok(solution)
+    }
+
+    fn solve_multiple<'a>(
+        &'a mut self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Box<Iterator<Item = CanonicalSolution> + 'a> {
+        self.solver.solve_multiple(goal)
+    }
+
+    /// A cached solution comes without the overflows and flounders
+    /// that were found computing it.
+    fn solve_partial(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> PartialSolution {
+        if let Some(solution) = self.cache.get(goal) {
+            return PartialSolution {
+                solution: solution.clone(),
+                overflows: vec![],
+                flounders: vec![],
+            };
+        }
+
+        let partial = self.solver.solve_partial(goal);
+        self.cache.insert(goal.clone(), partial.solution.clone());
+        partial
+    }
+
+    /// First answers are not cached.
+    fn solve_any(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<CanonicalSolution> {
+        self.solver.solve_any(goal)
+    }
+
+    /// Proofs are not cached: the goal is solved again by the
+    /// underlying solver, which reuses its tables if it can.
+    fn proof_tree(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<ProofTree> {
+        self.solver.proof_tree(goal)
+    }
+
+    fn overflows(&self) -> Vec<Overflow> {
+        self.solver.overflows()
+    }
+
+    /// Goals whose solutions came from the cache have no tables.
+    fn snapshot(&self) -> ForestSnapshot<SlgContext> {
+        self.solver.snapshot()
+    }
+
+    fn clear_cache(&mut self) {
+        self.cache.clear();
+        self.solver.clear_cache();
+    }
+
+    /// The cache does not record what its solutions depend on, so it
+    /// is emptied; the underlying solver keeps what it can.
+    fn update_program(&mut self, program: Arc<dyn ProgramClauseSet>) {
+        self.cache.clear();
+        self.solver.update_program(program);
+    }
+
+    fn set_event_listener(&mut self, listener: Box<dyn EventListener<SlgContext>>) {
+        self.solver.set_event_listener(listener);
+    }
+}
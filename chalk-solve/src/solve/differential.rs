@@ -0,0 +1,222 @@
+//! Differential solving: running every goal through two solvers and
+//! recording the goals on which they disagree, to catch soundness or
+//! completeness bugs that only one of them has. Create one with
+//! `SolverChoice::create_differential_solver`.
+//!
+//! There is only one solver (SLG) at the moment, so the two solvers
+//! differ in their configuration -- say, depth-first against
+//! breadth-first search, or with and without goal simplification.
+//! Options that legitimately change answers (such as a smaller
+//! `max_size`) will of course show up as disagreements too.
+
+use crate::solve::slg::implementation::SlgContext;
+use crate::solve::proof::ProofTree;
+use crate::solve::{
+    CanonicalSolution, Overflow, PartialSolution, Solution, Solver, SolverChoice,
+};
+use chalk_engine::cancel::{CancellationToken, Canceled};
+use chalk_engine::listener::EventListener;
+use chalk_engine::snapshot::ForestSnapshot;
+use chalk_ir::*;
+use std::sync::Arc;
+
+/// A goal on which the two solvers disagree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Disagreement {
+    /// The goal as it was given.
+    pub goal: UCanonical<InEnvironment<Goal>>,
+
+    /// A goal with as few of the conjuncts of `goal` as could be kept
+    /// while the solvers still disagree.
+    pub minimized_goal: UCanonical<InEnvironment<Goal>>,
+
+    /// The solution of each solver to `minimized_goal`.
+    pub solutions: (Option<Solution>, Option<Solution>),
+}
+
+pub struct DifferentialSolver {
+    choices: (SolverChoice, SolverChoice),
+    solvers: (Box<Solver>, Box<Solver>),
+    program: Arc<dyn ProgramClauseSet>,
+    disagreements: Vec<Disagreement>,
+}
+
+impl DifferentialSolver {
+    pub fn new(choices: (SolverChoice, SolverChoice), program: Arc<dyn ProgramClauseSet>) -> Self {
+        DifferentialSolver {
+            choices,
+            solvers: (
+                choices.0.create_solver_for_clause_set(program.clone()),
+                choices.1.create_solver_for_clause_set(program.clone()),
+            ),
+            program,
+            disagreements: vec![],
+        }
+    }
+
+    /// The disagreements found so far, in the order of the goals.
+    pub fn disagreements(&self) -> &[Disagreement] {
+        &self.disagreements
+    }
+
+    pub fn take_disagreements(&mut self) -> Vec<Disagreement> {
+        ::std::mem::replace(&mut self.disagreements, vec![])
+    }
+
+    /// Solves `goal` with fresh solvers, so that the answers do not
+    /// depend on the goals solved before.
+    fn solve_fresh(
+        &self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+        cancel: &CancellationToken,
+    ) -> Result<(Option<Solution>, Option<Solution>), Canceled> {
+        let mut first = self.choices.0.create_solver_for_clause_set(self.program.clone());
+        let mut second = self.choices.1.create_solver_for_clause_set(self.program.clone());
+        Ok((
+            first.solve_with_cancellation(goal, cancel)?,
+            second.solve_with_cancellation(goal, cancel)?,
+        ))
+    }
+
+    /// Drops the conjuncts of `goal` one at a time, as long as the
+    /// solvers keep disagreeing without them.
+    fn minimize(
+        &self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+        solutions: (Option<Solution>, Option<Solution>),
+        cancel: &CancellationToken,
+    ) -> Result<Disagreement, Canceled> {
+        let mut conjuncts = vec![];
+        push_conjuncts(&goal.canonical.value.goal, &mut conjuncts);
+
+        let mut minimized_goal = goal.clone();
+        let mut solutions = solutions;
+        let mut index = 0;
+        while conjuncts.len() > 1 && index < conjuncts.len() {
+            let mut remaining = conjuncts.clone();
+            remaining.remove(index);
+            let candidate = with_goal(goal, conjunction(&remaining));
+            let candidate_solutions = self.solve_fresh(&candidate, cancel)?;
+            if candidate_solutions.0 != candidate_solutions.1 {
+                conjuncts = remaining;
+                minimized_goal = candidate;
+                solutions = candidate_solutions;
+            } else {
+                index += 1;
+            }
+        }
+
+        Ok(Disagreement {
+            goal: goal.clone(),
+            minimized_goal,
+            solutions,
+        })
+    }
+}
+
+impl Solver for DifferentialSolver {
+    /// Returns the solution of the first solver.
+    fn solve_with_cancellation(
+        &mut self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+        cancel: &CancellationToken,
+    ) -> Result<Option<Solution>, Canceled> {
+        let first = self.solvers.0.solve_with_cancellation(goal, cancel)?;
+        let second = self.solvers.1.solve_with_cancellation(goal, cancel)?;
+        if first != second {
+            debug!("solvers disagree on {:?}: {:?} vs {:?}", goal, first, second);
+            let disagreement = self.minimize(goal, (first.clone(), second), cancel)?;
+            self.disagreements.push(disagreement);
+        }
+        Ok(first)
+    }
+
+    /// Only the first solver is used.
+    fn solve_multiple<'a>(
+        &'a mut self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Box<Iterator<Item = CanonicalSolution> + 'a> {
+        self.solvers.0.solve_multiple(goal)
+    }
+
+    /// Only the first solver is used: the solvers may legitimately
+    /// find different first answers.
+    fn solve_any(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<CanonicalSolution> {
+        self.solvers.0.solve_any(goal)
+    }
+
+    /// The solvers are compared as by `solve`; the overflows and
+    /// flounders are those of the first solver.
+    fn solve_partial(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> PartialSolution {
+        self.solve(goal);
+        self.solvers.0.solve_partial(goal)
+    }
+
+    /// Only the first solver is used.
+    fn proof_tree(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<ProofTree> {
+        self.solvers.0.proof_tree(goal)
+    }
+
+    fn overflows(&self) -> Vec<Overflow> {
+        self.solvers.0.overflows()
+    }
+
+    /// Only the tables of the first solver are shown.
+    fn snapshot(&self) -> ForestSnapshot<SlgContext> {
+        self.solvers.0.snapshot()
+    }
+
+    fn clear_cache(&mut self) {
+        self.solvers.0.clear_cache();
+        self.solvers.1.clear_cache();
+    }
+
+    fn update_program(&mut self, program: Arc<dyn ProgramClauseSet>) {
+        self.solvers.0.update_program(program.clone());
+        self.solvers.1.update_program(program.clone());
+        self.program = program;
+    }
+
+    /// The listener is attached to the first solver.
+    fn set_event_listener(&mut self, listener: Box<dyn EventListener<SlgContext>>) {
+        self.solvers.0.set_event_listener(listener);
+    }
+}
+
+fn push_conjuncts(goal: &Goal, conjuncts: &mut Vec<Goal>) {
+    match goal {
+        Goal::And(left, right) => {
+            push_conjuncts(left, conjuncts);
+            push_conjuncts(right, conjuncts);
+        }
+        Goal::All(goals) if !goals.is_empty() => {
+            for goal in goals {
+                push_conjuncts(goal, conjuncts);
+            }
+        }
+        goal => conjuncts.push(goal.clone()),
+    }
+}
+
+fn conjunction(conjuncts: &[Goal]) -> Goal {
+    match conjuncts {
+        [goal] => goal.clone(),
+        goals => Goal::All(goals.to_vec()),
+    }
+}
+
+fn with_goal(
+    root: &UCanonical<InEnvironment<Goal>>,
+    goal: Goal,
+) -> UCanonical<InEnvironment<Goal>> {
+    UCanonical {
+        universes: root.universes,
+        canonical: Canonical {
+            value: InEnvironment {
+                environment: root.canonical.value.environment.clone(),
+                goal,
+            },
+            binders: root.canonical.binders.clone(),
+        },
+    }
+}
@@ -0,0 +1,232 @@
+//! Elaboration of the environment. The hypotheses of an `if` goal are
+//! facts the goal may assume; but a hypothesis `T: Trait` also implies
+//! that `T` meets the supertraits of `Trait`, the bounds on its
+//! associated types, and so forth. These *implied bounds* are expressed
+//! by the program clauses for `FromEnv` (e.g. `FromEnv(T: Super) :-
+//! FromEnv(T: Trait)`), so the solver elaborates a hypothesis simply by
+//! turning it into `FromEnv(T: Trait)` (see `add_hypotheses`); the rest
+//! follows from those clauses as the goal is solved.
+//!
+//! `elaborate_environment` instead computes the elaborated environment
+//! up front, for hosts that want to know what a goal may assume.
+
+use crate::infer::InferenceTable;
+use crate::solve::truncate;
+use chalk_engine::fallible::*;
+use chalk_ir::fold::{
+    DefaultFreeVarFolder, DefaultInferenceFolder, DefaultTypeFolder, Fold, PlaceholderFolder,
+};
+use chalk_ir::*;
+use std::sync::Arc;
+
+/// Adds the `hypotheses` of an `if` goal to `environment`. Facts such
+/// as `T: Trait` are added as `FromEnv(T: Trait)`, so that the implied
+/// bounds of the hypotheses can be used too.
+pub fn add_hypotheses(
+    environment: &Arc<Environment>,
+    hypotheses: Vec<ProgramClause>,
+) -> Arc<Environment> {
+    environment.add_clauses(
+        hypotheses
+            .into_iter()
+            .map(|hypothesis| hypothesis.into_from_env_clause()),
+    )
+}
+
+/// Returns the facts of the environment of `goal`, along with every
+/// fact they imply by way of the program clauses whose conditions are
+/// all `FromEnv` goals: the supertraits of the traits in the
+/// environment, the bounds on their associated types, the types that
+/// the types in the environment imply to be well-formed, and so on.
+/// Facts larger than `max_size` are left out, which guarantees that
+/// the elaboration terminates.
+///
+/// `goal` should have been peeled (see `GoalExt::into_peeled_goal`), so
+/// that its hypotheses are in its environment. The facts are under the
+/// binders of `goal`, whose existential variables are treated as
+/// unknown types: the elaboration never instantiates them.
+pub fn elaborate_environment(
+    program: &ProgramEnvironment,
+    goal: &UCanonical<InEnvironment<Goal>>,
+    max_size: usize,
+) -> Canonical<Vec<DomainGoal>> {
+    let mut infer = InferenceTable::new();
+    for _ in 1..goal.universes {
+        infer.new_universe();
+    }
+    let binders = Binders {
+        binders: goal
+            .canonical
+            .binders
+            .iter()
+            .map(|kind| kind.clone().map(|_| ()))
+            .collect(),
+        value: goal.canonical.value.environment.clone(),
+        names: BinderNames::default(),
+    };
+    let environment = infer.instantiate_binders_universally(&binders);
+    let variables_universe = infer.max_universe();
+
+    let rules: Vec<_> = program
+        .program_clauses
+        .iter()
+        .filter(|clause| is_implied_bound_rule(clause))
+        .collect();
+
+    let mut facts: Vec<DomainGoal> = vec![];
+    for clause in &environment.clauses {
+        if let ProgramClause::Implies(implication) = clause {
+            let fact = implication.consequence.clone().into_from_env_goal();
+            if implication.conditions.is_empty() && !facts.contains(&fact) {
+                facts.push(fact);
+            }
+        }
+    }
+
+    loop {
+        let mut new_facts = vec![];
+        for rule in &rules {
+            let mut elaborator = Elaborator {
+                infer: &mut infer,
+                environment: &environment,
+                facts: &facts,
+            };
+            for fact in elaborator.consequences(rule) {
+                if !truncate::truncate(&mut infer, max_size, &fact).overflow
+                    && !facts.contains(&fact)
+                    && !new_facts.contains(&fact)
+                {
+                    new_facts.push(fact);
+                }
+            }
+        }
+        if new_facts.is_empty() {
+            break;
+        }
+        debug!("elaborate_environment: new facts {:?}", new_facts);
+        facts.extend(new_facts);
+    }
+
+    let facts = facts
+        .fold_with(&mut Generalizer { variables_universe }, 0)
+        .unwrap();
+    Canonical {
+        value: facts,
+        binders: goal.canonical.binders.clone(),
+    }
+}
+
+/// True for clauses like `FromEnv(T: Super) :- FromEnv(T: Trait)`,
+/// which can be applied to the facts of an environment.
+crate fn is_implied_bound_rule(clause: &ProgramClause) -> bool {
+    let implication = match clause {
+        ProgramClause::Implies(implication) => implication,
+        ProgramClause::ForAll(implication) => &implication.value,
+    };
+    !implication.conditions.is_empty()
+        && implication.conditions.iter().all(|condition| match condition {
+            Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::FromEnv(_))) => true,
+            _ => false,
+        })
+}
+
+struct Elaborator<'e> {
+    infer: &'e mut InferenceTable,
+    environment: &'e Arc<Environment>,
+    facts: &'e [DomainGoal],
+}
+
+impl<'e> Elaborator<'e> {
+    /// The consequences of `rule` for each way of matching its
+    /// conditions with the facts.
+    fn consequences(&mut self, rule: &ProgramClause) -> Vec<DomainGoal> {
+        let snapshot = self.infer.snapshot();
+        let implication = match rule {
+            ProgramClause::Implies(implication) => implication.clone(),
+            ProgramClause::ForAll(implication) => {
+                self.infer.instantiate_binders_existentially(implication)
+            }
+        };
+        let conditions: Vec<_> = implication
+            .conditions
+            .into_iter()
+            .filter_map(|condition| match condition {
+                Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) => Some(domain_goal),
+                _ => None,
+            })
+            .collect();
+
+        let mut consequences = vec![];
+        self.match_conditions(&implication.consequence, &conditions, &mut consequences);
+        self.infer.rollback_to(snapshot);
+        consequences
+    }
+
+    fn match_conditions(
+        &mut self,
+        consequence: &DomainGoal,
+        conditions: &[DomainGoal],
+        consequences: &mut Vec<DomainGoal>,
+    ) {
+        let (condition, conditions) = match conditions.split_first() {
+            Some(split) => split,
+            None => {
+                // Skip consequences that the conditions do not
+                // determine entirely.
+                let consequence = self.infer.normalize_deep(consequence);
+                if self.infer.canonicalize(&consequence).free_vars().is_empty() {
+                    consequences.push(consequence);
+                }
+                return;
+            }
+        };
+
+        for fact in self.facts {
+            let snapshot = self.infer.snapshot();
+            if let Ok(result) = self.infer.unify(self.environment, condition, fact) {
+                if result.goals.is_empty() && result.constraints.is_empty() {
+                    self.match_conditions(consequence, conditions, consequences);
+                }
+            }
+            self.infer.rollback_to(snapshot);
+        }
+    }
+}
+
+/// Replaces the placeholders that stand for the variables of the goal
+/// with the corresponding bound variables.
+struct Generalizer {
+    variables_universe: UniverseIndex,
+}
+
+impl DefaultTypeFolder for Generalizer {}
+
+impl DefaultInferenceFolder for Generalizer {}
+
+impl DefaultFreeVarFolder for Generalizer {}
+
+impl PlaceholderFolder for Generalizer {
+    fn fold_free_placeholder_ty(
+        &mut self,
+        universe: PlaceholderIndex,
+        binders: usize,
+    ) -> Fallible<Ty> {
+        if universe.universe() == self.variables_universe {
+            Ok(DebruijnIndex::new(universe.index()).shifted_in(binders).to_ty())
+        } else {
+            Ok(universe.to_ty())
+        }
+    }
+
+    fn fold_free_placeholder_lifetime(
+        &mut self,
+        universe: PlaceholderIndex,
+        binders: usize,
+    ) -> Fallible<Lifetime> {
+        if universe.universe() == self.variables_universe {
+            Ok(Lifetime::BoundVar(DebruijnIndex::new(universe.index()).shifted_in(binders)))
+        } else {
+            Ok(universe.to_lifetime())
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Explanations for goals that have no solution, or only an ambiguous
+//! one.
+//!
+//! When a goal fails, the most useful thing to know is usually which
+//! impls (or other clauses) were candidates, and which of their
+//! conditions could not be met. We find this out by asking the solver
+//! about the pieces of the goal individually: first to find the part
+//! of a compound goal that fails, and then about the conditions of
+//! each clause that could have proven it.
+//!
+//! When a goal is ambiguous, we instead collect the reasons for the
+//! ambiguity (see `AmbiguityReason`), so that tools can tell the user
+//! what to change.
+
+use crate::infer::InferenceTable;
+use crate::solve::{elaborate, Flounder, Guidance, Overflow, PartialSolution, Solver};
+use chalk_ir::cast::Cast;
+use chalk_ir::*;
+use std::fmt;
+use std::sync::Arc;
+
+/// Describes why a goal has no solution.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailureExplanation {
+    /// The goal that has no solution. If the original goal was a
+    /// compound goal (e.g. `A, B`), this is the first part of it that
+    /// fails on its own.
+    pub goal: Canonical<InEnvironment<Goal>>,
+
+    /// The clauses that could have proven `goal`, each with the reason
+    /// it did not. Empty if `goal` is not a domain goal.
+    pub candidates: Vec<Candidate>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub clause: ProgramClause,
+    pub failure: CandidateFailure,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CandidateFailure {
+    /// The consequence of the clause does not unify with the goal.
+    DoesNotUnify,
+
+    /// This condition of the clause (or a goal produced by
+    /// unification) has no solution.
+    Subgoal(Canonical<InEnvironment<Goal>>),
+
+    /// Each condition has a solution on its own, but they cannot all
+    /// be satisfied at once.
+    Conditions,
+}
+
+impl fmt::Display for FailureExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "no solution for `{:?}`", self.goal.value.goal)?;
+        if self.candidates.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, "; candidates were:")?;
+        for Candidate { clause, failure } in &self.candidates {
+            write!(f, "\n    {:?}\n        ", clause)?;
+            match failure {
+                CandidateFailure::DoesNotUnify => write!(f, "does not apply")?,
+                CandidateFailure::Subgoal(subgoal) => {
+                    write!(f, "`{:?}` has no solution", subgoal.value.goal)?
+                }
+                CandidateFailure::Conditions => {
+                    write!(f, "conditions cannot be satisfied together")?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describes why a goal has an ambiguous solution.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AmbiguityExplanation {
+    /// The goal whose solution is ambiguous.
+    pub goal: Canonical<InEnvironment<Goal>>,
+
+    /// Every reason we found for the ambiguity; usually, there is just
+    /// one.
+    pub reasons: Vec<AmbiguityReason>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AmbiguityReason {
+    /// More than one clause could prove the goal: each of these unifies
+    /// with it, and each of their conditions has a solution. (Only
+    /// reported when the goal is a domain goal.)
+    MultipleCandidates(Vec<ProgramClause>),
+
+    /// A negative subgoal could not be decided because it had
+    /// unresolved inference variables.
+    Floundered(Flounder),
+
+    /// The search overflowed, so that some answer had to be truncated
+    /// or some subgoal given up on.
+    Overflow(Overflow),
+
+    /// The solution does not determine these existential variables of
+    /// the goal (given by their index in the goal's binders); the goal
+    /// can be decided once they are known.
+    UnresolvedVariables(Vec<usize>),
+
+    /// The solution is conditional on negative subgoals that could not
+    /// be decided (see `Solution::Conditional`).
+    Conditional,
+}
+
+impl fmt::Display for AmbiguityExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "`{:?}` is ambiguous", self.goal.value.goal)?;
+        for reason in &self.reasons {
+            write!(f, "\n    ")?;
+            match reason {
+                AmbiguityReason::MultipleCandidates(clauses) => {
+                    write!(f, "multiple clauses apply:")?;
+                    for clause in clauses {
+                        write!(f, "\n        {:?}", clause)?;
+                    }
+                }
+                AmbiguityReason::Floundered(flounder) => write!(f, "{}", flounder)?,
+                AmbiguityReason::Overflow(overflow) => write!(f, "{}", overflow)?,
+                AmbiguityReason::UnresolvedVariables(variables) => {
+                    write!(f, "cannot determine the values of variables {:?}", variables)?
+                }
+                AmbiguityReason::Conditional => {
+                    write!(f, "depends on negative goals that cannot be decided")?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Explains why `goal` has no solution. Returns `None` if `goal` does
+/// in fact have some (possibly ambiguous) solution.
+pub fn explain_failure(
+    program: &Arc<ProgramEnvironment>,
+    solver: &mut Solver,
+    goal: &UCanonical<InEnvironment<Goal>>,
+) -> Option<FailureExplanation> {
+    Explainer { program, solver }.explain(goal)
+}
+
+/// Explains why `goal` has an ambiguous solution. Returns `None` if
+/// `goal` has a unique solution, or none at all. `solver` should not
+/// have been used for other goals yet, or it may report overflows and
+/// floundering that have nothing to do with `goal`.
+pub fn explain_ambiguity(
+    program: &Arc<ProgramEnvironment>,
+    solver: &mut Solver,
+    goal: &UCanonical<InEnvironment<Goal>>,
+) -> Option<AmbiguityExplanation> {
+    Explainer { program, solver }.explain_ambiguity(goal)
+}
+
+struct Explainer<'s> {
+    program: &'s Arc<ProgramEnvironment>,
+    solver: &'s mut Solver,
+}
+
+impl<'s> Explainer<'s> {
+    fn has_solution(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> bool {
+        self.solver.solve(goal).is_some()
+    }
+
+    fn explain(&mut self, goal: &UCanonical<InEnvironment<Goal>>) -> Option<FailureExplanation> {
+        debug_heading!("explain(goal={:?})", goal);
+
+        if self.has_solution(goal) {
+            return None;
+        }
+
+        let (mut infer, _, InEnvironment { environment, goal: value }) =
+            InferenceTable::from_canonical(goal.universes, &goal.canonical);
+
+        let subgoals = match value {
+            Goal::Quantified(QuantifierKind::ForAll, subgoal) => {
+                let subgoal = infer.instantiate_binders_universally(&subgoal);
+                vec![InEnvironment::new(&environment, *subgoal)]
+            }
+            Goal::Quantified(QuantifierKind::Exists, subgoal) => {
+                let subgoal = infer.instantiate_binders_existentially(&subgoal);
+                vec![InEnvironment::new(&environment, *subgoal)]
+            }
+            Goal::Implies(clauses, subgoal) => {
+                let environment = &elaborate::add_hypotheses(&environment, clauses);
+                vec![InEnvironment::new(environment, *subgoal)]
+            }
+            Goal::And(g1, g2) => vec![
+                InEnvironment::new(&environment, *g1),
+                InEnvironment::new(&environment, *g2),
+            ],
+            Goal::All(goals) => goals
+                .into_iter()
+                .map(|goal| InEnvironment::new(&environment, goal))
+                .collect(),
+            Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) => {
+                let candidates = self.candidates(&mut infer, &environment, &domain_goal);
+                return Some(FailureExplanation {
+                    goal: goal.canonical.clone(),
+                    candidates,
+                });
+            }
+            Goal::Any(_)
+            | Goal::Not(_)
+            | Goal::Leaf(LeafGoal::EqGoal(_))
+            | Goal::Leaf(LeafGoal::OutlivesGoal(_))
+            | Goal::CannotProve(()) => vec![],
+        };
+
+        // Blame the first part of the goal that fails on its own, if
+        // any; otherwise, the goal as a whole.
+        let explanation = subgoals
+            .iter()
+            .filter_map(|subgoal| {
+                let canonical = infer.canonicalize(subgoal).quantified;
+                let ucanonical = infer.u_canonicalize(&canonical).quantified;
+                self.explain(&ucanonical)
+            })
+            .next();
+
+        Some(explanation.unwrap_or_else(|| FailureExplanation {
+            goal: goal.canonical.clone(),
+            candidates: vec![],
+        }))
+    }
+
+    fn explain_ambiguity(
+        &mut self,
+        goal: &UCanonical<InEnvironment<Goal>>,
+    ) -> Option<AmbiguityExplanation> {
+        debug_heading!("explain_ambiguity(goal={:?})", goal);
+
+        let PartialSolution {
+            solution,
+            overflows,
+            flounders,
+        } = self.solver.solve_partial(goal);
+        let solution = solution?;
+        if solution.is_unique() {
+            return None;
+        }
+        let conditional = !solution.is_ambiguous();
+        let guidance = solution.guidance();
+
+        let mut reasons: Vec<_> = overflows
+            .into_iter()
+            .map(AmbiguityReason::Overflow)
+            .collect();
+        reasons.extend(flounders.into_iter().map(AmbiguityReason::Floundered));
+
+        let (mut infer, _, InEnvironment { environment, goal: value }) =
+            InferenceTable::from_canonical(goal.universes, &goal.canonical);
+        if let Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) = value {
+            let clauses: Vec<_> = self
+                .candidates(&mut infer, &environment, &domain_goal)
+                .into_iter()
+                .filter(|candidate| candidate.failure == CandidateFailure::Conditions)
+                .map(|candidate| candidate.clause)
+                .collect();
+            if clauses.len() > 1 {
+                reasons.push(AmbiguityReason::MultipleCandidates(clauses));
+            }
+        }
+
+        if conditional {
+            reasons.push(AmbiguityReason::Conditional);
+        }
+
+        let unresolved_variables = unresolved_variables(&goal.canonical, &guidance);
+        if !unresolved_variables.is_empty() {
+            reasons.push(AmbiguityReason::UnresolvedVariables(unresolved_variables));
+        }
+
+        Some(AmbiguityExplanation {
+            goal: goal.canonical.clone(),
+            reasons,
+        })
+    }
+
+    fn candidates(
+        &mut self,
+        infer: &mut InferenceTable,
+        environment: &Arc<Environment>,
+        domain_goal: &DomainGoal,
+    ) -> Vec<Candidate> {
+        let clauses: Vec<_> = environment
+            .clauses_for_goal(domain_goal)
+            .cloned()
+            .chain(self.program.program_clauses_for_goal(domain_goal))
+            .collect();
+
+        clauses
+            .into_iter()
+            .map(|clause| {
+                let snapshot = infer.snapshot();
+                let failure = self.candidate_failure(infer, environment, domain_goal, &clause);
+                infer.rollback_to(snapshot);
+                Candidate { clause, failure }
+            })
+            .collect()
+    }
+
+    fn candidate_failure(
+        &mut self,
+        infer: &mut InferenceTable,
+        environment: &Arc<Environment>,
+        domain_goal: &DomainGoal,
+        clause: &ProgramClause,
+    ) -> CandidateFailure {
+        let ProgramClauseImplication {
+            consequence,
+            conditions,
+        } = match clause {
+            ProgramClause::Implies(implication) => implication.clone(),
+            ProgramClause::ForAll(implication) => {
+                infer.instantiate_binders_existentially(implication)
+            }
+        };
+
+        let result = match infer.unify(environment, domain_goal, &consequence) {
+            Ok(result) => result,
+            Err(_) => return CandidateFailure::DoesNotUnify,
+        };
+
+        let subgoals = conditions
+            .into_iter()
+            .map(|condition| InEnvironment::new(environment, condition))
+            .chain(result.goals.into_iter().map(|g| g.cast()));
+
+        for subgoal in subgoals {
+            let canonical = infer.canonicalize(&subgoal).quantified;
+            let ucanonical = infer.u_canonicalize(&canonical).quantified;
+            if !self.has_solution(&ucanonical) {
+                return CandidateFailure::Subgoal(canonical);
+            }
+        }
+
+        CandidateFailure::Conditions
+    }
+}
+
+/// Returns the indices of the type variables of `goal` that `guidance`
+/// leaves undetermined.
+fn unresolved_variables(goal: &Canonical<InEnvironment<Goal>>, guidance: &Guidance) -> Vec<usize> {
+    let subst = match guidance {
+        Guidance::Definite(subst) | Guidance::Suggested(subst) => subst,
+        Guidance::Candidates { generalization, .. } => generalization,
+        Guidance::Unknown => {
+            return goal
+                .binders
+                .iter()
+                .enumerate()
+                .filter(|(_, kind)| kind.is_ty())
+                .map(|(index, _)| index)
+                .collect();
+        }
+    };
+
+    subst
+        .value
+        .parameters
+        .iter()
+        .enumerate()
+        .filter(|(_, parameter)| match parameter {
+            ParameterKind::Ty(ty) => ty.bound().is_some(),
+            ParameterKind::Lifetime(_) => false,
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
@@ -0,0 +1,173 @@
+//! Proof trees for goals with a unique solution.
+//!
+//! When asked to (see `SolverChoiceBuilder::record_proofs`), the SLG
+//! engine records how it derived each answer: the program clause its
+//! strand started from, and which answer of which table settled each
+//! subgoal (see `chalk_engine::Derivation`). A proof tree follows
+//! those records down from the answer that makes up the solution of
+//! the root goal, so it shows the clauses the solver actually used.
+
+use crate::infer::InferenceTable;
+use crate::solve::slg::implementation::SlgContext;
+use crate::solve::Solution;
+use chalk_engine::forest::Forest;
+use chalk_engine::{DerivedSubgoal, TableIndex};
+use chalk_ir::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// We give up on proofs deeper than this, rather than risk
+/// overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A proof that some goal holds.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofTree {
+    /// The goal proven at this node, with the values found by the
+    /// solver substituted in.
+    pub goal: Canonical<InEnvironment<Goal>>,
+
+    /// How `goal` was proven.
+    pub step: ProofStep,
+
+    /// Proofs of the subgoals that `step` gave rise to.
+    pub children: Vec<ProofTree>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProofStep {
+    /// The goal was resolved against this program clause (taken from
+    /// either the program or the environment); the children prove
+    /// its conditions.
+    Clause(ProgramClause),
+
+    /// A `forall`, `exists`, `if`, `any` or conjunction goal, broken
+    /// down into its parts (equalities among them are proven by
+    /// unification, and so have no node of their own).
+    Compound,
+
+    /// A goal `not { G }`, which holds because `G` has no solution.
+    Negation,
+
+    /// A coinductive goal that was already being proven further up,
+    /// so the proof is cyclic.
+    Cycle,
+}
+
+impl ProofTree {
+    fn fmt_indented(&self, f: &mut fmt::Formatter, indent: usize) -> Result<(), fmt::Error> {
+        write!(f, "{:1$}{2:?}", "", indent * 4, self.goal.value.goal)?;
+        match &self.step {
+            ProofStep::Clause(clause) => writeln!(f, " via {:?}", clause)?,
+            ProofStep::Compound => writeln!(f, "")?,
+            ProofStep::Negation => writeln!(f, " since the negated goal has no solution")?,
+            ProofStep::Cycle => writeln!(f, " by cycle")?,
+        }
+        for child in &self.children {
+            child.fmt_indented(f, indent + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProofTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Solves `goal` with `forest` and, if it has a unique solution,
+/// returns the proof that the forest recorded for it. Returns `None`
+/// if the forest does not record proofs, or if the solution rests on
+/// an answer that is not unconditional.
+pub fn proof_tree(
+    forest: &mut Forest<SlgContext, SlgContext>,
+    goal: &UCanonical<InEnvironment<Goal>>,
+) -> Option<ProofTree> {
+    match forest.solve(goal) {
+        Some(Solution::Unique(_)) => {}
+        _ => return None,
+    }
+
+    // A unique solution is made of the first answer of the root table.
+    let table = forest.table_for_goal(goal)?;
+    ProofBuilder { forest, depth: 0 }.prove(table, 0)
+}
+
+struct ProofBuilder<'f> {
+    forest: &'f Forest<SlgContext, SlgContext>,
+
+    /// The depth of the node being built.
+    depth: usize,
+}
+
+impl<'f> ProofBuilder<'f> {
+    /// Builds the proof of the `answer`-th answer of `table`.
+    fn prove(&mut self, table: TableIndex, answer: usize) -> Option<ProofTree> {
+        let goal = self.forest.table_goal(table);
+        let (answer_subst, derivation) = self.forest.derivation(table, answer)?;
+        debug_heading!("prove(goal={:?}, answer={:?})", goal, answer_subst);
+
+        if self.depth >= MAX_DEPTH {
+            return None;
+        }
+
+        self.depth += 1;
+        let children: Option<Vec<_>> = derivation
+            .subgoals
+            .iter()
+            .map(|&subgoal| self.prove_subgoal(subgoal))
+            .collect();
+        self.depth -= 1;
+
+        let step = match &derivation.clause {
+            Some(clause) => ProofStep::Clause(clause.clone()),
+            None => ProofStep::Compound,
+        };
+        Some(ProofTree {
+            goal: instantiate_goal(goal, answer_subst)?,
+            step,
+            children: children?,
+        })
+    }
+
+    fn prove_subgoal(&mut self, subgoal: DerivedSubgoal) -> Option<ProofTree> {
+        match subgoal {
+            DerivedSubgoal::Answer { table, answer } => self.prove(table, answer),
+            DerivedSubgoal::Negative { table } => {
+                let Canonical { value, binders } = &self.forest.table_goal(table).canonical;
+                let goal = Goal::Not(Box::new(value.goal.clone()));
+                Some(ProofTree {
+                    goal: Canonical {
+                        value: InEnvironment::new(&value.environment, goal),
+                        binders: binders.clone(),
+                    },
+                    step: ProofStep::Negation,
+                    children: vec![],
+                })
+            }
+            DerivedSubgoal::Cycle { table } => Some(ProofTree {
+                goal: self.forest.table_goal(table).canonical.clone(),
+                step: ProofStep::Cycle,
+                children: vec![],
+            }),
+        }
+    }
+}
+
+/// Substitutes the values of `answer` into `goal`.
+fn instantiate_goal(
+    goal: &UCanonical<InEnvironment<Goal>>,
+    answer: &Canonical<ConstrainedSubst>,
+) -> Option<Canonical<InEnvironment<Goal>>> {
+    let (mut infer, subst, goal_value) =
+        InferenceTable::from_canonical(goal.universes, &goal.canonical);
+    let ConstrainedSubst {
+        subst: answer_subst,
+        constraints: _,
+    } = infer.instantiate_canonical(answer);
+    infer
+        .unify(&goal_value.environment, &subst.parameters[..], &answer_subst.parameters[..])
+        .ok()?;
+    Some(infer.canonicalize(&goal_value).quantified)
+}
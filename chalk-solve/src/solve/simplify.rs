@@ -0,0 +1,163 @@
+//! A pass that simplifies root goals before the solver sees them (see
+//! `SolverChoiceBuilder::simplify_goals`). Machine-generated goals are
+//! often needlessly large -- the same where clause repeated, or a
+//! quantifier wrapped around conditions that do not mention its
+//! variables -- and the solver pays for each subgoal with a table.
+
+use chalk_ir::fold::shift::Shift;
+use chalk_ir::*;
+
+/// Returns a goal equivalent to `goal`, obtained by:
+///
+/// - flattening nested conjunctions and removing duplicate conjuncts;
+/// - reducing a disjunction to one of its alternatives, if that one is
+///   trivially true or is the only one;
+/// - dropping conjuncts that are trivially true, like `T = T`;
+/// - moving the conjuncts that do not mention the variables of a
+///   quantifier out of it (and dropping the quantifier if none of them
+///   do), as in `exists<T> { A, B<T> }` becoming `A, exists<T> { B<T> }`.
+pub fn simplify_goal(goal: &Goal) -> Goal {
+    match goal {
+        Goal::Quantified(kind, subgoal) => simplify_quantified(*kind, subgoal),
+        Goal::Implies(clauses, subgoal) => {
+            let subgoal = simplify_goal(subgoal);
+            if clauses.is_empty() || is_trivially_true(&subgoal) {
+                subgoal
+            } else {
+                Goal::Implies(clauses.clone(), Box::new(subgoal))
+            }
+        }
+        Goal::And(left, right) => {
+            let mut conjuncts = vec![];
+            push_conjuncts(simplify_goal(left), &mut conjuncts);
+            push_conjuncts(simplify_goal(right), &mut conjuncts);
+            conjunction(conjuncts)
+        }
+        Goal::All(goals) => {
+            if goals.is_empty() {
+                return goal.clone();
+            }
+            let mut conjuncts = vec![];
+            for goal in goals {
+                push_conjuncts(simplify_goal(goal), &mut conjuncts);
+            }
+            conjunction(conjuncts)
+        }
+        Goal::Any(goals) => {
+            let mut alternatives: Vec<Goal> = goals.iter().map(simplify_goal).collect();
+            if let Some(index) = alternatives.iter().position(is_trivially_true) {
+                alternatives.swap_remove(index)
+            } else if alternatives.len() == 1 {
+                alternatives.pop().unwrap()
+            } else {
+                Goal::Any(alternatives)
+            }
+        }
+        Goal::Not(subgoal) => Goal::Not(Box::new(simplify_goal(subgoal))),
+        Goal::Leaf(_) | Goal::CannotProve(()) => goal.clone(),
+    }
+}
+
+/// Simplifies the goal of a root goal with `simplify_goal`, leaving its
+/// canonical variables and environment as they are.
+pub fn simplify_root_goal(
+    goal: &UCanonical<InEnvironment<Goal>>,
+) -> UCanonical<InEnvironment<Goal>> {
+    UCanonical {
+        universes: goal.universes,
+        canonical: Canonical {
+            value: InEnvironment {
+                environment: goal.canonical.value.environment.clone(),
+                goal: simplify_goal(&goal.canonical.value.goal),
+            },
+            binders: goal.canonical.binders.clone(),
+        },
+    }
+}
+
+fn simplify_quantified(kind: QuantifierKind, subgoal: &Binders<Box<Goal>>) -> Goal {
+    let mut conjuncts = vec![];
+    push_conjuncts(simplify_goal(&subgoal.value), &mut conjuncts);
+
+    // Trivially true conjuncts can go even if they refer to the
+    // quantified variables, as long as something else is left.
+    if conjuncts.iter().any(|conjunct| !is_trivially_true(conjunct)) {
+        conjuncts.retain(|conjunct| !is_trivially_true(conjunct));
+    }
+
+    // Conjuncts that do not refer to the quantified variables can be
+    // shifted out of the binder.
+    let mut inner = vec![];
+    let mut outer = vec![];
+    for conjunct in conjuncts {
+        match conjunct.shifted_out(subgoal.binders.len()) {
+            Ok(conjunct) => outer.push(conjunct),
+            Err(_) => inner.push(conjunct),
+        }
+    }
+
+    if !inner.is_empty() {
+        outer.push(Goal::Quantified(
+            kind,
+            Binders {
+                binders: subgoal.binders.clone(),
+                value: Box::new(conjunction(inner)),
+                names: subgoal.names.clone(),
+            },
+        ));
+    }
+    conjunction(outer)
+}
+
+/// Pushes the conjuncts of `goal`, which is already simplified, onto
+/// `conjuncts`, skipping those that are there already.
+fn push_conjuncts(goal: Goal, conjuncts: &mut Vec<Goal>) {
+    match goal {
+        Goal::And(left, right) => {
+            push_conjuncts(*left, conjuncts);
+            push_conjuncts(*right, conjuncts);
+        }
+        // `all { }` is kept as the trivially true goal.
+        Goal::All(goals) => {
+            if goals.is_empty() {
+                push_conjunct(Goal::All(goals), conjuncts);
+            } else {
+                for goal in goals {
+                    push_conjuncts(goal, conjuncts);
+                }
+            }
+        }
+        goal => push_conjunct(goal, conjuncts),
+    }
+}
+
+fn push_conjunct(goal: Goal, conjuncts: &mut Vec<Goal>) {
+    if !conjuncts.contains(&goal) {
+        conjuncts.push(goal);
+    }
+}
+
+/// Builds the conjunction of `conjuncts`, which must not be empty,
+/// leaving out the trivially true ones (unless they are all trivially
+/// true, in which case one is kept).
+fn conjunction(conjuncts: Vec<Goal>) -> Goal {
+    let (trivial, mut conjuncts): (Vec<_>, Vec<_>) =
+        conjuncts.into_iter().partition(is_trivially_true);
+    let last = match conjuncts.pop() {
+        Some(last) => last,
+        None => return trivial.into_iter().next().unwrap(),
+    };
+    conjuncts
+        .into_iter()
+        .rev()
+        .fold(last, |goal, conjunct| Goal::And(Box::new(conjunct), Box::new(goal)))
+}
+
+fn is_trivially_true(goal: &Goal) -> bool {
+    match goal {
+        Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })) => a == b,
+        Goal::Leaf(LeafGoal::OutlivesGoal(OutlivesGoal { a, b })) => a == b,
+        Goal::All(goals) => goals.is_empty(),
+        _ => false,
+    }
+}
@@ -1,41 +1,212 @@
 use crate::infer::ucanonicalize::{UCanonicalized, UniverseMap};
-use crate::infer::unify::UnificationResult;
+use crate::infer::unify::{UnificationHook, UnificationResult, Variance};
 use crate::infer::InferenceTable;
+use crate::solve::elaborate;
+use crate::solve::simplify;
 use crate::solve::truncate::{self, Truncated};
-use crate::solve::Solution;
+use crate::solve::{ClauseOrder, Coinduction, SearchStrategy, Solution, SolverChoice};
 use chalk_engine::fallible::Fallible;
 use chalk_ir::cast::{Cast, Caster};
-use chalk_ir::could_match::CouldMatch;
+use chalk_ir::fingerprint::Fingerprint;
+use chalk_ir::fold::EraseLifetimes;
+use chalk_ir::heap_size::HeapSize;
 use chalk_ir::*;
 
 use chalk_engine::context;
 use chalk_engine::hh::HhGoal;
 use chalk_engine::{DelayedLiteral, ExClause, Literal};
 
-use std::fmt::Debug;
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex};
 
 mod aggregate;
 mod resolvent;
 
 #[derive(Clone, Debug)]
 pub struct SlgContext {
-    program: Arc<ProgramEnvironment>,
+    program: Arc<dyn ProgramClauseSet>,
     max_size: usize,
+
+    /// Maximum depth of the stack of subgoals (see
+    /// `SolverChoiceBuilder::overflow_depth`).
+    overflow_depth: Option<usize>,
+
+    /// If true, the lifetimes of goals and program clauses are erased
+    /// before they are solved (see `SolverChoiceBuilder::erase_lifetimes`).
+    erase_lifetimes: bool,
+
+    /// Maximum number of answers `make_solution` will examine (see
+    /// `SolverChoiceBuilder::max_answers`).
+    max_answers: Option<usize>,
+
+    /// The most answers an ambiguous solution lists (see
+    /// `SolverChoiceBuilder::max_candidates`).
+    max_candidates: Option<usize>,
+
+    coinduction: Coinduction,
+
+    /// If true, program clauses are tried, and candidates listed, in an
+    /// order that does not depend on how the program was written (see
+    /// `SolverChoiceBuilder::deterministic`).
+    deterministic: bool,
+
+    search_strategy: SearchStrategy,
+
+    /// If true, root goals are simplified before they are solved (see
+    /// `SolverChoiceBuilder::simplify_goals`).
+    simplify_goals: bool,
+
+    /// If true, trait goals that the environment could prove are not
+    /// proven from impls (see `SolverChoiceBuilder::prefer_where_clauses`).
+    prefer_where_clauses: bool,
+
+    clause_order: ClauseOrder,
+    record_proofs: bool,
+
+    /// How the stack grows while solving (see
+    /// `SolverChoiceBuilder::stack_red_zone`).
+    stack_red_zone: usize,
+    stack_segment_size: usize,
+
+    /// Consulted whenever two applications of the same type
+    /// constructor are unified (see `with_unification_hook`).
+    unification_hook: Option<Arc<dyn UnificationHook>>,
+
+    /// The inference tables to instantiate strands and goals in.
+    inference_tables: InferenceTablePool,
+}
+
+/// Empty inference tables, kept so that each strand the solver pursues
+/// reuses one instead of growing a new table. Clones of a context share
+/// its pool.
+#[derive(Clone, Default)]
+struct InferenceTablePool {
+    tables: Arc<Mutex<Vec<InferenceTable>>>,
+}
+
+impl InferenceTablePool {
+    /// The most tables kept. Strands are pursued recursively, one table
+    /// per level, so this many covers all but deep searches.
+    const MAX_TABLES: usize = 16;
+
+    fn take(&self) -> InferenceTable {
+        self.tables
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(InferenceTable::resettable)
+    }
+
+    fn give_back(&self, mut table: InferenceTable) {
+        table.reset();
+        let mut tables = self.tables.lock().unwrap();
+        if tables.len() < Self::MAX_TABLES {
+            tables.push(table);
+        }
+    }
+}
+
+impl Debug for InferenceTablePool {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "InferenceTablePool({})", self.tables.lock().unwrap().len())
+    }
 }
 
 pub(super) struct TruncatingInferenceTable {
-    program: Arc<ProgramEnvironment>,
+    program: Arc<dyn ProgramClauseSet>,
     max_size: usize,
+    erase_lifetimes: bool,
+    deterministic: bool,
+    prefer_where_clauses: bool,
+    clause_order: ClauseOrder,
     infer: InferenceTable,
 }
 
 impl SlgContext {
-    pub fn new(program: &Arc<ProgramEnvironment>, max_size: usize) -> SlgContext {
+    pub fn new(program: &Arc<ProgramEnvironment>, solver_choice: SolverChoice) -> SlgContext {
+        SlgContext::from_clause_set(program.clone(), solver_choice)
+    }
+
+    /// Like `new`, but takes the program clauses from `program`, which
+    /// may produce them lazily (see `ProgramClauseSet`).
+    pub fn from_clause_set(
+        program: Arc<dyn ProgramClauseSet>,
+        solver_choice: SolverChoice,
+    ) -> SlgContext {
+        SlgContext {
+            program,
+            max_size: solver_choice.max_size(),
+            overflow_depth: solver_choice.overflow_depth(),
+            erase_lifetimes: solver_choice.erase_lifetimes(),
+            max_answers: solver_choice.max_answers(),
+            max_candidates: solver_choice.max_candidates(),
+            coinduction: solver_choice.coinduction(),
+            deterministic: solver_choice.deterministic(),
+            search_strategy: solver_choice.search_strategy(),
+            simplify_goals: solver_choice.simplify_goals(),
+            prefer_where_clauses: solver_choice.prefer_where_clauses(),
+            clause_order: solver_choice.clause_order(),
+            record_proofs: solver_choice.record_proofs(),
+            stack_red_zone: solver_choice.stack_red_zone(),
+            stack_segment_size: solver_choice.stack_segment_size(),
+            unification_hook: None,
+            inference_tables: InferenceTablePool::default(),
+        }
+    }
+
+    /// Returns a context with the same settings, but that unifies types
+    /// as `hook` decides (see `UnificationHook`).
+    pub fn with_unification_hook(&self, hook: Arc<dyn UnificationHook>) -> SlgContext {
         SlgContext {
-            program: program.clone(),
-            max_size,
+            unification_hook: Some(hook),
+            ..self.clone()
+        }
+    }
+
+    pub fn program(&self) -> &Arc<dyn ProgramClauseSet> {
+        &self.program
+    }
+
+    /// Returns a context with the same settings, but for `program`.
+    pub fn with_program(&self, program: Arc<dyn ProgramClauseSet>) -> SlgContext {
+        SlgContext {
+            program,
+            ..self.clone()
+        }
+    }
+
+    /// Returns `goal` as it should be solved: simplified, if this
+    /// context is configured to simplify goals, and with its lifetimes
+    /// erased, if it erases lifetimes.
+    pub fn prepare_root_goal<'g>(
+        &self,
+        goal: &'g UCanonical<InEnvironment<Goal>>,
+    ) -> Cow<'g, UCanonical<InEnvironment<Goal>>> {
+        let mut goal = if self.simplify_goals {
+            Cow::Owned(simplify::simplify_root_goal(goal))
+        } else {
+            Cow::Borrowed(goal)
+        };
+        if self.erase_lifetimes {
+            // Erasing the lifetimes before the goal and its subgoals are
+            // canonicalized is what lets goals that differ only in their
+            // lifetimes share tables.
+            let canonical = &goal.canonical;
+            let erased = UCanonical {
+                canonical: Canonical {
+                    binders: canonical.binders.clone(),
+                    value: canonical.value.erase_lifetimes(),
+                },
+                universes: goal.universes,
+            };
+            goal = Cow::Owned(erased);
         }
+        goal
+    }
+
+    fn truncating_inference_table(&self, infer: InferenceTable) -> TruncatingInferenceTable {
+        TruncatingInferenceTable::new(self, infer)
     }
 }
 
@@ -58,16 +229,53 @@ impl context::Context for SlgContext {
     type GoalInEnvironment = InEnvironment<Goal>;
     type Substitution = Substitution;
     type RegionConstraint = InEnvironment<Constraint>;
-    type Variance = ();
+    type Variance = Variance;
 
     fn goal_in_environment(environment: &Arc<Environment>, goal: Goal) -> InEnvironment<Goal> {
         InEnvironment::new(environment, goal)
     }
+
+    fn goal_heap_size(goal: &UCanonical<InEnvironment<Goal>>) -> usize {
+        goal.heap_size()
+    }
+
+    fn ex_clause_heap_size(ex_clause: &Canonical<ExClause<Self>>) -> usize {
+        ex_clause.heap_size()
+    }
+
+    fn constrained_subst_heap_size(subst: &Canonical<ConstrainedSubst>) -> usize {
+        subst.heap_size()
+    }
 }
 
 impl context::ContextOps<SlgContext> for SlgContext {
     fn is_coinductive(&self, goal: &UCanonical<InEnvironment<Goal>>) -> bool {
-        goal.is_coinductive(&self.program)
+        let is_coinductive_trait = |trait_id: ItemId| match self.coinduction {
+            Coinduction::None => false,
+            Coinduction::Declared => self.program.is_coinductive_trait(trait_id),
+            Coinduction::AllTraits => true,
+        };
+        goal.canonical.value.goal.is_coinductive_with(&is_coinductive_trait)
+    }
+
+    fn max_stack_depth(&self) -> Option<usize> {
+        self.overflow_depth
+    }
+
+    fn breadth_first(&self) -> bool {
+        self.search_strategy == SearchStrategy::BreadthFirst
+    }
+
+    fn record_proofs(&self) -> bool {
+        self.record_proofs
+    }
+
+    fn stack_red_zone(&self) -> usize {
+        self.stack_red_zone
+    }
+
+    fn stack_segment_size(&self) -> usize {
+        self.stack_segment_size
     }
 
     fn instantiate_ucanonical_goal<R>(
@@ -75,10 +283,25 @@ impl context::ContextOps<SlgContext> for SlgContext {
         arg: &UCanonical<InEnvironment<Goal>>,
         op: impl context::WithInstantiatedUCanonicalGoal<Self, Output = R>,
     ) -> R {
-        let (infer, subst, InEnvironment { environment, goal }) =
-            InferenceTable::from_canonical(arg.universes, &arg.canonical);
-        let dyn_infer = &mut TruncatingInferenceTable::new(&self.program, self.max_size, infer);
-        op.with(dyn_infer, subst, environment, goal)
+        let (mut infer, subst, InEnvironment { environment, goal }) = self
+            .inference_tables
+            .take()
+            .instantiate_empty(arg.universes, &arg.canonical);
+        if self.erase_lifetimes {
+            // Once erased, the goal no longer mentions its lifetime
+            // variables, so they stand for the erased lifetime.
+            for parameter in subst.parameters.iter() {
+                if let ParameterKind::Lifetime(lifetime) = parameter {
+                    infer
+                        .unify(&environment, lifetime, &Lifetime::Erased)
+                        .expect("a lifetime variable unifies with an erased lifetime");
+                }
+            }
+        }
+        let mut dyn_infer = self.truncating_inference_table(infer);
+        let result = op.with(&mut dyn_infer, subst, environment, goal);
+        self.inference_tables.give_back(dyn_infer.infer);
+        result
     }
 
     fn instantiate_ex_clause<R>(
@@ -87,10 +310,14 @@ impl context::ContextOps<SlgContext> for SlgContext {
         canonical_ex_clause: &Canonical<ExClause<SlgContext>>,
         op: impl context::WithInstantiatedExClause<Self, Output = R>,
     ) -> R {
-        let (infer, _subst, ex_cluse) =
-            InferenceTable::from_canonical(num_universes, canonical_ex_clause);
-        let dyn_infer = &mut TruncatingInferenceTable::new(&self.program, self.max_size, infer);
-        op.with(dyn_infer, ex_cluse)
+        let (infer, _subst, ex_clause) = self
+            .inference_tables
+            .take()
+            .instantiate_empty(num_universes, canonical_ex_clause);
+        let mut dyn_infer = self.truncating_inference_table(infer);
+        let result = op.with(&mut dyn_infer, ex_clause);
+        self.inference_tables.give_back(dyn_infer.infer);
+        result
     }
 
     fn inference_normalized_subst_from_ex_clause(
@@ -138,10 +365,15 @@ impl context::ContextOps<SlgContext> for SlgContext {
 }
 
 impl TruncatingInferenceTable {
-    fn new(program: &Arc<ProgramEnvironment>, max_size: usize, infer: InferenceTable) -> Self {
+    fn new(context: &SlgContext, mut infer: InferenceTable) -> Self {
+        infer.set_unification_hook(context.unification_hook.clone());
         Self {
-            program: program.clone(),
-            max_size,
+            program: context.program.clone(),
+            max_size: context.max_size,
+            erase_lifetimes: context.erase_lifetimes,
+            deterministic: context.deterministic,
+            prefer_where_clauses: context.prefer_where_clauses,
+            clause_order: context.clause_order,
             infer,
         }
     }
@@ -176,8 +408,15 @@ impl context::InferenceTable<SlgContext, SlgContext> for TruncatingInferenceTabl
             Goal::Quantified(QuantifierKind::Exists, binders_goal) => HhGoal::Exists(binders_goal),
             Goal::Implies(dg, subgoal) => HhGoal::Implies(dg, *subgoal),
             Goal::And(g1, g2) => HhGoal::And(*g1, *g2),
+            Goal::All(goals) => HhGoal::All(goals),
+            Goal::Any(goals) => HhGoal::Any(goals),
             Goal::Not(g1) => HhGoal::Not(*g1),
-            Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })) => HhGoal::Unify((), a, b),
+            Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })) => {
+                HhGoal::Unify(Variance::Invariant, a, b)
+            }
+            Goal::Leaf(LeafGoal::OutlivesGoal(OutlivesGoal { a, b })) => {
+                HhGoal::Unify(Variance::Covariant, a.cast(), b.cast())
+            }
             Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) => HhGoal::DomainGoal(domain_goal),
             Goal::CannotProve(()) => HhGoal::CannotProve,
         }
@@ -189,7 +428,7 @@ impl context::InferenceTable<SlgContext, SlgContext> for TruncatingInferenceTabl
         env: &Arc<Environment>,
         clauses: Vec<ProgramClause>,
     ) -> Arc<Environment> {
-        Environment::add_clauses(env, clauses)
+        elaborate::add_hypotheses(env, clauses)
     }
 
     fn into_goal(&self, domain_goal: DomainGoal) -> Goal {
@@ -199,6 +438,36 @@ impl context::InferenceTable<SlgContext, SlgContext> for TruncatingInferenceTabl
     fn cannot_prove(&self) -> Goal {
         Goal::CannotProve(())
     }
+
+    fn any_goal(&self, goals: Vec<Goal>) -> Goal {
+        Goal::Any(goals)
+    }
+}
+
+/// True if `goal` is a trait goal and a where clause in `environment`
+/// could prove it.
+fn where_clause_applies(environment: &Environment, goal: &DomainGoal) -> bool {
+    match goal {
+        DomainGoal::Holds(WhereClause::Implemented(trait_ref)) => {
+            let from_env = DomainGoal::FromEnv(FromEnv::Trait(trait_ref.clone()));
+            let mut env_clauses = environment.clauses_for_goal(&from_env);
+            env_clauses.next().is_some()
+        }
+        _ => false,
+    }
+}
+
+/// How expensive `clause` is likely to be to try, for
+/// `ClauseOrder::FewestConditions`: its number of conditions, then its
+/// number of bound variables.
+fn clause_cost(clause: &ProgramClause) -> (usize, usize) {
+    match clause {
+        ProgramClause::Implies(implication) => (implication.conditions.len(), 0),
+        ProgramClause::ForAll(implication) => (
+            implication.value.conditions.len(),
+            implication.binders.len(),
+        ),
+    }
 }
 
 impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTable {
@@ -207,20 +476,40 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
         environment: &Arc<Environment>,
         goal: &DomainGoal,
     ) -> Vec<ProgramClause> {
-        let environment_clauses = environment
-            .clauses
-            .iter()
-            .filter(|&env_clause| env_clause.could_match(goal))
-            .cloned();
+        profile_scope!("clauses");
+        let environment_clauses = environment.clauses_for_goal(goal).cloned();
+
+        let mut program_clauses = self.program.program_clauses_for_goal(goal);
+        if self.prefer_where_clauses && where_clause_applies(environment, goal) {
+            // Only keep the clauses that prove the goal from the
+            // environment, like `Implemented(T: Foo) :- FromEnv(T: Foo)`.
+            program_clauses.retain(|clause| elaborate::is_implied_bound_rule(clause));
+        }
+        if self.erase_lifetimes {
+            // The goal, and so its environment, are already erased (see
+            // `SlgContext::prepare_root_goal`).
+            program_clauses = program_clauses
+                .iter()
+                .map(|clause| clause.erase_lifetimes())
+                .collect();
+        }
 
-        let program_clauses = self
-            .program
-            .program_clauses
-            .iter()
-            .filter(|&clause| clause.could_match(goal))
-            .cloned();
+        let clauses = environment_clauses.chain(program_clauses);
+        let mut clauses: Vec<_> = clauses.collect();
+        if self.deterministic {
+            // Order the clauses by their fingerprints, which depend only
+            // on their structure (and the paths of the items they name),
+            // not on the order in which they were generated.
+            clauses.sort_by_cached_key(|clause| Fingerprint::of(clause));
+        }
 
-        environment_clauses.chain(program_clauses).collect()
+        match self.clause_order {
+            ClauseOrder::AsWritten => {}
+            // A stable sort, so that ties keep the order above.
+            ClauseOrder::FewestConditions => clauses.sort_by_key(clause_cost),
+            ClauseOrder::Shuffled { .. } => self.clause_order.shuffle(&mut clauses, goal),
+        }
+        clauses
     }
 
     fn instantiate_binders_universally(&mut self, arg: &Binders<Box<Goal>>) -> Goal {
@@ -236,6 +525,7 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
     }
 
     fn canonicalize_goal(&mut self, value: &InEnvironment<Goal>) -> Canonical<InEnvironment<Goal>> {
+        profile_scope!("canonicalize");
         self.infer.canonicalize(value).quantified
     }
 
@@ -243,6 +533,7 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
         &mut self,
         value: &ExClause<SlgContext>,
     ) -> Canonical<ExClause<SlgContext>> {
+        profile_scope!("canonicalize");
         self.infer.canonicalize(value).quantified
     }
 
@@ -251,6 +542,7 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
         subst: Substitution,
         constraints: Vec<InEnvironment<Constraint>>,
     ) -> Canonical<ConstrainedSubst> {
+        profile_scope!("canonicalize");
         self.infer
             .canonicalize(&ConstrainedSubst { subst, constraints })
             .quantified
@@ -263,6 +555,7 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
         UCanonical<InEnvironment<Goal>>,
         crate::infer::ucanonicalize::UniverseMap,
     ) {
+        profile_scope!("canonicalize");
         let UCanonicalized {
             quantified,
             universes,
@@ -277,11 +570,12 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
     fn unify_parameters(
         &mut self,
         environment: &Arc<Environment>,
-        _: (),
+        variance: Variance,
         a: &Parameter,
         b: &Parameter,
     ) -> Fallible<UnificationResult> {
-        self.infer.unify(environment, a, b)
+        profile_scope!("unify");
+        self.infer.relate(environment, variance, a, b)
     }
 
     /// Since we do not have distinct types for the inference context and the slg-context,
@@ -296,6 +590,10 @@ impl context::UnificationOps<SlgContext, SlgContext> for TruncatingInferenceTabl
         c
     }
 
+    fn lift_program_clause(&self, clause: &ProgramClause) -> ProgramClause {
+        clause.clone()
+    }
+
     fn into_ex_clause(&mut self, result: UnificationResult, ex_clause: &mut ExClause<SlgContext>) {
         into_ex_clause(result, ex_clause)
     }
@@ -317,7 +615,7 @@ impl SubstitutionExt for Substitution {
     fn may_invalidate(&self, subst: &Canonical<Substitution>) -> bool {
         self.parameters
             .iter()
-            .zip(&subst.value.parameters)
+            .zip(subst.value.parameters.iter())
             .any(|(new, current)| MayInvalidate.aggregate_parameters(new, current))
     }
 }
@@ -341,8 +639,8 @@ impl MayInvalidate {
 
     // Returns true if the two types could be unequal.
     fn aggregate_tys(&mut self, new: &Ty, current: &Ty) -> bool {
-        match (new, current) {
-            (_, Ty::BoundVar(_)) => {
+        match (new.data(), current.data()) {
+            (_, TyData::BoundVar(_)) => {
                 // If the aggregate solution already has an inference
                 // variable here, then no matter what type we produce,
                 // the aggregate cannot get 'more generalized' than it
@@ -354,7 +652,7 @@ impl MayInvalidate {
                 false
             }
 
-            (Ty::BoundVar(_), _) => {
+            (TyData::BoundVar(_), _) => {
                 // If we see a type variable in the potential future
                 // solution, we have to be conservative. We don't know
                 // what type variable will wind up being! Remember
@@ -368,30 +666,31 @@ impl MayInvalidate {
                 true
             }
 
-            (Ty::InferenceVar(_), _) | (_, Ty::InferenceVar(_)) => {
+            (TyData::InferenceVar(_), _) | (_, TyData::InferenceVar(_)) => {
                 panic!(
                     "unexpected free inference variable in may-invalidate: {:?} vs {:?}",
                     new, current,
                 );
             }
 
-            (Ty::Apply(apply1), Ty::Apply(apply2)) => {
+            (TyData::Apply(apply1), TyData::Apply(apply2)) => {
                 self.aggregate_application_tys(apply1, apply2)
             }
 
-            (Ty::Projection(apply1), Ty::Projection(apply2)) => {
+            (TyData::Projection(apply1), TyData::Projection(apply2)) => {
                 self.aggregate_projection_tys(apply1, apply2)
             }
 
-            (Ty::UnselectedProjection(apply1), Ty::UnselectedProjection(apply2)) => {
+            (TyData::UnselectedProjection(apply1), TyData::UnselectedProjection(apply2)) => {
                 self.aggregate_unselected_projection_tys(apply1, apply2)
             }
 
             // For everything else, be conservative here and just say we may invalidate.
-            (Ty::ForAll(_), _)
-            | (Ty::Apply(_), _)
-            | (Ty::Projection(_), _)
-            | (Ty::UnselectedProjection(_), _) => true,
+            (TyData::ForAll(_), _)
+            | (TyData::Dyn(_), _)
+            | (TyData::Apply(_), _)
+            | (TyData::Projection(_), _)
+            | (TyData::UnselectedProjection(_), _) => true,
         }
     }
 
@@ -3,11 +3,14 @@ use crate::infer::InferenceTable;
 use crate::solve::slg::implementation::SubstitutionExt;
 use crate::solve::{Guidance, Solution};
 use chalk_ir::cast::Cast;
+use chalk_ir::fingerprint::Fingerprint;
 use chalk_ir::*;
 
 use chalk_engine::context;
 use chalk_engine::SimplifiedAnswer;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use super::SlgContext;
 
@@ -23,11 +26,24 @@ impl context::AggregateOps<SlgContext> for SlgContext {
         if simplified_answers.peek_answer().is_none() {
             return None;
         }
-        let SimplifiedAnswer { subst, ambiguous } = simplified_answers.next_answer().unwrap();
+        let SimplifiedAnswer {
+            subst,
+            ambiguous,
+            conditional,
+        } = simplified_answers.next_answer().unwrap();
+        let mut num_answers = 1;
 
-        // Exactly 1 unconditional answer?
-        if simplified_answers.peek_answer().is_none() && !ambiguous {
-            return Some(Solution::Unique(subst));
+        if simplified_answers.peek_answer().is_none() {
+            // Exactly 1 unconditional answer?
+            if !ambiguous {
+                return Some(Solution::Unique(subst));
+            }
+
+            // Exactly 1 answer, which holds if some undecided negative
+            // subgoals fail? Let the caller decide what to make of it.
+            if conditional {
+                return Some(Solution::Conditional(subst));
+            }
         }
 
         // Otherwise, we either have >1 answer, or else we have
@@ -44,25 +60,62 @@ impl context::AggregateOps<SlgContext> for SlgContext {
         // rust-lang/rust#21974.
         let mut subst = subst.map(|cs| cs.subst);
 
+        // If the caller asked for them, we also list the answers, for
+        // as long as there are no more than `max_candidates` of them.
+        let max_candidates = self.max_candidates.unwrap_or(0);
+        let mut candidates = vec![subst.clone()];
+
         // Extract answers and merge them into `subst`. Stop once we have
-        // a trivial subst (or run out of answers).
+        // a trivial subst (or run out of answers, or have examined
+        // `max_answers` of them) -- unless we are still listing
+        // candidates, in which case we keep going so as to find them
+        // all.
         let guidance = loop {
-            if subst.value.is_empty() || is_trivial(&subst) {
-                break Guidance::Unknown;
+            let listing_candidates = !subst.value.is_empty() && candidates.len() <= max_candidates;
+
+            if !listing_candidates {
+                if is_trivial(&subst) {
+                    break Guidance::Unknown;
+                }
+
+                if !simplified_answers
+                    .any_future_answer(|ref mut new_subst| new_subst.may_invalidate(&subst))
+                {
+                    break Guidance::Definite(subst);
+                }
             }
 
-            if !simplified_answers
-                .any_future_answer(|ref mut new_subst| new_subst.may_invalidate(&subst))
+            if self.max_answers.map_or(false, |max| num_answers >= max)
+                && simplified_answers.peek_answer().is_some()
             {
-                break Guidance::Definite(subst);
+                if is_trivial(&subst) {
+                    return Some(Solution::MoreAnswers(Guidance::Unknown));
+                }
+                return Some(Solution::MoreAnswers(Guidance::Suggested(subst)));
             }
 
             match simplified_answers.next_answer() {
                 Some(answer1) => {
+                    num_answers += 1;
                     subst = merge_into_guidance(root_goal, subst, &answer1.subst);
+                    if listing_candidates {
+                        candidates.push(answer1.subst.map(|cs| cs.subst));
+                    }
                 }
 
                 None => {
+                    if listing_candidates && candidates.len() > 1 {
+                        if self.deterministic {
+                            candidates.sort_by_cached_key(|candidate| Fingerprint::of(candidate));
+                        }
+                        break Guidance::Candidates {
+                            generalization: subst,
+                            candidates,
+                        };
+                    }
+                    if is_trivial(&subst) {
+                        break Guidance::Unknown;
+                    }
                     break Guidance::Definite(subst);
                 }
             }
@@ -93,38 +146,39 @@ fn merge_into_guidance(
     } = answer;
 
     // Collect the types that the two substitutions have in
-    // common.
-    let aggr_parameters: Vec<_> = guidance
-        .value
-        .parameters
-        .iter()
-        .zip(&subst1.parameters)
-        .enumerate()
-        .map(|(index, (value, value1))| {
-            // We have two values for some variable X that
-            // appears in the root goal. Find out the universe
-            // of X.
-            let universe = root_goal.binders[index].into_inner();
-
-            let ty = match value {
-                ParameterKind::Ty(ty) => ty,
-                ParameterKind::Lifetime(_) => {
-                    // Ignore the lifetimes from the substitution: we're just
-                    // creating guidance here anyway.
-                    return infer.new_variable(universe).to_lifetime().cast();
-                }
-            };
-
-            let ty1 = value1.assert_ty_ref();
-
-            // Combine the two types into a new type.
-            let mut aggr = AntiUnifier {
-                infer: &mut infer,
-                universe,
-            };
-            aggr.aggregate_tys(&ty, ty1).cast()
-        })
-        .collect();
+    // common. We use a single anti-unifier for all of them, so that
+    // e.g. `(?0 := u32, ?1 := u32)` and `(?0 := i32, ?1 := i32)`
+    // become `(?0 := ?X, ?1 := ?X)`.
+    let aggr_parameters: Arc<[Parameter]> = {
+        let mut aggr = AntiUnifier::new(&mut infer);
+        guidance
+            .value
+            .parameters
+            .iter()
+            .zip(subst1.parameters.iter())
+            .enumerate()
+            .map(|(index, (value, value1))| {
+                // We have two values for some variable X that
+                // appears in the root goal. Find out the universe
+                // of X.
+                aggr.universe = root_goal.binders[index].into_inner();
+
+                let ty = match value {
+                    ParameterKind::Ty(ty) => ty,
+                    ParameterKind::Lifetime(_) => {
+                        // Ignore the lifetimes from the substitution: we're just
+                        // creating guidance here anyway.
+                        return aggr.new_lifetime_variable().cast();
+                    }
+                };
+
+                let ty1 = value1.assert_ty_ref();
+
+                // Combine the two types into a new type.
+                aggr.aggregate_tys(&ty, ty1).cast()
+            })
+            .collect()
+    };
 
     let aggr_subst = Substitution {
         parameters: aggr_parameters,
@@ -146,7 +200,7 @@ fn is_trivial(subst: &Canonical<Substitution>) -> bool {
             // variables.
             ParameterKind::Ty(t) => match t.bound() {
                 None => false,
-                Some(depth) => depth == index,
+                Some(depth) => depth.depth() == index,
             },
 
             // And no lifetime mappings. (This is too strict, but we never
@@ -158,55 +212,90 @@ fn is_trivial(subst: &Canonical<Substitution>) -> bool {
 /// [Anti-unification] is the act of taking two things that do not
 /// unify and finding a minimal generarlization of them. So for
 /// example `Vec<u32>` anti-unified with `Vec<i32>` might be
-/// `Vec<?X>`. This is a **very simplistic** anti-unifier.
+/// `Vec<?X>`. This is a **very simplistic** anti-unifier, though it
+/// does generalize the same pair of types to the same variable each
+/// time, so that `(u32, u32)` and `(i32, i32)` become `(?X, ?X)`
+/// rather than `(?X, ?Y)`.
 ///
 /// [Anti-unification]: https://en.wikipedia.org/wiki/Anti-unification_(computer_science)
 struct AntiUnifier<'infer> {
     infer: &'infer mut InferenceTable,
+
+    /// The universe of the variables we create.
     universe: UniverseIndex,
+
+    /// The variable we created for each pair of types we had to
+    /// generalize, in each universe.
+    generalizations: HashMap<(UniverseIndex, Ty, Ty), Ty>,
 }
 
 impl<'infer> AntiUnifier<'infer> {
+    fn new(infer: &'infer mut InferenceTable) -> Self {
+        AntiUnifier {
+            infer,
+            universe: UniverseIndex::root(),
+            generalizations: HashMap::new(),
+        }
+    }
+
     fn aggregate_tys(&mut self, ty0: &Ty, ty1: &Ty) -> Ty {
-        match (ty0, ty1) {
-            // If we see bound things on either side, just drop in a
-            // fresh variable. This means we will sometimes
-            // overgeneralize.  So for example if we have two
-            // solutions that are both `(X, X)`, we just produce `(Y,
-            // Z)` in all cases.
-            (Ty::InferenceVar(_), Ty::InferenceVar(_)) => self.new_variable(),
+        match (ty0.data(), ty1.data()) {
+            // If we see variables on both sides, drop in a variable
+            // standing for that pair. So for example if we have two
+            // solutions that are `(X, X)` and `(Y, Y)`, we produce
+            // `(Z, Z)`.
+            (TyData::InferenceVar(_), TyData::InferenceVar(_))
+            | (TyData::BoundVar(_), TyData::BoundVar(_)) => self.generalize(ty0, ty1),
 
             // Ugh. Aggregating two types like `for<'a> fn(&'a u32,
             // &'a u32)` and `for<'a, 'b> fn(&'a u32, &'b u32)` seems
             // kinda' hard. Don't try to be smart for now, just plop a
-            // variable in there and be done with it.
-            (Ty::BoundVar(_), Ty::BoundVar(_)) | (Ty::ForAll(_), Ty::ForAll(_)) => {
-                self.new_variable()
+            // variable in there and be done with it. Same goes for
+            // object types.
+            (TyData::ForAll(_), TyData::ForAll(_)) | (TyData::Dyn(_), TyData::Dyn(_)) => {
+                self.generalize(ty0, ty1)
             }
 
-            (Ty::Apply(apply1), Ty::Apply(apply2)) => {
-                self.aggregate_application_tys(apply1, apply2)
-            }
+            (TyData::Apply(apply1), TyData::Apply(apply2)) => self
+                .aggregate_application_tys(apply1, apply2)
+                .unwrap_or_else(|| self.generalize(ty0, ty1)),
 
-            (Ty::Projection(apply1), Ty::Projection(apply2)) => {
-                self.aggregate_projection_tys(apply1, apply2)
-            }
+            (TyData::Projection(apply1), TyData::Projection(apply2)) => self
+                .aggregate_projection_tys(apply1, apply2)
+                .unwrap_or_else(|| self.generalize(ty0, ty1)),
 
-            (Ty::UnselectedProjection(apply1), Ty::UnselectedProjection(apply2)) => {
-                self.aggregate_unselected_projection_tys(apply1, apply2)
-            }
+            (TyData::UnselectedProjection(apply1), TyData::UnselectedProjection(apply2)) => self
+                .aggregate_unselected_projection_tys(apply1, apply2)
+                .unwrap_or_else(|| self.generalize(ty0, ty1)),
 
             // Mismatched base kinds.
-            (Ty::InferenceVar(_), _)
-            | (Ty::BoundVar(_), _)
-            | (Ty::ForAll(_), _)
-            | (Ty::Apply(_), _)
-            | (Ty::Projection(_), _)
-            | (Ty::UnselectedProjection(_), _) => self.new_variable(),
+            (TyData::InferenceVar(_), _)
+            | (TyData::BoundVar(_), _)
+            | (TyData::ForAll(_), _)
+            | (TyData::Dyn(_), _)
+            | (TyData::Apply(_), _)
+            | (TyData::Projection(_), _)
+            | (TyData::UnselectedProjection(_), _) => self.generalize(ty0, ty1),
         }
     }
 
-    fn aggregate_application_tys(&mut self, apply1: &ApplicationTy, apply2: &ApplicationTy) -> Ty {
+    /// Returns the variable standing for `ty0` and `ty1`, creating it
+    /// if this is the first time we generalize these two types.
+    fn generalize(&mut self, ty0: &Ty, ty1: &Ty) -> Ty {
+        let key = (self.universe, ty0.clone(), ty1.clone());
+        if let Some(ty) = self.generalizations.get(&key) {
+            return ty.clone();
+        }
+        let ty = self.new_variable();
+        self.generalizations.insert(key, ty.clone());
+        ty
+    }
+
+    fn aggregate_application_tys(
+        &mut self,
+        apply1: &ApplicationTy,
+        apply2: &ApplicationTy,
+    ) -> Option<Ty> {
         let ApplicationTy {
             name: name1,
             parameters: parameters1,
@@ -217,11 +306,14 @@ impl<'infer> AntiUnifier<'infer> {
         } = apply2;
 
         self.aggregate_name_and_substs(name1, parameters1, name2, parameters2)
-            .map(|(&name, parameters)| Ty::Apply(ApplicationTy { name, parameters }))
-            .unwrap_or_else(|| self.new_variable())
+            .map(|(&name, parameters)| TyData::Apply(ApplicationTy { name, parameters }).intern())
     }
 
-    fn aggregate_projection_tys(&mut self, proj1: &ProjectionTy, proj2: &ProjectionTy) -> Ty {
+    fn aggregate_projection_tys(
+        &mut self,
+        proj1: &ProjectionTy,
+        proj2: &ProjectionTy,
+    ) -> Option<Ty> {
         let ProjectionTy {
             associated_ty_id: name1,
             parameters: parameters1,
@@ -233,19 +325,19 @@ impl<'infer> AntiUnifier<'infer> {
 
         self.aggregate_name_and_substs(name1, parameters1, name2, parameters2)
             .map(|(&associated_ty_id, parameters)| {
-                Ty::Projection(ProjectionTy {
+                TyData::Projection(ProjectionTy {
                     associated_ty_id,
                     parameters,
                 })
+                .intern()
             })
-            .unwrap_or_else(|| self.new_variable())
     }
 
     fn aggregate_unselected_projection_tys(
         &mut self,
         proj1: &UnselectedProjectionTy,
         proj2: &UnselectedProjectionTy,
-    ) -> Ty {
+    ) -> Option<Ty> {
         let UnselectedProjectionTy {
             type_name: name1,
             parameters: parameters1,
@@ -257,12 +349,12 @@ impl<'infer> AntiUnifier<'infer> {
 
         self.aggregate_name_and_substs(name1, parameters1, name2, parameters2)
             .map(|(&type_name, parameters)| {
-                Ty::UnselectedProjection(UnselectedProjectionTy {
+                TyData::UnselectedProjection(UnselectedProjectionTy {
                     type_name,
                     parameters,
                 })
+                .intern()
             })
-            .unwrap_or_else(|| self.new_variable())
     }
 
     fn aggregate_name_and_substs<N>(
@@ -321,13 +413,17 @@ impl<'infer> AntiUnifier<'infer> {
 
             (Lifetime::BoundVar(_), _) | (_, Lifetime::BoundVar(_)) => self.new_lifetime_variable(),
 
-            (Lifetime::Placeholder(_), Lifetime::Placeholder(_)) => {
+            (Lifetime::Placeholder(_), Lifetime::Placeholder(_))
+            | (Lifetime::Erased, Lifetime::Erased) => {
                 if l1 == l2 {
                     *l1
                 } else {
                     self.new_lifetime_variable()
                 }
             }
+
+            (Lifetime::Placeholder(_), Lifetime::Erased)
+            | (Lifetime::Erased, Lifetime::Placeholder(_)) => self.new_lifetime_variable(),
         }
     }
 
@@ -344,10 +440,7 @@ impl<'infer> AntiUnifier<'infer> {
 #[test]
 fn vec_i32_vs_vec_u32() {
     let mut infer = InferenceTable::new();
-    let mut anti_unifier = AntiUnifier {
-        infer: &mut infer,
-        universe: UniverseIndex::root(),
-    };
+    let mut anti_unifier = AntiUnifier::new(&mut infer);
 
     let ty = anti_unifier.aggregate_tys(
         &ty!(apply (item 0) (apply (item 1))),
@@ -360,10 +453,7 @@ fn vec_i32_vs_vec_u32() {
 #[test]
 fn vec_i32_vs_vec_i32() {
     let mut infer = InferenceTable::new();
-    let mut anti_unifier = AntiUnifier {
-        infer: &mut infer,
-        universe: UniverseIndex::root(),
-    };
+    let mut anti_unifier = AntiUnifier::new(&mut infer);
 
     let ty = anti_unifier.aggregate_tys(
         &ty!(apply (item 0) (apply (item 1))),
@@ -376,10 +466,7 @@ fn vec_i32_vs_vec_i32() {
 #[test]
 fn vec_x_vs_vec_y() {
     let mut infer = InferenceTable::new();
-    let mut anti_unifier = AntiUnifier {
-        infer: &mut infer,
-        universe: UniverseIndex::root(),
-    };
+    let mut anti_unifier = AntiUnifier::new(&mut infer);
 
     // Note that the `var 0` and `var 1` in these types would be
     // referring to canonicalized free variables, not variables in
@@ -392,3 +479,18 @@ fn vec_x_vs_vec_y() {
     // But this `var 0` is from `infer.
     assert_eq!(ty!(apply (item 0) (infer 0)), ty);
 }
+
+/// Test the equivalent of `Pair<X, X>` vs `Pair<Y, Y>`
+#[test]
+fn pair_x_x_vs_pair_y_y() {
+    let mut infer = InferenceTable::new();
+    let mut anti_unifier = AntiUnifier::new(&mut infer);
+
+    let ty = anti_unifier.aggregate_tys(
+        &ty!(apply (item 0) (infer 0) (infer 0)),
+        &ty!(apply (item 0) (infer 1) (infer 1)),
+    );
+
+    // Both parameters are generalized to the same variable.
+    assert_eq!(ty!(apply (item 0) (infer 0) (infer 0)), ty);
+}
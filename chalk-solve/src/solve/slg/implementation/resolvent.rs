@@ -93,8 +93,15 @@ impl context::ResolventOps<SlgContext, SlgContext> for TruncatingInferenceTable
         debug!("consequence = {:?}", consequence);
         debug!("conditions = {:?}", conditions);
 
-        // Unify the selected literal Li with C'.
-        let unification_result = self.infer.unify(environment, goal, &consequence)?;
+        // Unify the selected literal Li with C'. If that fails, the
+        // snapshot still has to be closed.
+        let unification_result = match self.infer.unify(environment, goal, &consequence) {
+            Ok(result) => result,
+            Err(err) => {
+                self.infer.rollback_to(snapshot);
+                return Err(err);
+            }
+        };
 
         // Final X-clause that we will return.
         let mut ex_clause = ExClause {
@@ -273,16 +280,17 @@ impl<'t> AnswerSubstitutor<'t> {
 
     fn unify_free_answer_var(
         &mut self,
-        answer_depth: usize,
+        answer_depth: DebruijnIndex,
         pending: ParameterKind<&Ty, &Lifetime>,
     ) -> Fallible<bool> {
-        // This variable is bound in the answer, not free, so it
-        // doesn't represent a reference into the answer substitution.
-        if answer_depth < self.answer_binders {
-            return Ok(false);
-        }
+        let answer_index = match answer_depth.shifted_out(self.answer_binders) {
+            Some(answer_index) => answer_index,
+            // This variable is bound in the answer, not free, so it
+            // doesn't represent a reference into the answer substitution.
+            None => return Ok(false),
+        };
 
-        let answer_param = &self.answer_subst.parameters[answer_depth - self.answer_binders];
+        let answer_param = &self.answer_subst.parameters[answer_index.depth()];
 
         let pending_shifted = &pending
             .shifted_out(self.pending_binders)
@@ -308,7 +316,12 @@ impl<'t> AnswerSubstitutor<'t> {
     /// that case, there should be a corresponding bound variable in
     /// the pending goal. This bit of code just checks that latter
     /// case.
-    fn assert_matching_vars(&mut self, answer_depth: usize, pending_depth: usize) -> Fallible<()> {
+    fn assert_matching_vars(
+        &mut self,
+        answer_depth: DebruijnIndex,
+        pending_depth: DebruijnIndex,
+    ) -> Fallible<()> {
+        let (answer_depth, pending_depth) = (answer_depth.depth(), pending_depth.depth());
         assert!(answer_depth < self.answer_binders);
         assert!(pending_depth < self.pending_binders);
         assert_eq!(
@@ -329,48 +342,53 @@ impl<'t> Zipper for AnswerSubstitutor<'t> {
         // "inputs" to the subgoal table. We need to extract the
         // resulting answer that the subgoal found and unify it with
         // the value from our "pending subgoal".
-        if let Ty::BoundVar(answer_depth) = answer {
-            if self.unify_free_answer_var(*answer_depth, ParameterKind::Ty(pending))? {
+        if let Some(answer_depth) = answer.bound() {
+            if self.unify_free_answer_var(answer_depth, ParameterKind::Ty(pending))? {
                 return Ok(());
             }
         }
 
         // Otherwise, the answer and the selected subgoal ought to be a perfect match for
         // one another.
-        match (answer, pending) {
-            (Ty::BoundVar(answer_depth), Ty::BoundVar(pending_depth)) => {
+        match (answer.data(), pending.data()) {
+            (TyData::BoundVar(answer_depth), TyData::BoundVar(pending_depth)) => {
                 self.assert_matching_vars(*answer_depth, *pending_depth)
             }
 
-            (Ty::Apply(answer), Ty::Apply(pending)) => Zip::zip_with(self, answer, pending),
+            (TyData::Apply(answer), TyData::Apply(pending)) => {
+                Zip::zip_with(self, answer, pending)
+            }
 
-            (Ty::Projection(answer), Ty::Projection(pending)) => {
+            (TyData::Projection(answer), TyData::Projection(pending)) => {
                 Zip::zip_with(self, answer, pending)
             }
 
-            (Ty::UnselectedProjection(answer), Ty::UnselectedProjection(pending)) => {
+            (TyData::UnselectedProjection(answer), TyData::UnselectedProjection(pending)) => {
                 Zip::zip_with(self, answer, pending)
             }
 
-            (Ty::ForAll(answer), Ty::ForAll(pending)) => {
-                self.answer_binders += answer.num_binders;
-                self.pending_binders += pending.num_binders;
+            (TyData::ForAll(answer), TyData::ForAll(pending)) => {
+                self.answer_binders += answer.binders.len();
+                self.pending_binders += pending.binders.len();
                 Zip::zip_with(self, &answer.ty, &pending.ty)?;
-                self.answer_binders -= answer.num_binders;
-                self.pending_binders -= pending.num_binders;
+                self.answer_binders -= answer.binders.len();
+                self.pending_binders -= pending.binders.len();
                 Ok(())
             }
 
-            (Ty::InferenceVar(_), _) | (_, Ty::InferenceVar(_)) => panic!(
+            (TyData::Dyn(answer), TyData::Dyn(pending)) => Zip::zip_with(self, answer, pending),
+
+            (TyData::InferenceVar(_), _) | (_, TyData::InferenceVar(_)) => panic!(
                 "unexpected inference var in answer `{:?}` or pending goal `{:?}`",
                 answer, pending,
             ),
 
-            (Ty::BoundVar(_), _)
-            | (Ty::Apply(_), _)
-            | (Ty::Projection(_), _)
-            | (Ty::UnselectedProjection(_), _)
-            | (Ty::ForAll(_), _) => panic!(
+            (TyData::BoundVar(_), _)
+            | (TyData::Apply(_), _)
+            | (TyData::Projection(_), _)
+            | (TyData::UnselectedProjection(_), _)
+            | (TyData::ForAll(_), _)
+            | (TyData::Dyn(_), _) => panic!(
                 "structural mismatch between answer `{:?}` and pending goal `{:?}`",
                 answer, pending,
             ),
@@ -393,7 +411,8 @@ impl<'t> Zipper for AnswerSubstitutor<'t> {
                 self.assert_matching_vars(*answer_depth, *pending_depth)
             }
 
-            (Lifetime::Placeholder(_), Lifetime::Placeholder(_)) => {
+            (Lifetime::Placeholder(_), Lifetime::Placeholder(_))
+            | (Lifetime::Erased, Lifetime::Erased) => {
                 assert_eq!(answer, pending);
                 Ok(())
             }
@@ -403,7 +422,9 @@ impl<'t> Zipper for AnswerSubstitutor<'t> {
                 answer, pending,
             ),
 
-            (Lifetime::BoundVar(_), _) | (Lifetime::Placeholder(_), _) => panic!(
+            (Lifetime::BoundVar(_), _)
+            | (Lifetime::Placeholder(_), _)
+            | (Lifetime::Erased, _) => panic!(
                 "structural mismatch between answer `{:?}` and pending goal `{:?}`",
                 answer, pending,
             ),
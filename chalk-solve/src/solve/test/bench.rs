@@ -104,7 +104,7 @@ forall<T> {
 fn cycley_slg(b: &mut Bencher) {
     run_bench(
         CYCLEY,
-        SolverChoice::SLG { max_size: 20 },
+        SolverChoice::slg().max_size(20).build(),
         CYCLEY_GOAL,
         b,
         "Unique",
@@ -0,0 +1,290 @@
+//! Re-checking the solutions of the solver. The answer of a `Unique`
+//! solution is substituted back into its goal, which is then proven
+//! again by a simple evaluator: a depth-first search through the
+//! program clauses, with no tabling, caching or truncation. If the
+//! evaluator finds that the goal does not hold, the solver is unsound,
+//! and the goal is reported as a `SoundnessBug`.
+//!
+//! The evaluator gives up on goals that it would have to search too
+//! deeply for (such as those that only hold coinductively) and on goals
+//! it cannot handle (custom goals, or negated goals with variables in
+//! them); the solutions to those are taken on trust.
+
+use crate::infer::InferenceTable;
+use crate::solve::{elaborate, Solution};
+use chalk_ir::cast::Cast;
+use chalk_ir::*;
+use std::fmt;
+use std::sync::Arc;
+
+/// Goals nested more deeply than this in the search are not evaluated.
+const MAX_DEPTH: usize = 32;
+
+/// The evaluator gives up after trying this many clauses in all.
+const MAX_STEPS: usize = 10_000;
+
+/// A goal that the solver found a unique solution to, but which does
+/// not hold with that solution.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SoundnessBug {
+    pub goal: UCanonical<InEnvironment<Goal>>,
+    pub solution: Solution,
+}
+
+impl fmt::Display for SoundnessBug {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "internal error: the solver found `{}` for `{:?}`, but the goal does not hold with it",
+            self.solution, self.goal.canonical.value.goal
+        )
+    }
+}
+
+/// Checks that `goal` holds with `solution`, if it is `Unique`. Other
+/// solutions are not checked.
+pub fn verify_solution(
+    program: &dyn ProgramClauseSet,
+    goal: &UCanonical<InEnvironment<Goal>>,
+    solution: &Solution,
+) -> Result<(), SoundnessBug> {
+    let answer = match solution {
+        Solution::Unique(answer) => answer,
+        _ => return Ok(()),
+    };
+    let bug = || SoundnessBug {
+        goal: goal.clone(),
+        solution: solution.clone(),
+    };
+
+    let (mut infer, subst, InEnvironment { environment, goal: value }) =
+        InferenceTable::from_canonical(goal.universes, &goal.canonical);
+    let ConstrainedSubst {
+        subst: answer_subst,
+        constraints: _,
+    } = infer.instantiate_canonical(answer);
+    infer
+        .unify(&environment, &subst.parameters[..], &answer_subst.parameters[..])
+        .map_err(|_| bug())?;
+
+    let mut evaluator = Evaluator { program, steps: 0 };
+    match evaluator.solve(&mut infer, vec![(InEnvironment::new(&environment, value), 0)]) {
+        Outcome::Failed => Err(bug()),
+        Outcome::Proven | Outcome::Unknown => Ok(()),
+    }
+}
+
+/// The outcome of evaluating a list of goals.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Outcome {
+    /// The goals all hold, for some values of their variables.
+    Proven,
+
+    /// There are no values of their variables for which they all hold.
+    Failed,
+
+    /// The evaluator gave up before finding out.
+    Unknown,
+}
+
+struct Evaluator<'p> {
+    program: &'p dyn ProgramClauseSet,
+    steps: usize,
+}
+
+impl<'p> Evaluator<'p> {
+    /// Proves all of `goals`, each along with its depth in the search.
+    /// The goals are proven from the last to the first, so that the
+    /// variables that the last one binds are seen by the others, and the
+    /// search backtracks into it if they fail.
+    fn solve(
+        &mut self,
+        infer: &mut InferenceTable,
+        mut goals: Vec<(InEnvironment<Goal>, usize)>,
+    ) -> Outcome {
+        let (InEnvironment { environment, goal }, depth) = match goals.pop() {
+            Some(goal) => goal,
+            None => return Outcome::Proven,
+        };
+        let push = |goals: &mut Vec<_>, environment: &Arc<Environment>, goal: Goal| {
+            goals.push((InEnvironment::new(environment, goal), depth))
+        };
+
+        match goal {
+            Goal::Quantified(QuantifierKind::ForAll, subgoal) => {
+                let subgoal = infer.instantiate_binders_universally(&subgoal);
+                push(&mut goals, &environment, *subgoal);
+            }
+            Goal::Quantified(QuantifierKind::Exists, subgoal) => {
+                let subgoal = infer.instantiate_binders_existentially(&subgoal);
+                push(&mut goals, &environment, *subgoal);
+            }
+            Goal::Implies(clauses, subgoal) => {
+                let environment = elaborate::add_hypotheses(&environment, clauses);
+                push(&mut goals, &environment, *subgoal);
+            }
+            Goal::And(left, right) => {
+                push(&mut goals, &environment, *right);
+                push(&mut goals, &environment, *left);
+            }
+            Goal::All(conjuncts) => {
+                for conjunct in conjuncts.into_iter().rev() {
+                    push(&mut goals, &environment, conjunct);
+                }
+            }
+            Goal::Any(alternatives) => {
+                let alternatives = alternatives.into_iter().map(|alternative| {
+                    let mut goals = goals.clone();
+                    push(&mut goals, &environment, alternative);
+                    goals
+                });
+                return self.solve_any(infer, alternatives);
+            }
+            Goal::Not(subgoal) => {
+                let negated = InEnvironment::new(&environment, *subgoal);
+                return self.solve_not(infer, negated, depth, goals);
+            }
+            Goal::Leaf(LeafGoal::EqGoal(EqGoal { a, b })) => {
+                match infer.unify(&environment, &a, &b) {
+                    Ok(result) => {
+                        for subgoal in result.goals {
+                            goals.push((subgoal.cast(), depth));
+                        }
+                    }
+                    Err(_) => return Outcome::Failed,
+                }
+            }
+            // Region constraints are not checked.
+            Goal::Leaf(LeafGoal::OutlivesGoal(_)) => {}
+            Goal::Leaf(LeafGoal::DomainGoal(DomainGoal::Custom(_))) => return Outcome::Unknown,
+            Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) => {
+                return self.solve_domain_goal(infer, &environment, &domain_goal, depth, goals);
+            }
+            Goal::CannotProve(()) => return Outcome::Unknown,
+        }
+
+        self.solve(infer, goals)
+    }
+
+    /// Tries each list of goals in turn, until one of them is proven.
+    fn solve_any(
+        &mut self,
+        infer: &mut InferenceTable,
+        alternatives: impl Iterator<Item = Vec<(InEnvironment<Goal>, usize)>>,
+    ) -> Outcome {
+        let mut outcome = Outcome::Failed;
+        for goals in alternatives {
+            let snapshot = infer.snapshot();
+            let alternative = self.solve(infer, goals);
+            infer.rollback_to(snapshot);
+            match alternative {
+                Outcome::Proven => return Outcome::Proven,
+                Outcome::Unknown => outcome = Outcome::Unknown,
+                Outcome::Failed => {}
+            }
+        }
+        outcome
+    }
+
+    /// Proves `goals`, given that `negated` must have no solution. That
+    /// is only decided for goals without variables: whether a goal with
+    /// variables has no solution depends on what they turn out to be.
+    fn solve_not(
+        &mut self,
+        infer: &mut InferenceTable,
+        negated: InEnvironment<Goal>,
+        depth: usize,
+        goals: Vec<(InEnvironment<Goal>, usize)>,
+    ) -> Outcome {
+        let negation = if infer.canonicalize(&negated).free_vars.is_empty() {
+            let snapshot = infer.snapshot();
+            let outcome = self.solve(infer, vec![(negated, depth + 1)]);
+            infer.rollback_to(snapshot);
+            match outcome {
+                Outcome::Proven => return Outcome::Failed,
+                Outcome::Failed => Outcome::Proven,
+                Outcome::Unknown => Outcome::Unknown,
+            }
+        } else {
+            Outcome::Unknown
+        };
+
+        match (negation, self.solve(infer, goals)) {
+            (Outcome::Unknown, Outcome::Proven) => Outcome::Unknown,
+            (_, outcome) => outcome,
+        }
+    }
+
+    /// Proves `domain_goal` with each clause that could prove it in turn,
+    /// then the rest of `goals`.
+    fn solve_domain_goal(
+        &mut self,
+        infer: &mut InferenceTable,
+        environment: &Arc<Environment>,
+        domain_goal: &DomainGoal,
+        depth: usize,
+        goals: Vec<(InEnvironment<Goal>, usize)>,
+    ) -> Outcome {
+        if depth >= MAX_DEPTH {
+            return Outcome::Unknown;
+        }
+
+        let clauses: Vec<_> = environment
+            .clauses_for_goal(domain_goal)
+            .cloned()
+            .chain(self.program.program_clauses_for_goal(domain_goal))
+            .collect();
+
+        let mut outcome = Outcome::Failed;
+        for clause in clauses {
+            self.steps += 1;
+            if self.steps > MAX_STEPS {
+                return Outcome::Unknown;
+            }
+
+            let snapshot = infer.snapshot();
+            let applied =
+                self.apply_clause(infer, environment, domain_goal, &clause, depth, goals.clone());
+            infer.rollback_to(snapshot);
+            match applied {
+                Outcome::Proven => return Outcome::Proven,
+                Outcome::Unknown => outcome = Outcome::Unknown,
+                Outcome::Failed => {}
+            }
+        }
+        outcome
+    }
+
+    fn apply_clause(
+        &mut self,
+        infer: &mut InferenceTable,
+        environment: &Arc<Environment>,
+        domain_goal: &DomainGoal,
+        clause: &ProgramClause,
+        depth: usize,
+        mut goals: Vec<(InEnvironment<Goal>, usize)>,
+    ) -> Outcome {
+        let ProgramClauseImplication {
+            consequence,
+            conditions,
+        } = match clause {
+            ProgramClause::Implies(implication) => implication.clone(),
+            ProgramClause::ForAll(implication) => {
+                infer.instantiate_binders_existentially(implication)
+            }
+        };
+
+        let result = match infer.unify(environment, domain_goal, &consequence) {
+            Ok(result) => result,
+            Err(_) => return Outcome::Failed,
+        };
+        for subgoal in result.goals {
+            goals.push((subgoal.cast(), depth + 1));
+        }
+        for condition in conditions.into_iter().rev() {
+            goals.push((InEnvironment::new(environment, condition), depth + 1));
+        }
+
+        self.solve(infer, goals)
+    }
+}
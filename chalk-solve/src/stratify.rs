@@ -0,0 +1,245 @@
+//! Stratification checking. Negation is solved as negation-as-failure,
+//! which only has the expected meaning when no predicate depends
+//! negatively on itself: given `P :- not { Q }` and `Q :- not { P }`,
+//! neither `P` nor `Q` is true or false, and the solver can only
+//! report an ambiguous answer (with delayed literals). A program is
+//! *stratified* if it has no such negative cycle.
+//!
+//! The analysis is done on predicates rather than on goals: `T: Foo`
+//! and `u32: Foo` are the same predicate, `Implemented(Foo)`. It is
+//! therefore conservative, and may report cycles that the solver would
+//! never run into for the goals of interest.
+
+use chalk_ir::*;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+
+/// A domain goal with its parameters erased.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Predicate {
+    Implemented(ItemId),
+    ProjectionEq(ItemId),
+    ConstEvaluatable(Identifier),
+    WellFormedTrait(ItemId),
+    WellFormedTy,
+    FromEnvTrait(ItemId),
+    FromEnvTy,
+    Normalize(ItemId),
+    UnselectedNormalize(Identifier),
+    InScope(ItemId),
+    Derefs,
+    IsLocal,
+    IsUpstream,
+    IsFullyVisible,
+    LocalImplAllowed(ItemId),
+    Compatible,
+    DownstreamType,
+    IsSized,
+    NeedsDrop,
+    Custom(Identifier),
+}
+
+impl Predicate {
+    pub fn of(goal: &DomainGoal) -> Predicate {
+        match goal {
+            DomainGoal::Holds(WhereClause::Implemented(trait_ref)) => {
+                Predicate::Implemented(trait_ref.trait_id)
+            }
+            DomainGoal::Holds(WhereClause::ProjectionEq(projection_eq)) => {
+                Predicate::ProjectionEq(projection_eq.projection.associated_ty_id)
+            }
+            DomainGoal::Holds(WhereClause::ConstEvaluatable(evaluatable)) => {
+                Predicate::ConstEvaluatable(evaluatable.const_name)
+            }
+            DomainGoal::WellFormed(WellFormed::Trait(trait_ref)) => {
+                Predicate::WellFormedTrait(trait_ref.trait_id)
+            }
+            DomainGoal::WellFormed(WellFormed::Ty(_)) => Predicate::WellFormedTy,
+            DomainGoal::FromEnv(FromEnv::Trait(trait_ref)) => {
+                Predicate::FromEnvTrait(trait_ref.trait_id)
+            }
+            DomainGoal::FromEnv(FromEnv::Ty(_)) => Predicate::FromEnvTy,
+            DomainGoal::Normalize(normalize) => {
+                Predicate::Normalize(normalize.projection.associated_ty_id)
+            }
+            DomainGoal::UnselectedNormalize(normalize) => {
+                Predicate::UnselectedNormalize(normalize.projection.type_name)
+            }
+            DomainGoal::InScope(trait_id) => Predicate::InScope(*trait_id),
+            DomainGoal::Derefs(_) => Predicate::Derefs,
+            DomainGoal::IsLocal(_) => Predicate::IsLocal,
+            DomainGoal::IsUpstream(_) => Predicate::IsUpstream,
+            DomainGoal::IsFullyVisible(_) => Predicate::IsFullyVisible,
+            DomainGoal::LocalImplAllowed(trait_ref) => {
+                Predicate::LocalImplAllowed(trait_ref.trait_id)
+            }
+            DomainGoal::Compatible(()) => Predicate::Compatible,
+            DomainGoal::DownstreamType(_) => Predicate::DownstreamType,
+            DomainGoal::IsSized(_) => Predicate::IsSized,
+            DomainGoal::NeedsDrop(_) => Predicate::NeedsDrop,
+            DomainGoal::Custom(custom) => Predicate::Custom(custom.name),
+        }
+    }
+}
+
+/// A program clause for `from` with `to` among its conditions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Dependency {
+    pub from: Predicate,
+    pub to: Predicate,
+
+    /// True if `to` appears under a `not { .. }`.
+    pub negative: bool,
+}
+
+/// A cycle of dependencies, the first of which is negative.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NegativeCycle {
+    pub dependencies: Vec<Dependency>,
+}
+
+impl NegativeCycle {
+    /// The predicate that depends negatively on itself.
+    pub fn predicate(&self) -> Predicate {
+        self.dependencies[0].from
+    }
+}
+
+/// Prints the cycle as a series of clauses, e.g. `Implemented(P) :-
+/// not { Implemented(Q) }; Implemented(Q) :- Implemented(P)`.
+impl fmt::Display for NegativeCycle {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{:?} depends negatively on itself: ", self.predicate())?;
+        for (index, dependency) in self.dependencies.iter().enumerate() {
+            if index > 0 {
+                write!(fmt, "; ")?;
+            }
+            if dependency.negative {
+                write!(fmt, "{:?} :- not {{ {:?} }}", dependency.from, dependency.to)?;
+            } else {
+                write!(fmt, "{:?} :- {:?}", dependency.from, dependency.to)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the negative cycles of the program, reporting at most one
+/// cycle for each group of mutually dependent predicates. The program
+/// is stratified if there are none.
+pub fn negative_cycles(env: &ProgramEnvironment) -> Vec<NegativeCycle> {
+    let graph = DependencyGraph::new(&env.program_clauses);
+
+    let mut cycles: Vec<NegativeCycle> = vec![];
+    for (&from, targets) in &graph.edges {
+        for (&to, &negative) in targets {
+            if !negative {
+                continue;
+            }
+            let reported = cycles.iter().any(|cycle| {
+                let predicate = cycle.predicate();
+                graph.path(from, predicate).is_some() && graph.path(predicate, from).is_some()
+            });
+            if reported {
+                continue;
+            }
+            if let Some(path) = graph.path(to, from) {
+                let mut dependencies = vec![Dependency { from, to, negative }];
+                dependencies.extend(path);
+                cycles.push(NegativeCycle { dependencies });
+            }
+        }
+    }
+    cycles
+}
+
+struct DependencyGraph {
+    /// For each predicate, the predicates its clauses depend on, and
+    /// whether any of them depends on it negatively.
+    edges: BTreeMap<Predicate, BTreeMap<Predicate, bool>>,
+}
+
+impl DependencyGraph {
+    fn new(clauses: &ProgramClauses) -> Self {
+        let mut graph = DependencyGraph {
+            edges: BTreeMap::new(),
+        };
+        for clause in clauses {
+            let implication = match clause {
+                ProgramClause::Implies(implication) => implication,
+                ProgramClause::ForAll(implication) => &implication.value,
+            };
+            let from = Predicate::of(&implication.consequence);
+            for condition in &implication.conditions {
+                graph.add_goal(from, condition, false);
+            }
+        }
+        graph
+    }
+
+    fn add_goal(&mut self, from: Predicate, goal: &Goal, negative: bool) {
+        match goal {
+            Goal::Quantified(_, subgoal) => self.add_goal(from, &subgoal.value, negative),
+            // The hypotheses of an implication are facts, not conditions.
+            Goal::Implies(_, subgoal) => self.add_goal(from, subgoal, negative),
+            Goal::And(left, right) => {
+                self.add_goal(from, left, negative);
+                self.add_goal(from, right, negative);
+            }
+            Goal::All(goals) | Goal::Any(goals) => {
+                for goal in goals {
+                    self.add_goal(from, goal, negative);
+                }
+            }
+            Goal::Not(subgoal) => self.add_goal(from, subgoal, true),
+            Goal::Leaf(LeafGoal::DomainGoal(domain_goal)) => {
+                let edge = self
+                    .edges
+                    .entry(from)
+                    .or_insert_with(BTreeMap::new)
+                    .entry(Predicate::of(domain_goal))
+                    .or_insert(false);
+                *edge |= negative;
+            }
+            Goal::Leaf(LeafGoal::EqGoal(_))
+            | Goal::Leaf(LeafGoal::OutlivesGoal(_))
+            | Goal::CannotProve(()) => {}
+        }
+    }
+
+    /// A shortest series of dependencies leading from `from` to `to`,
+    /// if there is one (the empty series if they are the same).
+    fn path(&self, from: Predicate, to: Predicate) -> Option<Vec<Dependency>> {
+        let mut parents: BTreeMap<Predicate, Predicate> = BTreeMap::new();
+        let mut visited = BTreeSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(from);
+        queue.push_back(from);
+
+        while let Some(predicate) = queue.pop_front() {
+            if predicate == to {
+                let mut path = vec![];
+                let mut current = to;
+                while current != from {
+                    let parent = parents[&current];
+                    let negative = self.edges[&parent][&current];
+                    path.push(Dependency {
+                        from: parent,
+                        to: current,
+                        negative,
+                    });
+                    current = parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &target in self.edges.get(&predicate).into_iter().flat_map(|t| t.keys()) {
+                if visited.insert(target) {
+                    parents.insert(target, predicate);
+                    queue.push_back(target);
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,83 @@
+//! Solves each goal of a generated program (see `chalk_fuzz::generate`)
+//! with two solvers, and checks that they agree on whether it holds:
+//! yes, no, or maybe. Run with `cargo fuzz run differential`.
+//!
+//! There is only one solver (SLG) at the moment, so the two solvers
+//! use different search strategies, which should never change whether
+//! a goal holds. (The exact solutions may legitimately differ, e.g. in
+//! the guidance of ambiguous ones, so those are not compared.)
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+
+extern crate chalk;
+extern crate chalk_fuzz;
+extern crate chalk_ir;
+extern crate chalk_parse;
+extern crate chalk_solve;
+
+use std::sync::Arc;
+
+use chalk::db::ChalkDatabase;
+use chalk::query::LoweringDatabase;
+use chalk::rust_ir::lowering::LowerGoal;
+use chalk_solve::ext::GoalExt;
+use chalk_solve::solve::{SearchStrategy, Solution, SolverChoice};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Outcome {
+    Yes,
+    No,
+    Maybe,
+    Error,
+}
+
+fn classify<E>(result: Result<Option<Solution>, E>) -> Outcome {
+    match result {
+        Ok(Some(Solution::Unique(_))) => Outcome::Yes,
+        Ok(Some(_)) => Outcome::Maybe,
+        Ok(None) => Outcome::No,
+        Err(_) => Outcome::Error,
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let test_case = chalk_fuzz::generate(data);
+    let choices = [
+        SolverChoice::slg()
+            .search_strategy(SearchStrategy::DepthFirst)
+            .build(),
+        SolverChoice::slg()
+            .search_strategy(SearchStrategy::BreadthFirst)
+            .build(),
+    ];
+
+    let program_text = Arc::new(test_case.program.clone());
+    ChalkDatabase::with_program(program_text, choices[0], |db| {
+        let program = match db.checked_program() {
+            Ok(program) => program,
+            Err(_) => return,
+        };
+        let env = db.environment().unwrap();
+
+        chalk_ir::tls::set_current_program(&program, || {
+            for goal_text in &test_case.goals {
+                let goal = chalk_parse::parse_goal(goal_text)
+                    .and_then(|goal| goal.lower(&*program))
+                    .unwrap_or_else(|err| panic!("invalid goal `{}`: {}", goal_text, err))
+                    .into_peeled_goal();
+                let outcomes: Vec<_> = choices
+                    .iter()
+                    .map(|choice| classify(choice.solve_root_goal(&env, &goal)))
+                    .collect();
+                assert_eq!(
+                    outcomes[0], outcomes[1],
+                    "solvers disagree on `{}` in program:\n{}",
+                    goal_text, test_case.program
+                );
+            }
+        });
+    });
+});
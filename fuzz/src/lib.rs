@@ -0,0 +1,127 @@
+//! Generates small chalk programs, and goals to solve against them,
+//! from the bytes that the fuzzer provides. The programs draw on a
+//! fixed set of structs and traits, with a handful of impls between
+//! them (possibly generic, with where clauses), so that the goals have
+//! a fair chance of interesting solutions: unique, ambiguous, or none.
+//!
+//! Not every program generated is valid (e.g. the impls may overlap);
+//! fuzz targets should skip those that fail to lower.
+
+const STRUCTS: &[&str] = &["A", "B", "C"];
+const GENERIC_STRUCTS: &[&str] = &["Box", "Vec"];
+const TRAITS: &[&str] = &["Foo", "Bar", "Baz"];
+
+/// How deeply generic structs are nested in the types generated.
+const MAX_TYPE_DEPTH: usize = 3;
+
+const MAX_IMPLS: usize = 8;
+const MAX_GOALS: usize = 4;
+
+/// A program, along with the goals to solve against it.
+#[derive(Clone, Debug)]
+pub struct TestCase {
+    pub program: String,
+    pub goals: Vec<String>,
+}
+
+pub fn generate(data: &[u8]) -> TestCase {
+    let mut generator = Generator { data };
+
+    let mut program = String::new();
+    for name in STRUCTS {
+        program.push_str(&format!("struct {} {{ }}\n", name));
+    }
+    for name in GENERIC_STRUCTS {
+        program.push_str(&format!("struct {}<T> {{ }}\n", name));
+    }
+    for name in TRAITS {
+        if generator.below(4) == 0 {
+            program.push_str("#[auto] ");
+        }
+        program.push_str(&format!("trait {} {{ }}\n", name));
+    }
+    for _ in 0..generator.below(MAX_IMPLS + 1) {
+        program.push_str(&generator.generate_impl());
+        program.push('\n');
+    }
+
+    let goals = (0..=generator.below(MAX_GOALS))
+        .map(|_| generator.generate_goal())
+        .collect();
+    TestCase { program, goals }
+}
+
+struct Generator<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Generator<'a> {
+    /// The next byte of the input, or 0 once it is exhausted.
+    fn next(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte
+            }
+            None => 0,
+        }
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.next() as usize % n
+    }
+
+    fn pick(&mut self, names: &[&'static str]) -> &'static str {
+        names[self.below(names.len())]
+    }
+
+    /// A type, which may mention the type variable `var` if one is given.
+    fn generate_type(&mut self, depth: usize, var: Option<&str>) -> String {
+        let choices = if depth < MAX_TYPE_DEPTH { 3 } else { 2 };
+        match (self.below(choices), var) {
+            (1, Some(var)) => var.to_string(),
+            (2, _) => {
+                let name = self.pick(GENERIC_STRUCTS);
+                format!("{}<{}>", name, self.generate_type(depth + 1, var))
+            }
+            _ => self.pick(STRUCTS).to_string(),
+        }
+    }
+
+    fn generate_impl(&mut self) -> String {
+        let trait_name = self.pick(TRAITS);
+        let self_ty = self.generate_type(0, Some("X"));
+        let negative = if self.below(8) == 0 { "!" } else { "" };
+        if !self_ty.contains('X') {
+            return format!("impl {}{} for {} {{ }}", negative, trait_name, self_ty);
+        }
+
+        let where_clause = if negative.is_empty() && self.below(2) == 0 {
+            format!(" where X: {}", self.pick(TRAITS))
+        } else {
+            String::new()
+        };
+        format!(
+            "impl<X> {}{} for {}{} {{ }}",
+            negative, trait_name, self_ty, where_clause
+        )
+    }
+
+    fn generate_goal(&mut self) -> String {
+        let trait_name = self.pick(TRAITS);
+        match self.below(3) {
+            0 => format!("{}: {}", self.generate_type(0, None), trait_name),
+            1 => format!(
+                "exists<X> {{ {}: {} }}",
+                self.generate_type(0, Some("X")),
+                trait_name
+            ),
+            _ => format!(
+                "forall<X> {{ if (X: {}) {{ {}: {} }} }}",
+                self.pick(TRAITS),
+                self.generate_type(0, Some("X")),
+                trait_name
+            ),
+        }
+    }
+}
@@ -1,6 +1,7 @@
 extern crate chalk;
 extern crate chalk_engine;
 extern crate chalk_ir;
+extern crate chalk_macros;
 extern crate chalk_parse;
 extern crate chalk_solve;
 extern crate docopt;
@@ -12,22 +13,32 @@ extern crate serde_derive;
 #[macro_use]
 extern crate failure;
 
+use std::cell::RefCell;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::process::exit;
 use std::sync::Arc;
 
 use chalk::db::ChalkDatabase;
-use chalk::query::LoweringDatabase;
+use chalk::incremental::relower;
+use chalk::mem_stats::{self, Phase};
+use chalk::query::{ClauseFamilies, LoweringDatabase};
 use chalk::rust_ir;
 use chalk::rust_ir::lowering::*;
-use chalk_engine::fallible::NoSolution;
+use chalk_macros::profile;
 use chalk_solve::ext::*;
-use chalk_solve::solve::SolverChoice;
+use chalk_solve::solve::explain::explain_failure;
+use chalk_solve::solve::verify::verify_solution;
+use chalk_solve::solve::{OverflowKind, PartialSolution, Solver, SolverChoice};
+use chalk_solve::stratify::negative_cycles;
 use docopt::Docopt;
 use failure::Fallible;
 use rustyline::error::ReadlineError;
 
+#[cfg(feature = "mem-stats")]
+#[global_allocator]
+static ALLOCATOR: mem_stats::CountingAllocator = mem_stats::CountingAllocator;
+
 const USAGE: &'static str = "
 chalk repl
 
@@ -39,8 +50,15 @@ Options:
   --help              Show this screen.
   --program=PATH      Specifies the path to the `.chalk` file containing traits/impls.
   --goal=GOAL         Specifies a goal to evaluate (may be given more than once).
-  --overflow-depth=N  Specifies the overflow depth [default: 10].
-  --no-cache          Disable caching.
+  --max-size=N        Specifies the size at which goals are truncated [default: 10].
+  --overflow-depth=N  Specifies how deeply subgoals may be nested.
+  --no-cache          Disable caching of results between goals.
+  --erase-lifetimes   Treat all lifetimes as equal while solving.
+  --max-answers=N     Stop after examining N answers to a goal.
+  --proof             Print a proof tree for goals with a unique solution.
+  --explain           Explain why goals without a solution fail.
+  --verify            Check that unique solutions hold, reporting solver bugs.
+  --profile=PATH      Write a profile of the solver to PATH, as folded stacks for a flamegraph.
 ";
 
 /// This struct represents the various command line options available.
@@ -48,8 +66,15 @@ Options:
 struct Args {
     flag_program: Option<String>,
     flag_goal: Vec<String>,
-    flag_overflow_depth: usize,
+    flag_max_size: usize,
+    flag_overflow_depth: Option<usize>,
     flag_no_cache: bool,
+    flag_erase_lifetimes: bool,
+    flag_max_answers: Option<usize>,
+    flag_proof: bool,
+    flag_explain: bool,
+    flag_verify: bool,
+    flag_profile: Option<String>,
 }
 
 /// A loaded and parsed program.
@@ -57,6 +82,10 @@ struct Program {
     text: String,
     ir: Arc<rust_ir::Program>,
     env: Arc<chalk_ir::ProgramEnvironment>,
+
+    /// The solver used for all goals against this program, so that
+    /// work is shared between them (unless `--no-cache` is given).
+    solver: RefCell<Box<Solver>>,
 }
 
 impl Program {
@@ -66,9 +95,48 @@ impl Program {
     /// [`SolverChoice`]: struct.solve.SolverChoice.html
     fn new(text: String, solver_choice: SolverChoice) -> Fallible<Program> {
         ChalkDatabase::with_program(Arc::new(text.clone()), solver_choice, |db| {
-            let ir = db.checked_program().unwrap();
+            let ir = db.checked_program().map_err(|err| format_err!("{}", err))?;
             let env = db.environment().unwrap();
-            Ok(Program { text, ir, env })
+            chalk_ir::tls::set_current_program(&ir, || {
+                for cycle in db.negative_cycles().unwrap().iter() {
+                    eprintln!("warning: {}", cycle);
+                }
+            });
+            for cycle in db.supertrait_cycles().unwrap().iter() {
+                eprintln!("warning: {}", cycle);
+            }
+            let solver = RefCell::new(solver_choice.create_solver(&env));
+            Ok(Program {
+                text,
+                ir,
+                env,
+                solver,
+            })
+        })
+    }
+
+    /// Creates the Program for `text`, an edited version of this program.
+    /// Only the items affected by the edit are lowered and checked again.
+    fn edit(&self, text: String, solver_choice: SolverChoice) -> Fallible<Program> {
+        let old = chalk_parse::parse_program(&self.text)?;
+        let new = chalk_parse::parse_program(&text)?;
+        let families = ClauseFamilies::default();
+        let ir = relower(&self.ir, &old, &new, solver_choice, families)?.program;
+        let env = Arc::new(ir.environment(families));
+        chalk_ir::tls::set_current_program(&ir, || {
+            for cycle in negative_cycles(&env) {
+                eprintln!("warning: {}", cycle);
+            }
+        });
+        for cycle in ir.supertrait_cycles() {
+            eprintln!("warning: {}", cycle);
+        }
+        let solver = RefCell::new(solver_choice.create_solver(&env));
+        Ok(Program {
+            text,
+            ir,
+            env,
+            solver,
         })
     }
 }
@@ -80,7 +148,11 @@ fn run() -> Fallible<()> {
         .unwrap_or_else(|e| e.exit());
 
     // Validate arguments.
-    if args.flag_overflow_depth == 0 {
+    if args.flag_max_size == 0 {
+        eprintln!("error: max size must be at least 1");
+        exit(1);
+    }
+    if args.flag_overflow_depth == Some(0) {
         eprintln!("error: overflow depth must be at least 1");
         exit(1);
     }
@@ -88,7 +160,7 @@ fn run() -> Fallible<()> {
     // Load the .chalk file, if given.
     let mut prog = None;
     if let Some(program) = &args.flag_program {
-        match load_program(args, program) {
+        match load_program(args, program, None) {
             Ok(p) => prog = Some(p),
             Err(err) => {
                 eprintln!("error loading program: {}", err);
@@ -103,7 +175,7 @@ fn run() -> Fallible<()> {
             if let Err(e) = process(args, line, rl, &mut prog) {
                 eprintln!("error: {}", e);
             }
-        })
+        })?;
     } else {
         // Check that a program was provided.
         // TODO: It's customary to print Usage info when an error like this
@@ -123,9 +195,22 @@ fn run() -> Fallible<()> {
             }
             Ok(())
         })?;
+    }
+
+    if let Some(path) = &args.flag_profile {
+        write_profile(path)?;
+    }
+    Ok(())
+}
 
-        Ok(())
+/// Writes the time spent in each phase of the solver to `path`, as
+/// folded stacks for a flamegraph.
+fn write_profile(path: &str) -> Fallible<()> {
+    if !profile::enabled() {
+        eprintln!("warning: the solver is only profiled with the `profile` feature");
     }
+    File::create(path)?.write_all(profile::folded_stacks().as_bytes())?;
+    Ok(())
 }
 
 /// Reads input lines from the user. Lines start with the string given by `prompt`.
@@ -174,11 +259,32 @@ fn process(
         help()
     } else if command == "program" {
         // Load a .chalk file via stdin, until EOF is found.
-        *prog = Some(Program::new(read_program(rl)?, args.solver_choice())?);
+        let text = read_program(rl)?;
+        *prog = Some(reload(args, text, prog.as_ref())?);
     } else if command.starts_with("load ") {
         // Load a .chalk file.
         let filename = &command["load ".len()..];
-        *prog = Some(load_program(args, filename)?);
+        *prog = Some(load_program(args, filename, prog.as_ref())?);
+    } else if command == "mem-stats" {
+        print_mem_stats();
+    } else if command == "tables" {
+        // Print out the tables of the solver, as the last goal left them.
+        let prog = prog.as_ref().ok_or(format_err!("no program currently loaded"))?;
+        chalk_ir::tls::set_current_program(&prog.ir, || {
+            print!("{}", prog.solver.borrow().snapshot());
+        });
+    } else if command.starts_with("dot ") {
+        // Solve the goal with a fresh solver, so that its tables are the
+        // only ones, and print them as a graphviz graph.
+        let prog = prog.as_ref().ok_or(format_err!("no program currently loaded"))?;
+        chalk_ir::tls::set_current_program(&prog.ir, || -> Fallible<()> {
+            let goal = chalk_parse::parse_goal(&command["dot ".len()..])?;
+            let goal = goal.lower(&*prog.ir)?.into_peeled_goal();
+            let mut solver = args.solver_choice().create_solver(&prog.env);
+            let _ = solver.solve(&goal);
+            print!("{}", solver.snapshot().to_dot());
+            Ok(())
+        })?;
     } else if command.starts_with("debug ") {
         match command.split_whitespace().nth(1) {
             Some(level) => std::env::set_var("CHALK_DEBUG", level),
@@ -201,6 +307,9 @@ fn process(
                 // TODO: Write a line of documentation here.
                 "lowered" => println!("{:#?}", prog.env),
 
+                // Print out the sizes of the program.
+                "stats" => print!("{}", prog.ir.stats()),
+
                 // Assume this is a goal.
                 // TODO: Print out "type 'help' to see available commands" if it
                 // fails to parse?
@@ -216,10 +325,19 @@ fn process(
 /// Load the file into a string, and parse it.
 // TODO: Could we pass in an Options struct or something? The Args struct
 // still has Strings where it should have Enums... (e.g. solver_choice)
-fn load_program(args: &Args, filename: &str) -> Fallible<Program> {
+fn load_program(args: &Args, filename: &str, prev: Option<&Program>) -> Fallible<Program> {
     let mut text = String::new();
     File::open(filename)?.read_to_string(&mut text)?;
-    Ok(Program::new(text, args.solver_choice())?)
+    reload(args, text, prev)
+}
+
+/// Lowers the program `text`, reusing what it has in common with the
+/// previously loaded program `prev`, if any.
+fn reload(args: &Args, text: String, prev: Option<&Program>) -> Fallible<Program> {
+    match prev {
+        Some(prev) => prev.edit(text, args.solver_choice()),
+        None => Program::new(text, args.solver_choice()),
+    }
 }
 
 /// Print out help for commands in interpreter mode.
@@ -231,8 +349,23 @@ fn help() {
     println!("  load <file>   load program from <file>");
     println!("  print         print the current program");
     println!("  lowered       print the lowered program");
+    println!("  stats         print the sizes of the program");
     println!("  <goal>        attempt to solve <goal>");
     println!("  debug <level> set debug level to <level>");
+    println!("  mem-stats     print the allocations of each phase so far");
+    println!("  tables        print the solver's tables after the last goal");
+    println!("  dot <goal>    print the tables for <goal> as a graphviz graph");
+}
+
+/// Print out the memory statistics of each phase.
+fn print_mem_stats() {
+    if !mem_stats::enabled() {
+        println!("memory statistics are only kept with the `mem-stats` feature");
+        return;
+    }
+    for (phase, stats) in mem_stats::statistics() {
+        println!("{:<10}{}", phase, stats);
+    }
 }
 
 /// Read a program from the command-line. Stop reading when EOF is read. If
@@ -251,30 +384,77 @@ fn read_program(rl: &mut rustyline::Editor<()>) -> Fallible<String> {
 // TODO: Could we pass in an Options struct or something? The Args struct
 // still has Strings where it should have Enums... (e.g. solver_choice)
 fn goal(args: &Args, text: &str, prog: &Program) -> Fallible<()> {
-    let goal = chalk_parse::parse_goal(text)?.lower(&*prog.ir)?;
-    let peeled_goal = goal.into_peeled_goal();
-    match args
-        .solver_choice()
-        .solve_root_goal(&prog.env, &peeled_goal)
-    {
-        Ok(Some(v)) => println!("{}\n", v),
-        Ok(None) => println!("No possible solution.\n"),
-        Err(NoSolution) => println!("Solver failed"),
+    let goal = chalk_parse::parse_goal(text)?;
+    let forall_binders = peeled_forall_binders(&goal);
+    let peeled_goal = goal.lower(&*prog.ir)?.into_peeled_goal_with_universes();
+    let forall_names = peeled_goal.forall_names(forall_binders);
+    let peeled_goal = peeled_goal.goal;
+    let mut solver = prog.solver.borrow_mut();
+    if args.flag_no_cache {
+        solver.clear_cache();
     }
+    // Name the placeholders in the output after the binders they come from.
+    chalk_ir::tls::with_forall_names(forall_names, || {
+        let solution = mem_stats::measure(Phase::Solving, || solver.solve_partial(&peeled_goal));
+        match solution {
+            PartialSolution {
+                solution: Some(v),
+                overflows,
+                flounders,
+            } => {
+                println!("{}\n", v);
+                if args.flag_verify {
+                    if let Err(bug) = verify_solution(&*prog.env, &peeled_goal, &v) {
+                        eprintln!("{}\n", bug);
+                    }
+                }
+                if let Some(overflow) = overflows.iter().min_by_key(|overflow| overflow.depth()) {
+                    let what = match overflow.kind {
+                        OverflowKind::Depth => "Overflowed",
+                        OverflowKind::Size => "Truncated",
+                    };
+                    println!("{} at depth {}: {}\n", what, overflow.depth(), overflow);
+                }
+                for flounder in &flounders {
+                    println!("{}\n", flounder);
+                }
+                if args.flag_proof && v.is_unique() {
+                    if let Some(proof_tree) = solver.proof_tree(&peeled_goal) {
+                        println!("Proof:\n{}", proof_tree);
+                    }
+                }
+            }
+            PartialSolution { solution: None, .. } => {
+                println!("No possible solution.\n");
+                if args.flag_explain {
+                    let explanation = explain_failure(&prog.env, &mut **solver, &peeled_goal);
+                    if let Some(explanation) = explanation {
+                        println!("{}\n", explanation);
+                    }
+                }
+            }
+        }
+    });
     Ok(())
 }
 
 impl Args {
     fn solver_choice(&self) -> SolverChoice {
-        SolverChoice::SLG {
-            max_size: self.flag_overflow_depth,
+        let mut builder = SolverChoice::slg()
+            .max_size(self.flag_max_size)
+            .erase_lifetimes(self.flag_erase_lifetimes)
+            .record_proofs(self.flag_proof);
+        if let Some(overflow_depth) = self.flag_overflow_depth {
+            builder = builder.overflow_depth(overflow_depth);
         }
+        if let Some(max_answers) = self.flag_max_answers {
+            builder = builder.max_answers(max_answers);
+        }
+        builder.build()
     }
 }
 
 fn main() {
-    use ::std::io::Write;
-
     ::std::process::exit(match run() {
         Ok(_) => 0,
         Err(ref e) => {
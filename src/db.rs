@@ -1,11 +1,24 @@
-use crate::query::{self, ProgramSolverChoice, ProgramText};
+use crate::query::{self, ClauseFamilies, ProgramClauseFamilies, ProgramSolverChoice, ProgramText};
+use chalk_macros::intern::{self, Interner};
 use chalk_solve::solve::SolverChoice;
 use salsa::Database;
 use std::sync::Arc;
 
-#[derive(Default)]
 pub struct ChalkDatabase {
     runtime: salsa::Runtime<ChalkDatabase>,
+
+    /// The interner of the identifiers of the program: the one that was
+    /// current when the database was created.
+    interner: Arc<Interner>,
+}
+
+impl Default for ChalkDatabase {
+    fn default() -> Self {
+        ChalkDatabase {
+            runtime: salsa::Runtime::default(),
+            interner: intern::current_interner(),
+        }
+    }
 }
 
 impl Database for ChalkDatabase {
@@ -15,6 +28,11 @@ impl Database for ChalkDatabase {
 }
 
 impl ChalkDatabase {
+    /// Creates a database for `program_text` and runs `f` on it, with the
+    /// interner of the database installed as the current interner. To
+    /// keep the identifiers of the program apart from those of other
+    /// programs, install an interner of its own around the call (see
+    /// `chalk_macros::intern::with_interner`).
     pub fn with_program<F: FnOnce(&mut ChalkDatabase) -> R, R>(
         program_text: Arc<String>,
         solver_choice: SolverChoice,
@@ -24,8 +42,15 @@ impl ChalkDatabase {
 
         db.query_mut(ProgramText).set((), program_text);
         db.query_mut(ProgramSolverChoice).set((), solver_choice);
+        db.query_mut(ProgramClauseFamilies)
+            .set((), ClauseFamilies::default());
+
+        let interner = db.interner.clone();
+        intern::with_interner(&interner, || f(&mut db))
+    }
 
-        f(&mut db)
+    pub fn interner(&self) -> &Arc<Interner> {
+        &self.interner
     }
 }
 
@@ -34,9 +59,13 @@ salsa::database_storage! {
         impl query::LoweringDatabase {
             fn program_text() for query::ProgramText;
             fn solver_choice() for query::ProgramSolverChoice;
+            fn clause_families() for query::ProgramClauseFamilies;
             fn program_ir() for query::ProgramIr;
             fn lowered_program() for query::LoweredProgram;
             fn checked_program() for query::CheckedProgram;
+            fn stratified_program() for query::StratifiedProgram;
+            fn negative_cycles() for query::NegativeCycles;
+            fn supertrait_cycles() for query::SupertraitCycles;
             fn environment() for query::Environment;
         }
     }
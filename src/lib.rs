@@ -4,32 +4,27 @@
 #![feature(in_band_lifetimes)]
 #![feature(specialization)]
 #![feature(step_trait)]
-#![feature(try_blocks)]
 
 extern crate chalk_parse;
-#[macro_use]
 extern crate chalk_macros;
 extern crate chalk_engine;
-#[macro_use]
 extern crate chalk_ir;
+extern crate chalk_rules;
 extern crate chalk_solve;
 extern crate diff;
-#[macro_use]
 extern crate failure;
 extern crate itertools;
-extern crate lalrpop_intern;
-extern crate petgraph;
+#[macro_use]
+extern crate lazy_static;
 extern crate stacker;
 
-#[macro_use]
 mod test_util;
 
-pub mod rust_ir;
-
-crate mod coherence;
-crate mod rules;
+pub use chalk_rules::{incremental, rust_ir};
+pub use chalk_rules::{CoherenceError, SpecializationGraph, SupertraitCycle, WfError, WfErrors};
 
 pub mod db;
+pub mod mem_stats;
 pub mod query;
 
 mod test;
@@ -0,0 +1,203 @@
+//! Memory statistics: how many allocations each phase of lowering and
+//! solving makes, and how much memory it uses at its peak, for checking
+//! that changes meant to save memory (interning, arenas) actually do.
+//!
+//! The allocations are only counted when `CountingAllocator` is the
+//! global allocator. The library does not install it: hosts that want
+//! the statistics do so themselves, with
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator = CountingAllocator;
+//! ```
+//!
+//! as `chalki` does when built with the `mem-stats` feature. Otherwise
+//! `measure` just runs the phase, and `statistics` is empty.
+//!
+//! The counters are shared by all threads, so the statistics of phases
+//! that run at the same time on different threads include each other's
+//! allocations. `count_thread_allocations` counts those of the current
+//! thread alone.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+mod test;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+lazy_static! {
+    static ref STATISTICS: Mutex<BTreeMap<Phase, MemStats>> = Mutex::new(BTreeMap::new());
+}
+
+/// The phases that statistics are kept for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Phase {
+    /// Parsing the program and lowering it to Rust IR.
+    Lowering,
+
+    /// Generating the program clauses.
+    Clauses,
+
+    /// Checking coherence and well-formedness.
+    Checking,
+
+    /// Solving goals.
+    Solving,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Phase::Lowering => "lowering",
+            Phase::Clauses => "clauses",
+            Phase::Checking => "checking",
+            Phase::Solving => "solving",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+/// What was allocated during a phase, or during all the runs of a phase
+/// put together.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemStats {
+    /// The number of allocations, counting reallocations.
+    pub allocations: usize,
+
+    /// The bytes allocated in all, including those freed again.
+    pub allocated_bytes: usize,
+
+    /// The most bytes that were live at once, beyond those that were
+    /// already live when the phase started.
+    pub peak_bytes: usize,
+}
+
+impl MemStats {
+    /// Adds up the statistics of two runs of a phase; the peak is that of
+    /// the run with the highest one.
+    pub fn merge(self, other: MemStats) -> MemStats {
+        MemStats {
+            allocations: self.allocations + other.allocations,
+            allocated_bytes: self.allocated_bytes + other.allocated_bytes,
+            peak_bytes: self.peak_bytes.max(other.peak_bytes),
+        }
+    }
+}
+
+impl fmt::Display for MemStats {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{} allocations, {} bytes allocated, {} bytes at peak",
+            self.allocations, self.allocated_bytes, self.peak_bytes
+        )
+    }
+}
+
+/// Whether allocations are counted, i.e. whether `CountingAllocator`
+/// is the global allocator. Every process allocates before it gets
+/// around to measuring anything, so it is installed if and only if it
+/// has counted some allocation.
+pub fn enabled() -> bool {
+    ALLOCATIONS.load(Ordering::SeqCst) > 0
+}
+
+/// Runs `op` as a run of `phase`, adding what it allocates to the
+/// statistics of `phase`. Phases can be nested, in which case the
+/// allocations of the inner phase count towards both.
+pub fn measure<R>(phase: Phase, op: impl FnOnce() -> R) -> R {
+    if !enabled() {
+        return op();
+    }
+
+    let (result, stats) = count(op);
+    let mut statistics = STATISTICS.lock().unwrap();
+    let total = statistics.entry(phase).or_default();
+    *total = total.merge(stats);
+    result
+}
+
+/// The statistics of each phase that ran since they were last reset.
+pub fn statistics() -> BTreeMap<Phase, MemStats> {
+    STATISTICS.lock().unwrap().clone()
+}
+
+pub fn reset_statistics() {
+    STATISTICS.lock().unwrap().clear();
+}
+
+/// Runs `op`, returning how many allocations it made on the current
+/// thread. Unlike `measure`, this is not thrown off by other threads,
+/// which makes it usable in tests. Always 0 if allocations are not
+/// counted.
+pub fn count_thread_allocations<R>(op: impl FnOnce() -> R) -> (R, usize) {
+    let before = THREAD_ALLOCATIONS.with(Cell::get);
+    let result = op();
+    (result, THREAD_ALLOCATIONS.with(Cell::get) - before)
+}
+
+/// Runs `op`, returning what it allocated.
+fn count<R>(op: impl FnOnce() -> R) -> (R, MemStats) {
+    let allocations = ALLOCATIONS.load(Ordering::SeqCst);
+    let allocated_bytes = ALLOCATED_BYTES.load(Ordering::SeqCst);
+    let live_bytes = LIVE_BYTES.load(Ordering::SeqCst);
+
+    // Measure the peak of `op` alone, then put back that of the
+    // enclosing phase (if it is higher).
+    let outer_peak = PEAK_LIVE_BYTES.swap(live_bytes, Ordering::SeqCst);
+    let result = op();
+    let peak = PEAK_LIVE_BYTES.load(Ordering::SeqCst);
+    raise_peak(outer_peak);
+
+    let stats = MemStats {
+        allocations: ALLOCATIONS.load(Ordering::SeqCst) - allocations,
+        allocated_bytes: ALLOCATED_BYTES.load(Ordering::SeqCst) - allocated_bytes,
+        peak_bytes: peak.saturating_sub(live_bytes),
+    };
+    (result, stats)
+}
+
+fn raise_peak(bytes: usize) {
+    let mut peak = PEAK_LIVE_BYTES.load(Ordering::SeqCst);
+    while peak < bytes {
+        match PEAK_LIVE_BYTES.compare_exchange(peak, bytes, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => return,
+            Err(current) => peak = current,
+        }
+    }
+}
+
+/// Forwards to the system allocator, counting allocations along the way.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+            ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::SeqCst);
+            let live = LIVE_BYTES.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            raise_peak(live);
+            // The thread-local may already be gone while the thread exits.
+            let _ = THREAD_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
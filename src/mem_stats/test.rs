@@ -0,0 +1,46 @@
+#![cfg(test)]
+
+use super::*;
+
+#[cfg(feature = "mem-stats")]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn merge() {
+    let first = MemStats {
+        allocations: 2,
+        allocated_bytes: 64,
+        peak_bytes: 48,
+    };
+    let second = MemStats {
+        allocations: 1,
+        allocated_bytes: 32,
+        peak_bytes: 32,
+    };
+    assert_eq!(
+        first.merge(second),
+        MemStats {
+            allocations: 3,
+            allocated_bytes: 96,
+            peak_bytes: 48,
+        }
+    );
+}
+
+#[test]
+fn measure_phase() {
+    let len = measure(Phase::Solving, || vec![0u8; 1024].len());
+    assert_eq!(len, 1024);
+
+    // Other tests may run phases at the same time, so only lower bounds
+    // can be checked, and not the peak, which they may reset.
+    let stats = statistics().get(&Phase::Solving).cloned();
+    if enabled() {
+        let stats = stats.unwrap();
+        assert!(stats.allocations >= 1);
+        assert!(stats.allocated_bytes >= 1024);
+    } else {
+        assert_eq!(stats, None);
+    }
+}
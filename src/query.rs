@@ -1,12 +1,16 @@
 // https://crates.io/crates/salsa
 // hello world https://github.com/salsa-rs/salsa/blob/master/examples/hello_world/main.rs
 
-use crate::coherence::orphan;
-use crate::rules::wf;
+use crate::mem_stats::{self, Phase};
 use crate::rust_ir;
 use crate::rust_ir::lowering::LowerProgram;
-use chalk_ir::ProgramEnvironment;
+use chalk_ir::{tls, ProgramEnvironment};
+use chalk_rules::coherence::orphan;
+use chalk_rules::rules::supertraits::SupertraitCycle;
+use chalk_rules::rules::wf;
+pub use chalk_rules::rules::ClauseFamilies;
 use chalk_solve::solve::SolverChoice;
+use chalk_solve::stratify::{self, NegativeCycle};
 use failure::Fallible;
 use std::sync::Arc;
 
@@ -24,6 +28,14 @@ salsa::query_group! {
             storage input;
         }
 
+        /// The families of derived clauses that `environment` generates;
+        /// all of them unless changed after `ChalkDatabase::with_program`.
+        fn clause_families() -> ClauseFamilies {
+            type ProgramClauseFamilies;
+
+            storage input;
+        }
+
         // FIXME: Result<..., String> is only needed because the error type is not clone
 
         /// The program IR before recording specialization priorities.
@@ -42,6 +54,25 @@ salsa::query_group! {
             type CheckedProgram;
         }
 
+        /// The lowered IR, with checks performed, rejecting programs
+        /// that are not stratified (see `negative_cycles`).
+        fn stratified_program() -> Result<Arc<rust_ir::Program>, String> {
+            type StratifiedProgram;
+        }
+
+        /// The cycles through negation in the program, if any. Hosts
+        /// that accept such programs may want to report them as
+        /// warnings.
+        fn negative_cycles() -> Result<Arc<Vec<NegativeCycle>>, String> {
+            type NegativeCycles;
+        }
+
+        /// The cycles among the supertraits of the program, if any (see
+        /// `Program::supertrait_cycles`).
+        fn supertrait_cycles() -> Result<Arc<Vec<SupertraitCycle>>, String> {
+            type SupertraitCycles;
+        }
+
         /// The program as logic.
         fn environment() -> Result<Arc<ProgramEnvironment>, String> {
             type Environment;
@@ -50,10 +81,10 @@ salsa::query_group! {
 }
 
 fn program_ir(db: &impl LoweringDatabase) -> Result<Arc<rust_ir::Program>, String> {
-    let x: Fallible<_> = try {
+    let x = mem_stats::measure(Phase::Lowering, || -> Fallible<_> {
         let text = db.program_text();
-        Arc::new(chalk_parse::parse_program(&text)?.lower()?)
-    };
+        Ok(Arc::new(chalk_parse::parse_program(&text)?.lower()?))
+    });
 
     x.map_err(|err| err.to_string())
 }
@@ -62,10 +93,10 @@ fn lowered_program(db: &impl LoweringDatabase) -> Result<Arc<rust_ir::Program>,
     let mut program = db.program_ir()?;
     let env = db.environment()?;
 
-    let x: Fallible<_> = try {
+    let x = mem_stats::measure(Phase::Checking, || -> Fallible<_> {
         Arc::make_mut(&mut program).record_specialization_priorities(env, db.solver_choice())?;
-        program
-    };
+        Ok(program)
+    });
 
     x.map_err(|err| err.to_string())
 }
@@ -74,15 +105,38 @@ fn checked_program(db: &impl LoweringDatabase) -> Result<Arc<rust_ir::Program>,
     let program = db.lowered_program()?;
     let env = db.environment()?;
 
-    let x: Fallible<_> = try {
+    let x = mem_stats::measure(Phase::Checking, || -> Fallible<_> {
         orphan::perform_orphan_check(program.clone(), env.clone(), db.solver_choice())?;
         wf::verify_well_formedness(program.clone(), env, db.solver_choice())?;
-        program
-    };
+        Ok(program)
+    });
     x.map_err(|err| err.to_string())
 }
 
+fn stratified_program(db: &impl LoweringDatabase) -> Result<Arc<rust_ir::Program>, String> {
+    let program = db.checked_program()?;
+    match db.negative_cycles()?.first() {
+        Some(cycle) => Err(tls::set_current_program(&program, || {
+            format!("program is not stratified: {}", cycle)
+        })),
+        None => Ok(program),
+    }
+}
+
+fn negative_cycles(db: &impl LoweringDatabase) -> Result<Arc<Vec<NegativeCycle>>, String> {
+    let env = db.environment()?;
+    Ok(Arc::new(stratify::negative_cycles(&env)))
+}
+
+fn supertrait_cycles(db: &impl LoweringDatabase) -> Result<Arc<Vec<SupertraitCycle>>, String> {
+    Ok(Arc::new(db.program_ir()?.supertrait_cycles()))
+}
+
 fn environment(db: &impl LoweringDatabase) -> Result<Arc<ProgramEnvironment>, String> {
-    let env = db.program_ir()?.environment();
+    let program = db.program_ir()?;
+    let mut env = mem_stats::measure(Phase::Clauses, || program.environment(db.clause_families()));
+    db.solver_choice()
+        .clause_order()
+        .shuffle_program_clauses(&mut env);
     Ok(Arc::new(env))
 }
@@ -4,7 +4,8 @@ use crate::test_util::*;
 use chalk_engine::fallible::{Fallible, NoSolution};
 use chalk_ir;
 use chalk_solve::ext::*;
-use chalk_solve::solve::{Solution, SolverChoice};
+use chalk_solve::solve::verify::verify_solution;
+use chalk_solve::solve::{ClauseOrder, Coinduction, SearchStrategy, Solution, SolverChoice};
 use std::collections::HashMap;
 
 mod bench;
@@ -18,15 +19,51 @@ fn result_to_string(result: &Fallible<Option<Solution>>) -> String {
     }
 }
 
+/// Whether `expected` is a prefix of `actual`, ignoring whitespace.
+fn starts_with_expected(actual: &str, expected: &str) -> bool {
+    let expected1: String = expected.chars().filter(|w| !w.is_whitespace()).collect();
+    let actual1: String = actual.chars().filter(|w| !w.is_whitespace()).collect();
+    !expected1.is_empty() && actual1.starts_with(&expected1)
+}
+
 fn assert_result(result: &Fallible<Option<Solution>>, expected: &str) {
     let result = result_to_string(result);
 
     println!("expected:\n{}", expected);
     println!("actual:\n{}", result);
 
-    let expected1: String = expected.chars().filter(|w| !w.is_whitespace()).collect();
-    let result1: String = result.chars().filter(|w| !w.is_whitespace()).collect();
-    assert!(!expected1.is_empty() && result1.starts_with(&expected1));
+    assert!(starts_with_expected(&result, expected));
+}
+
+/// Checks the answers that `solve_multiple` produced against
+/// `expected`, in order; each answer need only start with the expected
+/// one, as in `assert_result`.
+fn assert_answers(answers: &[String], expected: &[&str]) {
+    println!("expected answers:\n{:#?}", expected);
+    println!("actual answers:\n{:#?}", answers);
+
+    assert_eq!(answers.len(), expected.len(), "wrong number of answers");
+    for (answer, expected) in answers.iter().zip(expected) {
+        assert!(
+            starts_with_expected(answer, expected),
+            "answer `{}` does not match `{}`",
+            answer,
+            expected
+        );
+    }
+}
+
+/// What a goal of a `test!` should yield.
+enum Expected<'a> {
+    /// The solution that `solve_root_goal` finds.
+    Solution(&'a str),
+
+    /// All the answers that `solve_multiple` produces, in order.
+    AllAnswers(Vec<&'a str>),
+
+    /// The first answers that `solve_multiple` produces, in order; the
+    /// stream may have more (or even infinitely many) after them.
+    FirstAnswers(Vec<&'a str>),
 }
 
 macro_rules! test {
@@ -48,7 +85,39 @@ macro_rules! test {
         test!(@program[$program]
               @parsed_goals[
                   $($parsed_goals)*
-                      (stringify!($goal), SolverChoice::default(), $expected)
+                      (stringify!($goal), SolverChoice::default(), Expected::Solution($expected))
+              ]
+              @unparsed_goals[$($unparsed_goals)*])
+    };
+
+    // goal { G } yields_all { "A1", "A2" } -- test that the answers to G are exactly
+    // A1 and A2, in that order
+    (@program[$program:tt] @parsed_goals[$($parsed_goals:tt)*] @unparsed_goals[
+        goal $goal:tt yields_all { $($expected:expr),* $(,)* }
+        $($unparsed_goals:tt)*
+    ]) => {
+        test!(@program[$program]
+              @parsed_goals[
+                  $($parsed_goals)*
+                      (stringify!($goal),
+                       SolverChoice::default(),
+                       Expected::AllAnswers(vec![$($expected),*]))
+              ]
+              @unparsed_goals[$($unparsed_goals)*])
+    };
+
+    // goal { G } yields_first { "A1", "A2" } -- test that the first two answers to G are
+    // A1 and A2, in that order
+    (@program[$program:tt] @parsed_goals[$($parsed_goals:tt)*] @unparsed_goals[
+        goal $goal:tt yields_first { $($expected:expr),* $(,)* }
+        $($unparsed_goals:tt)*
+    ]) => {
+        test!(@program[$program]
+              @parsed_goals[
+                  $($parsed_goals)*
+                      (stringify!($goal),
+                       SolverChoice::default(),
+                       Expected::FirstAnswers(vec![$($expected),*]))
               ]
               @unparsed_goals[$($unparsed_goals)*])
     };
@@ -66,7 +135,7 @@ macro_rules! test {
     ]) => {
         test!(@program[$program]
               @parsed_goals[$($parsed_goals)*
-                            $($((stringify!($goal), $C, $expected))+)+]
+                            $($((stringify!($goal), $C, Expected::Solution($expected)))+)+]
               @unparsed_goals[goal $($unparsed_goals)*])
     };
 
@@ -76,17 +145,39 @@ macro_rules! test {
     ]) => {
         test!(@program[$program]
               @parsed_goals[$($parsed_goals)*
-                            $($((stringify!($goal), $C, $expected))+)+]
+                            $($((stringify!($goal), $C, Expected::Solution($expected)))+)+]
               @unparsed_goals[])
     };
 }
 
-fn solve_goal(program_text: &str, goals: Vec<(&str, SolverChoice, &str)>) {
+/// Whether to check the unique solutions that the tests find (see
+/// `chalk_solve::solve::verify`), which is done when `CHALK_VERIFY` is
+/// set: the test harness takes no flags of its own.
+fn verify_enabled() -> bool {
+    std::env::var_os("CHALK_VERIFY").is_some()
+}
+
+/// The seed to shuffle the clauses with (see `ClauseOrder::Shuffled`)
+/// when solving the goals that use the default solver choice, if
+/// `CHALK_SHUFFLE_SEED` is set.
+fn shuffle_seed() -> Option<u64> {
+    std::env::var("CHALK_SHUFFLE_SEED")
+        .ok()
+        .map(|seed| seed.parse().expect("CHALK_SHUFFLE_SEED is not a number"))
+}
+
+fn solve_goal(program_text: &str, goals: Vec<(&str, SolverChoice, Expected)>) {
     println!("program {}", program_text);
     assert!(program_text.starts_with("{"));
     assert!(program_text.ends_with("}"));
     let mut program_env_cache = HashMap::new();
     for (goal_text, solver_choice, expected) in goals {
+        let solver_choice = match shuffle_seed() {
+            Some(seed) if solver_choice == SolverChoice::default() => SolverChoice::slg()
+                .clause_order(ClauseOrder::Shuffled { seed })
+                .build(),
+            _ => solver_choice,
+        };
         let (program, env) = program_env_cache.entry(solver_choice).or_insert_with(|| {
             let program_text = &program_text[1..program_text.len() - 1]; // exclude `{}`
             parse_and_lower_program_with_env(program_text, solver_choice).unwrap()
@@ -101,50 +192,35 @@ fn solve_goal(program_text: &str, goals: Vec<(&str, SolverChoice, &str)>) {
 
             println!("using solver: {:?}", solver_choice);
             let peeled_goal = goal.into_peeled_goal();
-            let result = solver_choice.solve_root_goal(&env, &peeled_goal);
-            assert_result(&result, expected);
+            let (expected_answers, limit) = match &expected {
+                Expected::Solution(expected) => {
+                    let result = solver_choice.solve_root_goal(&env, &peeled_goal);
+                    if verify_enabled() {
+                        if let Ok(Some(solution)) = &result {
+                            if let Err(bug) = verify_solution(&**env, &peeled_goal, solution) {
+                                panic!("{}", bug);
+                            }
+                        }
+                    }
+                    assert_result(&result, expected);
+                    return;
+                }
+                // Take one answer more than expected, to catch any extra.
+                Expected::AllAnswers(expected) => (expected, expected.len() + 1),
+                Expected::FirstAnswers(expected) => (expected, expected.len()),
+            };
+
+            let answers: Vec<_> = solver_choice
+                .create_solver(&env)
+                .solve_multiple(&peeled_goal)
+                .take(limit)
+                .map(|answer| answer.to_string())
+                .collect();
+            assert_answers(&answers, expected_answers);
         });
     }
 }
 
-#[test]
-fn prove_clone() {
-    test! {
-        program {
-            struct Foo { }
-            struct Bar { }
-            struct Vec<T> { }
-            trait Clone { }
-            impl<T> Clone for Vec<T> where T: Clone { }
-            impl Clone for Foo { }
-        }
-
-        goal {
-            Vec<Foo>: Clone
-        } yields {
-            "Unique; substitution [], lifetime constraints []"
-        }
-
-        goal {
-            Foo: Clone
-        } yields {
-            "Unique; substitution [], lifetime constraints []"
-        }
-
-        goal {
-            Bar: Clone
-        } yields {
-            "No possible solution"
-        }
-
-        goal {
-            Vec<Bar>: Clone
-        } yields {
-            "No possible solution"
-        }
-    }
-}
-
 #[test]
 fn inner_cycle() {
     // Interesting test that shows why recursive solver needs to run
@@ -178,37 +254,6 @@ fn inner_cycle() {
     }
 }
 
-#[test]
-fn prove_infer() {
-    test! {
-        program {
-            struct Foo { }
-            struct Bar { }
-            trait Map<T> { }
-            impl Map<Bar> for Foo { }
-            impl Map<Foo> for Bar { }
-        }
-
-        goal {
-            exists<A, B> { A: Map<B> }
-        } yields {
-            "Ambiguous; no inference guidance"
-        }
-
-        goal {
-            exists<A> { A: Map<Bar> }
-        } yields {
-            "Unique; substitution [?0 := Foo], lifetime constraints []"
-        }
-
-        goal {
-            exists<A> { Foo: Map<A> }
-        } yields {
-            "Unique; substitution [?0 := Bar], lifetime constraints []"
-        }
-    }
-}
-
 #[test]
 fn prove_forall() {
     test! {
@@ -1055,6 +1100,72 @@ fn forall_projection_gat() {
     }
 }
 
+#[test]
+fn object_types() {
+    test! {
+        program {
+            trait Iterator { type Item; }
+            trait Clone { }
+            #[auto] trait Send { }
+            struct u32 { }
+        }
+
+        goal {
+            dyn Iterator<Item = u32> + Send: Iterator
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            dyn Iterator<Item = u32> + Send: Send
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            dyn Iterator<Item = u32>: Send
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            dyn Iterator<Item = u32>: Clone
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            exists<T> {
+                Normalize(<dyn Iterator<Item = u32> as Iterator>::Item -> T)
+            }
+        } yields {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            exists<T> {
+                dyn Iterator<Item = T>: Iterator<Item = u32>
+            }
+        } yields {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            exists<T> {
+                dyn Iterator<Item = T> + Send = dyn Iterator<Item = u32> + Send
+            }
+        } yields {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            dyn Iterator<Item = u32> = dyn Iterator<Item = u32> + Send
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
 #[test]
 fn struct_wf() {
     test! {
@@ -1242,6 +1353,136 @@ fn unify_quantified_lifetimes() {
     }
 }
 
+#[test]
+fn outlives_goals() {
+    test! {
+        program {
+        }
+
+        // Placeholders cannot be equated with other lifetimes, but they
+        // can be required to outlive them.
+        goal {
+            forall<'a, 'b> {
+                'a: 'b
+            }
+        } yields {
+            "Unique; substitution [], \
+             lifetime constraints [InEnvironment { environment: Env([]), goal: '!1_0: '!1_1 }]"
+        }
+
+        goal {
+            exists<'b> {
+                forall<'a> {
+                    'a: 'b
+                }
+            }
+        } yields {
+            "Unique; for<?U0> { \
+             substitution [?0 := '^0], \
+             lifetime constraints [InEnvironment { environment: Env([]), goal: '!1_0: '^0 }] \
+             }"
+        }
+
+        goal {
+            forall<'a> {
+                'a: 'a
+            }
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+    }
+}
+
+#[test]
+fn unify_erased_lifetimes() {
+    test! {
+        program {
+            trait Foo { }
+            struct Fun<'a> { }
+            impl Foo for for<'a> Fun<'a> { }
+        }
+
+        goal {
+            exists<'a> {
+                forall<'b> {
+                    'a = 'b
+                }
+            }
+        } yields[SolverChoice::slg().erase_lifetimes(true).build()] {
+            "Unique; substitution [?0 := '<erased>], lifetime constraints []"
+        }
+
+        goal {
+            forall<'a> { Fun<'a>: Foo }
+        } yields[SolverChoice::slg().erase_lifetimes(true).build()] {
+            "Unique; substitution [], lifetime constraints []"
+        }
+    }
+}
+
+#[test]
+fn erased_lifetimes_share_tables() {
+    let program_text = "
+        trait Foo { }
+        struct Bar { }
+        struct Ref<'a, T> { }
+        struct Pair<A, B> { }
+        impl Foo for Bar { }
+        impl<'a, T> Foo for Ref<'a, T> where T: Foo { }
+        impl<A, B> Foo for Pair<A, B> where A: Foo, B: Foo { }
+    ";
+    let goal_text = "forall<'a, 'b> { Pair<Ref<'a, Bar>, Ref<'b, Bar>>: Foo }";
+
+    // Without erasure, `Ref<'a, Bar>: Foo` and `Ref<'b, Bar>: Foo` get
+    // tables of their own; with it, they are the same goal.
+    let num_tables: Vec<usize> = [false, true]
+        .iter()
+        .map(|&erase_lifetimes| {
+            let solver_choice = SolverChoice::slg()
+                .erase_lifetimes(erase_lifetimes)
+                .build();
+            let (program, env) =
+                parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+            let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+            let mut solver = solver_choice.create_solver(&env);
+            assert!(solver.solve(&goal.into_peeled_goal()).unwrap().is_unique());
+            solver.snapshot().tables.len()
+        })
+        .collect();
+
+    assert!(num_tables[1] < num_tables[0], "{:?}", num_tables);
+}
+
+#[test]
+fn higher_ranked_types() {
+    test! {
+        program {
+            struct u32 { }
+        }
+
+        goal {
+            for<T> fn(T) = for<U> fn(U)
+        } yields {
+            "Unique; substitution [], lifetime constraints []"
+        }
+
+        // `T` is a placeholder, which cannot be equal to anything else.
+        goal {
+            for<T> fn(T) = fn(u32)
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            exists<V> {
+                for<T> fn(T) = for<U> fn(V)
+            }
+        } yields {
+            "No possible solution"
+        }
+    }
+}
+
 #[test]
 fn equality_binder() {
     test! {
@@ -1559,6 +1800,43 @@ fn suggested_subst() {
     }
 }
 
+#[test]
+fn candidate_substitutions() {
+    test! {
+        program {
+            trait Foo<A> {}
+            trait Bar {}
+            struct u32 {}
+            struct i32 {}
+            impl Foo<u32> for u32 {}
+            impl Foo<i32> for i32 {}
+            impl Bar for u32 {}
+            impl Bar for i32 {}
+        }
+
+        // Both answers map `T` and `U` to the same type.
+        goal {
+            exists<T, U> {
+                T: Foo<U>
+            }
+        } yields {
+            "Ambiguous; definite substitution for<?U0> { [?0 := ^0, ?1 := ^0] }"
+        }
+
+        goal {
+            exists<T> {
+                T: Bar
+            }
+        } yields[SolverChoice::default()] {
+            "Ambiguous; no inference guidance"
+        } yields[SolverChoice::slg().max_candidates(1).build()] {
+            "Ambiguous; no inference guidance"
+        } yields[SolverChoice::slg().max_candidates(2).build()] {
+            "Ambiguous; definite substitution for<?U0> { [?0 := ^0] }; candidate substitutions"
+        }
+    }
+}
+
 #[test]
 fn simple_negation() {
     test! {
@@ -1715,12 +1993,64 @@ fn negation_free_vars() {
     }
 }
 
+/// `P` and `Q` depend on one another through a negative loop, so the
+/// answer is conditional on `not { u32: Q }`, which cannot be decided.
 #[test]
-fn where_clause_trumps() {
+fn negative_loop_conditional() {
     test! {
         program {
-            struct Foo { }
-
+            trait P { }
+            trait Q { }
+            struct u32 { }
+
+            forall<> { u32: P if not { u32: Q } }
+            forall<> { u32: Q if not { u32: P } }
+        }
+
+        goal {
+            u32: P
+        } yields {
+            "Conditional; substitution [], lifetime constraints []"
+        }
+    }
+}
+
+/// Answers that rest on a truncated answer are ambiguous, not
+/// conditional, whether they use it positively or negatively.
+#[test]
+fn truncated_answers_are_not_conditional() {
+    test! {
+        program {
+            trait Foo { }
+            trait Bar { }
+            struct u32 { }
+            struct Vec<T> { }
+
+            impl Foo for u32 { }
+            impl<T> Foo for Vec<T> where T: Foo { }
+            forall<T> { T: Bar if T: Foo }
+        }
+
+        goal {
+            Vec<Vec<Vec<Vec<u32>>>>: Bar
+        } yields[SolverChoice::slg().max_size(2).build()] {
+            "Ambiguous; no inference guidance"
+        }
+
+        goal {
+            not { Vec<Vec<Vec<Vec<u32>>>>: Foo }
+        } yields[SolverChoice::slg().max_size(2).build()] {
+            "Ambiguous; no inference guidance"
+        }
+    }
+}
+
+#[test]
+fn where_clause_trumps() {
+    test! {
+        program {
+            struct Foo { }
+
             trait Marker { }
             impl Marker for Foo { }
         }
@@ -1763,6 +2093,47 @@ fn inapplicable_assumption_does_not_shadow() {
     }
 }
 
+#[test]
+fn assumptions_about_several_traits() {
+    test! {
+        program {
+            trait Foo { }
+            trait Bar<T> { }
+            trait Baz { type Item; }
+        }
+
+        goal {
+            forall<T, U, V> {
+                if (T: Foo; U: Bar<T>; T: Bar<U>; V: Baz<Item = T>) {
+                    T: Bar<U>
+                }
+            }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<T, U, V> {
+                if (T: Foo; U: Bar<T>; T: Bar<U>; V: Baz<Item = T>) {
+                    U: Foo
+                }
+            }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T, U, V> {
+                if (T: Foo; U: Bar<T>; T: Bar<U>; V: Baz<Item = T>) {
+                    <V as Baz>::Item = T
+                }
+            }
+        } yields {
+            "Unique"
+        }
+    }
+}
+
 #[test]
 fn auto_trait_without_impls() {
     test! {
@@ -1903,6 +2274,91 @@ fn coinductive_semantics() {
     }
 }
 
+#[test]
+fn auto_trait_constituent_types() {
+    test! {
+        program {
+            #[auto] trait Send { }
+
+            struct i32 { }
+            struct Rc<T> { }
+            impl<T> !Send for Rc<T> { }
+
+            struct Vec<T> { elem: T }
+            struct Tree<T> {
+                value: T,
+                children: Vec<Tree<T>>
+            }
+            struct Forest<T> {
+                trees: Vec<Tree<T>>,
+                shared: Tree<Rc<T>>
+            }
+        }
+
+        // The cycle `Tree<i32>: Send :- Vec<Tree<i32>>: Send :- Tree<i32>: Send`
+        // is a proof, as `Send` is coinductive.
+        goal {
+            Tree<i32>: Send
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Send) {
+                    Forest<T>: Send
+                }
+            }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Send) {
+                    Vec<Tree<Vec<T>>>: Send
+                }
+            }
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn coinductive_trait_attribute() {
+    test! {
+        program {
+            #[coinductive] trait Sized { }
+            trait Copy { }
+
+            struct Node<T> { }
+            impl<T> Sized for Node<T> where Node<T>: Sized { }
+            impl<T> Copy for Node<T> where Node<T>: Copy { }
+        }
+
+        goal {
+            forall<T> {
+                Node<T>: Sized
+            }
+        } yields[SolverChoice::default()] {
+            "Unique"
+        } yields[SolverChoice::slg().coinduction(Coinduction::None).build()] {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> {
+                Node<T>: Copy
+            }
+        } yields[SolverChoice::default()] {
+            "No possible solution"
+        } yields[SolverChoice::slg().coinduction(Coinduction::AllTraits).build()] {
+            "Unique"
+        }
+    }
+}
+
 #[test]
 fn mixed_semantics() {
     test! {
@@ -2370,25 +2826,25 @@ fn quantified_types() {
     test! {
         program {
             trait Foo { }
-            struct fn<'a> { }
-            struct fn2<'a, 'b> { }
-            impl Foo for for<'a> fn<'a> { }
+            struct Fun<'a> { }
+            struct Fun2<'a, 'b> { }
+            impl Foo for for<'a> Fun<'a> { }
         }
 
         goal {
-            for<'a> fn<'a>: Foo
+            for<'a> Fun<'a>: Foo
         } yields {
             "Unique"
         }
 
         goal {
-            for<'a, 'b> fn2<'a, 'b> = for<'b, 'a> fn2<'a, 'b>
+            for<'a, 'b> Fun2<'a, 'b> = for<'b, 'a> Fun2<'a, 'b>
         } yields {
             "Unique"
         }
 
         goal {
-            forall<'a> { fn<'a>: Foo }
+            forall<'a> { Fun<'a>: Foo }
         } yields {
             // Lifetime constraints are unsatisfiable
             "Unique; substitution [], \
@@ -2458,6 +2914,48 @@ fn recursive_where_clause_on_type() {
     }
 }
 
+#[test]
+fn const_evaluatable() {
+    test! {
+        program {
+            trait Foo { }
+            struct Array<T> { }
+            struct u32 { }
+            impl<T> Foo for Array<T> where ConstEvaluatable(SizeOf<T>) { }
+
+            forall { ConstEvaluatable(SizeOf<u32>) }
+        }
+
+        goal {
+            forall<T> { Array<T>: Foo }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            forall<T> {
+                if (ConstEvaluatable(SizeOf<T>)) {
+                    Array<T>: Foo
+                }
+            }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            Array<u32>: Foo
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            exists<T> { ConstEvaluatable(SizeOf<T>) }
+        } yields {
+            "Unique; substitution [?0 := u32]"
+        }
+    }
+}
+
 #[test]
 fn deref_goal() {
     test! {
@@ -2507,6 +3005,140 @@ fn deref_goal() {
     }
 }
 
+#[test]
+fn sized_and_needs_drop() {
+    test! {
+        program {
+            #[lang_drop]
+            trait Drop { }
+            struct u32 { }
+            struct Vec<T> { }
+            struct Foo { a: u32 }
+            struct Bar { a: u32, b: Vec<u32> }
+            struct Wrapper<T> { t: T }
+            impl<T> Drop for Vec<T> { }
+        }
+
+        goal { IsSized(Foo) } yields { "Unique" }
+        goal { IsSized(Wrapper<Bar>) } yields { "Unique" }
+
+        goal { NeedsDrop(Vec<u32>) } yields { "Unique" }
+        goal { NeedsDrop(Bar) } yields { "Unique" }
+        goal { NeedsDrop(Wrapper<Bar>) } yields { "Unique" }
+
+        goal { NeedsDrop(Foo) } yields { "No possible solution" }
+        goal { NeedsDrop(Wrapper<u32>) } yields { "No possible solution" }
+    }
+}
+
+#[test]
+fn builtin_impls_for_tuples_and_fn_ptrs() {
+    test! {
+        program {
+            #[lang_copy]
+            trait Copy { }
+            #[lang_clone]
+            trait Clone { }
+            #[lang_fn_once]
+            trait FnOnce<Args> { type Output; }
+            #[lang_fn]
+            trait Fn<Args> { }
+            struct u32 { }
+            struct Vec<T> { }
+            impl Copy for u32 { }
+            impl Clone for u32 { }
+            impl<T> Clone for Vec<T> where T: Clone { }
+        }
+
+        goal { (): Copy } yields { "Unique" }
+        goal { (u32, (u32,)): Copy } yields { "Unique" }
+        goal { (u32, Vec<u32>): Copy } yields { "No possible solution" }
+        goal { (u32, Vec<u32>): Clone } yields { "Unique" }
+        goal { WellFormed((u32, Vec<u32>)) } yields { "Unique" }
+
+        goal { fn(Vec<u32>) -> Vec<u32>: Copy } yields { "Unique" }
+        goal { fn(u32) -> Vec<u32>: Fn<(u32,)> } yields { "Unique" }
+        goal { fn(u32): Fn<(Vec<u32>,)> } yields { "No possible solution" }
+
+        goal {
+            exists<T> {
+                fn(T): Fn<(u32,)>
+            }
+        } yields {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            exists<T> {
+                Normalize(<fn(u32) -> Vec<u32> as FnOnce<(u32,)>>::Output -> T)
+            }
+        } yields {
+            "Unique; substitution [?0 := Vec<u32>]"
+        }
+    }
+}
+
+#[test]
+fn auto_traits_for_tuples_and_fn_ptrs() {
+    test! {
+        program {
+            #[auto] trait Send { }
+            struct u32 { }
+            struct Rc<T> { }
+            struct Wrapper { field: (u32, fn(Rc<u32>)) }
+            struct NotSend { field: (u32, Rc<u32>) }
+            impl<T> !Send for Rc<T> { }
+        }
+
+        goal { (u32, u32): Send } yields { "Unique" }
+        goal { (u32, Rc<u32>): Send } yields { "No possible solution" }
+        goal { forall<T> { if (T: Send) { (T, (T,)): Send } } } yields { "Unique" }
+        goal { forall<T> { (T, u32): Send } } yields { "No possible solution" }
+        goal { fn(Rc<u32>) -> Rc<u32>: Send } yields { "Unique" }
+
+        goal { Wrapper: Send } yields { "Unique" }
+        goal { NotSend: Send } yields { "No possible solution" }
+    }
+}
+
+#[test]
+fn auto_trait_cycle_through_tuple() {
+    test! {
+        program {
+            #[auto] trait Send { }
+            struct u32 { }
+            struct Rc<T> { }
+            struct Box<T> { value: T }
+            struct List<T> { next: (T, Box<List<T>>) }
+            impl<T> !Send for Rc<T> { }
+        }
+
+        goal { List<u32>: Send } yields { "Unique" }
+        goal { List<Rc<u32>>: Send } yields { "No possible solution" }
+        goal { forall<T> { if (T: Send) { List<T>: Send } } } yields { "Unique" }
+    }
+}
+
+#[test]
+fn tuples_and_fn_ptrs_are_upstream() {
+    test! {
+        program {
+            #[upstream] struct Upstream { }
+            struct Local { }
+        }
+
+        goal { IsLocal((Local, Local)) } yields { "No possible solution" }
+        goal { IsLocal(fn(Local) -> Local) } yields { "No possible solution" }
+        goal { IsUpstream((Local,)) } yields { "Unique" }
+        goal { IsUpstream(fn(Local)) } yields { "Unique" }
+
+        goal { IsFullyVisible((Local, Upstream)) } yields { "Unique" }
+        goal { IsFullyVisible(fn(Local) -> Upstream) } yields { "Unique" }
+        goal { forall<T> { IsFullyVisible((Local, T)) } } yields { "No possible solution" }
+        goal { forall<T> { IsFullyVisible(fn(T)) } } yields { "No possible solution" }
+    }
+}
+
 #[test]
 fn local_and_upstream_types() {
     test! {
@@ -2864,3 +3496,1665 @@ fn local_impl_allowed_for_traits() {
         goal { forall<'a, T> { LocalImplAllowed(Upstream: UpstreamTrait<'a, Upstream, Local, T>) } } yields { "Unique" }
     }
 }
+
+#[test]
+fn solve_multiple_answers() {
+    let program_text = "
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        trait Clone { }
+        impl Clone for Foo { }
+        impl Clone for Bar { }
+        impl<T> Clone for Vec<T> where T: Clone { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Clone }").unwrap();
+        let peeled_goal = goal.into_peeled_goal();
+        let mut solver = solver_choice.create_solver(&env);
+
+        // The goal has infinitely many answers; the stream is lazy, so
+        // we can still pull out the first few.
+        let answers: Vec<_> = solver.solve_multiple(&peeled_goal).take(3).collect();
+        assert_eq!(answers.len(), 3);
+        assert!(answers.iter().all(|answer| !answer.ambiguous));
+
+        let answers: Vec<_> = answers.iter().map(|answer| format!("{}", answer)).collect();
+        assert!(answers.iter().any(|answer| answer.contains("?0 := Foo")));
+        assert!(answers.iter().any(|answer| answer.contains("?0 := Bar")));
+    });
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "Vec<Foo>: Clone").unwrap();
+        let peeled_goal = goal.into_peeled_goal();
+        let mut solver = solver_choice.create_solver(&env);
+        assert_eq!(solver.solve_multiple(&peeled_goal).count(), 1);
+    });
+}
+
+#[test]
+fn answers_in_order() {
+    test! {
+        program {
+            struct Foo { }
+            struct Vec<T> { }
+            trait Clone { }
+            trait Copy { }
+            impl Clone for Foo { }
+            impl<T> Clone for Vec<T> where T: Clone { }
+            impl Copy for Foo { }
+        }
+
+        goal {
+            exists<T> { T: Copy }
+        } yields_all {
+            "substitution [?0 := Foo], lifetime constraints []"
+        }
+
+        goal {
+            Vec<Foo>: Copy
+        } yields_all {
+        }
+
+        // `Vec<T>: Clone` only has answers once `T: Clone` does, so the
+        // answers come in order of nesting.
+        goal {
+            exists<T> { T: Clone }
+        } yields_first {
+            "substitution [?0 := Foo]",
+            "substitution [?0 := Vec<Foo>]",
+            "substitution [?0 := Vec<Vec<Foo>>]"
+        }
+    }
+}
+
+#[test]
+fn max_answers() {
+    test! {
+        program {
+            struct u32 { }
+            struct i32 { }
+            struct A { }
+            struct B { }
+            struct X { }
+            struct Y { }
+            struct Triple<T, U, V> { }
+            trait Foo { }
+            impl Foo for Triple<u32, A, X> { }
+            impl Foo for Triple<u32, B, Y> { }
+            impl Foo for Triple<i32, A, Y> { }
+        }
+
+        // Any two answers have something in common, but all three do not.
+        goal {
+            exists<T, U, V> { Triple<T, U, V>: Foo }
+        } yields {
+            "Ambiguous; no inference guidance"
+        }
+
+        goal {
+            exists<T, U, V> { Triple<T, U, V>: Foo }
+        } yields[SolverChoice::slg().max_answers(2).build()] {
+            "Ambiguous; too many answers"
+        }
+
+        goal {
+            exists<U, V> { Triple<u32, U, V>: Foo }
+        } yields[SolverChoice::slg().max_answers(2).build()] {
+            "Ambiguous; no inference guidance"
+        }
+
+        goal {
+            Triple<u32, A, X>: Foo
+        } yields[SolverChoice::slg().max_answers(1).build()] {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn cancellation() {
+    use chalk_engine::cancel::{CancellationToken, Canceled};
+    use std::time::Duration;
+
+    let program_text = "
+        struct Foo { }
+        trait Clone { }
+        impl Clone for Foo { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "Foo: Clone").unwrap();
+        let peeled_goal = goal.into_peeled_goal();
+        let mut solver = solver_choice.create_solver(&env);
+
+        let cancel = CancellationToken::new();
+        cancel.clone().cancel();
+        assert_eq!(
+            solver.solve_with_cancellation(&peeled_goal, &cancel),
+            Err(Canceled::Requested)
+        );
+
+        let expired = CancellationToken::with_timeout(Duration::from_secs(0));
+        assert_eq!(
+            solver.solve_with_cancellation(&peeled_goal, &expired),
+            Err(Canceled::DeadlinePassed)
+        );
+
+        let result = solver.solve_with_cancellation(&peeled_goal, &CancellationToken::new());
+        assert!(result.unwrap().unwrap().is_unique());
+    });
+}
+
+/// The token is polled at every step of the search, not only when a
+/// strand yields.
+#[test]
+fn cancellation_within_quantum() {
+    use chalk_engine::cancel::{CancellationToken, Canceled};
+    use chalk_engine::listener::EventListener;
+    use chalk_engine::TableIndex;
+    use chalk_ir::{Canonical, ConstrainedSubst, Goal, InEnvironment, UCanonical};
+    use chalk_solve::solve::slg::implementation::SlgContext;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Cancels `cancel` as soon as the search enters a subgoal, and
+    /// counts the answers found after that.
+    struct CancelOnSubgoal {
+        cancel: CancellationToken,
+        goals_entered: usize,
+        answers_after_cancel: Arc<AtomicUsize>,
+    }
+
+    impl EventListener<SlgContext> for CancelOnSubgoal {
+        fn goal_entered(&mut self, _table: TableIndex, _goal: &UCanonical<InEnvironment<Goal>>) {
+            self.goals_entered += 1;
+            if self.goals_entered > 1 {
+                self.cancel.cancel();
+            }
+        }
+
+        fn answer_produced(
+            &mut self,
+            _table: TableIndex,
+            _answer: &Canonical<ConstrainedSubst>,
+            _ambiguous: bool,
+        ) {
+            if self.cancel.is_canceled() {
+                self.answers_after_cancel.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    let program_text = "
+        trait Foo { }
+        trait Bar { }
+        struct u32 { }
+        impl Foo for u32 where u32: Bar { }
+        impl Bar for u32 { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "u32: Foo").unwrap();
+        let peeled_goal = goal.into_peeled_goal();
+        let mut solver = solver_choice.create_solver(&env);
+        let cancel = CancellationToken::new();
+        let answers_after_cancel = Arc::new(AtomicUsize::new(0));
+        solver.set_event_listener(Box::new(CancelOnSubgoal {
+            cancel: cancel.clone(),
+            goals_entered: 0,
+            answers_after_cancel: answers_after_cancel.clone(),
+        }));
+
+        assert_eq!(
+            solver.solve_with_cancellation(&peeled_goal, &cancel),
+            Err(Canceled::Requested)
+        );
+        assert_eq!(answers_after_cancel.load(Ordering::SeqCst), 0);
+
+        // The search picks up where it left off.
+        assert!(solver.solve(&peeled_goal).unwrap().is_unique());
+    });
+}
+
+#[test]
+fn memory_limit() {
+    use chalk_engine::cancel::{CancellationToken, Canceled};
+
+    let program_text = "
+        trait Foo { }
+        trait Bar { }
+        struct u32 { }
+        impl Foo for u32 where u32: Bar { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "u32: Foo").unwrap();
+        let peeled_goal = goal.into_peeled_goal();
+
+        // Solving the goal needs a table for `u32: Foo` and another
+        // one for `u32: Bar`, which takes more than a byte.
+        let cancel = CancellationToken::new().limit_memory(1);
+        let mut solver = solver_choice.create_solver(&env);
+        match solver.solve_with_cancellation(&peeled_goal, &cancel) {
+            Err(Canceled::ResourceExhausted {
+                memory_used,
+                max_memory,
+            }) => {
+                assert!(memory_used > 1);
+                assert_eq!(max_memory, 1);
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        let cancel = CancellationToken::new().limit_memory(1 << 20);
+        let mut solver = solver_choice.create_solver(&env);
+        assert_eq!(solver.solve_with_cancellation(&peeled_goal, &cancel), Ok(None));
+
+        // The tables from the query above do not count against the
+        // limit of the next one, which reuses them.
+        let cancel = CancellationToken::new().limit_memory(1);
+        assert_eq!(solver.solve_with_cancellation(&peeled_goal, &cancel), Ok(None));
+    });
+}
+
+#[test]
+fn proof_tree() {
+    use chalk_solve::solve::proof::ProofStep;
+
+    let program_text = "
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        trait Clone { }
+        impl<T> Clone for Vec<T> where T: Clone { }
+        impl Clone for Foo { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "Vec<Vec<Foo>>: Clone")
+            .unwrap()
+            .into_peeled_goal();
+        let (solution, proof_tree) = solver_choice
+            .solve_root_goal_with_proof(&env, &goal)
+            .unwrap()
+            .unwrap();
+        assert!(solution.is_unique());
+
+        // A solver only has proofs if it records them.
+        assert!(solver_choice.create_solver(&env).proof_tree(&goal).is_none());
+
+        let proof_tree = proof_tree.unwrap();
+
+        // `Vec<Vec<Foo>>: Clone` via the impl for `Vec<T>`, which
+        // requires `Vec<Foo>: Clone`, which requires `Foo: Clone`.
+        let mut node = &proof_tree;
+        for _ in 0..2 {
+            match node.step {
+                ProofStep::Clause(_) => {}
+                _ => panic!("expected a clause, found {:?}", node.step),
+            }
+            assert_eq!(node.children.len(), 1);
+            node = &node.children[0];
+        }
+        match node.step {
+            ProofStep::Clause(_) => {}
+            _ => panic!("expected a clause, found {:?}", node.step),
+        }
+        assert!(node.children.is_empty());
+
+        let goal = parse_and_lower_goal(&program, "exists<T> { Vec<T>: Clone }").unwrap();
+        let (solution, proof_tree) = solver_choice
+            .solve_root_goal_with_proof(&env, &goal.into_peeled_goal())
+            .unwrap()
+            .unwrap();
+        assert!(!solution.is_unique());
+        assert!(proof_tree.is_none());
+
+        let goal = parse_and_lower_goal(&program, "Bar: Clone").unwrap();
+        let result = solver_choice
+            .solve_root_goal_with_proof(&env, &goal.into_peeled_goal())
+            .unwrap();
+        assert!(result.is_none());
+    });
+}
+
+#[test]
+fn explain_failure() {
+    use chalk_solve::solve::explain::CandidateFailure;
+
+    let program_text = "
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        trait Clone { }
+        impl<T> Clone for Vec<T> where T: Clone { }
+        impl Clone for Foo { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "Foo: Clone, Vec<Bar>: Clone").unwrap();
+        let explanation = solver_choice
+            .explain_failure(&env, &goal.into_peeled_goal())
+            .unwrap();
+
+        // We blame `Vec<Bar>: Clone`, and in turn the missing `Bar: Clone`.
+        assert_eq!(
+            format!("{:?}", explanation.goal.value.goal),
+            "Implemented(Vec<Bar>: Clone)"
+        );
+        assert!(explanation.candidates.iter().any(|candidate| match &candidate.failure {
+            CandidateFailure::Subgoal(subgoal) => {
+                format!("{:?}", subgoal.value.goal) == "Implemented(Bar: Clone)"
+            }
+            _ => false,
+        }));
+
+        let goal = parse_and_lower_goal(&program, "Vec<Foo>: Clone").unwrap();
+        assert!(solver_choice
+            .explain_failure(&env, &goal.into_peeled_goal())
+            .is_none());
+    });
+}
+
+#[test]
+fn explain_ambiguity() {
+    use chalk_solve::solve::explain::AmbiguityReason;
+
+    let program_text = "
+        struct Foo { }
+        struct Bar { }
+        trait Clone { }
+        impl Clone for Foo { }
+        impl Clone for Bar { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Clone }").unwrap();
+        let explanation = solver_choice
+            .explain_ambiguity(&env, &goal.into_peeled_goal())
+            .unwrap();
+        assert!(explanation.reasons.iter().any(|reason| match reason {
+            AmbiguityReason::MultipleCandidates(clauses) => clauses.len() == 2,
+            _ => false,
+        }));
+        assert!(explanation
+            .reasons
+            .contains(&AmbiguityReason::UnresolvedVariables(vec![0])));
+
+        let goal = parse_and_lower_goal(&program, "exists<T> { not { T: Clone } }").unwrap();
+        let explanation = solver_choice
+            .explain_ambiguity(&env, &goal.into_peeled_goal())
+            .unwrap();
+        assert!(explanation.reasons.iter().any(|reason| match reason {
+            AmbiguityReason::Floundered(_) => true,
+            _ => false,
+        }));
+
+        let goal = parse_and_lower_goal(&program, "Foo: Clone").unwrap();
+        assert!(solver_choice
+            .explain_ambiguity(&env, &goal.into_peeled_goal())
+            .is_none());
+    });
+}
+
+#[test]
+fn solution_cache() {
+    use chalk_solve::solve::cache::SolutionCache;
+    use chalk_solve::solve::{Guidance, Solver};
+
+    let program_text = "
+        struct Foo { }
+        struct Bar { }
+        trait Clone { }
+        impl Clone for Foo { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let foo_goal = parse_and_lower_goal(&program, "Foo: Clone")
+            .unwrap()
+            .into_peeled_goal();
+        let bar_goal = parse_and_lower_goal(&program, "Bar: Clone")
+            .unwrap()
+            .into_peeled_goal();
+
+        let mut solver = solver_choice.create_cached_solver(&env, SolutionCache::new());
+        let foo_solution = solver.solve(&foo_goal);
+        assert!(foo_solution.as_ref().unwrap().is_unique());
+        assert_eq!(solver.solve(&bar_goal), None);
+
+        let mut cache = solver.into_cache();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&foo_goal), Some(&foo_solution));
+        assert_eq!(cache.get(&bar_goal), Some(&None));
+
+        // A warm-started solver answers from the cache without
+        // consulting the program, so a doctored entry shows through.
+        let doctored = Some(Solution::Ambig(Guidance::Unknown));
+        cache.insert(bar_goal.clone(), doctored.clone());
+        let mut solver = solver_choice.create_cached_solver(&env, cache);
+        assert_eq!(solver.solve(&foo_goal), foo_solution);
+        assert_eq!(solver.solve(&bar_goal), doctored);
+
+        solver.clear_cache();
+        assert!(solver.cache().is_empty());
+        assert_eq!(solver.solve(&bar_goal), None);
+
+        // With room for a single goal, solving another one evicts it.
+        let mut solver = solver_choice.create_cached_solver(&env, SolutionCache::with_capacity(1));
+        solver.solve(&foo_goal);
+        solver.solve(&bar_goal);
+        assert_eq!(solver.cache().len(), 1);
+        assert_eq!(solver.cache().evictions(), 1);
+        let mut cache = solver.into_cache();
+        assert_eq!(cache.get(&foo_goal), None);
+        assert_eq!(cache.get(&bar_goal), Some(&None));
+    });
+}
+
+#[test]
+fn solve_all() {
+    let program_text = "
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        trait Clone { }
+        impl<T> Clone for Vec<T> where T: Clone { }
+        impl Clone for Foo { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goals: Vec<_> = [
+            "Foo: Clone",
+            "Bar: Clone",
+            "Vec<Foo>: Clone",
+            "Vec<Vec<Bar>>: Clone",
+            "exists<T> { Vec<T>: Clone }",
+        ]
+        .iter()
+        .map(|text| parse_and_lower_goal(&program, text).unwrap().into_peeled_goal())
+        .collect();
+
+        let expected: Vec<_> = goals
+            .iter()
+            .map(|goal| solver_choice.solve_root_goal(&env, goal).unwrap())
+            .collect();
+        for num_threads in 1..4 {
+            let solver_choice = SolverChoice::slg().num_threads(num_threads).build();
+            assert_eq!(solver_choice.solve_all(&env, &goals), expected);
+        }
+        assert_eq!(solver_choice.solve_batch(&env, &goals), expected);
+    });
+}
+
+/// The goals of a batch share tables, but each goal gets the same solution
+/// whatever goals were solved before it.
+#[test]
+fn solve_batch_order() {
+    let program_text = "
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        trait Clone { }
+        trait Copy where Self: Clone { }
+        impl<T> Clone for Vec<T> where T: Clone { }
+        impl Clone for Foo { }
+        impl Clone for Bar { }
+        impl Copy for Foo { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goals: Vec<_> = [
+            "Vec<Vec<Foo>>: Clone",
+            "Vec<Foo>: Copy",
+            "exists<T> { Vec<T>: Clone }",
+            "exists<T> { Vec<T>: Clone, T: Copy }",
+            "Vec<Foo>: Clone",
+            "forall<T> { if (T: Copy) { Vec<T>: Clone } }",
+        ]
+        .iter()
+        .map(|text| parse_and_lower_goal(&program, text).unwrap().into_peeled_goal())
+        .collect();
+        let expected = solver_choice.solve_batch(&env, &goals);
+
+        let mut reversed = goals.clone();
+        reversed.reverse();
+        let mut solutions = solver_choice.solve_batch(&env, &reversed);
+        solutions.reverse();
+        assert_eq!(solutions, expected);
+
+        for shift in 1..goals.len() {
+            let mut rotated = goals.clone();
+            rotated.rotate_left(shift);
+            let mut solutions = solver_choice.solve_batch(&env, &rotated);
+            solutions.rotate_right(shift);
+            assert_eq!(solutions, expected);
+        }
+    });
+}
+
+#[test]
+fn lazy_program_clauses() {
+    use chalk_ir::*;
+    use std::sync::Arc;
+
+    /// Supplies the clauses of `env`, plus a blanket impl of every
+    /// trait named in a goal -- an infinite set of clauses, which we
+    /// can only produce on demand.
+    #[derive(Debug)]
+    struct BlanketImpls {
+        env: Arc<ProgramEnvironment>,
+    }
+
+    impl ProgramClauseSet for BlanketImpls {
+        fn program_clauses_for_goal(&self, goal: &DomainGoal) -> Vec<ProgramClause> {
+            let mut clauses = self.env.program_clauses_for_goal(goal);
+            if let DomainGoal::Holds(WhereClause::Implemented(trait_ref)) = goal {
+                clauses.push(ProgramClause::ForAll(Binders {
+                    binders: vec![ParameterKind::Ty(())],
+                    value: ProgramClauseImplication {
+                        consequence: DomainGoal::Holds(WhereClause::Implemented(TraitRef {
+                            trait_id: trait_ref.trait_id,
+                            parameters: smallvec![ParameterKind::Ty(
+                                DebruijnIndex::INNERMOST.to_ty()
+                            )],
+                        })),
+                        conditions: smallvec![],
+                    },
+                    names: BinderNames::default(),
+                }));
+            }
+            clauses
+        }
+
+        fn is_coinductive_trait(&self, trait_id: ItemId) -> bool {
+            self.env.is_coinductive_trait(trait_id)
+        }
+    }
+
+    let program_text = "
+        struct Foo { }
+        trait Magic { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "Foo: Magic")
+            .unwrap()
+            .into_peeled_goal();
+
+        let mut solver = solver_choice.create_solver(&env);
+        assert_eq!(solver.solve(&goal), None);
+
+        let mut solver =
+            solver_choice.create_solver_for_clause_set(Arc::new(BlanketImpls { env: env.clone() }));
+        assert!(solver.solve(&goal).unwrap().is_unique());
+    });
+}
+
+#[test]
+fn custom_goals() {
+    use chalk_ir::*;
+    use chalk_macros::intern::intern;
+    use std::sync::Arc;
+
+    /// Handles `Custom(Same<A, B>)`, which holds if `A` and `B` are
+    /// the same type.
+    #[derive(Debug)]
+    struct Same;
+
+    impl CustomGoalHandler for Same {
+        fn program_clauses(&self, goal: &CustomGoal) -> Vec<ProgramClause> {
+            vec![ProgramClause::ForAll(Binders {
+                binders: vec![ParameterKind::Ty(())],
+                value: ProgramClauseImplication {
+                    consequence: DomainGoal::Custom(CustomGoal {
+                        name: goal.name,
+                        parameters: vec![
+                            ParameterKind::Ty(DebruijnIndex::INNERMOST.to_ty()),
+                            ParameterKind::Ty(DebruijnIndex::INNERMOST.to_ty()),
+                        ],
+                    }),
+                    conditions: smallvec![],
+                },
+                names: BinderNames::default(),
+            })]
+        }
+    }
+
+    let program_text = "
+        struct Foo { }
+        struct Bar { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let mut custom_goals = CustomGoals::new(env.clone());
+        custom_goals.register(intern("Same"), Arc::new(Same));
+        let mut solver = solver_choice.create_solver_for_clause_set(Arc::new(custom_goals));
+        let mut solve = |text: &str| {
+            let goal = parse_and_lower_goal(&program, text)
+                .unwrap()
+                .into_peeled_goal();
+            solver.solve(&goal)
+        };
+
+        assert!(solve("Custom(Same<Foo, Foo>)").unwrap().is_unique());
+        assert_eq!(solve("Custom(Same<Foo, Bar>)"), None);
+        assert_eq!(
+            format!("{}", solve("exists<T> { Custom(Same<Foo, T>) }").unwrap()),
+            "Unique; substitution [?0 := Foo], lifetime constraints []"
+        );
+
+        // There is no handler for `Other`.
+        assert_eq!(solve("Custom(Other<Foo>)"), None);
+    });
+}
+
+#[test]
+fn solver_choice_options() {
+    test! {
+        program {
+            #[auto] trait Send { }
+            trait Foo { }
+
+            struct i32 { }
+            struct Vec<T> { }
+            impl Foo for i32 { }
+            impl<T> Foo for Vec<T> where T: Foo { }
+
+            struct Ptr<T> { }
+            impl<T> Send for Ptr<T> where T: Send { }
+
+            struct List<T> {
+                data: T,
+                next: Ptr<List<T>>
+            }
+        }
+
+        // Proving this needs the goal stack to grow seven goals deep.
+        // (The depth limit is high enough for the impls to be
+        // well-formed, which the program is checked for first.)
+        goal {
+            Vec<Vec<Vec<Vec<Vec<i32>>>>>: Foo
+        } yields[SolverChoice::slg().overflow_depth(7).build()] {
+            "Unique"
+        } yields[SolverChoice::slg().overflow_depth(6).build()] {
+            "Ambiguous"
+        }
+
+        // The proof is cyclic, which is only fine if `Send` is
+        // coinductive.
+        goal {
+            List<i32>: Send
+        } yields[SolverChoice::default()] {
+            "Unique"
+        } yields[SolverChoice::slg().coinduction(Coinduction::None).build()] {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn simplify_goals() {
+    use chalk_solve::solve::simplify::simplify_goal;
+
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let solver_choice = SolverChoice::slg().simplify_goals(true).build();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let simplifications = vec![
+            ("u32: Foo, (u32: Foo, Vec<u32>: Foo)", "u32: Foo, Vec<u32>: Foo"),
+            ("u32 = u32, Vec<u32>: Foo, u32 = u32", "Vec<u32>: Foo"),
+            ("u32 = u32", "u32 = u32"),
+            ("if () { Vec<u32>: Foo }", "Vec<u32>: Foo"),
+            (
+                "exists<T> { u32: Foo, T: Foo, u32: Foo }",
+                "u32: Foo, exists<T> { T: Foo }",
+            ),
+            ("forall<T> { u32: Foo }", "u32: Foo"),
+            (
+                "exists<T> { forall<U> { T: Foo, U = U, Vec<T>: Foo } }",
+                "exists<T> { T: Foo, Vec<T>: Foo }",
+            ),
+            ("not { u32: Foo, u32: Foo }", "not { u32: Foo }"),
+            ("all { u32: Foo; all { Vec<u32>: Foo; u32: Foo } }", "u32: Foo, Vec<u32>: Foo"),
+            ("any { u32: Foo; u32 = u32 }", "u32 = u32"),
+            ("any { all { u32: Foo } }", "u32: Foo"),
+        ];
+        for (goal_text, expected_text) in simplifications {
+            let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+            let expected = parse_and_lower_goal(&program, expected_text).unwrap();
+            assert_eq!(simplify_goal(&goal), *expected, "simplifying `{}`", goal_text);
+        }
+
+        // Simplified goals have the same solutions.
+        let goal = parse_and_lower_goal(&program, "exists<T> { u32: Foo, Vec<T>: Foo, T = u32 }")
+            .unwrap()
+            .into_peeled_goal();
+        assert_eq!(
+            format!("{}", solver_choice.solve_root_goal(&env, &goal).unwrap().unwrap()),
+            "Unique; substitution [?0 := u32], lifetime constraints []"
+        );
+    });
+}
+
+#[test]
+fn all_and_any_goals() {
+    test! {
+        program {
+            struct u32 {}
+            struct i32 {}
+            struct Bar {}
+            trait Foo {}
+            impl Foo for u32 {}
+            impl Foo for Bar {}
+        }
+
+        goal {
+            all { u32: Foo; Bar: Foo }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            all { u32: Foo; i32: Foo }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            any { i32: Foo; Bar: Foo }
+        } yields {
+            "Unique"
+        }
+
+        goal {
+            exists<T> { any { T = u32; T = Bar } }
+        } yields {
+            "Ambiguous"
+        }
+
+        goal {
+            exists<T> { any { T = u32; T = i32 }, T: Foo }
+        } yields {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            any { }
+        } yields {
+            "No possible solution"
+        }
+
+        goal {
+            all { }
+        } yields {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn deterministic_answer_order() {
+    // The same program twice, with the items declared in a different
+    // order.
+    let program_texts = [
+        "
+        trait Clone { }
+        trait Copy { }
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        impl Clone for Foo { }
+        impl Clone for Bar { }
+        impl<T> Clone for Vec<T> where T: Clone { }
+        impl Copy for Foo { }
+        impl Copy for Bar { }
+        ",
+        "
+        struct Vec<T> { }
+        struct Bar { }
+        trait Copy { }
+        trait Clone { }
+        impl Copy for Bar { }
+        impl<T> Clone for Vec<T> where T: Clone { }
+        impl Clone for Bar { }
+        struct Foo { }
+        impl Copy for Foo { }
+        impl Clone for Foo { }
+        ",
+    ];
+    let solver_choice = SolverChoice::slg()
+        .deterministic(true)
+        .max_candidates(4)
+        .build();
+
+    // No program is set in `chalk_ir::tls` while solving (or printing
+    // the results), so nothing can depend on the names of the items.
+    let results: Vec<(Vec<String>, String)> = program_texts
+        .iter()
+        .map(|program_text| {
+            let (program, env) =
+                parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+            let clone_goal = parse_and_lower_goal(&program, "exists<T> { T: Clone }").unwrap();
+            let copy_goal = parse_and_lower_goal(&program, "exists<T> { T: Copy }").unwrap();
+            let mut solver = solver_choice.create_solver(&env);
+            let answers = solver
+                .solve_multiple(&clone_goal.into_peeled_goal())
+                .take(4)
+                .map(|answer| format!("{}", answer))
+                .collect();
+            let solution = solver.solve(&copy_goal.into_peeled_goal()).unwrap();
+            (answers, format!("{}", solution))
+        })
+        .collect();
+
+    assert_eq!(results[0].0.len(), 4);
+    assert_eq!(results[0], results[1]);
+    assert!(results[0].1.contains("candidate substitutions"), "{}", results[0].1);
+}
+
+#[test]
+fn breadth_first_search() {
+    test! {
+        program {
+            #[auto] trait Send { }
+            trait Foo { }
+            trait Bar { }
+            struct S<T> { }
+            struct i32 { }
+            impl<T> Foo for S<T> where T: Foo, T: Bar { }
+            impl Foo for i32 { }
+
+            struct Ptr<T> { }
+            impl<T> Send for Ptr<T> where T: Send { }
+
+            struct List<T> {
+                data: T,
+                next: Ptr<List<T>>
+            }
+        }
+
+        goal {
+            exists<T> {
+                T: Foo
+            }
+        } yields[SolverChoice::default()] {
+            "Unique; substitution [?0 := i32]"
+        } yields[SolverChoice::slg().search_strategy(SearchStrategy::BreadthFirst).build()] {
+            "Unique; substitution [?0 := i32]"
+        }
+
+        goal {
+            S<i32>: Foo
+        } yields[SolverChoice::default()] {
+            "No possible solution"
+        } yields[SolverChoice::slg().search_strategy(SearchStrategy::BreadthFirst).build()] {
+            "No possible solution"
+        }
+
+        goal {
+            List<i32>: Send
+        } yields[SolverChoice::default()] {
+            "Unique"
+        } yields[SolverChoice::slg().search_strategy(SearchStrategy::BreadthFirst).build()] {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn elaborated_environment() {
+    use chalk_solve::solve::elaborate::elaborate_environment;
+
+    let program_text = "
+        trait Super { }
+        trait Sub where Self: Super { }
+        struct Foo<T> where T: Sub { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        // The hypothesis `T: Sub` implies `T: Super`, and `Foo<T>` being
+        // well-formed implies `T: Sub`.
+        for goal_text in &[
+            "forall<T> { if (T: Sub) { T: Super } }",
+            "forall<T> { if (FromEnv(Foo<T>)) { T: Super } }",
+        ] {
+            let goal = parse_and_lower_goal(&program, goal_text)
+                .unwrap()
+                .into_peeled_goal();
+            let facts = elaborate_environment(&env, &goal, 10);
+            assert!(facts.binders.is_empty());
+            let facts: Vec<String> = facts
+                .value
+                .iter()
+                .map(|fact| format!("{:?}", fact))
+                .collect();
+            for expected in &["FromEnv(!1_0 as Sub)", "FromEnv(!1_0 as Super)"] {
+                assert!(facts.iter().any(|fact| fact == expected), "{:?}", facts);
+            }
+
+            let solution = SolverChoice::default().solve_root_goal(&env, &goal).unwrap();
+            assert!(solution.unwrap().is_unique());
+        }
+    });
+}
+
+#[test]
+fn clause_families() {
+    use crate::db::ChalkDatabase;
+    use crate::query::{ClauseFamilies, LoweringDatabase, ProgramClauseFamilies};
+    use salsa::Database;
+    use std::sync::Arc;
+
+    let program_text = Arc::new(
+        "
+        trait Super { }
+        trait Sub where Self: Super { }
+        trait Iterator { type Item; }
+        "
+        .to_string(),
+    );
+    let goals = [
+        "forall<T> { if (T: Sub) { T: Super } }",
+        "forall<T> { if (T: Super) { WellFormed(T: Super) } }",
+        "forall<T> { if (T: Iterator) { exists<U> { T: Iterator<Item = U> } } }",
+    ];
+
+    // Solves each goal with the clauses of `families`.
+    let solve = |families: ClauseFamilies, expected: [&str; 3]| {
+        ChalkDatabase::with_program(program_text.clone(), SolverChoice::default(), |db| {
+            db.query_mut(ProgramClauseFamilies).set((), families);
+            let program = db.lowered_program().unwrap();
+            let env = db.environment().unwrap();
+            chalk_ir::tls::set_current_program(&program, || {
+                for (goal_text, expected) in goals.iter().zip(&expected) {
+                    let goal = parse_and_lower_goal(&program, goal_text)
+                        .unwrap()
+                        .into_peeled_goal();
+                    let result = SolverChoice::default().solve_root_goal(&env, &goal);
+                    assert_result(&result, expected);
+                }
+            })
+        })
+    };
+
+    solve(
+        ClauseFamilies::default(),
+        [
+            "Unique",
+            "Unique",
+            "Unique; substitution [?0 := (Iterator::Item)<!1_0>]",
+        ],
+    );
+    solve(
+        ClauseFamilies {
+            implied_bounds: false,
+            ..ClauseFamilies::default()
+        },
+        ["No possible solution", "Unique", "Unique"],
+    );
+    solve(
+        ClauseFamilies {
+            well_formed: false,
+            ..ClauseFamilies::default()
+        },
+        ["Unique", "No possible solution", "Unique"],
+    );
+    solve(
+        ClauseFamilies {
+            projection_fallback: false,
+            ..ClauseFamilies::default()
+        },
+        ["Unique", "Unique", "No possible solution"],
+    );
+}
+
+#[test]
+fn stratification() {
+    use crate::db::ChalkDatabase;
+    use crate::query::LoweringDatabase;
+    use std::sync::Arc;
+
+    // Returns the negative cycles of the program, and whether it is
+    // accepted by `stratified_program`.
+    let check = |program_text: &str| {
+        let program_text = Arc::new(program_text.to_string());
+        ChalkDatabase::with_program(program_text, SolverChoice::default(), |db| {
+            let program = db.checked_program().unwrap();
+            let cycles = db.negative_cycles().unwrap();
+            let cycles: Vec<String> = chalk_ir::tls::set_current_program(&program, || {
+                cycles.iter().map(|cycle| cycle.to_string()).collect()
+            });
+            (cycles, db.stratified_program().is_ok())
+        })
+    };
+
+    assert_eq!(
+        check(
+            "
+            trait P { }
+            trait Q { }
+            struct u32 { }
+            impl Q for u32 { }
+            forall<T> { T: P if not { T: Q } }
+            "
+        ),
+        (vec![], true)
+    );
+
+    assert_eq!(
+        check(
+            "
+            trait P { }
+            struct u32 { }
+            forall<> { u32: P if not { u32: P } }
+            "
+        ),
+        (
+            vec![
+                "Implemented(P) depends negatively on itself: \
+                 Implemented(P) :- not { Implemented(P) }"
+                    .to_string()
+            ],
+            false
+        )
+    );
+
+    assert_eq!(
+        check(
+            "
+            trait P { }
+            trait Q { }
+            trait R { }
+            forall<T> { T: P if not { T: Q } }
+            forall<T> { T: Q if T: R }
+            forall<T> { T: R if exists<U> { T: P, U: R } }
+            "
+        ),
+        (
+            vec![
+                "Implemented(P) depends negatively on itself: \
+                 Implemented(P) :- not { Implemented(Q) }; \
+                 Implemented(Q) :- Implemented(R); \
+                 Implemented(R) :- Implemented(P)"
+                    .to_string()
+            ],
+            false
+        )
+    );
+}
+
+#[test]
+fn differential_solving() {
+    use chalk_solve::solve::Solver;
+
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+
+        // Search strategies must not change the solutions.
+        let breadth_first = SolverChoice::slg()
+            .search_strategy(SearchStrategy::BreadthFirst)
+            .build();
+        let mut solver = SolverChoice::default().create_differential_solver(breadth_first, &env);
+        for goal_text in &["u32: Foo, Vec<Vec<u32>>: Foo", "exists<T> { Vec<T>: Foo }"] {
+            let goal = parse_and_lower_goal(&program, goal_text)
+                .unwrap()
+                .into_closed_goal();
+            solver.solve(&goal);
+        }
+        assert!(solver.disagreements().is_empty());
+
+        // A small `max_size` does, and the disagreement is narrowed
+        // down to the conjunct that needs a large goal.
+        let truncating = SolverChoice::slg().max_size(2).build();
+        let mut solver = SolverChoice::default().create_differential_solver(truncating, &env);
+        let goal = parse_and_lower_goal(&program, "u32: Foo, Vec<Vec<Vec<Vec<u32>>>>: Foo")
+            .unwrap()
+            .into_closed_goal();
+        let solution = solver.solve(&goal).unwrap();
+        assert!(solution.is_unique());
+
+        let disagreements = solver.take_disagreements();
+        assert_eq!(disagreements.len(), 1);
+        let expected = parse_and_lower_goal(&program, "Vec<Vec<Vec<Vec<u32>>>>: Foo")
+            .unwrap()
+            .into_closed_goal();
+        assert_eq!(disagreements[0].minimized_goal, expected);
+        assert_ne!(disagreements[0].solutions.0, disagreements[0].solutions.1);
+    });
+}
+
+#[test]
+fn solve_any() {
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct i32 { }
+        struct Bar { }
+        impl Foo for u32 { }
+        impl Foo for i32 { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        // `solve` reports that there are several answers, while
+        // `solve_any` is happy with the first one.
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Foo }")
+            .unwrap()
+            .into_peeled_goal();
+        let solution = solver_choice.solve_root_goal(&env, &goal).unwrap().unwrap();
+        assert!(!solution.is_unique());
+        let answer = solver_choice.solve_any_root_goal(&env, &goal).unwrap().unwrap();
+        assert!(!answer.ambiguous);
+
+        let goal = parse_and_lower_goal(&program, "Bar: Foo")
+            .unwrap()
+            .into_closed_goal();
+        assert_eq!(solver_choice.solve_any_root_goal(&env, &goal).unwrap(), None);
+    });
+}
+
+#[test]
+fn partial_solutions() {
+    use chalk_solve::solve::OverflowKind;
+
+    let program_text = "
+        trait Foo { }
+        struct i32 { }
+        struct Vec<T> { }
+        impl Foo for i32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "Vec<Vec<Vec<i32>>>: Foo")
+            .unwrap()
+            .into_closed_goal();
+
+        let mut solver = SolverChoice::slg()
+            .overflow_depth(5)
+            .build()
+            .create_solver(&env);
+        let partial = solver.solve_partial(&goal);
+        assert!(partial.is_complete());
+        assert!(partial.solution.unwrap().is_unique());
+
+        // Too shallow a stack: the solution is ambiguous, and we are
+        // told why.
+        let mut solver = SolverChoice::slg()
+            .overflow_depth(2)
+            .build()
+            .create_solver(&env);
+        let partial = solver.solve_partial(&goal);
+        assert!(!partial.is_complete());
+        assert!(!partial.truncated());
+        assert!(partial.overflow_depth().unwrap() < 2);
+        assert!(partial
+            .overflows
+            .iter()
+            .all(|overflow| overflow.goal_stack[0] == goal));
+        let text = partial.to_string();
+        assert!(text.starts_with("Ambiguous"), "{}", text);
+        assert!(text.contains("; overflowed at depth"), "{}", text);
+        assert!(!text.contains("truncated"), "{}", text);
+
+        // The negated goal is too large to be solved precisely, so it
+        // is truncated, and we are told about that too.
+        let goal = parse_and_lower_goal(&program, "not { Vec<Vec<Vec<i32>>>: Foo }")
+            .unwrap()
+            .into_closed_goal();
+        let mut solver = SolverChoice::slg().max_size(2).build().create_solver(&env);
+        let partial = solver.solve_partial(&goal);
+        assert!(partial.truncated());
+        assert!(partial
+            .overflows
+            .iter()
+            .any(|overflow| overflow.kind == OverflowKind::Size));
+        let text = partial.to_string();
+        assert!(text.starts_with("Ambiguous"), "{}", text);
+        assert!(text.contains("; terms truncated at depth"), "{}", text);
+    });
+}
+
+#[test]
+fn partial_solution_flounders() {
+    let program_text = "
+        trait A { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl<T> A for Vec<T> { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let mut solver = SolverChoice::slg().build().create_solver(&env);
+
+        let goal = parse_and_lower_goal(&program, "not { u32: A }")
+            .unwrap()
+            .into_peeled_goal();
+        assert!(solver.solve_partial(&goal).flounders.is_empty());
+
+        let goal = parse_and_lower_goal(&program, "exists<T> { not { T: A } }")
+            .unwrap()
+            .into_peeled_goal();
+        let partial = solver.solve_partial(&goal);
+        assert!(partial.solution.as_ref().unwrap().is_ambiguous());
+        assert_eq!(partial.flounders.len(), 1);
+        assert_eq!(partial.flounders[0].goal_stack[0], goal);
+        let subgoal = parse_and_lower_goal(&program, "exists<T> { T: A }")
+            .unwrap()
+            .into_peeled_goal();
+        assert_eq!(partial.flounders[0].subgoal, subgoal);
+        let text = partial.to_string();
+        assert!(text.contains("; floundered on `not {"), "{}", text);
+    });
+}
+
+#[test]
+fn peeled_goal_universes() {
+    use chalk_ir::UniverseIndex;
+
+    let program_text = "
+        trait Foo<T> { }
+        struct u32 { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        // The placeholders of `T` do not appear in the goal, so its
+        // universe is dropped, and those of `U` and `W` are in the
+        // first universe.
+        let goal = parse_and_lower_goal(
+            &program,
+            "forall<T> { forall<U, W> { exists<V> { V = W, u32: Foo<U> } } }",
+        )
+        .unwrap()
+        .into_peeled_goal_with_universes();
+        let universe = UniverseIndex::new(1);
+        assert_eq!(goal.forall_universes, vec![None, Some(universe)]);
+
+        // So the answer `!1_1` is `W`, the second binder of the second
+        // `forall<>`.
+        let goal = parse_and_lower_goal(
+            &program,
+            "forall<T> { forall<U, W> { exists<V> { V = W } } }",
+        )
+        .unwrap()
+        .into_peeled_goal_with_universes();
+        let solution = solver_choice.solve_root_goal(&env, &goal.goal);
+        assert_result(&solution, "Unique; substitution [?0 := !1_1]");
+        let placeholder = universe.placeholder(1);
+        assert_eq!(goal.placeholder_binder(placeholder), Some((1, 1)));
+    });
+}
+
+#[test]
+fn forall_names() {
+    use crate::rust_ir::lowering::{peeled_forall_binders, LowerGoal};
+
+    let program_text = "
+        struct u32 { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = chalk_parse::parse_goal(
+            "forall<T> { forall<U, 'a> { exists<V, 'b> { V = U, 'b = 'a } } }",
+        )
+        .unwrap();
+        let forall_binders = peeled_forall_binders(&goal);
+        let goal = goal
+            .lower(&*program)
+            .unwrap()
+            .into_peeled_goal_with_universes();
+        let solution = solver_choice.solve_root_goal(&env, &goal.goal);
+        assert_result(&solution, "Unique; substitution [?0 := !1_0, ?1 := '!1_1]");
+
+        // `T` gets no universe, but still counts for the depth.
+        chalk_ir::tls::with_forall_names(goal.forall_names(forall_binders), || {
+            assert_result(
+                &solution,
+                "Unique; substitution [\
+                 ?0 := !U(from forall<U, 'a> at depth 2), \
+                 ?1 := !'a(from forall<U, 'a> at depth 2)]",
+            );
+        });
+    });
+}
+
+#[test]
+fn prefer_where_clauses() {
+    test! {
+        program {
+            trait Foo<T> { }
+            struct u32 { }
+            struct i32 { }
+            impl<T> Foo<u32> for T { }
+        }
+
+        // Both the where clause and the impl apply; rustc would pick
+        // the where clause and infer `U = i32`.
+        goal {
+            forall<T> {
+                if (T: Foo<i32>) {
+                    exists<U> {
+                        T: Foo<U>
+                    }
+                }
+            }
+        } yields[SolverChoice::default()] {
+            "Ambiguous"
+        } yields[SolverChoice::slg().prefer_where_clauses(true).build()] {
+            "Unique; substitution [?0 := i32]"
+        }
+
+        // The where clause does not apply to other trait references, so
+        // the impl is still used for them.
+        goal {
+            forall<T> {
+                if (T: Foo<i32>) {
+                    T: Foo<u32>
+                }
+            }
+        } yields[SolverChoice::slg().prefer_where_clauses(true).build()] {
+            "Unique"
+        }
+
+        goal {
+            forall<T> {
+                if (T: Foo<i32>) {
+                    i32: Foo<u32>
+                }
+            }
+        } yields[SolverChoice::slg().prefer_where_clauses(true).build()] {
+            "Unique"
+        }
+    }
+}
+
+#[test]
+fn solution_accessors() {
+    use chalk_solve::solve::Guidance;
+
+    let program_text = "
+        trait Foo<T> { }
+        struct u32 { }
+        struct i32 { }
+        impl Foo<i32> for i32 { }
+        impl Foo<u32> for i32 { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let solve = |goal_text: &str| {
+            let goal = parse_and_lower_goal(&program, goal_text).unwrap();
+            solver_choice
+                .solve_root_goal(&env, &goal.into_peeled_goal())
+                .unwrap()
+                .unwrap()
+        };
+
+        let unique = solve("exists<T> { i32: Foo<T>, T = i32 }");
+        assert!(unique.is_unique());
+        assert!(!unique.is_ambiguous());
+        assert!(unique.constraints().is_empty());
+        assert_eq!(format!("{}", unique.definite_subst().unwrap()), "[?0 := i32]");
+        assert_eq!(unique.guidance(), Guidance::Definite(unique.definite_subst().unwrap()));
+
+        // `T` is either `i32` or `u32`.
+        let ambiguous = solve("exists<T> { i32: Foo<T> }");
+        assert!(!ambiguous.is_unique());
+        assert!(ambiguous.is_ambiguous());
+        assert!(ambiguous.constrained_subst().is_none());
+        assert!(ambiguous.constraints().is_empty());
+        assert_eq!(ambiguous.definite_subst(), None);
+    });
+}
+
+#[test]
+fn clause_order() {
+    // The order in which clauses are tried does not change the results.
+    test! {
+        program {
+            trait Foo { }
+            trait Bar { }
+            struct u32 { }
+            struct i32 { }
+            struct Vec<T> { }
+            impl<T> Foo for Vec<T> where T: Foo, T: Bar { }
+            impl Foo for u32 { }
+            impl Foo for i32 { }
+            impl Bar for u32 { }
+        }
+
+        goal {
+            exists<T> {
+                T: Foo
+            }
+        } yields[SolverChoice::default()] {
+            "Ambiguous"
+        } yields[SolverChoice::slg().clause_order(ClauseOrder::FewestConditions).build()] {
+            "Ambiguous"
+        }
+
+        goal {
+            exists<T> {
+                Vec<T>: Foo
+            }
+        } yields[SolverChoice::default()] {
+            "Unique; substitution [?0 := u32]"
+        } yields[SolverChoice::slg().clause_order(ClauseOrder::FewestConditions).build()] {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            Vec<i32>: Foo
+        } yields[SolverChoice::default()] {
+            "No possible solution"
+        } yields[SolverChoice::slg().clause_order(ClauseOrder::FewestConditions).build()] {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn shuffled_clause_order() {
+    // However the clauses are ordered, the solutions are the same.
+    test! {
+        program {
+            trait Foo { }
+            trait Bar { }
+            struct u32 { }
+            struct i32 { }
+            struct Vec<T> { }
+            impl<T> Foo for Vec<T> where T: Foo, T: Bar { }
+            impl Foo for u32 { }
+            impl Foo for i32 { }
+            impl Bar for u32 { }
+        }
+
+        goal {
+            exists<T> {
+                Vec<T>: Foo
+            }
+        } yields[
+            SolverChoice::slg().clause_order(ClauseOrder::Shuffled { seed: 0 }).build(),
+            SolverChoice::slg().clause_order(ClauseOrder::Shuffled { seed: 1 }).build(),
+            SolverChoice::slg().clause_order(ClauseOrder::Shuffled { seed: 2 }).build()
+        ] {
+            "Unique; substitution [?0 := u32]"
+        }
+
+        goal {
+            Vec<i32>: Foo
+        } yields[
+            SolverChoice::slg().clause_order(ClauseOrder::Shuffled { seed: 0 }).build(),
+            SolverChoice::slg().clause_order(ClauseOrder::Shuffled { seed: 1 }).build(),
+            SolverChoice::slg().clause_order(ClauseOrder::Shuffled { seed: 2 }).build()
+        ] {
+            "No possible solution"
+        }
+    }
+}
+
+#[test]
+fn shuffled_program_clauses() {
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct i32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl Foo for i32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let clauses = |solver_choice| {
+        let (_, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+        env.program_clauses.clone()
+    };
+
+    // Lowering shuffles the clauses it generates too, but they are
+    // still the same clauses.
+    let as_written = clauses(SolverChoice::default());
+    let shuffled: Vec<_> = (0..3)
+        .map(|seed| {
+            clauses(
+                SolverChoice::slg()
+                    .clause_order(ClauseOrder::Shuffled { seed })
+                    .build(),
+            )
+        })
+        .collect();
+    assert!(shuffled
+        .iter()
+        .all(|clauses| clauses.fingerprint() == as_written.fingerprint()));
+    assert!(shuffled.iter().any(|clauses| *clauses != as_written));
+}
+
+#[test]
+fn stream_answers() {
+    use chalk_engine::cancel::CancellationToken;
+
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let cancel = CancellationToken::new();
+
+        // There are infinitely many answers; the solver stops once we
+        // drop the receiver.
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Foo }")
+            .unwrap()
+            .into_peeled_goal();
+        let expected: Vec<_> = solver_choice
+            .create_solver(&env)
+            .solve_multiple(&goal)
+            .take(3)
+            .collect();
+        let answers = solver_choice.stream_answers(&env, &goal, &cancel);
+        let answers: Vec<_> = answers.iter().take(3).collect();
+        assert_eq!(answers, expected);
+
+        // A goal with a single answer closes the channel after it.
+        let goal = parse_and_lower_goal(&program, "Vec<u32>: Foo")
+            .unwrap()
+            .into_peeled_goal();
+        let answers: Vec<_> = solver_choice
+            .stream_answers(&env, &goal, &cancel)
+            .iter()
+            .collect();
+        assert_eq!(answers.len(), 1);
+        assert!(!answers[0].ambiguous);
+
+        // No answers at all once canceled.
+        cancel.cancel();
+        let answers = solver_choice.stream_answers(&env, &goal, &cancel);
+        assert_eq!(answers.iter().count(), 0);
+    });
+}
+
+#[test]
+fn verify_solutions() {
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct i32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let solver_choice = SolverChoice::default();
+    let (program, env) = parse_and_lower_program_with_env(program_text, solver_choice).unwrap();
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = |text: &str| parse_and_lower_goal(&program, text).unwrap().into_peeled_goal();
+
+        let foo = goal("exists<T> { Vec<T>: Foo, T = u32 }");
+        let solution = solver_choice.solve_root_goal(&env, &foo).unwrap().unwrap();
+        assert_eq!(verify_solution(&*env, &foo, &solution), Ok(()));
+
+        // `i32` is not `Foo`, so a solver answering it would be unsound.
+        let not_foo = goal("exists<T> { T = i32 }");
+        let solution = solver_choice.solve_root_goal(&env, &not_foo).unwrap().unwrap();
+        let bug = verify_solution(&*env, &foo, &solution).unwrap_err();
+        assert_eq!(bug.goal, foo);
+        assert!(bug.to_string().starts_with("internal error"));
+    });
+}
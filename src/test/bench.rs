@@ -3,9 +3,14 @@
 extern crate test;
 use self::test::Bencher;
 
+use crate::rust_ir::lowering::LowerProgram;
 use chalk_ir;
+use chalk_ir::{
+    Canonical, ConstrainedSubst, DebruijnIndex, ParameterKind, Substitution, UniverseIndex,
+};
+use chalk_parse;
 use chalk_solve::ext::*;
-use chalk_solve::solve::SolverChoice;
+use chalk_solve::solve::{ClauseOrder, SolverChoice};
 
 use super::{assert_result, parse_and_lower_goal, parse_and_lower_program_with_env};
 
@@ -98,9 +103,186 @@ forall<T> {
 fn cycley_slg(b: &mut Bencher) {
     run_bench(
         CYCLEY,
-        SolverChoice::SLG { max_size: 20 },
+        SolverChoice::slg().max_size(20).build(),
         CYCLEY_GOAL,
         b,
         "Unique",
     );
 }
+
+#[bench]
+fn cycley_slg_fewest_conditions(b: &mut Bencher) {
+    run_bench(
+        CYCLEY,
+        SolverChoice::slg()
+            .max_size(20)
+            .clause_order(ClauseOrder::FewestConditions)
+            .build(),
+        CYCLEY_GOAL,
+        b,
+        "Unique",
+    );
+}
+
+/// Declares the impls with the most conditions first, which is the worst
+/// order to try them in.
+const DEEP_IMPLS_FIRST: &str = "
+trait Foo { }
+trait Bar { }
+
+struct u32 { }
+struct i32 { }
+struct Vec<T> { }
+struct Box<T> { }
+
+impl<T> Foo for Vec<T> where T: Foo, T: Bar, Box<T>: Foo, Box<T>: Bar { }
+impl<T> Foo for Box<T> where T: Foo, T: Bar { }
+impl Foo for u32 { }
+impl Foo for i32 { }
+
+impl<T> Bar for Vec<T> where T: Bar, Box<T>: Bar { }
+impl<T> Bar for Box<T> where T: Bar { }
+impl Bar for u32 { }
+";
+
+const DEEP_IMPLS_FIRST_GOAL: &str = "
+exists<T> {
+    Vec<Vec<T>>: Foo
+}
+";
+
+#[bench]
+fn deep_impls_first_as_written(b: &mut Bencher) {
+    run_bench(
+        DEEP_IMPLS_FIRST,
+        SolverChoice::slg().max_size(20).build(),
+        DEEP_IMPLS_FIRST_GOAL,
+        b,
+        "Ambiguous",
+    );
+}
+
+#[bench]
+fn deep_impls_first_fewest_conditions(b: &mut Bencher) {
+    run_bench(
+        DEEP_IMPLS_FIRST,
+        SolverChoice::slg()
+            .max_size(20)
+            .clause_order(ClauseOrder::FewestConditions)
+            .build(),
+        DEEP_IMPLS_FIRST_GOAL,
+        b,
+        "Ambiguous",
+    );
+}
+
+/// Iterator adapters wrapped in each other, whose item types are
+/// projections on the item types of the iterators they wrap: solving
+/// the goal proves each of the wrapped adapters to be an iterator,
+/// through many trait references with few parameters each.
+const ADAPTERS: &str = "
+trait Iterator { type Item; }
+
+struct u32 { }
+struct Pair<A, B> { }
+struct Once<T> { }
+struct Rev<I> { }
+struct Zip<A, B> { }
+
+impl<T> Iterator for Once<T> { type Item = T; }
+impl<I> Iterator for Rev<I> where I: Iterator { type Item = <I as Iterator>::Item; }
+impl<A, B> Iterator for Zip<A, B> where A: Iterator, B: Iterator {
+    type Item = Pair<<A as Iterator>::Item, <B as Iterator>::Item>;
+}
+";
+
+const ADAPTERS_GOAL: &str = "
+exists<T> {
+    Normalize(<Zip<Rev<Rev<Once<u32>>>, Zip<Once<u32>, Rev<Once<u32>>>> as Iterator>::Item -> T)
+}
+";
+
+const NORMALIZE_ADAPTERS_ANSWER: &str =
+    "Unique; substitution [?0 := Pair<<Rev<Rev<Once<u32>>> as Iterator>::Item, \
+     <Zip<Once<u32>, Rev<Once<u32>>> as Iterator>::Item>]";
+
+#[bench]
+fn normalize_adapters(b: &mut Bencher) {
+    run_bench(
+        ADAPTERS,
+        // The goal is larger than the default `max_size`.
+        SolverChoice::slg().max_size(20).build(),
+        ADAPTERS_GOAL,
+        b,
+        NORMALIZE_ADAPTERS_ANSWER,
+    );
+}
+
+#[bench]
+fn normalize_adapters_fewest_conditions(b: &mut Bencher) {
+    run_bench(
+        ADAPTERS,
+        SolverChoice::slg()
+            .max_size(20)
+            .clause_order(ClauseOrder::FewestConditions)
+            .build(),
+        ADAPTERS_GOAL,
+        b,
+        NORMALIZE_ADAPTERS_ANSWER,
+    );
+}
+
+/// A program with `n` generic structs, traits and impls, for measuring
+/// lowering.
+fn many_items(n: usize) -> String {
+    let mut program_text = String::new();
+    for i in 0..n {
+        program_text.push_str(&format!(
+            "
+            struct S{i}<T> {{ }}
+            trait Trait{i}<U> where U: Trait0<U> {{ type Item; }}
+            impl<T, U> Trait{i}<U> for S{i}<T> where T: Trait{i}<U> {{ type Item = S{i}<U>; }}
+            ",
+            i = i
+        ));
+    }
+    program_text
+}
+
+#[bench]
+fn lower_many_items(b: &mut Bencher) {
+    let program = chalk_parse::parse_program(&many_items(200)).unwrap();
+    b.iter(|| program.lower().unwrap());
+}
+
+/// An answer with 16 parameters and no constraints.
+fn answer() -> Canonical<ConstrainedSubst> {
+    let parameters = (0..16)
+        .map(|index| ParameterKind::Ty(DebruijnIndex::new(index).to_ty()))
+        .collect();
+    Canonical {
+        value: ConstrainedSubst {
+            subst: Substitution { parameters },
+            constraints: vec![],
+        },
+        binders: vec![ParameterKind::Ty(UniverseIndex::ROOT); 16],
+    }
+}
+
+/// Cloning an answer, as the solver does each time it applies one to a
+/// goal: the parameters of the substitution are shared, not copied.
+#[bench]
+fn clone_answer(b: &mut Bencher) {
+    let answer = answer();
+    b.iter(|| answer.clone());
+}
+
+/// The only allocation when cloning an answer is for its binders; it
+/// took another one for the parameters before they were shared.
+#[cfg(feature = "mem-stats")]
+#[test]
+fn clone_answer_allocations() {
+    let answer = answer();
+    let (_clone, allocations) = crate::mem_stats::count_thread_allocations(|| answer.clone());
+    assert_eq!(allocations, 1);
+}
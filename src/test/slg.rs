@@ -36,7 +36,8 @@ fn solve_goal(program_text: &str, goals: Vec<(usize, usize, &str, &str)>) {
             assert!(goal_text.ends_with("}"));
             let goal = parse_and_lower_goal(&program, &goal_text[1..goal_text.len() - 1]).unwrap();
             let peeled_goal = goal.into_peeled_goal();
-            let mut forest = Forest::new(SlgContext::new(&env, max_size));
+            let solver_choice = SolverChoice::slg().max_size(max_size).build();
+            let mut forest = Forest::new(SlgContext::new(&env, solver_choice));
             let result = format!("{:#?}", forest.force_answers(peeled_goal, num_answers));
 
             assert_test_result_eq(&expected, &result);
@@ -61,7 +62,8 @@ fn solve_goal_fixed_num_answers(program_text: &str, goals: Vec<(usize, usize, &s
             assert!(goal_text.ends_with("}"));
             let goal = parse_and_lower_goal(&program, &goal_text[1..goal_text.len() - 1]).unwrap();
             let peeled_goal = goal.into_peeled_goal();
-            let mut forest = Forest::new(SlgContext::new(env, max_size));
+            let solver_choice = SolverChoice::slg().max_size(max_size).build();
+            let mut forest = Forest::new(SlgContext::new(env, solver_choice));
             let result = format!("{:?}", forest.solve(&peeled_goal));
 
             assert_test_result_eq(&expected, &result);
@@ -1108,3 +1110,464 @@ fn negative_answer_delayed_literal() {
         }
     }
 }
+
+/// Check that we record the goal stack when answers overflow `max_size`.
+#[test]
+fn overflow_goal_stack() {
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    chalk_ir::tls::set_current_program(&program, || {
+        let solver_choice = SolverChoice::slg().max_size(3).build();
+        let mut forest = Forest::new(SlgContext::new(&env, solver_choice));
+
+        let goal = parse_and_lower_goal(&program, "u32: Foo").unwrap();
+        forest.force_answers(goal.into_peeled_goal(), 1);
+        assert!(forest.overflows().is_empty());
+
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Foo }")
+            .unwrap()
+            .into_peeled_goal();
+        forest.force_answers(goal.clone(), 5);
+        let overflows = forest.overflows();
+        assert!(!overflows.is_empty());
+        assert!(overflows
+            .iter()
+            .all(|overflow| overflow.goal_stack.first() == Some(&goal)));
+    });
+}
+
+/// Check that we record which negative subgoal floundered.
+#[test]
+fn flounder_subgoal() {
+    let program_text = "
+        trait A { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl<T> A for Vec<T> { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    chalk_ir::tls::set_current_program(&program, || {
+        let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+
+        let goal = parse_and_lower_goal(&program, "not { u32: A }").unwrap();
+        assert!(forest.solve(&goal.into_peeled_goal()).unwrap().is_unique());
+        assert!(forest.flounders().is_empty());
+
+        let goal = parse_and_lower_goal(&program, "exists<T> { not { T: A } }")
+            .unwrap()
+            .into_peeled_goal();
+        assert!(!forest.solve(&goal).unwrap().is_unique());
+
+        let flounders = forest.flounders();
+        assert_eq!(flounders.len(), 1);
+        assert_eq!(flounders[0].goal_stack.first(), Some(&goal));
+        let subgoal = parse_and_lower_goal(&program, "exists<T> { T: A }")
+            .unwrap()
+            .into_peeled_goal();
+        assert_eq!(flounders[0].subgoal, subgoal);
+    });
+}
+
+/// Check that an event listener is told about each step of the search.
+#[test]
+fn event_listener() {
+    use chalk_engine::listener::EventListener;
+    use chalk_engine::TableIndex;
+    use chalk_ir::{Canonical, ConstrainedSubst, Goal, InEnvironment, UCanonical};
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl EventListener<SlgContext> for Recorder {
+        fn goal_entered(&mut self, table: TableIndex, _goal: &UCanonical<InEnvironment<Goal>>) {
+            let event = format!("entered {:?}", table);
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn clause_tried(&mut self, table: TableIndex, _clause: &dyn Debug, applies: bool) {
+            let event = format!("tried clause for {:?}: {}", table, applies);
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn answer_produced(
+            &mut self,
+            table: TableIndex,
+            _answer: &Canonical<ConstrainedSubst>,
+            ambiguous: bool,
+        ) {
+            let event = format!("answer for {:?}: {}", table, ambiguous);
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn table_completed(&mut self, table: TableIndex) {
+            let event = format!("completed {:?}", table);
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    chalk_ir::tls::set_current_program(&program, || {
+        let recorder = Recorder::default();
+        let events = recorder.events.clone();
+        let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+        forest.set_event_listener(Box::new(recorder));
+
+        let goal = parse_and_lower_goal(&program, "Vec<u32>: Foo")
+            .unwrap()
+            .into_peeled_goal();
+        assert!(forest.solve(&goal).unwrap().is_unique());
+
+        let events = events.lock().unwrap();
+        assert_eq!(events[0], "entered TableIndex(0)");
+        assert!(events.contains(&"entered TableIndex(1)".to_string()));
+        assert!(events.contains(&"tried clause for TableIndex(0): true".to_string()));
+        assert!(events.contains(&"answer for TableIndex(0): false".to_string()));
+        assert!(events.contains(&"completed TableIndex(1)".to_string()));
+        assert_eq!(events.last().unwrap(), "completed TableIndex(0)");
+    });
+}
+
+/// Check that answers are cached across queries until the cache is cleared.
+#[test]
+fn clear_cache() {
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    chalk_ir::tls::set_current_program(&program, || {
+        let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+        let goal = parse_and_lower_goal(&program, "Vec<u32>: Foo")
+            .unwrap()
+            .into_peeled_goal();
+        let subgoal = parse_and_lower_goal(&program, "u32: Foo")
+            .unwrap()
+            .into_peeled_goal();
+
+        assert!(forest.solve(&goal).unwrap().is_unique());
+        assert_eq!(forest.num_cached_answers_for_goal(&goal), 1);
+        assert_eq!(forest.num_cached_answers_for_goal(&subgoal), 1);
+
+        forest.clear_cache();
+        assert_eq!(forest.num_cached_answers_for_goal(&goal), 0);
+        assert_eq!(forest.num_cached_answers_for_goal(&subgoal), 0);
+
+        assert!(forest.solve(&subgoal).unwrap().is_unique());
+        assert_eq!(forest.num_cached_answers_for_goal(&subgoal), 1);
+    });
+}
+
+/// Check that the u-canonical forms of subgoals are reused within the
+/// solving of a root goal, and only within it.
+#[test]
+fn u_canonical_goals() {
+    let program_text = "
+        trait Foo { }
+        trait Bar { }
+        struct u32 { }
+        struct i32 { }
+        struct Vec<T> { }
+        struct Pair<T, U> { }
+        impl Foo for u32 { }
+        impl Foo for i32 { }
+        impl Bar for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo, T: Bar { }
+        impl<T> Bar for Vec<T> where T: Foo, T: Bar { }
+        impl<T, U> Foo for Pair<T, U> where T: Foo, U: Foo, T: Bar { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    chalk_ir::tls::set_current_program(&program, || {
+        let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+        let goal = parse_and_lower_goal(&program, "exists<T, U> { Pair<T, U>: Foo }")
+            .unwrap()
+            .into_peeled_goal();
+
+        // The impls keep asking for the same few subgoals, so most of
+        // them are found memoized.
+        assert_eq!(forest.force_answers(goal.clone(), 10).len(), 10);
+        let stats = forest.u_canonical_goal_stats();
+        assert!(stats.hits > stats.misses, "{:?}", stats);
+
+        // Solving the goal again from scratch finds nothing memoized
+        // from the first time: the memo only lasts for one root goal.
+        forest.clear_cache();
+        forest.force_answers(goal, 10);
+        let misses = forest.u_canonical_goal_stats().misses - stats.misses;
+        assert_eq!(misses, stats.misses);
+    });
+}
+
+#[test]
+fn update_program() {
+    use chalk_solve::solve::Solver;
+
+    let program_text = "
+        trait Clone { }
+        trait Copy { }
+        struct Foo { }
+        struct Bar { }
+        struct Vec<T> { }
+        impl Copy for Foo { }
+        impl<T> Clone for Vec<T> where T: Clone { }
+    ";
+    // The same program, with one impl added at the start. Ids do not
+    // depend on the position of items, so the other items keep theirs.
+    let edited_text = &format!("impl Clone for Bar {{ }}\n{}", program_text);
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    let (edited_program, edited_env) =
+        parse_and_lower_program_with_env(edited_text, SolverChoice::default()).unwrap();
+    assert_eq!(program.changed_items(&edited_program).len(), 1);
+    assert!(program.changed_items(&program).is_empty());
+
+    chalk_ir::tls::set_current_program(&program, || {
+        let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+        let copy_goal = parse_and_lower_goal(&program, "Foo: Copy")
+            .unwrap()
+            .into_peeled_goal();
+        let clone_goal = parse_and_lower_goal(&program, "Vec<Bar>: Clone")
+            .unwrap()
+            .into_peeled_goal();
+
+        assert!(forest.solve(&copy_goal).unwrap().is_unique());
+        assert!(forest.solve(&clone_goal).is_none());
+
+        forest.update_program(edited_env.clone());
+
+        // `Foo: Copy` is unaffected by the new impl, and keeps its
+        // answer; `Vec<Bar>: Clone` depends on `Bar: Clone`, and is
+        // solved again.
+        assert_eq!(forest.num_cached_answers_for_goal(&copy_goal), 1);
+        assert_eq!(forest.num_cached_answers_for_goal(&clone_goal), 0);
+        assert!(forest.solve(&clone_goal).unwrap().is_unique());
+    });
+}
+
+/// Check that stepping through a search finds the same answers as
+/// solving, and that the tables can be inspected in between.
+#[test]
+fn step_by_step() {
+    use chalk_engine::forest::Step;
+
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    chalk_ir::tls::set_current_program(&program, || {
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Foo }")
+            .unwrap()
+            .into_peeled_goal();
+
+        let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+        let expected: Vec<_> = forest.solve_multiple(&goal).take(3).collect();
+
+        let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+        let mut stepper = forest.stepper(&goal);
+        let root_table = stepper.root_table();
+        assert_eq!(stepper.forest().table_goal(root_table), &goal);
+        assert!(stepper.forest().table_answers(root_table).is_empty());
+
+        let mut answers = vec![];
+        let mut steps = 0;
+        while answers.len() < 3 {
+            match stepper.step() {
+                Step::Answer(answer) => answers.push(answer),
+                Step::Pending => {}
+                Step::Done => panic!("ran out of answers after {:?}", answers),
+            }
+            steps += 1;
+            assert!(steps < 100);
+        }
+        assert_eq!(answers, expected);
+
+        // There are infinitely many answers, so some strands are left.
+        let forest = stepper.forest();
+        assert_eq!(forest.table_answers(root_table), expected);
+        assert!(forest.table_num_strands(root_table) > 0);
+        assert!(forest.tables().any(|table| table == root_table));
+    });
+}
+
+#[test]
+fn table_snapshot() {
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    chalk_ir::tls::set_current_program(&program, || {
+        let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+
+        let goal = parse_and_lower_goal(&program, "Vec<u32>: Foo")
+            .unwrap()
+            .into_peeled_goal();
+        assert!(forest.solve(&goal).unwrap().is_unique());
+        let snapshot = forest.snapshot();
+        let root = snapshot
+            .tables
+            .iter()
+            .find(|table| table.goal == goal)
+            .unwrap();
+        assert!(root.is_complete());
+        assert_eq!(root.num_answers, 1);
+        assert_eq!(root.num_ambiguous_answers, 0);
+        assert!(!root.on_stack);
+        assert_eq!(snapshot.incomplete_tables().count(), 0);
+
+        // There are infinitely many answers, so the table cannot be
+        // complete after the first few.
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Foo }")
+            .unwrap()
+            .into_peeled_goal();
+        assert_eq!(forest.solve_multiple(&goal).take(2).count(), 2);
+        let snapshot = forest.snapshot();
+        let root = snapshot
+            .incomplete_tables()
+            .find(|table| table.goal == goal)
+            .unwrap();
+        assert!(root.num_answers >= 2);
+    });
+}
+
+#[test]
+fn table_graph() {
+    let program_text = "
+        #[auto] trait Send { }
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        struct List { next: List }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    chalk_ir::tls::set_current_program(&program, || {
+        // `?T: Foo` selects itself as a subgoal, through the impl for
+        // `Vec<T>`: an inductive cycle.
+        let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+        let goal = parse_and_lower_goal(&program, "exists<T> { T: Foo }")
+            .unwrap()
+            .into_peeled_goal();
+        assert_eq!(forest.solve_multiple(&goal).take(2).count(), 2);
+        let snapshot = forest.snapshot();
+        let root = snapshot
+            .tables
+            .iter()
+            .find(|table| table.goal == goal)
+            .unwrap();
+        assert!(root.subgoals.contains(&root.table));
+        let dot = snapshot.to_dot();
+        assert!(dot.starts_with("digraph tables {"));
+        assert!(dot.contains("peripheries=2"));
+        assert!(dot.contains("[color=red]"));
+
+        // `List: Send` requires itself, which is fine for an auto trait.
+        let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+        let goal = parse_and_lower_goal(&program, "List: Send")
+            .unwrap()
+            .into_peeled_goal();
+        assert!(forest.solve(&goal).unwrap().is_unique());
+        let snapshot = forest.snapshot();
+        let root = snapshot
+            .tables
+            .iter()
+            .find(|table| table.goal == goal)
+            .unwrap();
+        assert!(root.coinductive && root.subgoals.contains(&root.table));
+        let dot = snapshot.to_dot();
+        assert!(dot.contains("shape=ellipse"));
+        assert!(dot.contains("[color=green]"));
+    });
+}
+
+/// An answer found by overflowing the depth of the stack must not be
+/// reused when the same goal is solved at a shallower depth, so that
+/// solutions do not depend on what the forest solved before.
+#[test]
+fn depth_overflows_are_not_cached() {
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl Foo for u32 { }
+        impl<T> Foo for Vec<T> where T: Foo { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    chalk_ir::tls::set_current_program(&program, || {
+        let solver_choice = SolverChoice::slg().overflow_depth(6).build();
+        let mut forest = Forest::new(SlgContext::new(&env, solver_choice));
+        let deep = parse_and_lower_goal(&program, "Vec<Vec<Vec<Vec<Vec<u32>>>>>: Foo")
+            .unwrap()
+            .into_peeled_goal();
+        let shallow = parse_and_lower_goal(&program, "Vec<Vec<Vec<Vec<u32>>>>: Foo")
+            .unwrap()
+            .into_peeled_goal();
+
+        assert!(!forest.solve(&deep).unwrap().is_unique());
+        assert!(forest.solve(&shallow).unwrap().is_unique());
+    });
+}
+
+/// The memory of the tables includes what their goals and answers hold
+/// on the heap, and not just the size of each table, strand and answer.
+#[test]
+fn memory_usage_counts_heap_data() {
+    let program_text = "
+        trait Foo { }
+        struct u32 { }
+        struct Vec<T> { }
+        impl<T> Foo for Vec<T> { }
+    ";
+    let (program, env) =
+        parse_and_lower_program_with_env(program_text, SolverChoice::default()).unwrap();
+    chalk_ir::tls::set_current_program(&program, || {
+        let memory_usage = |goal: &str| {
+            let mut forest = Forest::new(SlgContext::new(&env, SolverChoice::default()));
+            let goal = parse_and_lower_goal(&program, goal).unwrap();
+            assert!(forest.solve(&goal.into_peeled_goal()).unwrap().is_unique());
+            forest.memory_usage()
+        };
+
+        // Both goals take one table, with one answer; only the types in
+        // them differ.
+        let shallow = memory_usage("Vec<u32>: Foo");
+        let deep = memory_usage("Vec<Vec<Vec<Vec<u32>>>>: Foo");
+        assert!(deep > shallow, "{} <= {}", deep, shallow);
+    });
+}
@@ -14,15 +14,6 @@ use itertools::Itertools;
 use std::fmt::Write;
 use std::sync::Arc;
 
-pub fn parse_and_lower_program(
-    text: &str,
-    solver_choice: SolverChoice,
-) -> Result<Arc<Program>, String> {
-    ChalkDatabase::with_program(Arc::new(text.to_string()), solver_choice, |db| {
-        db.checked_program()
-    })
-}
-
 pub fn parse_and_lower_program_with_env(
     text: &str,
     solver_choice: SolverChoice,
@@ -37,37 +28,6 @@ pub fn parse_and_lower_goal(program: &Program, text: &str) -> Result<Box<Goal>,
     chalk_parse::parse_goal(text)?.lower(program)
 }
 
-macro_rules! lowering_success {
-    (program $program:tt) => {
-        let program_text = stringify!($program);
-        assert!(program_text.starts_with("{"));
-        assert!(program_text.ends_with("}"));
-        let result = parse_and_lower_program(
-            &program_text[1..program_text.len() - 1],
-            chalk_solve::solve::SolverChoice::default(),
-        );
-        if let Err(ref e) = result {
-            println!("lowering error: {}", e);
-        }
-        assert!(result.is_ok());
-    };
-}
-
-macro_rules! lowering_error {
-    (program $program:tt error_msg { $expected:expr }) => {
-        let program_text = stringify!($program);
-        assert!(program_text.starts_with("{"));
-        assert!(program_text.ends_with("}"));
-        let error = parse_and_lower_program(
-            &program_text[1..program_text.len() - 1],
-            chalk_solve::solve::SolverChoice::default(),
-        )
-        .unwrap_err();
-        let expected = $expected;
-        assert_eq!(error.to_string(), expected.to_string());
-    };
-}
-
 crate fn assert_test_result_eq(expected: &str, actual: &str) {
     let expected_trimmed: String = expected
         .lines()
@@ -0,0 +1,207 @@
+//! Golden tests: each program `tests/golden/<name>.chalk` is lowered,
+//! and the goals of the companion file `tests/golden/<name>.goals` are
+//! solved against it. The companion file is a list of blocks:
+//!
+//! ```notrust
+//! goal {
+//!     Vec<Foo>: Clone
+//! }
+//! yields {
+//!     Unique; substitution [], lifetime constraints []
+//! }
+//! ```
+//!
+//! Each `goal` is followed by the solution expected for it (or by the
+//! error lowering it gives). A program that fails to lower has an
+//! `error` block before its goals instead.
+//!
+//! The test fails if the results differ from those in the companion
+//! files. Run `cargo test --test golden -- --bless` to write the actual
+//! results into the files instead, and review them before committing.
+
+extern crate chalk;
+extern crate chalk_ir;
+extern crate chalk_parse;
+extern crate chalk_solve;
+extern crate diff;
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+
+use chalk::db::ChalkDatabase;
+use chalk::query::LoweringDatabase;
+use chalk::rust_ir::lowering::LowerGoal;
+use chalk_solve::ext::GoalExt;
+use chalk_solve::solve::SolverChoice;
+
+/// A `keyword { body }` block of a companion file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Block {
+    keyword: String,
+    body: String,
+}
+
+impl Block {
+    fn new(keyword: &str, body: &str) -> Block {
+        // Ignore the indentation of the body, and blank lines around it.
+        let lines: Vec<_> = body.lines().map(|line| line.trim()).collect();
+        Block {
+            keyword: keyword.to_string(),
+            body: lines.join("\n").trim().to_string(),
+        }
+    }
+}
+
+fn parse_blocks(text: &str) -> Result<Vec<Block>, String> {
+    let mut blocks = vec![];
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let open = rest.find('{').ok_or("expected `{`")?;
+        let keyword = rest[..open].trim();
+        let mut depth = 0;
+        let mut close = None;
+        for (index, c) in rest[open..].char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => continue,
+            }
+            if depth == 0 {
+                close = Some(open + index);
+                break;
+            }
+        }
+        let close = close.ok_or_else(|| format!("unclosed `{}` block", keyword))?;
+        blocks.push(Block::new(keyword, &rest[open + 1..close]));
+        rest = rest[close + 1..].trim_start();
+    }
+    Ok(blocks)
+}
+
+fn render_blocks(blocks: &[Block]) -> String {
+    let mut text = String::new();
+    for (index, block) in blocks.iter().enumerate() {
+        if index > 0 && block.keyword != "yields" {
+            text.push('\n');
+        }
+        text.push_str(&block.keyword);
+        text.push_str(" {\n");
+        for line in block.body.lines() {
+            if !line.is_empty() {
+                text.push_str("    ");
+                text.push_str(line);
+            }
+            text.push('\n');
+        }
+        text.push_str("}\n");
+    }
+    text
+}
+
+/// Lowers `program_text` and solves each of `goals`, returning the
+/// blocks that the companion file should hold.
+fn run(program_text: &str, goals: &[String]) -> Vec<Block> {
+    let solver_choice = SolverChoice::default();
+    ChalkDatabase::with_program(Arc::new(program_text.to_string()), solver_choice, |db| {
+        let mut blocks = vec![];
+        let (program, env) = match db.checked_program() {
+            Ok(program) => (program, db.environment().unwrap()),
+            Err(err) => {
+                blocks.push(Block::new("error", &err));
+                blocks.extend(goals.iter().map(|goal| Block::new("goal", goal)));
+                return blocks;
+            }
+        };
+
+        chalk_ir::tls::set_current_program(&program, || {
+            for goal_text in goals {
+                let goal = chalk_parse::parse_goal(goal_text)
+                    .and_then(|goal| goal.lower(&*program))
+                    .map(|goal| goal.into_peeled_goal());
+                let result = match goal {
+                    Ok(goal) => match solver_choice.solve_root_goal(&env, &goal) {
+                        Ok(Some(solution)) => solution.to_string(),
+                        Ok(None) => "No possible solution".to_string(),
+                        Err(_) => "Error".to_string(),
+                    },
+                    Err(err) => format!("error: {}", err),
+                };
+                blocks.push(Block::new("goal", goal_text));
+                blocks.push(Block::new("yields", &result));
+            }
+        });
+        blocks
+    })
+}
+
+/// Runs the test of `program_path`, returning the differences from the
+/// expected results if it fails. With `bless`, the companion file is
+/// updated to the actual results instead.
+fn run_test(program_path: &Path, bless: bool) -> Result<(), String> {
+    let goals_path = program_path.with_extension("goals");
+    let program_text = fs::read_to_string(program_path).map_err(|err| err.to_string())?;
+    let goals_text = fs::read_to_string(&goals_path).unwrap_or_default();
+
+    let expected = parse_blocks(&goals_text)
+        .map_err(|err| format!("{}: {}", goals_path.display(), err))?;
+    let goals: Vec<_> = expected
+        .iter()
+        .filter(|block| block.keyword == "goal")
+        .map(|block| block.body.clone())
+        .collect();
+
+    let expected = render_blocks(&expected);
+    let actual = render_blocks(&run(&program_text, &goals));
+    if actual == expected {
+        return Ok(());
+    }
+    if bless {
+        return fs::write(&goals_path, actual).map_err(|err| err.to_string());
+    }
+
+    let mut message = String::new();
+    for line in diff::lines(&expected, &actual) {
+        match line {
+            diff::Result::Left(line) => message.push_str(&format!("-{}\n", line)),
+            diff::Result::Right(line) => message.push_str(&format!("+{}\n", line)),
+            diff::Result::Both(line, _) => message.push_str(&format!(" {}\n", line)),
+        }
+    }
+    Err(message)
+}
+
+fn main() {
+    let bless = env::args().any(|arg| arg == "--bless");
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+    let mut programs: Vec<PathBuf> = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |extension| extension == "chalk"))
+        .collect();
+    programs.sort();
+
+    let mut failures = 0;
+    for program in &programs {
+        let name = program.file_stem().unwrap().to_string_lossy();
+        match run_test(program, bless) {
+            Ok(()) => println!("test golden/{} ... ok", name),
+            Err(message) => {
+                println!("test golden/{} ... FAILED\n{}", name, message);
+                failures += 1;
+            }
+        }
+    }
+
+    println!(
+        "\ngolden test result: {} passed; {} failed",
+        programs.len() - failures,
+        failures
+    );
+    if failures > 0 {
+        println!("run `cargo test --test golden -- --bless` to accept the actual results");
+        process::exit(1);
+    }
+}